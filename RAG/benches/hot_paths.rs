@@ -0,0 +1,192 @@
+// Benchmarks of the ingestion and retrieval hot paths over synthetic corpora, to catch
+// performance regressions and measure index work (ANN, SIMD) against the brute-force baseline.
+//
+//   cargo bench -p rag_system --bench hot_paths
+//   cargo bench -p rag_system --bench hot_paths --features token-chunking -- chunking
+//
+// The corpora are generated from a fixed seed, so runs are comparable across commits.
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use rag_system::pipeline::{DenseRetriever, RetrievalQuery};
+use rag_system::prompt::estimate_tokens;
+use rag_system::providers::cosine_similarity;
+use rag_system::{
+    Document, DocumentProcessor, EmbeddingProvider, EmbeddingService, MockEmbeddingProvider, QueryRequest, RankingWeights,
+    Retriever,
+};
+use std::hint::black_box;
+use tokio::runtime::Runtime;
+
+const WORDS: &[&str] = &[
+    "policy", "insured", "premium", "coverage", "hospitalisation", "claim", "benefit", "waiting", "period", "exclusion",
+    "treatment", "surgery", "maternity", "grace", "renewal", "sum", "deductible", "cashless", "network", "hospital",
+    "pre-existing", "disease", "ayush", "room", "rent", "icu", "charges", "organ", "donor", "ambulance", "domiciliary",
+    "daycare", "cataract", "knee", "months", "years", "thirty", "days", "limit", "per", "annum", "the", "of", "and", "is",
+    "for", "any", "shall", "be", "under", "this", "to", "in", "not",
+];
+
+// Deterministic pseudo-random words (xorshift), sentences of 8-20 words
+fn synthetic_text(words: usize, seed: u64) -> String {
+    let mut state = seed.max(1);
+    let mut next = || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+    let mut text = String::with_capacity(words * 8);
+    let mut sentence_left = 0;
+    for _ in 0..words {
+        if sentence_left == 0 {
+            if !text.is_empty() {
+                text.push_str(". ");
+            }
+            sentence_left = 8 + next() % 13;
+        } else {
+            text.push(' ');
+        }
+        text.push_str(WORDS[(next() % WORDS.len() as u64) as usize]);
+        sentence_left -= 1;
+    }
+    text.push('.');
+    text
+}
+
+// `documents` documents of `words` words each, chunked with the default settings
+fn synthetic_corpus(documents: usize, words: usize) -> Vec<Document> {
+    let processor = DocumentProcessor::new();
+    (0..documents)
+        .map(|i| processor.process_text(format!("doc-{}.pdf", i), synthetic_text(words, i as u64 + 1)))
+        .collect()
+}
+
+fn chunk_count(documents: &[Document]) -> u64 {
+    documents.iter().map(|doc| doc.chunks.len() as u64).sum()
+}
+
+fn chunking(c: &mut Criterion) {
+    let mut group = c.benchmark_group("chunking");
+    for words in [10_000, 100_000] {
+        let text = synthetic_text(words, 42);
+        group.throughput(Throughput::Bytes(text.len() as u64));
+
+        let processor = DocumentProcessor::new();
+        group.bench_with_input(BenchmarkId::new("characters", words), &text, |b, text| {
+            b.iter(|| processor.process_text("bench.pdf".to_string(), black_box(text.clone())))
+        });
+
+        #[cfg(feature = "token-chunking")]
+        {
+            let chunker = rag_system::TokenChunker::new(256, 32).expect("tokenizer");
+            let text = rag_system::SharedText::from(text.as_str());
+            group.bench_with_input(BenchmarkId::new("tokens", words), &text, |b, text| {
+                b.iter(|| chunker.chunk(black_box(text)))
+            });
+        }
+    }
+    group.finish();
+}
+
+fn tokenization(c: &mut Criterion) {
+    let mut group = c.benchmark_group("tokenization");
+    let text = synthetic_text(10_000, 7);
+    group.throughput(Throughput::Bytes(text.len() as u64));
+
+    group.bench_function("estimate_tokens", |b| b.iter(|| estimate_tokens(black_box(&text))));
+
+    let mock = MockEmbeddingProvider::new();
+    group.bench_function("feature_hashing", |b| b.iter(|| mock.embed(black_box(&text))));
+
+    #[cfg(feature = "token-chunking")]
+    {
+        let bpe = tiktoken_rs::cl100k_base().expect("tokenizer");
+        group.bench_function("cl100k", |b| b.iter(|| bpe.encode_with_special_tokens(black_box(&text)).len()));
+    }
+    group.finish();
+}
+
+fn embedding_generation(c: &mut Criterion) {
+    let runtime = Runtime::new().expect("tokio runtime");
+    let mut group = c.benchmark_group("embedding_generation");
+    group.sample_size(10);
+    for documents in [10, 100] {
+        let corpus = synthetic_corpus(documents, 5_000);
+        group.throughput(Throughput::Elements(chunk_count(&corpus)));
+
+        group.bench_with_input(BenchmarkId::new("tfidf", documents), &corpus, |b, corpus| {
+            let service = runtime.block_on(EmbeddingService::new()).expect("embedding service");
+            b.to_async(&runtime).iter_batched(
+                || corpus.clone(),
+                |mut corpus| {
+                    let service = &service;
+                    async move { service.generate_embeddings(&mut corpus).await.expect("embeddings") }
+                },
+                BatchSize::LargeInput,
+            )
+        });
+
+        group.bench_with_input(BenchmarkId::new("mock", documents), &corpus, |b, corpus| {
+            let mock = MockEmbeddingProvider::new();
+            b.to_async(&runtime).iter_batched(
+                || corpus.clone(),
+                |mut corpus| {
+                    let mock = &mock;
+                    async move { EmbeddingProvider::generate_embeddings(mock, &mut corpus).await.expect("embeddings") }
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+// Exhaustive scoring of every chunk against a query, as DenseRetriever does today
+fn similarity_search(c: &mut Criterion) {
+    let runtime = Runtime::new().expect("tokio runtime");
+    let mut group = c.benchmark_group("similarity_search");
+    let embeddings = MockEmbeddingProvider::new();
+    let request = QueryRequest { query: "waiting period for cataract surgery".to_string(), ..Default::default() };
+    let weights = RankingWeights::default();
+
+    for documents in [10, 100, 500] {
+        let mut corpus = synthetic_corpus(documents, 5_000);
+        runtime
+            .block_on(EmbeddingProvider::generate_embeddings(&embeddings, &mut corpus))
+            .expect("embeddings");
+        group.throughput(Throughput::Elements(chunk_count(&corpus)));
+
+        let query_embedding = embeddings.embed(&request.query);
+        let vectors: Vec<&[f32]> = corpus
+            .iter()
+            .flat_map(|doc| doc.chunks.iter().filter_map(|chunk| chunk.embedding.as_deref()))
+            .collect();
+        group.bench_with_input(BenchmarkId::new("cosine_scan", documents), &vectors, |b, vectors| {
+            b.iter(|| {
+                vectors
+                    .iter()
+                    .map(|vector| cosine_similarity(black_box(&query_embedding), vector))
+                    .fold(f32::MIN, f32::max)
+            })
+        });
+
+        let scoped: Vec<&Document> = corpus.iter().collect();
+        let query = RetrievalQuery {
+            request: &request,
+            text: &request.query,
+            documents: &scoped,
+            embeddings: &embeddings,
+            max_results: 5,
+            mmr_lambda: 0.7,
+            ranking_weights: &weights,
+            score_threshold: None,
+            duplicate_threshold: None,
+        };
+        group.bench_with_input(BenchmarkId::new("dense_retriever", documents), &query, |b, query| {
+            b.to_async(&runtime).iter(|| async { DenseRetriever.retrieve(query).await.expect("retrieval") })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, chunking, tokenization, embedding_generation, similarity_search);
+criterion_main!(benches);
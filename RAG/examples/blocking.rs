@@ -0,0 +1,24 @@
+use rag_system::blocking::RagLibrary;
+use rag_system::{EmbeddingBackend, LlmBackend, RagLibraryBuilder};
+
+// Indexes a directory and answers a question without any async code:
+// cargo run -p rag_system --example blocking -- <documents dir> "<question>"
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args().skip(1);
+    let documents_dir = args.next().unwrap_or_else(|| ".".to_string());
+    let question = args.next().unwrap_or_else(|| "What is the grace period?".to_string());
+
+    let builder = RagLibraryBuilder::new()
+        .with_documents_dir(documents_dir)
+        .with_embedding_backend(EmbeddingBackend::Tfidf)
+        .with_llm_backend(LlmBackend::Mock);
+    let library = RagLibrary::from_builder(builder)?;
+    println!("Indexed {} documents", library.load_documents()?);
+
+    let response = library.query(&question, 3)?;
+    println!("{}", response.response);
+    for citation in &response.citations {
+        println!("  - {}", citation.document);
+    }
+    Ok(())
+}
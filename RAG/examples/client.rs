@@ -1,6 +1,5 @@
 use reqwest::Client;
 use serde_json::json;
-use tokio;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -12,7 +11,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Test health check
     println!("\n📋 Health Check:");
     let health_response = client
-        .get(&format!("{}/health", base_url))
+        .get(format!("{}/health", base_url))
         .send()
         .await?;
     
@@ -23,7 +22,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Test document info
     println!("\n📚 Document Information:");
     let docs_response = client
-        .get(&format!("{}/documents", base_url))
+        .get(format!("{}/documents", base_url))
         .send()
         .await?;
     
@@ -38,7 +37,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     });
 
     let query_response = client
-        .post(&format!("{}/query", base_url))
+        .post(format!("{}/query", base_url))
         .header("Content-Type", "application/json")
         .json(&query_payload)
         .send()
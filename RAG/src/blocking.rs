@@ -0,0 +1,92 @@
+use crate::error::Result;
+use crate::library::{DocumentInput, RagConfig, RagLibraryBuilder};
+use crate::models::{Document, QueryRequest, QueryResponse};
+use crate::snapshot::SnapshotStatus;
+use std::future::Future;
+use std::path::Path;
+use tokio::runtime::Runtime;
+
+// A RagLibrary for code without an async runtime, e.g. a plain CLI tool: every call blocks on
+// a runtime the facade owns. Do not use it from inside a tokio runtime (calls would panic);
+// async code should use crate::RagLibrary directly.
+pub struct RagLibrary {
+    // Dropped before the runtime, which its providers may still use while shutting down
+    inner: crate::RagLibrary,
+    runtime: Runtime,
+}
+
+impl RagLibrary {
+    // Set up from the environment with the documents directory loaded (see
+    // crate::RagLibrary::new)
+    pub fn new() -> Result<Self> {
+        let runtime = new_runtime()?;
+        let inner = runtime.block_on(crate::RagLibrary::new())?;
+        Ok(Self { inner, runtime })
+    }
+
+    // Set up from `config` without ingesting anything; call load_documents next
+    pub fn with_config(config: RagConfig) -> Result<Self> {
+        Self::from_builder(RagLibraryBuilder::new().with_config(config))
+    }
+
+    // Builds `builder`'s library without ingesting anything; call load_documents next
+    pub fn from_builder(builder: RagLibraryBuilder) -> Result<Self> {
+        let runtime = new_runtime()?;
+        let inner = runtime.block_on(builder.build())?;
+        Ok(Self { inner, runtime })
+    }
+
+    // The async library, for what the facade does not wrap; run its futures with `block_on`
+    pub fn inner(&self) -> &crate::RagLibrary {
+        &self.inner
+    }
+
+    // Runs `future` on the facade's runtime
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+
+    pub fn load_documents(&self) -> Result<usize> {
+        self.block_on(self.inner.load_documents())
+    }
+
+    pub fn add_document(&self, input: DocumentInput) -> Result<String> {
+        self.block_on(self.inner.add_document(input))
+    }
+
+    pub fn remove_document(&self, id: &str) -> Result<Document> {
+        self.block_on(self.inner.remove_document(id))
+    }
+
+    pub fn answer(&self, request: &QueryRequest) -> Result<QueryResponse> {
+        self.block_on(self.inner.answer(request))
+    }
+
+    // Answers `query` from up to `max_results` chunks
+    pub fn query(&self, query: &str, max_results: usize) -> Result<QueryResponse> {
+        let request = QueryRequest { query: query.to_string(), max_results: Some(max_results), ..Default::default() };
+        self.answer(&request)
+    }
+
+    pub fn save_state(&self) -> Result<()> {
+        self.block_on(self.inner.save_state())
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        self.block_on(self.inner.save(path))
+    }
+
+    pub fn load(&self, path: &Path) -> Result<SnapshotStatus> {
+        self.block_on(self.inner.load(path))
+    }
+
+    // Number of documents in the store
+    pub fn document_count(&self) -> usize {
+        self.block_on(self.inner.store().len())
+    }
+}
+
+// Two workers: enough for HTTP clients and timers to make progress while a call blocks
+fn new_runtime() -> Result<Runtime> {
+    Ok(tokio::runtime::Builder::new_multi_thread().worker_threads(2).enable_all().build()?)
+}
@@ -0,0 +1,130 @@
+use crate::models::{QueryRequest, QueryResponse};
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_MAX_CACHED_RESPONSES: usize = 1024;
+
+// Source of collection versions. One counter is shared by every collection in the process, so
+// two collections never hand out the same version and cannot serve each other's answers.
+static NEXT_COLLECTION_VERSION: AtomicU64 = AtomicU64::new(1);
+
+// A version no collection has had so far; collections take a new one on every change (see
+// DocumentStore::generation), which invalidates the answers cached for the old one
+pub fn next_collection_version() -> u64 {
+    NEXT_COLLECTION_VERSION.fetch_add(1, Ordering::Relaxed)
+}
+
+// Cache key for `request` against a collection version; the whole request is part of the
+// key so differently tuned runs of the same question don't share answers
+pub fn cache_key(version: u64, request: &QueryRequest) -> String {
+    format!("{:016x}:{}", version, serde_json::to_string(request).unwrap_or_default())
+}
+
+// Lookups served from a cache and lookups that missed it
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl CacheStats {
+    // Share of lookups that were hits; None before the first lookup
+    pub fn hit_rate(&self) -> Option<f64> {
+        match self.hits + self.misses {
+            0 => None,
+            lookups => Some(self.hits as f64 / lookups as f64),
+        }
+    }
+}
+
+// Full responses with a time-to-live and a bounded number of entries
+pub struct ResponseCache {
+    entries: RwLock<HashMap<String, (Instant, QueryResponse)>>,
+    ttl: Duration,
+    max_entries: usize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl ResponseCache {
+    pub fn new(ttl: Duration, max_entries: usize) -> Self {
+        Self {
+            entries: RwLock::new(HashMap::new()),
+            ttl,
+            max_entries: max_entries.max(1),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<QueryResponse> {
+        let found = self
+            .entries
+            .read()
+            .unwrap()
+            .get(key)
+            .filter(|(stored_at, _)| stored_at.elapsed() < self.ttl)
+            .map(|(_, response)| response.clone());
+        let counter = if found.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        found
+    }
+
+    // Lookups through get since the cache was created; get_stale is not counted
+    pub fn stats(&self) -> CacheStats {
+        CacheStats { hits: self.hits.load(Ordering::Relaxed), misses: self.misses.load(Ordering::Relaxed) }
+    }
+
+    // The entry however old, as long as it has not been evicted
+    pub fn get_stale(&self, key: &str) -> Option<QueryResponse> {
+        self.entries.read().unwrap().get(key).map(|(_, response)| response.clone())
+    }
+
+    pub fn insert(&self, key: String, response: QueryResponse) {
+        let mut entries = self.entries.write().unwrap();
+
+        if entries.len() >= self.max_entries && !entries.contains_key(&key) {
+            entries.retain(|_, (stored_at, _)| stored_at.elapsed() < self.ttl);
+            // Still full: drop the oldest entry
+            if entries.len() >= self.max_entries {
+                if let Some(oldest) = entries
+                    .iter()
+                    .min_by_key(|(_, (stored_at, _))| *stored_at)
+                    .map(|(key, _)| key.clone())
+                {
+                    entries.remove(&oldest);
+                }
+            }
+        }
+
+        entries.insert(key, (Instant::now(), response));
+    }
+
+    // Drops the entries whose response `cites`, returning how many there were
+    pub fn remove_citing(&self, cites: impl Fn(&QueryResponse) -> bool) -> usize {
+        let mut entries = self.entries.write().unwrap();
+        let before = entries.len();
+        entries.retain(|_, (_, response)| !cites(response));
+        before - entries.len()
+    }
+
+    pub fn any_citing(&self, cites: impl Fn(&QueryResponse) -> bool) -> bool {
+        self.entries.read().unwrap().values().any(|(_, response)| cites(response))
+    }
+
+    pub fn clear(&self) {
+        self.entries.write().unwrap().clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
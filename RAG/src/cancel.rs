@@ -0,0 +1,45 @@
+use std::future::Future;
+
+use crate::error::{RagError, Result};
+
+pub use tokio_util::sync::CancellationToken;
+
+tokio::task_local! {
+    static CURRENT: CancellationToken;
+}
+
+// Runs `future` with `token` as its cancellation token, e.g. one cancelled when the client
+// of a request goes away. Once it is cancelled, library work inside stops with
+// RagError::Cancelled: queries drop their LLM calls in flight, and ingestion stops between
+// documents, before the store is changed. Tasks the future spawns are not covered; run
+// them with the token too (see `current`).
+pub async fn run<F: Future>(token: CancellationToken, future: F) -> F::Output {
+    CURRENT.scope(token, future).await
+}
+
+// The token of the enclosing `run`, if any
+pub fn current() -> Option<CancellationToken> {
+    CURRENT.try_with(CancellationToken::clone).ok()
+}
+
+// Fails once the enclosing `run`'s token is cancelled
+pub(crate) fn check() -> Result<()> {
+    match CURRENT.try_with(CancellationToken::is_cancelled) {
+        Ok(true) => Err(RagError::Cancelled),
+        _ => Ok(()),
+    }
+}
+
+// Runs `future` like until_cancelled, failing with RagError::Cancelled when cancelled
+pub(crate) async fn or_cancelled<T, F: Future<Output = Result<T>>>(future: F) -> Result<T> {
+    until_cancelled(future).await.unwrap_or(Err(RagError::Cancelled))
+}
+
+// Runs `future`, which must be safe to abandon at any await point, until the enclosing
+// `run`'s token is cancelled; None when it was
+pub(crate) async fn until_cancelled<F: Future>(future: F) -> Option<F::Output> {
+    match current() {
+        Some(token) => token.run_until_cancelled(future).await,
+        None => Some(future.await),
+    }
+}
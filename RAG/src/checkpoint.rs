@@ -0,0 +1,123 @@
+use crate::encryption::{self, StateCipher};
+use crate::error::Result;
+use crate::models::Document;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+// File name of the ingestion checkpoint kept in the state directory
+pub const CHECKPOINT_FILE: &str = "ingest-checkpoint.jsonl";
+
+// Documents per checkpoint unless configured otherwise
+pub const DEFAULT_CHECKPOINT_EVERY: usize = 100;
+
+// First line of a checkpoint; the documents follow, one per line
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+    // See config_fingerprint
+    config_fingerprint: String,
+}
+
+// Progress of a long ingestion run (see RagLibrary::load_documents), so one that crashed or was
+// stopped resumes after the last document it embedded instead of starting over. Embedded
+// documents are appended as they are done, and the file is removed once the run completes
+// and the full index is saved.
+//
+// With a cipher every line is encrypted on its own, so appending needs no rewrite.
+pub struct Checkpoint {
+    path: PathBuf,
+    file: File,
+    cipher: Option<StateCipher>,
+}
+
+impl Checkpoint {
+    // Documents recorded at `path` by an interrupted run with the same settings, each passed to
+    // `each` as it is read (see Snapshot::read_with). A line cut short by a crash ends the list.
+    pub fn resume(
+        path: &Path,
+        cipher: Option<&StateCipher>,
+        config_fingerprint: &str,
+        mut each: impl FnMut(&mut Document),
+    ) -> Result<Vec<Document>> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut lines = BufReader::new(file).lines();
+        let header = match lines.next() {
+            Some(line) => serde_json::from_str::<Header>(&encryption::open_text(cipher, line?)?).ok(),
+            None => None,
+        };
+        if header.is_none_or(|header| header.config_fingerprint != config_fingerprint) {
+            tracing::info!("Ignoring ingestion checkpoint {} taken with other settings", path.display());
+            return Ok(Vec::new());
+        }
+
+        let mut documents = Vec::new();
+        for line in lines {
+            let line = encryption::open_text(cipher, line?);
+            match line.ok().and_then(|line| serde_json::from_str(&line).ok()) {
+                Some(mut document) => {
+                    each(&mut document);
+                    documents.push(document);
+                }
+                None => break,
+            }
+        }
+        Ok(documents)
+    }
+
+    // Starts a checkpoint at `path` holding `documents`, replacing any earlier one. It is
+    // written next to `path` and renamed into place, so a crash meanwhile keeps the old one.
+    pub fn create<F>(
+        path: &Path,
+        cipher: Option<&StateCipher>,
+        config_fingerprint: &str,
+        documents: &[Document],
+        prepare: F,
+    ) -> Result<Self>
+    where
+        F: Fn(&Document) -> Result<Cow<'_, Document>>,
+    {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let temp = path.with_extension("jsonl.tmp");
+        let header = Header { config_fingerprint: config_fingerprint.to_string() };
+        let mut file = File::create(&temp)?;
+        let header = serde_json::to_string(&header).map_err(io::Error::from)?;
+        writeln!(file, "{}", encryption::seal_text(cipher, header))?;
+        let mut checkpoint = Self { path: temp, file, cipher: cipher.cloned() };
+        checkpoint.append(documents, prepare)?;
+        fs::rename(&checkpoint.path, path)?;
+        checkpoint.path = path.to_path_buf();
+        Ok(checkpoint)
+    }
+
+    // Records `documents` as done, each as returned by `prepare` (see Snapshot::write); they
+    // are on disk when this returns
+    pub fn append<F>(&mut self, documents: &[Document], prepare: F) -> Result<()>
+    where
+        F: Fn(&Document) -> Result<Cow<'_, Document>>,
+    {
+        let mut lines = Vec::new();
+        for document in documents {
+            let line = serde_json::to_string(&*prepare(document)?).map_err(io::Error::from)?;
+            lines.extend_from_slice(encryption::seal_text(self.cipher.as_ref(), line).as_bytes());
+            lines.push(b'\n');
+        }
+        self.file.write_all(&lines)?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+
+    // Removes the checkpoint once the run it tracks has completed
+    pub fn finish(self) -> Result<()> {
+        drop(self.file);
+        fs::remove_file(&self.path)?;
+        Ok(())
+    }
+}
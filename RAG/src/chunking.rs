@@ -0,0 +1,114 @@
+use crate::models::{Document, DocumentChunk, SharedText};
+use crate::error::{RagError, Result};
+use tiktoken_rs::{cl100k_base, CoreBPE};
+use unicode_segmentation::UnicodeSegmentation;
+use uuid::Uuid;
+
+// Splits text into chunks of whole sentences holding at most `max_tokens` cl100k tokens
+// each, repeating up to `overlap_tokens` worth of trailing sentences at the start of the next
+// chunk. Chunks are ranges of the text, so their positions are byte offsets into it.
+pub struct TokenChunker {
+    bpe: CoreBPE,
+    max_tokens: usize,
+    overlap_tokens: usize,
+}
+
+// A sentence with its byte offset in the text
+#[derive(Debug, Clone)]
+struct IndexedSentence {
+    content: String,
+    start: usize,
+}
+
+impl TokenChunker {
+    pub fn new(max_tokens: usize, overlap_tokens: usize) -> Result<Self> {
+        let bpe = cl100k_base().map_err(|e| RagError::Config(format!("Failed to load tokenizer: {}", e)))?;
+        Ok(Self { bpe, max_tokens, overlap_tokens })
+    }
+
+    // `text` chunked as a new document named `filename`
+    pub fn document(&self, filename: String, text: String) -> Document {
+        let text = SharedText::from(text);
+        let document = Document {
+            id: Uuid::new_v4().to_string(),
+            chunks: self.chunk(&text),
+            filename,
+            content: text,
+            metadata: Default::default(),
+        };
+        tracing::info!(doc_id = %document.id, filename = %document.filename, chunk_count = document.chunks.len(), "Chunked document");
+        document
+    }
+
+    pub fn chunk(&self, text: &SharedText) -> Vec<DocumentChunk> {
+        let mut chunks = Vec::new();
+        let mut buffer: Vec<IndexedSentence> = Vec::new();
+        let mut buffer_tokens = 0;
+
+        for sentence in segment_sentences(text) {
+            let sentence_tokens = self.tokens(&sentence.content);
+
+            if buffer_tokens + sentence_tokens > self.max_tokens && !buffer.is_empty() {
+                chunks.extend(chunk_from_sentences(text, &buffer));
+
+                let mut overlap = Vec::new();
+                let mut overlap_tokens = 0;
+                for s in buffer.iter().rev() {
+                    let s_tokens = self.tokens(&s.content);
+                    if overlap_tokens + s_tokens > self.overlap_tokens {
+                        break;
+                    }
+                    overlap.insert(0, s.clone());
+                    overlap_tokens += s_tokens;
+                }
+
+                buffer = overlap;
+                buffer_tokens = overlap_tokens;
+            }
+
+            buffer.push(sentence);
+            buffer_tokens += sentence_tokens;
+        }
+
+        chunks.extend(chunk_from_sentences(text, &buffer));
+
+        chunks
+    }
+
+    fn tokens(&self, text: &str) -> usize {
+        self.bpe.encode_ordinary(text).len()
+    }
+}
+
+// The range of `text` from the first sentence to the end of the last; None without sentences
+fn chunk_from_sentences(text: &SharedText, sentences: &[IndexedSentence]) -> Option<DocumentChunk> {
+    let (first, last) = (sentences.first()?, sentences.last()?);
+    let (start, end) = (first.start, last.start + last.content.len());
+
+    Some(DocumentChunk {
+        id: Uuid::new_v4().to_string(),
+        content: text.slice(start..end)?,
+        start_position: start,
+        end_position: end,
+        embedding: None,
+        entities: None,
+    })
+}
+
+// Unicode sentences of `text`, trimmed, with the offset where each starts
+fn segment_sentences(text: &str) -> Vec<IndexedSentence> {
+    let mut sentences = Vec::new();
+    let mut offset = 0;
+
+    for sentence in text.unicode_sentences() {
+        let trimmed = sentence.trim();
+        if !trimmed.is_empty() {
+            sentences.push(IndexedSentence {
+                content: trimmed.to_string(),
+                start: offset + (sentence.len() - sentence.trim_start().len()),
+            });
+        }
+        offset += sentence.len();
+    }
+    sentences
+}
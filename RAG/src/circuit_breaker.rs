@@ -0,0 +1,211 @@
+use crate::error::{RagError, Result};
+use crate::models::Document;
+use crate::providers::{EmbeddingProvider, Generation, LlmProvider};
+use crate::vector_tier::ChunkVectors;
+use async_trait::async_trait;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+pub const DEFAULT_FAILURE_THRESHOLD: u32 = 5;
+pub const DEFAULT_COOLDOWN: Duration = Duration::from_secs(30);
+
+// Stops calling a provider that keeps failing. After `failure_threshold` consecutive
+// failures the circuit opens and calls fail at once with RagError::Unavailable, instead of
+// each request waiting on the same timeout. Once `cooldown` has passed a single trial call
+// is let through: success closes the circuit, failure opens it for another cooldown.
+//
+// Only failures that say something about the provider count: transport errors, 429 and 5xx
+// replies and embedding failures. A rejected prompt (4xx) is the caller's problem.
+pub struct CircuitBreaker {
+    // Named in errors and logs, e.g. "llm"
+    name: &'static str,
+    failure_threshold: u32,
+    cooldown: Duration,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum State {
+    Closed { failures: u32 },
+    Open { until: Instant },
+    // The trial call is in flight; everything else still fails fast. A trial that never
+    // reports back (its request was cancelled) is replaced after a cooldown.
+    HalfOpen { since: Instant },
+}
+
+impl CircuitBreaker {
+    pub fn new(name: &'static str, failure_threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            name,
+            failure_threshold: failure_threshold.max(1),
+            cooldown,
+            state: Mutex::new(State::Closed { failures: 0 }),
+        }
+    }
+
+    // Whether calls are currently failing fast
+    pub fn is_open(&self) -> bool {
+        match *self.state.lock().unwrap() {
+            State::Closed { .. } => false,
+            State::Open { until } => Instant::now() < until,
+            State::HalfOpen { .. } => true,
+        }
+    }
+
+    // Runs `call` unless the circuit is open
+    pub async fn call<T, F>(&self, call: F) -> Result<T>
+    where
+        F: Future<Output = Result<T>>,
+    {
+        self.admit()?;
+        let result = call.await;
+        match &result {
+            Err(e) if counts_as_failure(e) => self.on_failure(),
+            // A rejected request still shows the provider is up
+            _ => self.on_success(),
+        }
+        result
+    }
+
+    fn admit(&self) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        match *state {
+            State::Closed { .. } => Ok(()),
+            State::Open { until } if Instant::now() >= until => {
+                tracing::info!(provider = self.name, "Circuit half-open, sending a trial call");
+                *state = State::HalfOpen { since: Instant::now() };
+                Ok(())
+            }
+            State::HalfOpen { since } if since.elapsed() >= self.cooldown => {
+                *state = State::HalfOpen { since: Instant::now() };
+                Ok(())
+            }
+            State::Open { until } => Err(self.unavailable(until.saturating_duration_since(Instant::now()))),
+            State::HalfOpen { since } => Err(self.unavailable(self.cooldown.saturating_sub(since.elapsed()))),
+        }
+    }
+
+    fn on_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        if matches!(*state, State::HalfOpen { .. }) {
+            tracing::info!(provider = self.name, "Circuit closed, provider recovered");
+        }
+        *state = State::Closed { failures: 0 };
+    }
+
+    fn on_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        let failures = match *state {
+            State::Closed { failures } => failures + 1,
+            // Failed trial
+            State::HalfOpen { .. } => self.failure_threshold,
+            // Calls admitted before the circuit opened; it is open already
+            State::Open { .. } => return,
+        };
+        *state = if failures >= self.failure_threshold {
+            tracing::warn!(
+                provider = self.name,
+                failures,
+                "Circuit open, failing calls fast for {:?}",
+                self.cooldown
+            );
+            State::Open { until: Instant::now() + self.cooldown }
+        } else {
+            State::Closed { failures }
+        };
+    }
+
+    fn unavailable(&self, retry_after: Duration) -> RagError {
+        RagError::Unavailable {
+            provider: self.name.to_string(),
+            // Rounded up, so a retry after that many seconds gets the trial call
+            retry_after_secs: retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0),
+        }
+    }
+}
+
+fn counts_as_failure(error: &RagError) -> bool {
+    match error {
+        RagError::LlmApi { status, .. } => *status == 429 || *status >= 500,
+        RagError::Llm(_) | RagError::Embedding(_) => true,
+        _ => false,
+    }
+}
+
+// An LLM provider behind a circuit breaker
+pub struct BreakerLlmProvider {
+    inner: Arc<dyn LlmProvider>,
+    breaker: Arc<CircuitBreaker>,
+}
+
+impl BreakerLlmProvider {
+    pub fn new(inner: Arc<dyn LlmProvider>, breaker: Arc<CircuitBreaker>) -> Self {
+        Self { inner, breaker }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for BreakerLlmProvider {
+    async fn generate(&self, prompt: &str) -> Result<String> {
+        self.breaker.call(self.inner.generate(prompt)).await
+    }
+
+    async fn generate_with_confidence(&self, prompt: &str) -> Result<Generation> {
+        self.breaker.call(self.inner.generate_with_confidence(prompt)).await
+    }
+
+    async fn generate_stream(&self, prompt: &str, deltas: &mpsc::Sender<String>) -> Result<String> {
+        self.breaker.call(self.inner.generate_stream(prompt, deltas)).await
+    }
+}
+
+// An embedding provider behind a circuit breaker, which can be shared by several providers
+// of the same backend (e.g. the library's and those of ad-hoc documents)
+pub struct BreakerEmbeddingProvider {
+    inner: Arc<dyn EmbeddingProvider>,
+    breaker: Arc<CircuitBreaker>,
+}
+
+impl BreakerEmbeddingProvider {
+    pub fn new(inner: Arc<dyn EmbeddingProvider>, breaker: Arc<CircuitBreaker>) -> Self {
+        Self { inner, breaker }
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for BreakerEmbeddingProvider {
+    async fn generate_embeddings(&self, documents: &mut [Document]) -> Result<()> {
+        self.breaker.call(self.inner.generate_embeddings(documents)).await
+    }
+
+    async fn embed_query(&self, query: &str) -> Result<Vec<f32>> {
+        self.breaker.call(self.inner.embed_query(query)).await
+    }
+
+    fn fitted_to_corpus(&self) -> bool {
+        self.inner.fitted_to_corpus()
+    }
+
+    // The wrapped provider's, so snapshots stay valid with or without the breaker
+    fn kind(&self) -> String {
+        self.inner.kind()
+    }
+
+    fn fitted_state(&self) -> Option<serde_json::Value> {
+        self.inner.fitted_state()
+    }
+
+    fn restore_fitted_state(&self, state: serde_json::Value) -> Result<()> {
+        self.inner.restore_fitted_state(state)
+    }
+
+    fn calculate_similarity(&self, embedding1: &[f32], embedding2: &[f32]) -> f32 {
+        self.inner.calculate_similarity(embedding1, embedding2)
+    }
+
+    fn stored_vectors(&self, document: &Document) -> Result<Option<Arc<ChunkVectors>>> {
+        self.inner.stored_vectors(document)
+    }
+}
@@ -0,0 +1,471 @@
+use crate::conflict::{extract_mentions, sentences, Unit, STOP_WORDS};
+use crate::models::*;
+use crate::prompt::build_clause_prompt;
+use crate::providers::LlmProvider;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::sync::Arc;
+
+// Words kept of a subject; longer phrases are rarely what a clause applies to
+const MAX_SUBJECT_WORDS: usize = 6;
+// Chunks cited by a clause answer
+const MAX_CLAUSE_CITATIONS: usize = 5;
+
+// Phrases marking a sentence as a clause of each kind, checked in this order: a sub-limit is
+// usually stated as a share of the sum insured, and a waiting period can mention an exclusion
+const KIND_KEYWORDS: &[(ClauseKind, &[&str])] = &[
+    (ClauseKind::WaitingPeriod, &["waiting period"]),
+    (ClauseKind::SubLimit, &["sub-limit", "sub limit", "sublimit", "capped at", "limited to"]),
+    (ClauseKind::SumInsured, &["sum insured"]),
+    (ClauseKind::Exclusion, &["excluded", "exclusion", "not covered", "not payable", "shall not be liable"]),
+];
+
+// Phrases in a question asking for a clause of each kind, in the same order
+const QUESTION_KEYWORDS: &[(ClauseKind, &[&str])] = &[
+    (ClauseKind::WaitingPeriod, &["waiting period"]),
+    (ClauseKind::SubLimit, &["sub-limit", "sub limit", "sublimit", "limit on", "limit for", "capped"]),
+    (ClauseKind::SumInsured, &["sum insured"]),
+    (ClauseKind::Exclusion, &["exclu"]),
+];
+
+// Words that introduce the subject after a keyword ("waiting period for cataract surgery")
+const SUBJECT_PREFIXES: &[&str] = &["for ", "on ", "of ", "in respect of ", "applicable to ", "applicable for "];
+// Words that end it ("... cataract surgery is 24 months")
+const SUBJECT_ENDS: &[&str] = &[" is ", " are ", " shall ", " will ", " would ", " of rs", " at ", " upto ", " up to ", " under "];
+// Trimmed from either end of a subject
+const FILLER_WORDS: &[&str] = &[
+    "a", "an", "the", "is", "are", "be", "been", "has", "have", "shall", "will", "with", "of", "to", "at", "there", "any",
+    "all", "initial", "specific", "general", "standard", "following", "expenses", "related",
+];
+// Words of a question that say nothing about the subject
+const QUESTION_WORDS: &[&str] = &[
+    "what", "which", "how", "long", "much", "many", "does", "did", "can", "there", "tell", "about", "waiting", "period",
+    "sub", "limit", "sublimit", "capped", "sum", "insured", "excluded", "exclusion", "covered", "cover", "policy", "plan",
+    "insurance",
+];
+
+// How clauses are extracted at ingest. CLAUSE_EXTRACTION: "off" (default), "patterns" for the
+// built-in sentence patterns, or "llm" to ask the LLM about passages the patterns flag
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ClauseExtraction {
+    #[default]
+    Off,
+    Patterns,
+    Llm,
+}
+
+impl FromStr for ClauseExtraction {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "off" | "none" => Ok(Self::Off),
+            "patterns" => Ok(Self::Patterns),
+            "llm" => Ok(Self::Llm),
+            other => Err(format!("unknown clause extraction {:?}, expected off, patterns or llm", other)),
+        }
+    }
+}
+
+impl ClauseExtraction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Patterns => "patterns",
+            Self::Llm => "llm",
+        }
+    }
+}
+
+// Fills in DocumentMetadata::clauses of documents being ingested, so questions about waiting
+// periods, sub-limits, exclusions and sums insured can be answered from them directly (see
+// match_clauses)
+pub struct ClauseExtractor {
+    mode: ClauseExtraction,
+    llm: Arc<dyn LlmProvider>,
+}
+
+#[derive(Deserialize)]
+struct RawClause {
+    kind: String,
+    #[serde(default)]
+    subject: String,
+    #[serde(default)]
+    value: Value,
+    #[serde(default)]
+    text: String,
+}
+
+impl ClauseExtractor {
+    pub fn new(mode: ClauseExtraction, llm: Arc<dyn LlmProvider>) -> Self {
+        Self { mode, llm }
+    }
+
+    pub fn mode(&self) -> ClauseExtraction {
+        self.mode
+    }
+
+    // Extracts the clauses of the documents that have none yet; documents restored from a
+    // snapshot or checkpoint keep theirs
+    pub async fn extract(&self, documents: &mut [Document]) {
+        if self.mode == ClauseExtraction::Off {
+            return;
+        }
+        for document in documents.iter_mut().filter(|doc| doc.metadata.clauses.is_none()) {
+            let mut clauses = Vec::new();
+            for chunk in &document.chunks {
+                let found = match self.mode {
+                    ClauseExtraction::Llm if mentions_clause(&chunk.content) => self.ask_llm(chunk).await,
+                    _ => pattern_clauses(&chunk.content, &chunk.id),
+                };
+                for clause in found {
+                    let duplicate = clauses.iter().any(|c: &Clause| {
+                        c.kind == clause.kind && c.subject == clause.subject && c.value == clause.value
+                    });
+                    if !duplicate {
+                        clauses.push(clause);
+                    }
+                }
+            }
+            tracing::debug!("Extracted {} clauses from {}", clauses.len(), document.filename);
+            document.metadata.clauses = Some(clauses);
+        }
+    }
+
+    // The clauses the LLM finds in `chunk`, or the patterns' when it fails or answers
+    // something other than a JSON array
+    async fn ask_llm(&self, chunk: &DocumentChunk) -> Vec<Clause> {
+        let output = match self.llm.generate(&build_clause_prompt(&chunk.content)).await {
+            Ok(output) => output,
+            Err(e) => {
+                tracing::warn!("Clause extraction for chunk {} fell back to patterns: {}", chunk.id, e);
+                return pattern_clauses(&chunk.content, &chunk.id);
+            }
+        };
+        match parse_clauses(&output, &chunk.id) {
+            Some(clauses) => clauses,
+            None => {
+                tracing::warn!("Clause extraction for chunk {} fell back to patterns: unparseable output", chunk.id);
+                pattern_clauses(&chunk.content, &chunk.id)
+            }
+        }
+    }
+}
+
+fn parse_clauses(output: &str, chunk_id: &str) -> Option<Vec<Clause>> {
+    let start = output.find('[')?;
+    let end = output.rfind(']')?;
+    if end < start {
+        return None;
+    }
+    let raw: Vec<RawClause> = serde_json::from_str(&output[start..=end]).ok()?;
+    let clauses = raw
+        .into_iter()
+        .filter_map(|raw| {
+            let kind = raw.kind.parse().ok()?;
+            let value = match raw.value {
+                Value::Null => None,
+                Value::String(value) => Some(value.trim().to_string()).filter(|value| !value.is_empty()),
+                other => Some(other.to_string()),
+            };
+            let text = raw.text.trim().to_string();
+            (!text.is_empty()).then(|| Clause {
+                kind,
+                subject: normalize_subject(&raw.subject),
+                value,
+                text,
+                chunk_id: chunk_id.to_string(),
+            })
+        })
+        .collect();
+    Some(clauses)
+}
+
+fn mentions_clause(text: &str) -> bool {
+    let lower = text.to_ascii_lowercase();
+    KIND_KEYWORDS.iter().any(|(_, keywords)| keywords.iter().any(|keyword| lower.contains(keyword)))
+}
+
+// Clauses stated in `text` as single sentences such as "The waiting period for cataract
+// surgery is 24 months". Waiting periods, sub-limits and sums insured need a value in the
+// sentence, exclusions a subject.
+pub fn pattern_clauses(text: &str, chunk_id: &str) -> Vec<Clause> {
+    let mut clauses = Vec::new();
+    for sentence in text.split(['\n', ';']).flat_map(sentences) {
+        let sentence = sentence.trim();
+        // ASCII lowercasing keeps the byte offsets of `sentence`
+        let lower = sentence.to_ascii_lowercase();
+        let Some((kind, start, end)) = KIND_KEYWORDS.iter().find_map(|(kind, keywords)| {
+            keywords.iter().find_map(|keyword| lower.find(keyword).map(|start| (*kind, start, start + keyword.len())))
+        }) else {
+            continue;
+        };
+
+        let mentions = extract_mentions(sentence);
+        let value = match kind {
+            ClauseKind::WaitingPeriod => mentions.iter().find(|m| m.unit == Unit::Days),
+            ClauseKind::SubLimit => mentions.iter().find(|m| matches!(m.unit, Unit::Percent | Unit::Currency)),
+            ClauseKind::SumInsured => mentions.iter().find(|m| m.unit == Unit::Currency),
+            ClauseKind::Exclusion => None,
+        };
+        let value = value.map(|mention| mention.text.clone());
+        if value.is_none() && kind != ClauseKind::Exclusion {
+            continue;
+        }
+
+        let subject = subject(&lower, start, end, kind);
+        if subject.is_empty() && kind == ClauseKind::Exclusion {
+            continue;
+        }
+        clauses.push(Clause {
+            kind,
+            subject,
+            value,
+            text: sentence.trim_end_matches('.').to_string() + ".",
+            chunk_id: chunk_id.to_string(),
+        });
+    }
+    clauses
+}
+
+// What the clause whose keyword spans lower[start..end] applies to: the phrase after it
+// ("waiting period for X is ..."), else the one before it ("X is excluded")
+fn subject(lower: &str, start: usize, end: usize, kind: ClauseKind) -> String {
+    let after = &lower[end..];
+    let after = after.strip_prefix('s').unwrap_or(after).trim_start_matches([' ', ',']);
+    if let Some(rest) = SUBJECT_PREFIXES.iter().find_map(|prefix| after.strip_prefix(prefix)) {
+        let rest = format!(" {} ", rest);
+        let stop = SUBJECT_ENDS
+            .iter()
+            .filter_map(|end| rest.find(end))
+            .chain(rest.find(|c: char| c.is_ascii_digit() || ",;:()₹".contains(c)))
+            .min()
+            .unwrap_or(rest.len());
+        let words: Vec<&str> = rest[..stop].split_whitespace().take(MAX_SUBJECT_WORDS).collect();
+        let subject = normalize_subject(&words.join(" "));
+        if !subject.is_empty() {
+            return subject;
+        }
+    }
+
+    // A sum insured without a subject is the policy's
+    if kind == ClauseKind::SumInsured {
+        return String::new();
+    }
+    let before = &lower[..start];
+    let begin = before.rfind([',', ';', ':', '(', ')']).map(|i| i + 1).unwrap_or(0);
+    let words: Vec<&str> = before[begin..].split_whitespace().collect();
+    let words = &words[words.len().saturating_sub(MAX_SUBJECT_WORDS)..];
+    normalize_subject(&words.join(" "))
+}
+
+// Lower case, without punctuation or filler words at either end
+fn normalize_subject(subject: &str) -> String {
+    let lower = subject.to_lowercase();
+    let mut words: Vec<&str> = lower
+        .split_whitespace()
+        .map(|word| word.trim_matches(|c: char| !c.is_alphanumeric() && c != '-'))
+        .filter(|word| !word.is_empty())
+        .collect();
+    while words.first().is_some_and(|word| FILLER_WORDS.contains(word)) {
+        words.remove(0);
+    }
+    while words.last().is_some_and(|word| FILLER_WORDS.contains(word)) {
+        words.pop();
+    }
+    words.join(" ")
+}
+
+// A question answered from extracted clauses
+pub struct ClauseMatch<'a> {
+    pub answer: String,
+    // The clauses stating the answer, with their documents; cited in this order
+    pub clauses: Vec<(&'a Document, &'a Clause)>,
+}
+
+// Answers `query` from the clauses of `documents` when it asks for a clause kind and the
+// clauses best matching its subject all state the same value; None leaves it to the LLM.
+// A clause matches when every word of its subject is in the question and every other word
+// of the question is in its subject or sentence, so a question about "knee surgery" is not
+// answered with the clause for "surgery".
+pub fn match_clauses<'a>(query: &str, documents: impl Iterator<Item = &'a Document>) -> Option<ClauseMatch<'a>> {
+    let lower = query.to_lowercase();
+    let kind = QUESTION_KEYWORDS
+        .iter()
+        .find(|(_, keywords)| keywords.iter().any(|keyword| lower.contains(keyword)))
+        .map(|(kind, _)| *kind)?;
+    let asked: HashSet<String> = content_words(&lower).filter(|word| !QUESTION_WORDS.contains(&word.as_str())).collect();
+
+    let mut best: Vec<(&Document, &Clause)> = Vec::new();
+    let mut best_score = 0;
+    for document in documents {
+        for clause in document.metadata.clauses.iter().flatten().filter(|clause| clause.kind == kind) {
+            let subject: HashSet<String> = content_words(&clause.subject).collect();
+            let text: HashSet<String> = content_words(&clause.text.to_lowercase()).collect();
+            if !subject.iter().all(|word| asked.contains(word))
+                || !asked.iter().all(|word| subject.contains(word) || text.contains(word))
+            {
+                continue;
+            }
+            let score = subject.len() + 1;
+            if score > best_score {
+                best_score = score;
+                best.clear();
+            }
+            if score == best_score {
+                best.push((document, clause));
+            }
+        }
+    }
+
+    let (_, first) = *best.first()?;
+    let value = first.value.as_deref().map(str::to_lowercase);
+    if best.iter().any(|(_, clause)| clause.value.as_deref().map(str::to_lowercase) != value) {
+        tracing::debug!("Clauses disagree on the {:?} asked about; answering with the LLM", kind);
+        return None;
+    }
+    best.truncate(MAX_CLAUSE_CITATIONS);
+
+    // The wording of each clause, unless it says exactly what the answer does
+    let statement = statement(first);
+    let sources: Vec<String> = best
+        .iter()
+        .map(|(doc, clause)| match clause.text.eq_ignore_ascii_case(&statement) {
+            true => format!("({})", doc.filename),
+            false => format!("\"{}\" ({})", clause.text, doc.filename),
+        })
+        .collect();
+    Some(ClauseMatch { answer: format!("{}\n\n{}", statement, sources.join("\n")), clauses: best })
+}
+
+fn statement(clause: &Clause) -> String {
+    let value = clause.value.as_deref().unwrap_or_default();
+    let subject = |joiner: &str| match clause.subject.as_str() {
+        "" => String::new(),
+        subject => format!(" {} {}", joiner, subject),
+    };
+    match clause.kind {
+        ClauseKind::WaitingPeriod => format!("The waiting period{} is {}.", subject("for"), value),
+        ClauseKind::SubLimit => format!("The sub-limit{} is {}.", subject("on"), value),
+        ClauseKind::SumInsured => format!("The sum insured{} is {}.", subject("for"), value),
+        ClauseKind::Exclusion => {
+            let mut chars = clause.subject.chars();
+            let subject: String = chars.next().into_iter().flat_map(char::to_uppercase).chain(chars).collect();
+            format!("{} is excluded.", subject)
+        }
+    }
+}
+
+// Words that can tell subjects apart: numbers, and words of three letters or more that are
+// not stop words, without a plural "s"
+fn content_words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| {
+            word.chars().all(|c| c.is_ascii_digit())
+                || (word.chars().count() > 2 && !STOP_WORDS.contains(&word.to_lowercase().as_str()))
+        })
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let word = word.to_lowercase();
+            match word.strip_suffix('s') {
+                Some(stem) if stem.len() > 3 && !stem.ends_with('s') => stem.to_string(),
+                _ => word,
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY: &str = "The waiting period for cataract surgery is 24 months. Room rent is capped at 1% of the sum insured \
+                          per day. The sum insured is Rs. 5,00,000. Cosmetic surgery is excluded.\n\
+                          The waiting period for knee replacement surgery is 48 months.";
+
+    fn document(filename: &str, text: &str) -> Document {
+        let mut document = Document {
+            id: filename.to_string(),
+            filename: filename.to_string(),
+            content: text.into(),
+            chunks: Vec::new(),
+            metadata: DocumentMetadata::default(),
+        };
+        document.metadata.clauses = Some(pattern_clauses(text, &format!("{}-0", filename)));
+        document
+    }
+
+    fn summary(clause: &Clause) -> (ClauseKind, &str, Option<&str>) {
+        (clause.kind, clause.subject.as_str(), clause.value.as_deref())
+    }
+
+    #[test]
+    fn pattern_clauses_finds_each_kind_with_its_subject_and_value() {
+        let clauses = pattern_clauses(POLICY, "policy-0");
+        let found: Vec<_> = clauses.iter().map(summary).collect();
+
+        assert_eq!(
+            found,
+            [
+                (ClauseKind::WaitingPeriod, "cataract surgery", Some("24 months")),
+                (ClauseKind::SubLimit, "room rent", Some("1%")),
+                (ClauseKind::SumInsured, "", Some("Rs. 5,00,000")),
+                (ClauseKind::Exclusion, "cosmetic surgery", None),
+                (ClauseKind::WaitingPeriod, "knee replacement surgery", Some("48 months")),
+            ]
+        );
+        assert_eq!(clauses[0].text, "The waiting period for cataract surgery is 24 months.");
+        assert!(clauses.iter().all(|clause| clause.chunk_id == "policy-0"));
+    }
+
+    #[test]
+    fn pattern_clauses_needs_a_value_or_subject() {
+        // No duration, no amount, and nothing said to be excluded
+        let text = "A waiting period applies to some treatments. The sum insured can be enhanced at renewal. Is excluded.";
+        assert!(pattern_clauses(text, "c").is_empty());
+    }
+
+    #[test]
+    fn match_clauses_answers_from_the_clause_for_the_subject_asked() {
+        let documents = [document("policy.pdf", POLICY)];
+
+        let matched = match_clauses("What is the waiting period for cataract surgery?", documents.iter()).unwrap();
+        assert!(matched.answer.starts_with("The waiting period for cataract surgery is 24 months."), "{}", matched.answer);
+        assert!(matched.answer.contains("(policy.pdf)"));
+        assert_eq!(matched.clauses.len(), 1);
+
+        let matched = match_clauses("Waiting period for knee replacement surgery?", documents.iter()).unwrap();
+        assert!(matched.answer.contains("48 months"), "{}", matched.answer);
+
+        let matched = match_clauses("Is cosmetic surgery excluded?", documents.iter()).unwrap();
+        assert!(matched.answer.starts_with("Cosmetic surgery is excluded."), "{}", matched.answer);
+    }
+
+    #[test]
+    fn match_clauses_does_not_answer_a_narrower_or_unknown_subject() {
+        let documents = [document("policy.pdf", POLICY)];
+
+        // Only knee replacement has a replacement clause
+        assert!(match_clauses("What is the waiting period for hip replacement?", documents.iter()).is_none());
+        assert!(match_clauses("What is the waiting period for dental treatment?", documents.iter()).is_none());
+        // Not a clause question at all
+        assert!(match_clauses("Who is the insurer?", documents.iter()).is_none());
+    }
+
+    #[test]
+    fn match_clauses_leaves_disagreeing_documents_to_the_llm() {
+        let documents = [
+            document("old.pdf", "The waiting period for cataract surgery is 24 months."),
+            document("new.pdf", "The waiting period for cataract surgery is 12 months."),
+        ];
+        assert!(match_clauses("What is the waiting period for cataract surgery?", documents.iter()).is_none());
+
+        let documents = [
+            document("a.pdf", "The waiting period for cataract surgery is 24 months."),
+            document("b.pdf", "The waiting period for cataract surgery is 24 Months."),
+        ];
+        let matched = match_clauses("Cataract surgery waiting period?", documents.iter()).unwrap();
+        assert_eq!(matched.clauses.len(), 2);
+    }
+}
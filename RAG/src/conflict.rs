@@ -0,0 +1,209 @@
+use crate::models::*;
+
+// Conflicts reported per response; beyond this the notice would drown out the context
+const MAX_CONFLICTS: usize = 5;
+
+// Content words preceding a value that describe what it measures ("waiting period")
+const TOPIC_WORDS: usize = 4;
+
+// Words ending in a full stop that does not end the sentence
+const ABBREVIATIONS: &[&str] = &["rs", "no", "inr", "e.g", "i.e", "viz", "approx"];
+
+pub(crate) const STOP_WORDS: &[&str] = &[
+    "the", "and", "for", "are", "any", "all", "shall", "will", "with", "from", "upto", "than", "this",
+    "that", "such", "which", "has", "have", "been", "within", "after", "before", "under", "per", "not",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Unit {
+    // Durations, normalised to days (a month counts as 30 days, a year as 12 months)
+    Days,
+    Percent,
+    Currency,
+}
+
+pub(crate) struct Mention {
+    pub(crate) topic: Vec<String>,
+    pub(crate) amount: f64,
+    pub(crate) unit: Unit,
+    pub(crate) text: String,
+}
+
+// Finds values (durations, percentages, amounts) that different documents state for the
+// same topic but with different numbers, e.g. a 24 month waiting period in one policy
+// version and 36 months in another. Purely lexical, so it only catches explicit numbers.
+pub fn detect_conflicts(chunks: &[ScoredChunk], documents: &[Document]) -> Vec<Conflict> {
+    // (document, chunk id, mention) for every retrieved chunk
+    let mut mentions: Vec<(&Document, &str, Mention)> = Vec::new();
+    for scored in chunks {
+        let Some(doc) = documents.iter().find(|d| d.chunks.iter().any(|c| c.id == scored.chunk.id)) else {
+            continue;
+        };
+        for mention in extract_mentions(&scored.chunk.content) {
+            mentions.push((doc, scored.chunk.id.as_str(), mention));
+        }
+    }
+
+    let mut conflicts: Vec<Conflict> = Vec::new();
+
+    for (i, (doc_a, chunk_a, a)) in mentions.iter().enumerate() {
+        for (doc_b, chunk_b, b) in mentions.iter().skip(i + 1) {
+            if doc_a.id == doc_b.id || a.unit != b.unit || (a.amount - b.amount).abs() < f64::EPSILON {
+                continue;
+            }
+            let Some(topic) = shared_topic(&a.topic, &b.topic) else {
+                continue;
+            };
+
+            let index = match conflicts.iter().position(|c| c.topic == topic) {
+                Some(index) => index,
+                None if conflicts.len() < MAX_CONFLICTS => {
+                    conflicts.push(Conflict {
+                        topic,
+                        values: Vec::new(),
+                    });
+                    conflicts.len() - 1
+                }
+                None => continue,
+            };
+
+            for (doc, chunk_id, mention) in [(doc_a, chunk_a, a), (doc_b, chunk_b, b)] {
+                let values = &mut conflicts[index].values;
+                if !values.iter().any(|v| v.document == doc.filename && v.value == mention.text) {
+                    values.push(ConflictingValue {
+                        document: doc.filename.clone(),
+                        value: mention.text.clone(),
+                        chunk_id: chunk_id.to_string(),
+                    });
+                }
+            }
+        }
+    }
+
+    conflicts
+}
+
+// Text added to the LLM context so it reports the disagreement instead of picking one value
+pub fn conflict_notice(conflicts: &[Conflict]) -> String {
+    if conflicts.is_empty() {
+        return String::new();
+    }
+
+    let mut notice = String::from(
+        "NOTE: The documents disagree on the points below. Do not silently pick one value: \
+         state each value together with the document it comes from.\n",
+    );
+    for conflict in conflicts {
+        let values: Vec<String> = conflict
+            .values
+            .iter()
+            .map(|v| format!("{} ({})", v.value, v.document))
+            .collect();
+        notice.push_str(&format!("- {}: {}\n", conflict.topic, values.join("; ")));
+    }
+    notice.push('\n');
+    notice
+}
+
+// Both topics must share at least two words, or every word of a one-word topic
+fn shared_topic(a: &[String], b: &[String]) -> Option<String> {
+    let shared: Vec<&String> = a.iter().filter(|word| b.contains(word)).collect();
+    let required = a.len().min(b.len()).min(2);
+    (required > 0 && shared.len() >= required).then(|| {
+        shared.iter().map(|w| w.as_str()).collect::<Vec<_>>().join(" ")
+    })
+}
+
+// Every value in `text` with the content words preceding it, also used by ClauseExtractor
+pub(crate) fn extract_mentions(text: &str) -> Vec<Mention> {
+    let mut mentions = Vec::new();
+
+    for sentence in text.split(['\n', ';']).flat_map(sentences) {
+        let tokens: Vec<&str> = sentence.split_whitespace().collect();
+
+        for (idx, token) in tokens.iter().enumerate() {
+            let clean = token.trim_matches(|c: char| "()[],:".contains(c));
+            let previous = idx
+                .checked_sub(1)
+                .map(|p| tokens[p].trim_matches(|c: char| "()[],:".contains(c)).to_lowercase());
+            let next = tokens
+                .get(idx + 1)
+                .map(|n| n.trim_matches(|c: char| !c.is_alphanumeric() && c != '%').to_lowercase());
+
+            let (number, currency_prefix) = match clean.strip_prefix('₹') {
+                Some(rest) => (rest, true),
+                None => (clean, matches!(previous.as_deref(), Some("rs" | "rs." | "inr" | "₹"))),
+            };
+            let (number, percent_suffix) = match number.strip_suffix('%') {
+                Some(rest) => (rest, true),
+                None => (number, false),
+            };
+            let Ok(amount) = number.replace(',', "").parse::<f64>() else {
+                continue;
+            };
+
+            let (unit, factor) = if percent_suffix || matches!(next.as_deref(), Some("%" | "percent")) {
+                (Unit::Percent, 1.0)
+            } else if currency_prefix {
+                (Unit::Currency, 1.0)
+            } else {
+                match next.as_deref() {
+                    Some("day" | "days") => (Unit::Days, 1.0),
+                    Some("month" | "months") => (Unit::Days, 30.0),
+                    Some("year" | "years") => (Unit::Days, 360.0),
+                    _ => continue,
+                }
+            };
+
+            // Skip the currency token itself when looking for the topic
+            let topic_end = if currency_prefix && !clean.starts_with('₹') { idx - 1 } else { idx };
+            let topic = topic_words(&tokens[..topic_end]);
+            if topic.is_empty() {
+                continue;
+            }
+
+            let text = match (unit, currency_prefix) {
+                (Unit::Currency, true) if !clean.starts_with('₹') => format!("{} {}", tokens[idx - 1], clean),
+                (Unit::Days, _) => format!("{} {}", clean, next.unwrap_or_default()),
+                (Unit::Percent, _) if !percent_suffix => format!("{}%", clean),
+                _ => clean.to_string(),
+            };
+
+            mentions.push(Mention {
+                topic,
+                amount: amount * factor,
+                unit,
+                text: text.trim_end_matches(['.', ',']).to_string(),
+            });
+        }
+    }
+
+    mentions
+}
+
+// Splits `text` at ". ", except after abbreviations such as "Rs." that precede an amount
+pub(crate) fn sentences(text: &str) -> Vec<&str> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    for (end, _) in text.match_indices(". ") {
+        let word = text[start..end].rsplit(' ').next().unwrap_or_default().to_ascii_lowercase();
+        if !ABBREVIATIONS.contains(&word.as_str()) {
+            sentences.push(&text[start..end]);
+            start = end + 2;
+        }
+    }
+    sentences.push(&text[start..]);
+    sentences
+}
+
+fn topic_words(tokens: &[&str]) -> Vec<String> {
+    let mut words: Vec<String> = tokens
+        .iter()
+        .rev()
+        .map(|t| t.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase())
+        .filter(|w| w.chars().count() > 2 && w.chars().all(char::is_alphabetic) && !STOP_WORDS.contains(&w.as_str()))
+        .take(TOPIC_WORDS)
+        .collect();
+    words.reverse();
+    words
+}
@@ -0,0 +1,132 @@
+use crate::models::{Decision, DecisionOutcome};
+use serde::Deserialize;
+use serde_json::Value;
+
+// Shape the decision prompt asks for. Fields are loose because models often write
+// "Approved", quote amounts as "Rs. 50,000" or return a single clause as a string.
+#[derive(Deserialize)]
+struct RawDecision {
+    decision: String,
+    #[serde(default)]
+    amount: Value,
+    #[serde(default)]
+    applicable_clauses: Value,
+    #[serde(default)]
+    justification: String,
+}
+
+// Extracts the decision JSON from the LLM output, tolerating code fences and text around
+// the object. Returns None when no valid decision is found.
+pub fn parse_decision(output: &str) -> Option<Decision> {
+    let start = output.find('{')?;
+    let end = output.rfind('}')?;
+    if end < start {
+        return None;
+    }
+
+    let raw: RawDecision = serde_json::from_str(&output[start..=end]).ok()?;
+
+    let outcome = match raw.decision.trim().to_lowercase().replace([' ', '-'], "_").as_str() {
+        "approved" | "approve" | "covered" => DecisionOutcome::Approved,
+        "rejected" | "reject" | "denied" | "not_covered" => DecisionOutcome::Rejected,
+        "needs_info" | "needs_information" | "insufficient_information" => DecisionOutcome::NeedsInfo,
+        _ => return None,
+    };
+
+    let applicable_clauses = match raw.applicable_clauses {
+        Value::Array(items) => items
+            .into_iter()
+            .filter_map(|item| match item {
+                Value::String(clause) => Some(clause),
+                Value::Null => None,
+                other => Some(other.to_string()),
+            })
+            .collect(),
+        Value::String(clause) if !clause.trim().is_empty() => vec![clause],
+        _ => Vec::new(),
+    };
+
+    Some(Decision {
+        decision: outcome,
+        amount: parse_amount(&raw.amount),
+        applicable_clauses,
+        justification: raw.justification.trim().to_string(),
+    })
+}
+
+fn parse_amount(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(text) => {
+            // Keep digits and the decimal point: "Rs. 1,50,000.00" -> 150000.00
+            let digits: String = text
+                .chars()
+                .skip_while(|c| !c.is_ascii_digit())
+                .filter(|c| c.is_ascii_digit() || *c == '.')
+                .collect();
+            digits.trim_end_matches('.').parse().ok()
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_decision_wrapped_in_a_code_fence() {
+        let output = "Here is the decision:\n```json\n{\n  \"decision\": \"approved\",\n  \"amount\": 50000,\n  \
+                      \"applicable_clauses\": [\"4.1\", \"Section 3: Waiting periods\"],\n  \
+                      \"justification\": \" Knee surgery is covered after 24 months. \"\n}\n```";
+        let decision = parse_decision(output).unwrap();
+
+        assert_eq!(decision.decision, DecisionOutcome::Approved);
+        assert_eq!(decision.amount, Some(50000.0));
+        assert_eq!(decision.applicable_clauses, ["4.1", "Section 3: Waiting periods"]);
+        assert_eq!(decision.justification, "Knee surgery is covered after 24 months.");
+    }
+
+    #[test]
+    fn reads_outcome_synonyms() {
+        let outcome = |decision: &str| parse_decision(&format!(r#"{{"decision": "{}"}}"#, decision)).map(|d| d.decision);
+
+        assert_eq!(outcome("Covered"), Some(DecisionOutcome::Approved));
+        assert_eq!(outcome("not covered"), Some(DecisionOutcome::Rejected));
+        assert_eq!(outcome("Denied"), Some(DecisionOutcome::Rejected));
+        assert_eq!(outcome("needs-info"), Some(DecisionOutcome::NeedsInfo));
+        assert_eq!(outcome("insufficient information"), Some(DecisionOutcome::NeedsInfo));
+        assert_eq!(outcome("maybe"), None);
+    }
+
+    #[test]
+    fn reads_amounts_written_as_text() {
+        let amount = |amount: &str| parse_decision(&format!(r#"{{"decision": "approved", "amount": {}}}"#, amount)).unwrap().amount;
+
+        assert_eq!(amount(r#""Rs. 1,50,000.00""#), Some(150000.0));
+        assert_eq!(amount(r#""₹25,000""#), Some(25000.0));
+        assert_eq!(amount("12.5"), Some(12.5));
+        assert_eq!(amount(r#""not stated""#), None);
+        assert_eq!(amount("null"), None);
+    }
+
+    #[test]
+    fn accepts_loose_clause_lists() {
+        let clauses = |clauses: &str| {
+            parse_decision(&format!(r#"{{"decision": "rejected", "applicable_clauses": {}}}"#, clauses)).unwrap().applicable_clauses
+        };
+
+        assert_eq!(clauses(r#""Exclusion 2""#), ["Exclusion 2"]);
+        assert_eq!(clauses(r#"[4, null, "5.2"]"#), ["4", "5.2"]);
+        assert!(clauses(r#""  ""#).is_empty());
+        assert!(clauses("{}").is_empty());
+    }
+
+    #[test]
+    fn rejects_output_without_a_decision_object() {
+        assert!(parse_decision("The claim is approved.").is_none());
+        assert!(parse_decision("} approved {").is_none());
+        assert!(parse_decision(r#"{"decision": "approved""#).is_none());
+        assert!(parse_decision(r#"{"amount": 100}"#).is_none());
+    }
+}
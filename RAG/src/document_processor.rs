@@ -1,126 +1,227 @@
 use crate::models::*;
-use anyhow::Result;
-use pdf_extract::extract_text;
+use crate::error::Result;
+use crate::loader::LoaderRegistry;
 use regex::Regex;
+use sha2::{Digest, Sha256};
 use std::fs;
-use std::path::Path;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use uuid::Uuid;
 
-pub struct DocumentProcessor;
+// Chunk length and overlap between consecutive chunks, in characters
+pub const DEFAULT_CHUNK_SIZE: usize = 500;
+pub const DEFAULT_CHUNK_OVERLAP: usize = 50;
+
+pub struct DocumentProcessor {
+    chunk_size: usize,
+    chunk_overlap: usize,
+    loaders: Arc<LoaderRegistry>,
+}
+
+impl Default for DocumentProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl DocumentProcessor {
     pub fn new() -> Self {
-        Self
+        Self {
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            chunk_overlap: DEFAULT_CHUNK_OVERLAP,
+            loaders: Arc::new(LoaderRegistry::new()),
+        }
+    }
+
+    // The overlap is capped below the chunk size so chunking always makes progress
+    pub fn with_chunking(mut self, chunk_size: usize, chunk_overlap: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self.chunk_overlap = chunk_overlap.min(self.chunk_size - 1);
+        self
+    }
+
+    // Extracts text with `loaders` instead of the built-in ones alone
+    pub fn with_loaders(mut self, loaders: Arc<LoaderRegistry>) -> Self {
+        self.loaders = loaders;
+        self
+    }
+
+    pub fn loaders(&self) -> &Arc<LoaderRegistry> {
+        &self.loaders
     }
 
     pub async fn process_documents(&self, documents_dir: &str) -> Result<Vec<Document>> {
         let mut documents = Vec::new();
-        let paths = fs::read_dir(documents_dir)?;
-
-        for path in paths {
-            let path = path?;
-            let file_path = path.path();
-            
-            if let Some(extension) = file_path.extension() {
-                if extension == "pdf" {
-                    let doc = self.process_pdf(&file_path).await?;
-                    documents.push(doc);
-                }
-            }
+
+        for file_path in self.list_documents(documents_dir)? {
+            let doc = self.process_file(&file_path).await?;
+            documents.push(doc);
         }
 
-        log::info!("Processed {} documents", documents.len());
+        tracing::info!("Processed {} documents", documents.len());
         Ok(documents)
     }
 
-    async fn process_pdf(&self, file_path: &Path) -> Result<Document> {
-        let filename = file_path.file_name()
-            .unwrap()
-            .to_string_lossy()
-            .to_string();
-        
-        log::info!("Processing PDF: {}", filename);
-        
-        let content = extract_text(file_path)?;
-        let chunks = self.create_chunks(&content);
-        
-        Ok(Document {
+    // The files in `documents_dir` that process_documents would ingest: PDFs and those of
+    // extensions registered with LoaderRegistry::with_loader
+    pub fn list_documents(&self, documents_dir: &str) -> Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(documents_dir)? {
+            let file_path = entry?.path();
+            if file_path.extension().is_some_and(|extension| self.loaders.is_scanned(&extension.to_string_lossy())) {
+                files.push(file_path);
+            }
+        }
+        Ok(files)
+    }
+
+    // Ingests a single file, e.g. one returned by list_documents, with the loader of its
+    // extension (see LoaderRegistry)
+    pub async fn process_file(&self, file_path: &Path) -> Result<Document> {
+        let filename = file_path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let bytes = fs::read(file_path)?;
+        let mut document = self.process_bytes(&filename, &bytes)?;
+        document.metadata = self.load_metadata(file_path);
+        Ok(document)
+    }
+
+    // Ingests file contents, typed by the filename's extension
+    pub fn process_bytes(&self, filename: &str, bytes: &[u8]) -> Result<Document> {
+        let content = self.loaders.load(filename, bytes)?;
+        Ok(self.process_text(filename.to_string(), content))
+    }
+
+    // Chunks already extracted text as a new document
+    pub fn process_text(&self, filename: String, content: String) -> Document {
+        let content = SharedText::from(content);
+        let document = Document {
             id: Uuid::new_v4().to_string(),
             filename,
+            chunks: self.create_chunks(&content),
             content,
-            chunks,
-        })
+            metadata: DocumentMetadata::default(),
+        };
+        tracing::info!(doc_id = %document.id, filename = %document.filename, chunk_count = document.chunks.len(), "Chunked document");
+        document
+    }
+
+    // Identifies the current contents of a source file: a hash of the file and its sidecar, so
+    // editing either one counts as a change
+    pub fn fingerprint(&self, file_path: &Path) -> Result<String> {
+        let mut hasher = Sha256::new();
+        hasher.update(fs::read(file_path)?);
+        if let Ok(sidecar) = fs::read(file_path.with_extension("meta.json")) {
+            hasher.update(sidecar);
+        }
+        Ok(hex::encode(hasher.finalize()))
     }
 
-    fn create_chunks(&self, content: &str) -> Vec<DocumentChunk> {
-        let chunk_size = 500; // characters
-        let overlap = 50; // characters overlap between chunks
+    // Reads tags, version and access control from an optional `<file>.meta.json` sidecar;
+    // the timestamp defaults to the file's modification time when the sidecar does not set one
+    fn load_metadata(&self, file_path: &Path) -> DocumentMetadata {
+        let sidecar = file_path.with_extension("meta.json");
+        let mut metadata: DocumentMetadata = match fs::read_to_string(&sidecar) {
+            Ok(raw) => serde_json::from_str(&raw).unwrap_or_else(|e| {
+                tracing::warn!("Ignoring invalid metadata file {}: {}", sidecar.display(), e);
+                DocumentMetadata::default()
+            }),
+            Err(_) => DocumentMetadata::default(),
+        };
+
+        metadata.source = Some(file_path.to_string_lossy().to_string());
+        metadata.fingerprint = self.fingerprint(file_path).ok();
+
+        if metadata.updated_at.is_none() {
+            metadata.updated_at = fs::metadata(file_path)
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|d| d.as_secs());
+        }
+
+        metadata
+    }
+
+    // Chunks of whole sentences up to `chunk_size` characters, each starting with the last
+    // `chunk_overlap` characters of the one before. Their text is a range of `content`.
+    fn create_chunks(&self, content: &SharedText) -> Vec<DocumentChunk> {
         let mut chunks = Vec::new();
-        
-        // Clean and normalize text
-        let cleaned_content = self.clean_text(content);
-        let sentences = self.split_into_sentences(&cleaned_content);
-        
-        let mut current_chunk = String::new();
-        let mut start_pos = 0;
-        
-        for sentence in sentences {
-            if current_chunk.chars().count() + sentence.chars().count() > chunk_size && !current_chunk.is_empty() {
-                // Create chunk
-                let chunk = DocumentChunk {
-                    id: Uuid::new_v4().to_string(),
-                    content: current_chunk.trim().to_string(),
-                    start_position: start_pos,
-                    end_position: start_pos + current_chunk.chars().count(),
-                    embedding: None,
-                };
-                chunks.push(chunk);
-                
-                // Start new chunk with overlap
-                let overlap_text = if current_chunk.chars().count() > overlap {
-                    current_chunk.chars().skip(current_chunk.chars().count() - overlap).collect::<String>()
-                } else {
-                    current_chunk.clone()
+        // Byte range of the chunk being filled
+        let mut current: Option<Range<usize>> = None;
+
+        for sentence in self.sentence_spans(content) {
+            let Some(chunk) = &mut current else {
+                current = Some(sentence);
+                continue;
+            };
+            if content[chunk.start..sentence.end].chars().count() > self.chunk_size {
+                chunks.extend(chunk_at(content, chunk.clone()));
+                // Start the next chunk with the overlap
+                chunk.start = match self.chunk_overlap {
+                    0 => sentence.start,
+                    overlap => content[chunk.clone()]
+                        .char_indices()
+                        .rev()
+                        .nth(overlap - 1)
+                        .map_or(chunk.start, |(offset, _)| chunk.start + offset),
                 };
-                
-                start_pos = start_pos + current_chunk.chars().count() - overlap_text.chars().count();
-                current_chunk = overlap_text + " " + &sentence;
-            } else {
-                if !current_chunk.is_empty() {
-                    current_chunk.push(' ');
-                }
-                current_chunk.push_str(&sentence);
             }
+            chunk.end = sentence.end;
         }
-        
-        // Add the last chunk if it's not empty
-        if !current_chunk.is_empty() {
-            let chunk = DocumentChunk {
-                id: Uuid::new_v4().to_string(),
-                content: current_chunk.trim().to_string(),
-                start_position: start_pos,
-                end_position: start_pos + current_chunk.chars().count(),
-                embedding: None,
-            };
-            chunks.push(chunk);
+        if let Some(chunk) = current {
+            chunks.extend(chunk_at(content, chunk));
         }
-        
-        log::info!("Created {} chunks", chunks.len());
+
         chunks
     }
 
-    fn clean_text(&self, text: &str) -> String {
-        let re_whitespace = Regex::new(r"\s+").unwrap();
-        let re_special = Regex::new(r"[^\w\s.,!?;:()\-\[\]{}]").unwrap();
-        
-        let cleaned = re_special.replace_all(text, " ");
-        let cleaned = re_whitespace.replace_all(&cleaned, " ");
-        
-        cleaned.trim().to_string()
+    // Byte ranges of the sentences of `text`, ending with their punctuation, without the
+    // whitespace around them
+    fn sentence_spans(&self, text: &str) -> Vec<Range<usize>> {
+        let re = Regex::new(r"[.!?]+(\s+)").unwrap();
+        let mut spans = Vec::new();
+        let mut start = 0;
+        for captures in re.captures_iter(text) {
+            let gap = captures.get(1).unwrap();
+            spans.push(start..gap.start());
+            start = gap.end();
+        }
+        spans.push(start..text.len());
+        spans.into_iter().filter_map(|span| trim_span(text, span)).collect()
     }
+}
+
+// `range` of `content` as a chunk, trimmed; None if it is blank
+fn chunk_at(content: &SharedText, range: Range<usize>) -> Option<DocumentChunk> {
+    let range = trim_span(content, range)?;
+    Some(DocumentChunk {
+        id: Uuid::new_v4().to_string(),
+        content: content.slice(range.clone())?,
+        start_position: range.start,
+        end_position: range.end,
+        embedding: None,
+        entities: None,
+    })
+}
 
-    fn split_into_sentences(&self, text: &str) -> Vec<String> {
-        let re = Regex::new(r"[.!?]+\s+").unwrap();
-        re.split(text).map(|s| s.to_string()).collect()
+fn trim_span(text: &str, span: Range<usize>) -> Option<Range<usize>> {
+    let untrimmed = &text[span.clone()];
+    let trimmed = untrimmed.trim();
+    if trimmed.is_empty() {
+        return None;
     }
+    let start = span.start + (untrimmed.len() - untrimmed.trim_start().len());
+    Some(start..start + trimmed.len())
+}
+
+// Name for a document fetched from `url`: the last segment of its path, without the query
+// string (e.g. SAS tokens)
+pub fn filename_from_url(url: &str) -> String {
+    url.split('?')
+        .next()
+        .and_then(|path| path.split('/').next_back())
+        .filter(|name| !name.is_empty())
+        .unwrap_or("unknown_url_doc")
+        .to_string()
 }
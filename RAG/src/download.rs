@@ -0,0 +1,213 @@
+#[cfg(feature = "http")]
+use crate::error::{RagError, Result};
+#[cfg(feature = "http")]
+use reqwest::{header, redirect, Url};
+#[cfg(feature = "http")]
+use std::net::SocketAddr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
+
+pub const DEFAULT_DOWNLOAD_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+pub const DEFAULT_DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(120);
+pub const DEFAULT_MAX_DOWNLOAD_BYTES: u64 = 50 * 1024 * 1024;
+#[cfg(feature = "http")]
+const MAX_REDIRECTS: usize = 5;
+
+// Fetches documents added by URL (DocumentInput::Url) within a connect timeout, an overall
+// timeout and a size limit; bodies are read in pieces and abandoned as soon as they exceed it.
+// Unless private addresses are allowed, only http(s) hosts resolving to public addresses are
+// fetched, the checked addresses are the ones connected to, and every redirect is checked the
+// same way.
+#[cfg(feature = "http")]
+#[derive(Debug, Clone)]
+pub struct Downloader {
+    connect_timeout: Duration,
+    timeout: Duration,
+    max_bytes: u64,
+    allow_private: bool,
+}
+
+#[cfg(feature = "http")]
+impl Downloader {
+    pub fn new(connect_timeout: Duration, timeout: Duration, max_bytes: u64, allow_private: bool) -> Self {
+        Self { connect_timeout, timeout, max_bytes, allow_private }
+    }
+
+    // The body and Content-Type of `url`
+    pub async fn fetch(&self, url: &str) -> Result<(Vec<u8>, Option<String>)> {
+        let failed = |reason: String| RagError::Ingestion(format!("Failed to download {}: {}", url, reason));
+        let mut target = Url::parse(url).map_err(|_| failed("not a valid URL".to_string()))?;
+        for _ in 0..=MAX_REDIRECTS {
+            let client = self.client(&target).await.map_err(failed)?;
+            let mut response = client.get(target.clone()).send().await.map_err(|e| failed(e.to_string()))?;
+            if response.status().is_redirection() {
+                let location = response
+                    .headers()
+                    .get(header::LOCATION)
+                    .and_then(|value| value.to_str().ok())
+                    .ok_or_else(|| failed(format!("server returned {} without a location", response.status())))?;
+                let next = target
+                    .join(location)
+                    .map_err(|_| failed(format!("redirected to an invalid URL: {}", location)))?;
+                tracing::info!("{} redirected to {}", target, next);
+                target = next;
+                continue;
+            }
+            if !response.status().is_success() {
+                return Err(failed(format!("server returned {}", response.status())));
+            }
+
+            let content_type = response
+                .headers()
+                .get(header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string);
+            let too_large = || failed(format!("larger than {} bytes", self.max_bytes));
+            if response.content_length().is_some_and(|length| length > self.max_bytes) {
+                return Err(too_large());
+            }
+            let mut body = Vec::new();
+            while let Some(chunk) = response.chunk().await.map_err(|e| failed(e.to_string()))? {
+                if (body.len() + chunk.len()) as u64 > self.max_bytes {
+                    return Err(too_large());
+                }
+                body.extend_from_slice(&chunk);
+            }
+            return Ok((body, content_type));
+        }
+        Err(failed(format!("more than {} redirects", MAX_REDIRECTS)))
+    }
+
+    // Client for one request to `url`, connecting only to its checked addresses
+    async fn client(&self, url: &Url) -> std::result::Result<reqwest::Client, String> {
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(format!("{} is not an http or https URL", url));
+        }
+        let mut builder = reqwest::Client::builder()
+            .redirect(redirect::Policy::none())
+            .connect_timeout(self.connect_timeout)
+            .timeout(self.timeout);
+        if !self.allow_private {
+            let host = url.host_str().ok_or_else(|| format!("{} has no host", url))?;
+            // IPv6 hosts come bracketed, e.g. "[::1]"
+            match host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+                Ok(ip) if !is_public(ip) => return Err(format!("{} is not a public address", ip)),
+                Ok(_) => {}
+                Err(_) => {
+                    let addrs = resolve(host, url.port_or_known_default().unwrap_or(80)).await?;
+                    builder = builder.resolve_to_addrs(host, &addrs);
+                }
+            }
+        }
+        builder.build().map_err(|e| e.to_string())
+    }
+}
+
+// The addresses of `host`, all of which must be public
+#[cfg(feature = "http")]
+async fn resolve(host: &str, port: u16) -> std::result::Result<Vec<SocketAddr>, String> {
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|e| format!("could not resolve {}: {}", host, e))?
+        .collect();
+    if addrs.is_empty() {
+        return Err(format!("{} did not resolve to any address", host));
+    }
+    if let Some(private) = addrs.iter().find(|addr| !is_public(addr.ip())) {
+        return Err(format!("{} resolves to {}, which is not a public address", host, private.ip()));
+    }
+    Ok(addrs)
+}
+
+// Globally routable: not loopback, private, link-local (cloud metadata services), shared,
+// multicast or otherwise reserved
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(mapped) => is_public_v4(mapped),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        || a == 0
+        // Shared address space (carrier-grade NAT), 100.64.0.0/10
+        || (a == 100 && (64..128).contains(&b))
+        // Benchmarking, 198.18.0.0/15
+        || (a == 198 && (b == 18 || b == 19))
+        // Reserved, 240.0.0.0/4
+        || a >= 240)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // Unique local, fc00::/7
+        || (first & 0xfe00) == 0xfc00
+        // Link-local, fe80::/10
+        || (first & 0xffc0) == 0xfe80
+        // Documentation, 2001:db8::/32
+        || (first == 0x2001 && ip.segments()[1] == 0x0db8))
+}
+
+#[cfg(all(test, feature = "http"))]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn downloader(max_bytes: u64, allow_private: bool) -> Downloader {
+        Downloader::new(Duration::from_secs(5), Duration::from_secs(5), max_bytes, allow_private)
+    }
+
+    // Serves `body` once on a loopback port, without a Content-Length so only streaming can
+    // tell how large it is
+    async fn serve_once(body: Vec<u8>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = [0u8; 1024];
+            let _ = socket.read(&mut request).await;
+            let head = "HTTP/1.1 200 OK\r\nContent-Type: application/pdf\r\nConnection: close\r\n\r\n";
+            let _ = socket.write_all(head.as_bytes()).await;
+            let _ = socket.write_all(&body).await;
+        });
+        format!("http://{}/policy.pdf", addr)
+    }
+
+    #[tokio::test]
+    async fn private_addresses_are_refused() {
+        for url in ["http://127.0.0.1/policy.pdf", "http://169.254.169.254/latest", "http://[::1]/policy.pdf", "file:///etc/passwd"] {
+            let error = downloader(1024, false).fetch(url).await.unwrap_err();
+            assert!(matches!(error, RagError::Ingestion(_)), "{}", url);
+        }
+    }
+
+    #[tokio::test]
+    async fn bodies_within_the_limit_are_downloaded() {
+        let url = serve_once(b"%PDF-1.4 policy".to_vec()).await;
+        let (body, content_type) = downloader(1024, true).fetch(&url).await.unwrap();
+        assert_eq!(body, b"%PDF-1.4 policy");
+        assert_eq!(content_type.as_deref(), Some("application/pdf"));
+    }
+
+    #[tokio::test]
+    async fn larger_bodies_are_abandoned() {
+        let url = serve_once(vec![b'x'; 4096]).await;
+        let error = downloader(1024, true).fetch(&url).await.unwrap_err();
+        assert!(error.to_string().contains("larger than 1024 bytes"), "{}", error);
+    }
+}
@@ -1,33 +1,47 @@
 use crate::models::*;
-use anyhow::Result;
+use crate::cancel;
+use crate::providers::{cosine_similarity, EmbeddingProvider};
+use crate::error::{RagError, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
+
+// The fitted vocabulary and IDF scores, as saved with a snapshot of the index
+#[derive(Serialize, Deserialize)]
+struct TfidfState {
+    vocabulary: HashMap<String, usize>,
+    idf_scores: HashMap<String, f32>,
+}
 
 pub struct EmbeddingService {
-    vocabulary: Arc<HashMap<String, usize>>,
-    idf_scores: Arc<HashMap<String, f32>>,
+    // Fitted on the corpus by generate_embeddings so query embeddings share its space
+    vocabulary: RwLock<Arc<HashMap<String, usize>>>,
+    idf_scores: RwLock<Arc<HashMap<String, f32>>>,
 }
 
 impl EmbeddingService {
     pub async fn new() -> Result<Self> {
-        log::info!("Initializing embedding service...");
+        tracing::info!("Initializing embedding service...");
         
         Ok(Self {
-            vocabulary: Arc::new(HashMap::new()),
-            idf_scores: Arc::new(HashMap::new()),
+            vocabulary: RwLock::new(Arc::new(HashMap::new())),
+            idf_scores: RwLock::new(Arc::new(HashMap::new())),
         })
     }
 
-    pub async fn generate_embeddings(&self, documents: &mut Vec<Document>) -> Result<()> {
-        log::info!("Generating embeddings for all document chunks...");
+    pub async fn generate_embeddings(&self, documents: &mut [Document]) -> Result<()> {
+        tracing::info!("Generating embeddings for all document chunks...");
         
         // Build vocabulary from all chunks
         let mut word_counts: HashMap<String, usize> = HashMap::new();
         let mut doc_frequencies: HashMap<String, usize> = HashMap::new();
         let total_docs = documents.iter().map(|d| d.chunks.len()).sum::<usize>();
         
-        // First pass: build vocabulary and document frequencies
+        // First pass: build vocabulary and document frequencies. Cancellation is only checked
+        // here, before the vocabulary is replaced.
         for document in documents.iter() {
+            cancel::check()?;
             for chunk in &document.chunks {
                 let words = self.tokenize(&chunk.content);
                 let unique_words: std::collections::HashSet<_> = words.iter().collect();
@@ -64,6 +78,8 @@ impl EmbeddingService {
         // Update self with vocabulary and IDF scores
         let vocabulary_arc = Arc::new(vocabulary);
         let idf_scores_arc = Arc::new(idf_scores);
+        *self.vocabulary.write().unwrap() = vocabulary_arc.clone();
+        *self.idf_scores.write().unwrap() = idf_scores_arc.clone();
         
         // Second pass: generate embeddings for each chunk
         for document in documents.iter_mut() {
@@ -74,7 +90,7 @@ impl EmbeddingService {
                     &idf_scores_arc,
                 ));
             }
-            log::info!("Generated embeddings for document: {}", document.filename);
+            tracing::debug!(doc_id = %document.id, chunk_count = document.chunks.len(), "Embedded document");
         }
         
         Ok(())
@@ -82,7 +98,9 @@ impl EmbeddingService {
 
     pub async fn embed_query(&self, query: &str) -> Result<Vec<f32>> {
         // Use the same vocabulary for query embedding
-        let embedding = self.create_tfidf_embedding(query, &self.vocabulary, &self.idf_scores);
+        let vocabulary = self.vocabulary.read().unwrap().clone();
+        let idf_scores = self.idf_scores.read().unwrap().clone();
+        let embedding = self.create_tfidf_embedding(query, &vocabulary, &idf_scores);
         Ok(embedding)
     }
 
@@ -139,21 +157,37 @@ impl EmbeddingService {
     }
 
     pub fn calculate_similarity(&self, embedding1: &[f32], embedding2: &[f32]) -> f32 {
-        let min_len = embedding1.len().min(embedding2.len());
-        
-        let dot_product: f32 = embedding1[..min_len]
-            .iter()
-            .zip(embedding2[..min_len].iter())
-            .map(|(a, b)| a * b)
-            .sum();
-        
-        let norm1: f32 = embedding1[..min_len].iter().map(|x| x * x).sum::<f32>().sqrt();
-        let norm2: f32 = embedding2[..min_len].iter().map(|x| x * x).sum::<f32>().sqrt();
-        
-        if norm1 == 0.0 || norm2 == 0.0 {
-            0.0
-        } else {
-            dot_product / (norm1 * norm2)
-        }
+        cosine_similarity(embedding1, embedding2)
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for EmbeddingService {
+    async fn generate_embeddings(&self, documents: &mut [Document]) -> Result<()> {
+        EmbeddingService::generate_embeddings(self, documents).await
+    }
+
+    async fn embed_query(&self, query: &str) -> Result<Vec<f32>> {
+        EmbeddingService::embed_query(self, query).await
+    }
+
+    fn kind(&self) -> String {
+        "tfidf".to_string()
+    }
+
+    fn fitted_state(&self) -> Option<serde_json::Value> {
+        let state = TfidfState {
+            vocabulary: self.vocabulary.read().unwrap().as_ref().clone(),
+            idf_scores: self.idf_scores.read().unwrap().as_ref().clone(),
+        };
+        serde_json::to_value(state).ok()
+    }
+
+    fn restore_fitted_state(&self, state: serde_json::Value) -> Result<()> {
+        let state: TfidfState = serde_json::from_value(state)
+            .map_err(|e| RagError::Embedding(format!("Invalid TF-IDF state: {}", e)))?;
+        *self.vocabulary.write().unwrap() = Arc::new(state.vocabulary);
+        *self.idf_scores.write().unwrap() = Arc::new(state.idf_scores);
+        Ok(())
     }
 }
@@ -0,0 +1,387 @@
+use crate::error::{RagError, Result};
+use aes_gcm::aead::stream::{DecryptorBE32, EncryptorBE32};
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use std::env;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Cursor, ErrorKind, Read, Write};
+use std::path::Path;
+use std::process::Command;
+
+// First bytes of every file written encrypted. Files without them are read as plaintext, so
+// state saved before a key was configured still loads (and is encrypted when next saved).
+const FILE_MAGIC: &[u8; 8] = b"RAGENC1\n";
+
+// Prefix of encrypted lines and database values, followed by the base64 of nonce and ciphertext
+const TEXT_PREFIX: &str = "enc1:";
+
+// Files are encrypted in segments of this many plaintext bytes, so they can be written and
+// read as streams; a shorter segment ends the file
+const SEGMENT_LEN: usize = 64 * 1024;
+const TAG_LEN: usize = 16;
+// Nonce prefix of an encrypted file; the other 5 bytes of the nonce are the segment counter
+// and last-segment flag (see aead::stream)
+const STREAM_NONCE_LEN: usize = 7;
+const NONCE_LEN: usize = 12;
+
+// Where the key encrypting the state comes from: 32 bytes, base64 or hex encoded
+#[derive(Clone)]
+pub enum StateKey {
+    Inline(String),
+    // A command printing the key, e.g. one decrypting a data key with a KMS:
+    // `aws kms decrypt --ciphertext-blob fileb://state.key --query Plaintext --output text`
+    Command(String),
+}
+
+// Keys stay out of logs and config dumps
+impl fmt::Debug for StateKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Inline(_) => f.write_str("Inline(..)"),
+            Self::Command(command) => f.debug_tuple("Command").field(command).finish(),
+        }
+    }
+}
+
+impl StateKey {
+    // STATE_ENCRYPTION_KEY, else STATE_ENCRYPTION_KEY_COMMAND
+    pub fn from_env() -> Option<Self> {
+        let var = |name| env::var(name).ok().filter(|value: &String| !value.trim().is_empty());
+        var("STATE_ENCRYPTION_KEY")
+            .map(Self::Inline)
+            .or_else(|| var("STATE_ENCRYPTION_KEY_COMMAND").map(Self::Command))
+    }
+
+    fn resolve(&self) -> Result<[u8; 32]> {
+        let encoded = match self {
+            Self::Inline(key) => key.clone(),
+            Self::Command(command) => {
+                let output = Command::new("sh")
+                    .arg("-c")
+                    .arg(command)
+                    .output()
+                    .map_err(|e| RagError::Config(format!("Cannot run the encryption key command: {}", e)))?;
+                if !output.status.success() {
+                    let stderr = String::from_utf8_lossy(&output.stderr);
+                    return Err(RagError::Config(format!(
+                        "The encryption key command failed ({}) {}",
+                        output.status,
+                        stderr.trim()
+                    )));
+                }
+                String::from_utf8_lossy(&output.stdout).into_owned()
+            }
+        };
+        let encoded = encoded.trim();
+        let bytes = match hex::decode(encoded) {
+            Ok(bytes) => bytes,
+            Err(_) => STANDARD
+                .decode(encoded)
+                .map_err(|_| RagError::Config("The encryption key is neither base64 nor hex".to_string()))?,
+        };
+        bytes.try_into().map_err(|bytes: Vec<u8>| {
+            RagError::Config(format!("The encryption key has {} bytes instead of 32", bytes.len()))
+        })
+    }
+}
+
+// AES-256-GCM encryption of what the library and the api persist: index snapshots, ingestion
+// checkpoints, spilled vectors and metadata database records. Every file or value gets a
+// random nonce, and tampering is detected when it is read back. Cheap to clone.
+#[derive(Clone)]
+pub struct StateCipher {
+    cipher: Aes256Gcm,
+}
+
+impl fmt::Debug for StateCipher {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("StateCipher(..)")
+    }
+}
+
+impl StateCipher {
+    // Runs the key command, if that is where the key comes from
+    pub fn new(key: &StateKey) -> Result<Self> {
+        let key = key.resolve()?;
+        Ok(Self { cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)) })
+    }
+
+    // `text` encrypted, as printable text
+    pub fn seal_text(&self, text: &str) -> String {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let mut sealed = nonce.to_vec();
+        // Only fails for plaintexts of more than 64 GiB
+        sealed.extend(self.cipher.encrypt(&nonce, text.as_bytes()).expect("plaintext too long"));
+        format!("{}{}", TEXT_PREFIX, STANDARD.encode(sealed))
+    }
+
+    // Text returned by seal_text
+    pub fn open_text(&self, sealed: &str) -> Result<String> {
+        let unreadable = || RagError::Config("Cannot decrypt stored data: wrong key or corrupted".to_string());
+        let bytes = sealed
+            .strip_prefix(TEXT_PREFIX)
+            .and_then(|encoded| STANDARD.decode(encoded).ok())
+            .filter(|bytes| bytes.len() >= NONCE_LEN + TAG_LEN)
+            .ok_or_else(unreadable)?;
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+        let plaintext = self.cipher.decrypt(Nonce::from_slice(nonce), ciphertext).map_err(|_| unreadable())?;
+        String::from_utf8(plaintext).map_err(|_| unreadable())
+    }
+}
+
+// `text` as stored: sealed when there is a cipher
+pub fn seal_text(cipher: Option<&StateCipher>, text: String) -> String {
+    match cipher {
+        Some(cipher) => cipher.seal_text(&text),
+        None => text,
+    }
+}
+
+// Text stored by seal_text. Plaintext, stored before a key was configured, is returned as is.
+pub fn open_text(cipher: Option<&StateCipher>, stored: String) -> Result<String> {
+    match (cipher, stored.starts_with(TEXT_PREFIX)) {
+        (Some(cipher), true) => cipher.open_text(&stored),
+        (None, true) => Err(RagError::Config("Stored data is encrypted and no key is configured".to_string())),
+        (_, false) => Ok(stored),
+    }
+}
+
+// Whether the file at `path` was written encrypted
+pub fn is_encrypted(path: &Path) -> io::Result<bool> {
+    let mut magic = [0; FILE_MAGIC.len()];
+    let mut file = File::open(path)?;
+    match file.read_exact(&mut magic) {
+        Ok(()) => Ok(&magic == FILE_MAGIC),
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+// Writes to `inner`, encrypted when there is a cipher. Call finish once done: encrypted files
+// are incomplete without their last segment.
+pub fn writer<W: Write>(cipher: Option<&StateCipher>, mut inner: W) -> io::Result<StateWriter<W>> {
+    let Some(cipher) = cipher else {
+        return Ok(StateWriter::Plain(inner));
+    };
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let prefix = &nonce[..STREAM_NONCE_LEN];
+    inner.write_all(FILE_MAGIC)?;
+    inner.write_all(prefix)?;
+    Ok(StateWriter::Encrypted {
+        inner,
+        encryptor: Box::new(EncryptorBE32::from_aead(cipher.cipher.clone(), prefix.into())),
+        buffer: Vec::with_capacity(SEGMENT_LEN),
+    })
+}
+
+pub enum StateWriter<W: Write> {
+    Plain(W),
+    // The cipher state is boxed, it is much larger than a plain writer
+    Encrypted { inner: W, encryptor: Box<EncryptorBE32<Aes256Gcm>>, buffer: Vec<u8> },
+}
+
+impl<W: Write> StateWriter<W> {
+    // Writes the last segment and hands back the inner writer, still to be flushed
+    pub fn finish(self) -> io::Result<W> {
+        match self {
+            Self::Plain(inner) => Ok(inner),
+            Self::Encrypted { mut inner, encryptor, buffer } => {
+                inner.write_all(&encryptor.encrypt_last(buffer.as_slice()).map_err(encryption_failed)?)?;
+                Ok(inner)
+            }
+        }
+    }
+}
+
+impl<W: Write> Write for StateWriter<W> {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(inner) => inner.write(bytes),
+            Self::Encrypted { inner, encryptor, buffer } => {
+                buffer.extend_from_slice(bytes);
+                // Full segments are written as they fill up, which leaves the last one short
+                while buffer.len() >= SEGMENT_LEN {
+                    inner.write_all(&encryptor.encrypt_next(&buffer[..SEGMENT_LEN]).map_err(encryption_failed)?)?;
+                    buffer.drain(..SEGMENT_LEN);
+                }
+                Ok(bytes.len())
+            }
+        }
+    }
+
+    // Buffered plaintext is only written by finish
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Plain(inner) | Self::Encrypted { inner, .. } => inner.flush(),
+        }
+    }
+}
+
+fn encryption_failed(_: aes_gcm::aead::Error) -> io::Error {
+    io::Error::other("encryption failed")
+}
+
+// Reads from `inner`, decrypting if it was written encrypted; plaintext is read as is
+pub fn reader<R: Read>(cipher: Option<&StateCipher>, mut inner: R) -> io::Result<StateReader<R>> {
+    let mut start = Vec::with_capacity(FILE_MAGIC.len());
+    (&mut inner).take(FILE_MAGIC.len() as u64).read_to_end(&mut start)?;
+    if start != FILE_MAGIC {
+        return Ok(StateReader::Plain(Cursor::new(start).chain(inner)));
+    }
+    let Some(cipher) = cipher else {
+        return Err(io::Error::new(ErrorKind::InvalidData, "the file is encrypted and no key is configured"));
+    };
+    let mut prefix = [0; STREAM_NONCE_LEN];
+    inner.read_exact(&mut prefix)?;
+    Ok(StateReader::Encrypted {
+        inner,
+        decryptor: Some(Box::new(DecryptorBE32::from_aead(cipher.cipher.clone(), (&prefix).into()))),
+        plaintext: Cursor::new(Vec::new()),
+    })
+}
+
+pub enum StateReader<R: Read> {
+    Plain(io::Chain<Cursor<Vec<u8>>, R>),
+    // `decryptor` is None once the last segment was read
+    Encrypted { inner: R, decryptor: Option<Box<DecryptorBE32<Aes256Gcm>>>, plaintext: Cursor<Vec<u8>> },
+}
+
+impl<R: Read> Read for StateReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let (inner, decryptor, plaintext) = match self {
+            Self::Plain(inner) => return inner.read(buf),
+            Self::Encrypted { inner, decryptor, plaintext } => (inner, decryptor, plaintext),
+        };
+        loop {
+            let read = plaintext.read(buf)?;
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+            let Some(current) = decryptor.as_mut() else {
+                return Ok(0);
+            };
+            let mut segment = Vec::with_capacity(SEGMENT_LEN + TAG_LEN);
+            inner.by_ref().take((SEGMENT_LEN + TAG_LEN) as u64).read_to_end(&mut segment)?;
+            let decrypted = match segment.len() {
+                len if len == SEGMENT_LEN + TAG_LEN => current.decrypt_next(segment.as_slice()),
+                // A file cut off at a segment boundary ends without a last segment and fails here
+                _ => decryptor.take().unwrap().decrypt_last(segment.as_slice()),
+            };
+            let decrypted = decrypted
+                .map_err(|_| io::Error::new(ErrorKind::InvalidData, "cannot decrypt: wrong key, truncated or corrupted"))?;
+            *plaintext = Cursor::new(decrypted);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Where the segments of an encrypted file start, after the magic and nonce prefix
+    const HEADER_LEN: usize = FILE_MAGIC.len() + STREAM_NONCE_LEN;
+
+    fn cipher(byte: u8) -> StateCipher {
+        StateCipher::new(&StateKey::Inline(hex::encode([byte; 32]))).unwrap()
+    }
+
+    fn encrypt(cipher: &StateCipher, plaintext: &[u8]) -> Vec<u8> {
+        let mut writer = writer(Some(cipher), Vec::new()).unwrap();
+        // Odd-sized writes, so segments are filled across calls
+        for piece in plaintext.chunks(1000) {
+            writer.write_all(piece).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    fn decrypt(cipher: Option<&StateCipher>, file: &[u8]) -> io::Result<Vec<u8>> {
+        let mut plaintext = Vec::new();
+        reader(cipher, file)?.read_to_end(&mut plaintext)?;
+        Ok(plaintext)
+    }
+
+    fn plaintext(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn files_round_trip_at_and_around_segment_boundaries() {
+        let cipher = cipher(7);
+        for len in [0, 1, SEGMENT_LEN - 1, SEGMENT_LEN, SEGMENT_LEN + 1, 2 * SEGMENT_LEN, 2 * SEGMENT_LEN + 5] {
+            let plaintext = plaintext(len);
+            let file = encrypt(&cipher, &plaintext);
+
+            assert!(file.starts_with(FILE_MAGIC));
+            assert_eq!(file.len(), HEADER_LEN + len + (len / SEGMENT_LEN + 1) * TAG_LEN, "{} bytes", len);
+            assert_eq!(decrypt(Some(&cipher), &file).unwrap(), plaintext, "{} bytes", len);
+        }
+    }
+
+    #[test]
+    fn files_cut_at_a_segment_boundary_do_not_read_as_complete() {
+        let cipher = cipher(7);
+        let file = encrypt(&cipher, &plaintext(2 * SEGMENT_LEN + 5));
+        let segment = SEGMENT_LEN + TAG_LEN;
+
+        for len in [HEADER_LEN, HEADER_LEN + segment, HEADER_LEN + 2 * segment, file.len() - 1] {
+            let error = decrypt(Some(&cipher), &file[..len]).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::InvalidData, "cut to {} bytes", len);
+        }
+    }
+
+    #[test]
+    fn files_need_the_right_key_and_detect_tampering() {
+        let file = encrypt(&cipher(7), b"sum insured: Rs. 5,00,000");
+
+        assert_eq!(decrypt(Some(&cipher(8)), &file).unwrap_err().kind(), ErrorKind::InvalidData);
+        assert_eq!(decrypt(None, &file).unwrap_err().kind(), ErrorKind::InvalidData);
+
+        let mut tampered = file.clone();
+        tampered[HEADER_LEN] ^= 1;
+        assert_eq!(decrypt(Some(&cipher(7)), &tampered).unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn plaintext_files_are_read_as_is() {
+        let text = b"{\"documents\": []}";
+        assert_eq!(decrypt(Some(&cipher(7)), text).unwrap(), text);
+        assert_eq!(decrypt(None, text).unwrap(), text);
+        // Shorter than the magic
+        assert_eq!(decrypt(Some(&cipher(7)), b"RAG").unwrap(), b"RAG");
+
+        let mut writer = writer(None, Vec::new()).unwrap();
+        writer.write_all(text).unwrap();
+        assert_eq!(writer.finish().unwrap(), text);
+    }
+
+    #[test]
+    fn text_round_trips_and_plaintext_passes_through() {
+        let key = cipher(7);
+        let sealed = seal_text(Some(&key), "policy ₹ number 12345".to_string());
+
+        assert!(sealed.starts_with(TEXT_PREFIX));
+        assert_ne!(sealed, seal_text(Some(&key), "policy ₹ number 12345".to_string()));
+        assert_eq!(open_text(Some(&key), sealed.clone()).unwrap(), "policy ₹ number 12345");
+        assert!(open_text(Some(&cipher(8)), sealed.clone()).is_err());
+        assert!(open_text(None, sealed).is_err());
+        assert!(open_text(Some(&key), format!("{}AAAA", TEXT_PREFIX)).is_err());
+
+        assert_eq!(seal_text(None, "plain".to_string()), "plain");
+        assert_eq!(open_text(Some(&key), "plain".to_string()).unwrap(), "plain");
+    }
+
+    #[test]
+    fn keys_are_hex_or_base64_of_32_bytes() {
+        assert!(StateCipher::new(&StateKey::Inline(STANDARD.encode([3; 32]))).is_ok());
+        assert!(StateCipher::new(&StateKey::Inline(format!(" {}\n", hex::encode([3; 32])))).is_ok());
+        assert!(matches!(StateCipher::new(&StateKey::Inline(hex::encode([3; 16]))), Err(RagError::Config(_))));
+        assert!(matches!(StateCipher::new(&StateKey::Inline("not a key!".to_string())), Err(RagError::Config(_))));
+
+        let command = StateKey::Command(format!("echo {}", hex::encode([3; 32])));
+        assert!(StateCipher::new(&command).is_ok());
+        assert!(StateCipher::new(&StateKey::Command("exit 1".to_string())).is_err());
+    }
+}
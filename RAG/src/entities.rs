@@ -0,0 +1,310 @@
+use crate::conflict::STOP_WORDS;
+use crate::models::*;
+
+// Words spelling out the numbers policies write durations and ages with; tens and units also
+// make up compounds, hyphenated ("twenty-four") or not ("thirty six")
+const UNIT_WORDS: &[&str] = &["one", "two", "three", "four", "five", "six", "seven", "eight", "nine"];
+const TEEN_WORDS: &[&str] = &[
+    "ten", "eleven", "twelve", "thirteen", "fourteen", "fifteen", "sixteen", "seventeen", "eighteen", "nineteen",
+];
+const TENS_WORDS: &[&str] = &["twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety"];
+const DURATION_UNITS: &[&str] = &["hour", "hours", "day", "days", "week", "weeks", "month", "months", "year", "years"];
+const CURRENCY_PREFIXES: &[&str] = &["rs", "rs.", "inr", "₹", "usd", "$"];
+const AMOUNT_SUFFIXES: &[&str] = &["lakh", "lakhs", "crore", "crores", "rupees"];
+
+// Words a procedure is named with ("cataract surgery", "knee replacement") and the endings of
+// single-word ones ("appendectomy", "angioplasty")
+const PROCEDURE_HEADS: &[&str] = &[
+    "surgery", "replacement", "transplant", "transplantation", "repair", "therapy", "implant", "removal", "grafting",
+    "dialysis", "chemotherapy", "radiotherapy", "bypass",
+];
+const PROCEDURE_ENDINGS: &[&str] = &["ectomy", "otomy", "ostomy", "plasty", "scopy"];
+
+// Places policies name, e.g. for zone-based premiums or network hospitals
+const LOCATIONS: &[&str] = &[
+    "india", "mumbai", "delhi", "new delhi", "bengaluru", "bangalore", "chennai", "kolkata", "hyderabad", "pune",
+    "ahmedabad", "jaipur", "lucknow", "surat", "kochi", "chandigarh", "noida", "gurgaon", "gurugram", "thane",
+    "maharashtra", "karnataka", "tamil nadu", "kerala", "gujarat", "rajasthan", "uttar pradesh", "west bengal",
+    "telangana", "andhra pradesh", "punjab", "haryana", "bihar", "odisha", "madhya pradesh", "goa", "assam",
+];
+
+// Phrases in a question asking for each kind of entity
+const QUESTION_PHRASES: &[(EntityKind, &[&str])] = &[
+    (
+        EntityKind::Duration,
+        &["waiting period", "grace period", "how long", "how many days", "how many months", "how many years", "duration", "free look"],
+    ),
+    (
+        EntityKind::Amount,
+        &[
+            "how much", "sum insured", "sub-limit", "sub limit", "limit on", "limit for", "limit of", "maximum amount",
+            "amount payable", "premium amount", "co-payment", "copay", "deductible", "capped at",
+        ],
+    ),
+    (
+        EntityKind::Age,
+        &["what age", "which age", "entry age", "minimum age", "maximum age", "age limit", "age of", "aged", "how old", "years old"],
+    ),
+    (EntityKind::Location, &["which city", "what city", "location", "located", "zone"]),
+];
+
+// Fills in the entities of chunks that have none yet. Cheap enough to run on every chunk
+// that is stored, including those restored from a snapshot taken before entities existed.
+pub fn enrich(documents: &mut [Document]) {
+    for chunk in documents.iter_mut().flat_map(|doc| doc.chunks.iter_mut()).filter(|chunk| chunk.entities.is_none()) {
+        chunk.entities = Some(extract_entities(&chunk.content));
+    }
+}
+
+// Amounts, durations, ages, locations and procedures `text` mentions, each once, in order
+pub fn extract_entities(text: &str) -> Vec<Entity> {
+    let tokens: Vec<&str> = text.split_whitespace().collect();
+    let words: Vec<String> = tokens
+        .iter()
+        .map(|token| token.trim_matches(|c: char| !c.is_alphanumeric() && !"%₹$".contains(c)).to_lowercase())
+        .collect();
+    let mut entities: Vec<Entity> = Vec::new();
+    let mut push = |kind, text: String| {
+        if !entities.iter().any(|entity| entity.kind == kind && entity.text.eq_ignore_ascii_case(&text)) {
+            entities.push(Entity { kind, text });
+        }
+    };
+
+    // Index of the first word not part of a location already found
+    let mut after_location = 0;
+    for (i, word) in words.iter().enumerate() {
+        if i < after_location {
+            continue;
+        }
+        let next = words.get(i + 1).map(String::as_str).unwrap_or_default();
+        let after_next = words.get(i + 2).map(String::as_str).unwrap_or_default();
+        let previous = i.checked_sub(1).map(|p| words[p].as_str()).unwrap_or_default();
+        // "aged 60", "age of 18"; where that phrase starts
+        let age_from = match previous {
+            "age" | "aged" => Some(i - 1),
+            "of" if i >= 2 && words[i - 2] == "age" => Some(i - 2),
+            _ => None,
+        };
+        // Where the number at words[i] starts, taking in the tens of "thirty six"
+        let number_start = match TENS_WORDS.contains(&previous) && UNIT_WORDS.contains(&word.as_str()) {
+            true => i - 1,
+            false => i,
+        };
+        let original = |from: usize, to: usize| {
+            tokens[from..=to].join(" ").trim_matches(|c: char| !c.is_alphanumeric() && !"%₹$".contains(c)).to_string()
+        };
+
+        // "46-year-old", "46M"
+        if let Some(age) = word.strip_suffix("-year-old").or_else(|| word.strip_suffix("-years-old")) {
+            if is_number(age) {
+                push(EntityKind::Age, original(i, i));
+                continue;
+            }
+        }
+        if let Some(age) = word.strip_suffix(['m', 'f']) {
+            if age.len() == 2 && age.chars().all(|c| c.is_ascii_digit()) && tokens[i].chars().any(char::is_uppercase) {
+                push(EntityKind::Age, original(i, i));
+                continue;
+            }
+        }
+
+        if is_number(word) {
+            let number = word.trim_end_matches('%');
+            if word.ends_with('%') || next == "%" || next == "percent" {
+                let end = if word.ends_with('%') { i } else { i + 1 };
+                push(EntityKind::Amount, original(i, end));
+            } else if CURRENCY_PREFIXES.contains(&previous) || tokens[i].starts_with(['₹', '$']) {
+                let start = if tokens[i].starts_with(['₹', '$']) { i } else { i - 1 };
+                let end = if AMOUNT_SUFFIXES.contains(&next) { i + 1 } else { i };
+                push(EntityKind::Amount, original(start, end));
+            } else if AMOUNT_SUFFIXES.contains(&next) {
+                push(EntityKind::Amount, original(number_start, i + 1));
+            } else if DURATION_UNITS.contains(&next) {
+                // "46 years old", "18 years of age" and "aged 60 years" are ages, and so are both
+                // ends of "91 days to 25 years of age" and "aged 18 to 65 years"
+                let range_end = i + 3 < words.len()
+                    && after_next == "to"
+                    && is_number(&words[i + 3])
+                    && DURATION_UNITS.contains(&words.get(i + 4).map(String::as_str).unwrap_or_default());
+                let age = age_after(&words, i + 2)
+                    || (range_end && age_after(&words, i + 5))
+                    || age_from.is_some()
+                    || (previous == "to" && i >= 3 && is_number(&words[i - 2]) && matches!(words[i - 3].as_str(), "age" | "aged"));
+                match age {
+                    true => push(EntityKind::Age, original(age_from.unwrap_or(number_start), i + 1)),
+                    false => push(EntityKind::Duration, original(number_start, i + 1)),
+                }
+            } else if let Some(from) = age_from.filter(|_| number.chars().all(|c| c.is_ascii_digit())) {
+                push(EntityKind::Age, original(from, i));
+            }
+            continue;
+        }
+
+        if let Some(length) = location_at(&words, i) {
+            push(EntityKind::Location, original(i, i + length - 1));
+            after_location = i + length;
+            continue;
+        }
+
+        if PROCEDURE_HEADS.contains(&word.as_str()) {
+            // With the word naming what it is done on, unless that is a stop word
+            let named = i > 0
+                && previous.len() > 2
+                && previous.chars().all(|c| c.is_alphabetic() || c == '-')
+                && !STOP_WORDS.contains(&previous)
+                && !PROCEDURE_HEADS.contains(&previous);
+            push(EntityKind::Procedure, if named { original(i - 1, i) } else { original(i, i) });
+        } else if PROCEDURE_ENDINGS.iter().any(|ending| word.len() > ending.len() + 2 && word.ends_with(ending)) {
+            push(EntityKind::Procedure, original(i, i));
+        }
+    }
+    entities
+}
+
+// The kinds of entity `question` asks for, e.g. durations for "What is the waiting period
+// for cataract surgery?"
+pub fn kinds_asked(question: &str) -> Vec<EntityKind> {
+    // Padded, so phrases only match whole words ("age" is not in "coverage")
+    let normalized: String = question
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' { c } else { ' ' })
+        .collect();
+    let padded = format!(" {} ", normalized.split_whitespace().collect::<Vec<_>>().join(" "));
+    QUESTION_PHRASES
+        .iter()
+        .filter(|(_, phrases)| phrases.iter().any(|phrase| padded.contains(&format!(" {} ", phrase))))
+        .map(|(kind, _)| *kind)
+        .collect()
+}
+
+// Whether the chunk mentions any of `kinds`; chunks not yet enriched count as mentioning all
+pub fn mentions_any(chunk: &DocumentChunk, kinds: &[EntityKind]) -> bool {
+    chunk.entities.as_ref().is_none_or(|entities| entities.iter().any(|entity| kinds.contains(&entity.kind)))
+}
+
+// Whether words[i..] start with "old" or "of age"
+fn age_after(words: &[String], i: usize) -> bool {
+    match words.get(i).map(String::as_str) {
+        Some("old") => true,
+        Some("of") => words.get(i + 1).is_some_and(|word| word == "age"),
+        _ => false,
+    }
+}
+
+fn is_number(word: &str) -> bool {
+    let digits = word.trim_end_matches('%').replace([',', '.'], "");
+    (!digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit())) || is_number_word(word)
+}
+
+// "four", "fourteen", "forty", "forty-four"
+fn is_number_word(word: &str) -> bool {
+    if [UNIT_WORDS, TEEN_WORDS, TENS_WORDS].iter().any(|words| words.contains(&word)) {
+        return true;
+    }
+    word.split_once('-').is_some_and(|(tens, unit)| TENS_WORDS.contains(&tens) && UNIT_WORDS.contains(&unit))
+}
+
+// Words in the location name starting at words[i], preferring two-word names ("tamil nadu")
+fn location_at(words: &[String], i: usize) -> Option<usize> {
+    let two = words.get(i + 1).map(|next| format!("{} {}", words[i], next));
+    if two.is_some_and(|two| LOCATIONS.contains(&two.as_str())) {
+        return Some(2);
+    }
+    LOCATIONS.contains(&words[i].as_str()).then_some(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn found(text: &str) -> Vec<(EntityKind, String)> {
+        extract_entities(text).into_iter().map(|entity| (entity.kind, entity.text)).collect()
+    }
+
+    fn entity(kind: EntityKind, text: &str) -> (EntityKind, String) {
+        (kind, text.to_string())
+    }
+
+    #[test]
+    fn extracts_amounts_durations_and_procedures() {
+        assert_eq!(
+            found("Cataract surgery is covered after 24 months, up to Rs. 50,000 or 10% of the sum insured."),
+            [
+                entity(EntityKind::Procedure, "Cataract surgery"),
+                entity(EntityKind::Duration, "24 months"),
+                entity(EntityKind::Amount, "Rs. 50,000"),
+                entity(EntityKind::Amount, "10%"),
+            ]
+        );
+        assert_eq!(
+            found("Appendectomy costs are limited to 2 lakhs."),
+            [entity(EntityKind::Procedure, "Appendectomy"), entity(EntityKind::Amount, "2 lakhs")]
+        );
+    }
+
+    #[test]
+    fn tells_ages_from_durations() {
+        assert_eq!(found("A 46-year-old male"), [entity(EntityKind::Age, "46-year-old")]);
+        assert_eq!(
+            found("46M, knee replacement in Pune"),
+            [
+                entity(EntityKind::Age, "46M"),
+                entity(EntityKind::Procedure, "knee replacement"),
+                entity(EntityKind::Location, "Pune"),
+            ]
+        );
+        assert_eq!(found("Members aged 60 years"), [entity(EntityKind::Age, "aged 60 years")]);
+        assert_eq!(
+            found("Children from 91 days to 25 years of age"),
+            [entity(EntityKind::Age, "91 days"), entity(EntityKind::Age, "25 years")]
+        );
+        assert_eq!(found("after 2 years of continuous coverage"), [entity(EntityKind::Duration, "2 years")]);
+    }
+
+    #[test]
+    fn reads_number_words_including_compounds() {
+        assert_eq!(found("a grace period of thirty days"), [entity(EntityKind::Duration, "thirty days")]);
+        assert_eq!(found("within twenty-four hours"), [entity(EntityKind::Duration, "twenty-four hours")]);
+        assert_eq!(found("after thirty six months"), [entity(EntityKind::Duration, "thirty six months")]);
+        assert_eq!(found("fourteen days"), [entity(EntityKind::Duration, "fourteen days")]);
+        // Not numbers
+        assert!(found("twenty-something days").is_empty());
+    }
+
+    #[test]
+    fn extracts_two_word_locations_once() {
+        assert_eq!(
+            found("Network hospitals in Tamil Nadu and New Delhi; tamil nadu zone"),
+            [entity(EntityKind::Location, "Tamil Nadu"), entity(EntityKind::Location, "New Delhi")]
+        );
+    }
+
+    #[test]
+    fn kinds_asked_reads_the_question() {
+        assert_eq!(kinds_asked("What is the waiting period for cataract surgery?"), [EntityKind::Duration]);
+        assert_eq!(kinds_asked("How much is the sub-limit on room rent?"), [EntityKind::Amount]);
+        assert_eq!(kinds_asked("What is the entry age?"), [EntityKind::Age]);
+        assert_eq!(kinds_asked("Which city is in zone A?"), [EntityKind::Location]);
+        // Whole words only, and no kind for questions that ask for none
+        assert!(kinds_asked("Does the coverage include maternity?").is_empty());
+        assert!(kinds_asked("Is the policy cost effective?").is_empty());
+    }
+
+    #[test]
+    fn mentions_any_counts_unenriched_chunks() {
+        let mut chunk = DocumentChunk {
+            id: "c".to_string(),
+            content: "Cataract surgery after 24 months".into(),
+            start_position: 0,
+            end_position: 0,
+            embedding: None,
+            entities: None,
+        };
+        assert!(mentions_any(&chunk, &[EntityKind::Location]));
+
+        chunk.entities = Some(extract_entities(&chunk.content));
+        assert!(mentions_any(&chunk, &[EntityKind::Duration]));
+        assert!(!mentions_any(&chunk, &[EntityKind::Location]));
+    }
+}
@@ -0,0 +1,44 @@
+use std::sync::Arc;
+use thiserror::Error;
+
+// Errors of the library API. Cloneable, so an error shared by several questions of a batch
+// can be reported for each of them.
+#[derive(Debug, Clone, Error)]
+pub enum RagError {
+    // A document could not be read, extracted or chunked
+    #[error("ingestion failed: {0}")]
+    Ingestion(String),
+    #[error("embedding failed: {0}")]
+    Embedding(String),
+    // The LLM API answered with an error status
+    #[error("LLM API returned {status}: {body}")]
+    LlmApi { status: u16, body: String },
+    // The LLM API could not be reached, or its reply could not be read
+    #[error("LLM request failed: {0}")]
+    Llm(String),
+    // A provider's circuit breaker is open after repeated failures; calls fail fast until
+    // the next trial call, in about `retry_after_secs`
+    #[error("{provider} provider unavailable after repeated failures, retry in {retry_after_secs}s")]
+    Unavailable { provider: String, retry_after_secs: u64 },
+    #[error("{0} not found")]
+    NotFound(String),
+    // The request cannot be answered as given, e.g. an empty question
+    #[error("invalid request: {0}")]
+    InvalidRequest(String),
+    // Missing or invalid settings, e.g. no API key
+    #[error("configuration error: {0}")]
+    Config(String),
+    // The operation's cancellation token was cancelled (see cancel::run)
+    #[error("operation cancelled")]
+    Cancelled,
+    #[error("I/O error: {0}")]
+    Io(Arc<std::io::Error>),
+}
+
+impl From<std::io::Error> for RagError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(Arc::new(e))
+    }
+}
+
+pub type Result<T> = std::result::Result<T, RagError>;
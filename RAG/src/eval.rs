@@ -0,0 +1,226 @@
+use crate::error::{RagError, Result};
+use crate::library::RagLibrary;
+use crate::models::{QueryRequest, QueryResponse};
+use crate::prompt::build_faithfulness_prompt;
+use crate::providers::LlmProvider;
+use crate::usage::{self, TokenUsage};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use tracing::{info_span, Instrument};
+
+// Offline evaluation of the whole pipeline against a set of questions with known answers, for
+// comparing chunk sizes, retrieval settings and prompts. Each case is answered like a normal
+// query and scored on:
+//
+//   hit rate      - the expected source document is among the citations
+//   MRR           - mean reciprocal rank of the first citation from that document
+//   similarity    - embedding similarity of the answer to the reference answer
+//   faithfulness  - share of the answer's claims an LLM judge finds supported by the context
+
+// One line of an evaluation set
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvalCase {
+    pub question: String,
+    pub reference_answer: String,
+    // Filename of the document that holds the answer; matched with or without extension
+    pub source_doc: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaseResult {
+    pub question: String,
+    pub source_doc: String,
+    // Missing when answering failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub answer: Option<String>,
+    // Documents of the citations, best first
+    pub retrieved: Vec<String>,
+    // 1-based position of the first citation from `source_doc`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hit_rank: Option<usize>,
+    pub answer_similarity: f32,
+    // None when the judge was off, there was no context or the verdict could not be read
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub faithfulness: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvalReport {
+    pub cases: usize,
+    pub failed: usize,
+    pub hit_rate: f32,
+    pub mrr: f32,
+    pub mean_answer_similarity: f32,
+    // Mean over the judged cases; None when none was judged
+    pub mean_faithfulness: Option<f32>,
+    pub judged: usize,
+    // LLM work for answers and judging together
+    pub usage: TokenUsage,
+    pub results: Vec<CaseResult>,
+}
+
+// Reads an evaluation set: one JSON EvalCase per line, blank lines ignored
+pub fn read_cases(path: &Path) -> Result<Vec<EvalCase>> {
+    let raw = fs::read_to_string(path)?;
+    raw.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line)
+                .map_err(|e| RagError::Config(format!("{} line {}: invalid eval case: {}", path.display(), i + 1, e)))
+        })
+        .collect()
+}
+
+pub struct Evaluator {
+    // Judges faithfulness; None skips it
+    judge: Option<Arc<dyn LlmProvider>>,
+    // Template for every case's request; the query is replaced by the case question
+    request: QueryRequest,
+}
+
+impl Evaluator {
+    // Judged by the library's own LLM
+    pub fn new(library: &RagLibrary) -> Self {
+        Self {
+            judge: Some(library.query_service.llm().clone()),
+            request: QueryRequest::default(),
+        }
+    }
+
+    // A different (e.g. stronger) model as judge, or None to skip faithfulness
+    pub fn with_judge(mut self, judge: Option<Arc<dyn LlmProvider>>) -> Self {
+        self.judge = judge;
+        self
+    }
+
+    // Request settings under test, e.g. max_results or strategy
+    pub fn with_request(mut self, request: QueryRequest) -> Self {
+        self.request = request;
+        self
+    }
+
+    // Answers every case from the library's store in order. Failed cases are reported in the
+    // results and count as misses.
+    pub async fn run(&self, library: &RagLibrary, cases: &[EvalCase]) -> EvalReport {
+        let (results, usage) = usage::track(async {
+            let mut results = Vec::with_capacity(cases.len());
+            for (i, case) in cases.iter().enumerate() {
+                let span = info_span!("eval_case", case = i + 1);
+                results.push(self.run_case(library, case).instrument(span).await);
+            }
+            results
+        })
+        .await;
+        report(results, usage)
+    }
+
+    async fn run_case(&self, library: &RagLibrary, case: &EvalCase) -> CaseResult {
+        let mut result = CaseResult {
+            question: case.question.clone(),
+            source_doc: case.source_doc.clone(),
+            status: None,
+            answer: None,
+            retrieved: Vec::new(),
+            hit_rank: None,
+            answer_similarity: 0.0,
+            faithfulness: None,
+            error: None,
+        };
+
+        // Debug output carries the context the judge checks the answer against
+        let request = QueryRequest { query: case.question.clone(), debug: Some(true), ..self.request.clone() };
+        let response = match library.answer(&request).await {
+            Ok(response) => response,
+            Err(e) => {
+                tracing::warn!("Eval case \"{}\" failed: {}", case.question, e);
+                result.error = Some(e.to_string());
+                return result;
+            }
+        };
+
+        result.retrieved = response.citations.iter().map(|citation| citation.document.clone()).collect();
+        result.hit_rank = result
+            .retrieved
+            .iter()
+            .position(|document| same_document(document, &case.source_doc))
+            .map(|i| i + 1);
+        match answer_similarity(library, &response.response, &case.reference_answer).await {
+            Ok(similarity) => result.answer_similarity = similarity,
+            Err(e) => tracing::warn!("Could not score the answer to \"{}\": {}", case.question, e),
+        }
+        if let Some(judge) = &self.judge {
+            result.faithfulness = judge_faithfulness(judge.as_ref(), &case.question, &response).await;
+        }
+        result.status = Some(response.status);
+        result.answer = Some(response.response);
+        result
+    }
+}
+
+// Filenames compare case-insensitively, and a source_doc without extension matches any
+fn same_document(document: &str, source_doc: &str) -> bool {
+    let document = document.to_lowercase();
+    let source_doc = source_doc.to_lowercase();
+    document == source_doc || Path::new(&document).file_stem().is_some_and(|stem| stem.to_string_lossy() == source_doc)
+}
+
+async fn answer_similarity(library: &RagLibrary, answer: &str, reference: &str) -> Result<f32> {
+    let embeddings = library.store().embeddings();
+    let answer = embeddings.embed_query(answer).await?;
+    let reference = embeddings.embed_query(reference).await?;
+    Ok(embeddings.calculate_similarity(&answer, &reference))
+}
+
+// Share of supported claims in [0, 1], read from the first number the judge writes
+async fn judge_faithfulness(judge: &dyn LlmProvider, question: &str, response: &QueryResponse) -> Option<f32> {
+    let context = response.debug.as_ref().map(|debug| debug.context.as_str()).filter(|context| !context.is_empty())?;
+    let prompt = build_faithfulness_prompt(question, context, &response.response);
+    let span = usage::llm_span("judge");
+    let verdict = match judge.generate(&prompt).instrument(span.clone()).await {
+        Ok(verdict) => verdict,
+        Err(e) => {
+            usage::record_error(&span, &e);
+            tracing::warn!("Faithfulness judge failed for \"{}\": {}", question, e);
+            return None;
+        }
+    };
+    usage::record(&span, &prompt, &verdict);
+
+    let score = verdict
+        .split(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .find_map(|token| token.parse::<f32>().ok())
+        .filter(|score| (0.0..=1.0).contains(score));
+    if score.is_none() {
+        tracing::warn!("Unreadable faithfulness verdict for \"{}\": {}", question, verdict.trim());
+    }
+    score
+}
+
+fn report(results: Vec<CaseResult>, usage: TokenUsage) -> EvalReport {
+    let cases = results.len();
+    let mean = |total: f32, count: usize| if count == 0 { 0.0 } else { total / count as f32 };
+
+    let hits = results.iter().filter(|result| result.hit_rank.is_some()).count();
+    let reciprocal_ranks: f32 = results.iter().filter_map(|result| result.hit_rank).map(|rank| 1.0 / rank as f32).sum();
+    let similarity: f32 = results.iter().map(|result| result.answer_similarity).sum();
+    let judged: Vec<f32> = results.iter().filter_map(|result| result.faithfulness).collect();
+
+    EvalReport {
+        cases,
+        failed: results.iter().filter(|result| result.error.is_some()).count(),
+        hit_rate: mean(hits as f32, cases),
+        mrr: mean(reciprocal_ranks, cases),
+        mean_answer_similarity: mean(similarity, cases),
+        mean_faithfulness: (!judged.is_empty()).then(|| mean(judged.iter().sum(), judged.len())),
+        judged: judged.len(),
+        usage,
+        results,
+    }
+}
@@ -0,0 +1,171 @@
+use std::ops::Range;
+use unicode_segmentation::UnicodeSegmentation;
+
+// Length of citation excerpts, in user-perceived characters (grapheme clusters)
+pub const DEFAULT_EXCERPT_CHARS: usize = 200;
+
+// Marks text left out at either end of an excerpt
+const ELLIPSIS: &str = "...";
+
+// Share of the window an end may move inwards to land on a sentence or word boundary
+const MAX_SNAP_FRACTION: usize = 4;
+
+// An excerpt of `text` of at most `max_chars` grapheme clusters, with ellipses where text was
+// cut mid-sentence. It is centered on `focus`, a character (not byte) range of `text` such as a
+// highlight, or else starts at the beginning. The ends are moved to sentence boundaries
+// where one is close, else to word boundaries, and never split a character. Runs of
+// whitespace, e.g. line breaks, become single spaces. With `max_chars` 0 the text is kept whole.
+pub fn excerpt(text: &str, focus: Option<Range<usize>>, max_chars: usize) -> String {
+    let graphemes: Vec<(usize, &str)> = text.grapheme_indices(true).collect();
+    if graphemes.len() <= max_chars || max_chars == 0 {
+        return collapse_whitespace(text);
+    }
+
+    // Focus in grapheme indices
+    let focus = focus.map(|focus| {
+        let mut chars = 0;
+        let mut range = graphemes.len()..graphemes.len();
+        for (idx, (_, grapheme)) in graphemes.iter().enumerate() {
+            if chars <= focus.start {
+                range.start = idx;
+            }
+            if chars < focus.end {
+                range.end = idx + 1;
+            }
+            chars += grapheme.chars().count();
+        }
+        range.start..range.end.max(range.start)
+    });
+
+    let (mut start, mut end) = match &focus {
+        Some(focus) if focus.len() >= max_chars => (focus.start, focus.start + max_chars),
+        Some(focus) => {
+            let slack = max_chars - focus.len();
+            let start = focus.start.saturating_sub(slack / 2).min(graphemes.len() - max_chars);
+            (start, start + max_chars)
+        }
+        None => (0, max_chars),
+    };
+
+    // The ends may move inwards, but not into a focus that fits the window
+    let snap = max_chars / MAX_SNAP_FRACTION;
+    let keep = focus.filter(|focus| focus.end <= end).unwrap_or(start..start);
+    // Whether each end cuts a sentence, which the ellipsis marks
+    let (mut cut_start, mut cut_end) = (start > 0, end < graphemes.len());
+    if cut_start {
+        let limit = (start + snap).min(keep.start).max(start);
+        if let Some((boundary, sentence)) = boundary_after(&graphemes, start, limit) {
+            (start, cut_start) = (boundary, !sentence);
+        }
+    }
+    if cut_end {
+        let limit = end.saturating_sub(snap).max(keep.end).min(end);
+        if let Some((boundary, sentence)) = boundary_before(&graphemes, limit, end) {
+            (end, cut_end) = (boundary, !sentence);
+        }
+    }
+
+    let from = graphemes[start].0;
+    let to = graphemes.get(end).map_or(text.len(), |(offset, _)| *offset);
+    let mut excerpt = collapse_whitespace(&text[from..to]);
+    if cut_start {
+        excerpt.insert_str(0, ELLIPSIS);
+    }
+    if cut_end {
+        excerpt.push_str(ELLIPSIS);
+    }
+    excerpt
+}
+
+// The first sentence start in `from..=to`, else the first word start; with whether it starts
+// a sentence
+fn boundary_after(graphemes: &[(usize, &str)], from: usize, to: usize) -> Option<(usize, bool)> {
+    let starts_word = |idx: usize| idx > 0 && is_space(graphemes[idx - 1].1) && !is_space(graphemes[idx].1);
+    let starts_sentence = |idx: usize| starts_word(idx) && ends_sentence(graphemes, idx);
+    (from..=to)
+        .find(|&idx| starts_sentence(idx))
+        .map(|idx| (idx, true))
+        .or_else(|| (from..=to).find(|&idx| starts_word(idx)).map(|idx| (idx, false)))
+}
+
+// The last sentence end in `from..=to` (an index just past the punctuation), else the last
+// word end; with whether it ends a sentence
+fn boundary_before(graphemes: &[(usize, &str)], from: usize, to: usize) -> Option<(usize, bool)> {
+    let ends_word = |idx: usize| idx > 0 && !is_space(graphemes[idx - 1].1) && is_space(graphemes[idx].1);
+    let ends_sentence_at = |idx: usize| ends_word(idx) && ends_sentence(graphemes, idx);
+    (from..=to)
+        .rev()
+        .find(|&idx| ends_sentence_at(idx))
+        .map(|idx| (idx, true))
+        .or_else(|| (from..=to).rev().find(|&idx| ends_word(idx)).map(|idx| (idx, false)))
+}
+
+// Whether the text before `idx`, ignoring whitespace, ends a sentence
+fn ends_sentence(graphemes: &[(usize, &str)], idx: usize) -> bool {
+    graphemes[..idx]
+        .iter()
+        .rev()
+        .find(|(_, grapheme)| !is_space(grapheme))
+        .is_some_and(|(_, grapheme)| matches!(*grapheme, "." | "!" | "?" | ";"))
+}
+
+fn is_space(grapheme: &str) -> bool {
+    grapheme.chars().all(char::is_whitespace)
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLAUSE: &str = "The policy covers hospitalisation for at least 24 hours. Cataract surgery is covered after a \
+                          waiting period of two years. Pre-existing diseases are covered after 36 months of continuous \
+                          coverage. Maternity expenses are excluded.";
+
+    #[test]
+    fn short_text_is_kept_whole_with_whitespace_collapsed() {
+        assert_eq!(excerpt("Grace period:\n  thirty days.", None, 200), "Grace period: thirty days.");
+        assert_eq!(excerpt(CLAUSE, None, 0), CLAUSE.split_whitespace().collect::<Vec<_>>().join(" "));
+    }
+
+    #[test]
+    fn excerpt_without_focus_starts_at_the_beginning_and_ends_on_a_sentence() {
+        assert_eq!(excerpt(CLAUSE, None, 70), "The policy covers hospitalisation for at least 24 hours.");
+    }
+
+    #[test]
+    fn excerpt_is_centered_on_the_focus() {
+        let start = CLAUSE.find("36 months").unwrap();
+        let excerpt = excerpt(CLAUSE, Some(start..start + "36 months".len()), 80);
+
+        assert!(excerpt.contains("36 months"), "{}", excerpt);
+        assert!(!excerpt.contains("hospitalisation"), "{}", excerpt);
+        assert!(excerpt.chars().count() <= 80 + 2 * ELLIPSIS.len(), "{}", excerpt);
+    }
+
+    #[test]
+    fn cut_words_are_marked_with_ellipses() {
+        let text = "word ".repeat(40);
+        let excerpt = excerpt(&text, Some(100..104), 20);
+
+        assert!(excerpt.starts_with(ELLIPSIS) && excerpt.ends_with(ELLIPSIS), "{}", excerpt);
+        assert!(excerpt.trim_matches('.').split(' ').all(|word| word == "word"), "{}", excerpt);
+    }
+
+    #[test]
+    fn focus_is_in_characters_and_graphemes_are_never_split() {
+        // "₹" is three bytes and "👨‍👩‍👧" one grapheme of five characters
+        let text = format!("{} Sum insured ₹5,00,000 for the family 👨‍👩‍👧 floater. {}", "a ".repeat(50), "b ".repeat(50));
+        let start = text.chars().position(|c| c == '₹').unwrap();
+        let excerpt = excerpt(&text, Some(start..start + 9), 40);
+
+        assert!(excerpt.contains("₹5,00,000"), "{}", excerpt);
+
+        // Five families with no word boundary to snap to
+        let families = "👨‍👩‍👧".repeat(5);
+        assert_eq!(super::excerpt(&families, None, 2), format!("{}{}", "👨‍👩‍👧".repeat(2), ELLIPSIS));
+    }
+}
@@ -0,0 +1,135 @@
+use crate::models::{QueryRequest, QueryResponse};
+use crate::error::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::RwLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Answered queries remembered so feedback can be tied back to the question and answer
+pub const DEFAULT_MAX_TRACKED_QUERIES: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum Rating {
+    Up,
+    Down,
+}
+
+// What was asked and answered under a query id
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct QueryRecord {
+    pub query_id: String,
+    pub query: String,
+    pub answer: String,
+    pub status: String,
+    pub chunk_ids: Vec<String>,
+    pub timestamp: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Feedback {
+    pub query_id: String,
+    pub rating: Rating,
+    pub comment: Option<String>,
+    pub timestamp: u64,
+    // Missing when the query is no longer tracked (e.g. it was answered before a restart)
+    pub query: Option<QueryRecord>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+}
+
+// In-memory feedback with an optional JSONL log that survives restarts
+pub struct FeedbackStore {
+    queries: RwLock<VecDeque<QueryRecord>>,
+    feedback: RwLock<Vec<Feedback>>,
+    max_queries: usize,
+    log_path: Option<PathBuf>,
+}
+
+impl FeedbackStore {
+    pub fn new(max_queries: usize, log_path: Option<PathBuf>) -> Self {
+        Self {
+            queries: RwLock::new(VecDeque::new()),
+            feedback: RwLock::new(Vec::new()),
+            max_queries: max_queries.max(1),
+            log_path,
+        }
+    }
+
+    pub fn track_query(&self, request: &QueryRequest, response: &QueryResponse) {
+        let mut queries = self.queries.write().unwrap();
+        queries.push_back(QueryRecord {
+            query_id: response.query_id.clone(),
+            query: request.query.clone(),
+            answer: response.response.clone(),
+            status: response.status.clone(),
+            chunk_ids: response.citations.iter().map(|c| c.chunk_id.clone()).collect(),
+            timestamp: unix_timestamp(),
+            tenant: request.tenant.clone(),
+        });
+        while queries.len() > self.max_queries {
+            queries.pop_front();
+        }
+    }
+
+    pub fn query(&self, query_id: &str) -> Option<QueryRecord> {
+        self.queries
+            .read()
+            .unwrap()
+            .iter()
+            .rev()
+            .find(|record| record.query_id == query_id)
+            .cloned()
+    }
+
+    // Another tenant's query is never attached, even when its id is known
+    pub fn record(&self, query_id: &str, rating: Rating, comment: Option<String>, tenant: Option<&str>) -> Result<Feedback> {
+        let feedback = Feedback {
+            query_id: query_id.to_string(),
+            rating,
+            comment: comment.filter(|c| !c.trim().is_empty()),
+            timestamp: unix_timestamp(),
+            query: self.query(query_id).filter(|record| record.tenant.as_deref() == tenant),
+            tenant: tenant.map(str::to_string),
+        };
+
+        if let Some(path) = &self.log_path {
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            writeln!(file, "{}", serde_json::to_string(&feedback).map_err(std::io::Error::from)?)?;
+        }
+
+        self.feedback.write().unwrap().push(feedback.clone());
+        Ok(feedback)
+    }
+
+    pub fn list(&self, tenant: Option<&str>) -> Vec<Feedback> {
+        self.feedback
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|feedback| feedback.tenant.as_deref() == tenant)
+            .cloned()
+            .collect()
+    }
+}
+
+impl Default for FeedbackStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_TRACKED_QUERIES, None)
+    }
+}
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
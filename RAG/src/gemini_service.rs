@@ -1,104 +1,197 @@
 use crate::models::*;
-use anyhow::Result;
+use crate::prompt::{build_context, build_prompt};
+pub use crate::providers::DEFAULT_GEMINI_MODEL;
+use crate::providers::{Generation, LlmProvider};
+use crate::error::{RagError, Result};
+use async_trait::async_trait;
 use reqwest::Client;
 use std::env;
+use tokio::sync::mpsc;
+
+// Where the Gemini API is served; GEMINI_BASE_URL overrides it, e.g. for a proxy or a mock
+// server in tests
+pub const DEFAULT_GEMINI_BASE_URL: &str = "https://generativelanguage.googleapis.com";
 
 pub struct GeminiService {
     client: Client,
     api_key: String,
+    model: String,
+    base_url: String,
 }
 
 impl GeminiService {
     pub fn new() -> Result<Self> {
         let api_key = env::var("GEMINI_API_KEY")
-            .map_err(|_| anyhow::anyhow!("GEMINI_API_KEY environment variable not set"))?;
+            .map_err(|_| RagError::Config("GEMINI_API_KEY environment variable not set".to_string()))?;
 
-        Ok(Self {
-            client: Client::new(),
-            api_key,
+        let service = Self::with_api_key(api_key);
+        Ok(match env::var("GEMINI_BASE_URL") {
+            Ok(base_url) if !base_url.is_empty() => service.with_base_url(&base_url),
+            _ => service,
         })
     }
 
-    pub async fn generate_response(&self, query: &str, relevant_chunks: &[DocumentChunk], documents: &[Document]) -> Result<String> {
-        let context = self.build_context(relevant_chunks, documents);
-        let prompt = self.build_prompt(query, &context);
+    pub fn with_api_key(api_key: impl Into<String>) -> Self {
+        Self {
+            client: Client::new(),
+            api_key: api_key.into(),
+            model: DEFAULT_GEMINI_MODEL.to_string(),
+            base_url: DEFAULT_GEMINI_BASE_URL.to_string(),
+        }
+    }
+
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
 
-        let request = GeminiRequest {
+    pub fn with_model(mut self, model: &str) -> Self {
+        self.model = model.to_string();
+        self
+    }
+
+    pub async fn generate_response(
+        &self,
+        query: &str,
+        relevant_chunks: &[DocumentChunk],
+        documents: &[Document],
+        answer_language: &str,
+    ) -> Result<String> {
+        let context = build_context(relevant_chunks, documents);
+        let prompt = build_prompt(query, &context, answer_language, "", &AbstentionPolicy::default());
+        self.generate(&prompt).await
+    }
+
+    // Cheap reachability and credentials check: lists a single model, which costs no tokens
+    pub async fn check_reachable(&self) -> Result<()> {
+        let response = self
+            .client
+            .get(format!("{}/v1beta/models?pageSize=1&key={}", self.base_url, self.api_key))
+            .send()
+            .await
+            .map_err(request_error)?;
+        if !response.status().is_success() {
+            return Err(status_error(response).await);
+        }
+        Ok(())
+    }
+
+    fn request_body(prompt: &str) -> GeminiRequest {
+        GeminiRequest {
             contents: vec![GeminiContent {
                 parts: vec![GeminiPart {
-                    text: prompt,
+                    text: prompt.to_string(),
                 }],
             }],
             generation_config: Some(GeminiGenerationConfig {
                 temperature: 0.3,
                 max_output_tokens: 1000,
             }),
-        };
+        }
+    }
 
-        let url = format!(
-            "https://generativelanguage.googleapis.com/v1beta/models/gemini-2.5-flash:generateContent?key={}",
+    // `method` is "generateContent" or "streamGenerateContent"
+    fn url(&self, method: &str) -> String {
+        format!(
+            "{}/v1beta/models/{}:{}?{}key={}",
+            self.base_url,
+            self.model,
+            method,
+            if method == "streamGenerateContent" { "alt=sse&" } else { "" },
             self.api_key
-        );
+        )
+    }
+}
 
+fn response_text(response: &GeminiResponse) -> Option<String> {
+    response
+        .candidates
+        .first()
+        .and_then(|c| c.content.parts.first())
+        .map(|p| p.text.clone())
+}
+
+// Transport and decoding errors; the URL is left out since it carries the API key
+fn request_error(e: reqwest::Error) -> RagError {
+    RagError::Llm(format!("Gemini API request failed: {}", e.without_url()))
+}
+
+async fn status_error(response: reqwest::Response) -> RagError {
+    let status = response.status().as_u16();
+    let body = response.text().await.unwrap_or_default();
+    RagError::LlmApi { status, body }
+}
+
+#[async_trait]
+impl LlmProvider for GeminiService {
+    async fn generate(&self, prompt: &str) -> Result<String> {
+        Ok(self.generate_with_confidence(prompt).await?.text)
+    }
+
+    // The confidence is the mean token probability, exp(avgLogprobs)
+    async fn generate_with_confidence(&self, prompt: &str) -> Result<Generation> {
         let response = self.client
-            .post(&url)
-            .json(&request)
+            .post(self.url("generateContent"))
+            .json(&Self::request_body(prompt))
             .send()
-            .await?;
+            .await
+            .map_err(request_error)?;
 
         if !response.status().is_success() {
-            let error_text = response.text().await?;
-            return Err(anyhow::anyhow!("Gemini API error: {}", error_text));
+            return Err(status_error(response).await);
         }
 
-        let gemini_response: GeminiResponse = response.json().await?;
+        let gemini_response: GeminiResponse = response.json().await.map_err(request_error)?;
         
-        let answer = gemini_response
+        let answer = response_text(&gemini_response)
+            .unwrap_or_else(|| "No response generated".to_string());
+        let confidence = gemini_response
             .candidates
             .first()
-            .and_then(|c| c.content.parts.first())
-            .map(|p| p.text.clone())
-            .unwrap_or_else(|| "No response generated".to_string());
-
-        Ok(answer)
-    }
+            .and_then(|candidate| candidate.avg_logprobs)
+            .map(|logprob| (logprob.exp() as f32).clamp(0.0, 1.0));
 
-    fn build_context(&self, chunks: &[DocumentChunk], documents: &[Document]) -> String {
-        let mut context = String::new();
-        
-        for chunk in chunks {
-            // Find the document this chunk belongs to
-            if let Some(doc) = documents.iter().find(|d| d.chunks.iter().any(|c| c.id == chunk.id)) {
-                context.push_str(&format!(
-                    "Document: {}\nContent: {}\n\n",
-                    doc.filename,
-                    chunk.content
-                ));
-            }
-        }
-        
-        context
+        Ok(Generation { text: answer, confidence })
     }
 
-    fn build_prompt(&self, query: &str, context: &str) -> String {
-        format!(
-            r#"You are an expert assistant that answers questions based solely on the provided context documents. 
-
-INSTRUCTIONS:
-1. Answer the question using ONLY the information from the provided context
-2. Be concise but comprehensive
-3. If you quote or reference specific information, indicate which document it came from
-4. If the context doesn't contain enough information to answer the question, say so clearly
-5. Do not add information not present in the context
-6. Focus on accuracy and relevance
-7. If user provides info such as M or F the user is specifying it's gender for example: 46M, knee surgery, Pune, 3-month policy means 46 year old male asking if knee surgery is covered or not he is from pune and has 3 months policy
+    // Reads the SSE stream of partial GeminiResponses and forwards each text part
+    async fn generate_stream(&self, prompt: &str, deltas: &mpsc::Sender<String>) -> Result<String> {
+        let mut response = self.client
+            .post(self.url("streamGenerateContent"))
+            .json(&Self::request_body(prompt))
+            .send()
+            .await
+            .map_err(request_error)?;
 
-CONTEXT DOCUMENTS:
-{context}
+        if !response.status().is_success() {
+            return Err(status_error(response).await);
+        }
 
-QUESTION: {query}
+        let mut buffer = String::new();
+        let mut answer = String::new();
+        while let Some(bytes) = response.chunk().await.map_err(request_error)? {
+            buffer.push_str(&String::from_utf8_lossy(&bytes));
+
+            // Events are "data: {json}" lines; keep a trailing partial line for the next chunk
+            while let Some(newline) = buffer.find('\n') {
+                let line: String = buffer.drain(..=newline).collect();
+                let Some(data) = line.trim().strip_prefix("data:") else {
+                    continue;
+                };
+                let partial: GeminiResponse = serde_json::from_str(data.trim())
+                    .map_err(|e| RagError::Llm(format!("Invalid Gemini stream event: {}", e)))?;
+                if let Some(text) = response_text(&partial) {
+                    answer.push_str(&text);
+                    // A closed receiver only means nobody is listening any more
+                    let _ = deltas.send(text).await;
+                }
+            }
+        }
 
-ANSWER (be specific and cite sources):"#
-        )
+        if answer.is_empty() {
+            answer = "No response generated".to_string();
+            let _ = deltas.send(answer.clone()).await;
+        }
+        Ok(answer)
     }
 }
@@ -0,0 +1,119 @@
+use crate::models::{Document, DocumentChunk, PageSpan, SourceOffsets, TextSpan};
+use std::collections::HashSet;
+
+// Share of a sentence's content words that must appear in the answer for it to count as evidence
+const MIN_SENTENCE_OVERLAP: f32 = 0.35;
+const MIN_SHARED_WORDS: usize = 2;
+
+// Finds the sentences of `chunk` that support `answer` by lexical alignment.
+// Offsets are character (not byte) positions into `chunk`.
+pub fn find_supporting_spans(chunk: &str, answer: &str) -> Vec<TextSpan> {
+    let answer_words: HashSet<String> = content_words(answer).collect();
+    if answer_words.is_empty() {
+        return Vec::new();
+    }
+
+    let mut spans: Vec<TextSpan> = Vec::new();
+
+    for (start, end) in sentence_bounds(chunk) {
+        let sentence: String = chunk.chars().skip(start).take(end - start).collect();
+        let words: HashSet<String> = content_words(&sentence).collect();
+        if words.is_empty() {
+            continue;
+        }
+
+        let shared = words.intersection(&answer_words).count();
+        if shared >= MIN_SHARED_WORDS && shared as f32 / words.len() as f32 >= MIN_SENTENCE_OVERLAP {
+            // Merge with the previous span when consecutive sentences both match
+            match spans.last_mut() {
+                Some(last) if last.end + 1 >= start => {
+                    last.end = end;
+                    last.text = chunk.chars().skip(last.start).take(end - last.start).collect();
+                }
+                _ => spans.push(TextSpan {
+                    start,
+                    end,
+                    text: sentence.trim().to_string(),
+                }),
+            }
+        }
+    }
+
+    spans
+}
+
+// (start, end) character ranges of the sentences in `text`, trimmed of surrounding whitespace
+fn sentence_bounds(text: &str) -> Vec<(usize, usize)> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut bounds = Vec::new();
+    let mut start = 0;
+
+    for (idx, c) in chars.iter().enumerate() {
+        let at_boundary = matches!(c, '.' | '!' | '?' | ';' | '\n')
+            && chars.get(idx + 1).is_none_or(|next| next.is_whitespace());
+        if at_boundary || idx + 1 == chars.len() {
+            push_trimmed(&chars, start, idx + 1, &mut bounds);
+            start = idx + 1;
+        }
+    }
+
+    bounds
+}
+
+fn push_trimmed(chars: &[char], mut start: usize, mut end: usize, bounds: &mut Vec<(usize, usize)>) {
+    while start < end && chars[start].is_whitespace() {
+        start += 1;
+    }
+    while end > start && chars[end - 1].is_whitespace() {
+        end -= 1;
+    }
+    if start < end {
+        bounds.push((start, end));
+    }
+}
+
+fn content_words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() > 2)
+        .map(str::to_lowercase)
+}
+
+// Where `chunk` (or a window of neighbouring chunks) lies in `document`'s text; None when its
+// text is not a range of the document's, as with chunks of snapshots from older versions
+pub fn source_offsets(document: &Document, chunk: &DocumentChunk) -> Option<SourceOffsets> {
+    let range = chunk.start_position..chunk.end_position;
+    let text = document.content.get(range.clone()).filter(|text| *text == &*chunk.content)?;
+    let before = &document.content[..range.start];
+    let char_start = before.chars().count();
+    let pages = match document.content.contains('\x0c') {
+        true => page_spans(before, text),
+        false => Vec::new(),
+    };
+    Some(SourceOffsets {
+        byte_start: range.start,
+        byte_end: range.end,
+        char_start,
+        char_end: char_start + text.chars().count(),
+        pages,
+    })
+}
+
+// The parts of `text`, which follows `before` in a document, on each page
+fn page_spans(before: &str, text: &str) -> Vec<PageSpan> {
+    let mut page = 1 + before.matches('\x0c').count();
+    // Characters between the start of the page and `text`
+    let mut offset = before.rsplit('\x0c').next().unwrap_or_default().chars().count();
+    let mut spans = Vec::new();
+    for (idx, part) in text.split('\x0c').enumerate() {
+        if idx > 0 {
+            page += 1;
+            offset = 0;
+        }
+        let chars = part.chars().count();
+        if chars > 0 {
+            spans.push(PageSpan { page, char_start: offset, char_end: offset + chars });
+        }
+        offset += chars;
+    }
+    spans
+}
@@ -0,0 +1,94 @@
+use std::env;
+
+pub const DEFAULT_LANGUAGE: &str = "English";
+
+// Unicode block ranges for the scripts we expect users to type in.
+// Latin-script queries fall through to the default language.
+const SCRIPT_RANGES: &[(char, char, &str)] = &[
+    ('\u{0900}', '\u{097F}', "Hindi"),
+    ('\u{0980}', '\u{09FF}', "Bengali"),
+    ('\u{0A00}', '\u{0A7F}', "Punjabi"),
+    ('\u{0A80}', '\u{0AFF}', "Gujarati"),
+    ('\u{0B00}', '\u{0B7F}', "Odia"),
+    ('\u{0B80}', '\u{0BFF}', "Tamil"),
+    ('\u{0C00}', '\u{0C7F}', "Telugu"),
+    ('\u{0C80}', '\u{0CFF}', "Kannada"),
+    ('\u{0D00}', '\u{0D7F}', "Malayalam"),
+    ('\u{0600}', '\u{06FF}', "Arabic"),
+    ('\u{0400}', '\u{04FF}', "Russian"),
+    ('\u{4E00}', '\u{9FFF}', "Chinese"),
+    ('\u{3040}', '\u{30FF}', "Japanese"),
+    ('\u{AC00}', '\u{D7AF}', "Korean"),
+];
+
+// Letters of the Arabic block that Urdu uses and Arabic does not (ٹ ڈ ڑ ں ھ ہ ے); an
+// Arabic-script query with any of them is taken as Urdu
+const URDU_LETTERS: &[char] =
+    &['\u{0679}', '\u{0688}', '\u{0691}', '\u{06BA}', '\u{06BE}', '\u{06C1}', '\u{06D2}'];
+
+// Detects the language of `text` from the dominant non-Latin script.
+// Returns `DEFAULT_LANGUAGE` when no other script makes up a meaningful share of the letters.
+pub fn detect_language(text: &str) -> &'static str {
+    let mut counts = vec![0usize; SCRIPT_RANGES.len()];
+    let mut letters = 0usize;
+
+    for c in text.chars().filter(|c| c.is_alphabetic()) {
+        letters += 1;
+        if let Some(idx) = SCRIPT_RANGES
+            .iter()
+            .position(|(start, end, _)| c >= *start && c <= *end)
+        {
+            counts[idx] += 1;
+        }
+    }
+
+    if letters == 0 {
+        return DEFAULT_LANGUAGE;
+    }
+
+    let (best_idx, best_count) = counts
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, count)| **count)
+        .map(|(idx, count)| (idx, *count))
+        .unwrap_or((0, 0));
+
+    // Mixed queries ("knee surgery कवर है?") still count as the non-Latin language
+    if best_count * 4 >= letters {
+        match SCRIPT_RANGES[best_idx].2 {
+            "Arabic" if text.contains(URDU_LETTERS) => "Urdu",
+            language => language,
+        }
+    } else {
+        DEFAULT_LANGUAGE
+    }
+}
+
+// Reads the `ANSWER_LANGUAGE` override; "auto" or an empty value means detect per query.
+pub fn answer_language_override() -> Option<String> {
+    env::var("ANSWER_LANGUAGE")
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty() && !value.eq_ignore_ascii_case("auto"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arabic_queries_are_arabic() {
+        assert_eq!(detect_language("هل تغطي الوثيقة جراحة الركبة؟"), "Arabic");
+    }
+
+    #[test]
+    fn urdu_letters_make_an_arabic_script_query_urdu() {
+        assert_eq!(detect_language("کیا پالیسی گھٹنے کی سرجری کو کور کرتی ہے؟"), "Urdu");
+    }
+
+    #[test]
+    fn mixed_queries_count_as_their_script() {
+        assert_eq!(detect_language("knee surgery कवर है?"), "Hindi");
+        assert_eq!(detect_language("Is knee surgery covered?"), DEFAULT_LANGUAGE);
+    }
+}
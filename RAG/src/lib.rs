@@ -1,11 +1,70 @@
 pub mod models;
+pub mod error;
 pub mod document_processor;
+pub mod loader;
 pub mod embedding_service;
+#[cfg(feature = "gemini")]
 pub mod gemini_service;
 pub mod query_service;
+pub mod language;
+pub mod providers;
+pub mod prompt;
+pub mod prompt_log;
+pub mod pii;
+pub mod mock;
+pub mod retrieval;
+pub mod session;
+pub mod highlight;
+pub mod excerpt;
+pub mod router;
+pub mod decision;
+pub mod cache;
+pub mod download;
+pub mod cancel;
+pub mod checkpoint;
+pub mod encryption;
+pub mod circuit_breaker;
+pub mod conflict;
+pub mod clauses;
+pub mod entities;
+pub mod translation;
+pub mod feedback;
+pub mod library;
+pub mod blocking;
+pub mod usage;
+pub mod store;
+pub mod pipeline;
+pub mod snapshot;
+pub mod vector_tier;
+pub mod eval;
+pub mod logging;
+#[cfg(feature = "token-chunking")]
+pub mod chunking;
+#[cfg(feature = "url-ingestion")]
+pub mod ingest;
 
 pub use models::*;
+pub use error::RagError;
 pub use document_processor::DocumentProcessor;
+pub use loader::{DocumentLoader, LoaderRegistry};
 pub use embedding_service::EmbeddingService;
+#[cfg(feature = "gemini")]
 pub use gemini_service::GeminiService;
 pub use query_service::QueryService;
+pub use library::{DocumentInput, EmbeddingBackend, IngestProgress, LlmBackend, RagConfig, RagLibrary, RagLibraryBuilder};
+pub use language::detect_language;
+pub use providers::{EmbeddingProvider, Generation, LlmProvider, TranslationProvider};
+pub use mock::{MockEmbeddingProvider, MockLlmProvider};
+pub use session::{ConversationTurn, Session};
+pub use usage::TokenUsage;
+pub use feedback::{Feedback, Rating};
+pub use store::DocumentStore;
+pub use pipeline::{ContextBuilder, Generator, Reranker, RetrievalQuery, Retriever};
+pub use snapshot::SnapshotStatus;
+pub use cancel::CancellationToken;
+pub use circuit_breaker::CircuitBreaker;
+pub use vector_tier::VectorTier;
+pub use eval::{EvalCase, EvalReport, Evaluator};
+pub use logging::{BoxedLayer, LogFormat, Logging};
+#[cfg(feature = "token-chunking")]
+pub use chunking::TokenChunker;
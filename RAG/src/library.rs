@@ -0,0 +1,1123 @@
+use crate::cancel;
+use crate::checkpoint::{Checkpoint, CHECKPOINT_FILE, DEFAULT_CHECKPOINT_EVERY};
+use crate::clauses::{ClauseExtraction, ClauseExtractor};
+use crate::circuit_breaker::{BreakerEmbeddingProvider, BreakerLlmProvider, CircuitBreaker, DEFAULT_COOLDOWN, DEFAULT_FAILURE_THRESHOLD};
+use crate::document_processor::{DocumentProcessor, DEFAULT_CHUNK_OVERLAP, DEFAULT_CHUNK_SIZE};
+use crate::download::{DEFAULT_DOWNLOAD_CONNECT_TIMEOUT, DEFAULT_DOWNLOAD_TIMEOUT, DEFAULT_MAX_DOWNLOAD_BYTES};
+#[cfg(feature = "http")]
+use crate::download::Downloader;
+use crate::models::*;
+use crate::embedding_service::EmbeddingService;
+use crate::entities;
+use crate::encryption::{self, StateCipher, StateKey};
+#[cfg(feature = "gemini")]
+use crate::gemini_service::GeminiService;
+#[cfg(feature = "http")]
+use crate::document_processor::filename_from_url;
+use crate::mock::{MockEmbeddingProvider, MockLlmProvider};
+use crate::loader::{DocumentLoader, LoaderRegistry};
+use crate::logging::Logging;
+use crate::providers::{embedding_provider_from_env, llm_provider_from_env, EmbeddingProvider, LlmProvider, DEFAULT_GEMINI_MODEL};
+use crate::pipeline::{ContextBuilder, Generator, Reranker, Retriever};
+use crate::pii::{PiiRedactor, RedactingLlmProvider};
+use crate::prompt_log::{LoggedLlmProvider, PromptLog, Redactor, DEFAULT_PROMPT_LOG_FILES, DEFAULT_PROMPT_LOG_MAX_BYTES, DEFAULT_REDACTIONS};
+use crate::query_service::{QueryService, DEFAULT_MAX_CONTEXT_TOKENS_LIMIT, DEFAULT_MAX_RESULTS_LIMIT};
+use crate::session::{DEFAULT_MAX_SESSIONS, DEFAULT_MAX_TURNS, DEFAULT_SESSION_TTL};
+use crate::snapshot::{config_fingerprint, Snapshot, SnapshotStatus, SNAPSHOT_FILE, SNAPSHOT_VERSION};
+use crate::store::DocumentStore;
+use crate::vector_tier::{VectorTier, VectorTierUsage, SPILL_DIR};
+use crate::error::Result;
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::field::Empty;
+use tracing::{info_span, Instrument, Span};
+
+// Settings of a RagLibrary. `from_env` reads the environment variables documented on
+// each field; hosts with their own configuration (e.g. CLI flags) fill it in directly.
+#[derive(Debug, Clone)]
+pub struct RagConfig {
+    // DOCUMENTS_DIR, default "."
+    pub documents_dir: String,
+    // Further directories or single files ingested along with the documents directory
+    pub extra_sources: Vec<PathBuf>,
+    // GEMINI_MODEL
+    pub gemini_model: String,
+    // CHUNK_SIZE / CHUNK_OVERLAP, in characters
+    pub chunk_size: usize,
+    pub chunk_overlap: usize,
+    // LLM_BATCH_SIZE > 1 answers several questions of a batch per LLM call
+    pub llm_batch_size: usize,
+    // RESPONSE_CACHE_TTL_SECS enables caching of full responses for repeated questions
+    pub response_cache_ttl: Option<Duration>,
+    // FEEDBACK_LOG keeps user feedback in a JSONL file across restarts
+    pub feedback_log: Option<PathBuf>,
+    // STATE_DIR holds what the library keeps across restarts: a snapshot of the index
+    // (index.json), the checkpoint of an unfinished ingestion run, and the feedback log unless
+    // FEEDBACK_LOG is set
+    pub state_dir: Option<PathBuf>,
+    // INGEST_CHECKPOINT_EVERY: documents load_documents ingests and embeds per batch. Each
+    // batch becomes searchable when done and is checkpointed in the state directory, so an
+    // interrupted run resumes after it; 0 ingests everything in one batch, without checkpoints
+    pub ingest_checkpoint_every: usize,
+    // SESSION_HISTORY_TURNS: question/answer turns of a conversation kept verbatim
+    pub session_history_turns: usize,
+    // SESSION_SUMMARIES=true compresses older turns into an LLM-written summary
+    pub session_summaries: bool,
+    // MAX_SESSIONS: sessions kept at once, the least recently used dropped beyond it
+    pub max_sessions: usize,
+    // SESSION_TTL_SECS: how long a session is kept without a new turn; 0 (None) keeps it
+    pub session_ttl: Option<Duration>,
+    // MAX_RESULTS_LIMIT / MAX_CONTEXT_TOKENS_LIMIT: the most chunks and context tokens a
+    // request is served with, whatever it asks for
+    pub max_results_limit: usize,
+    pub max_context_tokens_limit: usize,
+    // DOWNLOAD_CONNECT_TIMEOUT_SECS / DOWNLOAD_TIMEOUT_SECS / MAX_DOWNLOAD_BYTES: limits on
+    // fetching a document added by URL (see Downloader)
+    pub download_connect_timeout: Duration,
+    pub download_timeout: Duration,
+    pub max_download_bytes: u64,
+    // ALLOW_PRIVATE_URLS=true lets document URLs point at private, loopback and link-local
+    // addresses
+    pub allow_private_urls: bool,
+    // CIRCUIT_BREAKER_FAILURES: consecutive LLM or embedding failures after which calls to
+    // that provider fail fast (see CircuitBreaker); 0 disables the breakers
+    pub circuit_breaker_failures: u32,
+    // CIRCUIT_BREAKER_COOLDOWN_SECS: how long an open circuit fails fast before a trial call
+    pub circuit_breaker_cooldown: Duration,
+    // VECTOR_MEMORY_BUDGET_MB: memory the chunk vectors of all stores may take together; the
+    // least recently used beyond it are spilled to disk and read back when needed (see
+    // VectorTier). 0 keeps every vector in memory.
+    pub vector_memory_budget_mb: usize,
+    // VECTOR_SPILL_DIR: where spilled vectors go, in a spilled-vectors directory that is
+    // emptied at startup; defaults to the state directory, else the system temp directory
+    pub vector_spill_dir: Option<PathBuf>,
+    // PROMPT_LOG: JSONL file receiving every LLM prompt and response (see PromptLog), rotated
+    // at PROMPT_LOG_MAX_BYTES with PROMPT_LOG_FILES older files kept
+    pub prompt_log: Option<PathBuf>,
+    pub prompt_log_max_bytes: u64,
+    pub prompt_log_files: usize,
+    // PROMPT_LOG_REDACT: comma-separated kinds of data masked in the prompt log (see
+    // Redactor), or "none"; PROMPT_LOG_REDACT_PATTERN: a regex also masked
+    pub prompt_log_redact: Vec<String>,
+    pub prompt_log_redact_pattern: Option<String>,
+    // PII_REDACT: comma-separated kinds of personal data masked in prompts before they are
+    // sent to the LLM and restored in its answers (see PiiRedactor), or "none"; PII_NER_URL: a
+    // Presidio-compatible analyzer that also finds names
+    pub pii_redact: Vec<String>,
+    pub pii_ner_url: Option<String>,
+    // STATE_ENCRYPTION_KEY, or STATE_ENCRYPTION_KEY_COMMAND printing it: encrypts the
+    // snapshot, checkpoint and spilled vectors (see StateCipher)
+    pub state_encryption_key: Option<StateKey>,
+    // CLAUSE_EXTRACTION: how waiting periods, sub-limits, exclusions and sums insured are
+    // extracted at ingest for answering from directly (see ClauseExtractor); off by default
+    pub clause_extraction: ClauseExtraction,
+}
+
+impl Default for RagConfig {
+    fn default() -> Self {
+        Self {
+            documents_dir: ".".to_string(),
+            extra_sources: Vec::new(),
+            gemini_model: DEFAULT_GEMINI_MODEL.to_string(),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            chunk_overlap: DEFAULT_CHUNK_OVERLAP,
+            llm_batch_size: 1,
+            response_cache_ttl: None,
+            feedback_log: None,
+            state_dir: None,
+            ingest_checkpoint_every: DEFAULT_CHECKPOINT_EVERY,
+            session_history_turns: DEFAULT_MAX_TURNS,
+            session_summaries: false,
+            max_sessions: DEFAULT_MAX_SESSIONS,
+            session_ttl: Some(DEFAULT_SESSION_TTL),
+            max_results_limit: DEFAULT_MAX_RESULTS_LIMIT,
+            max_context_tokens_limit: DEFAULT_MAX_CONTEXT_TOKENS_LIMIT,
+            download_connect_timeout: DEFAULT_DOWNLOAD_CONNECT_TIMEOUT,
+            download_timeout: DEFAULT_DOWNLOAD_TIMEOUT,
+            max_download_bytes: DEFAULT_MAX_DOWNLOAD_BYTES,
+            allow_private_urls: false,
+            circuit_breaker_failures: DEFAULT_FAILURE_THRESHOLD,
+            circuit_breaker_cooldown: DEFAULT_COOLDOWN,
+            vector_memory_budget_mb: 0,
+            vector_spill_dir: None,
+            prompt_log: None,
+            prompt_log_max_bytes: DEFAULT_PROMPT_LOG_MAX_BYTES,
+            prompt_log_files: DEFAULT_PROMPT_LOG_FILES,
+            prompt_log_redact: DEFAULT_REDACTIONS.iter().map(|kind| kind.to_string()).collect(),
+            prompt_log_redact_pattern: None,
+            pii_redact: Vec::new(),
+            pii_ner_url: None,
+            state_encryption_key: None,
+            clause_extraction: ClauseExtraction::Off,
+        }
+    }
+}
+
+impl RagConfig {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            documents_dir: env::var("DOCUMENTS_DIR").unwrap_or(defaults.documents_dir),
+            extra_sources: defaults.extra_sources,
+            gemini_model: env::var("GEMINI_MODEL").unwrap_or(defaults.gemini_model),
+            chunk_size: env_parse("CHUNK_SIZE").unwrap_or(defaults.chunk_size),
+            chunk_overlap: env_parse("CHUNK_OVERLAP").unwrap_or(defaults.chunk_overlap),
+            llm_batch_size: env_parse("LLM_BATCH_SIZE").unwrap_or(defaults.llm_batch_size),
+            response_cache_ttl: env_parse("RESPONSE_CACHE_TTL_SECS")
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs),
+            feedback_log: env::var("FEEDBACK_LOG").ok().map(PathBuf::from),
+            state_dir: env::var("STATE_DIR").ok().map(PathBuf::from),
+            ingest_checkpoint_every: env_parse("INGEST_CHECKPOINT_EVERY").unwrap_or(defaults.ingest_checkpoint_every),
+            session_history_turns: env_parse("SESSION_HISTORY_TURNS").unwrap_or(defaults.session_history_turns),
+            session_summaries: env_parse("SESSION_SUMMARIES").unwrap_or(defaults.session_summaries),
+            max_sessions: env_parse("MAX_SESSIONS").unwrap_or(defaults.max_sessions),
+            session_ttl: match env_parse::<u64>("SESSION_TTL_SECS") {
+                Some(0) => None,
+                Some(secs) => Some(Duration::from_secs(secs)),
+                None => defaults.session_ttl,
+            },
+            max_results_limit: env_parse("MAX_RESULTS_LIMIT").unwrap_or(defaults.max_results_limit),
+            max_context_tokens_limit: env_parse("MAX_CONTEXT_TOKENS_LIMIT").unwrap_or(defaults.max_context_tokens_limit),
+            download_connect_timeout: env_parse("DOWNLOAD_CONNECT_TIMEOUT_SECS")
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.download_connect_timeout),
+            download_timeout: env_parse("DOWNLOAD_TIMEOUT_SECS").map(Duration::from_secs).unwrap_or(defaults.download_timeout),
+            max_download_bytes: env_parse("MAX_DOWNLOAD_BYTES").unwrap_or(defaults.max_download_bytes),
+            allow_private_urls: env_parse("ALLOW_PRIVATE_URLS").unwrap_or(defaults.allow_private_urls),
+            circuit_breaker_failures: env_parse("CIRCUIT_BREAKER_FAILURES").unwrap_or(defaults.circuit_breaker_failures),
+            circuit_breaker_cooldown: env_parse("CIRCUIT_BREAKER_COOLDOWN_SECS")
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.circuit_breaker_cooldown),
+            vector_memory_budget_mb: env_parse("VECTOR_MEMORY_BUDGET_MB").unwrap_or(defaults.vector_memory_budget_mb),
+            vector_spill_dir: env::var("VECTOR_SPILL_DIR").ok().map(PathBuf::from),
+            prompt_log: env::var("PROMPT_LOG").ok().map(PathBuf::from),
+            prompt_log_max_bytes: env_parse("PROMPT_LOG_MAX_BYTES").unwrap_or(defaults.prompt_log_max_bytes),
+            prompt_log_files: env_parse("PROMPT_LOG_FILES").unwrap_or(defaults.prompt_log_files),
+            prompt_log_redact: env::var("PROMPT_LOG_REDACT")
+                .map(|kinds| parse_redactions(&kinds))
+                .unwrap_or(defaults.prompt_log_redact),
+            prompt_log_redact_pattern: env::var("PROMPT_LOG_REDACT_PATTERN").ok().filter(|pattern| !pattern.is_empty()),
+            pii_redact: env::var("PII_REDACT").map(|kinds| parse_redactions(&kinds)).unwrap_or(defaults.pii_redact),
+            pii_ner_url: env::var("PII_NER_URL").ok().filter(|url| !url.is_empty()),
+            state_encryption_key: StateKey::from_env(),
+            clause_extraction: env_parse("CLAUSE_EXTRACTION").unwrap_or(defaults.clause_extraction),
+        }
+    }
+}
+
+fn env_parse<T: std::str::FromStr>(name: &str) -> Option<T> {
+    env::var(name).ok().and_then(|value| value.parse().ok())
+}
+
+// Redaction kinds from a comma-separated list such as "email,phone"; "none" masks nothing
+pub fn parse_redactions(kinds: &str) -> Vec<String> {
+    kinds
+        .split(',')
+        .map(str::trim)
+        .filter(|kind| !kind.is_empty() && *kind != "none")
+        .map(str::to_string)
+        .collect()
+}
+
+// Makes the embedding providers of a library: the shared one, and fresh ones for documents
+// indexed separately
+#[derive(Clone)]
+pub enum EmbeddingBackend {
+    // TF-IDF fitted on the documents being indexed
+    Tfidf,
+    // Hash-based vectors, for tests and offline demos
+    Mock,
+    // Providers made by the given function, e.g. ones calling an embeddings API
+    Custom(Arc<dyn Fn() -> Arc<dyn EmbeddingProvider> + Send + Sync>),
+    // Chosen by EMBEDDING_PROVIDER
+    FromEnv,
+}
+
+impl EmbeddingBackend {
+    pub async fn provider(&self) -> Result<Arc<dyn EmbeddingProvider>> {
+        Ok(match self {
+            Self::Tfidf => Arc::new(EmbeddingService::new().await?),
+            Self::Mock => Arc::new(MockEmbeddingProvider::new()),
+            Self::Custom(make) => make(),
+            Self::FromEnv => embedding_provider_from_env().await?,
+        })
+    }
+}
+
+#[derive(Clone)]
+pub enum LlmBackend {
+    // Gemini with this API key and the configured model
+    #[cfg(feature = "gemini")]
+    Gemini { api_key: String },
+    // Canned responses, so no API key is needed
+    Mock,
+    Custom(Arc<dyn LlmProvider>),
+    // Chosen by LLM_PROVIDER, with GEMINI_API_KEY for Gemini
+    FromEnv,
+}
+
+impl LlmBackend {
+    fn provider(&self, gemini_model: &str) -> Result<Arc<dyn LlmProvider>> {
+        Ok(match self {
+            #[cfg(feature = "gemini")]
+            Self::Gemini { api_key } => Arc::new(GeminiService::with_api_key(api_key.clone()).with_model(gemini_model)),
+            Self::Mock => Arc::new(MockLlmProvider::new()),
+            Self::Custom(llm) => llm.clone(),
+            Self::FromEnv => llm_provider_from_env(gemini_model)?,
+        })
+    }
+}
+
+// Assembles a RagLibrary from explicit settings. Nothing is read from the environment
+// except by the FromEnv backends, which are the defaults, and RagConfig::from_env if the
+// caller passes it in.
+pub struct RagLibraryBuilder {
+    config: RagConfig,
+    embeddings: EmbeddingBackend,
+    llm: LlmBackend,
+    // Custom pipeline stages, see the pipeline module
+    retriever: Option<Arc<dyn Retriever>>,
+    rerankers: Vec<Arc<dyn Reranker>>,
+    context_builder: Option<Arc<dyn ContextBuilder>>,
+    generator: Option<Arc<dyn Generator>>,
+    loaders: LoaderRegistry,
+    // Takes precedence over RagConfig::state_encryption_key
+    state_cipher: Option<StateCipher>,
+}
+
+impl RagLibraryBuilder {
+    pub fn new() -> Self {
+        Self {
+            config: RagConfig::default(),
+            embeddings: EmbeddingBackend::FromEnv,
+            llm: LlmBackend::FromEnv,
+            retriever: None,
+            rerankers: Vec::new(),
+            context_builder: None,
+            generator: None,
+            loaders: LoaderRegistry::new(),
+            state_cipher: None,
+        }
+    }
+
+    // Replaces every setting, e.g. with RagConfig::from_env(); later calls adjust it
+    pub fn with_config(mut self, config: RagConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn with_documents_dir(mut self, dir: impl Into<String>) -> Self {
+        self.config.documents_dir = dir.into();
+        self
+    }
+
+    // Another directory or single file to ingest; may be called repeatedly
+    pub fn with_document_source(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.extra_sources.push(path.into());
+        self
+    }
+
+    pub fn with_chunking(mut self, chunk_size: usize, chunk_overlap: usize) -> Self {
+        self.config.chunk_size = chunk_size;
+        self.config.chunk_overlap = chunk_overlap;
+        self
+    }
+
+    pub fn with_embedding_backend(mut self, backend: EmbeddingBackend) -> Self {
+        self.embeddings = backend;
+        self
+    }
+
+    pub fn with_llm_backend(mut self, backend: LlmBackend) -> Self {
+        self.llm = backend;
+        self
+    }
+
+    pub fn with_gemini_model(mut self, model: impl Into<String>) -> Self {
+        self.config.gemini_model = model.into();
+        self
+    }
+
+    pub fn with_state_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.config.state_dir = Some(dir.into());
+        self
+    }
+
+    pub fn with_ingest_checkpoints(mut self, every: usize) -> Self {
+        self.config.ingest_checkpoint_every = every;
+        self
+    }
+
+    pub fn with_feedback_log(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.feedback_log = Some(path.into());
+        self
+    }
+
+    pub fn with_llm_batch_size(mut self, size: usize) -> Self {
+        self.config.llm_batch_size = size;
+        self
+    }
+
+    pub fn with_response_cache(mut self, ttl: Option<Duration>) -> Self {
+        self.config.response_cache_ttl = ttl;
+        self
+    }
+
+    pub fn with_session_history(mut self, turns: usize, summaries: bool) -> Self {
+        self.config.session_history_turns = turns;
+        self.config.session_summaries = summaries;
+        self
+    }
+
+    // Sessions kept at once, and how long an unused one is kept (None until evicted)
+    pub fn with_session_limits(mut self, max_sessions: usize, ttl: Option<Duration>) -> Self {
+        self.config.max_sessions = max_sessions;
+        self.config.session_ttl = ttl;
+        self
+    }
+
+    // The most chunks and context tokens a request is served with
+    pub fn with_request_limits(mut self, max_results: usize, max_context_tokens: usize) -> Self {
+        self.config.max_results_limit = max_results;
+        self.config.max_context_tokens_limit = max_context_tokens;
+        self
+    }
+
+    // Connect timeout, overall timeout and size limit for documents added by URL
+    pub fn with_download_limits(mut self, connect_timeout: Duration, timeout: Duration, max_bytes: u64) -> Self {
+        self.config.download_connect_timeout = connect_timeout;
+        self.config.download_timeout = timeout;
+        self.config.max_download_bytes = max_bytes;
+        self
+    }
+
+    // Lets document URLs point at private, loopback and link-local addresses
+    pub fn with_private_urls(mut self, allow: bool) -> Self {
+        self.config.allow_private_urls = allow;
+        self
+    }
+
+    // Consecutive provider failures that open a circuit (0 disables), and how long it stays open
+    pub fn with_circuit_breaker(mut self, failures: u32, cooldown: Duration) -> Self {
+        self.config.circuit_breaker_failures = failures;
+        self.config.circuit_breaker_cooldown = cooldown;
+        self
+    }
+
+    // Memory for chunk vectors (0 keeps them all in memory), and where the rest are spilled
+    pub fn with_vector_memory_budget(mut self, megabytes: usize, spill_dir: Option<PathBuf>) -> Self {
+        self.config.vector_memory_budget_mb = megabytes;
+        self.config.vector_spill_dir = spill_dir;
+        self
+    }
+
+    // Logs every LLM prompt and response to `path`, masking `redact` kinds (see Redactor)
+    pub fn with_prompt_log(mut self, path: impl Into<PathBuf>, redact: Vec<String>) -> Self {
+        self.config.prompt_log = Some(path.into());
+        self.config.prompt_log_redact = redact;
+        self
+    }
+
+    // Masks `kinds` of personal data in LLM prompts, names found by the analyzer at `ner_url`
+    // too, and restores them in answers (see PiiRedactor)
+    pub fn with_pii_redaction(mut self, kinds: Vec<String>, ner_url: Option<String>) -> Self {
+        self.config.pii_redact = kinds;
+        self.config.pii_ner_url = ner_url;
+        self
+    }
+
+    pub fn with_clause_extraction(mut self, extraction: ClauseExtraction) -> Self {
+        self.config.clause_extraction = extraction;
+        self
+    }
+
+    // Encrypts the state with the key `key` yields (see StateCipher)
+    pub fn with_state_encryption(mut self, key: StateKey) -> Self {
+        self.config.state_encryption_key = Some(key);
+        self
+    }
+
+    // Like with_state_encryption, for hosts that resolved the key already, e.g. to share the
+    // cipher with their own storage
+    pub fn with_state_cipher(mut self, cipher: StateCipher) -> Self {
+        self.state_cipher = Some(cipher);
+        self
+    }
+
+    pub fn with_retriever(mut self, retriever: Arc<dyn Retriever>) -> Self {
+        self.retriever = Some(retriever);
+        self
+    }
+
+    // Runs `reranker` after the built-in filters and before the final MMR selection; stages
+    // added this way run in the order they were added
+    pub fn with_reranker(mut self, reranker: Arc<dyn Reranker>) -> Self {
+        self.rerankers.push(reranker);
+        self
+    }
+
+    pub fn with_context_builder(mut self, context_builder: Arc<dyn ContextBuilder>) -> Self {
+        self.context_builder = Some(context_builder);
+        self
+    }
+
+    pub fn with_generator(mut self, generator: Arc<dyn Generator>) -> Self {
+        self.generator = Some(generator);
+        self
+    }
+
+    // Ingests files with extension `extension`, or downloads of the given MIME types, with
+    // `loader`; the documents directory and sources are scanned for them too (see LoaderRegistry)
+    pub fn with_loader(mut self, extension: &str, mime_types: &[&str], loader: Arc<dyn DocumentLoader>) -> Self {
+        self.loaders = self.loaders.with_loader(extension, mime_types, loader);
+        self
+    }
+
+    // Sets up the services with an empty store; documents are ingested by
+    // RagLibrary::load_documents
+    pub async fn build(self) -> Result<RagLibrary> {
+        let mut config = self.config;
+        if let Some(dir) = &config.state_dir {
+            std::fs::create_dir_all(dir)?;
+            if config.feedback_log.is_none() {
+                config.feedback_log = Some(dir.join("feedback.jsonl"));
+            }
+        }
+
+        tracing::info!("Initializing RAG Library...");
+        let cipher = match self.state_cipher {
+            Some(cipher) => Some(cipher),
+            None => config.state_encryption_key.as_ref().map(StateCipher::new).transpose()?,
+        };
+        let breakers = (config.circuit_breaker_failures > 0).then(|| {
+            let breaker = |name| Arc::new(CircuitBreaker::new(name, config.circuit_breaker_failures, config.circuit_breaker_cooldown));
+            Breakers { llm: breaker("llm"), embedding: breaker("embedding") }
+        });
+        let embedding_service = with_embedding_breaker(self.embeddings.provider().await?, breakers.as_ref());
+        let mut llm = self.llm.provider(&config.gemini_model)?;
+        let mut prompt_log = None;
+        if let Some(path) = &config.prompt_log {
+            let redactor = Redactor::new(&config.prompt_log_redact, config.prompt_log_redact_pattern.as_deref())?;
+            let log = Arc::new(PromptLog::new(path, config.prompt_log_max_bytes, config.prompt_log_files, redactor)?);
+            tracing::info!("Logging LLM prompts and responses to {}", path.display());
+            llm = Arc::new(LoggedLlmProvider::new(llm, log.clone()));
+            prompt_log = Some(log);
+        }
+        // Outside the prompt log, which then shows what was actually sent
+        if !config.pii_redact.is_empty() || config.pii_ner_url.is_some() {
+            let redactor = PiiRedactor::new(&config.pii_redact, config.pii_ner_url.as_deref())?;
+            tracing::info!("Masking personal data in LLM prompts");
+            llm = Arc::new(RedactingLlmProvider::new(llm, Arc::new(redactor)));
+        }
+        if let Some(breakers) = &breakers {
+            llm = Arc::new(BreakerLlmProvider::new(llm, breakers.llm.clone()));
+        }
+        // Behind the same layers as answering, so extraction prompts are logged and masked too
+        let clause_extractor = (config.clause_extraction != ClauseExtraction::Off)
+            .then(|| Arc::new(ClauseExtractor::new(config.clause_extraction, llm.clone())));
+        let mut query_service = QueryService::new(embedding_service.clone(), llm)
+            .with_llm_batch_size(config.llm_batch_size)
+            .with_response_cache(config.response_cache_ttl)
+            .with_feedback_log(config.feedback_log.clone())
+            .with_session_history(config.session_history_turns)
+            .with_session_summaries(config.session_summaries)
+            .with_session_limits(config.max_sessions, config.session_ttl)
+            .with_request_limits(config.max_results_limit, config.max_context_tokens_limit);
+        if let Some(retriever) = self.retriever {
+            query_service = query_service.with_retriever(retriever);
+        }
+        for reranker in self.rerankers {
+            query_service = query_service.with_reranker(reranker);
+        }
+        if let Some(context_builder) = self.context_builder {
+            query_service = query_service.with_context_builder(context_builder);
+        }
+        if let Some(generator) = self.generator {
+            query_service = query_service.with_generator(generator);
+        }
+        let query_service = Arc::new(query_service);
+
+        let vector_tier = match config.vector_memory_budget_mb {
+            0 => None,
+            megabytes => {
+                let dir = match (&config.vector_spill_dir, &config.state_dir) {
+                    (Some(dir), _) | (None, Some(dir)) => dir.join(SPILL_DIR),
+                    (None, None) => env::temp_dir().join(format!("rag-{}-{}", SPILL_DIR, std::process::id())),
+                };
+                tracing::info!("Spilling vectors beyond {} MB to {}", megabytes, dir.display());
+                Some(Arc::new(VectorTier::new(dir, megabytes.saturating_mul(1024 * 1024))?.with_cipher(cipher.clone())))
+            }
+        };
+        let store = match &vector_tier {
+            Some(tier) => DocumentStore::with_vector_tier(embedding_service.clone(), tier),
+            None => DocumentStore::new(embedding_service.clone()),
+        };
+
+        Ok(RagLibrary {
+            query_service,
+            store: Arc::new(store.with_clause_extractor(clause_extractor.clone())),
+            clause_extractor,
+            prompt_log,
+            embedding_service,
+            embeddings: self.embeddings,
+            breakers,
+            vector_tier,
+            cipher,
+            loaders: Arc::new(self.loaders),
+            ingest_progress: Mutex::new(None),
+            config,
+        })
+    }
+}
+
+impl Default for RagLibraryBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// A document to add to the library's store. The type is taken from the file extension (see
+// LoaderRegistry); URLs without a known one are typed by their Content-Type, else read as PDFs.
+#[derive(Debug, Clone)]
+pub enum DocumentInput {
+    Bytes { filename: String, bytes: Vec<u8> },
+    Path(PathBuf),
+    // Fetched within the configured download limits, and only from public addresses unless
+    // allow_private_urls is set (see Downloader)
+    #[cfg(feature = "http")]
+    Url(String),
+}
+
+pub struct RagLibrary {
+    pub query_service: Arc<QueryService>,
+    // The documents of the documents directory (and extra sources), embedded with
+    // `embedding_service`
+    store: Arc<DocumentStore>,
+    pub embedding_service: Arc<dyn EmbeddingProvider>,
+    embeddings: EmbeddingBackend,
+    // None when disabled
+    breakers: Option<Breakers>,
+    // Shared by every store of the library; None without a vector memory budget
+    vector_tier: Option<Arc<VectorTier>>,
+    // Encrypts what is saved to the state directory; None without a key
+    cipher: Option<StateCipher>,
+    loaders: Arc<LoaderRegistry>,
+    // Shared by every store of the library; None when clause extraction is off
+    clause_extractor: Option<Arc<ClauseExtractor>>,
+    // Scrubbed of purged documents; None without PROMPT_LOG
+    prompt_log: Option<Arc<PromptLog>>,
+    // Set while load_documents ingests the sources
+    ingest_progress: Mutex<Option<IngestProgress>>,
+    pub config: RagConfig,
+}
+
+// How far a load_documents run has got
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IngestProgress {
+    // Documents embedded and searchable, including those resumed from a checkpoint
+    pub indexed: usize,
+    pub total: usize,
+}
+
+// One breaker per provider, shared by everything that calls it
+struct Breakers {
+    llm: Arc<CircuitBreaker>,
+    embedding: Arc<CircuitBreaker>,
+}
+
+fn with_embedding_breaker(provider: Arc<dyn EmbeddingProvider>, breakers: Option<&Breakers>) -> Arc<dyn EmbeddingProvider> {
+    match breakers {
+        Some(breakers) => Arc::new(BreakerEmbeddingProvider::new(provider, breakers.embedding.clone())),
+        None => provider,
+    }
+}
+
+impl RagLibrary {
+    pub fn builder() -> RagLibraryBuilder {
+        RagLibraryBuilder::new()
+    }
+
+    // Set up from the environment with the documents directory loaded
+    pub async fn new() -> Result<Self> {
+        let library = Self::init().await?;
+        library.load_documents().await?;
+        Ok(library)
+    }
+
+    // Sets up the services from the environment (and .env) without ingesting anything, so a
+    // server can start answering probes while `load_documents` runs
+    pub async fn init() -> Result<Self> {
+        // Load environment variables
+        dotenv::dotenv().ok();
+        // The host application may already have installed a subscriber
+        let _ = Logging::new("error").try_init();
+        Self::init_with_config(RagConfig::from_env()).await
+    }
+
+    pub async fn init_with_config(config: RagConfig) -> Result<Self> {
+        Self::builder().with_config(config).build().await
+    }
+
+    // Processor with the configured chunking and loaders
+    pub fn document_processor(&self) -> DocumentProcessor {
+        DocumentProcessor::new()
+            .with_chunking(self.config.chunk_size, self.config.chunk_overlap)
+            .with_loaders(self.loaders.clone())
+    }
+
+    #[cfg(feature = "http")]
+    fn downloader(&self) -> Downloader {
+        let config = &self.config;
+        Downloader::new(config.download_connect_timeout, config.download_timeout, config.max_download_bytes, config.allow_private_urls)
+    }
+
+    pub fn store(&self) -> &Arc<DocumentStore> {
+        &self.store
+    }
+
+    // The cipher encrypting the saved state, if a key is configured
+    pub fn state_cipher(&self) -> Option<&StateCipher> {
+        self.cipher.as_ref()
+    }
+
+    // Ingests and embeds every document in the documents directory and the extra sources
+    // into the store, replacing what it held. Returns the number of documents. With a state
+    // directory, the snapshot there is loaded instead while it is current, and a fresh index
+    // is saved for the next start.
+    //
+    // Documents are ingested in batches of `ingest_checkpoint_every`, each searchable once
+    // embedded (see ingest_progress). With a state directory every batch is also checkpointed,
+    // and a run that was interrupted resumes after the last one, re-ingesting only files that
+    // changed since.
+    #[tracing::instrument(
+        name = "ingest",
+        skip_all,
+        fields(documents_dir = %self.config.documents_dir, from_snapshot = false, documents = Empty, chunk_count = Empty)
+    )]
+    pub async fn load_documents(&self) -> Result<usize> {
+        let span = Span::current();
+        if let Some(path) = self.snapshot_path() {
+            match self.load(&path).await {
+                Ok(SnapshotStatus::Loaded { documents }) => {
+                    tracing::info!("Loaded {} documents from {}", documents, path.display());
+                    span.record("from_snapshot", true);
+                    span.record("documents", documents);
+                    span.record("chunk_count", self.store.chunk_count().await);
+                    // A snapshot saved before a key was configured is replaced by an encrypted one
+                    if self.cipher.is_some() && !encryption::is_encrypted(&path).unwrap_or(true) {
+                        if let Err(e) = self.save_state().await {
+                            tracing::warn!("Failed to encrypt the index snapshot: {}", e);
+                        }
+                    }
+                    return Ok(documents);
+                }
+                Ok(SnapshotStatus::Missing) => {}
+                Ok(SnapshotStatus::Stale(reason)) => tracing::info!("Snapshot is stale ({}), reindexing", reason),
+                Err(e) => tracing::warn!("Ignoring snapshot {}: {}", path.display(), e),
+            }
+        }
+
+        let result = self.ingest_sources().await;
+        *self.ingest_progress.lock().unwrap() = None;
+        let count = result?;
+        span.record("documents", count);
+        span.record("chunk_count", self.store.chunk_count().await);
+        if let Err(e) = self.save_state().await {
+            tracing::warn!("Failed to save the index snapshot: {}", e);
+        }
+
+        tracing::info!("RAG Library initialized successfully!");
+        Ok(count)
+    }
+
+    // Progress of a running load_documents; None when none is running
+    pub fn ingest_progress(&self) -> Option<IngestProgress> {
+        *self.ingest_progress.lock().unwrap()
+    }
+
+    // Ingests the source files batch by batch, resuming from a checkpoint
+    async fn ingest_sources(&self) -> Result<usize> {
+        let processor = self.document_processor();
+        let files = self.source_files(&processor)?;
+        let fingerprint = self.config_fingerprint();
+        let checkpoint_path = self.config.state_dir.as_ref().map(|dir| dir.join(CHECKPOINT_FILE));
+        let checkpoint_path = checkpoint_path.filter(|_| self.config.ingest_checkpoint_every > 0);
+
+        // Documents of the interrupted run whose files are still there, unchanged. Their
+        // embeddings are only current if they do not depend on the rest of the corpus.
+        let fitted = self.store.embeddings().fitted_to_corpus();
+        let each = |document: &mut Document| match fitted {
+            true => document.chunks.iter_mut().for_each(|chunk| chunk.embedding = None),
+            false => self.store.offload(document),
+        };
+        let mut resumed = match &checkpoint_path {
+            Some(path) => Checkpoint::resume(path, self.cipher.as_ref(), &fingerprint, each).unwrap_or_else(|e| {
+                tracing::warn!("Ignoring ingestion checkpoint {}: {}", path.display(), e);
+                Vec::new()
+            }),
+            None => Vec::new(),
+        };
+        resumed.retain(|document| {
+            let unchanged = document.metadata.source.as_ref().is_some_and(|source| {
+                let source = Path::new(source);
+                files.iter().any(|file| file == source) && processor.fingerprint(source).ok() == document.metadata.fingerprint
+            });
+            if !unchanged {
+                self.store.forget_vectors(&document.id);
+            }
+            unchanged
+        });
+        let remaining: Vec<&PathBuf> = files
+            .iter()
+            .filter(|file| {
+                let file = file.to_string_lossy();
+                !resumed.iter().any(|doc| doc.metadata.source.as_deref() == Some(&*file))
+            })
+            .collect();
+        let mut checkpoint = match &checkpoint_path {
+            Some(path) => Some(Checkpoint::create(path, self.cipher.as_ref(), &fingerprint, &resumed, |document| self.with_vectors(document))?),
+            None => None,
+        };
+
+        if resumed.is_empty() {
+            self.store.replace(Vec::new()).await;
+        } else {
+            tracing::info!("Resuming ingestion: {} of {} documents already done", resumed.len(), files.len());
+            if fitted {
+                self.store.rebuild(resumed).await?;
+            } else {
+                self.store.replace(resumed).await;
+            }
+        }
+        let mut indexed = self.store.len().await;
+        *self.ingest_progress.lock().unwrap() = Some(IngestProgress { indexed, total: files.len() });
+
+        let batch_size = match self.config.ingest_checkpoint_every {
+            0 => remaining.len().max(1),
+            every => every,
+        };
+        for batch in remaining.chunks(batch_size) {
+            let mut documents = Vec::with_capacity(batch.len());
+            for file in batch {
+                cancel::check()?;
+                documents.push(processor.process_file(file).await?);
+            }
+            indexed = self.store.add(documents).await?;
+            if let Some(checkpoint) = &mut checkpoint {
+                let documents = self.store.read().await;
+                checkpoint.append(&documents[documents.len() - batch.len()..], |document| self.with_vectors(document))?;
+            }
+            *self.ingest_progress.lock().unwrap() = Some(IngestProgress { indexed, total: files.len() });
+            if remaining.len() > batch_size {
+                tracing::info!("Ingested {} of {} documents", indexed, files.len());
+            }
+        }
+
+        // The snapshot saved next makes the checkpoint redundant
+        if let Some(checkpoint) = checkpoint {
+            if let Err(e) = checkpoint.finish() {
+                tracing::warn!("Failed to remove the ingestion checkpoint: {}", e);
+            }
+        }
+        tracing::info!("Processed {} documents", indexed);
+        Ok(indexed)
+    }
+
+    // Where load_documents keeps its snapshot; None without a state directory
+    pub fn snapshot_path(&self) -> Option<PathBuf> {
+        self.config.state_dir.as_ref().map(|dir| dir.join(SNAPSHOT_FILE))
+    }
+
+    // Saves the store to the state directory, if there is one, so the next start can load it
+    pub async fn save_state(&self) -> Result<()> {
+        match self.snapshot_path() {
+            Some(path) => self.save(&path).await,
+            None => Ok(()),
+        }
+    }
+
+    // Writes the store's documents, with their chunks and embeddings, and the embedding
+    // provider's corpus statistics to `path`, encrypted when a key is configured
+    pub async fn save(&self, path: &Path) -> Result<()> {
+        let documents = self.store.read().await;
+        let embedding_state = self.store.embeddings().fitted_state();
+        Snapshot::write(path, self.cipher.as_ref(), &self.config_fingerprint(), &documents, embedding_state, |document| self.with_vectors(document))?;
+        tracing::info!("Saved {} documents to {}", documents.len(), path.display());
+        Ok(())
+    }
+
+    // `document` of the store with the vectors a vector tier holds for it put back
+    fn with_vectors<'a>(&self, document: &'a Document) -> Result<Cow<'a, Document>> {
+        if self.vector_tier.is_none() {
+            return Ok(Cow::Borrowed(document));
+        }
+        let mut document = document.clone();
+        self.store.restore_vectors(std::slice::from_mut(&mut document))?;
+        Ok(Cow::Owned(document))
+    }
+
+    // Replaces the store's contents with the snapshot at `path` unless it is stale: taken
+    // with other chunking, embedding or clause extraction settings, or before a source file was added, edited
+    // or deleted
+    //
+    // With a vector tier the snapshot is read twice, so the vectors never all sit in memory:
+    // once without them to check it, then again moving each document's into the tier.
+    pub async fn load(&self, path: &Path) -> Result<SnapshotStatus> {
+        let read = match self.vector_tier {
+            Some(_) => Snapshot::read_with(path, self.cipher.as_ref(), |document| document.chunks.iter_mut().for_each(|chunk| chunk.embedding = None)),
+            None => Snapshot::read(path, self.cipher.as_ref()),
+        };
+        let Some(snapshot) = read? else {
+            return Ok(SnapshotStatus::Missing);
+        };
+        if let Some(reason) = self.staleness(&snapshot)? {
+            return Ok(SnapshotStatus::Stale(reason));
+        }
+
+        let mut documents = self.store.write().await;
+        let snapshot = match self.vector_tier {
+            Some(_) => match Snapshot::read_with(path, self.cipher.as_ref(), |document| self.store.offload(document))? {
+                Some(snapshot) => snapshot,
+                None => return Ok(SnapshotStatus::Missing),
+            },
+            None => snapshot,
+        };
+        if let Some(state) = snapshot.embedding_state {
+            self.store.embeddings().restore_fitted_state(state)?;
+        }
+        self.store.swap_in(&mut documents, snapshot.documents);
+        Ok(SnapshotStatus::Loaded { documents: documents.len() })
+    }
+
+    // Clause extraction counts as an embedding setting, so snapshots taken without it are
+    // ingested again; with it off the fingerprint is what it was before clauses existed
+    fn config_fingerprint(&self) -> String {
+        let mut kind = self.store.embeddings().kind();
+        if self.config.clause_extraction != ClauseExtraction::Off {
+            kind = format!("{}|clauses={}", kind, self.config.clause_extraction.as_str());
+        }
+        config_fingerprint(self.config.chunk_size, self.config.chunk_overlap, &kind)
+    }
+
+    // What changed since `snapshot` was taken, if anything
+    fn staleness(&self, snapshot: &Snapshot) -> Result<Option<String>> {
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Ok(Some(format!("format version {} instead of {}", snapshot.version, SNAPSHOT_VERSION)));
+        }
+        if snapshot.config_fingerprint != self.config_fingerprint() {
+            return Ok(Some("chunking, embedding or clause extraction settings changed".to_string()));
+        }
+        if snapshot.embedding_state.is_none() && self.store.embeddings().fitted_to_corpus() {
+            return Ok(Some("no embedding state was saved".to_string()));
+        }
+
+        let processor = self.document_processor();
+        for document in &snapshot.documents {
+            let Some(source) = &document.metadata.source else {
+                continue;
+            };
+            let source = Path::new(source);
+            if !source.exists() {
+                return Ok(Some(format!("{} was deleted", source.display())));
+            }
+            if processor.fingerprint(source).ok() != document.metadata.fingerprint {
+                return Ok(Some(format!("{} changed", source.display())));
+            }
+        }
+        for file in self.source_files(&processor)? {
+            let source = file.to_string_lossy();
+            if !snapshot.documents.iter().any(|doc| doc.metadata.source.as_deref() == Some(&*source)) {
+                return Ok(Some(format!("{} is new", source)));
+            }
+        }
+        Ok(None)
+    }
+
+    // The files load_documents ingests
+    fn source_files(&self, processor: &DocumentProcessor) -> Result<Vec<PathBuf>> {
+        let mut files = processor.list_documents(&self.config.documents_dir)?;
+        for source in &self.config.extra_sources {
+            if source.is_dir() {
+                files.extend(processor.list_documents(&source.to_string_lossy())?);
+            } else {
+                files.push(source.clone());
+            }
+        }
+        Ok(files)
+    }
+
+    // Extracts, chunks and embeds `input` into the store, and returns the new document's id.
+    // Only the new document is embedded unless the embedding provider is fitted to the corpus.
+    #[tracing::instrument(name = "ingest", skip_all, fields(doc_id = Empty, filename = Empty, chunk_count = Empty))]
+    pub async fn add_document(&self, input: DocumentInput) -> Result<String> {
+        let document = self.prepare_document(input).await?;
+        let id = document.id.clone();
+        let span = Span::current();
+        span.record("doc_id", id.as_str());
+        span.record("filename", document.filename.as_str());
+        span.record("chunk_count", document.chunks.len());
+        let count = self.store.add(vec![document]).await?;
+        tracing::info!("Added document {}, {} in the store", id, count);
+        Ok(id)
+    }
+
+    // Removes a document from the store and returns it
+    pub async fn remove_document(&self, id: &str) -> Result<Document> {
+        let removed = self.store.remove(id).await?;
+        tracing::info!("Removed document {} ({})", id, removed.filename);
+        Ok(removed)
+    }
+
+    // Removes the document with id `id` from `store` for good, e.g. for an erasure request:
+    // with its chunks and embeddings, from every cached response citing it and from the prompt
+    // log. A document of the library's own store is also dropped from the saved snapshot. The
+    // file it was ingested from belongs to whoever manages the documents directory and is only
+    // deleted, so it is not ingested again, with `delete_source`.
+    pub async fn purge_from(&self, store: &Arc<DocumentStore>, id: &str, delete_source: bool) -> Result<PurgeReport> {
+        let removed = store.remove(id).await?;
+        let chunk_ids: HashSet<String> = removed.chunks.iter().map(|chunk| chunk.id.clone()).collect();
+        let cached_responses_removed = self.query_service.purge_cached_responses(&chunk_ids);
+
+        let source_deleted = match &removed.metadata.source {
+            Some(source) if delete_source => {
+                let source = Path::new(source);
+                match std::fs::remove_file(source) {
+                    Ok(()) => {
+                        let _ = std::fs::remove_file(source.with_extension("meta.json"));
+                        Some(source.display().to_string())
+                    }
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            Some(source) => {
+                tracing::warn!("Kept {}, the source of purged document {}; it is ingested again on the next reload", source, id);
+                None
+            }
+            None => None,
+        };
+        let chunk_texts: Vec<&str> = removed.chunks.iter().map(|chunk| &*chunk.content).collect();
+        let prompt_log_entries_removed = match &self.prompt_log {
+            Some(log) => log.purge(&removed.filename, &chunk_texts)?,
+            None => 0,
+        };
+        let snapshot_rewritten = Arc::ptr_eq(store, &self.store) && self.snapshot_path().is_some();
+        if snapshot_rewritten {
+            self.save_state().await?;
+        }
+
+        let prompt_log_clean = match &self.prompt_log {
+            Some(log) => !log.holds_any(&removed.filename, &chunk_texts)?,
+            None => true,
+        };
+        let verified = {
+            let documents = store.read().await;
+            !documents.iter().any(|doc| doc.id == id || doc.chunks.iter().any(|chunk| chunk_ids.contains(&chunk.id)))
+                && !self.query_service.caches_any_of(&chunk_ids)
+                && prompt_log_clean
+        };
+        tracing::info!("Purged document {} ({})", id, removed.filename);
+        Ok(PurgeReport {
+            document_id: removed.id,
+            filename: removed.filename,
+            content_sha256: hex::encode(Sha256::digest(removed.content.as_bytes())),
+            chunks_removed: chunk_ids.len(),
+            cached_responses_removed,
+            prompt_log_entries_removed,
+            source_deleted,
+            snapshot_rewritten,
+            verified,
+            purged_at: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default(),
+        })
+    }
+
+    // purge_from the library's own store
+    pub async fn purge_document(&self, id: &str, delete_source: bool) -> Result<PurgeReport> {
+        self.purge_from(&self.store.clone(), id, delete_source).await
+    }
+
+    // Extracted and chunked, but not yet embedded
+    async fn prepare_document(&self, input: DocumentInput) -> Result<Document> {
+        let processor = self.document_processor();
+        match input {
+            DocumentInput::Bytes { filename, bytes } => processor.process_bytes(&filename, &bytes),
+            DocumentInput::Path(path) => processor.process_file(&path).await,
+            #[cfg(feature = "http")]
+            DocumentInput::Url(url) => {
+                let (bytes, content_type) = self.downloader().fetch(&url).await?;
+                let mut filename = filename_from_url(&url);
+                let extension = filename.rsplit_once('.').map(|(_, ext)| ext).unwrap_or_default();
+                if self.loaders.loader(extension).is_none() {
+                    let extension = content_type
+                        .and_then(|content_type| self.loaders.extension_for_mime_type(&content_type))
+                        .unwrap_or("pdf");
+                    filename = format!("{}.{}", filename, extension);
+                }
+                processor.process_bytes(&filename, &bytes)
+            }
+        }
+    }
+
+    // Answers from the store's documents
+    pub async fn answer(&self, request: &QueryRequest) -> Result<QueryResponse> {
+        let documents = self.store.read().await;
+        let request = QueryRequest { collection_version: Some(self.store.generation()), ..request.clone() };
+        self.query_service
+            .answer_with_embeddings(&request, &documents, self.store.embeddings().as_ref())
+            .await
+    }
+
+    // An empty store with a provider of its own, for a collection indexed separately from
+    // this library's (e.g. a tenant's documents)
+    pub async fn new_store(&self) -> Result<DocumentStore> {
+        let embeddings = self.new_embedding_provider().await?;
+        let store = match &self.vector_tier {
+            Some(tier) => DocumentStore::with_vector_tier(embeddings, tier),
+            None => DocumentStore::new(embeddings),
+        };
+        Ok(store.with_clause_extractor(self.clause_extractor.clone()))
+    }
+
+    // Embeds documents that are not part of the shared index (e.g. a PDF fetched for one
+    // request) with a fresh provider, so corpus statistics such as the TF-IDF vocabulary
+    // of the shared index are left untouched. Pass the returned provider to
+    // QueryService::answer_with_embeddings.
+    pub async fn index_ad_hoc(&self, documents: &mut [Document]) -> Result<Arc<dyn EmbeddingProvider>> {
+        entities::enrich(documents);
+        let embeddings = self.new_embedding_provider().await?;
+        let span = info_span!("embedding", kind = "ad_hoc", documents = documents.len());
+        embeddings
+            .generate_embeddings(documents)
+            .instrument(span)
+            .await?;
+        Ok(embeddings)
+    }
+
+    // Provider of the configured kind with no corpus fitted yet, for a collection that is
+    // indexed separately from the shared one (e.g. a tenant's documents)
+    pub async fn new_embedding_provider(&self) -> Result<Arc<dyn EmbeddingProvider>> {
+        Ok(with_embedding_breaker(self.embeddings.provider().await?, self.breakers.as_ref()))
+    }
+
+    // Memory the vector tier uses; None without a vector memory budget
+    pub fn vector_tier_usage(&self) -> Option<VectorTierUsage> {
+        self.vector_tier.as_ref().map(|tier| tier.usage())
+    }
+
+    // Providers whose circuit is open, so calls to them currently fail fast
+    pub fn open_circuits(&self) -> Vec<&'static str> {
+        let Some(breakers) = &self.breakers else { return Vec::new() };
+        [("llm", &breakers.llm), ("embedding", &breakers.embedding)]
+            .into_iter()
+            .filter(|(_, breaker)| breaker.is_open())
+            .map(|(name, _)| name)
+            .collect()
+    }
+}
+
@@ -0,0 +1,120 @@
+use crate::error::{RagError, Result};
+use pdf_extract::extract_text_from_mem;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+
+// Turns the bytes of a file into its text, which DocumentProcessor then chunks. Loaders run
+// on the ingesting task, like the built-in PDF extraction.
+pub trait DocumentLoader: Send + Sync {
+    fn load(&self, filename: &str, bytes: &[u8]) -> Result<String>;
+}
+
+// The loaders DocumentProcessor picks from, by file extension (lowercase, without the dot),
+// with MIME types mapped to an extension. `new` has the built-in ones: PDF, text and Markdown,
+// and DOCX with the `url-ingestion` feature. Registering an extension again replaces its
+// loader, e.g. a different PDF extractor.
+//
+// Directory scans (DocumentProcessor::list_documents) pick up PDFs and the extensions
+// registered with `with_loader`.
+#[derive(Clone)]
+pub struct LoaderRegistry {
+    loaders: HashMap<String, Arc<dyn DocumentLoader>>,
+    mime_types: HashMap<String, String>,
+    scanned: BTreeSet<String>,
+}
+
+impl Default for LoaderRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LoaderRegistry {
+    pub fn new() -> Self {
+        let mut registry = Self { loaders: HashMap::new(), mime_types: HashMap::new(), scanned: BTreeSet::new() };
+        registry.register("pdf", &["application/pdf"], Arc::new(PdfLoader));
+        registry.register("txt", &["text/plain"], Arc::new(TextLoader));
+        registry.register("md", &["text/markdown"], Arc::new(TextLoader));
+        #[cfg(feature = "url-ingestion")]
+        registry.register(
+            "docx",
+            &["application/vnd.openxmlformats-officedocument.wordprocessingml.document"],
+            Arc::new(DocxLoader),
+        );
+        registry.scanned.insert("pdf".to_string());
+        registry
+    }
+
+    // Loads files with extension `extension` (e.g. "xyz" or ".xyz") and content of the given
+    // MIME types with `loader`
+    pub fn with_loader(mut self, extension: &str, mime_types: &[&str], loader: Arc<dyn DocumentLoader>) -> Self {
+        let extension = self.register(extension, mime_types, loader);
+        self.scanned.insert(extension);
+        self
+    }
+
+    fn register(&mut self, extension: &str, mime_types: &[&str], loader: Arc<dyn DocumentLoader>) -> String {
+        let extension = extension.trim_start_matches('.').to_lowercase();
+        for mime_type in mime_types {
+            self.mime_types.insert(mime_type.to_lowercase(), extension.clone());
+        }
+        self.loaders.insert(extension.clone(), loader);
+        extension
+    }
+
+    pub fn loader(&self, extension: &str) -> Option<&Arc<dyn DocumentLoader>> {
+        self.loaders.get(&extension.to_lowercase())
+    }
+
+    // The extension registered for `mime_type`; parameters such as "; charset=utf-8" are ignored
+    pub fn extension_for_mime_type(&self, mime_type: &str) -> Option<&str> {
+        let essence = mime_type.split(';').next().unwrap_or_default().trim().to_lowercase();
+        self.mime_types.get(&essence).map(String::as_str)
+    }
+
+    // Every extension with a loader, sorted
+    pub fn extensions(&self) -> Vec<&str> {
+        let mut extensions: Vec<&str> = self.loaders.keys().map(String::as_str).collect();
+        extensions.sort_unstable();
+        extensions
+    }
+
+    pub(crate) fn is_scanned(&self, extension: &str) -> bool {
+        self.scanned.contains(&extension.to_lowercase())
+    }
+
+    // Text of `bytes` by the extension of `filename`
+    pub fn load(&self, filename: &str, bytes: &[u8]) -> Result<String> {
+        let extension = filename.rsplit_once('.').map(|(_, ext)| ext).unwrap_or_default();
+        match self.loader(extension) {
+            Some(loader) => loader.load(filename, bytes),
+            None => Err(RagError::Ingestion(format!("Unsupported file type for {}", filename))),
+        }
+    }
+}
+
+struct PdfLoader;
+
+impl DocumentLoader for PdfLoader {
+    fn load(&self, filename: &str, bytes: &[u8]) -> Result<String> {
+        extract_text_from_mem(bytes).map_err(|e| RagError::Ingestion(format!("{}: {}", filename, e)))
+    }
+}
+
+struct TextLoader;
+
+impl DocumentLoader for TextLoader {
+    fn load(&self, filename: &str, bytes: &[u8]) -> Result<String> {
+        String::from_utf8(bytes.to_vec()).map_err(|_| RagError::Ingestion(format!("{} is not valid UTF-8 text", filename)))
+    }
+}
+
+#[cfg(feature = "url-ingestion")]
+struct DocxLoader;
+
+#[cfg(feature = "url-ingestion")]
+impl DocumentLoader for DocxLoader {
+    fn load(&self, _filename: &str, bytes: &[u8]) -> Result<String> {
+        crate::ingest::docx_text(bytes)
+    }
+}
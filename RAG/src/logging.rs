@@ -0,0 +1,100 @@
+use std::env;
+use std::error::Error;
+use std::io::{self, IsTerminal};
+use std::str::FromStr;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer, Registry};
+
+// Layout of log lines
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    // Human-readable lines
+    #[default]
+    Text,
+    // One JSON object per line, with span fields, for log shippers
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            other => Err(format!("unknown log format {:?}, expected text or json", other)),
+        }
+    }
+}
+
+// The tracing subscriber shared by the api, rag-cli and RagLibrary::init. Levels come from
+// RUST_LOG in env-filter syntax (e.g. "info,rag_system=debug"), falling back to the default
+// filter. Spans are logged when they close, with their fields and time.busy/time.idle: the
+// latency of "ingest" (doc_id, chunk_count), "embedding", "retrieval" (candidates,
+// chunk_count) and each "llm" call (phase, prompt_tokens, completion_tokens). Records of
+// crates that still log through `log` are forwarded.
+pub struct Logging {
+    default_filter: String,
+    format: LogFormat,
+    stderr: bool,
+    layers: Vec<BoxedLayer>,
+}
+
+// A layer installed next to the log output, e.g. a trace exporter
+pub type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+impl Logging {
+    // Format from LOG_FORMAT ("text" or "json"), text when unset or invalid
+    pub fn new(default_filter: &str) -> Self {
+        let format = env::var("LOG_FORMAT")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or_default();
+        Self { default_filter: default_filter.to_string(), format, stderr: false, layers: Vec::new() }
+    }
+
+    pub fn with_format(mut self, format: LogFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    // Log to stderr instead of stdout, e.g. when stdout carries a command's output
+    pub fn with_stderr(mut self) -> Self {
+        self.stderr = true;
+        self
+    }
+
+    // Also installs `layer`. RUST_LOG only filters the log output, so the layer should bring
+    // its own filter.
+    pub fn with_layer(mut self, layer: BoxedLayer) -> Self {
+        self.layers.push(layer);
+        self
+    }
+
+    // Installs the subscriber globally; fails when one is already installed
+    pub fn try_init(self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(&self.default_filter));
+        // Colours only for a terminal, not for files and log collectors
+        let (writer, ansi) = if self.stderr {
+            (BoxMakeWriter::new(io::stderr), io::stderr().is_terminal())
+        } else {
+            (BoxMakeWriter::new(io::stdout), io::stdout().is_terminal())
+        };
+        let output = tracing_subscriber::fmt::layer()
+            .with_span_events(FmtSpan::CLOSE)
+            .with_ansi(ansi)
+            .with_writer(writer);
+        let output: BoxedLayer = match self.format {
+            LogFormat::Text => output.with_filter(filter).boxed(),
+            LogFormat::Json => output.json().with_current_span(true).with_span_list(true).with_filter(filter).boxed(),
+        };
+
+        let mut layers = self.layers;
+        layers.insert(0, output);
+        tracing_subscriber::registry().with(layers).try_init()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,145 @@
+use crate::models::*;
+use crate::providers::{EmbeddingProvider, LlmProvider};
+use crate::error::Result;
+use async_trait::async_trait;
+use std::sync::Mutex;
+
+const DEFAULT_MOCK_DIMENSIONS: usize = 256;
+const DEFAULT_MOCK_RESPONSE: &str = "This is a mock response generated without calling an LLM.";
+
+// Deterministic embeddings via feature hashing: every token is hashed into a fixed
+// number of buckets, so texts sharing words end up close without any model or API key
+pub struct MockEmbeddingProvider {
+    dimensions: usize,
+}
+
+impl MockEmbeddingProvider {
+    pub fn new() -> Self {
+        Self::with_dimensions(DEFAULT_MOCK_DIMENSIONS)
+    }
+
+    pub fn with_dimensions(dimensions: usize) -> Self {
+        Self {
+            dimensions: dimensions.max(1),
+        }
+    }
+
+    pub fn embed(&self, text: &str) -> Vec<f32> {
+        let mut embedding = vec![0.0; self.dimensions];
+
+        for token in text
+            .to_lowercase()
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|token| !token.is_empty())
+        {
+            let hash = fnv1a(token.as_bytes());
+            let idx = (hash % self.dimensions as u64) as usize;
+            // Use one hash bit as the sign so collisions tend to cancel out
+            let sign = if hash >> 63 == 0 { 1.0 } else { -1.0 };
+            embedding[idx] += sign;
+        }
+
+        let norm: f32 = embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            for value in embedding.iter_mut() {
+                *value /= norm;
+            }
+        }
+
+        embedding
+    }
+}
+
+impl Default for MockEmbeddingProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for MockEmbeddingProvider {
+    async fn generate_embeddings(&self, documents: &mut [Document]) -> Result<()> {
+        for document in documents.iter_mut() {
+            for chunk in document.chunks.iter_mut() {
+                chunk.embedding = Some(self.embed(&chunk.content));
+            }
+        }
+        Ok(())
+    }
+
+    async fn embed_query(&self, query: &str) -> Result<Vec<f32>> {
+        Ok(self.embed(query))
+    }
+
+    fn fitted_to_corpus(&self) -> bool {
+        false
+    }
+
+    fn kind(&self) -> String {
+        format!("mock-{}", self.dimensions)
+    }
+}
+
+// Returns canned responses: the first rule whose needle appears in the prompt wins,
+// otherwise the default response. Every prompt is recorded for assertions.
+pub struct MockLlmProvider {
+    rules: Vec<(String, String)>,
+    default_response: String,
+    prompts: Mutex<Vec<String>>,
+}
+
+impl MockLlmProvider {
+    pub fn new() -> Self {
+        Self::with_default_response(DEFAULT_MOCK_RESPONSE)
+    }
+
+    pub fn with_default_response(response: impl Into<String>) -> Self {
+        Self {
+            rules: Vec::new(),
+            default_response: response.into(),
+            prompts: Mutex::new(Vec::new()),
+        }
+    }
+
+    // Answers with `response` whenever the prompt contains `needle`
+    pub fn with_rule(mut self, needle: impl Into<String>, response: impl Into<String>) -> Self {
+        self.rules.push((needle.into(), response.into()));
+        self
+    }
+
+    pub fn prompts(&self) -> Vec<String> {
+        self.prompts.lock().unwrap().clone()
+    }
+}
+
+impl Default for MockLlmProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl LlmProvider for MockLlmProvider {
+    async fn generate(&self, prompt: &str) -> Result<String> {
+        self.prompts.lock().unwrap().push(prompt.to_string());
+
+        let response = self
+            .rules
+            .iter()
+            .find(|(needle, _)| prompt.contains(needle.as_str()))
+            .map(|(_, response)| response.clone())
+            .unwrap_or_else(|| self.default_response.clone());
+
+        Ok(response)
+    }
+}
+
+// FNV-1a is stable across platforms and Rust versions, unlike std's DefaultHasher
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
@@ -1,34 +1,720 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::{Deref, Range};
+use std::sync::Arc;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "DocumentFields")]
 pub struct Document {
     pub id: String,
     pub filename: String,
-    pub content: String,
+    // The extracted text; chunks are ranges of it
+    pub content: SharedText,
     pub chunks: Vec<DocumentChunk>,
+    #[serde(default)]
+    pub metadata: DocumentMetadata,
+}
+
+impl Document {
+    // Whether `principal` may retrieve this document. Callers without one only read documents
+    // without a restricting access control list.
+    pub fn readable_by(&self, principal: Option<&Principal>) -> bool {
+        match (principal, &self.metadata.access) {
+            (_, None) => true,
+            (Some(principal), Some(access)) => access.allows(principal),
+            (None, Some(access)) => access.is_public(),
+        }
+    }
+}
+
+// Document as it is saved. The chunks are saved with their own text, which is shared with the
+// document's again once it is read back.
+#[derive(Deserialize)]
+struct DocumentFields {
+    id: String,
+    filename: String,
+    content: SharedText,
+    chunks: Vec<DocumentChunk>,
+    #[serde(default)]
+    metadata: DocumentMetadata,
+}
+
+impl From<DocumentFields> for Document {
+    fn from(fields: DocumentFields) -> Self {
+        let DocumentFields { id, filename, content, mut chunks, metadata } = fields;
+        for chunk in &mut chunks {
+            // Chunks saved by older versions are not ranges of the content and keep their own text
+            if let Some(text) = content.slice(chunk.start_position..chunk.end_position).filter(|text| *text == chunk.content) {
+                chunk.content = text;
+            }
+        }
+        Self { id, filename, content, chunks, metadata }
+    }
+}
+
+// Text held once and shared: a document's content and the text of its chunks are ranges of
+// one reference-counted string, so chunks, and the candidates ranked for every query, do not
+// copy it. Derefs to str, and is serialized as a plain string.
+#[derive(Clone, Default)]
+pub struct SharedText {
+    text: Arc<str>,
+    start: usize,
+    end: usize,
+}
+
+impl SharedText {
+    // The byte range `range` of this text, sharing it; None unless it lies within the text, on
+    // character boundaries
+    pub fn slice(&self, range: Range<usize>) -> Option<Self> {
+        self.as_str().get(range.clone())?;
+        Some(Self { text: self.text.clone(), start: self.start + range.start, end: self.start + range.end })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.text[self.start..self.end]
+    }
+}
+
+impl Deref for SharedText {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for SharedText {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl Borrow<str> for SharedText {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<String> for SharedText {
+    fn from(text: String) -> Self {
+        let end = text.len();
+        Self { text: text.into(), start: 0, end }
+    }
+}
+
+impl From<&str> for SharedText {
+    fn from(text: &str) -> Self {
+        Self { text: text.into(), start: 0, end: text.len() }
+    }
+}
+
+impl From<SharedText> for String {
+    fn from(text: SharedText) -> Self {
+        text.as_str().to_string()
+    }
+}
+
+impl PartialEq for SharedText {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for SharedText {}
+
+impl PartialEq<str> for SharedText {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for SharedText {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl Hash for SharedText {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state)
+    }
+}
+
+impl fmt::Display for SharedText {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Debug for SharedText {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl Serialize for SharedText {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for SharedText {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(String::deserialize(deserializer)?.into())
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DocumentMetadata {
+    // Free-form labels such as "current" or "superseded"
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub version: Option<String>,
+    // Unix timestamp (seconds) of the document version, e.g. the file modification time
+    #[serde(default)]
+    pub updated_at: Option<u64>,
+    // File the document was ingested from; None for uploads and documents fetched by URL
+    #[serde(default)]
+    pub source: Option<String>,
+    // SHA-256 of the source file and its metadata sidecar when it was ingested, to tell which
+    // files changed since
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+    // Who may retrieve the document; public when None
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access: Option<AccessControl>,
+    // Clauses extracted at ingest (see ClauseExtractor); None when extraction did not run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clauses: Option<Vec<Clause>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ClauseKind {
+    WaitingPeriod,
+    SubLimit,
+    Exclusion,
+    SumInsured,
+}
+
+impl std::str::FromStr for ClauseKind {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        match value.trim().to_lowercase().replace([' ', '-'], "_").as_str() {
+            "waiting_period" => Ok(Self::WaitingPeriod),
+            "sub_limit" | "sublimit" => Ok(Self::SubLimit),
+            "exclusion" => Ok(Self::Exclusion),
+            "sum_insured" => Ok(Self::SumInsured),
+            other => Err(format!("unknown clause kind {:?}, expected waiting_period, sub_limit, exclusion or sum_insured", other)),
+        }
+    }
+}
+
+// A policy term stated in a document, e.g. the waiting period for cataract surgery
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Clause {
+    pub kind: ClauseKind,
+    // What the clause applies to, e.g. "cataract surgery"; empty when it is the whole policy
+    pub subject: String,
+    // As stated, e.g. "24 months", "10%" or "Rs. 50,000"; None for exclusions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    // The sentence stating it
+    pub text: String,
+    // Chunk the sentence is in
+    pub chunk_id: String,
+}
+
+// Callers a document is restricted to: those matching any listed user, tenant or role. With
+// every list empty the document is public.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct AccessControl {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub users: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tenants: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<String>,
+}
+
+impl AccessControl {
+    pub fn is_public(&self) -> bool {
+        self.users.is_empty() && self.tenants.is_empty() && self.roles.is_empty()
+    }
+
+    pub fn allows(&self, principal: &Principal) -> bool {
+        self.is_public()
+            || self.users.contains(&principal.user)
+            || self.tenants.contains(&principal.tenant)
+            || self.roles.iter().any(|role| principal.roles.contains(role))
+    }
+}
+
+// The caller a question is answered for, checked against document ACLs
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Principal {
+    pub user: String,
+    pub tenant: String,
+    #[serde(default)]
+    pub roles: Vec<String>,
+}
+
+// What RagLibrary::purge_from removed, so a deletion request can be answered with evidence
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct PurgeReport {
+    pub document_id: String,
+    pub filename: String,
+    // SHA-256 of the removed text, so the requester can tell which content was purged without
+    // it being kept anywhere
+    pub content_sha256: String,
+    // Chunks removed with their embeddings, in memory and spilled to disk
+    pub chunks_removed: usize,
+    // Cached responses that cited the document
+    pub cached_responses_removed: usize,
+    // Prompt log entries whose prompt or response held the document's text (see
+    // PromptLog::purge); 0 without PROMPT_LOG
+    #[serde(default)]
+    pub prompt_log_entries_removed: usize,
+    // The source file in the documents directory and its metadata sidecar, when the document
+    // was ingested from one and deleting it was asked for, so it is not ingested again
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_deleted: Option<String>,
+    // The index snapshot was saved again without the document
+    pub snapshot_rewritten: bool,
+    // Checked after the purge, and only covering these: the store holds neither the document
+    // nor any of its chunks, no cached response cites one of its chunks, and no prompt log entry
+    // holds its text. Answers and logs kept elsewhere (tracing output, the LLM provider, clients)
+    // are not checked. The api also requires that no audit entry cites one of its chunks.
+    pub verified: bool,
+    // Unix timestamp (seconds)
+    pub purged_at: u64,
+}
+
+// Weights for mixing document metadata into chunk scores:
+// score = similarity + recency * (relative age, newest = 1) + sum of matching tag weights
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RankingWeights {
+    #[serde(default)]
+    pub recency: f32,
+    #[serde(default)]
+    pub tags: HashMap<String, f32>,
+}
+
+impl RankingWeights {
+    pub fn is_neutral(&self) -> bool {
+        self.recency == 0.0 && self.tags.values().all(|w| *w == 0.0)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DocumentChunk {
     pub id: String,
-    pub content: String,
+    pub content: SharedText,
+    // Byte offsets of the chunk in its document's content
     pub start_position: usize,
     pub end_position: usize,
     pub embedding: Option<Vec<f32>>,
+    // Found by entities::enrich when the chunk is stored; None before that
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entities: Option<Vec<Entity>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum EntityKind {
+    // Sums of money and percentages, e.g. "Rs. 50,000" or "10%"
+    Amount,
+    // e.g. "24 months"
+    Duration,
+    // e.g. "aged 46" or "18 years of age"
+    Age,
+    Location,
+    // Medical procedures, e.g. "cataract surgery"
+    Procedure,
+}
+
+// Something a chunk mentions, as written
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Entity {
+    pub kind: EntityKind,
+    pub text: String,
+}
+
+// A retrieved chunk with the score of the current ranking stage and its raw query similarity
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScoredChunk {
+    pub chunk: DocumentChunk,
+    pub score: f32,
+    pub similarity: f32,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RetrievalStrategy {
+    // Single dense retrieval pass for the (optionally rewritten) query
+    #[default]
+    Dense,
+    // Retrieve for several LLM-generated query variants and fuse with RRF
+    MultiQuery,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeywordMode {
+    // Chunks mentioning the keywords rank higher
+    #[default]
+    Boost,
+    // Only chunks mentioning at least one keyword are considered
+    Filter,
+}
+
+pub const INSUFFICIENT_INFORMATION_RESPONSE: &str =
+    "I don't have enough information in the provided documents to answer that question.";
+
+// What to do when the documents don't support an answer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct AbstentionPolicy {
+    // Minimum query similarity for a chunk to be used; if no chunk reaches it the
+    // service abstains without calling the LLM. None keeps every chunk.
+    #[serde(default)]
+    pub threshold: Option<f32>,
+    // Reply when abstaining; the LLM is also told to use it for unanswerable questions
+    #[serde(default = "default_abstention_message")]
+    pub message: String,
+    // Answer from general knowledge, labelled as not coming from the documents, instead of refusing
+    #[serde(default)]
+    pub general_knowledge_fallback: bool,
+}
+
+fn default_abstention_message() -> String {
+    INSUFFICIENT_INFORMATION_RESPONSE.to_string()
+}
+
+impl Default for AbstentionPolicy {
+    fn default() -> Self {
+        Self {
+            threshold: None,
+            message: default_abstention_message(),
+            general_knowledge_fallback: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseMode {
+    // Free-text answer
+    #[default]
+    Answer,
+    // Claim adjudication: a typed `Decision` alongside the justification text
+    Decision,
+    // Per-document answers plus a comparison table across the documents in scope
+    Compare,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum DecisionOutcome {
+    Approved,
+    Rejected,
+    NeedsInfo,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Decision {
+    pub decision: DecisionOutcome,
+    // Payable amount, when the policy states one
+    pub amount: Option<f64>,
+    // Clause numbers or headings the decision relies on
+    pub applicable_clauses: Vec<String>,
+    pub justification: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct QueryRequest {
     pub query: String,
     pub max_results: Option<usize>,
+    // Language to answer in; detected from the query when not set
+    pub language: Option<String>,
+    // MMR relevance/diversity trade-off in [0, 1]; overrides the service default
+    pub mmr_lambda: Option<f32>,
+    // Rewrite the query with the LLM before retrieval; overrides the service default
+    pub rewrite_query: Option<bool>,
+    // Retrieval strategy; overrides the service default
+    pub strategy: Option<RetrievalStrategy>,
+    // Conversation to continue, created with QueryService::create_session; unknown or expired
+    // ids fail with RagError::NotFound
+    pub session_id: Option<String>,
+    // Restrict retrieval to these documents (matched by id or filename)
+    pub document_ids: Option<Vec<String>>,
+    pub filenames: Option<Vec<String>>,
+    // Minimum query similarity for a chunk to be used; overrides the service default
+    pub score_threshold: Option<f32>,
+    // Adjacent chunks to add on each side of every hit; overrides the service default
+    pub neighbor_window: Option<usize>,
+    // Similarity above which two retrieved chunks count as duplicates; overrides the service default
+    pub duplicate_threshold: Option<f32>,
+    // Must-include keywords such as "maternity" or "grace period"
+    pub keywords: Option<Vec<String>>,
+    pub keyword_mode: Option<KeywordMode>,
+    // Score added for matching every keyword in boost mode
+    pub keyword_boost: Option<f32>,
+    // Token budget for the retrieved context; overrides the service default
+    pub max_context_tokens: Option<usize>,
+    // Return scores per ranking stage, the packed context and the full prompt
+    pub debug: Option<bool>,
+    // Metadata-aware scoring; overrides the service default
+    pub ranking_weights: Option<RankingWeights>,
+    pub response_mode: Option<ResponseMode>,
+    // Translate the query into the corpus language before retrieval when they differ
+    pub translate_query: Option<bool>,
+    // Replaces the service's abstention policy; `score_threshold` still overrides its threshold
+    pub abstention: Option<AbstentionPolicy>,
+    // Owner of the documents being queried; answers and feedback are only visible to it
+    pub tenant: Option<String>,
+    // Caller whose identity restricts retrieval to the documents it may read (see
+    // AccessControl); without one only documents without an access control list are used
+    pub principal: Option<Principal>,
+    // Answer straight from an extracted clause when exactly one value matches the question,
+    // without the LLM (default true)
+    pub clause_answers: Option<bool>,
+    // Only use chunks mentioning one of these kinds of entity
+    pub entity_kinds: Option<Vec<EntityKind>>,
+    // Without entity_kinds, boost chunks mentioning the kinds the question asks for, e.g.
+    // durations for a waiting period question (default true; see EntityReranker)
+    pub entity_boost: Option<bool>,
+    // Version of the documents being queried, e.g. DocumentStore::generation; answers are only
+    // cached for a versioned collection. Set by the server, never taken from a request body.
+    #[serde(skip)]
+    pub collection_version: Option<u64>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+// Query similarity of the chunks retrieved for a question, a rough measure of how well the
+// documents cover it
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RetrievalScores {
+    pub chunks: usize,
+    pub top: f32,
+    pub mean: f32,
+    pub min: f32,
+}
+
+impl RetrievalScores {
+    // None when nothing was retrieved
+    pub fn from_chunks(chunks: &[ScoredChunk]) -> Option<Self> {
+        if chunks.is_empty() {
+            return None;
+        }
+        let similarities = chunks.iter().map(|scored| scored.similarity);
+        Some(Self {
+            chunks: chunks.len(),
+            top: similarities.clone().fold(f32::MIN, f32::max),
+            mean: similarities.clone().sum::<f32>() / chunks.len() as f32,
+            min: similarities.fold(f32::MAX, f32::min),
+        })
+    }
+}
+
+// Share of the answer confidence each signal carries; signals that are missing hand their
+// share to the others
+const TOP_SIMILARITY_WEIGHT: f32 = 0.6;
+const SEPARATION_WEIGHT: f32 = 0.2;
+const GENERATION_WEIGHT: f32 = 0.2;
+
+// Lead of the best chunk over the mean similarity at which it counts as fully separated
+const FULL_SEPARATION: f32 = 0.2;
+
+// How sure the system is of an answer, with the signals it combines. Each is in [0, 1].
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct AnswerConfidence {
+    pub score: f32,
+    // Query similarity of the best chunk
+    pub top_similarity: f32,
+    // How far the best chunk stands out from the other retrieved chunks; None with one chunk
+    pub separation: Option<f32>,
+    // The model's own confidence (see providers::Generation), when the provider reports one
+    pub generation: Option<f32>,
+}
+
+impl AnswerConfidence {
+    // None when nothing was retrieved
+    pub fn from_signals(retrieval: Option<&RetrievalScores>, generation: Option<f32>) -> Option<Self> {
+        let retrieval = retrieval?;
+        let unit = |value: f32| if value.is_nan() { 0.0 } else { value.clamp(0.0, 1.0) };
+        let top_similarity = unit(retrieval.top);
+        let separation = (retrieval.chunks > 1).then(|| unit((retrieval.top - retrieval.mean) / FULL_SEPARATION));
+        let generation = generation.map(unit);
+
+        let signals = [
+            (Some(top_similarity), TOP_SIMILARITY_WEIGHT),
+            (separation, SEPARATION_WEIGHT),
+            (generation, GENERATION_WEIGHT),
+        ];
+        let (sum, weights) = signals
+            .iter()
+            .filter_map(|(value, weight)| value.map(|value| (value * weight, weight)))
+            .fold((0.0, 0.0), |(sum, weights), (value, weight)| (sum + value, weights + weight));
+        Some(Self { score: sum / weights, top_similarity, separation, generation })
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct QueryResponse {
+    // Identifies this answer, e.g. when sending feedback about it
+    #[serde(default)]
+    pub query_id: String,
     pub status: String,
     pub response: String,
     pub citations: Vec<Citation>,
     pub processing_time_ms: u128,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rewritten_query: Option<String>,
+    // Query in the corpus language, when it was translated for retrieval
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub translated_query: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub debug: Option<QueryDebug>,
+    // Similarity of the retrieved chunks; None when the question skipped retrieval
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retrieval_scores: Option<RetrievalScores>,
+    // How sure the system is of the answer, in [0, 1] (see AnswerConfidence); None when the
+    // question skipped retrieval
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence_score: Option<f32>,
+    // Set in decision mode when the LLM output could be parsed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decision: Option<Decision>,
+    // Values the retrieved documents disagree on
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub conflicts: Vec<Conflict>,
+    // Compare mode: the answer for each document, split out of the full response
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub document_answers: Vec<DocumentAnswer>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct DocumentAnswer {
+    pub document: String,
+    pub answer: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Conflict {
+    // Words describing what the values measure, e.g. "waiting period"
+    pub topic: String,
+    pub values: Vec<ConflictingValue>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ConflictingValue {
+    pub document: String,
+    // As written in the document, e.g. "36 months"
+    pub value: String,
+    pub chunk_id: String,
+}
+
+// Diagnostics returned when a request sets `debug: true`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct QueryDebug {
+    // "document_question", "small_talk" or "clause"
+    pub route: String,
+    pub retrieval_query: String,
+    pub stages: Vec<RankingStage>,
+    // Packed context and full prompt sent to the LLM
+    pub context: String,
+    pub prompt: String,
+    // The signals behind the response's confidence_score
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<AnswerConfidence>,
+}
+
+// Ranking after one retrieval stage (dense, multi_query_rrf, keywords, entities, score_threshold, deduplicate, mmr)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RankingStage {
+    pub name: String,
+    pub candidates: usize,
+    pub top: Vec<StageScore>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct StageScore {
+    pub chunk_id: String,
+    pub score: f32,
+    pub similarity: f32,
+}
+
+// Progress reported by QueryService::answer_streaming while an answer is produced
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StreamEvent {
+    // Pipeline stage that just started ("retrieving", "generating")
+    Status { stage: String },
+    // Retrieval finished: number of chunks and the documents they came from
+    Retrieved { chunks: usize, documents: Vec<String> },
+    // Next piece of the answer text
+    Delta { text: String },
+}
+
+// Ranked chunks returned by QueryService::retrieve, without an LLM answer
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RetrievalResponse {
+    pub status: String,
+    pub query: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rewritten_query: Option<String>,
+    // Query in the corpus language, when it was translated for retrieval
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub translated_query: Option<String>,
+    pub chunks: Vec<RetrievedChunk>,
+    pub processing_time_ms: u128,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub debug: Option<QueryDebug>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RetrievedChunk {
+    pub document_id: String,
+    pub filename: String,
+    pub chunk_id: String,
+    pub content: String,
+    pub start_position: usize,
+    pub end_position: usize,
+    // Final ranking score (after fusion and keyword boosts)
+    pub score: f32,
+    // Raw similarity to the query
+    pub similarity: f32,
+    // Where the chunk lies in the document's extracted text, with character and per-page offsets
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<SourceOffsets>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -36,6 +722,54 @@ pub struct Citation {
     pub document: String,
     pub text_excerpt: String,
     pub confidence_score: f32,
+    #[serde(default)]
+    pub chunk_id: String,
+    // 1-based page the chunk starts on, when the extracted text kept page breaks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page: Option<usize>,
+    // Spans of the cited chunk that support the answer, for highlighting evidence
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub highlights: Vec<TextSpan>,
+    // Where the chunk lies in the document's extracted text; a highlight's offsets are
+    // relative to `source.char_start`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<SourceOffsets>,
+}
+
+// Exact position of a chunk in its document's extracted text, for highlighting it in the
+// source document
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SourceOffsets {
+    // Byte range [byte_start, byte_end) of the UTF-8 text
+    pub byte_start: usize,
+    pub byte_end: usize,
+    // The same range in characters (Unicode scalar values)
+    pub char_start: usize,
+    pub char_end: usize,
+    // The part on each page the range covers, in order; empty when the text has no page breaks
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pages: Vec<PageSpan>,
+}
+
+// A character range [char_start, char_end) of one page's text (pages are separated by form
+// feeds in the extracted text)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct PageSpan {
+    // 1-based
+    pub page: usize,
+    pub char_start: usize,
+    pub char_end: usize,
+}
+
+// A character range [start, end) inside a chunk, with the text it covers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct TextSpan {
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -68,6 +802,9 @@ pub struct GeminiResponse {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GeminiCandidate {
     pub content: GeminiContent,
+    // Mean log-probability of the generated tokens, reported by most models
+    #[serde(default, rename = "avgLogprobs", skip_serializing_if = "Option::is_none")]
+    pub avg_logprobs: Option<f64>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -0,0 +1,378 @@
+use crate::error::{RagError, Result};
+use crate::prompt_log::{
+    DATE_PATTERN, EMAIL_PATTERN, PHONE_MIN_DIGITS, PHONE_PATTERN, POLICY_NUMBER_MIN_DIGITS, POLICY_NUMBER_PATTERN,
+};
+use crate::providers::{Generation, LlmProvider};
+use async_trait::async_trait;
+use regex::{Captures, Regex};
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock};
+use tokio::sync::mpsc;
+
+// Kinds PII_REDACT accepts, in the order they are applied: numbers with a fixed shape go
+// before the looser phone and policy number patterns that would also match them
+pub const PII_KINDS: &[&str] = &["aadhaar", "pan", "email", "phone", "policy_number", "name"];
+
+// Streamed text from an unclosed '[' on is held back until it can no longer be the start of
+// a placeholder
+const MAX_PLACEHOLDER_LEN: usize = 32;
+
+// A placeholder as PiiRedactor writes them, e.g. [NAME_1]; compiled once, restore runs on
+// every answer and streamed piece
+static PLACEHOLDER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\[[A-Z_]+_\d+\]").unwrap());
+
+// Masks personal data in prompts before they leave the server, replacing each distinct value
+// with a numbered placeholder such as [NAME_1] or [AADHAAR_2]. The returned PiiMapping turns
+// the placeholders the LLM repeats back into the original values.
+//
+// Kinds: "aadhaar" (12 digits, optionally in groups of four), "pan" (ABCDE1234F), "email",
+// "phone" and "policy_number" (as in prompt_log::Redactor), and "name": words after an
+// honorific (Mr, Mrs, Dr, Shri, Smt, ...) or a label such as "Insured:" or "Nominee -".
+// With a NER endpoint, the PERSON entities it finds are masked as names too.
+pub struct PiiRedactor {
+    rules: Vec<Rule>,
+    #[cfg(feature = "http")]
+    ner: Option<NerClient>,
+}
+
+struct Rule {
+    // Placeholder label, e.g. "NAME"
+    label: String,
+    // The value is the first capture group when there is one, else the whole match
+    pattern: Regex,
+    min_digits: usize,
+    keep: Option<Regex>,
+}
+
+impl PiiRedactor {
+    pub fn new(kinds: &[String], ner_url: Option<&str>) -> Result<Self> {
+        if let Some(unknown) = kinds.iter().find(|kind| !PII_KINDS.contains(&kind.as_str())) {
+            return Err(RagError::Config(format!(
+                "Unknown PII kind {:?}, expected one of {}",
+                unknown,
+                PII_KINDS.join(", ")
+            )));
+        }
+        let rules = PII_KINDS
+            .iter()
+            .filter(|kind| kinds.iter().any(|wanted| wanted == *kind))
+            .map(|kind| {
+                let (pattern, min_digits, keep) = match *kind {
+                    "aadhaar" => (r"\b[2-9]\d{3}[ -]?\d{4}[ -]?\d{4}\b", 0, None::<String>),
+                    "pan" => (r"\b[A-Z]{5}\d{4}[A-Z]\b", 0, None),
+                    "email" => (EMAIL_PATTERN, 0, None),
+                    "phone" => (PHONE_PATTERN, PHONE_MIN_DIGITS, None),
+                    // Unlike in the prompt log, plain numbers are kept: they are usually amounts
+                    // the answer depends on
+                    "policy_number" => (
+                        POLICY_NUMBER_PATTERN,
+                        POLICY_NUMBER_MIN_DIGITS,
+                        Some(format!(r"{}|^\d+$", DATE_PATTERN)),
+                    ),
+                    _ => (
+                        concat!(
+                            r"(?:\b(?:Mr|Mrs|Ms|Miss|Dr|Shri|Sri|Smt|Kumari|Km)\.?\s+",
+                            r"|(?i:\b(?:name|insured(?: person)?|proposer|policy ?holder|nominee|patient))\s*[:\-]\s*)",
+                            r"([A-Z][a-z]+(?:[ \t]+[A-Z][a-z]+){0,3})"
+                        ),
+                        0,
+                        None,
+                    ),
+                };
+                Rule {
+                    label: kind.to_uppercase(),
+                    pattern: Regex::new(pattern).unwrap(),
+                    min_digits,
+                    keep: keep.map(|keep| Regex::new(&keep).unwrap()),
+                }
+            })
+            .collect();
+
+        #[cfg(not(feature = "http"))]
+        if ner_url.is_some() {
+            return Err(RagError::Config("A PII NER endpoint needs the http feature".to_string()));
+        }
+        Ok(Self {
+            rules,
+            #[cfg(feature = "http")]
+            ner: ner_url.map(NerClient::new).transpose()?,
+        })
+    }
+
+    // `text` with personal data replaced by placeholders, and how to put it back
+    pub async fn redact(&self, text: &str) -> Result<(String, PiiMapping)> {
+        let mut mapping = PiiMapping::default();
+        #[cfg(feature = "http")]
+        let mut text = match &self.ner {
+            Some(ner) => ner.redact_names(text, &mut mapping).await?,
+            None => text.to_string(),
+        };
+        #[cfg(not(feature = "http"))]
+        let mut text = text.to_string();
+
+        for rule in &self.rules {
+            text = rule
+                .pattern
+                .replace_all(&text, |captures: &Captures| {
+                    let matched = captures.get(0).unwrap();
+                    let value = captures.get(1).unwrap_or(matched);
+                    let digits = value.as_str().chars().filter(char::is_ascii_digit).count();
+                    if digits < rule.min_digits || rule.keep.as_ref().is_some_and(|keep| keep.is_match(value.as_str())) {
+                        return matched.as_str().to_string();
+                    }
+                    let placeholder = mapping.placeholder(&rule.label, value.as_str());
+                    let (start, end) = (value.start() - matched.start(), value.end() - matched.start());
+                    format!("{}{}{}", &matched.as_str()[..start], placeholder, &matched.as_str()[end..])
+                })
+                .into_owned();
+        }
+        Ok((text, mapping))
+    }
+}
+
+// Placeholders handed out while redacting one prompt, and the values they stand for
+#[derive(Debug, Clone, Default)]
+pub struct PiiMapping {
+    values: HashMap<String, String>,
+    // (label, value) -> placeholder, so a value repeated in the prompt keeps one placeholder
+    placeholders: HashMap<(String, String), String>,
+}
+
+impl PiiMapping {
+    fn placeholder(&mut self, label: &str, value: &str) -> String {
+        let key = (label.to_string(), value.to_string());
+        if let Some(placeholder) = self.placeholders.get(&key) {
+            return placeholder.clone();
+        }
+        let count = self.placeholders.keys().filter(|(other, _)| other == label).count();
+        let placeholder = format!("[{}_{}]", label, count + 1);
+        self.values.insert(placeholder.clone(), value.to_string());
+        self.placeholders.insert(key, placeholder.clone());
+        placeholder
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    // `text` with the placeholders of this mapping replaced by their values; others are kept
+    pub fn restore(&self, text: &str) -> String {
+        if self.is_empty() {
+            return text.to_string();
+        }
+        PLACEHOLDER
+            .replace_all(text, |captures: &Captures| {
+                self.values.get(&captures[0]).cloned().unwrap_or_else(|| captures[0].to_string())
+            })
+            .into_owned()
+    }
+
+    // Where in `pending` streamed text has to be held back because a placeholder may be cut
+    // off there; the end of `pending` when nothing is
+    fn safe_prefix_len(pending: &str) -> usize {
+        match pending.rfind('[') {
+            Some(open) if !pending[open..].contains(']') && pending.len() - open < MAX_PLACEHOLDER_LEN => open,
+            _ => pending.len(),
+        }
+    }
+}
+
+// Finds names with a Presidio-compatible analyzer: POST {"text", "language", "entities"}
+// answered by [{"entity_type", "start", "end"}], offsets counted in characters
+#[cfg(feature = "http")]
+struct NerClient {
+    url: String,
+    client: reqwest::Client,
+}
+
+#[cfg(feature = "http")]
+#[derive(serde::Deserialize)]
+struct NerEntity {
+    entity_type: String,
+    start: usize,
+    end: usize,
+}
+
+#[cfg(feature = "http")]
+impl NerClient {
+    fn new(url: &str) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(10))
+            .build()
+            .map_err(|e| RagError::Config(format!("Cannot create the NER client: {}", e)))?;
+        Ok(Self { url: url.to_string(), client })
+    }
+
+    async fn redact_names(&self, text: &str, mapping: &mut PiiMapping) -> Result<String> {
+        let body = serde_json::json!({ "text": text, "language": "en", "entities": ["PERSON"] });
+        let failed = |e: reqwest::Error| RagError::Llm(format!("PII detection failed: {}", e));
+        let response = self.client.post(&self.url).json(&body).send().await.map_err(failed)?;
+        let entities: Vec<NerEntity> = response.error_for_status().map_err(failed)?.json().await.map_err(failed)?;
+
+        // Character offsets to byte offsets, with one past the end
+        let bytes: Vec<usize> = text.char_indices().map(|(index, _)| index).chain([text.len()]).collect();
+        let mut spans: Vec<(usize, usize)> = entities
+            .iter()
+            .filter(|entity| entity.entity_type == "PERSON" && entity.start < entity.end && entity.end < bytes.len())
+            .map(|entity| (bytes[entity.start], bytes[entity.end]))
+            .collect();
+        spans.sort_unstable();
+
+        let mut redacted = String::with_capacity(text.len());
+        let mut copied = 0;
+        for (start, end) in spans {
+            // Overlapping entities: the first one wins
+            if start < copied {
+                continue;
+            }
+            redacted.push_str(&text[copied..start]);
+            redacted.push_str(&mapping.placeholder("NAME", &text[start..end]));
+            copied = end;
+        }
+        redacted.push_str(&text[copied..]);
+        Ok(redacted)
+    }
+}
+
+// An LLM provider that only ever sees redacted prompts; placeholders in its output are
+// replaced by the original values, so answers read as if nothing had been masked. Embedding
+// calls are not affected.
+pub struct RedactingLlmProvider {
+    inner: Arc<dyn LlmProvider>,
+    redactor: Arc<PiiRedactor>,
+}
+
+impl RedactingLlmProvider {
+    pub fn new(inner: Arc<dyn LlmProvider>, redactor: Arc<PiiRedactor>) -> Self {
+        Self { inner, redactor }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for RedactingLlmProvider {
+    async fn generate(&self, prompt: &str) -> Result<String> {
+        let (prompt, mapping) = self.redactor.redact(prompt).await?;
+        let text = self.inner.generate(&prompt).await?;
+        Ok(mapping.restore(&text))
+    }
+
+    async fn generate_with_confidence(&self, prompt: &str) -> Result<Generation> {
+        let (prompt, mapping) = self.redactor.redact(prompt).await?;
+        let generation = self.inner.generate_with_confidence(&prompt).await?;
+        Ok(Generation { text: mapping.restore(&generation.text), ..generation })
+    }
+
+    async fn generate_stream(&self, prompt: &str, deltas: &mpsc::Sender<String>) -> Result<String> {
+        let (prompt, mapping) = self.redactor.redact(prompt).await?;
+        let (sender, mut receiver) = mpsc::channel(32);
+        let generate = async move { self.inner.generate_stream(&prompt, &sender).await };
+        let forward = async {
+            let mut pending = String::new();
+            while let Some(delta) = receiver.recv().await {
+                pending.push_str(&delta);
+                let ready: String = pending.drain(..PiiMapping::safe_prefix_len(&pending)).collect();
+                if !ready.is_empty() {
+                    let _ = deltas.send(mapping.restore(&ready)).await;
+                }
+            }
+            if !pending.is_empty() {
+                let _ = deltas.send(mapping.restore(&pending)).await;
+            }
+        };
+        let (text, ()) = tokio::join!(generate, forward);
+        Ok(mapping.restore(&text?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLAIM: &str = "Claim by Mr. Ravi Kumar (Aadhaar 2345 6789 0123, PAN ABCDE1234F, ravi.kumar@example.com) \
+                         for Rs. 50000. Nominee: Sita Kumar. Contact Mr. Ravi Kumar on +91 98765 43210.";
+
+    fn redactor(kinds: &[&str]) -> PiiRedactor {
+        let kinds: Vec<String> = kinds.iter().map(|kind| kind.to_string()).collect();
+        PiiRedactor::new(&kinds, None).unwrap()
+    }
+
+    // Echoes the prompt back, streamed in pieces of `piece` bytes so placeholders are cut
+    struct EchoLlm {
+        piece: usize,
+    }
+
+    #[async_trait]
+    impl LlmProvider for EchoLlm {
+        async fn generate(&self, prompt: &str) -> Result<String> {
+            Ok(prompt.to_string())
+        }
+
+        async fn generate_stream(&self, prompt: &str, deltas: &mpsc::Sender<String>) -> Result<String> {
+            for piece in prompt.as_bytes().chunks(self.piece) {
+                let _ = deltas.send(String::from_utf8(piece.to_vec()).unwrap()).await;
+            }
+            Ok(prompt.to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn redacts_each_kind_and_restores_the_values() {
+        let redactor = redactor(PII_KINDS);
+        let (redacted, mapping) = redactor.redact(CLAIM).await.unwrap();
+
+        assert_eq!(
+            redacted,
+            "Claim by Mr. [NAME_1] (Aadhaar [AADHAAR_1], PAN [PAN_1], [EMAIL_1]) for Rs. 50000. \
+             Nominee: [NAME_2]. Contact Mr. [NAME_1] on [PHONE_1]."
+        );
+        assert_eq!(mapping.restore(&redacted), CLAIM);
+        // Placeholders the mapping did not hand out are left alone
+        assert_eq!(mapping.restore("[NAME_1] and [NAME_9]"), "Ravi Kumar and [NAME_9]");
+    }
+
+    #[tokio::test]
+    async fn redacts_only_the_kinds_asked_for() {
+        let (redacted, mapping) = redactor(&["pan"]).redact(CLAIM).await.unwrap();
+        assert_eq!(redacted, CLAIM.replace("ABCDE1234F", "[PAN_1]"));
+        assert!(!mapping.is_empty());
+
+        let (redacted, mapping) = redactor(&[]).redact(CLAIM).await.unwrap();
+        assert_eq!(redacted, CLAIM);
+        assert!(mapping.is_empty());
+    }
+
+    #[test]
+    fn unknown_kinds_are_rejected() {
+        let kinds = vec!["name".to_string(), "passport".to_string()];
+        assert!(matches!(PiiRedactor::new(&kinds, None), Err(RagError::Config(_))));
+    }
+
+    #[test]
+    fn streaming_holds_back_what_may_be_a_placeholder() {
+        assert_eq!(PiiMapping::safe_prefix_len("Claim of [NAM"), "Claim of ".len());
+        assert_eq!(PiiMapping::safe_prefix_len("Claim of [NAME_1] is"), "Claim of [NAME_1] is".len());
+        assert_eq!(PiiMapping::safe_prefix_len("no brackets"), "no brackets".len());
+        // Too long ago to still be a placeholder
+        let text = format!("[{}", "x".repeat(MAX_PLACEHOLDER_LEN));
+        assert_eq!(PiiMapping::safe_prefix_len(&text), text.len());
+    }
+
+    #[tokio::test]
+    async fn streamed_placeholders_cut_across_deltas_are_restored() {
+        for piece in [1, 3, 7, 16] {
+            let provider = RedactingLlmProvider::new(Arc::new(EchoLlm { piece }), Arc::new(redactor(PII_KINDS)));
+            let (sender, mut receiver) = mpsc::channel(1024);
+
+            let text = provider.generate_stream(CLAIM, &sender).await.unwrap();
+            drop(sender);
+            let mut streamed = String::new();
+            while let Some(delta) = receiver.recv().await {
+                assert!(!delta.contains("[NAME") && !delta.contains("[PHONE"), "{:?} in pieces of {}", delta, piece);
+                streamed.push_str(&delta);
+            }
+
+            assert_eq!(text, CLAIM);
+            assert_eq!(streamed, CLAIM, "pieces of {}", piece);
+        }
+    }
+}
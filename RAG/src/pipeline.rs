@@ -0,0 +1,442 @@
+use crate::entities::{kinds_asked, mentions_any};
+use crate::models::*;
+use crate::prompt::{build_context_within_budget, build_decision_prompt, build_multi_query_prompt, build_prompt};
+use crate::providers::{EmbeddingProvider, Generation, LlmProvider};
+use crate::retrieval::{
+    apply_keywords, apply_metadata_weights, expand_with_neighbors, mmr_rerank, reciprocal_rank_fusion, sort_by_score,
+    suppress_near_duplicates, DEFAULT_KEYWORD_BOOST, ENTITY_BOOST, MMR_CANDIDATE_MULTIPLIER,
+};
+use crate::usage;
+use crate::error::Result;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tracing::{info_span, Instrument};
+
+// The stages QueryService runs for a document question:
+//
+//   Retriever -> Rerankers (in order) -> ContextBuilder -> Generator
+//
+// Each one is a trait object, so a stage can be swapped or added (e.g. a filter that only
+// keeps policy clauses) through QueryService::with_retriever, with_reranker,
+// with_context_builder and with_generator. The built-in stages below are the defaults.
+
+// A question as the retrieval stages see it. Request settings are resolved against the
+// service defaults, so stages don't need to know them.
+pub struct RetrievalQuery<'a> {
+    pub request: &'a QueryRequest,
+    // Text to search with: the question after rewriting, follow-up expansion and translation
+    pub text: &'a str,
+    // The documents the request is scoped to
+    pub documents: &'a [&'a Document],
+    pub embeddings: &'a dyn EmbeddingProvider,
+    pub max_results: usize,
+    pub mmr_lambda: f32,
+    pub ranking_weights: &'a RankingWeights,
+    // Chunks less similar to the query are dropped
+    pub score_threshold: Option<f32>,
+    pub duplicate_threshold: Option<f32>,
+}
+
+// Finds candidate chunks in the scoped documents
+#[async_trait]
+pub trait Retriever: Send + Sync {
+    // Stage name in debug output
+    fn name(&self) -> &str;
+
+    // Candidates, best first
+    async fn retrieve(&self, query: &RetrievalQuery<'_>) -> Result<Vec<ScoredChunk>>;
+}
+
+// Rescores, filters or trims the candidates. When a stage leaves none, the service abstains
+// instead of calling the LLM.
+#[async_trait]
+pub trait Reranker: Send + Sync {
+    // Stage name in debug output
+    fn name(&self) -> &str;
+
+    // Stages with nothing to do for a query are skipped and left out of debug output
+    fn applies(&self, _query: &RetrievalQuery<'_>) -> bool {
+        true
+    }
+
+    async fn rerank(&self, query: &RetrievalQuery<'_>, chunks: Vec<ScoredChunk>) -> Result<Vec<ScoredChunk>>;
+}
+
+// How much context a ContextBuilder may produce
+#[derive(Debug, Clone, Copy)]
+pub struct ContextOptions {
+    // Adjacent chunks to add on each side of every selected one
+    pub neighbor_window: usize,
+    // Estimated token budget; None is unlimited
+    pub max_tokens: Option<usize>,
+}
+
+// Turns the selected chunks into the context text given to the generator
+pub trait ContextBuilder: Send + Sync {
+    fn build(&self, chunks: &[ScoredChunk], documents: &[Document], options: &ContextOptions) -> String;
+}
+
+// What a Generator is asked to answer
+pub struct GenerationInput<'a> {
+    pub request: &'a QueryRequest,
+    pub context: &'a str,
+    pub answer_language: &'a str,
+    // Earlier turns of the session, empty outside one
+    pub conversation: &'a str,
+    pub abstention: &'a AbstentionPolicy,
+}
+
+// Writes the answer. Compare, batch and general-knowledge answers come with a prompt of their
+// own and only use `generate`.
+#[async_trait]
+pub trait Generator: Send + Sync {
+    // Prompt for a single document question; returned in debug output
+    fn prompt(&self, input: &GenerationInput<'_>) -> String;
+
+    // Sends the text to `deltas` as it is produced, when given
+    async fn generate(&self, prompt: &str, deltas: Option<&mpsc::Sender<String>>) -> Result<String>;
+
+    // Like generate, with the model's confidence when it reports one
+    async fn generate_with_confidence(&self, prompt: &str, deltas: Option<&mpsc::Sender<String>>) -> Result<Generation> {
+        let text = self.generate(prompt, deltas).await?;
+        Ok(Generation { text, confidence: None })
+    }
+}
+
+// Scores every embedded chunk against the query, highest similarity first
+pub struct DenseRetriever;
+
+#[async_trait]
+impl Retriever for DenseRetriever {
+    fn name(&self) -> &str {
+        "dense"
+    }
+
+    async fn retrieve(&self, query: &RetrievalQuery<'_>) -> Result<Vec<ScoredChunk>> {
+        let query_embedding = query
+            .embeddings
+            .embed_query(query.text)
+            .instrument(info_span!("embedding", kind = "query"))
+            .await?;
+        rank_chunks(query.embeddings, &query_embedding, query.documents, candidate_count(query))
+    }
+}
+
+// Ranks chunks for the query plus LLM-generated variants of it and fuses the lists with RRF
+pub struct MultiQueryRetriever {
+    llm: Arc<dyn LlmProvider>,
+    variants: usize,
+}
+
+impl MultiQueryRetriever {
+    pub fn new(llm: Arc<dyn LlmProvider>, variants: usize) -> Self {
+        Self { llm, variants }
+    }
+}
+
+#[async_trait]
+impl Retriever for MultiQueryRetriever {
+    fn name(&self) -> &str {
+        "multi_query_rrf"
+    }
+
+    async fn retrieve(&self, query: &RetrievalQuery<'_>) -> Result<Vec<ScoredChunk>> {
+        let mut queries = vec![query.text.to_string()];
+        let prompt = build_multi_query_prompt(query.text, self.variants);
+        let span = usage::llm_span("query_variants");
+        match self.llm.generate(&prompt).instrument(span.clone()).await {
+            Ok(output) => {
+                usage::record(&span, &prompt, &output);
+                queries.extend(
+                    output
+                        .lines()
+                        .map(|line| line.trim().trim_start_matches(|c: char| c.is_ascii_digit() || c == '.' || c == '-' || c == ')').trim())
+                        .filter(|line| !line.is_empty())
+                        .take(self.variants)
+                        .map(str::to_string),
+                )
+            }
+            Err(e) => {
+                usage::record_error(&span, &e);
+                tracing::warn!("Query variant generation failed, using original query only: {}", e)
+            }
+        }
+        tracing::info!("Multi-query retrieval with {} queries", queries.len());
+
+        let mut ranked_lists = Vec::with_capacity(queries.len());
+        for variant in &queries {
+            let query_embedding = query
+                .embeddings
+                .embed_query(variant)
+                .instrument(info_span!("embedding", kind = "query_variant"))
+                .await?;
+            ranked_lists.push(rank_chunks(query.embeddings, &query_embedding, query.documents, candidate_count(query))?);
+        }
+
+        Ok(reciprocal_rank_fusion(ranked_lists))
+    }
+}
+
+// Every embedded chunk with its similarity to the query, best first. Vectors a store keeps in
+// a VectorTier (see EmbeddingProvider::stored_vectors) are only copied into the first `keep`
+// candidates, the ones duplicate suppression and MMR compare, so a query does not hold the
+// vectors of the whole index in memory at once.
+fn rank_chunks(
+    embeddings: &dyn EmbeddingProvider,
+    query_embedding: &[f32],
+    documents: &[&Document],
+    keep: usize,
+) -> Result<Vec<ScoredChunk>> {
+    let mut chunk_scores: Vec<ScoredChunk> = Vec::new();
+    // Document and chunk index of the candidates whose vectors are stored
+    let mut stored_chunks: HashMap<String, (usize, usize)> = HashMap::new();
+
+    for (doc_index, document) in documents.iter().enumerate() {
+        let stored = match document.chunks.iter().any(|chunk| chunk.embedding.is_some()) {
+            true => None,
+            false => embeddings.stored_vectors(document)?,
+        };
+        for (chunk_index, chunk) in document.chunks.iter().enumerate() {
+            let chunk_embedding = match &stored {
+                Some(vectors) => vectors.get(chunk_index).and_then(Option::as_ref),
+                None => chunk.embedding.as_ref(),
+            };
+            if let Some(chunk_embedding) = chunk_embedding {
+                let similarity = embeddings.calculate_similarity(query_embedding, chunk_embedding);
+                if stored.is_some() {
+                    stored_chunks.insert(chunk.id.clone(), (doc_index, chunk_index));
+                }
+                chunk_scores.push(ScoredChunk {
+                    chunk: chunk.clone(),
+                    score: similarity,
+                    similarity,
+                });
+            }
+        }
+    }
+
+    sort_by_score(&mut chunk_scores);
+    if !stored_chunks.is_empty() {
+        for scored in chunk_scores.iter_mut().take(keep) {
+            let Some(&(doc_index, chunk_index)) = stored_chunks.get(&scored.chunk.id) else {
+                continue;
+            };
+            if let Some(vectors) = embeddings.stored_vectors(documents[doc_index])? {
+                scored.chunk.embedding = vectors.get(chunk_index).cloned().flatten();
+            }
+        }
+    }
+    Ok(chunk_scores)
+}
+
+// Candidates duplicate suppression and MMR choose the results from
+fn candidate_count(query: &RetrievalQuery<'_>) -> usize {
+    query.max_results.saturating_mul(MMR_CANDIDATE_MULTIPLIER)
+}
+
+// Prefers newer or specially tagged documents (see apply_metadata_weights)
+pub struct MetadataReranker;
+
+#[async_trait]
+impl Reranker for MetadataReranker {
+    fn name(&self) -> &str {
+        "metadata"
+    }
+
+    fn applies(&self, query: &RetrievalQuery<'_>) -> bool {
+        !query.ranking_weights.is_neutral()
+    }
+
+    async fn rerank(&self, query: &RetrievalQuery<'_>, chunks: Vec<ScoredChunk>) -> Result<Vec<ScoredChunk>> {
+        Ok(apply_metadata_weights(chunks, query.documents, query.ranking_weights))
+    }
+}
+
+// Boosts or filters by the request's keywords
+pub struct KeywordReranker;
+
+#[async_trait]
+impl Reranker for KeywordReranker {
+    fn name(&self) -> &str {
+        "keywords"
+    }
+
+    fn applies(&self, query: &RetrievalQuery<'_>) -> bool {
+        query.request.keywords.as_ref().is_some_and(|keywords| !keywords.is_empty())
+    }
+
+    async fn rerank(&self, query: &RetrievalQuery<'_>, chunks: Vec<ScoredChunk>) -> Result<Vec<ScoredChunk>> {
+        let request = query.request;
+        Ok(apply_keywords(
+            chunks,
+            request.keywords.as_deref().unwrap_or_default(),
+            request.keyword_mode.unwrap_or_default(),
+            request.keyword_boost.unwrap_or(DEFAULT_KEYWORD_BOOST),
+        ))
+    }
+}
+
+// Prefers the chunks mentioning the kinds of entity the question asks for (e.g. durations for
+// a waiting period question) by adding ENTITY_BOOST to their score. Kinds the request lists
+// in entity_kinds are required instead: chunks mentioning none of them are dropped.
+pub struct EntityReranker;
+
+impl EntityReranker {
+    // The kinds wanted, and whether the request listed them
+    fn wanted(query: &RetrievalQuery<'_>) -> (Vec<EntityKind>, bool) {
+        let request = query.request;
+        match request.entity_kinds.as_ref().filter(|kinds| !kinds.is_empty()) {
+            Some(kinds) => (kinds.clone(), true),
+            None if request.entity_boost.unwrap_or(true) => (kinds_asked(&request.query), false),
+            None => (Vec::new(), false),
+        }
+    }
+}
+
+#[async_trait]
+impl Reranker for EntityReranker {
+    fn name(&self) -> &str {
+        "entities"
+    }
+
+    fn applies(&self, query: &RetrievalQuery<'_>) -> bool {
+        !Self::wanted(query).0.is_empty()
+    }
+
+    async fn rerank(&self, query: &RetrievalQuery<'_>, mut chunks: Vec<ScoredChunk>) -> Result<Vec<ScoredChunk>> {
+        let (kinds, listed) = Self::wanted(query);
+        if listed {
+            chunks.retain(|scored| mentions_any(&scored.chunk, &kinds));
+            return Ok(chunks);
+        }
+        for scored in chunks.iter_mut().filter(|scored| mentions_any(&scored.chunk, &kinds)) {
+            scored.score += ENTITY_BOOST;
+        }
+        sort_by_score(&mut chunks);
+        Ok(chunks)
+    }
+}
+
+// Drops weak matches, so nothing is left (and the service abstains) when no chunk is relevant
+pub struct ScoreThresholdFilter;
+
+#[async_trait]
+impl Reranker for ScoreThresholdFilter {
+    fn name(&self) -> &str {
+        "score_threshold"
+    }
+
+    fn applies(&self, query: &RetrievalQuery<'_>) -> bool {
+        query.score_threshold.is_some()
+    }
+
+    async fn rerank(&self, query: &RetrievalQuery<'_>, mut chunks: Vec<ScoredChunk>) -> Result<Vec<ScoredChunk>> {
+        if let Some(threshold) = query.score_threshold {
+            chunks.retain(|scored| scored.similarity >= threshold);
+        }
+        Ok(chunks)
+    }
+}
+
+// Collapses near-duplicates so the context budget isn't spent on the same paragraph twice
+pub struct DuplicateFilter;
+
+#[async_trait]
+impl Reranker for DuplicateFilter {
+    fn name(&self) -> &str {
+        "deduplicate"
+    }
+
+    fn applies(&self, query: &RetrievalQuery<'_>) -> bool {
+        query.duplicate_threshold.is_some()
+    }
+
+    async fn rerank(&self, query: &RetrievalQuery<'_>, chunks: Vec<ScoredChunk>) -> Result<Vec<ScoredChunk>> {
+        Ok(match query.duplicate_threshold {
+            Some(threshold) => suppress_near_duplicates(chunks, threshold, candidate_count(query)),
+            None => chunks,
+        })
+    }
+}
+
+// Takes the top results, skipping chunks that mostly repeat an already selected one
+pub struct MmrReranker;
+
+#[async_trait]
+impl Reranker for MmrReranker {
+    fn name(&self) -> &str {
+        "mmr"
+    }
+
+    async fn rerank(&self, query: &RetrievalQuery<'_>, chunks: Vec<ScoredChunk>) -> Result<Vec<ScoredChunk>> {
+        Ok(mmr_rerank(chunks, query.max_results, query.mmr_lambda))
+    }
+}
+
+// The built-in reranking stages, in the order they run
+pub fn default_rerankers() -> Vec<Arc<dyn Reranker>> {
+    vec![
+        Arc::new(MetadataReranker),
+        Arc::new(KeywordReranker),
+        Arc::new(EntityReranker),
+        Arc::new(ScoreThresholdFilter),
+        Arc::new(DuplicateFilter),
+        Arc::new(MmrReranker),
+    ]
+}
+
+// Chunk texts with their neighbours, labelled by document, packed best first into the budget
+pub struct PackedContextBuilder;
+
+impl ContextBuilder for PackedContextBuilder {
+    fn build(&self, chunks: &[ScoredChunk], documents: &[Document], options: &ContextOptions) -> String {
+        let chunks = expand_with_neighbors(chunks, documents, options.neighbor_window);
+        build_context_within_budget(&chunks, documents, options.max_tokens)
+    }
+}
+
+// Answers with an LLM, using the answer or decision prompt depending on the response mode
+pub struct LlmGenerator {
+    llm: Arc<dyn LlmProvider>,
+}
+
+impl LlmGenerator {
+    pub fn new(llm: Arc<dyn LlmProvider>) -> Self {
+        Self { llm }
+    }
+}
+
+#[async_trait]
+impl Generator for LlmGenerator {
+    fn prompt(&self, input: &GenerationInput<'_>) -> String {
+        let request = input.request;
+        match request.response_mode.unwrap_or_default() {
+            ResponseMode::Decision => build_decision_prompt(&request.query, input.context, input.answer_language, input.conversation),
+            _ => build_prompt(&request.query, input.context, input.answer_language, input.conversation, input.abstention),
+        }
+    }
+
+    async fn generate(&self, prompt: &str, deltas: Option<&mpsc::Sender<String>>) -> Result<String> {
+        Ok(self.generate_with_confidence(prompt, deltas).await?.text)
+    }
+
+    // Streamed answers come without a confidence
+    async fn generate_with_confidence(&self, prompt: &str, deltas: Option<&mpsc::Sender<String>>) -> Result<Generation> {
+        let span = usage::llm_span("answer");
+        span.record("streaming", deltas.is_some());
+        let output = match deltas {
+            Some(deltas) => self
+                .llm
+                .generate_stream(prompt, deltas)
+                .instrument(span.clone())
+                .await
+                .map(|text| Generation { text, confidence: None }),
+            None => self.llm.generate_with_confidence(prompt).instrument(span.clone()).await,
+        }
+        .inspect_err(|e| usage::record_error(&span, e))?;
+        usage::record(&span, prompt, &output.text);
+        Ok(output)
+    }
+}
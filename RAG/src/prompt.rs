@@ -0,0 +1,424 @@
+use crate::models::*;
+use crate::session::ConversationTurn;
+
+pub fn build_context(chunks: &[DocumentChunk], documents: &[Document]) -> String {
+    build_context_within_budget(chunks, documents, None)
+}
+
+// Builds the context from `chunks` in rank order, stopping once `max_tokens` (estimated)
+// would be exceeded. The first chunk is truncated rather than dropped so the LLM always
+// gets some context.
+pub fn build_context_within_budget(chunks: &[DocumentChunk], documents: &[Document], max_tokens: Option<usize>) -> String {
+    let mut context = String::new();
+    let mut used_tokens = 0;
+
+    for chunk in chunks {
+        // Find the document this chunk belongs to
+        if let Some(doc) = documents.iter().find(|d| d.chunks.iter().any(|c| c.id == chunk.id)) {
+            let entry = format!(
+                "Document: {}\nContent: {}\n\n",
+                doc.filename,
+                chunk.content
+            );
+            let entry_tokens = estimate_tokens(&entry);
+
+            if let Some(budget) = max_tokens {
+                if used_tokens + entry_tokens > budget {
+                    if context.is_empty() {
+                        let truncated: String = entry.chars().take(budget * CHARS_PER_TOKEN).collect();
+                        context.push_str(&truncated);
+                        context.push_str("\n\n");
+                    }
+                    tracing::info!("Context budget of {} tokens reached", budget);
+                    break;
+                }
+            }
+
+            used_tokens += entry_tokens;
+            context.push_str(&entry);
+        }
+    }
+
+    context
+}
+
+// Rough average for English text with BPE tokenizers
+const CHARS_PER_TOKEN: usize = 4;
+
+// Cheap token estimate; good enough for budgeting without pulling in a tokenizer
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(CHARS_PER_TOKEN)
+}
+
+// The instruction telling the LLM what to do when the context has no answer
+fn abstention_instruction(abstention: &AbstentionPolicy) -> String {
+    if abstention.general_knowledge_fallback {
+        "If the context doesn't contain enough information, say so first, then answer from general knowledge and clearly label that part as not based on the documents".to_string()
+    } else {
+        format!(
+            "If the context doesn't contain enough information to answer the question, reply with: \"{}\"",
+            abstention.message
+        )
+    }
+}
+
+pub fn build_prompt(
+    query: &str,
+    context: &str,
+    answer_language: &str,
+    conversation: &str,
+    abstention: &AbstentionPolicy,
+) -> String {
+    let abstention = abstention_instruction(abstention);
+    let conversation = if conversation.is_empty() {
+        String::new()
+    } else {
+        format!("CONVERSATION SO FAR (use it to resolve follow-up questions):\n{conversation}")
+    };
+
+    format!(
+        r#"You are an expert assistant that answers questions based solely on the provided context documents. 
+
+INSTRUCTIONS:
+1. Answer the question using ONLY the information from the provided context
+2. Be concise but comprehensive
+3. If you quote or reference specific information, indicate which document it came from
+4. {abstention}
+5. Do not add information not present in the context
+6. Focus on accuracy and relevance
+7. If user provides info such as M or F the user is specifying it's gender for example: 46M, knee surgery, Pune, 3-month policy means 46 year old male asking if knee surgery is covered or not he is from pune and has 3 months policy
+8. Write the answer in {answer_language}, even if the context documents are in a different language. Keep policy names, clause numbers and amounts as they appear in the documents
+
+CONTEXT DOCUMENTS:
+{context}
+
+{conversation}QUESTION: {query}
+
+ANSWER (be specific and cite sources):"#
+    )
+}
+
+pub fn build_decision_prompt(query: &str, context: &str, answer_language: &str, conversation: &str) -> String {
+    let conversation = if conversation.is_empty() {
+        String::new()
+    } else {
+        format!("CONVERSATION SO FAR (use it to resolve follow-up questions):\n{conversation}")
+    };
+
+    format!(
+        r#"You are an insurance claim adjudicator. Decide the claim described in the query based solely on the provided policy documents.
+
+INSTRUCTIONS:
+1. Use ONLY the information from the provided context
+2. Shorthand such as "46M, knee surgery, Pune, 3-month policy" means a 46 year old male from Pune with a policy active for 3 months asking whether knee surgery is covered
+3. Decide "approved" if the policy covers the claim, "rejected" if a clause excludes it or a waiting period applies, and "needs_info" if the context or the query does not contain enough information
+4. Set "amount" to the payable amount as a number when the policy states one, otherwise null
+5. List the clause numbers or headings the decision relies on in "applicable_clauses"
+6. Write the "justification" in {answer_language}, referencing the clauses and documents used
+7. Respond with ONLY a JSON object of this form, without code fences or other text:
+{{"decision": "approved" | "rejected" | "needs_info", "amount": number | null, "applicable_clauses": ["..."], "justification": "..."}}
+
+CONTEXT DOCUMENTS:
+{context}
+
+{conversation}QUERY: {query}
+
+JSON:"#
+    )
+}
+
+// `sections` are (filename, context) pairs, one per document being compared
+pub fn build_compare_prompt(query: &str, sections: &[(String, String)], answer_language: &str) -> String {
+    let mut context = String::new();
+    for (filename, section) in sections {
+        context.push_str(&format!("=== DOCUMENT: {} ===\n{}\n", filename, section));
+    }
+
+    format!(
+        r####"You are an expert assistant that compares documents based solely on the provided excerpts.
+
+INSTRUCTIONS:
+1. Answer the question separately for each document, using ONLY that document's excerpts
+2. Start each document's answer with a line "### <document name>" using the exact names given below
+3. If a document's excerpts don't answer the question, say so for that document instead of guessing
+4. After the per-document answers, add a line "### Comparison" followed by a Markdown table with one column per document and one row per aspect that differs or matters (coverage, limits, waiting periods, exclusions, ...)
+5. Write everything in {answer_language}. Keep policy names, clause numbers and amounts as they appear in the documents
+
+DOCUMENT EXCERPTS:
+{context}
+QUESTION: {query}
+
+ANSWER:"####
+    )
+}
+
+// Splits a compare-mode answer into the sections written for each of `filenames`
+pub fn parse_compare_sections(output: &str, filenames: &[String]) -> Vec<DocumentAnswer> {
+    let mut answers: Vec<DocumentAnswer> = Vec::new();
+    let mut current: Option<usize> = None;
+
+    for line in output.lines() {
+        if let Some(heading) = line.trim_start().strip_prefix("###") {
+            let heading = heading.trim().trim_matches('*').trim();
+            current = filenames.iter().find(|name| name.eq_ignore_ascii_case(heading)).map(|name| {
+                answers.push(DocumentAnswer {
+                    document: name.clone(),
+                    answer: String::new(),
+                });
+                answers.len() - 1
+            });
+            continue;
+        }
+
+        if let Some(idx) = current {
+            answers[idx].answer.push_str(line);
+            answers[idx].answer.push('\n');
+        }
+    }
+
+    for answer in answers.iter_mut() {
+        answer.answer = answer.answer.trim().to_string();
+    }
+    answers
+}
+
+pub fn build_batch_prompt(
+    questions: &[&str],
+    context: &str,
+    answer_language: &str,
+    abstention: &AbstentionPolicy,
+) -> String {
+    let abstention = abstention_instruction(abstention);
+    let questions: String = questions
+        .iter()
+        .enumerate()
+        .map(|(idx, question)| format!("[{}] {}\n", idx + 1, question))
+        .collect();
+
+    format!(
+        r#"You are an expert assistant that answers questions based solely on the provided context documents.
+
+INSTRUCTIONS:
+1. Answer every question using ONLY the information from the provided context
+2. Be concise but comprehensive, and indicate which document each answer comes from
+3. For each question: {abstention}
+4. Write the answers in {answer_language}. Keep policy names, clause numbers and amounts as they appear in the documents
+5. Start each answer on a new line with the number of its question in square brackets, e.g. "[1] ...", and answer the questions in order
+
+CONTEXT DOCUMENTS:
+{context}
+
+QUESTIONS:
+{questions}
+ANSWERS:"#
+    )
+}
+
+// Splits a batched answer into `count` answers keyed by their "[n]" markers.
+// Questions without a (non-empty) answer come back as None.
+pub fn parse_batch_answers(output: &str, count: usize) -> Vec<Option<String>> {
+    let mut answers: Vec<Option<String>> = vec![None; count];
+    let mut current: Option<usize> = None;
+
+    for line in output.lines() {
+        let trimmed = line.trim_start();
+        let marker = trimmed
+            .strip_prefix('[')
+            .and_then(|rest| rest.split_once(']'))
+            .and_then(|(number, rest)| Some((number.trim().parse::<usize>().ok()?, rest)));
+
+        match marker {
+            Some((number, rest)) if (1..=count).contains(&number) => {
+                current = Some(number - 1);
+                answers[number - 1] = Some(rest.trim().to_string());
+            }
+            _ => {
+                if let Some(answer) = current.and_then(|idx| answers[idx].as_mut()) {
+                    answer.push('\n');
+                    answer.push_str(line);
+                }
+            }
+        }
+    }
+
+    answers
+        .into_iter()
+        .map(|answer| answer.map(|a| a.trim().to_string()).filter(|a| !a.is_empty()))
+        .collect()
+}
+
+pub fn build_translation_prompt(text: &str, target_language: &str) -> String {
+    format!(
+        r#"Translate the following question about insurance policy documents into {target_language}.
+
+INSTRUCTIONS:
+1. Keep the meaning, numbers, ages, amounts and medical terms exactly
+2. Use the wording a policy document in {target_language} would use
+3. Return ONLY the translation on a single line, without quotes or explanations
+
+QUESTION: {text}
+
+TRANSLATION:"#
+    )
+}
+
+// Asks for the clauses a passage states, as a JSON array ClauseExtractor parses
+pub fn build_clause_prompt(passage: &str) -> String {
+    format!(
+        r#"You extract policy terms from a passage of an insurance policy document.
+
+INSTRUCTIONS:
+1. List every waiting period, sub-limit, exclusion and sum insured the passage states
+2. "kind" is one of: waiting_period, sub_limit, exclusion, sum_insured
+3. "subject" is what the term applies to in a few words (e.g. "cataract surgery"), or "" for the whole policy
+4. "value" is the duration, percentage or amount exactly as written (e.g. "24 months"), or null for exclusions
+5. "text" is the sentence stating the term, copied verbatim from the passage
+6. Return ONLY a JSON array of objects with the keys kind, subject, value and text; return [] if there are none
+
+PASSAGE:
+{passage}
+
+CLAUSES:"#
+    )
+}
+
+pub fn build_rewrite_prompt(query: &str) -> String {
+    format!(
+        r#"You rewrite user questions about insurance policy documents into explicit search queries.
+
+INSTRUCTIONS:
+1. Expand abbreviations and shorthand (e.g. "46M" means "46 year old male", "PED" means "pre-existing disease", "3-month policy" means "policy active for 3 months")
+2. Use the formal wording found in policy documents (coverage, waiting period, exclusions, sum insured, claim)
+3. Keep every fact from the original question; do not invent new ones
+4. Return ONLY the rewritten query on a single line, without quotes or explanations
+
+QUESTION: {query}
+
+REWRITTEN QUERY:"#
+    )
+}
+
+pub fn build_multi_query_prompt(query: &str, variants: usize) -> String {
+    format!(
+        r#"You generate alternative search queries for retrieving passages from insurance policy documents.
+
+INSTRUCTIONS:
+1. Write {variants} different rephrasings of the question below
+2. Vary the vocabulary: use formal policy terms, synonyms and expanded abbreviations
+3. Each rephrasing must ask for the same information as the original question
+4. Return ONLY the queries, one per line, without numbering or explanations
+
+QUESTION: {query}
+
+QUERIES:"#
+    )
+}
+
+pub fn build_faithfulness_prompt(query: &str, context: &str, answer: &str) -> String {
+    format!(
+        r#"You check whether an answer is supported by the context it was written from.
+
+INSTRUCTIONS:
+1. Split the answer into its factual claims
+2. Judge each claim against the context only, not against general knowledge
+3. Score the share of claims the context supports, from 0.0 (none) to 1.0 (all)
+4. Return ONLY the score as a decimal number
+
+CONTEXT DOCUMENTS:
+{context}
+
+QUESTION: {query}
+
+ANSWER: {answer}
+
+SCORE:"#
+    )
+}
+
+pub fn build_summary_prompt(previous_summary: Option<&str>, turns: &[ConversationTurn]) -> String {
+    let mut transcript = String::new();
+    if let Some(summary) = previous_summary {
+        transcript.push_str(&format!("Earlier summary: {}\n\n", summary));
+    }
+    for turn in turns {
+        transcript.push_str(&format!("User: {}\nAssistant: {}\n\n", turn.question, turn.answer));
+    }
+
+    format!(
+        r#"Summarize the following conversation about insurance policy documents in at most 5 sentences.
+Keep the facts the user stated about themselves (age, gender, location, policy duration, procedures) and the key answers given.
+
+CONVERSATION:
+{transcript}
+SUMMARY:"#
+    )
+}
+
+// Used when retrieval found nothing relevant and the abstention policy allows a
+// general-knowledge answer
+pub fn build_general_knowledge_prompt(query: &str, answer_language: &str, conversation: &str) -> String {
+    format!(
+        r#"You are an assistant for an insurance policy question-answering service.
+No passage of the indexed policy documents is relevant to the question below.
+
+INSTRUCTIONS:
+1. Start by saying that the documents don't cover this question
+2. Then give a brief, general answer from your own knowledge, clearly labelled as general information that may not apply to the user's policy
+3. Write the answer in {answer_language}
+
+{conversation}QUESTION: {query}
+
+ANSWER:"#
+    )
+}
+
+pub fn build_small_talk_prompt(query: &str, answer_language: &str, conversation: &str) -> String {
+    format!(
+        r#"You are a friendly assistant for an insurance policy question-answering service.
+The user sent a greeting or general message rather than a question about the documents.
+Reply briefly and politely in {answer_language}, and mention that you can answer questions about the indexed policy documents.
+
+{conversation}MESSAGE: {query}
+
+REPLY:"#
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_answers_by_their_markers() {
+        let output = "[1] The grace period is 30 days.\n[2] Yes, after 24 months.\n[3] Maternity is excluded.";
+
+        assert_eq!(
+            parse_batch_answers(output, 3),
+            [
+                Some("The grace period is 30 days.".to_string()),
+                Some("Yes, after 24 months.".to_string()),
+                Some("Maternity is excluded.".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_continuation_lines_and_answers_out_of_order() {
+        let output = "Answers:\n  [2] Covered up to:\n- Rs. 50,000 per year\n- 10% co-payment\n\n[ 1 ]No.";
+
+        assert_eq!(
+            parse_batch_answers(output, 2),
+            [Some("No.".to_string()), Some("Covered up to:\n- Rs. 50,000 per year\n- 10% co-payment".to_string())]
+        );
+    }
+
+    #[test]
+    fn missing_empty_and_out_of_range_answers_are_none() {
+        // [2] is left empty; [3] is beyond the questions asked, so like "[x]" it continues [1]
+        let output = "[2]\n[1] Yes.\n[3] Extra.\n[x] Not a marker.";
+
+        assert_eq!(parse_batch_answers(output, 2), [Some("Yes.\n[3] Extra.\n[x] Not a marker.".to_string()), None]);
+        assert_eq!(parse_batch_answers("no markers here", 2), [None, None]);
+        assert!(parse_batch_answers("[1] Yes.", 0).is_empty());
+    }
+}
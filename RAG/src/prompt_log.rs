@@ -0,0 +1,297 @@
+use crate::error::{RagError, Result};
+use crate::providers::{Generation, LlmProvider};
+use async_trait::async_trait;
+use regex::{Captures, Regex};
+use serde::Serialize;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+
+pub const DEFAULT_PROMPT_LOG_MAX_BYTES: u64 = 10 * 1024 * 1024;
+pub const DEFAULT_PROMPT_LOG_FILES: usize = 5;
+
+// What PROMPT_LOG_REDACT masks unless set otherwise
+pub const DEFAULT_REDACTIONS: &[&str] = &["email", "phone", "policy_number"];
+
+// Patterns of the built-in kinds, shared with pii::PiiRedactor
+pub(crate) const EMAIL_PATTERN: &str = r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}";
+pub(crate) const PHONE_PATTERN: &str = r"\+\d[\d \-]{7,}\d|\b\d[\d \-]{8,}\d\b";
+pub(crate) const PHONE_MIN_DIGITS: usize = 10;
+pub(crate) const POLICY_NUMBER_PATTERN: &str = r"\b[A-Z0-9][A-Z0-9/\-]{4,}[A-Z0-9]\b";
+pub(crate) const POLICY_NUMBER_MIN_DIGITS: usize = 6;
+// Dates look like policy numbers but are kept
+pub(crate) const DATE_PATTERN: &str = r"^(\d{4}[/-]\d{1,2}[/-]\d{1,2}|\d{1,2}[/-]\d{1,2}[/-]\d{4})$";
+
+// Masks personal data in logged text. Built-in kinds: "email", "phone" (international or
+// 10+ digit numbers) and "policy_number" (runs of capitals, digits, '/' and '-' holding at
+// least six digits, e.g. P/211111/01/2024/000123, other than dates), applied in the order
+// given. A custom pattern is replaced by [redacted].
+pub struct Redactor {
+    rules: Vec<Rule>,
+}
+
+struct Rule {
+    pattern: Regex,
+    replacement: &'static str,
+    // Matches without enough digits, or matching `keep` as a whole, are left alone, so
+    // amounts and dates stay readable
+    min_digits: usize,
+    keep: Option<Regex>,
+}
+
+impl Redactor {
+    pub fn new(kinds: &[String], custom_pattern: Option<&str>) -> Result<Self> {
+        let mut rules = Vec::new();
+        for kind in kinds {
+            let (pattern, replacement, min_digits, keep) = match kind.as_str() {
+                "email" => (EMAIL_PATTERN, "[email]", 0, None),
+                "phone" => (PHONE_PATTERN, "[phone]", PHONE_MIN_DIGITS, None),
+                "policy_number" => {
+                    (POLICY_NUMBER_PATTERN, "[policy_number]", POLICY_NUMBER_MIN_DIGITS, Some(DATE_PATTERN))
+                }
+                other => {
+                    return Err(RagError::Config(format!(
+                        "Unknown redaction {:?}, expected email, phone or policy_number",
+                        other
+                    )))
+                }
+            };
+            rules.push(Rule {
+                pattern: Regex::new(pattern).unwrap(),
+                replacement,
+                min_digits,
+                keep: keep.map(|keep| Regex::new(keep).unwrap()),
+            });
+        }
+        if let Some(pattern) = custom_pattern {
+            let pattern = Regex::new(pattern)
+                .map_err(|e| RagError::Config(format!("Invalid redaction pattern {:?}: {}", pattern, e)))?;
+            rules.push(Rule { pattern, replacement: "[redacted]", min_digits: 0, keep: None });
+        }
+        Ok(Self { rules })
+    }
+
+    pub fn redact(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for rule in &self.rules {
+            text = rule
+                .pattern
+                .replace_all(&text, |captures: &Captures| {
+                    let matched = &captures[0];
+                    let digits = matched.chars().filter(char::is_ascii_digit).count();
+                    match digits < rule.min_digits || rule.keep.as_ref().is_some_and(|keep| keep.is_match(matched)) {
+                        true => matched.to_string(),
+                        false => rule.replacement.to_string(),
+                    }
+                })
+                .into_owned();
+        }
+        text
+    }
+}
+
+// One LLM call, as a line of the prompt log
+#[derive(Serialize)]
+struct PromptLogEntry<'a> {
+    // Unix timestamp (seconds)
+    timestamp: u64,
+    streaming: bool,
+    duration_ms: u128,
+    prompt: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a str>,
+}
+
+// Debug log of full prompts and LLM responses, for prompt engineering against real traffic.
+// Entries are JSON lines, redacted before they are written. Once the file would grow past
+// `max_bytes` it is renamed to `<path>.1` (and older ones to .2, .3, ...), keeping `files`
+// rotated files.
+pub struct PromptLog {
+    path: PathBuf,
+    max_bytes: u64,
+    files: usize,
+    redactor: Redactor,
+    file: Mutex<Option<(File, u64)>>,
+}
+
+impl PromptLog {
+    pub fn new(path: impl Into<PathBuf>, max_bytes: u64, files: usize, redactor: Redactor) -> Result<Self> {
+        let path = path.into();
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let log = Self { path, max_bytes, files, redactor, file: Mutex::new(None) };
+        *log.file.lock().unwrap() = Some(log.open()?);
+        Ok(log)
+    }
+
+    fn record(&self, streaming: bool, started: Instant, prompt: &str, outcome: std::result::Result<&str, &RagError>) {
+        let error = outcome.err().map(ToString::to_string);
+        let entry = PromptLogEntry {
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default(),
+            streaming,
+            duration_ms: started.elapsed().as_millis(),
+            prompt: self.redactor.redact(prompt),
+            response: outcome.ok().map(|response| self.redactor.redact(response)),
+            error: error.as_deref(),
+        };
+        if let Err(e) = self.write(&entry) {
+            tracing::warn!("Failed to write to the prompt log {}: {}", self.path.display(), e);
+        }
+    }
+
+    fn write(&self, entry: &PromptLogEntry) -> io::Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+
+        let mut file = self.file.lock().unwrap();
+        if file.as_ref().is_some_and(|(_, size)| *size > 0 && size + line.len() as u64 > self.max_bytes) {
+            *file = None;
+            self.rotate()?;
+        }
+        if file.is_none() {
+            *file = Some(self.open()?);
+        }
+        let (file, size) = file.as_mut().unwrap();
+        file.write_all(&line)?;
+        *size += line.len() as u64;
+        Ok(())
+    }
+
+    // Removes the entries whose prompt or response holds `filename`'s document, by its
+    // "Document: <filename>" context header or the text of any of `chunk_texts`, from the
+    // current and the rotated files. Returns how many were removed.
+    pub fn purge(&self, filename: &str, chunk_texts: &[&str]) -> io::Result<usize> {
+        let needles = self.needles(filename, chunk_texts);
+        let mut file = self.file.lock().unwrap();
+        // Reopened on the next write, at the rewritten file's end
+        *file = None;
+        let mut removed = 0;
+        for path in self.paths() {
+            let text = match fs::read_to_string(&path) {
+                Ok(text) => text,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+            let kept: Vec<&str> = text.lines().filter(|line| !mentions(line, &needles)).collect();
+            if kept.len() == text.lines().count() {
+                continue;
+            }
+            removed += text.lines().count() - kept.len();
+            let mut rewritten = kept.join("\n");
+            if !rewritten.is_empty() {
+                rewritten.push('\n');
+            }
+            let temporary = path.with_extension("purge.tmp");
+            fs::write(&temporary, rewritten)?;
+            fs::rename(&temporary, &path)?;
+        }
+        Ok(removed)
+    }
+
+    // Whether any entry still holds the document, after `purge`
+    pub fn holds_any(&self, filename: &str, chunk_texts: &[&str]) -> io::Result<bool> {
+        let needles = self.needles(filename, chunk_texts);
+        let _file = self.file.lock().unwrap();
+        for path in self.paths() {
+            match fs::read_to_string(&path) {
+                Ok(text) if text.lines().any(|line| mentions(line, &needles)) => return Ok(true),
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(false)
+    }
+
+    // What a logged prompt holding the document contains, redacted like the log
+    fn needles(&self, filename: &str, chunk_texts: &[&str]) -> Vec<String> {
+        let texts = chunk_texts.iter().map(|text| self.redactor.redact(text.trim())).filter(|text| !text.is_empty());
+        std::iter::once(format!("Document: {}\n", filename)).chain(texts).collect()
+    }
+
+    // The current file and the rotated ones
+    fn paths(&self) -> Vec<PathBuf> {
+        std::iter::once(self.path.clone()).chain((1..=self.files).map(|index| rotated(&self.path, index))).collect()
+    }
+
+    fn open(&self) -> io::Result<(File, u64)> {
+        let file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        let size = file.metadata()?.len();
+        Ok((file, size))
+    }
+
+    // Shifts <path>.1 .. <path>.(files - 1) up by one, dropping the oldest, and moves the
+    // current file to <path>.1; with `files` 0 the current file is just removed
+    fn rotate(&self) -> io::Result<()> {
+        if self.files == 0 {
+            return fs::remove_file(&self.path);
+        }
+        for index in (1..self.files).rev() {
+            let from = rotated(&self.path, index);
+            if from.exists() {
+                fs::rename(&from, rotated(&self.path, index + 1))?;
+            }
+        }
+        fs::rename(&self.path, rotated(&self.path, 1))
+    }
+}
+
+// Whether the entry on `line` has any of `needles` in its prompt or response. Lines that are
+// not entries are kept.
+fn mentions(line: &str, needles: &[String]) -> bool {
+    let Ok(entry) = serde_json::from_str::<serde_json::Value>(line) else {
+        return false;
+    };
+    ["prompt", "response"]
+        .iter()
+        .filter_map(|field| entry[field].as_str())
+        .any(|text| needles.iter().any(|needle| text.contains(needle.as_str())))
+}
+
+fn rotated(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(format!(".{}", index));
+    PathBuf::from(name)
+}
+
+// An LLM provider whose calls are written to a PromptLog
+pub struct LoggedLlmProvider {
+    inner: Arc<dyn LlmProvider>,
+    log: Arc<PromptLog>,
+}
+
+impl LoggedLlmProvider {
+    pub fn new(inner: Arc<dyn LlmProvider>, log: Arc<PromptLog>) -> Self {
+        Self { inner, log }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for LoggedLlmProvider {
+    async fn generate(&self, prompt: &str) -> Result<String> {
+        let started = Instant::now();
+        let outcome = self.inner.generate(prompt).await;
+        self.log.record(false, started, prompt, outcome.as_deref());
+        outcome
+    }
+
+    async fn generate_with_confidence(&self, prompt: &str) -> Result<Generation> {
+        let started = Instant::now();
+        let outcome = self.inner.generate_with_confidence(prompt).await;
+        self.log.record(false, started, prompt, outcome.as_ref().map(|generation| generation.text.as_str()));
+        outcome
+    }
+
+    async fn generate_stream(&self, prompt: &str, deltas: &mpsc::Sender<String>) -> Result<String> {
+        let started = Instant::now();
+        let outcome = self.inner.generate_stream(prompt, deltas).await;
+        self.log.record(true, started, prompt, outcome.as_deref());
+        outcome
+    }
+}
@@ -0,0 +1,145 @@
+use crate::embedding_service::EmbeddingService;
+#[cfg(feature = "gemini")]
+use crate::gemini_service::GeminiService;
+use crate::mock::{MockEmbeddingProvider, MockLlmProvider};
+use crate::models::*;
+use crate::error::Result;
+use crate::vector_tier::ChunkVectors;
+use async_trait::async_trait;
+use std::env;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+pub const DEFAULT_GEMINI_MODEL: &str = "gemini-2.5-flash";
+
+// Turns document chunks and queries into vectors in the same space
+#[async_trait]
+pub trait EmbeddingProvider: Send + Sync {
+    // Embeds every chunk of `documents` in place, (re)fitting any corpus statistics first
+    async fn generate_embeddings(&self, documents: &mut [Document]) -> Result<()>;
+
+    async fn embed_query(&self, query: &str) -> Result<Vec<f32>>;
+
+    // Whether embeddings depend on the whole corpus (e.g. TF-IDF), so every document has to
+    // be embedded again when one is added or removed. Providers that embed each text on its
+    // own return false and get incremental updates.
+    fn fitted_to_corpus(&self) -> bool {
+        true
+    }
+
+    // Identifies the embedding space, so saved embeddings are not mixed with another
+    // provider's (or the same provider with other settings)
+    fn kind(&self) -> String {
+        std::any::type_name::<Self>().to_string()
+    }
+
+    // The corpus statistics fitted by generate_embeddings, saved with a snapshot of the index
+    // so it can be answered from after a restart without embedding everything again
+    fn fitted_state(&self) -> Option<serde_json::Value> {
+        None
+    }
+
+    fn restore_fitted_state(&self, _state: serde_json::Value) -> Result<()> {
+        Ok(())
+    }
+
+    fn calculate_similarity(&self, embedding1: &[f32], embedding2: &[f32]) -> f32 {
+        cosine_similarity(embedding1, embedding2)
+    }
+
+    // The vectors of `document`'s chunks, in chunk order, when its store keeps them outside the
+    // chunks (see VectorTier); None when the chunks hold their own
+    fn stored_vectors(&self, _document: &Document) -> Result<Option<Arc<ChunkVectors>>> {
+        Ok(None)
+    }
+}
+
+// LLM output together with how sure the model was of it
+#[derive(Debug, Clone, Default)]
+pub struct Generation {
+    pub text: String,
+    // In [0, 1], e.g. from the token log-probabilities or a self-rating; None when the
+    // provider reports nothing
+    pub confidence: Option<f32>,
+}
+
+// Text-in, text-out language model used for answer generation
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    async fn generate(&self, prompt: &str) -> Result<String>;
+
+    // Like generate, with the model's confidence when the provider reports one
+    async fn generate_with_confidence(&self, prompt: &str) -> Result<Generation> {
+        let text = self.generate(prompt).await?;
+        Ok(Generation { text, confidence: None })
+    }
+
+    // Sends the answer to `deltas` as it is produced and returns the full text.
+    // Providers without streaming support send it as a single delta.
+    async fn generate_stream(&self, prompt: &str, deltas: &mpsc::Sender<String>) -> Result<String> {
+        let text = self.generate(prompt).await?;
+        let _ = deltas.send(text.clone()).await;
+        Ok(text)
+    }
+}
+
+// Translates queries into the corpus language before retrieval; an LLM-backed
+// implementation is the default, but an offline model can be plugged in instead
+#[async_trait]
+pub trait TranslationProvider: Send + Sync {
+    async fn translate(&self, text: &str, target_language: &str) -> Result<String>;
+}
+
+// EMBEDDING_PROVIDER=mock selects hash-based embeddings; anything else uses TF-IDF
+pub async fn embedding_provider_from_env() -> Result<Arc<dyn EmbeddingProvider>> {
+    match env::var("EMBEDDING_PROVIDER").as_deref() {
+        Ok("mock") => {
+            tracing::info!("Using mock embedding provider");
+            Ok(Arc::new(MockEmbeddingProvider::new()))
+        }
+        _ => Ok(Arc::new(EmbeddingService::new().await?)),
+    }
+}
+
+// LLM_PROVIDER=mock selects canned responses, so no GEMINI_API_KEY is needed;
+// otherwise Gemini `gemini_model` is used
+#[cfg(feature = "gemini")]
+pub fn llm_provider_from_env(gemini_model: &str) -> Result<Arc<dyn LlmProvider>> {
+    match env::var("LLM_PROVIDER").as_deref() {
+        Ok("mock") => {
+            tracing::info!("Using mock LLM provider");
+            Ok(Arc::new(MockLlmProvider::new()))
+        }
+        _ => Ok(Arc::new(GeminiService::new()?.with_model(gemini_model))),
+    }
+}
+
+// Built without the gemini feature the mock is the only LLM that can be chosen from the
+// environment; others are passed in as LlmBackend::Custom
+#[cfg(not(feature = "gemini"))]
+pub fn llm_provider_from_env(_gemini_model: &str) -> Result<Arc<dyn LlmProvider>> {
+    match env::var("LLM_PROVIDER").as_deref() {
+        Ok("mock") => tracing::info!("Using mock LLM provider"),
+        _ => tracing::warn!("Built without the gemini feature, using the mock LLM provider"),
+    }
+    Ok(Arc::new(MockLlmProvider::new()))
+}
+
+pub fn cosine_similarity(embedding1: &[f32], embedding2: &[f32]) -> f32 {
+    let min_len = embedding1.len().min(embedding2.len());
+
+    let dot_product: f32 = embedding1[..min_len]
+        .iter()
+        .zip(embedding2[..min_len].iter())
+        .map(|(a, b)| a * b)
+        .sum();
+
+    let norm1: f32 = embedding1[..min_len].iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm2: f32 = embedding2[..min_len].iter().map(|x| x * x).sum::<f32>().sqrt();
+
+    if norm1 == 0.0 || norm2 == 0.0 {
+        0.0
+    } else {
+        dot_product / (norm1 * norm2)
+    }
+}
@@ -1,96 +1,1488 @@
 use crate::models::*;
-use crate::embedding_service::EmbeddingService;
-use crate::gemini_service::GeminiService;
-use anyhow::Result;
+use crate::cache::{cache_key, CacheStats, ResponseCache, DEFAULT_MAX_CACHED_RESPONSES};
+use crate::clauses::{match_clauses, ClauseMatch};
+use crate::conflict::{conflict_notice, detect_conflicts};
+use crate::decision::parse_decision;
+use crate::feedback::{Feedback, FeedbackStore, QueryRecord, Rating, DEFAULT_MAX_TRACKED_QUERIES};
+use crate::excerpt::{excerpt, DEFAULT_EXCERPT_CHARS};
+use crate::highlight::{find_supporting_spans, source_offsets};
+use crate::language::{answer_language_override, detect_language, DEFAULT_LANGUAGE};
+use crate::pipeline::{
+    default_rerankers, ContextBuilder, ContextOptions, DenseRetriever, GenerationInput, Generator, LlmGenerator,
+    MultiQueryRetriever, PackedContextBuilder, Reranker, RetrievalQuery, Retriever,
+};
+use crate::prompt::{
+    build_batch_prompt, build_compare_prompt, build_general_knowledge_prompt, parse_compare_sections, parse_batch_answers,
+    build_rewrite_prompt, build_small_talk_prompt, build_summary_prompt,
+};
+use crate::providers::{EmbeddingProvider, LlmProvider, TranslationProvider};
+use crate::router::{classify_query, QueryIntent};
+use crate::translation::{corpus_language, LlmTranslator};
+use crate::usage;
+use crate::cancel;
+use crate::session::{Session, SessionStore};
+use crate::retrieval::{confidence_from_similarity, sort_by_score, DEFAULT_DUPLICATE_THRESHOLD, DEFAULT_MMR_LAMBDA};
+use crate::error::{RagError, Result};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::field::Empty;
+use tracing::{Instrument, Span};
+use uuid::Uuid;
+
+// Number of query variants generated for multi-query retrieval
+const DEFAULT_QUERY_VARIANTS: usize = 4;
+
+// Documents compared in one compare-mode answer, and chunks retrieved from each
+const MAX_COMPARE_DOCUMENTS: usize = 6;
+const DEFAULT_COMPARE_RESULTS: usize = 3;
+
+// Largest max_results and max_context_tokens a request is served with; larger values are
+// capped, so a client cannot make retrieval allocate without bound
+pub const DEFAULT_MAX_RESULTS_LIMIT: usize = 100;
+pub const DEFAULT_MAX_CONTEXT_TOKENS_LIMIT: usize = 100_000;
+
+// Outcome of the retrieval stages, before generation
+struct Retrieval {
+    rewritten_query: Option<String>,
+    translated_query: Option<String>,
+    // Query text actually embedded (after rewriting or follow-up expansion)
+    retrieval_query: String,
+    chunks: Vec<ScoredChunk>,
+    // No chunk passed the score threshold
+    abstained: bool,
+    // Ranking after every stage, only collected in debug mode
+    stages: Vec<RankingStage>,
+}
+
+// Chunks shown per stage in debug output
+const DEBUG_STAGE_LIMIT: usize = 20;
+
+// A closed receiver means the client went away; the answer is still finished and cached
+async fn send_event(events: &mpsc::Sender<StreamEvent>, event: StreamEvent) {
+    let _ = events.send(event).await;
+}
+
+// The LLM is told to reply with the abstention message when the context does not answer the
+// question, so such replies are reported like an abstention before the LLM call
+fn answer_status(response: &str, abstention: &AbstentionPolicy) -> &'static str {
+    if response.trim() == abstention.message.trim() {
+        "insufficient_information"
+    } else {
+        "success"
+    }
+}
+
+// Questions without any text are refused rather than answered as small talk or retrieved for
+fn check_query(request: &QueryRequest) -> Result<()> {
+    match request.query.trim().is_empty() {
+        true => Err(RagError::InvalidRequest("the query is empty".to_string())),
+        false => Ok(()),
+    }
+}
+
+// Whether `response` cites any of `chunk_ids`
+fn cites_any(response: &QueryResponse, chunk_ids: &HashSet<String>) -> bool {
+    response.citations.iter().any(|citation| chunk_ids.contains(&citation.chunk_id))
+}
+
+// Page of `content` that `position` (a byte offset) falls on, counting the form feeds
+// pdftotext puts between pages; None for text extracted without page breaks
+fn page_at(content: &str, position: usize) -> Option<usize> {
+    if !content.contains('\x0c') {
+        return None;
+    }
+    let before = content.get(..position)?;
+    Some(1 + before.matches('\x0c').count())
+}
+
+fn record_stage(stages: &mut Vec<RankingStage>, enabled: bool, name: &str, chunks: &[ScoredChunk]) {
+    if !enabled {
+        return;
+    }
+    stages.push(RankingStage {
+        name: name.to_string(),
+        candidates: chunks.len(),
+        top: chunks
+            .iter()
+            .take(DEBUG_STAGE_LIMIT)
+            .map(|scored| StageScore {
+                chunk_id: scored.chunk.id.clone(),
+                score: scored.score,
+                similarity: scored.similarity,
+            })
+            .collect(),
+    });
+}
 
 pub struct QueryService {
-    embedding_service: Arc<EmbeddingService>,
-    gemini_service: Arc<GeminiService>,
+    embedding_service: Arc<dyn EmbeddingProvider>,
+    llm: Arc<dyn LlmProvider>,
+    answer_language: Option<String>,
+    mmr_lambda: f32,
+    rewrite_queries: bool,
+    strategy: RetrievalStrategy,
+    query_variants: usize,
+    sessions: SessionStore,
+    summarize_sessions: bool,
+    abstention: AbstentionPolicy,
+    neighbor_window: usize,
+    duplicate_threshold: Option<f32>,
+    route_queries: bool,
+    max_context_tokens: Option<usize>,
+    max_results_limit: usize,
+    max_context_tokens_limit: usize,
+    ranking_weights: RankingWeights,
+    llm_batch_size: usize,
+    response_cache: Option<ResponseCache>,
+    detect_conflicts: bool,
+    translator: Option<Arc<dyn TranslationProvider>>,
+    feedback: FeedbackStore,
+    // Replaces the strategy-selected retriever when set
+    retriever: Option<Arc<dyn Retriever>>,
+    rerankers: Vec<Arc<dyn Reranker>>,
+    context_builder: Arc<dyn ContextBuilder>,
+    generator: Arc<dyn Generator>,
 }
 
 impl QueryService {
-    pub fn new(embedding_service: Arc<EmbeddingService>, gemini_service: Arc<GeminiService>) -> Self {
+    pub fn new(embedding_service: Arc<dyn EmbeddingProvider>, llm: Arc<dyn LlmProvider>) -> Self {
         Self {
             embedding_service,
-            gemini_service,
+            translator: Some(Arc::new(LlmTranslator::new(llm.clone()))),
+            generator: Arc::new(LlmGenerator::new(llm.clone())),
+            llm,
+            answer_language: answer_language_override(),
+            mmr_lambda: DEFAULT_MMR_LAMBDA,
+            rewrite_queries: false,
+            strategy: RetrievalStrategy::Dense,
+            query_variants: DEFAULT_QUERY_VARIANTS,
+            sessions: SessionStore::default(),
+            summarize_sessions: false,
+            abstention: AbstentionPolicy::default(),
+            neighbor_window: 0,
+            duplicate_threshold: Some(DEFAULT_DUPLICATE_THRESHOLD),
+            route_queries: true,
+            max_context_tokens: None,
+            max_results_limit: DEFAULT_MAX_RESULTS_LIMIT,
+            max_context_tokens_limit: DEFAULT_MAX_CONTEXT_TOKENS_LIMIT,
+            ranking_weights: RankingWeights::default(),
+            llm_batch_size: 1,
+            response_cache: None,
+            detect_conflicts: true,
+            feedback: FeedbackStore::default(),
+            retriever: None,
+            rerankers: default_rerankers(),
+            context_builder: Arc::new(PackedContextBuilder),
+        }
+    }
+
+    // Forces every answer into `language` instead of matching the query language
+    pub fn with_answer_language(mut self, language: Option<String>) -> Self {
+        self.answer_language = language;
+        self
+    }
+
+    // Relevance/diversity trade-off for MMR re-ranking; 1.0 disables diversification
+    pub fn with_mmr_lambda(mut self, lambda: f32) -> Self {
+        self.mmr_lambda = lambda;
+        self
+    }
+
+    // Rewrites terse queries into explicit retrieval queries with an extra LLM call
+    pub fn with_query_rewriting(mut self, enabled: bool) -> Self {
+        self.rewrite_queries = enabled;
+        self
+    }
+
+    pub fn with_retrieval_strategy(mut self, strategy: RetrievalStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    // Number of LLM-generated variants used by RetrievalStrategy::MultiQuery (clamped to 3-5)
+    pub fn with_query_variants(mut self, variants: usize) -> Self {
+        self.query_variants = variants.clamp(3, 5);
+        self
+    }
+
+    // Number of question/answer turns kept verbatim per session
+    pub fn with_session_history(mut self, max_turns: usize) -> Self {
+        self.sessions = std::mem::take(&mut self.sessions).with_max_turns(max_turns);
+        self
+    }
+
+    // Sessions kept at once (the least recently used are dropped beyond it), and how long an
+    // unused one is kept; None keeps them until dropped or deleted
+    pub fn with_session_limits(mut self, max_sessions: usize, ttl: Option<Duration>) -> Self {
+        self.sessions = std::mem::take(&mut self.sessions).with_limits(max_sessions, ttl);
+        self
+    }
+
+    // Compress turns that fall out of the session history into an LLM-written summary
+    pub fn with_session_summaries(mut self, enabled: bool) -> Self {
+        self.summarize_sessions = enabled;
+        self
+    }
+
+    // Chunks with a query similarity below `threshold` are dropped; if none remain the
+    // service abstains instead of calling the LLM
+    pub fn with_score_threshold(mut self, threshold: Option<f32>) -> Self {
+        self.abstention.threshold = threshold;
+        self
+    }
+
+    // Threshold, wording and general-knowledge fallback used when documents can't answer
+    pub fn with_abstention_policy(mut self, policy: AbstentionPolicy) -> Self {
+        self.abstention = policy;
+        self
+    }
+
+    // Adds up to `window` adjacent chunks on each side of every hit to the LLM context
+    pub fn with_neighbor_window(mut self, window: usize) -> Self {
+        self.neighbor_window = window;
+        self
+    }
+
+    // Chunks more similar than `threshold` (Jaccard or embedding) to a better-ranked
+    // chunk are collapsed; None keeps every candidate
+    pub fn with_duplicate_threshold(mut self, threshold: Option<f32>) -> Self {
+        self.duplicate_threshold = threshold;
+        self
+    }
+
+    // Answer greetings and small talk directly instead of running retrieval
+    pub fn with_query_routing(mut self, enabled: bool) -> Self {
+        self.route_queries = enabled;
+        self
+    }
+
+    // Caps the (estimated) size of the retrieved context passed to the LLM
+    pub fn with_max_context_tokens(mut self, max_tokens: Option<usize>) -> Self {
+        self.max_context_tokens = max_tokens;
+        self
+    }
+
+    // Caps the max_results and max_context_tokens of requests
+    pub fn with_request_limits(mut self, max_results: usize, max_context_tokens: usize) -> Self {
+        self.max_results_limit = max_results.max(1);
+        self.max_context_tokens_limit = max_context_tokens.max(1);
+        self
+    }
+
+    // Mixes document metadata (recency, tags like "current") into chunk scores
+    pub fn with_ranking_weights(mut self, weights: RankingWeights) -> Self {
+        self.ranking_weights = weights;
+        self
+    }
+
+    // The provider answers are generated with
+    pub fn llm(&self) -> &Arc<dyn LlmProvider> {
+        &self.llm
+    }
+
+    // Number of questions answer_batch answers per LLM call (1 = one call per question)
+    pub fn with_llm_batch_size(mut self, batch_size: usize) -> Self {
+        self.llm_batch_size = batch_size.max(1);
+        self
+    }
+
+    // Caches full responses for `ttl`, keyed by the request and its collection_version.
+    // Entries for a collection stop matching as soon as any document changes.
+    pub fn with_response_cache(mut self, ttl: Option<Duration>) -> Self {
+        self.response_cache = ttl.map(|ttl| ResponseCache::new(ttl, DEFAULT_MAX_CACHED_RESPONSES));
+        self
+    }
+
+    // Flags values (waiting periods, limits, percentages) that retrieved documents disagree on
+    pub fn with_conflict_detection(mut self, enabled: bool) -> Self {
+        self.detect_conflicts = enabled;
+        self
+    }
+
+    // Translator used when the query and corpus languages differ; None disables translation
+    pub fn with_translator(mut self, translator: Option<Arc<dyn TranslationProvider>>) -> Self {
+        self.translator = translator;
+        self
+    }
+
+    // Appends every feedback entry to a JSONL file in addition to keeping it in memory
+    pub fn with_feedback_log(mut self, path: Option<PathBuf>) -> Self {
+        self.feedback = FeedbackStore::new(DEFAULT_MAX_TRACKED_QUERIES, path);
+        self
+    }
+
+    // Retrieves with `retriever` regardless of the retrieval strategy
+    pub fn with_retriever(mut self, retriever: Arc<dyn Retriever>) -> Self {
+        self.retriever = Some(retriever);
+        self
+    }
+
+    // Adds a stage before the last reranker, which by default takes the top results with MMR,
+    // so the stage sees every candidate that passed the earlier filters
+    pub fn with_reranker(mut self, reranker: Arc<dyn Reranker>) -> Self {
+        let position = self.rerankers.len().saturating_sub(1);
+        self.rerankers.insert(position, reranker);
+        self
+    }
+
+    // Replaces every reranking stage; see pipeline::default_rerankers for the built-in ones
+    pub fn with_rerankers(mut self, rerankers: Vec<Arc<dyn Reranker>>) -> Self {
+        self.rerankers = rerankers;
+        self
+    }
+
+    pub fn with_context_builder(mut self, context_builder: Arc<dyn ContextBuilder>) -> Self {
+        self.context_builder = context_builder;
+        self
+    }
+
+    pub fn with_generator(mut self, generator: Arc<dyn Generator>) -> Self {
+        self.generator = generator;
+        self
+    }
+
+    pub fn clear_response_cache(&self) {
+        if let Some(cache) = &self.response_cache {
+            cache.clear();
+        }
+    }
+
+    // Hits and misses of the response cache, None when it is disabled
+    pub fn response_cache_stats(&self) -> Option<CacheStats> {
+        self.response_cache.as_ref().map(ResponseCache::stats)
+    }
+
+    // Drops cached responses citing any of `chunk_ids`, returning how many there were
+    pub fn purge_cached_responses(&self, chunk_ids: &HashSet<String>) -> usize {
+        match &self.response_cache {
+            Some(cache) => cache.remove_citing(|response| cites_any(response, chunk_ids)),
+            None => 0,
+        }
+    }
+
+    pub fn caches_any_of(&self, chunk_ids: &HashSet<String>) -> bool {
+        self.response_cache.as_ref().is_some_and(|cache| cache.any_citing(|response| cites_any(response, chunk_ids)))
+    }
+
+    pub fn create_session(&self) -> Session {
+        self.sessions.create()
+    }
+
+    // Session only `owner` is meant to use; callers check Session::owner before using it
+    pub fn create_session_for(&self, owner: &str) -> Session {
+        self.sessions.create_for(Some(owner))
+    }
+
+    pub fn max_session_turns(&self) -> usize {
+        self.sessions.max_turns()
+    }
+
+    pub fn session(&self, session_id: &str) -> Option<Session> {
+        self.sessions.get(session_id)
+    }
+
+    pub fn list_sessions(&self) -> Vec<Session> {
+        self.sessions.list()
+    }
+
+    pub fn delete_session(&self, session_id: &str) -> bool {
+        self.sessions.delete(session_id)
+    }
+
+    // Token budget for the request's context, within the configured limit
+    fn context_budget(&self, request: &QueryRequest) -> Option<usize> {
+        request.max_context_tokens.or(self.max_context_tokens).map(|budget| budget.min(self.max_context_tokens_limit))
+    }
+
+    // The session the request continues; sessions are created with create_session, so an
+    // unknown or expired id is an error rather than the start of a new conversation
+    fn request_session(&self, request: &QueryRequest) -> Result<Option<Session>> {
+        match request.session_id.as_deref() {
+            Some(id) => self.sessions.get(id).map(Some).ok_or_else(|| RagError::NotFound(format!("session {}", id))),
+            None => Ok(None),
         }
     }
 
     pub async fn query(&self, query: &str, documents: &[Document], max_results: usize) -> Result<QueryResponse> {
+        let request = QueryRequest {
+            query: query.to_string(),
+            max_results: Some(max_results),
+            ..Default::default()
+        };
+        self.answer(&request, documents).await
+    }
+
+    pub async fn answer(&self, request: &QueryRequest, documents: &[Document]) -> Result<QueryResponse> {
+        self.answer_with_embeddings(request, documents, self.embedding_service.as_ref()).await
+    }
+
+    // Answers against `documents` embedded by `embeddings` rather than the service's own
+    // provider, e.g. an ad-hoc document that was indexed for a single request
+    pub async fn answer_with_embeddings(
+        &self,
+        request: &QueryRequest,
+        documents: &[Document],
+        embeddings: &dyn EmbeddingProvider,
+    ) -> Result<QueryResponse> {
+        check_query(request)?;
+        let key = self.response_cache_key(request);
+        if let Some(cached) = self.cached_response(key.as_deref()) {
+            return Ok(self.track_query(request, cached));
+        }
+
+        let response = match cancel::or_cancelled(self.answer_uncached(request, documents, embeddings)).await {
+            Ok(response) => response,
+            Err(e) => return Ok(self.track_query(request, self.degraded_response(key.as_deref(), e)?)),
+        };
+        self.store_response(key, &response);
+        Ok(self.track_query(request, response))
+    }
+
+    // Same as answer_with_embeddings, but reports progress on `events` as it goes: status
+    // updates, the retrieved documents and the answer text in pieces as the LLM produces it.
+    // Cached answers, small talk and decision/compare modes arrive as a single delta.
+    pub async fn answer_streaming(
+        &self,
+        request: &QueryRequest,
+        documents: &[Document],
+        embeddings: &dyn EmbeddingProvider,
+        events: &mpsc::Sender<StreamEvent>,
+    ) -> Result<QueryResponse> {
+        check_query(request)?;
+        let key = self.response_cache_key(request);
+        if let Some(cached) = self.cached_response(key.as_deref()) {
+            send_event(events, StreamEvent::Delta { text: cached.response.clone() }).await;
+            return Ok(self.track_query(request, cached));
+        }
+
+        let response = match cancel::or_cancelled(self.stream_uncached(request, documents, embeddings, events)).await {
+            Ok(response) => response,
+            Err(e) => {
+                let response = self.degraded_response(key.as_deref(), e)?;
+                send_event(events, StreamEvent::Delta { text: response.response.clone() }).await;
+                return Ok(self.track_query(request, response));
+            }
+        };
+        self.store_response(key, &response);
+        Ok(self.track_query(request, response))
+    }
+
+    async fn stream_uncached(
+        &self,
+        request: &QueryRequest,
+        documents: &[Document],
+        embeddings: &dyn EmbeddingProvider,
+        events: &mpsc::Sender<StreamEvent>,
+    ) -> Result<QueryResponse> {
+        let small_talk = self.route_queries && classify_query(&request.query) == QueryIntent::SmallTalk;
+        if small_talk || request.response_mode.unwrap_or_default() != ResponseMode::Answer {
+            send_event(events, StreamEvent::Status { stage: "generating".to_string() }).await;
+            let response = self.answer_uncached(request, documents, embeddings).await?;
+            send_event(events, StreamEvent::Delta { text: response.response.clone() }).await;
+            return Ok(response);
+        }
+
+        let start_time = std::time::Instant::now();
+        let session = self.request_session(request)?;
+
+        send_event(events, StreamEvent::Status { stage: "retrieving".to_string() }).await;
+        let retrieval = self.run_retrieval(request, documents, embeddings, session.as_ref()).await?;
+
+        let mut sources: Vec<String> = Vec::new();
+        for scored in &retrieval.chunks {
+            if let Some(doc) = documents.iter().find(|d| d.chunks.iter().any(|c| c.id == scored.chunk.id)) {
+                if !sources.contains(&doc.filename) {
+                    sources.push(doc.filename.clone());
+                }
+            }
+        }
+        send_event(events, StreamEvent::Retrieved { chunks: retrieval.chunks.len(), documents: sources }).await;
+        send_event(events, StreamEvent::Status { stage: "generating".to_string() }).await;
+
+        // Forward LLM deltas while the answer is generated; the sender is dropped when
+        // generation finishes, which ends the forwarding loop
+        let (delta_tx, mut delta_rx) = mpsc::channel::<String>(32);
+        let generation = async move {
+            let delta_tx = delta_tx;
+            self.generate_answer(request, documents, retrieval, session, start_time, Some(&delta_tx))
+                .await
+        };
+        let forward = async {
+            while let Some(text) = delta_rx.recv().await {
+                send_event(events, StreamEvent::Delta { text }).await;
+            }
+        };
+        let (response, ()) = tokio::join!(generation, forward);
+        response
+    }
+
+    // Gives every answer its own id (also when served from the cache or shared by identical
+    // batch questions) and remembers it so feedback can refer to it
+    fn track_query(&self, request: &QueryRequest, mut response: QueryResponse) -> QueryResponse {
+        response.query_id = Uuid::new_v4().to_string();
+        self.feedback.track_query(request, &response);
+        response
+    }
+
+    // Stores a thumbs-up/down for an answer returned earlier, with the question and answer
+    // it refers to when they are still tracked and belong to the same tenant
+    pub fn record_feedback(
+        &self,
+        query_id: &str,
+        rating: Rating,
+        comment: Option<String>,
+        tenant: Option<&str>,
+    ) -> Result<Feedback> {
+        let feedback = self.feedback.record(query_id, rating, comment, tenant)?;
+        if feedback.query.is_none() {
+            tracing::warn!("Feedback for unknown query id {}", query_id);
+        }
+        Ok(feedback)
+    }
+
+    // Feedback recorded for `tenant` (None for requests without a tenant)
+    pub fn list_feedback(&self, tenant: Option<&str>) -> Vec<Feedback> {
+        self.feedback.list(tenant)
+    }
+
+    pub fn tracked_query(&self, query_id: &str) -> Option<QueryRecord> {
+        self.feedback.query(query_id)
+    }
+
+    async fn answer_uncached(
+        &self,
+        request: &QueryRequest,
+        documents: &[Document],
+        embeddings: &dyn EmbeddingProvider,
+    ) -> Result<QueryResponse> {
         let start_time = std::time::Instant::now();
+        let query = request.query.as_str();
+        let answer_language = self.resolve_answer_language(request);
+        let session = self.request_session(request)?;
+        let conversation = session.as_ref().map(Session::transcript).unwrap_or_default();
+        let debug = request.debug.unwrap_or(false);
+
+        // Small talk skips retrieval entirely
+        if self.route_queries && classify_query(query) == QueryIntent::SmallTalk {
+            tracing::info!("Routing query '{}' to the small-talk path", query);
+            let prompt = build_small_talk_prompt(query, &answer_language, &conversation);
+            let response = self.llm_generate("small_talk", &prompt).await?;
+            if let Some(session) = &session {
+                self.record_session_turn(session, query, &response).await;
+            }
 
-        // Generate query embedding
-        let query_embedding = self.embedding_service.embed_query(query).await?;
+            return Ok(QueryResponse {
+                status: "success".to_string(),
+                response,
+                processing_time_ms: start_time.elapsed().as_millis(),
+                session_id: session.map(|s| s.id),
+                debug: debug.then(|| QueryDebug {
+                    route: "small_talk".to_string(),
+                    retrieval_query: String::new(),
+                    stages: Vec::new(),
+                    context: String::new(),
+                    prompt,
+                    confidence: None,
+                }),
+                ..Default::default()
+            });
+        }
+
+        // Questions about a single clause value, in English (the language of the answer
+        // templates), are answered from the clauses extracted at ingest
+        if request.clause_answers.unwrap_or(true)
+            && request.response_mode.unwrap_or_default() == ResponseMode::Answer
+            && answer_language == DEFAULT_LANGUAGE
+        {
+            if let Some(found) = match_clauses(query, documents.iter().filter(|doc| Self::in_scope(request, doc))) {
+                tracing::info!("Answering query '{}' from {} extracted clauses", query, found.clauses.len());
+                return Ok(self.answer_from_clauses(query, documents, found, session, debug, start_time).await);
+            }
+        }
 
-        // Find relevant chunks
-        let relevant_chunks = self.find_relevant_chunks(&query_embedding, documents, max_results)?;
+        if request.response_mode.unwrap_or_default() == ResponseMode::Compare {
+            return self.answer_compare(request, documents, embeddings, session, &answer_language, start_time).await;
+        }
 
-        // Generate response using Gemini
-        let response = self.gemini_service
-            .generate_response(query, &relevant_chunks, documents)
-            .await?;
+        let retrieval = self.run_retrieval(request, documents, embeddings, session.as_ref()).await?;
+        self.generate_answer(request, documents, retrieval, session, start_time, None).await
+    }
+
+    // A clause answer, citing the chunks of the clauses it comes from
+    async fn answer_from_clauses(
+        &self,
+        query: &str,
+        documents: &[Document],
+        found: ClauseMatch<'_>,
+        session: Option<Session>,
+        debug: bool,
+        start_time: std::time::Instant,
+    ) -> QueryResponse {
+        let mut chunks: Vec<ScoredChunk> = Vec::new();
+        for (document, clause) in &found.clauses {
+            let chunk = document.chunks.iter().find(|chunk| chunk.id == clause.chunk_id);
+            if let Some(chunk) = chunk.filter(|chunk| !chunks.iter().any(|scored| scored.chunk.id == chunk.id)) {
+                chunks.push(ScoredChunk { chunk: chunk.clone(), score: 1.0, similarity: 1.0 });
+            }
+        }
+        // Highlighting the clause sentences rather than the templated answer
+        let wording: Vec<&str> = found.clauses.iter().map(|(_, clause)| clause.text.as_str()).collect();
+        let citations = self.create_citations(&chunks, documents, &wording.join(" "));
+        if let Some(session) = &session {
+            self.record_session_turn(session, query, &found.answer).await;
+        }
+
+        QueryResponse {
+            status: "success".to_string(),
+            citations,
+            processing_time_ms: start_time.elapsed().as_millis(),
+            session_id: session.map(|s| s.id),
+            debug: debug.then(|| QueryDebug {
+                route: "clause".to_string(),
+                retrieval_query: query.to_string(),
+                stages: Vec::new(),
+                context: wording.join("\n"),
+                prompt: String::new(),
+                confidence: None,
+            }),
+            response: found.answer,
+            ..Default::default()
+        }
+    }
+
+    // Compare mode: retrieves from every document in scope separately, so each one is
+    // represented in the context, and asks for per-document answers plus a comparison table
+    async fn answer_compare(
+        &self,
+        request: &QueryRequest,
+        documents: &[Document],
+        embeddings: &dyn EmbeddingProvider,
+        session: Option<Session>,
+        answer_language: &str,
+        start_time: std::time::Instant,
+    ) -> Result<QueryResponse> {
+        let query = request.query.as_str();
+        let mut compared: Vec<&Document> = documents.iter().filter(|doc| Self::in_scope(request, doc)).collect();
+        if compared.len() > MAX_COMPARE_DOCUMENTS {
+            tracing::warn!(
+                "Comparing the first {} of {} documents in scope; pass document_ids to choose",
+                MAX_COMPARE_DOCUMENTS,
+                compared.len()
+            );
+            compared.truncate(MAX_COMPARE_DOCUMENTS);
+        }
+
+        let context_options = ContextOptions {
+            neighbor_window: request.neighbor_window.unwrap_or(self.neighbor_window),
+            max_tokens: self.context_budget(request).map(|budget| budget / compared.len().max(1)),
+        };
+
+        let mut sections = Vec::with_capacity(compared.len());
+        let mut all_chunks: Vec<ScoredChunk> = Vec::new();
+        let mut stages = Vec::new();
+        let mut retrieval_query = String::new();
+        let mut rewritten_query = None;
+        let mut translated_query = None;
+
+        for document in &compared {
+            let scoped = QueryRequest {
+                document_ids: Some(vec![document.id.clone()]),
+                filenames: None,
+                max_results: Some(request.max_results.unwrap_or(DEFAULT_COMPARE_RESULTS)),
+                ..request.clone()
+            };
+            let retrieval = self.run_retrieval(&scoped, documents, embeddings, session.as_ref()).await?;
+            retrieval_query = retrieval.retrieval_query;
+            rewritten_query = rewritten_query.or(retrieval.rewritten_query);
+            translated_query = translated_query.or(retrieval.translated_query);
+            for mut stage in retrieval.stages {
+                stage.name = format!("{}:{}", document.filename, stage.name);
+                stages.push(stage);
+            }
+
+            let section = if retrieval.abstained {
+                "No relevant passages found in this document.\n".to_string()
+            } else {
+                self.context_builder.build(&retrieval.chunks, documents, &context_options)
+            };
+            sections.push((document.filename.clone(), section));
+            all_chunks.extend(retrieval.chunks);
+        }
+
+        let prompt = build_compare_prompt(query, &sections, answer_language);
+        let generation = self.generator.generate_with_confidence(&prompt, None).await?;
+        let response = generation.text;
+
+        if let Some(session) = &session {
+            self.record_session_turn(session, query, &response).await;
+        }
+
+        let filenames: Vec<String> = compared.iter().map(|doc| doc.filename.clone()).collect();
+        let document_answers = parse_compare_sections(&response, &filenames);
+        let citations = self.create_citations(&all_chunks, documents, &response);
+        let conflicts = self.find_conflicts(&all_chunks, documents);
+        let retrieval_scores = RetrievalScores::from_chunks(&all_chunks);
+        let confidence = AnswerConfidence::from_signals(retrieval_scores.as_ref(), generation.confidence);
+
+        Ok(QueryResponse {
+            status: "success".to_string(),
+            response,
+            citations,
+            processing_time_ms: start_time.elapsed().as_millis(),
+            retrieval_scores,
+            confidence_score: confidence.map(|confidence| confidence.score),
+            rewritten_query,
+            translated_query,
+            session_id: session.map(|s| s.id),
+            debug: request.debug.unwrap_or(false).then(|| QueryDebug {
+                route: "compare".to_string(),
+                retrieval_query,
+                stages,
+                context: sections
+                    .iter()
+                    .map(|(filename, section)| format!("=== DOCUMENT: {} ===\n{}", filename, section))
+                    .collect(),
+                prompt,
+                confidence,
+            }),
+            conflicts,
+            document_answers,
+            ..Default::default()
+        })
+    }
+
+    // LLM call in its own span (see usage::llm_span)
+    async fn llm_generate(&self, phase: &'static str, prompt: &str) -> Result<String> {
+        let span = usage::llm_span(phase);
+        let output = self
+            .llm
+            .generate(prompt)
+            .instrument(span.clone())
+            .await
+            .inspect_err(|e| usage::record_error(&span, e))?;
+        usage::record(&span, prompt, &output);
+        Ok(output)
+    }
+
+    // Generation half of the pipeline for a single question: context packing, the LLM
+    // call, session bookkeeping and citations
+    async fn generate_answer(
+        &self,
+        request: &QueryRequest,
+        documents: &[Document],
+        retrieval: Retrieval,
+        session: Option<Session>,
+        start_time: std::time::Instant,
+        deltas: Option<&mpsc::Sender<String>>,
+    ) -> Result<QueryResponse> {
+        let query = request.query.as_str();
+        let answer_language = self.resolve_answer_language(request);
+        let conversation = session.as_ref().map(Session::transcript).unwrap_or_default();
+        let retrieval_scores = RetrievalScores::from_chunks(&retrieval.chunks);
+        let rewritten_query = retrieval.rewritten_query;
+        let translated_query = retrieval.translated_query;
+        let decision_mode = request.response_mode.unwrap_or_default() == ResponseMode::Decision;
+        let mut debug_info = request.debug.unwrap_or(false).then(|| QueryDebug {
+            route: "document_question".to_string(),
+            retrieval_query: retrieval.retrieval_query,
+            stages: retrieval.stages,
+            context: String::new(),
+            prompt: String::new(),
+            confidence: None,
+        });
+        let abstention = self.abstention_policy(request);
+        if retrieval.abstained && abstention.general_knowledge_fallback && !decision_mode {
+            let prompt = build_general_knowledge_prompt(query, &answer_language, &conversation);
+            let generation = self.generator.generate_with_confidence(&prompt, deltas).await?;
+            let response = generation.text;
+            if let Some(session) = &session {
+                self.record_session_turn(session, query, &response).await;
+            }
+            let confidence = AnswerConfidence::from_signals(retrieval_scores.as_ref(), generation.confidence);
+            if let Some(debug_info) = debug_info.as_mut() {
+                debug_info.route = "general_knowledge".to_string();
+                debug_info.prompt = prompt;
+                debug_info.confidence = confidence;
+            }
+
+            return Ok(QueryResponse {
+                status: "general_knowledge".to_string(),
+                response,
+                processing_time_ms: start_time.elapsed().as_millis(),
+                retrieval_scores,
+                confidence_score: confidence.map(|confidence| confidence.score),
+                rewritten_query,
+                translated_query,
+                session_id: session.map(|s| s.id),
+                debug: debug_info,
+                ..Default::default()
+            });
+        }
+        if retrieval.abstained {
+            if let Some(deltas) = deltas {
+                let _ = deltas.send(abstention.message.clone()).await;
+            }
+            let confidence = AnswerConfidence::from_signals(retrieval_scores.as_ref(), None);
+            if let Some(debug_info) = debug_info.as_mut() {
+                debug_info.confidence = confidence;
+            }
+            return Ok(QueryResponse {
+                status: "insufficient_information".to_string(),
+                response: abstention.message.clone(),
+                processing_time_ms: start_time.elapsed().as_millis(),
+                retrieval_scores,
+                confidence_score: confidence.map(|confidence| confidence.score),
+                rewritten_query,
+                translated_query,
+                session_id: session.map(|s| s.id),
+                debug: debug_info,
+                decision: decision_mode.then(|| Decision {
+                    decision: DecisionOutcome::NeedsInfo,
+                    amount: None,
+                    applicable_clauses: Vec::new(),
+                    justification: abstention.message,
+                }),
+                ..Default::default()
+            });
+        }
+        let scored_chunks = retrieval.chunks;
+
+        let context_options = ContextOptions {
+            neighbor_window: request.neighbor_window.unwrap_or(self.neighbor_window),
+            max_tokens: self.context_budget(request),
+        };
+        let mut context = self.context_builder.build(&scored_chunks, documents, &context_options);
+
+        // Tell the LLM where documents disagree rather than letting it pick one value
+        let conflicts = self.find_conflicts(&scored_chunks, documents);
+        context.push_str(&conflict_notice(&conflicts));
+
+        let prompt = self.generator.prompt(&GenerationInput {
+            request,
+            context: &context,
+            answer_language: &answer_language,
+            conversation: &conversation,
+            abstention: &abstention,
+        });
+        // Decision output is JSON, so it is only sent once parsed (by the caller)
+        let generation = self.generator.generate_with_confidence(&prompt, deltas.filter(|_| !decision_mode)).await?;
+        let output = generation.text;
+
+        // In decision mode the justification doubles as the text answer
+        let (response, decision) = if decision_mode {
+            match parse_decision(&output) {
+                Some(decision) => (decision.justification.clone(), Some(decision)),
+                None => {
+                    tracing::warn!("Could not parse a decision from the LLM output, returning it as text");
+                    (output, None)
+                }
+            }
+        } else {
+            (output, None)
+        };
+
+        if let Some(session) = &session {
+            self.record_session_turn(session, query, &response).await;
+        }
+
+        let confidence = AnswerConfidence::from_signals(retrieval_scores.as_ref(), generation.confidence);
+        if let Some(debug_info) = debug_info.as_mut() {
+            debug_info.context = context;
+            debug_info.prompt = prompt;
+            debug_info.confidence = confidence;
+        }
 
         // Create citations
-        let citations = self.create_citations(&relevant_chunks, documents);
+        let citations = self.create_citations(&scored_chunks, documents, &response);
 
         let processing_time = start_time.elapsed().as_millis();
 
         Ok(QueryResponse {
-            status: "success".to_string(),
+            status: answer_status(&response, &abstention).to_string(),
             response,
             citations,
             processing_time_ms: processing_time,
+            retrieval_scores,
+            confidence_score: confidence.map(|confidence| confidence.score),
+            rewritten_query,
+            translated_query,
+            session_id: session.map(|s| s.id),
+            debug: debug_info,
+            decision,
+            conflicts,
+            ..Default::default()
         })
     }
 
-    fn find_relevant_chunks(
+    pub async fn query_batch(
         &self,
-        query_embedding: &[f32],
+        questions: &[String],
         documents: &[Document],
         max_results: usize,
-    ) -> Result<Vec<DocumentChunk>> {
-        let mut chunk_scores: Vec<(DocumentChunk, f32)> = Vec::new();
-
-        for document in documents {
-            for chunk in &document.chunks {
-                if let Some(chunk_embedding) = &chunk.embedding {
-                    let similarity = self.embedding_service
-                        .calculate_similarity(query_embedding, chunk_embedding);
-                    chunk_scores.push((chunk.clone(), similarity));
+    ) -> Vec<Result<QueryResponse>> {
+        let requests: Vec<QueryRequest> = questions
+            .iter()
+            .map(|question| QueryRequest {
+                query: question.clone(),
+                max_results: Some(max_results),
+                ..Default::default()
+            })
+            .collect();
+        self.answer_batch(&requests, documents).await
+    }
+
+    pub async fn answer_batch(&self, requests: &[QueryRequest], documents: &[Document]) -> Vec<Result<QueryResponse>> {
+        self.answer_batch_with_embeddings(requests, documents, self.embedding_service.as_ref()).await
+    }
+
+    // Answers several questions, one result per request in the same order. Identical
+    // requests are answered once. With an LLM batch size above 1, questions in the same
+    // answer language are answered together from one prompt whose context is the
+    // de-duplicated union of their chunks; questions the batched answer misses fall back to
+    // a single-question call. Session, small-talk and decision/compare mode requests always
+    // run on their own.
+    pub async fn answer_batch_with_embeddings(
+        &self,
+        requests: &[QueryRequest],
+        documents: &[Document],
+        embeddings: &dyn EmbeddingProvider,
+    ) -> Vec<Result<QueryResponse>> {
+        match cancel::until_cancelled(self.batch_answers(requests, documents, embeddings)).await {
+            Some(results) => results,
+            None => requests.iter().map(|_| Err(RagError::Cancelled)).collect(),
+        }
+    }
+
+    async fn batch_answers(
+        &self,
+        requests: &[QueryRequest],
+        documents: &[Document],
+        embeddings: &dyn EmbeddingProvider,
+    ) -> Vec<Result<QueryResponse>> {
+        let mut results: Vec<Option<Result<QueryResponse>>> = (0..requests.len()).map(|_| None).collect();
+
+        // Map every request onto the first identical one
+        let mut canonical: Vec<usize> = Vec::with_capacity(requests.len());
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        for (idx, request) in requests.iter().enumerate() {
+            let key = serde_json::to_string(request).unwrap_or_else(|_| idx.to_string());
+            canonical.push(*seen.entry(key).or_insert(idx));
+        }
+
+        let keys: Vec<Option<String>> = requests.iter().map(|request| self.response_cache_key(request)).collect();
+        let mut cache_hits = vec![false; requests.len()];
+
+        // Retrieval runs per question; generation is deferred so it can be batched
+        let mut pending: Vec<(usize, Retrieval, String)> = Vec::new();
+        for (idx, request) in requests.iter().enumerate() {
+            if canonical[idx] != idx {
+                continue;
+            }
+            if let Err(e) = check_query(request) {
+                results[idx] = Some(Err(e));
+                continue;
+            }
+
+            if let Some(cached) = self.cached_response(keys[idx].as_deref()) {
+                results[idx] = Some(Ok(cached));
+                cache_hits[idx] = true;
+                continue;
+            }
+
+            let runs_alone = self.llm_batch_size <= 1
+                || request.session_id.is_some()
+                || request.response_mode.unwrap_or_default() != ResponseMode::Answer
+                || (self.route_queries && classify_query(&request.query) == QueryIntent::SmallTalk);
+            if runs_alone {
+                results[idx] = Some(self.answer_uncached(request, documents, embeddings).await);
+                continue;
+            }
+
+            let start_time = std::time::Instant::now();
+            match self.run_retrieval(request, documents, embeddings, None).await {
+                Ok(retrieval) if retrieval.abstained => {
+                    results[idx] = Some(
+                        self.generate_answer(request, documents, retrieval, None, start_time, None).await,
+                    );
+                }
+                Ok(retrieval) => pending.push((idx, retrieval, self.resolve_answer_language(request))),
+                Err(e) => results[idx] = Some(Err(e)),
+            }
+        }
+
+        // Group by answer language so one prompt never has to switch languages
+        let mut groups: Vec<Vec<(usize, Retrieval, String)>> = Vec::new();
+        for item in pending {
+            match groups
+                .iter_mut()
+                .find(|group| group.len() < self.llm_batch_size && group[0].2 == item.2)
+            {
+                Some(group) => group.push(item),
+                None => groups.push(vec![item]),
+            }
+        }
+
+        for group in groups {
+            for (idx, result) in self.generate_batch(requests, documents, group).await {
+                results[idx] = Some(result);
+            }
+        }
+
+        for (idx, key) in keys.iter().enumerate() {
+            if let (Some(Ok(response)), false) = (&results[idx], cache_hits[idx]) {
+                self.store_response(key.clone(), response);
+            }
+        }
+
+        (0..requests.len())
+            .map(|idx| match &results[canonical[idx]] {
+                Some(Ok(response)) => Ok(self.track_query(&requests[idx], response.clone())),
+                Some(Err(e)) => {
+                    let response = self.degraded_response(keys[canonical[idx]].as_deref(), e.clone())?;
+                    Ok(self.track_query(&requests[idx], response))
+                }
+                None => Err(RagError::Llm("No response generated".to_string())),
+            })
+            .collect()
+    }
+
+    // Answers a group of retrieved questions with a single LLM call
+    async fn generate_batch(
+        &self,
+        requests: &[QueryRequest],
+        documents: &[Document],
+        group: Vec<(usize, Retrieval, String)>,
+    ) -> Vec<(usize, Result<QueryResponse>)> {
+        let start_time = std::time::Instant::now();
+        let mut results = Vec::with_capacity(group.len());
+
+        if group.len() == 1 {
+            for (idx, retrieval, _) in group {
+                let result = self.generate_answer(&requests[idx], documents, retrieval, None, start_time, None).await;
+                results.push((idx, result));
+            }
+            return results;
+        }
+
+        // Shared context: every question's chunks, each chunk once, best score first
+        let mut union: Vec<ScoredChunk> = Vec::new();
+        for (_, retrieval, _) in &group {
+            for scored in &retrieval.chunks {
+                match union.iter_mut().find(|existing| existing.chunk.id == scored.chunk.id) {
+                    Some(existing) if existing.score < scored.score => *existing = scored.clone(),
+                    Some(_) => {}
+                    None => union.push(scored.clone()),
                 }
             }
         }
+        sort_by_score(&mut union);
+
+        // The shared context serves every question, so it gets their combined budget
+        let context_options = ContextOptions {
+            neighbor_window: group
+                .iter()
+                .map(|(idx, _, _)| requests[*idx].neighbor_window.unwrap_or(self.neighbor_window))
+                .max()
+                .unwrap_or_default(),
+            max_tokens: group
+                .iter()
+                .map(|(idx, _, _)| self.context_budget(&requests[*idx]))
+                .sum::<Option<usize>>(),
+        };
+        let mut context = self.context_builder.build(&union, documents, &context_options);
+        context.push_str(&conflict_notice(&self.find_conflicts(&union, documents)));
+
+        let questions: Vec<&str> = group.iter().map(|(idx, _, _)| requests[*idx].query.as_str()).collect();
+        let prompt = build_batch_prompt(&questions, &context, &group[0].2, &self.abstention_policy(&requests[group[0].0]));
+        tracing::info!("Answering {} questions with one LLM call", questions.len());
+        // The model's confidence covers the whole batched output, so every answer gets it
+        let (mut answers, generation_confidence) = match self.generator.generate_with_confidence(&prompt, None).await {
+            Ok(generation) => (parse_batch_answers(&generation.text, questions.len()), generation.confidence),
+            Err(e) => {
+                tracing::warn!("Batched generation failed, answering questions one by one: {}", e);
+                (vec![None; questions.len()], None)
+            }
+        };
+
+        for (position, (idx, retrieval, _)) in group.into_iter().enumerate() {
+            let request = &requests[idx];
+            let Some(response) = answers[position].take() else {
+                tracing::info!("Batched answer missing for question {}, answering it alone", position + 1);
+                let result = self.generate_answer(request, documents, retrieval, None, start_time, None).await;
+                results.push((idx, result));
+                continue;
+            };
 
-        // Sort by similarity score (highest first)
-        chunk_scores.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+            let citations = self.create_citations(&retrieval.chunks, documents, &response);
+            let conflicts = self.find_conflicts(&retrieval.chunks, documents);
+            let retrieval_scores = RetrievalScores::from_chunks(&retrieval.chunks);
+            let confidence = AnswerConfidence::from_signals(retrieval_scores.as_ref(), generation_confidence);
+            results.push((
+                idx,
+                Ok(QueryResponse {
+                    status: answer_status(&response, &self.abstention_policy(request)).to_string(),
+                    response,
+                    citations,
+                    processing_time_ms: start_time.elapsed().as_millis(),
+                    retrieval_scores,
+                    confidence_score: confidence.map(|confidence| confidence.score),
+                    rewritten_query: retrieval.rewritten_query,
+                    translated_query: retrieval.translated_query,
+                    debug: request.debug.unwrap_or(false).then(|| QueryDebug {
+                        route: "document_question_batch".to_string(),
+                        retrieval_query: retrieval.retrieval_query,
+                        stages: retrieval.stages,
+                        context: context.clone(),
+                        prompt: prompt.clone(),
+                        confidence,
+                    }),
+                    conflicts,
+                    ..Default::default()
+                }),
+            ));
+        }
+
+        results
+    }
+
+    // Runs the retrieval half of the pipeline without generating an answer
+    pub async fn retrieve(&self, request: &QueryRequest, documents: &[Document]) -> Result<RetrievalResponse> {
+        self.retrieve_with_embeddings(request, documents, self.embedding_service.as_ref()).await
+    }
 
-        // Take top results
-        let relevant_chunks: Vec<DocumentChunk> = chunk_scores
+    pub async fn retrieve_with_embeddings(
+        &self,
+        request: &QueryRequest,
+        documents: &[Document],
+        embeddings: &dyn EmbeddingProvider,
+    ) -> Result<RetrievalResponse> {
+        check_query(request)?;
+        let start_time = std::time::Instant::now();
+        let session = self.request_session(request)?;
+        let retrieval = cancel::or_cancelled(self.run_retrieval(request, documents, embeddings, session.as_ref())).await?;
+
+        let chunks = retrieval
+            .chunks
             .into_iter()
-            .take(max_results)
-            .map(|(chunk, _)| chunk)
+            .filter_map(|scored| {
+                let doc = documents.iter().find(|d| d.chunks.iter().any(|c| c.id == scored.chunk.id))?;
+                let source = source_offsets(doc, &scored.chunk);
+                Some(RetrievedChunk {
+                    document_id: doc.id.clone(),
+                    filename: doc.filename.clone(),
+                    chunk_id: scored.chunk.id,
+                    content: scored.chunk.content.into(),
+                    start_position: scored.chunk.start_position,
+                    end_position: scored.chunk.end_position,
+                    score: scored.score,
+                    similarity: scored.similarity,
+                    source,
+                })
+            })
+            .collect();
+
+        Ok(RetrievalResponse {
+            status: if retrieval.abstained { "insufficient_information" } else { "success" }.to_string(),
+            query: request.query.clone(),
+            rewritten_query: retrieval.rewritten_query,
+            translated_query: retrieval.translated_query,
+            chunks,
+            processing_time_ms: start_time.elapsed().as_millis(),
+            debug: request.debug.unwrap_or(false).then(|| QueryDebug {
+                route: "document_question".to_string(),
+                retrieval_query: retrieval.retrieval_query,
+                stages: retrieval.stages,
+                context: String::new(),
+                prompt: String::new(),
+                confidence: None,
+            }),
+        })
+    }
+
+    // Rewriting, scoping, ranking, keyword scoring, thresholding, de-duplication and MMR
+    #[tracing::instrument(
+        name = "retrieval",
+        skip_all,
+        fields(documents = documents.len(), retriever = Empty, candidates = Empty, chunk_count = Empty, abstained = Empty)
+    )]
+    async fn run_retrieval(
+        &self,
+        request: &QueryRequest,
+        documents: &[Document],
+        embeddings: &dyn EmbeddingProvider,
+        session: Option<&Session>,
+    ) -> Result<Retrieval> {
+        let query = request.query.as_str();
+        let debug = request.debug.unwrap_or(false);
+        let mut stages = Vec::new();
+
+        // Optionally rewrite the query into policy language before retrieval
+        let rewritten_query = if request.rewrite_query.unwrap_or(self.rewrite_queries) {
+            self.rewrite_query(query).await
+        } else {
+            None
+        };
+        let retrieval_query = match (&rewritten_query, session.and_then(|s| s.history.back())) {
+            (Some(rewritten), _) => rewritten.clone(),
+            // Follow-ups like "and for maternity?" need the previous question to retrieve anything useful
+            (None, Some(last_turn)) => format!("{} {}", last_turn.question, query),
+            (None, None) => query.to_string(),
+        };
+        let mut retrieval_query_text = retrieval_query;
+
+        // Only search the documents the request is scoped to
+        let scoped_documents: Vec<&Document> = documents
+            .iter()
+            .filter(|doc| Self::in_scope(request, doc))
             .collect();
+        if scoped_documents.len() < documents.len() {
+            tracing::info!("Restricted retrieval to {} of {} documents", scoped_documents.len(), documents.len());
+        }
+
+        // Search in the corpus language; the answer still follows the user's language
+        let translated_query = if request.translate_query.unwrap_or(true) {
+            self.translate_for_corpus(&retrieval_query_text, &scoped_documents).await
+        } else {
+            None
+        };
+        if let Some(translated) = &translated_query {
+            retrieval_query_text = translated.clone();
+        }
+
+        let search = RetrievalQuery {
+            request,
+            text: &retrieval_query_text,
+            documents: &scoped_documents,
+            embeddings,
+            max_results: request.max_results.unwrap_or(5).min(self.max_results_limit),
+            mmr_lambda: request.mmr_lambda.unwrap_or(self.mmr_lambda),
+            ranking_weights: request.ranking_weights.as_ref().unwrap_or(&self.ranking_weights),
+            score_threshold: self.abstention_policy(request).threshold,
+            duplicate_threshold: request.duplicate_threshold.or(self.duplicate_threshold),
+        };
+        let retriever = self.retriever(request);
+        let mut chunks = retriever.retrieve(&search).await?;
+        record_stage(&mut stages, debug, retriever.name(), &chunks);
+        let span = Span::current();
+        span.record("retriever", retriever.name());
+        span.record("candidates", chunks.len());
+
+        let mut abstained = false;
+        for reranker in &self.rerankers {
+            if !reranker.applies(&search) {
+                continue;
+            }
+            chunks = reranker.rerank(&search, chunks).await?;
+            record_stage(&mut stages, debug, reranker.name(), &chunks);
+            // Abstain without an LLM call when nothing relevant is left
+            if chunks.is_empty() {
+                tracing::info!(stage = reranker.name(), "No chunk left, abstaining");
+                abstained = true;
+                break;
+            }
+        }
+        span.record("chunk_count", chunks.len());
+        span.record("abstained", abstained);
+
+        Ok(Retrieval {
+            rewritten_query,
+            translated_query,
+            retrieval_query: retrieval_query_text,
+            chunks,
+            abstained,
+            stages,
+        })
+    }
+
+    // The configured retriever, or the one for the request's retrieval strategy
+    fn retriever(&self, request: &QueryRequest) -> Arc<dyn Retriever> {
+        if let Some(retriever) = &self.retriever {
+            return retriever.clone();
+        }
+        match request.strategy.unwrap_or(self.strategy) {
+            RetrievalStrategy::Dense => Arc::new(DenseRetriever),
+            RetrievalStrategy::MultiQuery => Arc::new(MultiQueryRetriever::new(self.llm.clone(), self.query_variants)),
+        }
+    }
+
+    async fn record_session_turn(&self, session: &Session, question: &str, answer: &str) {
+        let evicted = self.sessions.record_turn(&session.id, question, answer);
+        if evicted.is_empty() || !self.summarize_sessions {
+            return;
+        }
+
+        let prompt = build_summary_prompt(session.summary.as_deref(), &evicted);
+        match self.llm_generate("session_summary", &prompt).await {
+            Ok(summary) => self.sessions.set_summary(&session.id, summary.trim().to_string()),
+            Err(e) => tracing::warn!("Failed to summarize session {}: {}", session.id, e),
+        }
+    }
+
+    // Returns None when rewriting fails or yields nothing, so retrieval falls back to the original query
+    // Translates `query` when its language differs from the (majority) language of `documents`
+    async fn translate_for_corpus(&self, query: &str, documents: &[&Document]) -> Option<String> {
+        let translator = self.translator.as_ref()?;
+        let query_language = detect_language(query);
+        let target_language = corpus_language(documents);
+        if query_language == target_language {
+            return None;
+        }
+
+        let span = usage::llm_span("translate");
+        match translator.translate(query, target_language).instrument(span.clone()).await {
+            Ok(translated) if !translated.is_empty() => {
+                tracing::info!("Translated query from {} to {}: '{}'", query_language, target_language, translated);
+                Some(translated)
+            }
+            Ok(_) => None,
+            Err(e) => {
+                usage::record_error(&span, &e);
+                tracing::warn!("Query translation failed, searching with the original query: {}", e);
+                None
+            }
+        }
+    }
+
+    async fn rewrite_query(&self, query: &str) -> Option<String> {
+        match self.llm_generate("rewrite", &build_rewrite_prompt(query)).await {
+            Ok(rewritten) => {
+                let rewritten = rewritten
+                    .lines()
+                    .map(str::trim)
+                    .find(|line| !line.is_empty())
+                    .unwrap_or_default()
+                    .trim_matches('"')
+                    .to_string();
+                if rewritten.is_empty() {
+                    None
+                } else {
+                    tracing::info!("Rewrote query '{}' as '{}'", query, rewritten);
+                    Some(rewritten)
+                }
+            }
+            Err(e) => {
+                tracing::warn!("Query rewriting failed, using original query: {}", e);
+                None
+            }
+        }
+    }
+
+    fn find_conflicts(&self, chunks: &[ScoredChunk], documents: &[Document]) -> Vec<Conflict> {
+        if !self.detect_conflicts {
+            return Vec::new();
+        }
+        let conflicts = detect_conflicts(chunks, documents);
+        if !conflicts.is_empty() {
+            tracing::info!("Retrieved documents disagree on {} point(s)", conflicts.len());
+        }
+        conflicts
+    }
+
+    // Session requests depend on the conversation so far and are never cached, nor are
+    // requests against documents without a collection_version
+    fn response_cache_key(&self, request: &QueryRequest) -> Option<String> {
+        match (&self.response_cache, request.collection_version, &request.session_id) {
+            (Some(_), Some(version), None) => Some(cache_key(version, request)),
+            _ => None,
+        }
+    }
+
+    fn cached_response(&self, key: Option<&str>) -> Option<QueryResponse> {
+        let response = self.response_cache.as_ref()?.get(key?)?;
+        tracing::info!("Serving cached response");
+        Some(QueryResponse {
+            processing_time_ms: 0,
+            ..response
+        })
+    }
 
-        log::info!("Found {} relevant chunks", relevant_chunks.len());
-        Ok(relevant_chunks)
+    // Answer for a request that failed because a provider's circuit is open (other errors are
+    // returned as they are): the last cached answer to it however old, or a notice that
+    // answering is unavailable. Either way the status is "degraded".
+    fn degraded_response(&self, key: Option<&str>, error: RagError) -> Result<QueryResponse> {
+        let RagError::Unavailable { provider, retry_after_secs } = &error else {
+            return Err(error);
+        };
+        let stale = key.and_then(|key| self.response_cache.as_ref()?.get_stale(key));
+        tracing::warn!(
+            provider = provider.as_str(),
+            stale_cache = stale.is_some(),
+            "Circuit open, serving a degraded answer"
+        );
+        Ok(match stale {
+            Some(response) => QueryResponse { status: "degraded".to_string(), processing_time_ms: 0, ..response },
+            None => QueryResponse {
+                status: "degraded".to_string(),
+                response: format!(
+                    "Answering is temporarily unavailable because the {} provider is failing. Please try again in {} seconds.",
+                    provider, retry_after_secs
+                ),
+                ..Default::default()
+            },
+        })
     }
 
-    fn create_citations(&self, chunks: &[DocumentChunk], documents: &[Document]) -> Vec<Citation> {
+    fn store_response(&self, key: Option<String>, response: &QueryResponse) {
+        if let (Some(cache), Some(key)) = (&self.response_cache, key) {
+            cache.insert(key, response.clone());
+        }
+    }
+
+    // Request policy (or the service default) with the request's score threshold applied
+    fn abstention_policy(&self, request: &QueryRequest) -> AbstentionPolicy {
+        let mut policy = request.abstention.clone().unwrap_or_else(|| self.abstention.clone());
+        if let Some(threshold) = request.score_threshold {
+            policy.threshold = Some(threshold);
+        }
+        policy
+    }
+
+    // Per-request language wins over the service-wide override, which wins over detection
+    fn resolve_answer_language(&self, request: &QueryRequest) -> String {
+        request
+            .language
+            .clone()
+            .or_else(|| self.answer_language.clone())
+            .unwrap_or_else(|| detect_language(&request.query).to_string())
+    }
+
+    // A document is in scope when the caller may read it (see AccessControl) and no filter is
+    // given or it matches any listed id or filename
+    fn in_scope(request: &QueryRequest, document: &Document) -> bool {
+        if !document.readable_by(request.principal.as_ref()) {
+            return false;
+        }
+        let by_id = request.document_ids.as_ref().filter(|ids| !ids.is_empty());
+        let by_name = request.filenames.as_ref().filter(|names| !names.is_empty());
+
+        if by_id.is_none() && by_name.is_none() {
+            return true;
+        }
+
+        by_id.is_some_and(|ids| ids.contains(&document.id))
+            || by_name.is_some_and(|names| names.iter().any(|name| name.eq_ignore_ascii_case(&document.filename)))
+    }
+
+    fn create_citations(&self, chunks: &[ScoredChunk], documents: &[Document], answer: &str) -> Vec<Citation> {
         let mut citations = Vec::new();
 
-        for chunk in chunks {
+        for ScoredChunk { chunk, similarity, .. } in chunks {
             if let Some(doc) = documents.iter().find(|d| d.chunks.iter().any(|c| c.id == chunk.id)) {
-                let excerpt = if chunk.content.len() > 200 {
-                    format!("{}...", &chunk.content[..200])
-                } else {
-                    chunk.content.clone()
-                };
+                // Centered on the evidence for the answer, if any
+                let highlights = find_supporting_spans(&chunk.content, answer);
+                let focus = highlights.first().map(|span| span.start..span.end);
 
                 citations.push(Citation {
                     document: doc.filename.clone(),
-                    text_excerpt: excerpt,
-                    confidence_score: 0.8, // Default confidence score
+                    text_excerpt: excerpt(&chunk.content, focus, DEFAULT_EXCERPT_CHARS),
+                    confidence_score: confidence_from_similarity(*similarity),
+                    chunk_id: chunk.id.clone(),
+                    page: page_at(&doc.content, chunk.start_position),
+                    highlights,
+                    source: source_offsets(doc, chunk),
                 });
             }
         }
@@ -0,0 +1,521 @@
+use crate::models::*;
+use crate::providers::cosine_similarity;
+use std::collections::{HashMap, HashSet};
+
+pub const DEFAULT_MMR_LAMBDA: f32 = 0.7;
+
+// Standard RRF damping constant from Cormack et al.
+pub const RRF_K: f32 = 60.0;
+
+pub const DEFAULT_DUPLICATE_THRESHOLD: f32 = 0.85;
+
+// Score added to a chunk containing every requested keyword (proportionally less for fewer)
+pub const DEFAULT_KEYWORD_BOOST: f32 = 0.2;
+
+// Score added to a chunk mentioning a kind of entity the question asks for (see
+// pipeline::EntityReranker); small, so it reorders close candidates only
+pub const ENTITY_BOOST: f32 = 0.05;
+
+// Shortest repeated text treated as chunk overlap when merging neighbours
+const MIN_MERGE_OVERLAP: usize = 8;
+
+// How many relevance-ranked candidates MMR chooses from, per requested result
+pub const MMR_CANDIDATE_MULTIPLIER: usize = 4;
+
+// Maximal marginal relevance: greedily picks the candidate that maximises
+// lambda * relevance - (1 - lambda) * (similarity to anything already picked).
+// lambda = 1.0 is plain relevance ranking, lower values favour diversity.
+// `candidates` must be sorted by relevance, highest first.
+pub fn mmr_rerank(
+    candidates: Vec<ScoredChunk>,
+    max_results: usize,
+    lambda: f32,
+) -> Vec<ScoredChunk> {
+    let lambda = lambda.clamp(0.0, 1.0);
+    let mut pool: Vec<ScoredChunk> = candidates
+        .into_iter()
+        .take(max_results.saturating_mul(MMR_CANDIDATE_MULTIPLIER))
+        .collect();
+    let mut selected: Vec<ScoredChunk> = Vec::with_capacity(max_results.min(pool.len()));
+
+    while selected.len() < max_results && !pool.is_empty() {
+        let mut best_idx = 0;
+        let mut best_score = f32::NEG_INFINITY;
+
+        for (idx, candidate) in pool.iter().enumerate() {
+            let redundancy = selected
+                .iter()
+                .map(|picked| chunk_similarity(&candidate.chunk, &picked.chunk))
+                .fold(0.0_f32, f32::max);
+            let score = lambda * candidate.score - (1.0 - lambda) * redundancy;
+
+            if score > best_score {
+                best_score = score;
+                best_idx = idx;
+            }
+        }
+
+        selected.push(pool.remove(best_idx));
+    }
+
+    selected
+}
+
+// Reciprocal rank fusion: each list contributes 1 / (RRF_K + rank) per chunk.
+// Fused scores are rescaled so the best chunk scores 1.0, keeping them comparable
+// with similarity scores for MMR. The best similarity across lists is kept.
+// Each list must be sorted best first.
+pub fn reciprocal_rank_fusion(ranked_lists: Vec<Vec<ScoredChunk>>) -> Vec<ScoredChunk> {
+    let mut fused: Vec<ScoredChunk> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+
+    for list in ranked_lists {
+        for (rank, scored) in list.into_iter().enumerate() {
+            let contribution = 1.0 / (RRF_K + rank as f32 + 1.0);
+            match positions.get(&scored.chunk.id) {
+                Some(&idx) => {
+                    fused[idx].score += contribution;
+                    fused[idx].similarity = fused[idx].similarity.max(scored.similarity);
+                }
+                None => {
+                    positions.insert(scored.chunk.id.clone(), fused.len());
+                    fused.push(ScoredChunk {
+                        score: contribution,
+                        ..scored
+                    });
+                }
+            }
+        }
+    }
+
+    sort_by_score(&mut fused);
+
+    if let Some(max_score) = fused.first().map(|scored| scored.score) {
+        if max_score > 0.0 {
+            for scored in fused.iter_mut() {
+                scored.score /= max_score;
+            }
+        }
+    }
+
+    fused
+}
+
+// Walks `candidates` in rank order and drops any chunk whose word-level Jaccard or
+// embedding similarity to an already kept chunk exceeds `threshold`. Stops once
+// `limit` chunks are kept, so only the head of a large ranking is compared.
+pub fn suppress_near_duplicates(candidates: Vec<ScoredChunk>, threshold: f32, limit: usize) -> Vec<ScoredChunk> {
+    let mut kept: Vec<(ScoredChunk, HashSet<String>)> = Vec::new();
+    let mut suppressed = 0;
+
+    for candidate in candidates {
+        if kept.len() >= limit {
+            break;
+        }
+
+        let words = word_set(&candidate.chunk.content);
+        let is_duplicate = kept.iter().any(|(other, other_words)| {
+            jaccard(&words, other_words) > threshold || chunk_similarity(&candidate.chunk, &other.chunk) > threshold
+        });
+
+        if is_duplicate {
+            suppressed += 1;
+        } else {
+            kept.push((candidate, words));
+        }
+    }
+
+    if suppressed > 0 {
+        tracing::info!("Suppressed {} near-duplicate chunks", suppressed);
+    }
+
+    kept.into_iter().map(|(scored, _)| scored).collect()
+}
+
+fn word_set(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+fn jaccard(a: &HashSet<String>, b: &HashSet<String>) -> f32 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(b).count() as f32 / union as f32
+}
+
+// Boosts (or, in filter mode, keeps only) chunks that mention the requested keywords.
+// Matching is a case-insensitive phrase search, so "grace period" must appear verbatim.
+pub fn apply_keywords(
+    mut candidates: Vec<ScoredChunk>,
+    keywords: &[String],
+    mode: KeywordMode,
+    boost: f32,
+) -> Vec<ScoredChunk> {
+    let keywords: Vec<String> = keywords
+        .iter()
+        .map(|k| k.trim().to_lowercase())
+        .filter(|k| !k.is_empty())
+        .collect();
+    if keywords.is_empty() {
+        return candidates;
+    }
+
+    let matches = |chunk: &DocumentChunk| {
+        let content = chunk.content.to_lowercase();
+        keywords.iter().filter(|k| content.contains(k.as_str())).count()
+    };
+
+    match mode {
+        KeywordMode::Filter => candidates.retain(|scored| matches(&scored.chunk) > 0),
+        KeywordMode::Boost => {
+            for scored in candidates.iter_mut() {
+                let matched = matches(&scored.chunk);
+                scored.score += boost * matched as f32 / keywords.len() as f32;
+            }
+            sort_by_score(&mut candidates);
+        }
+    }
+
+    candidates
+}
+
+// Adds metadata-derived terms to each chunk's score: `weights.recency` scaled by how new the
+// chunk's document is relative to the other candidates (oldest 0, newest 1) plus the weight
+// of every tag the document carries. Documents without a timestamp get no recency bonus.
+pub fn apply_metadata_weights(
+    mut candidates: Vec<ScoredChunk>,
+    documents: &[&Document],
+    weights: &RankingWeights,
+) -> Vec<ScoredChunk> {
+    if weights.is_neutral() {
+        return candidates;
+    }
+
+    let mut owners: HashMap<&str, &Document> = HashMap::new();
+    for doc in documents {
+        for chunk in &doc.chunks {
+            owners.insert(chunk.id.as_str(), doc);
+        }
+    }
+
+    let timestamps: Vec<u64> = documents.iter().filter_map(|d| d.metadata.updated_at).collect();
+    let oldest = timestamps.iter().copied().min().unwrap_or_default();
+    let newest = timestamps.iter().copied().max().unwrap_or_default();
+
+    for scored in candidates.iter_mut() {
+        let Some(doc) = owners.get(scored.chunk.id.as_str()) else {
+            continue;
+        };
+
+        if let Some(updated_at) = doc.metadata.updated_at {
+            let recency = if newest > oldest {
+                (updated_at - oldest) as f32 / (newest - oldest) as f32
+            } else {
+                1.0
+            };
+            scored.score += weights.recency * recency;
+        }
+
+        for tag in &doc.metadata.tags {
+            if let Some(weight) = weights.tags.get(tag) {
+                scored.score += weight;
+            }
+        }
+    }
+
+    sort_by_score(&mut candidates);
+    candidates
+}
+
+pub fn sort_by_score(chunks: &mut [ScoredChunk]) {
+    chunks.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+}
+
+// Maps a chunk's raw cosine similarity onto a [0, 1] confidence score
+pub fn confidence_from_similarity(similarity: f32) -> f32 {
+    if similarity.is_nan() {
+        0.0
+    } else {
+        similarity.clamp(0.0, 1.0)
+    }
+}
+
+// Replaces each selected chunk with the text of its surrounding chunks (up to `window`
+// on each side) so clauses cut at a chunk boundary reach the LLM in full. Windows that
+// touch within a document are merged and keep the id of their best-ranked chunk, so
+// context lookups by chunk id keep working.
+pub fn expand_with_neighbors(selected: &[ScoredChunk], documents: &[Document], window: usize) -> Vec<DocumentChunk> {
+    if window == 0 {
+        return selected.iter().map(|scored| scored.chunk.clone()).collect();
+    }
+
+    // (document index, first chunk index, last chunk index, rank, chunk id)
+    let mut windows: Vec<(usize, usize, usize, usize, String)> = Vec::new();
+
+    for (rank, scored) in selected.iter().enumerate() {
+        let located = documents.iter().enumerate().find_map(|(doc_idx, doc)| {
+            doc.chunks
+                .iter()
+                .position(|c| c.id == scored.chunk.id)
+                .map(|chunk_idx| (doc_idx, chunk_idx))
+        });
+
+        let Some((doc_idx, chunk_idx)) = located else {
+            continue;
+        };
+        let last_idx = documents[doc_idx].chunks.len() - 1;
+        let start = chunk_idx.saturating_sub(window);
+        let end = (chunk_idx + window).min(last_idx);
+        windows.push((doc_idx, start, end, rank, scored.chunk.id.clone()));
+    }
+
+    // Merged in document order, so a window that grows also takes in every later one it
+    // reaches; each keeps the id and rank of its best ranked chunk
+    windows.sort_by_key(|(doc_idx, start, ..)| (*doc_idx, *start));
+    let mut merged: Vec<(usize, usize, usize, usize, String)> = Vec::with_capacity(windows.len());
+    for window in windows {
+        match merged.last_mut() {
+            Some(last) if last.0 == window.0 && window.1 <= last.2 + 1 => {
+                last.2 = last.2.max(window.2);
+                if window.3 < last.3 {
+                    (last.3, last.4) = (window.3, window.4);
+                }
+            }
+            _ => merged.push(window),
+        }
+    }
+    merged.sort_by_key(|(.., rank, _)| *rank);
+
+    merged
+        .into_iter()
+        .map(|(doc_idx, start, end, _, id)| {
+            let document = &documents[doc_idx];
+            let chunks = &document.chunks[start..=end];
+            let (first, last) = (&chunks[0], &chunks[chunks.len() - 1]);
+            // Consecutive chunks are ranges of the document's content, overlap included, unless
+            // they were saved by an older version
+            let is_range = |chunk: &DocumentChunk| document.content.get(chunk.start_position..chunk.end_position) == Some(&*chunk.content);
+            let content = match is_range(first) && is_range(last) {
+                true => document.content.slice(first.start_position..last.end_position),
+                false => None,
+            };
+            let content = content.unwrap_or_else(|| {
+                chunks
+                    .iter()
+                    .skip(1)
+                    .fold(first.content.to_string(), |merged, next| merge_overlapping(&merged, &next.content))
+                    .into()
+            });
+
+            DocumentChunk {
+                id,
+                content,
+                start_position: chunks[0].start_position,
+                end_position: chunks[chunks.len() - 1].end_position,
+                embedding: None,
+                entities: None,
+            }
+        })
+        .collect()
+}
+
+// Joins two consecutive chunks, dropping the overlap the chunker repeats at the start of `next`
+fn merge_overlapping(merged: &str, next: &str) -> String {
+    // Byte offsets in `next` ending its first 1, 2, ... characters
+    let ends: Vec<usize> = next.char_indices().map(|(i, _)| i).skip(1).chain(std::iter::once(next.len())).collect();
+
+    // The longest prefix of `next` that `merged` ends with; very short matches are more likely
+    // coincidence than chunker overlap
+    let overlap = ends
+        .iter()
+        .skip(MIN_MERGE_OVERLAP - 1)
+        .rev()
+        .copied()
+        .find(|end| *end <= merged.len() && merged.ends_with(&next[..*end]));
+
+    match overlap {
+        Some(end) => format!("{}{}", merged, &next[end..]),
+        None => format!("{} {}", merged, next),
+    }
+}
+
+fn chunk_similarity(a: &DocumentChunk, b: &DocumentChunk) -> f32 {
+    match (&a.embedding, &b.embedding) {
+        (Some(a), Some(b)) => cosine_similarity(a, b),
+        _ => 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A document whose chunks are the words of `words`, each its own range of the content
+    fn document(id: &str, words: &[&str]) -> Document {
+        let content = words.join(" ");
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        for (i, word) in words.iter().enumerate() {
+            chunks.push(DocumentChunk {
+                id: format!("{}-{}", id, i),
+                content: (*word).into(),
+                start_position: start,
+                end_position: start + word.len(),
+                embedding: None,
+                entities: None,
+            });
+            start += word.len() + 1;
+        }
+        Document { id: id.to_string(), filename: format!("{}.pdf", id), content: content.into(), chunks, metadata: DocumentMetadata::default() }
+    }
+
+    fn scored(chunk: &DocumentChunk, score: f32) -> ScoredChunk {
+        ScoredChunk { chunk: chunk.clone(), score, similarity: score }
+    }
+
+    // A candidate that is not part of any document
+    fn candidate(id: &str, content: &str, embedding: Option<Vec<f32>>, score: f32) -> ScoredChunk {
+        let chunk = DocumentChunk {
+            id: id.to_string(),
+            content: content.into(),
+            start_position: 0,
+            end_position: content.len(),
+            embedding,
+            entities: None,
+        };
+        scored(&chunk, score)
+    }
+
+    fn ids(chunks: &[ScoredChunk]) -> Vec<&str> {
+        chunks.iter().map(|scored| scored.chunk.id.as_str()).collect()
+    }
+
+    #[test]
+    fn mmr_prefers_a_diverse_candidate_over_a_redundant_one() {
+        let candidates = vec![
+            candidate("cataract", "Cataract surgery waiting period", Some(vec![1.0, 0.0]), 0.9),
+            candidate("cataract-again", "Waiting period for cataract", Some(vec![0.99, 0.1]), 0.85),
+            candidate("maternity", "Maternity cover", Some(vec![0.0, 1.0]), 0.6),
+        ];
+
+        assert_eq!(ids(&mmr_rerank(candidates.clone(), 2, DEFAULT_MMR_LAMBDA)), ["cataract", "maternity"]);
+        // Plain relevance ranking
+        assert_eq!(ids(&mmr_rerank(candidates.clone(), 2, 1.0)), ["cataract", "cataract-again"]);
+        assert_eq!(ids(&mmr_rerank(candidates, 5, 1.5)), ["cataract", "cataract-again", "maternity"]);
+    }
+
+    #[test]
+    fn mmr_only_chooses_from_the_head_of_the_ranking() {
+        // Eight near-identical chunks, then the only different one just outside the pool of
+        // MMR_CANDIDATE_MULTIPLIER candidates per result
+        let mut candidates: Vec<ScoredChunk> =
+            (0..8).map(|i| candidate(&format!("c{}", i), "text", Some(vec![1.0, 0.0]), 0.9 - i as f32 / 100.0)).collect();
+        candidates.push(candidate("different", "text", Some(vec![0.0, 1.0]), 0.5));
+
+        assert_eq!(ids(&mmr_rerank(candidates.clone(), 2, 0.5)), ["c0", "c1"]);
+        assert_eq!(ids(&mmr_rerank(candidates, 3, 0.5)), ["c0", "different", "c1"]);
+        assert!(mmr_rerank(Vec::new(), 3, DEFAULT_MMR_LAMBDA).is_empty());
+    }
+
+    #[test]
+    fn mmr_with_a_huge_max_results_returns_every_candidate() {
+        let candidates: Vec<ScoredChunk> =
+            (0..3).map(|i| candidate(&format!("c{}", i), "text", None, 0.9 - i as f32 / 10.0)).collect();
+
+        assert_eq!(ids(&mmr_rerank(candidates, usize::MAX, DEFAULT_MMR_LAMBDA)), ["c0", "c1", "c2"]);
+    }
+
+    #[test]
+    fn rrf_ranks_chunks_found_by_several_queries_first() {
+        let lists = vec![
+            vec![candidate("a", "", None, 0.9), candidate("b", "", None, 0.8), candidate("c", "", None, 0.7)],
+            vec![candidate("b", "", None, 0.95), candidate("c", "", None, 0.6)],
+            vec![candidate("d", "", None, 0.5)],
+        ];
+
+        let fused = reciprocal_rank_fusion(lists);
+
+        assert_eq!(ids(&fused[..2]), ["b", "c"]);
+        assert_eq!(fused.len(), 4);
+        // Rescaled so the best scores 1.0, keeping the best similarity of any list
+        assert_eq!(fused[0].score, 1.0);
+        assert_eq!(fused[0].similarity, 0.95);
+        let expected = (1.0 / (RRF_K + 3.0) + 1.0 / (RRF_K + 2.0)) / (1.0 / (RRF_K + 2.0) + 1.0 / (RRF_K + 1.0));
+        assert!((fused[1].score - expected).abs() < 1e-6);
+        // "a" and "d" were each first in one list
+        assert_eq!(fused[2].score, fused[3].score);
+    }
+
+    #[test]
+    fn rrf_of_no_results_is_empty() {
+        assert!(reciprocal_rank_fusion(Vec::new()).is_empty());
+        assert!(reciprocal_rank_fusion(vec![Vec::new(), Vec::new()]).is_empty());
+    }
+
+    #[test]
+    fn near_duplicates_are_suppressed_by_words_or_embeddings() {
+        let candidates = vec![
+            candidate("clause", "The waiting period for cataract surgery is 24 months.", Some(vec![1.0, 0.0]), 0.9),
+            // Same words, other case and punctuation
+            candidate("copy", "the waiting period for cataract surgery is 24 months", Some(vec![0.0, 1.0]), 0.8),
+            // Other words, nearly the same embedding
+            candidate("paraphrase", "Cataract operations are covered after two years.", Some(vec![0.99, 0.05]), 0.7),
+            candidate("maternity", "Maternity expenses are excluded.", Some(vec![0.0, 1.0]), 0.6),
+        ];
+
+        let kept = suppress_near_duplicates(candidates.clone(), DEFAULT_DUPLICATE_THRESHOLD, 10);
+        assert_eq!(ids(&kept), ["clause", "maternity"]);
+
+        // Nothing is similar enough to a threshold above 1
+        assert_eq!(suppress_near_duplicates(candidates, 1.1, 10).len(), 4);
+    }
+
+    #[test]
+    fn near_duplicate_suppression_stops_at_the_limit() {
+        let candidates = vec![
+            candidate("a", "grace period of thirty days", None, 0.9),
+            candidate("b", "room rent limited to one percent", None, 0.8),
+            candidate("c", "ambulance charges up to two thousand", None, 0.7),
+        ];
+
+        assert_eq!(ids(&suppress_near_duplicates(candidates, DEFAULT_DUPLICATE_THRESHOLD, 2)), ["a", "b"]);
+    }
+
+    #[test]
+    fn neighbor_windows_merge_transitively() {
+        let doc = document("a", &["c0", "c1", "c2", "c3", "c4", "c5", "c6", "c7"]);
+        // 0 and 4 are apart until 2 joins them both
+        let selected = [scored(&doc.chunks[0], 0.9), scored(&doc.chunks[4], 0.8), scored(&doc.chunks[2], 0.7)];
+
+        let expanded = expand_with_neighbors(&selected, std::slice::from_ref(&doc), 1);
+
+        assert_eq!(expanded.len(), 1);
+        assert_eq!(expanded[0].id, "a-0");
+        assert_eq!(&*expanded[0].content, "c0 c1 c2 c3 c4 c5");
+    }
+
+    #[test]
+    fn neighbor_windows_keep_rank_order_and_best_ranked_id() {
+        let doc = document("a", &["c0", "c1", "c2", "c3", "c4", "c5", "c6", "c7"]);
+        let selected = [scored(&doc.chunks[7], 0.9), scored(&doc.chunks[1], 0.8), scored(&doc.chunks[0], 0.7)];
+
+        let expanded = expand_with_neighbors(&selected, std::slice::from_ref(&doc), 1);
+
+        let ids: Vec<&str> = expanded.iter().map(|chunk| chunk.id.as_str()).collect();
+        assert_eq!(ids, ["a-7", "a-1"]);
+        assert_eq!(&*expanded[1].content, "c0 c1 c2");
+    }
+
+    #[test]
+    fn merge_overlapping_drops_the_repeated_overlap() {
+        assert_eq!(merge_overlapping("the waiting period is", "period is 36 months"), "the waiting period is 36 months");
+        // Multi-byte characters in the overlap
+        assert_eq!(merge_overlapping("cover of ₹5,00,000 per", "₹5,00,000 per year"), "cover of ₹5,00,000 per year");
+        // Too short to be chunker overlap
+        assert_eq!(merge_overlapping("sum is", "is due"), "sum is is due");
+    }
+}
@@ -0,0 +1,109 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryIntent {
+    // Needs retrieval over the indexed documents
+    DocumentQuestion,
+    // Greetings, thanks and questions about the assistant itself
+    SmallTalk,
+}
+
+// Longest query still considered small talk; anything longer is treated as a real question
+const MAX_SMALL_TALK_WORDS: usize = 8;
+
+const SMALL_TALK_PHRASES: &[&str] = &[
+    "hi", "hello", "hey", "hii", "namaste", "good morning", "good afternoon", "good evening",
+    "thanks", "thank you", "thank u", "thx", "ok", "okay", "cool", "great", "bye", "goodbye",
+    "who are you", "what are you", "what can you do", "how are you", "help",
+];
+
+// Words that may accompany small-talk phrases without asking anything ("thanks a lot",
+// "hello there"); anything else left over makes the message a document question
+const FILLER_WORDS: &[&str] = &[
+    "a", "lot", "so", "very", "much", "again", "there", "all", "everyone", "dear", "sir", "madam", "bot", "buddy",
+];
+
+// Words that signal a document question even inside an otherwise chatty message
+const DOMAIN_TERMS: &[&str] = &[
+    "policy", "cover", "covered", "coverage", "claim", "premium", "insur", "waiting", "period",
+    "surgery", "hospital", "treatment", "exclusion", "benefit", "sum insured", "deductible",
+    "maternity", "document", "clause", "grace",
+];
+
+// Cheap keyword heuristic: only short messages made of small-talk phrases and filler words,
+// without any policy vocabulary, are routed away from retrieval. Empty queries are not small
+// talk; QueryService refuses them.
+pub fn classify_query(query: &str) -> QueryIntent {
+    let normalized: String = query
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c.is_whitespace() { c } else { ' ' })
+        .collect();
+    let normalized = normalized.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    if normalized.is_empty() {
+        return QueryIntent::DocumentQuestion;
+    }
+
+    if normalized.split(' ').count() > MAX_SMALL_TALK_WORDS
+        || DOMAIN_TERMS.iter().any(|term| normalized.contains(term))
+    {
+        return QueryIntent::DocumentQuestion;
+    }
+
+    // Small talk only if nothing but filler is left once the small-talk phrases are removed,
+    // so "ok what about knee replacement" and "thanks, and AYUSH?" still go through retrieval
+    let mut phrases: Vec<&str> = SMALL_TALK_PHRASES.to_vec();
+    phrases.sort_by_key(|phrase| std::cmp::Reverse(phrase.len()));
+    let mut remaining = format!(" {} ", normalized);
+    let mut matched = false;
+    for phrase in phrases {
+        let needle = format!(" {} ", phrase);
+        while remaining.contains(&needle) {
+            remaining = remaining.replacen(&needle, " ", 1);
+            matched = true;
+        }
+    }
+
+    if matched && remaining.split_whitespace().all(|word| FILLER_WORDS.contains(&word)) {
+        QueryIntent::SmallTalk
+    } else {
+        QueryIntent::DocumentQuestion
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn greetings_and_thanks_are_small_talk() {
+        for query in ["hi", "Hello!", "thanks a lot", "Thank you so much", "good morning, dear", "ok thanks bye", "Who are you?"] {
+            assert_eq!(classify_query(query), QueryIntent::SmallTalk, "{:?}", query);
+        }
+    }
+
+    #[test]
+    fn small_talk_with_a_question_goes_to_retrieval() {
+        for query in [
+            "thanks, and AYUSH?",
+            "ok and dental?",
+            "hello, is IVF?",
+            "ok what about knee replacement",
+            "hi, what is the grace period?",
+            "help me understand the claim process",
+        ] {
+            assert_eq!(classify_query(query), QueryIntent::DocumentQuestion, "{:?}", query);
+        }
+    }
+
+    #[test]
+    fn empty_and_long_messages_are_document_questions() {
+        assert_eq!(classify_query(""), QueryIntent::DocumentQuestion);
+        assert_eq!(classify_query("  ?! "), QueryIntent::DocumentQuestion);
+        assert_eq!(classify_query("hi hi hi hi hi hi hi hi hi"), QueryIntent::DocumentQuestion);
+        // "hi" is not a whole word of "this"
+        assert_eq!(classify_query("this"), QueryIntent::DocumentQuestion);
+    }
+}
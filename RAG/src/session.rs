@@ -0,0 +1,187 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::RwLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+pub const DEFAULT_MAX_TURNS: usize = 6;
+// Sessions kept at once; creating one more drops the least recently used
+pub const DEFAULT_MAX_SESSIONS: usize = 10_000;
+// Sessions unused for this long are dropped
+pub const DEFAULT_SESSION_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationTurn {
+    pub question: String,
+    pub answer: String,
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub id: String,
+    // Who may use the session (e.g. a tenant and user); None for sessions created without one
+    #[serde(default)]
+    pub owner: Option<String>,
+    pub history: VecDeque<ConversationTurn>,
+    // Compressed summary of turns that no longer fit in `history`
+    pub summary: Option<String>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+impl Session {
+    fn new(id: String) -> Self {
+        let now = unix_timestamp();
+        Self {
+            id,
+            owner: None,
+            history: VecDeque::new(),
+            summary: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    // Renders the summary and recent turns for inclusion in a prompt
+    pub fn transcript(&self) -> String {
+        let mut transcript = String::new();
+
+        if let Some(summary) = &self.summary {
+            transcript.push_str(&format!("Summary of earlier conversation: {}\n\n", summary));
+        }
+
+        for turn in &self.history {
+            transcript.push_str(&format!("User: {}\nAssistant: {}\n\n", turn.question, turn.answer));
+        }
+
+        transcript
+    }
+}
+
+// In-memory session storage with a bounded number of turns per session and of sessions.
+// Sessions are only created by `create`/`create_for`; ids nobody created are not sessions.
+pub struct SessionStore {
+    sessions: RwLock<HashMap<String, Session>>,
+    max_turns: usize,
+    max_sessions: usize,
+    // None keeps sessions until they are evicted or deleted
+    ttl: Option<Duration>,
+}
+
+impl SessionStore {
+    pub fn new(max_turns: usize) -> Self {
+        Self {
+            sessions: RwLock::new(HashMap::new()),
+            max_turns: max_turns.max(1),
+            max_sessions: DEFAULT_MAX_SESSIONS,
+            ttl: Some(DEFAULT_SESSION_TTL),
+        }
+    }
+
+    pub fn with_max_turns(mut self, max_turns: usize) -> Self {
+        self.max_turns = max_turns.max(1);
+        self
+    }
+
+    // At most `max_sessions` sessions, each dropped after `ttl` without a turn
+    pub fn with_limits(mut self, max_sessions: usize, ttl: Option<Duration>) -> Self {
+        self.max_sessions = max_sessions.max(1);
+        self.ttl = ttl;
+        self
+    }
+
+    pub fn max_turns(&self) -> usize {
+        self.max_turns
+    }
+
+    pub fn create(&self) -> Session {
+        self.create_for(None)
+    }
+
+    pub fn create_for(&self, owner: Option<&str>) -> Session {
+        let mut session = Session::new(Uuid::new_v4().to_string());
+        session.owner = owner.map(str::to_string);
+        let mut sessions = self.sessions.write().unwrap();
+        self.remove_expired(&mut sessions);
+        while sessions.len() >= self.max_sessions {
+            let Some(oldest) = sessions.values().min_by_key(|session| session.updated_at).map(|session| session.id.clone()) else {
+                break;
+            };
+            tracing::debug!("Evicting least recently used session {}", oldest);
+            sessions.remove(&oldest);
+        }
+        sessions.insert(session.id.clone(), session.clone());
+        session
+    }
+
+    // The session with `id`, unless it expired
+    pub fn get(&self, id: &str) -> Option<Session> {
+        let sessions = self.sessions.read().unwrap();
+        sessions.get(id).filter(|session| !self.expired(session)).cloned()
+    }
+
+    pub fn list(&self) -> Vec<Session> {
+        let mut sessions = self.sessions.write().unwrap();
+        self.remove_expired(&mut sessions);
+        let mut sessions: Vec<Session> = sessions.values().cloned().collect();
+        sessions.sort_by_key(|s| std::cmp::Reverse(s.updated_at));
+        sessions
+    }
+
+    pub fn delete(&self, id: &str) -> bool {
+        self.sessions.write().unwrap().remove(id).is_some()
+    }
+
+    // Appends a turn and returns the turns evicted to stay within `max_turns`. Nothing is
+    // recorded for a session that was deleted or evicted meanwhile.
+    pub fn record_turn(&self, id: &str, question: &str, answer: &str) -> Vec<ConversationTurn> {
+        let mut sessions = self.sessions.write().unwrap();
+        let Some(session) = sessions.get_mut(id) else {
+            return Vec::new();
+        };
+
+        let now = unix_timestamp();
+        session.history.push_back(ConversationTurn {
+            question: question.to_string(),
+            answer: answer.to_string(),
+            timestamp: now,
+        });
+        session.updated_at = now;
+
+        let mut evicted = Vec::new();
+        while session.history.len() > self.max_turns {
+            if let Some(turn) = session.history.pop_front() {
+                evicted.push(turn);
+            }
+        }
+        evicted
+    }
+
+    pub fn set_summary(&self, id: &str, summary: String) {
+        if let Some(session) = self.sessions.write().unwrap().get_mut(id) {
+            session.summary = Some(summary);
+        }
+    }
+
+    fn expired(&self, session: &Session) -> bool {
+        self.ttl.is_some_and(|ttl| unix_timestamp().saturating_sub(session.updated_at) >= ttl.as_secs())
+    }
+
+    fn remove_expired(&self, sessions: &mut HashMap<String, Session>) {
+        sessions.retain(|_, session| !self.expired(session));
+    }
+}
+
+impl Default for SessionStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_TURNS)
+    }
+}
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
@@ -0,0 +1,205 @@
+use crate::encryption::{self, StateCipher};
+use crate::error::Result;
+use crate::models::Document;
+use serde::de::{self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::ser::{self, SerializeSeq, Serializer};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Layout version of saved snapshots; snapshots of another version are treated as stale
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+// File name of the snapshot kept in the state directory
+pub const SNAPSHOT_FILE: &str = "index.json";
+
+// Everything needed to answer from an index without ingesting and embedding it again
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub version: u32,
+    // See config_fingerprint
+    pub config_fingerprint: String,
+    // Unix timestamp (seconds)
+    pub saved_at: u64,
+    // With their chunks and embeddings
+    pub documents: Vec<Document>,
+    // Corpus statistics of the embedding provider, e.g. the TF-IDF vocabulary and IDF scores
+    pub embedding_state: Option<serde_json::Value>,
+}
+
+// Outcome of RagLibrary::load
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnapshotStatus {
+    Loaded { documents: usize },
+    Missing,
+    // Not loaded because it no longer matches the sources or settings; says what changed
+    Stale(String),
+}
+
+// The layout Snapshot is saved in; its documents are serialized one by one
+#[derive(Serialize)]
+#[serde(bound = "Prepared<'a, F>: Serialize")]
+struct SnapshotFile<'a, F> {
+    version: u32,
+    config_fingerprint: &'a str,
+    saved_at: u64,
+    documents: Prepared<'a, F>,
+    embedding_state: Option<serde_json::Value>,
+}
+
+struct Prepared<'a, F> {
+    documents: &'a [Document],
+    prepare: F,
+}
+
+impl<F: Fn(&Document) -> Result<Cow<'_, Document>>> Serialize for Prepared<'_, F> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.documents.len()))?;
+        for document in self.documents {
+            let document = (self.prepare)(document).map_err(ser::Error::custom)?;
+            seq.serialize_element(&*document)?;
+        }
+        seq.end()
+    }
+}
+
+impl Snapshot {
+    // Writes a snapshot of `documents` to a temporary file next to `path` and renames it into
+    // place, so a crash while saving leaves the previous snapshot intact. Each document goes
+    // through `prepare` (e.g. to put back vectors kept in a VectorTier) just before it is
+    // written, so the set is never copied as a whole. With a cipher the file is encrypted.
+    pub fn write<F>(
+        path: &Path,
+        cipher: Option<&StateCipher>,
+        config_fingerprint: &str,
+        documents: &[Document],
+        embedding_state: Option<serde_json::Value>,
+        prepare: F,
+    ) -> Result<()>
+    where
+        F: Fn(&Document) -> Result<Cow<'_, Document>>,
+    {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let saved_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let snapshot = SnapshotFile {
+            version: SNAPSHOT_VERSION,
+            config_fingerprint,
+            saved_at,
+            documents: Prepared { documents, prepare },
+            embedding_state,
+        };
+        let temp = path.with_extension("json.tmp");
+        let mut file = encryption::writer(cipher, BufWriter::new(File::create(&temp)?))?;
+        serde_json::to_writer(&mut file, &snapshot).map_err(io::Error::from)?;
+        file.finish()?.flush()?;
+        fs::rename(&temp, path)?;
+        Ok(())
+    }
+
+    // None when there is no snapshot at `path`. An encrypted snapshot needs the cipher it was
+    // written with; one written without a cipher is read either way.
+    pub fn read(path: &Path, cipher: Option<&StateCipher>) -> Result<Option<Self>> {
+        Self::read_with(path, cipher, |_| {})
+    }
+
+    // Like read, passing every document to `each` as soon as it is parsed, e.g. to move its
+    // vectors into a VectorTier before the next one is read
+    pub fn read_with(path: &Path, cipher: Option<&StateCipher>, each: impl FnMut(&mut Document)) -> Result<Option<Self>> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let mut deserializer = serde_json::Deserializer::from_reader(BufReader::new(encryption::reader(cipher, file)?));
+        let snapshot = SnapshotSeed(each).deserialize(&mut deserializer).map_err(io::Error::from)?;
+        deserializer.end().map_err(io::Error::from)?;
+        Ok(Some(snapshot))
+    }
+}
+
+struct SnapshotSeed<F>(F);
+
+impl<'de, F: FnMut(&mut Document)> DeserializeSeed<'de> for SnapshotSeed<F> {
+    type Value = Snapshot;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> std::result::Result<Snapshot, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, F: FnMut(&mut Document)> Visitor<'de> for SnapshotSeed<F> {
+    type Value = Snapshot;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an index snapshot")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(mut self, mut map: A) -> std::result::Result<Snapshot, A::Error> {
+        let (mut version, mut config_fingerprint, mut saved_at, mut documents, mut embedding_state) =
+            (None, None, None, None, None);
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "version" => version = Some(map.next_value()?),
+                "config_fingerprint" => config_fingerprint = Some(map.next_value()?),
+                "saved_at" => saved_at = Some(map.next_value()?),
+                "documents" => documents = Some(map.next_value_seed(DocumentsSeed(&mut self.0))?),
+                "embedding_state" => embedding_state = map.next_value()?,
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(Snapshot {
+            version: version.ok_or_else(|| de::Error::missing_field("version"))?,
+            config_fingerprint: config_fingerprint.ok_or_else(|| de::Error::missing_field("config_fingerprint"))?,
+            saved_at: saved_at.ok_or_else(|| de::Error::missing_field("saved_at"))?,
+            documents: documents.ok_or_else(|| de::Error::missing_field("documents"))?,
+            embedding_state,
+        })
+    }
+}
+
+struct DocumentsSeed<'a, F>(&'a mut F);
+
+impl<'de, F: FnMut(&mut Document)> DeserializeSeed<'de> for DocumentsSeed<'_, F> {
+    type Value = Vec<Document>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> std::result::Result<Vec<Document>, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, F: FnMut(&mut Document)> Visitor<'de> for DocumentsSeed<'_, F> {
+    type Value = Vec<Document>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a list of documents")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Vec<Document>, A::Error> {
+        let mut documents = Vec::new();
+        while let Some(mut document) = seq.next_element::<Document>()? {
+            (self.0)(&mut document);
+            documents.push(document);
+        }
+        Ok(documents)
+    }
+}
+
+// Hash of the settings that shape the saved chunks and embeddings: the chunking and the kind
+// of embedding provider. A snapshot taken with other settings is stale.
+pub fn config_fingerprint(chunk_size: usize, chunk_overlap: usize, embedding_kind: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{}|{}|{}", chunk_size, chunk_overlap, embedding_kind));
+    hex::encode(hasher.finalize())
+}
@@ -0,0 +1,261 @@
+use crate::cache::next_collection_version;
+use crate::clauses::ClauseExtractor;
+use crate::entities;
+use crate::error::{RagError, Result};
+use crate::models::Document;
+use crate::providers::EmbeddingProvider;
+use crate::vector_tier::{TieredEmbeddingProvider, VectorPartition, VectorTier};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use tracing::{info_span, Instrument, Span};
+
+// A set of documents together with the embedding provider fitted to them. With a provider
+// whose corpus statistics (e.g. the TF-IDF vocabulary) depend on every document, all of it is
+// embedded again whenever the set changes; otherwise only added documents are embedded.
+// Stored chunks are tagged with the entities they mention (see entities::enrich).
+pub struct DocumentStore {
+    documents: RwLock<Vec<Document>>,
+    // Changes whenever the documents may have, including their access lists; see generation()
+    generation: AtomicU64,
+    embeddings: Arc<dyn EmbeddingProvider>,
+    // Holds the chunk vectors instead of the documents when set
+    vectors: Option<Arc<VectorPartition>>,
+    // Extracts the clauses of added documents when set
+    clauses: Option<Arc<ClauseExtractor>>,
+}
+
+impl DocumentStore {
+    pub fn new(embeddings: Arc<dyn EmbeddingProvider>) -> Self {
+        Self {
+            documents: RwLock::new(Vec::new()),
+            generation: AtomicU64::new(next_collection_version()),
+            embeddings,
+            vectors: None,
+            clauses: None,
+        }
+    }
+
+    // Keeps the chunk vectors in `tier` rather than in the documents, so the store's documents
+    // come without them; retrieval reads them through `embeddings()`, and restore_vectors puts
+    // them back into copies that need them
+    pub fn with_vector_tier(embeddings: Arc<dyn EmbeddingProvider>, tier: &Arc<VectorTier>) -> Self {
+        let vectors = Arc::new(tier.partition());
+        let embeddings = Arc::new(TieredEmbeddingProvider::new(embeddings, vectors.clone()));
+        Self {
+            documents: RwLock::new(Vec::new()),
+            generation: AtomicU64::new(next_collection_version()),
+            embeddings,
+            vectors: Some(vectors),
+            clauses: None,
+        }
+    }
+
+    // Extracts the clauses of documents as they are added or rebuilt, before they are embedded
+    pub fn with_clause_extractor(mut self, extractor: Option<Arc<ClauseExtractor>>) -> Self {
+        self.clauses = extractor;
+        self
+    }
+
+    // The documents, for answering or listing; writers wait while this is held
+    pub async fn read(&self) -> RwLockReadGuard<'_, Vec<Document>> {
+        self.documents.read().await
+    }
+
+    // Direct access for changes made in several steps; the caller is responsible for
+    // embedding the result, e.g. with `embeddings().generate_embeddings`
+    pub async fn write(&self) -> RwLockWriteGuard<'_, Vec<Document>> {
+        let documents = self.documents.write().await;
+        self.bump_generation();
+        documents
+    }
+
+    // Version of the documents, for keying cached answers (QueryRequest::collection_version).
+    // Every add, removal, rebuild and write() moves it on, so an access list edited or a
+    // document purged through write() counts as a change too. Read it while holding read() to
+    // get the version of exactly the documents being answered from.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    // Called with the write lock held, so readers never see new documents under an old version
+    fn bump_generation(&self) {
+        self.generation.store(next_collection_version(), Ordering::Release);
+    }
+
+    pub fn embeddings(&self) -> &Arc<dyn EmbeddingProvider> {
+        &self.embeddings
+    }
+
+    pub async fn len(&self) -> usize {
+        self.documents.read().await.len()
+    }
+
+    pub async fn chunk_count(&self) -> usize {
+        self.documents.read().await.iter().map(|doc| doc.chunks.len()).sum()
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.documents.read().await.is_empty()
+    }
+
+    // Swaps in `documents`, which must already be embedded with this store's provider
+    pub async fn replace(&self, documents: Vec<Document>) {
+        let mut current = self.documents.write().await;
+        self.swap_in(&mut current, documents);
+    }
+
+    // Puts the vectors a vector tier holds back into `documents`, e.g. copies about to be saved
+    pub fn restore_vectors(&self, documents: &mut [Document]) -> Result<()> {
+        if let Some(vectors) = &self.vectors {
+            for document in documents {
+                vectors.restore(document)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Moves the vectors of `document` into the vector tier, if there is one. If they cannot be
+    // written they stay in its chunks, which retrieval uses as well.
+    pub(crate) fn offload(&self, document: &mut Document) {
+        if let Some(vectors) = &self.vectors {
+            if let Err(e) = vectors.offload(document) {
+                tracing::warn!("Keeping the vectors of document {} in memory: {}", document.id, e);
+            }
+        }
+    }
+
+    // Deletes what the vector tier holds for the document with id `id`
+    pub(crate) fn forget_vectors(&self, id: &str) {
+        if let Some(vectors) = &self.vectors {
+            vectors.remove(id);
+        }
+    }
+
+    // Replaces `current`, which is this store's locked set, with `documents`
+    pub(crate) fn swap_in(&self, current: &mut Vec<Document>, mut documents: Vec<Document>) {
+        entities::enrich(&mut documents);
+        if self.vectors.is_some() {
+            documents.iter_mut().for_each(|document| self.offload(document));
+            let kept: HashSet<&str> = documents.iter().map(|doc| doc.id.as_str()).collect();
+            for document in current.iter().filter(|doc| !kept.contains(doc.id.as_str())) {
+                self.forget_vectors(&document.id);
+            }
+        }
+        *current = documents;
+        self.bump_generation();
+    }
+
+    // Adds `documents` and embeds them (re-embedding the whole set if the provider needs
+    // it). On failure the store is left as it was. Returns the number of documents now stored.
+    pub async fn add(&self, mut added: Vec<Document>) -> Result<usize> {
+        self.extract_clauses(&mut added).await;
+        entities::enrich(&mut added);
+        let mut documents = self.documents.write().await;
+        let first_added = documents.len();
+        documents.extend(added);
+        let embedded = match self.embeddings.fitted_to_corpus() {
+            true => &mut documents[..],
+            false => &mut documents[first_added..],
+        };
+        let span = embedding_span(embedded);
+        if let Err(e) = self.embeddings.generate_embeddings(embedded).instrument(span).await {
+            documents.truncate(first_added);
+            return Err(e);
+        }
+        embedded.iter_mut().for_each(|document| self.offload(document));
+        self.bump_generation();
+        Ok(documents.len())
+    }
+
+    // Removes the document with id `id`, re-embedding the rest if the provider needs it, and
+    // returns it. On failure the store is left as it was.
+    pub async fn remove(&self, id: &str) -> Result<Document> {
+        let mut documents = self.documents.write().await;
+        let position = documents
+            .iter()
+            .position(|doc| doc.id == id)
+            .ok_or_else(|| RagError::NotFound(format!("document {}", id)))?;
+        let removed = documents.remove(position);
+        if self.embeddings.fitted_to_corpus() {
+            let span = embedding_span(&documents);
+            if let Err(e) = self.embeddings.generate_embeddings(&mut documents).instrument(span).await {
+                documents.insert(position, removed);
+                return Err(e);
+            }
+            documents.iter_mut().for_each(|document| self.offload(document));
+        }
+        self.forget_vectors(id);
+        self.bump_generation();
+        Ok(removed)
+    }
+
+    // Embeds `documents` as a new set and swaps it in; the current set stays in place, and
+    // keeps answering, until the new one is ready
+    pub async fn rebuild(&self, mut documents: Vec<Document>) -> Result<usize> {
+        self.extract_clauses(&mut documents).await;
+        let span = embedding_span(&documents);
+        self.embeddings.generate_embeddings(&mut documents).instrument(span).await?;
+        let count = documents.len();
+        self.replace(documents).await;
+        Ok(count)
+    }
+
+    // Outside the lock, so answering goes on while the LLM is asked for clauses
+    async fn extract_clauses(&self, documents: &mut [Document]) {
+        if let Some(extractor) = &self.clauses {
+            extractor.extract(documents).await;
+        }
+    }
+}
+
+fn embedding_span(documents: &[Document]) -> Span {
+    let chunk_count: usize = documents.iter().map(|doc| doc.chunks.len()).sum();
+    info_span!("embedding", kind = "store", documents = documents.len(), chunk_count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockEmbeddingProvider;
+    use crate::models::DocumentMetadata;
+
+    fn document(id: &str) -> Document {
+        Document {
+            id: id.to_string(),
+            filename: format!("{}.pdf", id),
+            content: "Cataract surgery has a waiting period of 24 months.".into(),
+            chunks: Vec::new(),
+            metadata: DocumentMetadata::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn generation_moves_on_with_every_change() {
+        let store = DocumentStore::new(Arc::new(MockEmbeddingProvider::new()));
+        let mut seen = vec![store.generation()];
+
+        store.add(vec![document("a"), document("b")]).await.unwrap();
+        seen.push(store.generation());
+        drop(store.read().await);
+        assert_eq!(store.generation(), *seen.last().unwrap(), "reading is not a change");
+
+        store.write().await[0].metadata.access = None;
+        seen.push(store.generation());
+        store.remove("a").await.unwrap();
+        seen.push(store.generation());
+        store.rebuild(vec![document("c")]).await.unwrap();
+        seen.push(store.generation());
+
+        let distinct: HashSet<u64> = seen.iter().copied().collect();
+        assert_eq!(distinct.len(), seen.len());
+    }
+
+    #[test]
+    fn stores_never_share_a_generation() {
+        let first = DocumentStore::new(Arc::new(MockEmbeddingProvider::new()));
+        let second = DocumentStore::new(Arc::new(MockEmbeddingProvider::new()));
+        assert_ne!(first.generation(), second.generation());
+    }
+}
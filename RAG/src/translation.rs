@@ -0,0 +1,63 @@
+use crate::language::detect_language;
+use crate::models::Document;
+use crate::prompt::build_translation_prompt;
+use crate::providers::{LlmProvider, TranslationProvider};
+use crate::usage;
+use crate::error::Result;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// Characters of each document inspected when detecting the corpus language
+const LANGUAGE_SAMPLE_CHARS: usize = 2000;
+
+// Translation through the configured LLM
+pub struct LlmTranslator {
+    llm: Arc<dyn LlmProvider>,
+}
+
+impl LlmTranslator {
+    pub fn new(llm: Arc<dyn LlmProvider>) -> Self {
+        Self { llm }
+    }
+}
+
+#[async_trait]
+impl TranslationProvider for LlmTranslator {
+    async fn translate(&self, text: &str, target_language: &str) -> Result<String> {
+        let prompt = build_translation_prompt(text, target_language);
+        let output = self.llm.generate(&prompt).await?;
+        // Runs in the caller's "llm" span
+        usage::record(&tracing::Span::current(), &prompt, &output);
+        Ok(output
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .unwrap_or_default()
+            .trim_matches('"')
+            .to_string())
+    }
+}
+
+// Majority language of `documents`, detected from the start of each document
+pub fn corpus_language(documents: &[&Document]) -> &'static str {
+    let mut votes: HashMap<&'static str, usize> = HashMap::new();
+    for doc in documents {
+        let sample: String = doc.content.chars().take(LANGUAGE_SAMPLE_CHARS).collect();
+        let sample = if sample.trim().is_empty() {
+            // Documents built without their full text still have chunks
+            doc.chunks.first().map(|c| c.content.to_string()).unwrap_or_default()
+        } else {
+            sample
+        };
+        if !sample.trim().is_empty() {
+            *votes.entry(detect_language(&sample)).or_default() += 1;
+        }
+    }
+
+    votes
+        .into_iter()
+        .max_by_key(|(_, count)| *count)
+        .map(|(language, _)| language)
+        .unwrap_or(crate::language::DEFAULT_LANGUAGE)
+}
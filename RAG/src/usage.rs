@@ -0,0 +1,69 @@
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::ops::AddAssign;
+use std::sync::{Arc, Mutex};
+use tracing::field::Empty;
+use tracing::{info_span, Span};
+
+use crate::error::RagError;
+use crate::prompt::estimate_tokens;
+
+// LLM work done on behalf of one caller. Token counts are estimates (see estimate_tokens)
+// since not every provider reports usage.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct TokenUsage {
+    pub llm_calls: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+impl TokenUsage {
+    pub fn total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+}
+
+impl AddAssign for TokenUsage {
+    fn add_assign(&mut self, other: Self) {
+        self.llm_calls += other.llm_calls;
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+    }
+}
+
+tokio::task_local! {
+    static CURRENT: Arc<Mutex<TokenUsage>>;
+}
+
+// Runs `future` and returns what its LLM calls consumed. Calls made from tasks it spawns
+// are not counted; wrap those separately.
+pub async fn track<F: Future>(future: F) -> (F::Output, TokenUsage) {
+    let usage = Arc::new(Mutex::new(TokenUsage::default()));
+    let output = CURRENT.scope(usage.clone(), future).await;
+    let total = *usage.lock().unwrap();
+    (output, total)
+}
+
+// Span for one LLM call, so each phase's latency shows up separately in traces. `record`
+// fills in the token counts.
+pub(crate) fn llm_span(phase: &'static str) -> Span {
+    info_span!("llm", phase, streaming = Empty, prompt_tokens = Empty, completion_tokens = Empty, error = Empty)
+}
+
+// Marks the call's `span` as failed
+pub(crate) fn record_error(span: &Span, error: &RagError) {
+    span.record("error", tracing::field::display(error));
+}
+
+// Counts one LLM call against the enclosing `track`, if any, and on the call's `span`
+pub(crate) fn record(span: &Span, prompt: &str, completion: &str) {
+    let usage = TokenUsage {
+        llm_calls: 1,
+        prompt_tokens: estimate_tokens(prompt) as u64,
+        completion_tokens: estimate_tokens(completion) as u64,
+    };
+    span.record("prompt_tokens", usage.prompt_tokens);
+    span.record("completion_tokens", usage.completion_tokens);
+    let _ = CURRENT.try_with(|total| *total.lock().unwrap() += usage);
+}
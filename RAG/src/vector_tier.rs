@@ -0,0 +1,322 @@
+use crate::encryption::{self, StateCipher};
+use crate::error::Result;
+use crate::models::Document;
+use crate::providers::EmbeddingProvider;
+use async_trait::async_trait;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+// Name of the directory, under the spill directory, that holds the spilled vectors
+pub const SPILL_DIR: &str = "spilled-vectors";
+
+// The vectors of one document's chunks, in chunk order; None for a chunk without one
+pub type ChunkVectors = Vec<Option<Vec<f32>>>;
+
+// Keeps chunk vectors out of the documents, so a large index fits a memory budget. Every
+// document's vectors are written to a file in `dir`; the most recently used are also kept in
+// memory, up to `budget_bytes` for all stores together, and the rest are read back from disk
+// when a query needs them.
+pub struct VectorTier {
+    dir: PathBuf,
+    budget_bytes: usize,
+    cache: Mutex<Cache>,
+    next_partition: AtomicU64,
+    // Encrypts the spilled files; see with_cipher
+    cipher: Option<StateCipher>,
+}
+
+// Vectors in memory, keyed by partition and document id
+#[derive(Default)]
+struct Cache {
+    entries: HashMap<(u64, String), CacheEntry>,
+    // Keys by last use, least recent first
+    recency: BTreeMap<u64, (u64, String)>,
+    clock: u64,
+    bytes: usize,
+}
+
+struct CacheEntry {
+    vectors: Arc<ChunkVectors>,
+    last_used: u64,
+    bytes: usize,
+}
+
+impl Cache {
+    fn get(&mut self, key: &(u64, String)) -> Option<Arc<ChunkVectors>> {
+        let entry = self.entries.get_mut(key)?;
+        self.recency.remove(&entry.last_used);
+        self.clock += 1;
+        entry.last_used = self.clock;
+        self.recency.insert(self.clock, key.clone());
+        Some(entry.vectors.clone())
+    }
+
+    // Evicts the least recently used vectors until these fit; vectors larger than the whole
+    // budget are not kept
+    fn insert(&mut self, key: (u64, String), vectors: Arc<ChunkVectors>, budget_bytes: usize) {
+        self.remove(&key);
+        let bytes = vector_bytes(&vectors);
+        if bytes > budget_bytes {
+            return;
+        }
+        while self.bytes + bytes > budget_bytes {
+            let Some((_, oldest)) = self.recency.pop_first() else { break };
+            if let Some(entry) = self.entries.remove(&oldest) {
+                self.bytes -= entry.bytes;
+            }
+        }
+        self.clock += 1;
+        self.recency.insert(self.clock, key.clone());
+        self.entries.insert(key, CacheEntry { vectors, last_used: self.clock, bytes });
+        self.bytes += bytes;
+    }
+
+    fn remove(&mut self, key: &(u64, String)) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.recency.remove(&entry.last_used);
+            self.bytes -= entry.bytes;
+        }
+    }
+
+    fn remove_partition(&mut self, partition: u64) {
+        let keys: Vec<_> = self.entries.keys().filter(|(p, _)| *p == partition).cloned().collect();
+        for key in keys {
+            self.remove(&key);
+        }
+    }
+}
+
+// How much of its budget a VectorTier uses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VectorTierUsage {
+    pub cached_bytes: usize,
+    pub cached_documents: usize,
+    pub budget_bytes: usize,
+}
+
+impl VectorTier {
+    // Spills to `dir`, which is emptied first: it only ever holds this process's vectors
+    pub fn new(dir: impl Into<PathBuf>, budget_bytes: usize) -> Result<Self> {
+        let dir = dir.into();
+        match fs::remove_dir_all(&dir) {
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            budget_bytes,
+            cache: Mutex::new(Cache::default()),
+            next_partition: AtomicU64::new(0),
+            cipher: None,
+        })
+    }
+
+    // Encrypts the vectors written to disk, which may be enough to reconstruct the text
+    pub fn with_cipher(mut self, cipher: Option<StateCipher>) -> Self {
+        self.cipher = cipher;
+        self
+    }
+
+    // A section of the tier for one store's documents, so stores holding documents with the
+    // same id do not overwrite each other's vectors. Its vectors are deleted when it is dropped.
+    pub fn partition(self: &Arc<Self>) -> VectorPartition {
+        let id = self.next_partition.fetch_add(1, Ordering::Relaxed);
+        VectorPartition { tier: self.clone(), id, dir: self.dir.join(id.to_string()) }
+    }
+
+    pub fn usage(&self) -> VectorTierUsage {
+        let cache = self.cache.lock().unwrap();
+        VectorTierUsage { cached_bytes: cache.bytes, cached_documents: cache.entries.len(), budget_bytes: self.budget_bytes }
+    }
+}
+
+impl Drop for VectorTier {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+// One store's documents in a VectorTier (see VectorTier::partition)
+pub struct VectorPartition {
+    tier: Arc<VectorTier>,
+    id: u64,
+    dir: PathBuf,
+}
+
+impl VectorPartition {
+    // Moves the vectors of `document`'s chunks into the tier, replacing any it held for the
+    // document before. A document without vectors is left alone, and so is one whose vectors
+    // could not be written: they stay in its chunks.
+    pub fn offload(&self, document: &mut Document) -> Result<()> {
+        if document.chunks.iter().all(|chunk| chunk.embedding.is_none()) {
+            return Ok(());
+        }
+        let vectors: ChunkVectors = document.chunks.iter_mut().map(|chunk| chunk.embedding.take()).collect();
+        if let Err(e) = write_vectors(&self.dir, &self.path(&document.id), self.tier.cipher.as_ref(), &vectors) {
+            for (chunk, vector) in document.chunks.iter_mut().zip(vectors) {
+                chunk.embedding = vector;
+            }
+            return Err(e.into());
+        }
+        let key = (self.id, document.id.clone());
+        self.tier.cache.lock().unwrap().insert(key, Arc::new(vectors), self.tier.budget_bytes);
+        Ok(())
+    }
+
+    // The vectors of the document with id `id`, read back from disk unless they are in memory;
+    // None when the tier holds none for it
+    pub fn vectors(&self, id: &str) -> Result<Option<Arc<ChunkVectors>>> {
+        let key = (self.id, id.to_string());
+        if let Some(vectors) = self.tier.cache.lock().unwrap().get(&key) {
+            return Ok(Some(vectors));
+        }
+        let vectors = match read_vectors(&self.path(id), self.tier.cipher.as_ref()) {
+            Ok(vectors) => Arc::new(vectors),
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        self.tier.cache.lock().unwrap().insert(key, vectors.clone(), self.tier.budget_bytes);
+        Ok(Some(vectors))
+    }
+
+    // Puts the vectors the tier holds for `document` back into its chunks
+    pub fn restore(&self, document: &mut Document) -> Result<()> {
+        if let Some(vectors) = self.vectors(&document.id)? {
+            for (chunk, vector) in document.chunks.iter_mut().zip(vectors.iter()) {
+                if chunk.embedding.is_none() {
+                    chunk.embedding = vector.clone();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Deletes the vectors of the document with id `id`
+    pub fn remove(&self, id: &str) {
+        self.tier.cache.lock().unwrap().remove(&(self.id, id.to_string()));
+        match fs::remove_file(self.path(id)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => {
+                tracing::warn!("Failed to delete the spilled vectors of document {}: {}", id, e)
+            }
+            _ => {}
+        }
+    }
+
+    // Document ids are hex-encoded, as they may come from clients
+    fn path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.vec", hex::encode(id)))
+    }
+}
+
+impl Drop for VectorPartition {
+    fn drop(&mut self) {
+        self.tier.cache.lock().unwrap().remove_partition(self.id);
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn vector_bytes(vectors: &ChunkVectors) -> usize {
+    vectors.iter().flatten().map(|vector| vector.len() * size_of::<f32>()).sum()
+}
+
+// Little-endian: the number of chunks, then for each the vector length (u32::MAX for none)
+// and its values. Written next to `path` and renamed into place, so readers never see half
+// a file.
+fn write_vectors(dir: &Path, path: &Path, cipher: Option<&StateCipher>, vectors: &ChunkVectors) -> io::Result<()> {
+    let mut bytes = Vec::with_capacity(4 + vectors.len() * 4 + vector_bytes(vectors));
+    bytes.extend_from_slice(&(vectors.len() as u32).to_le_bytes());
+    for vector in vectors {
+        match vector {
+            Some(vector) => {
+                bytes.extend_from_slice(&(vector.len() as u32).to_le_bytes());
+                for value in vector {
+                    bytes.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+            None => bytes.extend_from_slice(&u32::MAX.to_le_bytes()),
+        }
+    }
+    fs::create_dir_all(dir)?;
+    let temp = path.with_extension("vec.tmp");
+    let mut file = encryption::writer(cipher, File::create(&temp)?)?;
+    file.write_all(&bytes)?;
+    file.finish()?;
+    fs::rename(&temp, path)
+}
+
+fn read_vectors(path: &Path, cipher: Option<&StateCipher>) -> io::Result<ChunkVectors> {
+    let mut bytes = Vec::new();
+    encryption::reader(cipher, File::open(path)?)?.read_to_end(&mut bytes)?;
+    let mut rest = bytes.as_slice();
+    let mut next_u32 = || -> io::Result<u32> {
+        let (value, tail) = rest
+            .split_first_chunk::<4>()
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, format!("{} is truncated", path.display())))?;
+        rest = tail;
+        Ok(u32::from_le_bytes(*value))
+    };
+    let count = next_u32()?;
+    let mut vectors = Vec::new();
+    for _ in 0..count {
+        let vector = match next_u32()? {
+            u32::MAX => None,
+            len => Some((0..len).map(|_| next_u32().map(f32::from_bits)).collect::<io::Result<Vec<f32>>>()?),
+        };
+        vectors.push(vector);
+    }
+    Ok(vectors)
+}
+
+// Gives retrieval the vectors a store keeps in a VectorTier (see
+// EmbeddingProvider::stored_vectors); everything else is passed to the wrapped provider
+pub struct TieredEmbeddingProvider {
+    inner: Arc<dyn EmbeddingProvider>,
+    vectors: Arc<VectorPartition>,
+}
+
+impl TieredEmbeddingProvider {
+    pub fn new(inner: Arc<dyn EmbeddingProvider>, vectors: Arc<VectorPartition>) -> Self {
+        Self { inner, vectors }
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for TieredEmbeddingProvider {
+    async fn generate_embeddings(&self, documents: &mut [Document]) -> Result<()> {
+        self.inner.generate_embeddings(documents).await
+    }
+
+    async fn embed_query(&self, query: &str) -> Result<Vec<f32>> {
+        self.inner.embed_query(query).await
+    }
+
+    fn fitted_to_corpus(&self) -> bool {
+        self.inner.fitted_to_corpus()
+    }
+
+    // The wrapped provider's, so snapshots stay valid with or without a tier
+    fn kind(&self) -> String {
+        self.inner.kind()
+    }
+
+    fn fitted_state(&self) -> Option<serde_json::Value> {
+        self.inner.fitted_state()
+    }
+
+    fn restore_fitted_state(&self, state: serde_json::Value) -> Result<()> {
+        self.inner.restore_fitted_state(state)
+    }
+
+    fn calculate_similarity(&self, embedding1: &[f32], embedding2: &[f32]) -> f32 {
+        self.inner.calculate_similarity(embedding1, embedding2)
+    }
+
+    fn stored_vectors(&self, document: &Document) -> Result<Option<Arc<ChunkVectors>>> {
+        self.vectors.vectors(&document.id)
+    }
+}
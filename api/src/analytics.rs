@@ -0,0 +1,211 @@
+use rag_system::cache::CacheStats;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::audit::AuditEntry;
+use crate::usage::UsageWindow;
+
+// Status of questions the system declined to answer from the documents
+const ABSTAINED_STATUS: &str = "insufficient_information";
+const FAILED_STATUS: &str = "error";
+
+const DEFAULT_TOP_DOCUMENTS: usize = 10;
+// Bounds on the volume series, so a tiny interval cannot make the response huge
+const MIN_INTERVAL_SECS: u64 = 60;
+const MAX_INTERVALS: u64 = 1000;
+
+// Query parameters of GET /admin/analytics
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AnalyticsQuery {
+    // How far back to look (hour, day, week or month; default day)
+    pub window: Option<UsageWindow>,
+    // Only this tenant's questions
+    pub tenant: Option<String>,
+    // Length of the volume intervals in seconds; by default 5 minutes for an hour, an hour for
+    // a day and a day for longer windows
+    pub interval_secs: Option<u64>,
+    // How many of the most retrieved documents to list (default 10)
+    pub top: Option<usize>,
+}
+
+impl AnalyticsQuery {
+    // The interval to use, or why the requested one cannot be
+    pub fn interval_secs(&self) -> Result<u64, String> {
+        let window = self.window.unwrap_or_default();
+        let interval = self.interval_secs.unwrap_or(match window {
+            UsageWindow::Hour => 5 * 60,
+            UsageWindow::Day => 60 * 60,
+            UsageWindow::Week | UsageWindow::Month => 24 * 60 * 60,
+        });
+        if interval < MIN_INTERVAL_SECS {
+            return Err(format!("interval_secs must be at least {}", MIN_INTERVAL_SECS));
+        }
+        if window.secs().div_ceil(interval) > MAX_INTERVALS {
+            return Err(format!("interval_secs would split the window into more than {} intervals", MAX_INTERVALS));
+        }
+        Ok(interval)
+    }
+}
+
+// Requests and questions in one interval
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct VolumeInterval {
+    // Unix timestamp the interval starts at
+    pub start: u64,
+    pub requests: u64,
+    pub questions: u64,
+}
+
+// Nearest-rank percentiles of the time taken to answer, over the questions it was recorded for
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct LatencyPercentiles {
+    pub samples: usize,
+    pub p50_ms: Option<u64>,
+    pub p90_ms: Option<u64>,
+    pub p95_ms: Option<u64>,
+    pub p99_ms: Option<u64>,
+    pub max_ms: Option<u64>,
+}
+
+impl LatencyPercentiles {
+    fn new(mut latencies: Vec<u64>) -> Self {
+        latencies.sort_unstable();
+        Self {
+            samples: latencies.len(),
+            p50_ms: percentile(&latencies, 50),
+            p90_ms: percentile(&latencies, 90),
+            p95_ms: percentile(&latencies, 95),
+            p99_ms: percentile(&latencies, 99),
+            max_ms: latencies.last().copied(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct DocumentRetrievals {
+    pub document: String,
+    // Answers citing chunks of the document
+    pub retrievals: u64,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct CacheHitRate {
+    #[serde(flatten)]
+    pub stats: CacheStats,
+    // None before the first lookup
+    pub hit_rate: Option<f64>,
+}
+
+impl From<CacheStats> for CacheHitRate {
+    fn from(stats: CacheStats) -> Self {
+        Self { stats, hit_rate: stats.hit_rate() }
+    }
+}
+
+// Lookups since the api started, all tenants together; absent for disabled caches
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct CacheHitRates {
+    // Answers served from the response cache (RESPONSE_CACHE_TTL_SECS)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response: Option<CacheHitRate>,
+    // Documents by URL found in the download cache (DOWNLOAD_CACHE_TTL_SECS)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub download: Option<CacheHitRate>,
+}
+
+// Questions asked over a window, from the audit log
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct QueryAnalytics {
+    pub window: String,
+    // Unix timestamps bounding the window
+    pub since: u64,
+    pub until: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    pub requests: u64,
+    pub questions: u64,
+    pub interval_secs: u64,
+    // Oldest first, intervals without questions included
+    pub volume: Vec<VolumeInterval>,
+    pub latency: LatencyPercentiles,
+    // Questions answered as insufficient_information, out of those that did not fail
+    pub abstained: u64,
+    pub abstention_rate: Option<f64>,
+    pub failed: u64,
+    // Most retrieved first
+    pub top_documents: Vec<DocumentRetrievals>,
+    pub caches: CacheHitRates,
+}
+
+impl QueryAnalytics {
+    // Aggregates `entries`, all within [since, until]
+    pub fn from_entries(query: &AnalyticsQuery, since: u64, until: u64, interval_secs: u64, entries: &[AuditEntry]) -> Self {
+        let mut volume: Vec<VolumeInterval> = (since..until.max(since + 1))
+            .step_by(interval_secs as usize)
+            .map(|start| VolumeInterval { start, requests: 0, questions: 0 })
+            .collect();
+        let last = volume.len() - 1;
+        let mut latencies = Vec::new();
+        let (mut questions, mut abstained, mut failed) = (0, 0, 0);
+        let mut cited: HashMap<&str, u64> = HashMap::new();
+        for entry in entries {
+            // Entries at `until` go into the last interval
+            let index = (entry.timestamp.saturating_sub(since) / interval_secs) as usize;
+            if let Some(interval) = volume.get_mut(index.min(last)) {
+                interval.requests += 1;
+                interval.questions += entry.questions.len() as u64;
+            }
+            for item in &entry.questions {
+                questions += 1;
+                match item.status.as_str() {
+                    ABSTAINED_STATUS => abstained += 1,
+                    FAILED_STATUS => failed += 1,
+                    _ => {}
+                }
+                latencies.extend(item.latency_ms);
+                for document in &item.documents {
+                    *cited.entry(document).or_default() += 1;
+                }
+            }
+        }
+        let mut top_documents: Vec<DocumentRetrievals> = cited
+            .into_iter()
+            .map(|(document, retrievals)| DocumentRetrievals { document: document.to_string(), retrievals })
+            .collect();
+        top_documents.sort_by(|a, b| b.retrievals.cmp(&a.retrievals).then_with(|| a.document.cmp(&b.document)));
+        top_documents.truncate(query.top.unwrap_or(DEFAULT_TOP_DOCUMENTS));
+
+        let answered = questions - failed;
+        Self {
+            window: format!("{:?}", query.window.unwrap_or_default()).to_lowercase(),
+            since,
+            until,
+            tenant: query.tenant.clone(),
+            requests: entries.len() as u64,
+            questions,
+            interval_secs,
+            volume,
+            latency: LatencyPercentiles::new(latencies),
+            abstained,
+            abstention_rate: (answered > 0).then(|| abstained as f64 / answered as f64),
+            failed,
+            top_documents,
+            caches: CacheHitRates::default(),
+        }
+    }
+
+    pub fn with_caches(mut self, caches: CacheHitRates) -> Self {
+        self.caches = caches;
+        self
+    }
+}
+
+// Nearest-rank `percent`th percentile of `sorted`
+pub fn percentile(sorted: &[u64], percent: usize) -> Option<u64> {
+    match sorted.len() {
+        0 => None,
+        n => Some(sorted[(n * percent).div_ceil(100).max(1) - 1]),
+    }
+}
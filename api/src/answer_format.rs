@@ -0,0 +1,108 @@
+use rag_system::models::{Conflict, Decision, DocumentAnswer, SourceOffsets, TextSpan};
+use rag_system::QueryResponse;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+// How answers are rendered
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AnswerFormat {
+    // Text with Markdown markup removed
+    Plain,
+    // The answer as the LLM wrote it, which is usually Markdown
+    #[default]
+    Markdown,
+    // Plain text answer plus a `structured` object with the decision, citations and conflicts
+    Json,
+    // Plain text answer; /query and synchronous /hackrx/run reply with a CSV of the citations
+    // (question, answer, document, page, excerpt, score) instead of JSON
+    Csv,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct StructuredCitation {
+    pub document: String,
+    pub chunk_id: String,
+    pub excerpt: String,
+    pub confidence: f32,
+    // Passages of the cited chunk that support the answer
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub highlights: Vec<TextSpan>,
+    // Where the chunk lies in the document's extracted text, per page too, for highlighting it
+    // in the source document
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<SourceOffsets>,
+}
+
+// Everything known about an answer, for programmatic consumers (format "json")
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct StructuredAnswer {
+    pub answer: String,
+    // How sure the system is of the answer, in [0, 1]; absent when the question skipped
+    // retrieval
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub decision: Option<Decision>,
+    pub citations: Vec<StructuredCitation>,
+    pub conflicts: Vec<Conflict>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub document_answers: Vec<DocumentAnswer>,
+}
+
+impl AnswerFormat {
+    pub fn render(self, answer: &str) -> String {
+        match self {
+            Self::Markdown => answer.to_string(),
+            Self::Plain | Self::Json | Self::Csv => strip_markdown(answer),
+        }
+    }
+
+    // The structured form of `response` in json mode, None otherwise
+    pub fn structured(self, response: &QueryResponse) -> Option<StructuredAnswer> {
+        if self != Self::Json {
+            return None;
+        }
+        Some(StructuredAnswer {
+            answer: strip_markdown(&response.response),
+            confidence: response.confidence_score,
+            decision: response.decision.clone(),
+            citations: response
+                .citations
+                .iter()
+                .map(|citation| StructuredCitation {
+                    document: citation.document.clone(),
+                    chunk_id: citation.chunk_id.clone(),
+                    excerpt: citation.text_excerpt.clone(),
+                    confidence: citation.confidence_score,
+                    highlights: citation.highlights.clone(),
+                    source: citation.source.clone(),
+                })
+                .collect(),
+            conflicts: response.conflicts.clone(),
+            document_answers: response.document_answers.clone(),
+        })
+    }
+}
+
+// Removes Markdown markup, keeping the text: headings, emphasis, code and quotes lose their
+// markers, links become "text (url)" and bullets become "- "
+pub fn strip_markdown(text: &str) -> String {
+    let rules = [
+        (r"(?m)^\s*```.*\n?", ""),
+        (r"(?m)^\s{0,3}#{1,6}\s+", ""),
+        (r"(?m)^\s{0,3}>\s?", ""),
+        (r"(?m)^(\s*)[*+]\s+", "$1- "),
+        (r"\*\*(.+?)\*\*|__(.+?)__", "$1$2"),
+        (r"\*(\S(?:[^*\n]*\S)?)\*", "$1"),
+        (r"`([^`\n]+)`", "$1"),
+        (r"\[([^\]\n]+)\]\(([^)\s]+)\)", "$1 ($2)"),
+    ];
+    let mut text = text.to_string();
+    for (pattern, replacement) in rules {
+        let regex = Regex::new(pattern).expect("valid regex");
+        text = regex.replace_all(&text, replacement).into_owned();
+    }
+    text.trim().to_string()
+}
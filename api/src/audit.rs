@@ -0,0 +1,334 @@
+use anyhow::Result;
+use axum::async_trait;
+use axum::extract::FromRequestParts;
+use axum::http::{request::Parts, HeaderMap, Method, Uri};
+use rag_system::{QueryResponse, TokenUsage};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use std::convert::Infallible;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+use crate::auth::Claims;
+use crate::config::Config;
+use crate::metadata_store::MetadataStore;
+use crate::REQUEST_ID_HEADER;
+
+// Answer kept in place of one based on a purged document, which may quote it
+pub const PURGED_ANSWER: &str = "[removed: based on a purged document]";
+
+// One question of an audited request
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AuditItem {
+    // Empty when the question failed
+    pub query_id: String,
+    pub question: String,
+    pub answer: String,
+    pub status: String,
+    // Documents and chunks the answer was based on
+    pub documents: Vec<String>,
+    pub chunk_ids: Vec<String>,
+    // How long answering took; absent for failed questions and entries written before it was
+    // recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+}
+
+impl AuditItem {
+    // Drops the answer and the references to `filename`, if this item cites any of its
+    // chunks `chunk_ids`; true if it did
+    pub fn redact(&mut self, filename: &str, chunk_ids: &HashSet<String>) -> bool {
+        if !self.chunk_ids.iter().any(|id| chunk_ids.contains(id)) {
+            return false;
+        }
+        self.answer = PURGED_ANSWER.to_string();
+        self.chunk_ids.retain(|id| !chunk_ids.contains(id));
+        self.documents.retain(|document| document != filename);
+        true
+    }
+
+    pub fn answered(question: &str, response: &QueryResponse) -> Self {
+        let mut documents: Vec<String> = Vec::new();
+        for citation in &response.citations {
+            if !documents.contains(&citation.document) {
+                documents.push(citation.document.clone());
+            }
+        }
+        Self {
+            query_id: response.query_id.clone(),
+            question: question.to_string(),
+            answer: response.response.clone(),
+            status: response.status.clone(),
+            documents,
+            chunk_ids: response.citations.iter().map(|c| c.chunk_id.clone()).collect(),
+            latency_ms: Some(response.processing_time_ms as u64),
+        }
+    }
+
+    pub fn failed(question: &str, error: &str) -> Self {
+        Self {
+            query_id: String::new(),
+            question: question.to_string(),
+            answer: error.to_string(),
+            status: "error".to_string(),
+            documents: Vec::new(),
+            chunk_ids: Vec::new(),
+            latency_ms: None,
+        }
+    }
+}
+
+// Where an audited request came in: its request id and endpoint. Extracted by handlers that
+// answer in a background task, which records the entry itself.
+#[derive(Debug, Clone)]
+pub struct AuditContext {
+    pub request_id: Option<String>,
+    // e.g. "POST /hackrx/run"
+    pub endpoint: String,
+}
+
+impl AuditContext {
+    pub fn from_parts(method: &Method, uri: &Uri, headers: &HeaderMap) -> Self {
+        Self {
+            request_id: headers
+                .get(REQUEST_ID_HEADER)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string),
+            endpoint: format!("{} {}", method, uri.path()),
+        }
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for AuditContext {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self::from_parts(&parts.method, &parts.uri, &parts.headers))
+    }
+}
+
+// Set by handlers on their response: the questions they answered, for usage and the audit log
+#[derive(Debug, Clone, Default)]
+pub struct AnsweredQuestions(pub Vec<AuditItem>);
+
+// Who asked what in one request, and what it cost
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AuditEntry {
+    pub id: String,
+    pub timestamp: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    pub user: String,
+    pub tenant: String,
+    // jti of the access token used
+    pub token_id: String,
+    // e.g. "POST /hackrx/run"
+    pub endpoint: String,
+    pub questions: Vec<AuditItem>,
+    pub usage: TokenUsage,
+}
+
+// Filters for GET /admin/audit; text filters are case-insensitive substring matches
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuditFilter {
+    pub user: Option<String>,
+    pub tenant: Option<String>,
+    pub token_id: Option<String>,
+    pub query_id: Option<String>,
+    // Matched against questions and answers
+    pub contains: Option<String>,
+    // Matched against the documents answers were based on
+    pub document: Option<String>,
+    // Unix timestamps bounding the entry time (inclusive)
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+}
+
+impl AuditEntry {
+    // See AuditItem::redact; returns the number of items redacted
+    pub fn redact(&mut self, filename: &str, chunk_ids: &HashSet<String>) -> usize {
+        self.questions.iter_mut().map(|item| item.redact(filename, chunk_ids)).filter(|&redacted| redacted).count()
+    }
+
+    pub fn cites_any(&self, chunk_ids: &HashSet<String>) -> bool {
+        self.questions.iter().any(|item| item.chunk_ids.iter().any(|id| chunk_ids.contains(id)))
+    }
+}
+
+impl AuditFilter {
+    pub fn matches(&self, entry: &AuditEntry) -> bool {
+        let contains = |haystack: &str, needle: &str| haystack.to_lowercase().contains(&needle.to_lowercase());
+        self.user.as_ref().is_none_or(|user| &entry.user == user)
+            && self.tenant.as_ref().is_none_or(|tenant| &entry.tenant == tenant)
+            && self.token_id.as_ref().is_none_or(|token_id| &entry.token_id == token_id)
+            && self.since.is_none_or(|since| entry.timestamp >= since)
+            && self.until.is_none_or(|until| entry.timestamp <= until)
+            && self.query_id.as_ref().is_none_or(|id| entry.questions.iter().any(|item| &item.query_id == id))
+            && self.contains.as_ref().is_none_or(|text| {
+                entry.questions.iter().any(|item| contains(&item.question, text) || contains(&item.answer, text))
+            })
+            && self.document.as_ref().is_none_or(|document| {
+                entry.questions.iter().any(|item| item.documents.iter().any(|doc| contains(doc, document)))
+            })
+    }
+}
+
+// Append-only record of answered requests. With DATABASE_URL set entries are saved to and
+// searched in the metadata database. With AUDIT_LOG set every entry is also appended to that
+// JSONL file, which searches read when there is no database; otherwise the most recent
+// entries are kept in memory only.
+pub struct AuditLog {
+    store: Option<MetadataStore>,
+    path: Option<PathBuf>,
+    // Also serializes appends so concurrent entries never interleave within a line
+    recent: Mutex<VecDeque<AuditEntry>>,
+    max_in_memory: usize,
+}
+
+impl AuditLog {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            store: None,
+            path: config.audit_log.clone(),
+            recent: Mutex::new(VecDeque::new()),
+            max_in_memory: config.audit_max_in_memory.max(1),
+        }
+    }
+
+    pub fn with_store(mut self, store: Option<MetadataStore>) -> Self {
+        self.store = store;
+        self
+    }
+
+    pub fn record(&self, claims: &Claims, context: &AuditContext, questions: Vec<AuditItem>, usage: TokenUsage) {
+        if questions.is_empty() {
+            return;
+        }
+        let entry = AuditEntry {
+            id: Uuid::new_v4().to_string(),
+            timestamp: unix_timestamp(),
+            request_id: context.request_id.clone(),
+            user: claims.sub.clone(),
+            tenant: claims.tenant.clone(),
+            token_id: claims.jti.clone(),
+            endpoint: context.endpoint.clone(),
+            questions,
+            usage,
+        };
+
+        let mut recent = self.recent.lock().unwrap();
+        if let Some(store) = &self.store {
+            store.save_audit_entry(&entry);
+        }
+        if let Some(path) = &self.path {
+            let appended = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut file| writeln!(file, "{}", serde_json::to_string(&entry).unwrap_or_default()));
+            if let Err(e) = appended {
+                tracing::error!("Failed to append to audit log {}: {}", path.display(), e);
+            }
+        }
+        recent.push_back(entry);
+        while recent.len() > self.max_in_memory {
+            recent.pop_front();
+        }
+    }
+
+    // Redacts the items citing the chunks `chunk_ids` of a purged document (see
+    // AuditItem::redact) wherever entries are kept: in memory, the AUDIT_LOG file and the
+    // metadata database. Returns the number of items redacted in the store searches read.
+    pub async fn purge(&self, filename: &str, chunk_ids: &HashSet<String>) -> Result<usize> {
+        let (in_memory, in_file) = {
+            let mut recent = self.recent.lock().unwrap();
+            let in_memory = recent.iter_mut().map(|entry| entry.redact(filename, chunk_ids)).sum::<usize>();
+            let in_file = match &self.path {
+                Some(path) => Some(redact_file(path, filename, chunk_ids)?),
+                None => None,
+            };
+            (in_memory, in_file)
+        };
+        if let Some(store) = &self.store {
+            return store.redact_audit_entries(filename, chunk_ids).await;
+        }
+        Ok(in_file.unwrap_or(in_memory))
+    }
+
+    // Entries matching `filter`, newest first
+    pub async fn search(&self, filter: AuditFilter) -> Result<Vec<AuditEntry>> {
+        if let Some(store) = &self.store {
+            return store.audit_entries(&filter).await;
+        }
+        let Some(path) = self.path.clone() else {
+            let recent = self.recent.lock().unwrap();
+            return Ok(recent.iter().rev().filter(|entry| filter.matches(entry)).cloned().collect());
+        };
+
+        tokio::task::spawn_blocking(move || {
+            let file = match File::open(&path) {
+                Ok(file) => file,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+                Err(e) => return Err(e.into()),
+            };
+            let mut entries = Vec::new();
+            for line in BufReader::new(file).lines() {
+                let line = line?;
+                match serde_json::from_str::<AuditEntry>(&line) {
+                    Ok(entry) if filter.matches(&entry) => entries.push(entry),
+                    Ok(_) => {}
+                    Err(e) => tracing::warn!("Skipping unreadable audit log line: {}", e),
+                }
+            }
+            entries.reverse();
+            Ok(entries)
+        })
+        .await?
+    }
+}
+
+// Rewrites the audit log at `path` with the entries redacted, through a temporary file so a
+// crash meanwhile keeps the old one. Lines that cannot be read are kept as they are.
+fn redact_file(path: &Path, filename: &str, chunk_ids: &HashSet<String>) -> Result<usize> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+    let mut redacted = 0;
+    let mut lines = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        match serde_json::from_str::<AuditEntry>(&line) {
+            Ok(mut entry) if entry.cites_any(chunk_ids) => {
+                redacted += entry.redact(filename, chunk_ids);
+                lines.push(serde_json::to_string(&entry)?);
+            }
+            _ => lines.push(line),
+        }
+    }
+    if redacted > 0 {
+        let temp = path.with_extension("tmp");
+        let mut file = File::create(&temp)?;
+        for line in lines {
+            writeln!(file, "{}", line)?;
+        }
+        file.sync_all()?;
+        std::fs::rename(&temp, path)?;
+    }
+    Ok(redacted)
+}
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
@@ -1,140 +1,323 @@
+use argon2::password_hash::{PasswordHash, PasswordVerifier};
+use argon2::Argon2;
 use axum::{
     extract::{Request, State},
-    http::{HeaderMap, StatusCode},
+    http::HeaderMap,
     middleware::Next,
     response::Response,
-    Json,
 };
-use headers::{Authorization, HeaderMapExt};
-use serde::Serialize;
+use jsonwebtoken::errors::ErrorKind;
+use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use rag_system::models::Principal;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::path::Path;
+use std::str::FromStr;
 use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use utoipa::ToSchema;
 
-#[derive(Serialize)]
-pub struct AuthError {
-    pub error: String,
-    pub message: String,
+use crate::error::ApiError;
+use crate::tenants::is_valid_tenant;
+use crate::AppState;
+
+const DEFAULT_ACCESS_TTL_SECS: u64 = 15 * 60;
+const DEFAULT_REFRESH_TTL_SECS: u64 = 7 * 24 * 60 * 60;
+const ISSUER: &str = "hackrx-rag";
+// Role USER_ROLES grants operators, required by the /admin routes
+pub const ADMIN_ROLE: &str = "admin";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenType {
+    Access,
+    Refresh,
 }
 
-pub async fn auth_middleware(
-    headers: HeaderMap,
-    request: Request,
-    next: Next,
-) -> Result<Response, (StatusCode, Json<AuthError>)> {
-    // Extract Authorization header
-    let auth_header = headers.get("authorization");
-    
-    if let Some(auth_value) = auth_header {
-        let auth_str = auth_value.to_str().map_err(|_| {
-            (
-                StatusCode::UNAUTHORIZED,
-                Json(AuthError {
-                    error: "invalid_header".to_string(),
-                    message: "Invalid authorization header format".to_string(),
-                }),
-            )
-        })?;
-
-        // Check if it starts with "Bearer "
-        if auth_str.starts_with("Bearer ") {
-            let token = &auth_str[7..]; // Remove "Bearer " prefix
-            
-            // Simple token validation - just check if token exists and is not empty
-            // In a real application, you would validate the JWT token here
-            if !token.is_empty() && token.len() > 10 {
-                // Token is present and has reasonable length
-                log::info!("Authentication successful for token: {}...{}", &token[..4], &token[token.len()-4..]);
-                let response = next.run(request).await;
-                return Ok(response);
-            } else {
-                return Err((
-                    StatusCode::UNAUTHORIZED,
-                    Json(AuthError {
-                        error: "invalid_token".to_string(),
-                        message: "Token is too short or invalid".to_string(),
-                    }),
-                ));
-            }
-        } else {
-            return Err((
-                StatusCode::UNAUTHORIZED,
-                Json(AuthError {
-                    error: "invalid_authorization".to_string(),
-                    message: "Authorization header must start with 'Bearer '".to_string(),
-                }),
-            ));
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Claims {
+    pub sub: String,
+    // Collection the user's documents, queries and feedback belong to
+    pub tenant: String,
+    pub iss: String,
+    pub iat: u64,
+    pub exp: u64,
+    // Unique per token, so individual tokens can be told apart in logs
+    pub jti: String,
+    // Refresh tokens are only accepted by POST /refresh, access tokens everywhere else
+    pub token_type: TokenType,
+    // Roles granted to the authenticated user by USER_ROLES, matched against document access
+    // control lists
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<String>,
+}
+
+impl Claims {
+    // The caller as documents' access control lists see it
+    pub fn principal(&self) -> Principal {
+        Principal { user: self.sub.clone(), tenant: self.tenant.clone(), roles: self.roles.clone() }
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.roles.iter().any(|role| role == ADMIN_ROLE)
+    }
+
+    // Fails with 403 unless the token carries the admin role
+    pub fn require_admin(&self) -> Result<(), ApiError> {
+        match self.is_admin() {
+            true => Ok(()),
+            false => Err(ApiError::forbidden("admin_required", format!("This requires the {} role", ADMIN_ROLE))),
         }
-    } else {
-        return Err((
-            StatusCode::UNAUTHORIZED,
-            Json(AuthError {
-                error: "missing_authorization".to_string(),
-                message: "Authorization header is required".to_string(),
+    }
+}
+
+// Roles of one user, from USER_ROLES: `<user>=<role>|<role>...`
+#[derive(Debug, Clone)]
+pub struct UserRoles {
+    pub user: String,
+    pub roles: Vec<String>,
+}
+
+impl FromStr for UserRoles {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().split_once('=') {
+            Some((user, roles)) if !user.trim().is_empty() => Ok(Self {
+                user: user.trim().to_string(),
+                roles: roles.split('|').map(str::trim).filter(|role| !role.is_empty()).map(str::to_string).collect(),
             }),
-        ));
+            _ => Err(format!("{:?} is not <user>=<role>|<role>...", value)),
+        }
     }
 }
 
-// Alternative implementation using axum-extra typed headers
-pub async fn auth_middleware_typed(
-    auth: Option<headers::Authorization<headers::authorization::Bearer>>,
-    request: Request,
-    next: Next,
-) -> Result<Response, (StatusCode, Json<AuthError>)> {
-    if let Some(auth) = auth {
-        let token = auth.token();
-        
-        // Simple token validation - just check if token exists and is not empty
-        if !token.is_empty() && token.len() > 10 {
-            log::info!("Authentication successful for token: {}...{}", &token[..4], &token[token.len()-4..]);
-            let response = next.run(request).await;
-            return Ok(response);
-        } else {
-            return Err((
-                StatusCode::UNAUTHORIZED,
-                Json(AuthError {
-                    error: "invalid_token".to_string(),
-                    message: "Token is too short or invalid".to_string(),
-                }),
-            ));
+// An account allowed to log in, bound to the one tenant its tokens are issued for
+#[derive(Debug, Clone)]
+pub struct UserAccount {
+    pub user: String,
+    // Argon2 PHC string, e.g. from `argon2 <salt> -id -e`
+    password_hash: String,
+    pub tenant: String,
+}
+
+// Accounts from USERS_FILE, one `<user>:<argon2 hash>:<tenant>` per line; blank lines and
+// lines starting with '#' are skipped. Without the file nobody can log in.
+#[derive(Debug, Clone, Default)]
+pub struct UserStore {
+    accounts: Vec<UserAccount>,
+}
+
+impl UserStore {
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Cannot read {}: {}", path.display(), e))?;
+        let mut accounts = Vec::new();
+        for (number, line) in text.lines().enumerate().map(|(i, line)| (i + 1, line.trim())) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let account = Self::parse_line(line)
+                .map_err(|e| anyhow::anyhow!("{} line {}: {}", path.display(), number, e))?;
+            if accounts.iter().any(|existing: &UserAccount| existing.user == account.user) {
+                anyhow::bail!("{} line {}: user {} is listed twice", path.display(), number, account.user);
+            }
+            accounts.push(account);
         }
-    } else {
-        return Err((
-            StatusCode::UNAUTHORIZED,
-            Json(AuthError {
-                error: "missing_authorization".to_string(),
-                message: "Authorization Bearer token is required".to_string(),
-            }),
-        ));
+        Ok(Self { accounts })
+    }
+
+    fn parse_line(line: &str) -> Result<UserAccount, String> {
+        let mut fields = line.split(':');
+        let (Some(user), Some(password_hash), Some(tenant), None) = (fields.next(), fields.next(), fields.next(), fields.next()) else {
+            return Err("expected <user>:<argon2 hash>:<tenant>".to_string());
+        };
+        if user.trim().is_empty() {
+            return Err("empty user name".to_string());
+        }
+        PasswordHash::new(password_hash.trim()).map_err(|e| format!("invalid password hash: {}", e))?;
+        if !is_valid_tenant(tenant.trim()) {
+            return Err(format!("invalid tenant {:?}", tenant.trim()));
+        }
+        Ok(UserAccount {
+            user: user.trim().to_string(),
+            password_hash: password_hash.trim().to_string(),
+            tenant: tenant.trim().to_string(),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.accounts.is_empty()
+    }
+
+    pub fn account(&self, user: &str) -> Option<&UserAccount> {
+        self.accounts.iter().find(|account| account.user == user)
+    }
+
+    // The account `user` logs in to, if `password` is its password
+    pub fn authenticate(&self, user: &str, password: &str) -> Option<&UserAccount> {
+        let account = self.account(user)?;
+        let hash = PasswordHash::new(&account.password_hash).ok()?;
+        Argon2::default().verify_password(password.as_bytes(), &hash).ok().map(|_| account)
     }
 }
 
-// Example of what a real JWT validation might look like (commented out since we don't have JWT dependencies)
-/*
-use jsonwebtoken::{decode, DecodingKey, Validation, Algorithm};
+// Access/refresh token pair returned by POST /login and POST /refresh
+#[derive(Serialize, ToSchema)]
+pub struct TokenPair {
+    // Access token for the Authorization: Bearer header
+    pub token: String,
+    pub refresh_token: String,
+    pub token_type: String,
+    // Lifetime of `token` in seconds
+    pub expires_in: u64,
+}
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Claims {
-    sub: String,
-    exp: usize,
+// Signs and validates JWTs. HS256 with JWT_SECRET by default; JWT_ALGORITHM=RS256 reads
+// PEM keys from JWT_PRIVATE_KEY_PATH and JWT_PUBLIC_KEY_PATH instead.
+pub struct JwtAuth {
+    algorithm: Algorithm,
+    encoding_key: EncodingKey,
+    decoding_key: DecodingKey,
+    access_ttl_secs: u64,
+    refresh_ttl_secs: u64,
 }
 
-pub fn validate_jwt_token(token: &str, secret: &str) -> Result<Claims, String> {
-    let key = DecodingKey::from_secret(secret.as_ref());
-    let validation = Validation::new(Algorithm::HS256);
-    
-    match decode::<Claims>(token, &key, &validation) {
-        Ok(token_data) => Ok(token_data.claims),
-        Err(err) => Err(format!("JWT validation failed: {}", err)),
+impl JwtAuth {
+    pub fn hs256(secret: &[u8]) -> Self {
+        Self {
+            algorithm: Algorithm::HS256,
+            encoding_key: EncodingKey::from_secret(secret),
+            decoding_key: DecodingKey::from_secret(secret),
+            access_ttl_secs: DEFAULT_ACCESS_TTL_SECS,
+            refresh_ttl_secs: DEFAULT_REFRESH_TTL_SECS,
+        }
+    }
+
+    pub fn rs256(private_pem: &[u8], public_pem: &[u8]) -> anyhow::Result<Self> {
+        Ok(Self {
+            algorithm: Algorithm::RS256,
+            encoding_key: EncodingKey::from_rsa_pem(private_pem)?,
+            decoding_key: DecodingKey::from_rsa_pem(public_pem)?,
+            access_ttl_secs: DEFAULT_ACCESS_TTL_SECS,
+            refresh_ttl_secs: DEFAULT_REFRESH_TTL_SECS,
+        })
+    }
+
+    pub fn with_ttls(mut self, access_ttl_secs: u64, refresh_ttl_secs: u64) -> Self {
+        self.access_ttl_secs = access_ttl_secs.max(1);
+        self.refresh_ttl_secs = refresh_ttl_secs.max(1);
+        self
+    }
+
+    pub fn from_env() -> anyhow::Result<Self> {
+        let auth = match env::var("JWT_ALGORITHM").as_deref() {
+            Ok("RS256") => {
+                let private_path = env::var("JWT_PRIVATE_KEY_PATH")
+                    .map_err(|_| anyhow::anyhow!("JWT_PRIVATE_KEY_PATH must be set for RS256"))?;
+                let public_path = env::var("JWT_PUBLIC_KEY_PATH")
+                    .map_err(|_| anyhow::anyhow!("JWT_PUBLIC_KEY_PATH must be set for RS256"))?;
+                Self::rs256(&std::fs::read(private_path)?, &std::fs::read(public_path)?)?
+            }
+            Ok("HS256") | Err(_) => match env::var("JWT_SECRET") {
+                Ok(secret) if !secret.is_empty() => Self::hs256(secret.as_bytes()),
+                _ => {
+                    // Tokens stay valid only until the next restart
+                    tracing::warn!("JWT_SECRET not set, signing tokens with a random per-process secret");
+                    let secret = format!("{}{}", uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple());
+                    Self::hs256(secret.as_bytes())
+                }
+            },
+            Ok(other) => return Err(anyhow::anyhow!("Unsupported JWT_ALGORITHM {}", other)),
+        };
+
+        let access_ttl = env_secs("JWT_ACCESS_TTL_SECS").unwrap_or(DEFAULT_ACCESS_TTL_SECS);
+        let refresh_ttl = env_secs("JWT_REFRESH_TTL_SECS").unwrap_or(DEFAULT_REFRESH_TTL_SECS);
+        Ok(auth.with_ttls(access_ttl, refresh_ttl))
+    }
+
+    pub fn issue_tokens(&self, subject: &str, tenant: &str, roles: &[String]) -> anyhow::Result<TokenPair> {
+        Ok(TokenPair {
+            token: self.issue(subject, tenant, roles, TokenType::Access, self.access_ttl_secs)?,
+            refresh_token: self.issue(subject, tenant, roles, TokenType::Refresh, self.refresh_ttl_secs)?,
+            token_type: "Bearer".to_string(),
+            expires_in: self.access_ttl_secs,
+        })
+    }
+
+    fn issue(&self, subject: &str, tenant: &str, roles: &[String], token_type: TokenType, ttl_secs: u64) -> anyhow::Result<String> {
+        let now = unix_timestamp();
+        let claims = Claims {
+            sub: subject.to_string(),
+            tenant: tenant.to_string(),
+            iss: ISSUER.to_string(),
+            iat: now,
+            exp: now + ttl_secs,
+            jti: uuid::Uuid::new_v4().to_string(),
+            token_type,
+            roles: roles.to_vec(),
+        };
+        Ok(encode(&Header::new(self.algorithm), &claims, &self.encoding_key)?)
+    }
+
+    // Checks signature, algorithm, issuer, expiry and that the token is of the expected type
+    pub fn validate(&self, token: &str, expected: TokenType) -> Result<Claims, ApiError> {
+        let mut validation = Validation::new(self.algorithm);
+        validation.set_issuer(&[ISSUER]);
+        validation.set_required_spec_claims(&["exp", "iss", "sub"]);
+        validation.leeway = 0;
+
+        let claims = decode::<Claims>(token, &self.decoding_key, &validation)
+            .map_err(|e| match e.kind() {
+                ErrorKind::ExpiredSignature => ApiError::unauthorized("token_expired", "Token has expired"),
+                _ => ApiError::unauthorized("invalid_token", format!("Token validation failed: {}", e)),
+            })?
+            .claims;
+
+        if claims.token_type != expected {
+            return Err(ApiError::unauthorized("invalid_token_type", "Wrong kind of token for this endpoint"));
+        }
+        if !is_valid_tenant(&claims.tenant) {
+            return Err(ApiError::unauthorized("invalid_token", "Token has an invalid tenant"));
+        }
+        Ok(claims)
     }
 }
-*/
 
-// Generate a simple mock token for testing
-pub fn generate_mock_token(user_id: &str) -> String {
-    format!("mock_token_{}_{}", user_id, uuid::Uuid::new_v4())
+// Requires a valid access token; the claims are made available to handlers as a
+// request extension
+pub async fn auth_middleware(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    let auth_value = headers
+        .get("authorization")
+        .ok_or_else(|| ApiError::unauthorized("missing_authorization", "Authorization header is required"))?;
+    let auth_str = auth_value
+        .to_str()
+        .map_err(|_| ApiError::unauthorized("invalid_header", "Invalid authorization header format"))?;
+    let token = auth_str.strip_prefix("Bearer ").ok_or_else(|| {
+        ApiError::unauthorized("invalid_authorization", "Authorization header must start with 'Bearer '")
+    })?;
+
+    let claims = state.auth.validate(token.trim(), TokenType::Access).inspect_err(|e| {
+        tracing::info!("Rejected token: {}", e.message);
+    })?;
+
+    tracing::info!("Authenticated {} of tenant {} (token {})", claims.sub, claims.tenant, claims.jti);
+    request.extensions_mut().insert(claims);
+    Ok(next.run(request).await)
+}
+
+fn env_secs(name: &str) -> Option<u64> {
+    env::var(name).ok().and_then(|v| v.parse().ok())
 }
 
-// Mock token validation that just checks format
-pub fn validate_mock_token(token: &str) -> bool {
-    token.starts_with("mock_token_") && token.len() > 20
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
 }
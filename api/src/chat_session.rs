@@ -0,0 +1,74 @@
+use rag_system::Session;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::auth::Claims;
+use crate::rag_response::RagResponse;
+
+// Chat sessions belong to one user of one tenant
+pub fn session_owner(claims: &Claims) -> String {
+    format!("{}/{}", claims.tenant, claims.sub)
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ChatSession {
+    pub session_id: String,
+    pub created_at: u64,
+    pub updated_at: u64,
+    // Turns kept verbatim; older ones are summarized or dropped
+    pub turns: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+}
+
+impl From<&Session> for ChatSession {
+    fn from(session: &Session) -> Self {
+        Self {
+            session_id: session.id.clone(),
+            created_at: session.created_at,
+            updated_at: session.updated_at,
+            turns: session.history.len(),
+            summary: session.summary.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ChatMessage {
+    // "user" or "assistant"
+    pub role: String,
+    pub content: String,
+    pub timestamp: u64,
+}
+
+// The conversation as the model sees it: the summary of older turns, then the recent ones
+#[derive(Serialize, ToSchema)]
+pub struct ChatTranscript {
+    #[serde(flatten)]
+    pub session: ChatSession,
+    pub messages: Vec<ChatMessage>,
+}
+
+impl From<&Session> for ChatTranscript {
+    fn from(session: &Session) -> Self {
+        let messages = session
+            .history
+            .iter()
+            .flat_map(|turn| {
+                [("user", &turn.question), ("assistant", &turn.answer)].map(|(role, content)| ChatMessage {
+                    role: role.to_string(),
+                    content: content.clone(),
+                    timestamp: turn.timestamp,
+                })
+            })
+            .collect();
+        Self { session: ChatSession::from(session), messages }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ChatReply {
+    pub session_id: String,
+    #[serde(flatten)]
+    pub response: RagResponse,
+}
@@ -0,0 +1,118 @@
+use axum::http::header::{CONTENT_DISPOSITION, CONTENT_TYPE};
+use axum::response::{IntoResponse, Response};
+use rag_system::QueryResponse;
+
+const HEADER: [&str; 8] = ["question", "answer", "query_id", "document", "page", "excerpt", "score", "chunk_id"];
+
+// One line of the citations spreadsheet: a cited chunk with the question and answer it
+// supports. Questions without citations (or that failed) get a single line with the
+// evidence columns empty, so every question shows up.
+#[derive(Debug, Clone)]
+pub struct CitationRow {
+    question: String,
+    answer: String,
+    query_id: String,
+    document: String,
+    page: Option<usize>,
+    excerpt: String,
+    score: Option<f32>,
+    chunk_id: String,
+}
+
+impl CitationRow {
+    // The rows for an answered question; `answer` is the rendered answer
+    pub fn from_response(question: &str, answer: &str, response: &QueryResponse) -> Vec<Self> {
+        if response.citations.is_empty() {
+            let mut row = Self::unanswered(question, answer);
+            row.query_id = response.query_id.clone();
+            return vec![row];
+        }
+        response
+            .citations
+            .iter()
+            .map(|citation| Self {
+                question: question.to_string(),
+                answer: answer.to_string(),
+                query_id: response.query_id.clone(),
+                document: citation.document.clone(),
+                page: citation.page,
+                excerpt: citation.text_excerpt.clone(),
+                score: Some(citation.confidence_score),
+                chunk_id: citation.chunk_id.clone(),
+            })
+            .collect()
+    }
+
+    // A question with no evidence, e.g. one that failed or was not reached before the deadline
+    pub fn unanswered(question: &str, answer: &str) -> Self {
+        Self {
+            question: question.to_string(),
+            answer: answer.to_string(),
+            query_id: String::new(),
+            document: String::new(),
+            page: None,
+            excerpt: String::new(),
+            score: None,
+            chunk_id: String::new(),
+        }
+    }
+
+    fn cells(&self) -> [String; 8] {
+        [
+            self.question.clone(),
+            self.answer.clone(),
+            self.query_id.clone(),
+            self.document.clone(),
+            self.page.map(|page| page.to_string()).unwrap_or_default(),
+            self.excerpt.clone(),
+            self.score.map(|score| format!("{:.4}", score)).unwrap_or_default(),
+            self.chunk_id.clone(),
+        ]
+    }
+}
+
+// RFC 4180 CSV with a header line and CRLF line endings. Starts with a byte order mark so
+// Excel opens it as UTF-8.
+pub fn to_csv(rows: &[CitationRow]) -> String {
+    let mut csv = String::from('\u{feff}');
+    push_line(&mut csv, HEADER.iter().map(|name| name.to_string()));
+    for row in rows {
+        push_line(&mut csv, row.cells().into_iter());
+    }
+    csv
+}
+
+// The rows as a downloadable text/csv attachment named `filename`
+pub fn csv_response(rows: &[CitationRow], filename: &str) -> Response {
+    (
+        [
+            (CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+            (CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename)),
+        ],
+        to_csv(rows),
+    )
+        .into_response()
+}
+
+fn push_line(csv: &mut String, cells: impl Iterator<Item = String>) {
+    let cells: Vec<String> = cells.map(|cell| escape(&cell)).collect();
+    csv.push_str(&cells.join(","));
+    csv.push_str("\r\n");
+}
+
+// Quotes cells containing separators, quotes or line breaks. Cells that a spreadsheet would
+// evaluate as a formula (starting with =, +, -, @, a tab or a carriage return) are prefixed
+// with an apostrophe, since excerpts and answers come from documents and the LLM rather than
+// from the analyst.
+fn escape(cell: &str) -> String {
+    let cell = if cell.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{}", cell)
+    } else {
+        cell.to_string()
+    };
+    if cell.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell
+    }
+}
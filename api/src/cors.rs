@@ -0,0 +1,42 @@
+use axum::http::{HeaderName, HeaderValue, Method};
+use std::str::FromStr;
+use std::time::Duration;
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+
+use crate::config::Config;
+
+// Cross-origin access as configured. By default no origin is allowed; browsers on other
+// origins need CORS_ORIGINS, or CORS_PERMISSIVE during development.
+pub fn cors_layer(config: &Config) -> CorsLayer {
+    if config.cors_permissive {
+        tracing::warn!("CORS_PERMISSIVE is set: any origin may call the API");
+        return CorsLayer::new()
+            .allow_origin(Any)
+            .allow_methods(Any)
+            .allow_headers(Any)
+            .expose_headers(Any);
+    }
+
+    CorsLayer::new()
+        .allow_origin(AllowOrigin::list(parse_all("origin", &config.cors_origins, HeaderValue::from_str)))
+        .allow_methods(parse_all("method", &config.cors_methods, |method| Method::from_str(&method.to_uppercase())))
+        .allow_headers(parse_all("header", &config.cors_headers, HeaderName::from_str))
+        .expose_headers(parse_all("header", &config.cors_expose_headers, HeaderName::from_str))
+        .max_age(Duration::from_secs(config.cors_max_age_secs))
+}
+
+// Parses each configured value, skipping (and logging) the invalid ones
+fn parse_all<T, E>(kind: &str, values: &[String], parse: impl Fn(&str) -> Result<T, E>) -> Vec<T> {
+    values
+        .iter()
+        .map(|value| value.trim())
+        .filter(|value| !value.is_empty())
+        .filter_map(|value| match parse(value) {
+            Ok(parsed) => Some(parsed),
+            Err(_) => {
+                tracing::warn!("Ignoring invalid CORS {} {:?}", kind, value);
+                None
+            }
+        })
+        .collect()
+}
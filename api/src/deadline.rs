@@ -0,0 +1,49 @@
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::error::ApiError;
+use crate::AppState;
+
+// Handlers that can return partial results stop at the deadline themselves; everything else
+// is cut off this long after it
+const GRACE: Duration = Duration::from_secs(1);
+
+// When the request has to be answered by, set on requests when REQUEST_TIMEOUT_SECS is
+// configured
+#[derive(Debug, Clone, Copy)]
+pub struct Deadline(pub Instant);
+
+// Runs `future` to completion, or until `deadline` passes (None when it did)
+pub async fn within<F: Future>(deadline: Option<Deadline>, future: F) -> Option<F::Output> {
+    match deadline {
+        Some(Deadline(at)) => tokio::time::timeout_at(at, future).await.ok(),
+        None => Some(future.await),
+    }
+}
+
+// Enforces the end-to-end request deadline. Streaming responses are only timed until their
+// headers are sent.
+pub async fn enforce_deadline(State(state): State<Arc<AppState>>, mut request: Request, next: Next) -> Response {
+    let Some(timeout) = state.config.request_timeout() else {
+        return next.run(request).await;
+    };
+
+    let deadline = Instant::now() + timeout;
+    request.extensions_mut().insert(Deadline(deadline));
+    match tokio::time::timeout_at(deadline + GRACE, next.run(request)).await {
+        Ok(response) => response,
+        Err(_) => ApiError::new(
+            axum::http::StatusCode::GATEWAY_TIMEOUT,
+            "deadline_exceeded",
+            format!("The request did not finish within {}s", timeout.as_secs()),
+        )
+        .into_response(),
+    }
+}
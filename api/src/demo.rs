@@ -0,0 +1,10 @@
+use axum::response::Html;
+
+// Single-page chat UI for demos: log in, upload documents to the caller's collection and chat
+// with them through /chat/sessions, with the passages backing each answer highlighted
+const DEMO_PAGE: &str = include_str!("../static/demo.html");
+
+// Handler for GET /demo
+pub async fn demo_page() -> Html<&'static str> {
+    Html(DEMO_PAGE)
+}
@@ -0,0 +1,65 @@
+use rag_system::models::{AccessControl, Clause, ClauseKind, Entity, EntityKind};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+// Filters for GET /documents
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DocumentFilter {
+    // Case-insensitive substring of the filename
+    pub filename: Option<String>,
+    // Exact metadata tag, e.g. "current"
+    pub tag: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DocumentSummary {
+    pub document_id: String,
+    pub filename: String,
+    pub chunks: usize,
+    pub tags: Vec<String>,
+    // Who may retrieve the document; absent for public documents
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access: Option<AccessControl>,
+}
+
+// Filters for GET /documents/{id}/chunks
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ChunkFilter {
+    // Case-insensitive substring of the chunk text
+    pub contains: Option<String>,
+    // Only chunks mentioning this kind of entity: amount, duration, age, location or procedure
+    pub entity: Option<EntityKind>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ChunkSummary {
+    pub chunk_id: String,
+    // Position of the chunk within the document
+    pub index: usize,
+    pub content: String,
+    pub start_position: usize,
+    pub end_position: usize,
+    // Amounts, durations, ages, locations and procedures the chunk mentions
+    pub entities: Vec<Entity>,
+}
+
+// Filters for GET /clauses
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ClauseFilter {
+    // waiting_period, sub_limit, exclusion or sum_insured
+    pub kind: Option<ClauseKind>,
+    // Case-insensitive substring of what the clause applies to, e.g. "cataract"
+    pub subject: Option<String>,
+    pub document_id: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ClauseSummary {
+    pub document_id: String,
+    pub filename: String,
+    #[serde(flatten)]
+    pub clause: Clause,
+}
@@ -0,0 +1,272 @@
+use rag_system::cache::CacheStats;
+use rag_system::models::Document;
+use rag_system::EmbeddingProvider;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::Config;
+
+// A document indexed on its own, ready to answer questions from
+pub struct AdHocIndex {
+    pub documents: Vec<Document>,
+    pub embeddings: Arc<dyn EmbeddingProvider>,
+    // Collection version its answers are cached under; the index never changes, and a reused
+    // cached index keeps it, so repeated questions about the same download hit the cache
+    pub version: u64,
+}
+
+impl AdHocIndex {
+    // Rough memory footprint: the text and the embedding vectors
+    fn size(&self) -> u64 {
+        self.documents
+            .iter()
+            .map(|doc| {
+                let chunks: usize = doc
+                    .chunks
+                    .iter()
+                    .map(|chunk| chunk.content.len() + chunk.embedding.as_ref().map_or(0, |e| e.len() * 4))
+                    .sum();
+                (doc.content.len() + chunks) as u64
+            })
+            .sum()
+    }
+}
+
+// What identifies the version of a download: the server's ETag and Last-Modified for
+// conditional requests, and a hash of the bytes for servers that send neither
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub content_hash: String,
+}
+
+// A cached download, as far as it has been processed
+pub enum Cached {
+    Indexed(Arc<AdHocIndex>),
+    // Read back from DOWNLOAD_CACHE_DIR: extracted and chunked, not embedded yet
+    Extracted(Box<Document>),
+}
+
+pub struct CacheLookup {
+    pub cached: Cached,
+    pub validators: Validators,
+    pub fetched_at: u64,
+    // Within the TTL, so it can be used without asking the server
+    pub fresh: bool,
+}
+
+struct Entry {
+    index: Arc<AdHocIndex>,
+    validators: Validators,
+    fetched_at: u64,
+    last_used: u64,
+    size: u64,
+}
+
+// On-disk form of an entry
+#[derive(Serialize, Deserialize)]
+struct StoredDownload {
+    url: String,
+    validators: Validators,
+    fetched_at: u64,
+    document: Document,
+}
+
+#[derive(Default)]
+struct Entries {
+    by_url: HashMap<String, Entry>,
+    total_size: u64,
+    // Incremented on every use, for least-recently-used eviction
+    clock: u64,
+}
+
+// Documents fetched by URL, so repeated questions about the same URL skip the download,
+// text extraction and embedding. Indexed documents are kept in memory up to
+// DOWNLOAD_CACHE_MAX_BYTES; with DOWNLOAD_CACHE_DIR set the extracted documents also survive
+// restarts. Entries older than DOWNLOAD_CACHE_TTL_SECS are revalidated with the server.
+pub struct DownloadCache {
+    entries: Mutex<Entries>,
+    dir: Option<PathBuf>,
+    ttl_secs: u64,
+    max_bytes: u64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl DownloadCache {
+    pub fn from_config(config: &Config) -> Self {
+        let dir = config.download_cache_dir.clone().filter(|_| config.download_cache_ttl_secs > 0);
+        if let Some(dir) = &dir {
+            if let Err(e) = std::fs::create_dir_all(dir) {
+                tracing::error!("Failed to create download cache directory {}: {}", dir.display(), e);
+            }
+        }
+        Self {
+            entries: Mutex::default(),
+            dir,
+            ttl_secs: config.download_cache_ttl_secs,
+            max_bytes: config.download_cache_max_bytes,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.ttl_secs > 0 && self.max_bytes > 0
+    }
+
+    pub fn lookup(&self, url: &str) -> Option<CacheLookup> {
+        if !self.enabled() {
+            return None;
+        }
+        let found = self.find(url);
+        let counter = if found.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        found
+    }
+
+    // Lookups since startup, stale entries included in the hits; None when the cache is disabled
+    pub fn stats(&self) -> Option<CacheStats> {
+        self.enabled()
+            .then(|| CacheStats { hits: self.hits.load(Ordering::Relaxed), misses: self.misses.load(Ordering::Relaxed) })
+    }
+
+    fn find(&self, url: &str) -> Option<CacheLookup> {
+        let now = unix_timestamp();
+        {
+            let mut entries = self.entries.lock().unwrap();
+            entries.clock += 1;
+            let clock = entries.clock;
+            if let Some(entry) = entries.by_url.get_mut(url) {
+                entry.last_used = clock;
+                return Some(CacheLookup {
+                    cached: Cached::Indexed(entry.index.clone()),
+                    validators: entry.validators.clone(),
+                    fetched_at: entry.fetched_at,
+                    fresh: now < entry.fetched_at + self.ttl_secs,
+                });
+            }
+        }
+
+        let stored = self.read_stored(url)?;
+        Some(CacheLookup {
+            cached: Cached::Extracted(Box::new(stored.document)),
+            validators: stored.validators,
+            fetched_at: stored.fetched_at,
+            fresh: now < stored.fetched_at + self.ttl_secs,
+        })
+    }
+
+    // Caches a freshly fetched (or revalidated) download
+    pub fn store(&self, url: &str, validators: Validators, index: Arc<AdHocIndex>) {
+        if !self.enabled() {
+            return;
+        }
+        let fetched_at = unix_timestamp();
+        if let Some(document) = index.documents.first() {
+            self.write_stored(url, &validators, fetched_at, document);
+        }
+        self.keep_in_memory(url, validators, fetched_at, index);
+    }
+
+    // Keeps an indexed download in memory, e.g. one read back from DOWNLOAD_CACHE_DIR, evicting
+    // the least recently used entries to stay under the size cap
+    pub fn keep_in_memory(&self, url: &str, validators: Validators, fetched_at: u64, index: Arc<AdHocIndex>) {
+        let size = index.size();
+        if !self.enabled() || size > self.max_bytes {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        entries.clock += 1;
+        let entry = Entry { index, validators, fetched_at, last_used: entries.clock, size };
+        if let Some(previous) = entries.by_url.insert(url.to_string(), entry) {
+            entries.total_size -= previous.size;
+        }
+        entries.total_size += size;
+        while entries.total_size > self.max_bytes {
+            let Some(oldest) = entries.by_url.iter().min_by_key(|(_, entry)| entry.last_used).map(|(url, _)| url.clone()) else {
+                break;
+            };
+            if let Some(evicted) = entries.by_url.remove(&oldest) {
+                entries.total_size -= evicted.size;
+                tracing::debug!("Evicted {} from the download cache", oldest);
+            }
+        }
+    }
+
+    fn stored_path(&self, url: &str) -> Option<PathBuf> {
+        let dir = self.dir.as_ref()?;
+        Some(dir.join(format!("{}.json", hex::encode(Sha256::digest(url.as_bytes())))))
+    }
+
+    fn read_stored(&self, url: &str) -> Option<StoredDownload> {
+        let path = self.stored_path(url)?;
+        let json = std::fs::read(&path).ok()?;
+        match serde_json::from_slice::<StoredDownload>(&json) {
+            Ok(stored) if stored.url == url => Some(stored),
+            Ok(_) => None,
+            Err(e) => {
+                tracing::warn!("Ignoring unreadable download cache file {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    // Saves the extracted document without its embeddings, which are cheap to recompute
+    // compared to the download and extraction, then trims the directory to the size cap
+    fn write_stored(&self, url: &str, validators: &Validators, fetched_at: u64, document: &Document) {
+        let (Some(dir), Some(path)) = (self.dir.as_ref(), self.stored_path(url)) else {
+            return;
+        };
+        let mut document = document.clone();
+        for chunk in document.chunks.iter_mut() {
+            chunk.embedding = None;
+        }
+        let stored = StoredDownload { url: url.to_string(), validators: validators.clone(), fetched_at, document };
+        let written = serde_json::to_vec(&stored)
+            .map_err(std::io::Error::from)
+            .and_then(|json| std::fs::write(&path, json));
+        if let Err(e) = written {
+            tracing::error!("Failed to write download cache file {}: {}", path.display(), e);
+        }
+        trim_dir(dir, self.max_bytes);
+    }
+}
+
+// Removes the least recently written files until the directory fits in `max_bytes`
+fn trim_dir(dir: &Path, max_bytes: u64) {
+    let Ok(read_dir) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let mut files: Vec<(SystemTime, u64, PathBuf)> = read_dir
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            Some((metadata.modified().ok()?, metadata.len(), entry.path()))
+        })
+        .collect();
+    let mut total: u64 = files.iter().map(|(_, len, _)| len).sum();
+    files.sort_by_key(|(modified, _, _)| *modified);
+    for (_, len, path) in files {
+        if total <= max_bytes {
+            break;
+        }
+        if std::fs::remove_file(&path).is_ok() {
+            total -= len;
+        }
+    }
+}
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
@@ -0,0 +1,215 @@
+use axum::{
+    body::Body,
+    extract::{
+        multipart::MultipartRejection,
+        rejection::{JsonRejection, QueryRejection},
+        FromRequest, FromRequestParts, Query, Request,
+    },
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use rag_system::RagError;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::REQUEST_ID_HEADER;
+
+// Body of every error response
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ErrorBody {
+    // Machine-readable code, e.g. "invalid_token" or "document_download_failed"
+    pub error: String,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub details: Option<serde_json::Value>,
+    // Same as the x-request-id response header, for quoting in bug reports
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+// Error returned by every handler and middleware
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub code: &'static str,
+    pub message: String,
+    pub details: Option<serde_json::Value>,
+    // Sent as the Retry-After header, in seconds
+    pub retry_after_secs: Option<u64>,
+    // A client error that may still go away, e.g. a document server that was unreachable
+    pub transient: bool,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            code,
+            message: message.into(),
+            details: None,
+            retry_after_secs: None,
+            transient: false,
+        }
+    }
+
+    pub fn bad_request(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, code, message)
+    }
+
+    pub fn unauthorized(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(StatusCode::UNAUTHORIZED, code, message)
+    }
+
+    pub fn forbidden(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(StatusCode::FORBIDDEN, code, message)
+    }
+
+    pub fn not_found(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, code, message)
+    }
+
+    pub fn unprocessable(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(StatusCode::UNPROCESSABLE_ENTITY, code, message)
+    }
+
+    pub fn unavailable(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(StatusCode::SERVICE_UNAVAILABLE, code, message)
+    }
+
+    pub fn internal(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, code, message)
+    }
+
+    // Whether trying again later may succeed: our own or an upstream failure rather than a
+    // problem with the request
+    pub fn is_retryable(&self) -> bool {
+        self.transient || self.status.is_server_error() || self.status == StatusCode::TOO_MANY_REQUESTS
+    }
+
+    pub fn transient(mut self) -> Self {
+        self.transient = true;
+        self
+    }
+
+    pub fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    pub fn with_retry_after(mut self, secs: u64) -> Self {
+        self.retry_after_secs = Some(secs);
+        self
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        if self.status.is_server_error() {
+            tracing::error!("{} ({}): {}", self.status, self.code, self.message);
+        }
+        let body = ErrorBody {
+            error: self.code.to_string(),
+            message: self.message,
+            details: self.details,
+            request_id: None,
+        };
+        // request_id_in_errors fills in the request id on the way out
+        let mut response = (self.status, Json(body.clone())).into_response();
+        if let Some(secs) = self.retry_after_secs {
+            response.headers_mut().insert(header::RETRY_AFTER, secs.into());
+        }
+        response.extensions_mut().insert(body);
+        response
+    }
+}
+
+// Malformed or mistyped JSON bodies get the same error shape as everything else
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        let code = match rejection.status() {
+            StatusCode::PAYLOAD_TOO_LARGE => "payload_too_large",
+            _ => "invalid_json",
+        };
+        Self::new(rejection.status(), code, rejection.body_text())
+    }
+}
+
+impl From<MultipartRejection> for ApiError {
+    fn from(rejection: MultipartRejection) -> Self {
+        Self::new(rejection.status(), "invalid_multipart", rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        Self::new(rejection.status(), "invalid_query", rejection.body_text())
+    }
+}
+
+// Library errors by cause: bad documents are the client's, LLM failures are upstream ones
+impl From<RagError> for ApiError {
+    fn from(e: RagError) -> Self {
+        let message = e.to_string();
+        match e {
+            RagError::Ingestion(_) => Self::unprocessable("ingestion_failed", message),
+            RagError::Embedding(_) => Self::internal("embedding_failed", message),
+            RagError::LlmApi { status: 429, .. } => Self::unavailable("llm_rate_limited", message),
+            RagError::LlmApi { status, .. } => {
+                Self::new(StatusCode::BAD_GATEWAY, "llm_error", message).with_details(serde_json::json!({ "status": status }))
+            }
+            RagError::Llm(_) => Self::new(StatusCode::BAD_GATEWAY, "llm_unavailable", message),
+            RagError::Unavailable { retry_after_secs, .. } => {
+                Self::unavailable("provider_unavailable", message).with_retry_after(retry_after_secs)
+            }
+            RagError::NotFound(_) => Self::not_found("not_found", message),
+            RagError::InvalidRequest(_) => Self::bad_request("invalid_request", message),
+            RagError::Config(_) => Self::internal("configuration_error", message),
+            RagError::Io(_) => Self::internal("io_error", message),
+            // Nobody is waiting for the response (the client left or the deadline passed);
+            // 499 as nginx logs it, so cancelled requests stand out
+            RagError::Cancelled => Self::new(
+                StatusCode::from_u16(499).expect("valid status code"),
+                "cancelled",
+                message,
+            ),
+        }
+    }
+}
+
+// Json extractor whose rejections are ApiErrors
+#[derive(FromRequest)]
+#[from_request(via(Json), rejection(ApiError))]
+pub struct ApiJson<T>(pub T);
+
+// Query string extractor whose rejections are ApiErrors
+#[derive(FromRequestParts)]
+#[from_request(via(Query), rejection(ApiError))]
+pub struct ApiQuery<T>(pub T);
+
+// Unknown routes
+pub async fn not_found() -> ApiError {
+    ApiError::not_found("not_found", "No such endpoint")
+}
+
+// Re-renders ApiError bodies with the request's x-request-id
+pub async fn request_id_in_errors(request: Request, next: Next) -> Response {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+
+    let response = next.run(request).await;
+    let Some(mut body) = response.extensions().get::<ErrorBody>().cloned() else {
+        return response;
+    };
+    body.request_id = request_id;
+
+    let (mut parts, _) = response.into_parts();
+    parts.headers.remove(header::CONTENT_LENGTH);
+    let json = serde_json::to_vec(&body).unwrap_or_default();
+    Response::from_parts(parts, Body::from(json))
+}
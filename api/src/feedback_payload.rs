@@ -0,0 +1,18 @@
+use rag_system::Rating;
+use serde::Deserialize;
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Deserialize, ToSchema)]
+pub struct FeedbackPayload {
+    // `query_id` returned with the answer being rated
+    pub query_id: String,
+    pub rating: Rating,
+    pub comment: Option<String>,
+}
+
+// Filters for GET /feedback
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FeedbackFilter {
+    pub rating: Option<Rating>,
+}
@@ -0,0 +1,374 @@
+use async_graphql::http::GraphiQLSource;
+use async_graphql::{
+    ComplexObject, Context, EmptySubscription, ErrorExtensions, InputObject, Json as GraphQLJson, Object, Schema,
+    SimpleObject, ID,
+};
+use axum::extract::Extension;
+use axum::response::Html;
+use axum::Json;
+use rag_system::models::{Citation, RetrievedChunk};
+use rag_system::{QueryResponse, RagError};
+use std::sync::{Arc, Mutex};
+
+use crate::answer_format::strip_markdown;
+use crate::audit::{AnsweredQuestions, AuditItem};
+use crate::auth::Claims;
+use crate::error::{ApiError, ApiJson};
+use crate::inline_document::InlineDocument;
+use crate::pagination::PageParams;
+use crate::query_payload::QueryPayload;
+use crate::retrieval_options::RetrievalOptions;
+use crate::tenants::Collection;
+use crate::utils::{ad_hoc_index, answer_questions, tenant_collection};
+use crate::AppState;
+
+pub type RagSchema = Schema<QueryRoot, MutationRoot, EmptySubscription>;
+
+// Questions answered while executing one GraphQL request, for usage and the audit log
+type Answered = Mutex<Vec<AuditItem>>;
+
+pub fn schema(state: Arc<AppState>) -> RagSchema {
+    Schema::build(QueryRoot, MutationRoot, EmptySubscription)
+        .data(state)
+        // Documents -> chunks is as deep as the schema goes; this stops abusive nesting
+        .limit_depth(10)
+        .finish()
+}
+
+// Handler for POST /graphql
+pub async fn handle_graphql(
+    Extension(schema): Extension<RagSchema>,
+    Extension(claims): Extension<Claims>,
+    ApiJson(request): ApiJson<async_graphql::Request>,
+) -> (Extension<AnsweredQuestions>, Json<async_graphql::Response>) {
+    let answered = Arc::new(Answered::default());
+    let response = schema.execute(request.data(claims).data(answered.clone())).await;
+    let answered = std::mem::take(&mut *answered.lock().unwrap());
+    (Extension(AnsweredQuestions(answered)), Json(response))
+}
+
+// Handler for GET /graphql: the GraphiQL IDE. Its requests need the Authorization header,
+// which can be set in the IDE's headers tab.
+pub async fn graphiql() -> Html<String> {
+    Html(GraphiQLSource::build().endpoint("/graphql").finish())
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    // The caller's indexed documents, optionally filtered by filename substring or tag
+    async fn documents(
+        &self,
+        ctx: &Context<'_>,
+        filename: Option<String>,
+        tag: Option<String>,
+        limit: Option<usize>,
+        offset: Option<usize>,
+    ) -> async_graphql::Result<DocumentPage> {
+        let collection = collection(ctx).await?;
+        let documents = collection.read().await;
+        let filename = filename.as_deref().map(str::to_lowercase);
+
+        let principal = ctx.data::<Claims>()?.principal();
+        let matching = documents
+            .iter()
+            .filter(|doc| doc.readable_by(Some(&principal)))
+            .filter(|doc| filename.as_ref().is_none_or(|part| doc.filename.to_lowercase().contains(part)))
+            .filter(|doc| tag.as_ref().is_none_or(|tag| doc.metadata.tags.contains(tag)))
+            .map(DocumentNode::new);
+        let page = PageParams { limit, offset }.paginate(matching);
+        Ok(DocumentPage { items: page.items, total: page.total, next_offset: page.next_offset })
+    }
+
+    async fn document(&self, ctx: &Context<'_>, id: ID) -> async_graphql::Result<Option<DocumentNode>> {
+        let collection = collection(ctx).await?;
+        let documents = collection.read().await;
+        let principal = ctx.data::<Claims>()?.principal();
+        Ok(documents
+            .iter()
+            .find(|doc| doc.id == *id && doc.readable_by(Some(&principal)))
+            .map(DocumentNode::new))
+    }
+
+    // Ranked chunks for a query, with their scores, without generating an answer
+    async fn search(&self, ctx: &Context<'_>, input: QueryInput) -> async_graphql::Result<Vec<ScoredChunk>> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        let claims = ctx.data::<Claims>()?;
+        let payload = input.into_payload();
+        payload.validate(&state.config).map_err(graphql_error)?;
+        let mut request = payload.options.to_request(payload.query.clone(), &state.config);
+        request.tenant = Some(claims.tenant.clone());
+        request.principal = Some(claims.principal());
+        let query_service = &state.rag_library.query_service;
+
+        let result = match payload.document_source() {
+            Some(source) => {
+                let index = ad_hoc_index(state, source).await.map_err(graphql_error)?;
+                query_service.retrieve_with_embeddings(&request, &index.documents, index.embeddings.as_ref()).await
+            }
+            None => {
+                let collection = collection(ctx).await?;
+                let documents = collection.read().await;
+                query_service.retrieve_with_embeddings(&request, &documents, collection.embeddings().as_ref()).await
+            }
+        };
+        Ok(result?.chunks.into_iter().map(ScoredChunk::from).collect())
+    }
+}
+
+pub struct MutationRoot;
+
+#[Object]
+impl MutationRoot {
+    // Answers a question, like POST /query
+    async fn query(&self, ctx: &Context<'_>, input: QueryInput) -> async_graphql::Result<Answer> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        let claims = ctx.data::<Claims>()?;
+        let payload = input.into_payload();
+        payload.validate(&state.config).map_err(graphql_error)?;
+        let _permit = state.answer_limiter.acquire().await.map_err(graphql_error)?;
+
+        let question = payload.options.to_request(payload.query.clone(), &state.config);
+        let result = answer_questions(state, claims, payload.document_source(), vec![question])
+            .await
+            .map_err(graphql_error)?
+            .pop()
+            .unwrap_or_else(|| Err(RagError::Llm("No response generated".to_string())));
+
+        let item = match &result {
+            Ok(response) => AuditItem::answered(&payload.query, response),
+            Err(e) => AuditItem::failed(&payload.query, &e.to_string()),
+        };
+        ctx.data::<Arc<Answered>>()?.lock().unwrap().push(item);
+        result.map(Answer::from).map_err(|e| graphql_error(e.into()))
+    }
+}
+
+// A question, optionally about one document instead of the caller's collection
+#[derive(InputObject)]
+pub struct QueryInput {
+    pub query: String,
+    pub document_url: Option<String>,
+    pub document: Option<InlineDocumentInput>,
+    pub max_results: Option<usize>,
+    // Restrict retrieval to these indexed documents (by id or filename)
+    pub document_ids: Option<Vec<String>>,
+    pub filenames: Option<Vec<String>>,
+    pub score_threshold: Option<f32>,
+}
+
+#[derive(InputObject)]
+pub struct InlineDocumentInput {
+    pub content_base64: String,
+    pub mime_type: String,
+    pub filename: Option<String>,
+}
+
+impl QueryInput {
+    // The same payload POST /query takes, so the same validation applies
+    fn into_payload(self) -> QueryPayload {
+        QueryPayload {
+            query: self.query,
+            pdf_url: self.document_url,
+            document: self.document.map(|document| InlineDocument {
+                content_base64: document.content_base64,
+                mime_type: document.mime_type,
+                filename: document.filename,
+            }),
+            options: RetrievalOptions {
+                max_results: self.max_results,
+                document_ids: self.document_ids,
+                filenames: self.filenames,
+                score_threshold: self.score_threshold,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct DocumentPage {
+    pub items: Vec<DocumentNode>,
+    // Documents matching the filters
+    pub total: usize,
+    // Offset of the following page; null on the last page
+    pub next_offset: Option<usize>,
+}
+
+#[derive(SimpleObject)]
+#[graphql(name = "Document", complex)]
+pub struct DocumentNode {
+    pub id: ID,
+    pub filename: String,
+    pub chunk_count: usize,
+    pub tags: Vec<String>,
+    pub version: Option<String>,
+    // Unix timestamp of the document version
+    pub updated_at: Option<u64>,
+    // File the document was ingested from
+    pub source: Option<String>,
+}
+
+impl DocumentNode {
+    fn new(document: &rag_system::models::Document) -> Self {
+        Self {
+            id: ID(document.id.clone()),
+            filename: document.filename.clone(),
+            chunk_count: document.chunks.len(),
+            tags: document.metadata.tags.clone(),
+            version: document.metadata.version.clone(),
+            updated_at: document.metadata.updated_at,
+            source: document.metadata.source.clone(),
+        }
+    }
+}
+
+#[ComplexObject]
+impl DocumentNode {
+    // The document's chunks in document order, optionally only those containing `contains`
+    async fn chunks(
+        &self,
+        ctx: &Context<'_>,
+        contains: Option<String>,
+        limit: Option<usize>,
+        offset: Option<usize>,
+    ) -> async_graphql::Result<Vec<Chunk>> {
+        let collection = collection(ctx).await?;
+        let documents = collection.read().await;
+        let Some(document) = documents.iter().find(|doc| doc.id == *self.id) else {
+            return Ok(Vec::new());
+        };
+        let contains = contains.as_deref().map(str::to_lowercase);
+
+        let matching = document
+            .chunks
+            .iter()
+            .enumerate()
+            .filter(|(_, chunk)| contains.as_ref().is_none_or(|part| chunk.content.to_lowercase().contains(part)))
+            .map(|(index, chunk)| Chunk {
+                id: ID(chunk.id.clone()),
+                index,
+                content: chunk.content.to_string(),
+                start_position: chunk.start_position,
+                end_position: chunk.end_position,
+            });
+        Ok(PageParams { limit, offset }.paginate(matching).items)
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct Chunk {
+    pub id: ID,
+    // Position of the chunk within the document
+    pub index: usize,
+    pub content: String,
+    pub start_position: usize,
+    pub end_position: usize,
+}
+
+#[derive(SimpleObject)]
+pub struct ScoredChunk {
+    pub document_id: ID,
+    pub filename: String,
+    pub chunk_id: ID,
+    pub content: String,
+    // Final ranking score, after fusion and keyword boosts
+    pub score: f32,
+    // Raw similarity to the query
+    pub similarity: f32,
+}
+
+impl From<RetrievedChunk> for ScoredChunk {
+    fn from(chunk: RetrievedChunk) -> Self {
+        Self {
+            document_id: ID(chunk.document_id),
+            filename: chunk.filename,
+            chunk_id: ID(chunk.chunk_id),
+            content: chunk.content,
+            score: chunk.score,
+            similarity: chunk.similarity,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(complex)]
+pub struct Answer {
+    pub query_id: ID,
+    // As generated, usually Markdown
+    pub answer: String,
+    pub status: String,
+    pub citations: Vec<AnswerCitation>,
+    pub processing_time_ms: u64,
+    pub rewritten_query: Option<String>,
+    pub translated_query: Option<String>,
+    // How sure the system is of the answer, in [0, 1]; null when the question skipped retrieval
+    pub confidence: Option<f32>,
+    // Typed decision in decision mode
+    pub decision: Option<GraphQLJson<serde_json::Value>>,
+    // Values the retrieved documents disagree on
+    pub conflicts: GraphQLJson<serde_json::Value>,
+}
+
+#[ComplexObject]
+impl Answer {
+    // The answer with Markdown markup removed
+    async fn plain_answer(&self) -> String {
+        strip_markdown(&self.answer)
+    }
+}
+
+impl From<QueryResponse> for Answer {
+    fn from(response: QueryResponse) -> Self {
+        Self {
+            query_id: ID(response.query_id),
+            answer: response.response,
+            status: response.status,
+            citations: response.citations.into_iter().map(AnswerCitation::from).collect(),
+            processing_time_ms: response.processing_time_ms as u64,
+            rewritten_query: response.rewritten_query,
+            translated_query: response.translated_query,
+            confidence: response.confidence_score,
+            decision: response.decision.and_then(|decision| serde_json::to_value(decision).ok()).map(GraphQLJson),
+            conflicts: GraphQLJson(serde_json::to_value(response.conflicts).unwrap_or_default()),
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct AnswerCitation {
+    pub document: String,
+    pub chunk_id: ID,
+    pub excerpt: String,
+    pub confidence: f32,
+}
+
+impl From<Citation> for AnswerCitation {
+    fn from(citation: Citation) -> Self {
+        Self {
+            document: citation.document,
+            chunk_id: ID(citation.chunk_id),
+            excerpt: citation.text_excerpt,
+            confidence: citation.confidence_score,
+        }
+    }
+}
+
+async fn collection(ctx: &Context<'_>) -> async_graphql::Result<Arc<Collection>> {
+    let state = ctx.data::<Arc<AppState>>()?;
+    let claims = ctx.data::<Claims>()?;
+    tenant_collection(state, &claims.tenant).await.map_err(graphql_error)
+}
+
+// GraphQL error with the API error's machine-readable code (and details) as extensions
+fn graphql_error(error: ApiError) -> async_graphql::Error {
+    async_graphql::Error::new(error.message).extend_with(|_, extensions| {
+        extensions.set("code", error.code);
+        if let Some(details) = &error.details {
+            if let Ok(details) = async_graphql::Value::from_json(details.clone()) {
+                extensions.set("details", details);
+            }
+        }
+    })
+}
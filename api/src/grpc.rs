@@ -0,0 +1,332 @@
+// tonic::Status is what every gRPC method returns, however large clippy finds it
+#![allow(clippy::result_large_err)]
+
+use anyhow::Result;
+use axum::http::StatusCode;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use rag_system::models::StreamEvent;
+use rag_system::{usage, QueryResponse, RagError, TokenUsage};
+use std::future::Future;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status};
+use tracing::Instrument;
+
+use crate::audit::{AuditContext, AuditItem};
+use crate::auth::{Claims, TokenType};
+use crate::deadline::Deadline;
+use crate::error::ApiError;
+use crate::hackrx_request::HackRxRequest;
+use crate::inline_document::{DocumentSource, InlineDocument};
+use crate::query_payload::QueryPayload;
+use crate::retrieval_options::RetrievalOptions;
+use crate::ingest_queue::IngestTask;
+use crate::utils::{answer_in_batches, answer_questions, fetch_document, index_and_wait, spawn_streaming_answer};
+use crate::validation::Validator;
+use crate::{AppState, REQUEST_ID_HEADER};
+
+pub mod proto {
+    tonic::include_proto!("hackrx.rag.v1");
+}
+
+use proto::rag_server::{Rag, RagServer};
+
+// The gRPC face of the API: same state, tokens, limits, usage accounting and audit log as
+// the REST handlers
+pub struct RagService {
+    state: Arc<AppState>,
+}
+
+// Serves the gRPC service on GRPC_PORT until `shutdown` resolves
+pub async fn serve<F>(state: Arc<AppState>, address: String, shutdown: F) -> Result<()>
+where
+    F: Future<Output = ()> + Send,
+{
+    let address = tokio::net::lookup_host(&address)
+        .await?
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("GRPC address {} does not resolve", address))?;
+    tonic::transport::Server::builder()
+        .add_service(RagServer::new(RagService { state }))
+        .serve_with_shutdown(address, shutdown)
+        .await?;
+    Ok(())
+}
+
+impl RagService {
+    // Same checks as auth_middleware, on the `authorization` metadata
+    fn authenticate<T>(&self, request: &Request<T>, method: &str) -> Result<(Claims, AuditContext), Status> {
+        let metadata = request.metadata();
+        let token = metadata
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| Status::unauthenticated("authorization metadata with a Bearer token is required"))?;
+        let claims = self.state.auth.validate(token.trim(), TokenType::Access).map_err(status)?;
+        tracing::info!("Authenticated {} of tenant {} (token {}) over gRPC", claims.sub, claims.tenant, claims.jti);
+
+        let audit = AuditContext {
+            request_id: metadata.get(REQUEST_ID_HEADER).and_then(|value| value.to_str().ok()).map(str::to_string),
+            endpoint: format!("gRPC {}", method),
+        };
+        Ok((claims, audit))
+    }
+
+    // Records the call and the questions it answered, as record_usage does for REST requests
+    fn record(&self, claims: &Claims, audit: &AuditContext, answered: Vec<AuditItem>, usage: TokenUsage) {
+        self.state.usage.record(claims, 1, answered.len() as u64, usage);
+        self.state.audit.record(claims, audit, answered, usage);
+    }
+
+    fn retrieval_options(&self, max_results: u32, document_ids: Vec<String>, filenames: Vec<String>) -> RetrievalOptions {
+        RetrievalOptions {
+            document_ids: Some(document_ids).filter(|ids| !ids.is_empty()),
+            filenames: Some(filenames).filter(|names| !names.is_empty()),
+            max_results: (max_results > 0).then_some(max_results as usize),
+            ..Default::default()
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Rag for RagService {
+    async fn ingest(&self, request: Request<proto::IngestRequest>) -> Result<Response<proto::IngestResponse>, Status> {
+        let (claims, _) = self.authenticate(&request, "Ingest")?;
+        let (url, inline) = document_source(request.into_inner().document);
+        let config = &self.state.config;
+
+        let mut validator = Validator::default();
+        match (&url, &inline) {
+            (Some(url), None) => validator.document_url("document.url", url, config),
+            (None, Some(document)) => validator.inline_document("document.inline", document, config),
+            _ => validator.error("document", "is required"),
+        }
+        validator.finish().map_err(status)?;
+        if self.state.readiness.is_draining() {
+            return Err(Status::unavailable("Server is shutting down"));
+        }
+
+        let Some(source) = DocumentSource::from_request(url.as_deref(), inline.as_ref()) else {
+            return Err(Status::invalid_argument("document is required"));
+        };
+        let document = fetch_document(source, config).await.map_err(status)?;
+        let (document_id, filename, chunks) = (document.id.clone(), document.filename.clone(), document.chunks.len());
+        let task = IngestTask::Upload { documents: vec![document] };
+        let total_documents = index_and_wait(&self.state, &claims.tenant, task).await.map_err(status)?.total_documents;
+        tracing::info!("Ingested {} over gRPC for tenant {}, {} total", filename, claims.tenant, total_documents);
+        self.state.usage.record(&claims, 1, 0, TokenUsage::default());
+
+        Ok(Response::new(proto::IngestResponse {
+            document_id,
+            filename,
+            chunks: chunks as u32,
+            total_documents: total_documents as u32,
+        }))
+    }
+
+    async fn query(&self, request: Request<proto::QueryRequest>) -> Result<Response<proto::Answer>, Status> {
+        let (claims, audit) = self.authenticate(&request, "Query")?;
+        let payload = self.query_payload(request.into_inner());
+        payload.validate(&self.state.config).map_err(status)?;
+        let _permit = self.state.answer_limiter.acquire().await.map_err(status)?;
+
+        let question = payload.options.to_request(payload.query.clone(), &self.state.config);
+        let answering = answer_questions(&self.state, &claims, payload.document_source(), vec![question]);
+        let (results, usage) = usage::track(answering).await;
+        let result = results
+            .map_err(status)?
+            .pop()
+            .unwrap_or_else(|| Err(RagError::Llm("No response generated".to_string())));
+
+        let item = match &result {
+            Ok(response) => AuditItem::answered(&payload.query, response),
+            Err(e) => AuditItem::failed(&payload.query, &e.to_string()),
+        };
+        self.record(&claims, &audit, vec![item], usage);
+        result.map(|response| Response::new(answer(response))).map_err(|e| status(e.into()))
+    }
+
+    async fn batch_query(
+        &self,
+        request: Request<proto::BatchQueryRequest>,
+    ) -> Result<Response<proto::BatchQueryResponse>, Status> {
+        let (claims, audit) = self.authenticate(&request, "BatchQuery")?;
+        let request = request.into_inner();
+        let (url, inline) = document_source(request.document);
+        let payload = HackRxRequest {
+            documents: url.unwrap_or_default(),
+            document: inline,
+            questions: request.questions,
+            callback_url: None,
+            options: self.retrieval_options(request.max_results, request.document_ids, request.filenames),
+        };
+        payload.validate(&self.state.config).map_err(status)?;
+        let _permit = self.state.answer_limiter.acquire().await.map_err(status)?;
+
+        let questions: Vec<_> = payload
+            .questions
+            .iter()
+            .map(|question| payload.options.to_request(question.clone(), &self.state.config))
+            .collect();
+        let deadline = self.state.config.request_timeout().map(|timeout| Deadline(Instant::now() + timeout));
+        let mut answers = Vec::new();
+        let mut answered = Vec::new();
+        let answering = answer_in_batches(&self.state, &claims, payload.document_source(), questions, deadline, |batch, results| {
+            for (question, result) in batch.iter().zip(results) {
+                match result {
+                    Ok(response) => {
+                        answered.push(AuditItem::answered(&question.query, &response));
+                        answers.push(answer(response));
+                    }
+                    Err(e) => {
+                        answered.push(AuditItem::failed(&question.query, &e.to_string()));
+                        answers.push(failed_answer(e.to_string()));
+                    }
+                }
+            }
+        });
+        let (completed, usage) = usage::track(answering).await;
+        let completed = completed.map_err(status)?;
+        self.record(&claims, &audit, answered, usage);
+
+        if !completed {
+            tracing::warn!("Deadline reached with {} of {} gRPC question(s) unanswered", payload.questions.len() - answers.len(), payload.questions.len());
+            answers.resize_with(payload.questions.len(), || failed_answer("Not answered before the request deadline".to_string()));
+        }
+        Ok(Response::new(proto::BatchQueryResponse { answers, timed_out: !completed }))
+    }
+
+    type StreamAnswerStream = ReceiverStream<Result<proto::AnswerEvent, Status>>;
+
+    async fn stream_answer(
+        &self,
+        request: Request<proto::QueryRequest>,
+    ) -> Result<Response<Self::StreamAnswerStream>, Status> {
+        let (claims, audit) = self.authenticate(&request, "StreamAnswer")?;
+        let payload = self.query_payload(request.into_inner());
+        payload.validate(&self.state.config).map_err(status)?;
+        // Held until the stream ends
+        let permit = self.state.answer_limiter.acquire().await.map_err(status)?;
+        // The answer itself is recorded by the streaming task
+        self.state.usage.record(&claims, 1, 0, TokenUsage::default());
+
+        let question = payload.options.to_request(payload.query.clone(), &self.state.config);
+        let (events_tx, mut events_rx) = mpsc::channel::<StreamEvent>(32);
+        let source = payload.document_source();
+        let answer_task = spawn_streaming_answer(self.state.clone(), claims, audit, source, question, events_tx)
+            .await
+            .map_err(status)?;
+
+        let (tx, rx) = mpsc::channel(64);
+        tokio::spawn(async move {
+            let _permit = permit;
+            while let Some(event) = events_rx.recv().await {
+                // The client cancelled the call; dropping the events stops the answer
+                if tx.send(Ok(answer_event(event))).await.is_err() {
+                    break;
+                }
+            }
+            drop(events_rx);
+            let last = match answer_task.await {
+                Ok(Ok(response)) => Ok(proto::AnswerEvent { event: Some(proto::answer_event::Event::Done(answer(response))) }),
+                Ok(Err(e)) => Err(status(e.into())),
+                Err(e) => Err(Status::internal(e.to_string())),
+            };
+            let _ = tx.send(last).await;
+        }.in_current_span());
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+}
+
+impl RagService {
+    fn query_payload(&self, request: proto::QueryRequest) -> QueryPayload {
+        let (url, inline) = document_source(request.document);
+        QueryPayload {
+            query: request.query,
+            pdf_url: url,
+            document: inline,
+            options: self.retrieval_options(request.max_results, request.document_ids, request.filenames),
+        }
+    }
+}
+
+// The URL or inline document of a request, in the form the REST payloads use, so the same
+// validation applies
+fn document_source(document: Option<proto::DocumentSource>) -> (Option<String>, Option<InlineDocument>) {
+    match document.and_then(|document| document.source) {
+        Some(proto::document_source::Source::Url(url)) => (Some(url), None),
+        Some(proto::document_source::Source::Inline(inline)) => (
+            None,
+            Some(InlineDocument {
+                content_base64: STANDARD.encode(inline.content),
+                mime_type: inline.mime_type,
+                filename: Some(inline.filename).filter(|name| !name.is_empty()),
+            }),
+        ),
+        None => (None, None),
+    }
+}
+
+fn answer(response: QueryResponse) -> proto::Answer {
+    proto::Answer {
+        query_id: response.query_id,
+        answer: response.response,
+        status: response.status,
+        citations: response
+            .citations
+            .into_iter()
+            .map(|citation| proto::Citation {
+                document: citation.document,
+                chunk_id: citation.chunk_id,
+                excerpt: citation.text_excerpt,
+                confidence: citation.confidence_score,
+            })
+            .collect(),
+        confidence: response.confidence_score.unwrap_or_default(),
+    }
+}
+
+fn failed_answer(error: String) -> proto::Answer {
+    proto::Answer {
+        answer: error,
+        status: "error".to_string(),
+        ..Default::default()
+    }
+}
+
+fn answer_event(event: StreamEvent) -> proto::AnswerEvent {
+    use proto::answer_event::Event;
+    let event = match event {
+        StreamEvent::Status { stage } => Event::Status(stage),
+        StreamEvent::Retrieved { chunks, documents } => Event::Retrieved(proto::Retrieved { chunks: chunks as u32, documents }),
+        StreamEvent::Delta { text } => Event::Delta(text),
+    };
+    proto::AnswerEvent { event: Some(event) }
+}
+
+// gRPC status for an API error, keeping its machine-readable code and details in the message
+fn status(error: ApiError) -> Status {
+    let message = match &error.details {
+        Some(details) => format!("{}: {} {}", error.code, error.message, details),
+        None => format!("{}: {}", error.code, error.message),
+    };
+    match error.status {
+        StatusCode::BAD_REQUEST
+        | StatusCode::UNPROCESSABLE_ENTITY
+        | StatusCode::PAYLOAD_TOO_LARGE
+        | StatusCode::UNSUPPORTED_MEDIA_TYPE => {
+            Status::invalid_argument(message)
+        }
+        StatusCode::UNAUTHORIZED => Status::unauthenticated(message),
+        StatusCode::FORBIDDEN => Status::permission_denied(message),
+        StatusCode::NOT_FOUND => Status::not_found(message),
+        StatusCode::TOO_MANY_REQUESTS => Status::resource_exhausted(message),
+        StatusCode::SERVICE_UNAVAILABLE | StatusCode::BAD_GATEWAY => Status::unavailable(message),
+        StatusCode::GATEWAY_TIMEOUT => Status::deadline_exceeded(message),
+        _ => Status::internal(message),
+    }
+}
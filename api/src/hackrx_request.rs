@@ -1,7 +1,53 @@
+use crate::config::Config;
+use crate::error::ApiError;
+use crate::inline_document::{DocumentSource, InlineDocument};
+use crate::retrieval_options::RetrievalOptions;
+use crate::validation::Validator;
 use serde::Deserialize;
+use utoipa::ToSchema;
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct HackRxRequest {
+    // URL of the document to answer from; empty (or absent) uses the tenant's collection
+    #[serde(default)]
     pub documents: String,
+    // The document itself, instead of a URL
+    pub document: Option<InlineDocument>,
     pub questions: Vec<String>,
+    // When set the run happens in the background: the response is the job and the answers
+    // are POSTed here once it finishes (see WebhookEvent)
+    pub callback_url: Option<String>,
+    #[serde(flatten)]
+    pub options: RetrievalOptions,
+}
+
+impl HackRxRequest {
+    // Rejects oversized requests before any document is downloaded or question answered
+    pub fn validate(&self, config: &Config) -> Result<(), ApiError> {
+        let mut validator = Validator::default();
+        validator.document_url("documents", &self.documents, config);
+        if let Some(document) = &self.document {
+            validator.inline_document("document", document, config);
+            if !self.documents.trim().is_empty() {
+                validator.error("document", "cannot be combined with documents");
+            }
+        }
+        if self.questions.is_empty() {
+            validator.error("questions", "must contain at least one question");
+        } else if self.questions.len() > config.max_questions {
+            validator.error("questions", format!("must contain at most {} questions", config.max_questions));
+        }
+        for (i, question) in self.questions.iter().enumerate().take(config.max_questions) {
+            validator.question(format!("questions[{}]", i), question, config);
+        }
+        if let Some(url) = &self.callback_url {
+            validator.callback_url("callback_url", url, config);
+        }
+        validator.retrieval_options(&self.options, config);
+        validator.finish()
+    }
+
+    pub fn document_source(&self) -> Option<DocumentSource<'_>> {
+        DocumentSource::from_request(Some(&self.documents), self.document.as_ref())
+    }
 }
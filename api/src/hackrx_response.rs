@@ -1,6 +1,172 @@
+use crate::answer_format::{AnswerFormat, StructuredAnswer};
+use crate::audit::AuditItem;
+use crate::citations_csv::CitationRow;
+use rag_system::models::{Decision, QueryDebug, RetrievalScores};
+use rag_system::{QueryResponse, RagError};
 use serde::Serialize;
+use utoipa::ToSchema;
 
-#[derive(Serialize)]
+// How answering one question went
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AnswerStatus {
+    Answered,
+    // The documents did not cover the question, so the answer says so instead
+    InsufficientContext,
+    Error,
+    // Not attempted before the request deadline
+    TimedOut,
+    // A provider is failing, so the answer is a stale cached one or says it is unavailable
+    Degraded,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AnswerDetails {
+    pub status: AnswerStatus,
+    // Retrieval and generation time for this question; questions answered in one LLM batch
+    // share the batch's time
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub processing_time_ms: Option<u128>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retrieval: Option<RetrievalScores>,
+    // How sure the system is of the answer, in [0, 1]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
 pub struct HackRxResponse {
     pub answers: Vec<String>,
+    // One entry per answer, in the same order
+    pub results: Vec<AnswerDetails>,
+    // Ids for rating each answer via POST /feedback; empty where a question failed
+    pub query_ids: Vec<String>,
+    // One entry per question, only when the request asked for debug output
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub debug: Option<Vec<Option<QueryDebug>>>,
+    // One entry per question in decision mode; None where no decision could be parsed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub decisions: Option<Vec<Option<Decision>>>,
+    // One entry per question with format "json"; None where a question failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub structured: Option<Vec<Option<StructuredAnswer>>>,
+    // Set when the request deadline passed before every question was answered; the remaining
+    // answers say so instead of answering
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub timed_out: bool,
+    #[serde(skip)]
+    pub unanswered: usize,
+    #[serde(skip)]
+    format: AnswerFormat,
+    // The questions answered so far, for the audit log
+    #[serde(skip)]
+    pub audit: Vec<AuditItem>,
+    // With format "csv", the citations of every answer so far
+    #[serde(skip)]
+    pub citations: Vec<CitationRow>,
+}
+
+impl HackRxResponse {
+    pub fn new(with_debug: bool, with_decisions: bool, format: AnswerFormat) -> Self {
+        Self {
+            answers: Vec::new(),
+            results: Vec::new(),
+            query_ids: Vec::new(),
+            debug: with_debug.then(Vec::new),
+            decisions: with_decisions.then(Vec::new),
+            structured: (format == AnswerFormat::Json).then(Vec::new),
+            timed_out: false,
+            unanswered: 0,
+            format,
+            audit: Vec::new(),
+            citations: Vec::new(),
+        }
+    }
+
+    // Appends the answer to the next question; failed questions get an error message in place
+    // of the answer
+    pub fn push(&mut self, question: &str, result: Result<QueryResponse, RagError>) {
+        let (query_id, answer, debug, decision, structured) = match result {
+            Ok(response) => {
+                self.audit.push(AuditItem::answered(question, &response));
+                self.results.push(AnswerDetails {
+                    status: match response.status.as_str() {
+                        "insufficient_information" => AnswerStatus::InsufficientContext,
+                        "degraded" => AnswerStatus::Degraded,
+                        _ => AnswerStatus::Answered,
+                    },
+                    processing_time_ms: Some(response.processing_time_ms),
+                    retrieval: response.retrieval_scores,
+                    confidence: response.confidence_score,
+                    error: None,
+                });
+                let structured = self.format.structured(&response);
+                let answer = self.format.render(&response.response);
+                if self.format == AnswerFormat::Csv {
+                    self.citations.extend(CitationRow::from_response(question, &answer, &response));
+                }
+                (response.query_id, answer, response.debug, response.decision, structured)
+            }
+            Err(e) => {
+                let e = e.to_string();
+                tracing::error!("Error processing question '{}': {}", question, e);
+                self.audit.push(AuditItem::failed(question, &e));
+                let answer = format!("Error processing question: {}", e);
+                if self.format == AnswerFormat::Csv {
+                    self.citations.push(CitationRow::unanswered(question, &answer));
+                }
+                self.results.push(AnswerDetails {
+                    status: AnswerStatus::Error,
+                    processing_time_ms: None,
+                    retrieval: None,
+                    confidence: None,
+                    error: Some(e),
+                });
+                (String::new(), answer, None, None, None)
+            }
+        };
+        self.query_ids.push(query_id);
+        self.answers.push(answer);
+        if let Some(all) = &mut self.debug {
+            all.push(debug);
+        }
+        if let Some(all) = &mut self.decisions {
+            all.push(decision);
+        }
+        if let Some(all) = &mut self.structured {
+            all.push(structured);
+        }
+    }
+
+    // Fills in the questions left when the deadline passed
+    pub fn time_out(&mut self, remaining: &[String]) {
+        const NOT_ANSWERED: &str = "Not answered: the request deadline was reached";
+        self.timed_out = true;
+        self.unanswered += remaining.len();
+        for question in remaining {
+            self.query_ids.push(String::new());
+            self.answers.push(NOT_ANSWERED.to_string());
+            if self.format == AnswerFormat::Csv {
+                self.citations.push(CitationRow::unanswered(question, NOT_ANSWERED));
+            }
+            self.results.push(AnswerDetails {
+                status: AnswerStatus::TimedOut,
+                processing_time_ms: None,
+                retrieval: None,
+                confidence: None,
+                error: None,
+            });
+            if let Some(all) = &mut self.debug {
+                all.push(None);
+            }
+            if let Some(all) = &mut self.decisions {
+                all.push(None);
+            }
+            if let Some(all) = &mut self.structured {
+                all.push(None);
+            }
+        }
+    }
 }
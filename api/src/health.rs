@@ -0,0 +1,247 @@
+use axum::{extract::State, http::StatusCode, Json};
+use serde::Serialize;
+use anyhow::{anyhow, Result};
+use rag_system::GeminiService;
+use std::env;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tokio::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use utoipa::ToSchema;
+
+use crate::metadata_store::MetadataStore;
+use crate::tenants::DEFAULT_TENANT;
+use crate::AppState;
+
+// Progress of the startup ingestion of DOCUMENTS_DIR
+#[derive(Debug, Clone)]
+pub enum IndexState {
+    Loading,
+    Loaded { documents: usize },
+    Failed(String),
+}
+
+pub struct Readiness {
+    index: RwLock<IndexState>,
+    // Set once shutdown starts, so load balancers stop sending new traffic
+    draining: AtomicBool,
+}
+
+impl Default for Readiness {
+    fn default() -> Self {
+        Self {
+            index: RwLock::new(IndexState::Loading),
+            draining: AtomicBool::new(false),
+        }
+    }
+}
+
+impl Readiness {
+    pub fn set_index(&self, state: IndexState) {
+        *self.index.write().unwrap() = state;
+    }
+
+    pub fn index(&self) -> IndexState {
+        self.index.read().unwrap().clone()
+    }
+
+    pub fn start_draining(&self) {
+        self.draining.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ReadinessCheck {
+    pub name: String,
+    pub ok: bool,
+    pub detail: String,
+    // How long the probe took; only set by /health/deep
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ReadinessReport {
+    // "ready" or "not_ready"
+    pub status: String,
+    pub checks: Vec<ReadinessCheck>,
+}
+
+// Liveness: the process is up and serving requests
+#[utoipa::path(get, path = "/healthz", tag = "health", responses((status = 200, description = "Process is alive", body = String)))]
+pub async fn healthz() -> &'static str {
+    "OK"
+}
+
+// Readiness: the startup index is loaded and the answer pipeline is usable
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    tag = "health",
+    responses(
+        (status = 200, description = "Ready to serve queries", body = ReadinessReport),
+        (status = 503, description = "Still loading, misconfigured or shutting down", body = ReadinessReport),
+    )
+)]
+pub async fn readyz(State(state): State<Arc<AppState>>) -> (StatusCode, Json<ReadinessReport>) {
+    let index = match state.readiness.index() {
+        IndexState::Loading => {
+            let detail = match state.rag_library.ingest_progress() {
+                // Those are already searchable
+                Some(progress) => format!(
+                    "Documents are still being ingested and embedded, {} of {} done",
+                    progress.indexed, progress.total
+                ),
+                None => "Documents are still being ingested and embedded".to_string(),
+            };
+            check("index", false, detail)
+        }
+        IndexState::Loaded { documents } => check("index", true, format!("{} documents indexed", documents)),
+        IndexState::Failed(e) => check("index", false, format!("Ingestion failed: {}", e)),
+    };
+
+    let llm = match env::var("LLM_PROVIDER").as_deref() {
+        Ok("mock") => check("llm", true, "Mock provider".to_string()),
+        _ => match env::var("GEMINI_API_KEY") {
+            Ok(key) if !key.trim().is_empty() => check("llm", true, "Gemini API key present".to_string()),
+            _ => check("llm", false, "GEMINI_API_KEY is not set".to_string()),
+        },
+    };
+
+    // Vectors are held in memory, so the store is reachable when the default collection is
+    let vector_store = match state.tenants.collection(DEFAULT_TENANT, &state.rag_library).await {
+        Ok(_) => check("vector_store", true, format!("In memory, {} tenant collection(s)", state.tenants.len().await)),
+        Err(e) => check("vector_store", false, e.to_string()),
+    };
+
+    let accepting = if state.readiness.is_draining() {
+        check("accepting_requests", false, "Shutting down".to_string())
+    } else {
+        check("accepting_requests", true, "Serving".to_string())
+    };
+
+    let checks = vec![index, llm, vector_store, accepting];
+    let ready = checks.iter().all(|c| c.ok);
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+
+    (
+        status,
+        Json(ReadinessReport {
+            status: if ready { "ready" } else { "not_ready" }.to_string(),
+            checks,
+        }),
+    )
+}
+
+// Dependencies: unlike /readyz this calls out to each of them, so it is slower and should not
+// be used as a frequent probe
+#[utoipa::path(
+    get,
+    path = "/health/deep",
+    tag = "health",
+    responses(
+        (status = 200, description = "Every dependency responded", body = ReadinessReport),
+        (status = 503, description = "At least one dependency failed; see checks", body = ReadinessReport),
+    )
+)]
+pub async fn deep_health(State(state): State<Arc<AppState>>) -> (StatusCode, Json<ReadinessReport>) {
+    let timeout = Duration::from_secs(state.config.health_check_timeout_secs);
+    let (llm, vector_store, pdftotext) = tokio::join!(
+        probe("llm", timeout, check_llm()),
+        probe("vector_store", timeout, check_vector_store(&state)),
+        probe("pdftotext", timeout, check_pdftotext()),
+    );
+
+    let open_circuits = state.rag_library.open_circuits();
+    let circuits = if open_circuits.is_empty() {
+        check("circuit_breakers", true, "All closed".to_string())
+    } else {
+        check("circuit_breakers", false, format!("Open, failing fast: {}", open_circuits.join(", ")))
+    };
+
+    let mut checks = vec![llm, vector_store, pdftotext, circuits];
+    if let Some(store) = &state.metadata {
+        checks.push(probe("metadata_database", timeout, check_metadata_database(store)).await);
+    }
+    let healthy = checks.iter().all(|c| c.ok);
+    let status = if healthy { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+
+    (
+        status,
+        Json(ReadinessReport {
+            status: if healthy { "healthy" } else { "unhealthy" }.to_string(),
+            checks,
+        }),
+    )
+}
+
+// Runs one dependency check within `timeout`, timing it
+async fn probe(name: &str, timeout: Duration, dependency: impl Future<Output = Result<String>>) -> ReadinessCheck {
+    let started = Instant::now();
+    let result = tokio::time::timeout(timeout, dependency).await;
+    let latency_ms = Some(started.elapsed().as_millis() as u64);
+    let (ok, detail) = match result {
+        Ok(Ok(detail)) => (true, detail),
+        Ok(Err(e)) => (false, e.to_string()),
+        Err(_) => (false, format!("No response within {}s", timeout.as_secs())),
+    };
+    ReadinessCheck {
+        latency_ms,
+        ..check(name, ok, detail)
+    }
+}
+
+async fn check_llm() -> Result<String> {
+    if env::var("LLM_PROVIDER").as_deref() == Ok("mock") {
+        return Ok("Mock provider".to_string());
+    }
+    GeminiService::new()?.check_reachable().await?;
+    Ok("Gemini API reachable".to_string())
+}
+
+// Embeds a probe query with the default collection's provider, which exercises the
+// embedding API when it is a remote one
+async fn check_vector_store(state: &AppState) -> Result<String> {
+    let collection = state.tenants.collection(DEFAULT_TENANT, &state.rag_library).await?;
+    collection.embeddings().embed_query("health check").await?;
+    let documents = collection.read().await.len();
+    Ok(match state.rag_library.vector_tier_usage() {
+        Some(usage) => format!(
+            "{} documents in the default collection, {:.1} of {:.1} MB of vectors in memory, the rest on disk",
+            documents,
+            usage.cached_bytes as f64 / (1024.0 * 1024.0),
+            usage.budget_bytes as f64 / (1024.0 * 1024.0)
+        ),
+        None => format!("In memory, {} documents in the default collection", documents),
+    })
+}
+
+async fn check_metadata_database(store: &MetadataStore) -> Result<String> {
+    store.ping().await?;
+    Ok(format!("{} database reachable", store.backend()))
+}
+
+async fn check_pdftotext() -> Result<String> {
+    // pdftotext -v prints its version to stderr and exits 0 (older releases exit 99)
+    let output = Command::new("pdftotext")
+        .arg("-v")
+        .output()
+        .await
+        .map_err(|e| anyhow!("pdftotext is not available: {}", e))?;
+    let version = String::from_utf8_lossy(&output.stderr);
+    Ok(version.lines().next().unwrap_or("pdftotext").trim().to_string())
+}
+
+fn check(name: &str, ok: bool, detail: String) -> ReadinessCheck {
+    ReadinessCheck {
+        name: name.to_string(),
+        ok,
+        detail,
+        latency_ms: None,
+    }
+}
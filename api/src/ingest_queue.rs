@@ -0,0 +1,238 @@
+use rag_system::cancel::{self, CancellationToken};
+use rag_system::models::Document;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tracing::{info_span, Instrument};
+
+use crate::config::Config;
+use crate::error::ApiError;
+use crate::jobs::{Job, JobRegistry, JobStatus};
+use crate::notifier::Notification;
+use crate::reindex_payload::ReindexPayload;
+use crate::utils::{notify_callback, reload_documents, run_ingest_job, run_reindex, run_upload_job, tenant_collection};
+use crate::AppState;
+
+// Ingestion work, run in the background by a pool of workers so uploads, reindexing and the
+// directory watcher share one bounded queue instead of each spawning its own tasks. Every
+// task is a job (see JobRegistry) whose status, attempts and progress can be polled; failures
+// that may be temporary (5xx, 429) are retried with exponential backoff.
+pub enum IngestTask {
+    // Download a document and add it to the tenant's collection (POST /jobs)
+    Url { document_url: String },
+    // Index documents extracted from an upload (POST /documents)
+    Upload { documents: Vec<Document> },
+    // Re-ingest the configured sources, or one of them (POST /admin/reindex)
+    Reindex(ReindexPayload),
+    // Apply what changed in the documents directory (the directory watcher)
+    Reload,
+}
+
+impl IngestTask {
+    // The job kind clients filter by
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::Url { .. } => "ingest",
+            Self::Upload { .. } => "upload",
+            Self::Reindex(_) => "reindex",
+            Self::Reload => "reload",
+        }
+    }
+}
+
+struct QueuedTask {
+    job_id: String,
+    tenant: String,
+    task: IngestTask,
+    // Receives the finished job (see WebhookEvent)
+    callback_url: Option<String>,
+    // For callers that wait for the job, e.g. a synchronous upload
+    done: Option<oneshot::Sender<Result<(), ApiError>>>,
+    // Stops the job between documents once cancelled (see rag_system::cancel)
+    cancellation: Option<CancellationToken>,
+}
+
+pub struct IngestQueue {
+    sender: mpsc::Sender<QueuedTask>,
+    // Taken by `start`
+    receiver: Mutex<Option<mpsc::Receiver<QueuedTask>>>,
+    workers: usize,
+    max_attempts: u32,
+    retry_backoff: Duration,
+}
+
+impl IngestQueue {
+    pub fn from_config(config: &Config) -> Self {
+        let (sender, receiver) = mpsc::channel(config.ingest_queue_capacity.max(1));
+        Self {
+            sender,
+            receiver: Mutex::new(Some(receiver)),
+            workers: config.ingest_workers.max(1),
+            max_attempts: config.ingest_max_attempts.max(1),
+            retry_backoff: Duration::from_secs(config.ingest_retry_backoff_secs),
+        }
+    }
+
+    // Starts the workers; tasks queued before are picked up then
+    pub fn start(&self, state: Arc<AppState>) {
+        let Some(receiver) = self.receiver.lock().unwrap().take() else {
+            return;
+        };
+        let receiver = Arc::new(tokio::sync::Mutex::new(receiver));
+        for worker in 0..self.workers {
+            let (state, receiver) = (state.clone(), receiver.clone());
+            tokio::spawn(async move {
+                loop {
+                    // Only one idle worker waits on the channel at a time
+                    let Some(queued) = receiver.lock().await.recv().await else {
+                        return;
+                    };
+                    let span = info_span!("ingest_job", job_id = %queued.job_id, kind = queued.task.kind(), worker);
+                    process(&state, queued).instrument(span).await;
+                }
+            });
+        }
+        tracing::info!("Started {} ingestion worker(s)", self.workers);
+    }
+
+    // Queues `task` as a new job of `tenant`; fails when the queue is full
+    pub fn enqueue(
+        &self,
+        jobs: &JobRegistry,
+        tenant: &str,
+        task: IngestTask,
+        callback_url: Option<String>,
+    ) -> Result<Job, ApiError> {
+        self.push(jobs, tenant, task, callback_url, None, None)
+    }
+
+    // Same as enqueue, with a receiver for the job's outcome. Cancelling `cancellation`, e.g.
+    // when the waiting client goes away, fails the job with "cancelled" at its next check.
+    pub fn enqueue_waiting(
+        &self,
+        jobs: &JobRegistry,
+        tenant: &str,
+        task: IngestTask,
+        cancellation: CancellationToken,
+    ) -> Result<(Job, oneshot::Receiver<Result<(), ApiError>>), ApiError> {
+        let (done, outcome) = oneshot::channel();
+        let job = self.push(jobs, tenant, task, None, Some(done), Some(cancellation))?;
+        Ok((job, outcome))
+    }
+
+    fn push(
+        &self,
+        jobs: &JobRegistry,
+        tenant: &str,
+        task: IngestTask,
+        callback_url: Option<String>,
+        done: Option<oneshot::Sender<Result<(), ApiError>>>,
+        cancellation: Option<CancellationToken>,
+    ) -> Result<Job, ApiError> {
+        // Reserve first, so no job is created for a task that cannot be queued
+        let permit = self.sender.try_reserve().map_err(|_| {
+            ApiError::unavailable("ingest_queue_full", "Too many ingestion jobs are queued, try again later")
+        })?;
+        let job = jobs.create(task.kind(), tenant);
+        permit.send(QueuedTask {
+            job_id: job.id.clone(),
+            tenant: tenant.to_string(),
+            task,
+            callback_url,
+            done,
+            cancellation,
+        });
+        Ok(job)
+    }
+}
+
+// Runs a task until it succeeds, fails for good or is out of attempts
+async fn process(state: &AppState, queued: QueuedTask) {
+    let queue = &state.ingest;
+    let job_id = queued.job_id.as_str();
+    let mut attempt = 1;
+    let result = loop {
+        state.jobs.update(job_id, |job| {
+            job.status = JobStatus::Running;
+            job.attempts = attempt;
+            job.processed = 0;
+        });
+        let attempt_run = run(state, &queued);
+        let outcome = match &queued.cancellation {
+            Some(token) => cancel::run(token.clone(), attempt_run).await,
+            None => attempt_run.await,
+        };
+        match outcome {
+            Ok(()) => break Ok(()),
+            Err(e) if e.is_retryable() && attempt < queue.max_attempts => {
+                let delay = queue.retry_backoff * 2u32.pow(attempt - 1);
+                tracing::warn!("Job {} attempt {} failed, retrying in {:?}: {}", job_id, attempt, delay, e.message);
+                state.jobs.update(job_id, |job| {
+                    job.status = JobStatus::Queued;
+                    job.message = Some(format!("Attempt {} failed, retrying in {}s: {}", attempt, delay.as_secs(), e.message));
+                });
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            Err(e) => break Err(e),
+        }
+    };
+
+    match &result {
+        Ok(()) => save_documents(state, &queued.tenant).await,
+        Err(e) => state.jobs.fail(job_id, e.message.clone()),
+    }
+    state.notifier.notify(match &result {
+        Ok(()) => Notification::IngestionCompleted {
+            tenant: queued.tenant.clone(),
+            kind: queued.task.kind().to_string(),
+            job_id: Some(job_id.to_string()),
+            message: state.jobs.get(job_id).and_then(|job| job.message),
+        },
+        Err(e) => Notification::IngestionFailed {
+            tenant: queued.tenant.clone(),
+            kind: queued.task.kind().to_string(),
+            job_id: Some(job_id.to_string()),
+            error: e.message.clone(),
+        },
+    });
+    notify_callback(state, job_id, queued.callback_url.as_deref()).await;
+    if let Some(done) = queued.done {
+        let _ = done.send(result);
+    }
+}
+
+// Keeps the metadata database in step with the collection a job changed
+pub async fn save_documents(state: &AppState, tenant: &str) {
+    let Some(store) = &state.metadata else {
+        return;
+    };
+    match tenant_collection(state, tenant).await {
+        Ok(collection) => store.save_documents(tenant, &collection.read().await),
+        Err(e) => tracing::warn!("Could not save the documents of tenant {}: {}", tenant, e.message),
+    }
+}
+
+async fn run(state: &AppState, queued: &QueuedTask) -> Result<(), ApiError> {
+    let (job_id, tenant) = (queued.job_id.as_str(), queued.tenant.as_str());
+    match &queued.task {
+        IngestTask::Url { document_url } => run_ingest_job(state, tenant, job_id, document_url).await,
+        IngestTask::Upload { documents } => run_upload_job(state, tenant, job_id, documents.clone()).await,
+        IngestTask::Reindex(payload) => run_reindex(state, job_id, payload).await,
+        IngestTask::Reload => {
+            let response = reload_documents(state).await?;
+            state.jobs.update(job_id, |job| {
+                job.status = JobStatus::Completed;
+                job.message = Some(format!(
+                    "{} added, {} updated, {} removed, {} unchanged",
+                    response.added.len(),
+                    response.updated.len(),
+                    response.removed.len(),
+                    response.unchanged
+                ));
+                job.result = serde_json::to_value(&response).ok();
+            });
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use base64::engine::general_purpose::{STANDARD, STANDARD_NO_PAD};
+use base64::Engine;
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+// Document sent in the request body, for clients that cannot host it at a public URL
+#[derive(Deserialize, ToSchema)]
+pub struct InlineDocument {
+    // File contents, base64-encoded (standard alphabet; padding optional)
+    pub content_base64: String,
+    // application/pdf, application/vnd.openxmlformats-officedocument.wordprocessingml.document,
+    // text/plain or text/markdown
+    pub mime_type: String,
+    // Name used in citations; defaults to "inline_document"
+    pub filename: Option<String>,
+}
+
+pub const SUPPORTED_MIME_TYPES: [&str; 4] = [
+    "application/pdf",
+    "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    "text/plain",
+    "text/markdown",
+];
+
+impl InlineDocument {
+    // File extension the contents are extracted as, None for unsupported types
+    pub fn extension(&self) -> Option<&'static str> {
+        // Parameters such as "; charset=utf-8" don't change the format
+        let mime_type = self.mime_type.split(';').next().unwrap_or_default().trim().to_lowercase();
+        match mime_type.as_str() {
+            "application/pdf" => Some("pdf"),
+            "application/vnd.openxmlformats-officedocument.wordprocessingml.document" => Some("docx"),
+            "text/plain" => Some("txt"),
+            "text/markdown" => Some("md"),
+            _ => None,
+        }
+    }
+
+    pub fn filename(&self) -> String {
+        self.filename
+            .clone()
+            .filter(|name| !name.trim().is_empty())
+            .unwrap_or_else(|| "inline_document".to_string())
+    }
+
+    pub fn decode(&self) -> Result<Vec<u8>, base64::DecodeError> {
+        let content = self.content_base64.trim();
+        STANDARD.decode(content).or_else(|_| STANDARD_NO_PAD.decode(content))
+    }
+
+    // Size of the decoded contents, without decoding them
+    pub fn decoded_len(&self) -> usize {
+        self.content_base64.trim().len() / 4 * 3
+    }
+}
+
+// Where the document a request is about comes from; requests without one use the tenant's
+// collection
+#[derive(Clone, Copy)]
+pub enum DocumentSource<'a> {
+    Url(&'a str),
+    Inline(&'a InlineDocument),
+}
+
+impl<'a> DocumentSource<'a> {
+    // Blank URLs mean "no document"; an inline document wins over a URL (validation rejects
+    // requests with both)
+    pub fn from_request(url: Option<&'a str>, inline: Option<&'a InlineDocument>) -> Option<Self> {
+        match (inline, url.filter(|url| !url.trim().is_empty())) {
+            (Some(document), _) => Some(Self::Inline(document)),
+            (None, Some(url)) => Some(Self::Url(url)),
+            (None, None) => None,
+        }
+    }
+}
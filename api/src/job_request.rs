@@ -0,0 +1,39 @@
+use crate::config::Config;
+use crate::error::ApiError;
+use crate::hackrx_request::HackRxRequest;
+use crate::validation::Validator;
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+// Body of POST /jobs
+#[derive(Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JobRequest {
+    // Download a document and add it to the tenant's collection
+    Ingest {
+        document_url: String,
+        // Receives the finished job (see WebhookEvent)
+        callback_url: Option<String>,
+    },
+    // Same body as POST /hackrx/run; answers appear in the job result as they are produced
+    HackrxRun(Box<HackRxRequest>),
+}
+
+impl JobRequest {
+    pub fn validate(&self, config: &Config) -> Result<(), ApiError> {
+        match self {
+            Self::Ingest { document_url, callback_url } => {
+                let mut validator = Validator::default();
+                if document_url.trim().is_empty() {
+                    validator.error("document_url", "must not be empty");
+                }
+                validator.document_url("document_url", document_url, config);
+                if let Some(url) = callback_url {
+                    validator.callback_url("callback_url", url, config);
+                }
+                validator.finish()
+            }
+            Self::HackrxRun(request) => request.validate(config),
+        }
+    }
+}
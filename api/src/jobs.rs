@@ -0,0 +1,170 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+use crate::metadata_store::MetadataStore;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+}
+
+// A background task whose progress clients poll by id
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Job {
+    pub id: String,
+    // What the job does, e.g. "reindex"
+    pub kind: String,
+    pub status: JobStatus,
+    // Work items finished so far out of `total` (e.g. files ingested)
+    pub processed: usize,
+    pub total: usize,
+    // processed / total as a whole percentage; 100 once the job completes
+    pub progress_percent: u8,
+    // Runs of the job so far; above 1 when it was retried after a failure
+    pub attempts: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    // Output of jobs that produce one, e.g. the answers of a hackrx run
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub result: Option<serde_json::Value>,
+    pub created_at: u64,
+    pub updated_at: u64,
+    // Only the tenant that started a job can see it
+    #[serde(skip)]
+    pub tenant: String,
+}
+
+// Filters for GET /admin/jobs
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct JobFilter {
+    pub status: Option<JobStatus>,
+    // e.g. "reindex"
+    pub kind: Option<String>,
+}
+
+#[derive(Default)]
+pub struct JobRegistry {
+    jobs: RwLock<HashMap<String, Job>>,
+    // Every change is saved there when DATABASE_URL is set
+    store: Option<MetadataStore>,
+}
+
+impl JobRegistry {
+    // Registry saving its jobs to `store`, starting with those stored earlier. Jobs that were
+    // queued or running when the server stopped are marked failed, as no worker picks them up.
+    pub async fn restore(store: MetadataStore) -> anyhow::Result<Self> {
+        let mut jobs = HashMap::new();
+        for mut job in store.jobs().await? {
+            if matches!(job.status, JobStatus::Queued | JobStatus::Running) {
+                job.status = JobStatus::Failed;
+                job.error = Some("Interrupted by a server restart".to_string());
+                job.updated_at = unix_timestamp();
+                store.save_job(&job);
+            }
+            jobs.insert(job.id.clone(), job);
+        }
+        tracing::info!("Restored {} job(s) from the metadata database", jobs.len());
+        Ok(Self {
+            jobs: RwLock::new(jobs),
+            store: Some(store),
+        })
+    }
+
+    pub fn create(&self, kind: &str, tenant: &str) -> Job {
+        let now = unix_timestamp();
+        let job = Job {
+            id: Uuid::new_v4().to_string(),
+            kind: kind.to_string(),
+            status: JobStatus::Queued,
+            processed: 0,
+            total: 0,
+            progress_percent: 0,
+            attempts: 0,
+            message: None,
+            error: None,
+            result: None,
+            created_at: now,
+            updated_at: now,
+            tenant: tenant.to_string(),
+        };
+        let mut jobs = self.jobs.write().unwrap();
+        if let Some(store) = &self.store {
+            store.save_job(&job);
+        }
+        jobs.insert(job.id.clone(), job.clone());
+        job
+    }
+
+    pub fn get(&self, id: &str) -> Option<Job> {
+        self.jobs.read().unwrap().get(id).cloned()
+    }
+
+    pub fn update(&self, id: &str, change: impl FnOnce(&mut Job)) {
+        if let Some(job) = self.jobs.write().unwrap().get_mut(id) {
+            change(job);
+            job.progress_percent = match (job.status, job.total) {
+                (JobStatus::Completed, _) => 100,
+                (_, 0) => 0,
+                (_, total) => (job.processed.min(total) * 100 / total) as u8,
+            };
+            job.updated_at = unix_timestamp();
+            // Saved under the lock, so the stored job never goes back to an earlier state
+            if let Some(store) = &self.store {
+                store.save_job(job);
+            }
+        }
+    }
+
+    // The tenant's jobs matching `filter`, newest first
+    pub fn list(&self, tenant: &str, filter: &JobFilter) -> Vec<Job> {
+        let mut jobs: Vec<Job> = self
+            .jobs
+            .read()
+            .unwrap()
+            .values()
+            .filter(|job| job.tenant == tenant)
+            .filter(|job| filter.status.is_none_or(|status| job.status == status))
+            .filter(|job| filter.kind.as_ref().is_none_or(|kind| &job.kind == kind))
+            .cloned()
+            .collect();
+        jobs.sort_by(|a, b| b.created_at.cmp(&a.created_at).then_with(|| a.id.cmp(&b.id)));
+        jobs
+    }
+
+    // Jobs that are queued or running
+    pub fn active(&self) -> usize {
+        self.jobs
+            .read()
+            .unwrap()
+            .values()
+            .filter(|job| matches!(job.status, JobStatus::Queued | JobStatus::Running))
+            .count()
+    }
+
+    pub fn fail(&self, id: &str, error: String) {
+        tracing::error!("Job {} failed: {}", id, error);
+        self.update(id, |job| {
+            job.status = JobStatus::Failed;
+            job.error = Some(error);
+        });
+    }
+}
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
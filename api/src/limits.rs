@@ -0,0 +1,114 @@
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio_stream::StreamExt;
+
+use crate::error::ApiError;
+
+// Caps how many requests run at once. Up to `max_queued` more wait (at most `queue_timeout`)
+// for a slot; beyond that requests are turned away with 503 and Retry-After instead of piling
+// up in memory.
+pub struct ConcurrencyLimiter {
+    name: &'static str,
+    permits: Arc<Semaphore>,
+    max_queued: usize,
+    queued: AtomicUsize,
+    queue_timeout: Duration,
+    retry_after_secs: u64,
+}
+
+impl ConcurrencyLimiter {
+    pub fn new(
+        name: &'static str,
+        max_concurrent: usize,
+        max_queued: usize,
+        queue_timeout: Duration,
+        retry_after_secs: u64,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            name,
+            permits: Arc::new(Semaphore::new(max_concurrent.max(1))),
+            max_queued,
+            queued: AtomicUsize::new(0),
+            queue_timeout,
+            retry_after_secs,
+        })
+    }
+
+    pub async fn acquire(&self) -> Result<OwnedSemaphorePermit, ApiError> {
+        if let Ok(permit) = self.permits.clone().try_acquire_owned() {
+            return Ok(permit);
+        }
+
+        // Reserve a queue slot, or give up straight away when the queue is full
+        let reserved = self
+            .queued
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |queued| {
+                (queued < self.max_queued).then_some(queued + 1)
+            })
+            .is_ok();
+        if !reserved {
+            tracing::warn!("{} limiter saturated, rejecting request", self.name);
+            return Err(self.busy("Server is at capacity, retry later"));
+        }
+
+        let waited = tokio::time::timeout(self.queue_timeout, self.permits.clone().acquire_owned()).await;
+        self.queued.fetch_sub(1, Ordering::SeqCst);
+        match waited {
+            Ok(Ok(permit)) => Ok(permit),
+            _ => {
+                tracing::warn!("Request queued for {:?} on the {} limiter, rejecting", self.queue_timeout, self.name);
+                Err(self.busy("Timed out waiting for capacity, retry later"))
+            }
+        }
+    }
+
+    // Slot for background work such as callback runs, which waits as long as it takes
+    // instead of being turned away
+    pub async fn wait(&self) -> OwnedSemaphorePermit {
+        self.permits.clone().acquire_owned().await.expect("limiter semaphore is never closed")
+    }
+
+    fn busy(&self, message: &str) -> ApiError {
+        ApiError::unavailable("overloaded", message)
+            .with_details(serde_json::json!({ "limiter": self.name }))
+            .with_retry_after(self.retry_after_secs)
+    }
+}
+
+// Middleware holding a slot of `limiter` for the duration of the request. Streaming
+// (server-sent event) responses keep their slot until the stream ends.
+pub async fn limit_concurrency(
+    State(limiter): State<Arc<ConcurrencyLimiter>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let permit = match limiter.acquire().await {
+        Ok(permit) => permit,
+        Err(e) => return e.into_response(),
+    };
+
+    let response = next.run(request).await;
+    let streaming = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|value| value.as_bytes().starts_with(b"text/event-stream"));
+    if !streaming {
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let body = body.into_data_stream().map(move |chunk| {
+        let _held = &permit;
+        chunk
+    });
+    Response::from_parts(parts, Body::from_stream(body))
+}
@@ -4,123 +4,622 @@ mod utils;
 mod auth;
 mod query_payload;
 mod rag_response;
+mod retrieval_options;
+mod feedback_payload;
+mod chat_session;
+mod upload_response;
+mod jobs;
+mod ingest_queue;
+mod metadata_store;
+mod watcher;
+mod reindex_payload;
+mod reload_response;
+mod openapi;
+mod tenants;
+mod health;
+#[cfg(feature = "grpc")]
+mod grpc;
+#[cfg(feature = "graphql")]
+mod graphql;
+#[cfg(feature = "demo")]
+mod demo;
+#[cfg(feature = "slack")]
+mod slack;
+#[cfg(feature = "otel")]
+mod telemetry;
+mod version;
+mod config;
+mod tls;
+mod error;
+mod validation;
+mod limits;
+mod pagination;
+mod document_listing;
+mod webhooks;
+mod job_request;
+mod usage;
+mod deadline;
+mod inline_document;
+mod answer_format;
+mod citations_csv;
+mod cors;
+mod audit;
+mod download_cache;
+mod url_guard;
+mod notifier;
+mod report;
+mod analytics;
+mod purge_response;
 
 use axum::{
-    extract::State, 
-    routing::{get, post}, 
+    extract::{DefaultBodyLimit, State},
+    routing::{delete, get, post}, 
     Json, Router,
     middleware,
-    http::{StatusCode, Method},
 };
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use tower_http::cors::{CorsLayer, Any};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tower::ServiceBuilder;
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
+use tower_http::trace::TraceLayer;
+use tracing::info_span;
+use axum::http::HeaderName;
+use clap::Parser;
 use serde::Serialize;
+use utoipa::{OpenApi, ToSchema};
+use utoipa_swagger_ui::SwaggerUi;
 
-use rag_system::{models::Document, RagLibrary};
+use rag_system::encryption::StateCipher;
+use rag_system::library::parse_redactions;
+use rag_system::pii::PiiRedactor;
+use rag_system::{LogFormat, Logging, RagLibrary};
+use config::Config;
+use jobs::JobRegistry;
+use ingest_queue::IngestQueue;
+use metadata_store::MetadataStore;
+use webhooks::Webhooks;
+use notifier::{Notification, Notifier};
+use limits::{limit_concurrency, ConcurrencyLimiter};
+use usage::{record_usage, UsageTracker};
+use deadline::enforce_deadline;
+use audit::AuditLog;
+use download_cache::DownloadCache;
+use tls::TlsMode;
+use openapi::ApiDoc;
+use health::{deep_health, healthz, readyz, IndexState, Readiness};
+use version::version;
+use tenants::{TenantRegistry, DEFAULT_TENANT};
 
 use crate::{
-    hackrx_request::HackRxRequest,
-    hackrx_response::HackRxResponse,
-    utils::handle_hackrx_run,
-    auth::{auth_middleware, generate_mock_token},
-    query_payload::QueryPayload,
-    rag_response::RagResponse,
+    utils::{
+        handle_feedback, handle_hackrx_run, handle_list_feedback, handle_query_with_pdf_url, handle_query_stream,
+        handle_retrieve,
+        handle_upload_documents, handle_list_documents, handle_delete_document, handle_list_chunks, handle_list_clauses, handle_reindex, handle_reload, handle_list_jobs,
+        handle_get_job, handle_create_job, handle_usage, handle_analytics, handle_search_audit, handle_create_chat_session,
+        handle_list_chat_sessions, handle_delete_chat_session, handle_list_chat_messages, handle_send_chat_message,
+    },
+    auth::{auth_middleware, JwtAuth, TokenPair, TokenType, UserStore},
+    error::{request_id_in_errors, ApiError, ApiJson, ErrorBody},
 };
 
-// Health check handler
-async fn health() -> &'static str {
-    "OK"
-}
-
-// Login endpoint for generating mock tokens
-#[derive(Serialize)]
+// Login endpoint issuing an access/refresh token pair
+#[derive(Serialize, ToSchema)]
 struct LoginResponse {
-    token: String,
+    #[serde(flatten)]
+    tokens: TokenPair,
     message: String,
 }
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, ToSchema)]
 struct LoginRequest {
     username: String,
     password: String,
+    // Tenant the account is bound to in USERS_FILE; any other is refused
+    tenant: Option<String>,
+}
+
+#[derive(serde::Deserialize, ToSchema)]
+struct RefreshRequest {
+    refresh_token: String,
 }
 
-async fn login(Json(payload): Json<LoginRequest>) -> Result<Json<LoginResponse>, (StatusCode, String)> {
-    // Mock authentication - in real app, verify credentials against database
+#[utoipa::path(
+    post,
+    path = "/login",
+    tag = "auth",
+    request_body = LoginRequest,
+    responses(
+        (status = 200, description = "Access and refresh tokens", body = LoginResponse),
+        (status = 400, description = "Missing username or password", body = ErrorBody),
+        (status = 401, description = "Invalid credentials", body = ErrorBody),
+        (status = 403, description = "The account is bound to a different tenant", body = ErrorBody),
+    )
+)]
+async fn login(
+    State(state): State<Arc<AppState>>,
+    ApiJson(payload): ApiJson<LoginRequest>,
+) -> Result<Json<LoginResponse>, ApiError> {
     if payload.username.is_empty() || payload.password.is_empty() {
-        return Err((StatusCode::BAD_REQUEST, "Username and password required".to_string()));
+        return Err(ApiError::bad_request("missing_credentials", "Username and password required"));
     }
-    
-    if payload.password.len() < 6 {
-        return Err((StatusCode::UNAUTHORIZED, "Invalid credentials".to_string()));
+
+    let account = state
+        .users
+        .authenticate(&payload.username, &payload.password)
+        .ok_or_else(|| ApiError::unauthorized("invalid_credentials", "Invalid credentials"))?;
+    if payload.tenant.as_deref().is_some_and(|tenant| tenant != account.tenant) {
+        return Err(ApiError::forbidden("tenant_not_allowed", "This account cannot log in to that tenant"));
     }
-    
-    let token = generate_mock_token(&payload.username);
+
+    let tokens = state.auth.issue_tokens(&account.user, &account.tenant, &state.config.roles_of(&account.user))
+        .map_err(|e| ApiError::internal("token_error", format!("Failed to issue token: {}", e)))?;
     
     Ok(Json(LoginResponse {
-        token,
+        tokens,
         message: "Login successful".to_string(),
     }))
 }
 
+// Exchanges a valid refresh token for a new token pair
+#[utoipa::path(
+    post,
+    path = "/refresh",
+    tag = "auth",
+    request_body = RefreshRequest,
+    responses(
+        (status = 200, description = "New access and refresh tokens", body = TokenPair),
+        (status = 401, description = "Refresh token is malformed, expired or not a refresh token, or its account was removed", body = ErrorBody),
+    )
+)]
+async fn refresh(
+    State(state): State<Arc<AppState>>,
+    ApiJson(payload): ApiJson<RefreshRequest>,
+) -> Result<Json<TokenPair>, ApiError> {
+    let claims = state.auth.validate(payload.refresh_token.trim(), TokenType::Refresh)?;
+
+    // The account, its tenant and roles are looked up again, so changes to USERS_FILE and
+    // USER_ROLES apply from the next refresh
+    let account = state
+        .users
+        .account(&claims.sub)
+        .ok_or_else(|| ApiError::unauthorized("invalid_credentials", "The account no longer exists"))?;
+    state.auth.issue_tokens(&account.user, &account.tenant, &state.config.roles_of(&account.user))
+        .map(Json)
+        .map_err(|e| ApiError::internal("token_error", e.to_string()))
+}
+
 // Protected endpoint to test authentication
+#[utoipa::path(
+    get,
+    path = "/protected",
+    tag = "auth",
+    responses(
+        (status = 200, description = "The token is valid", body = String),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
 async fn protected() -> &'static str {
     "This is a protected endpoint. You are authenticated!"
 }
 
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
 pub struct AppState {
     pub rag_library: Arc<RagLibrary>,
-    pub documents: Arc<RwLock<Vec<Document>>>,
+    pub tenants: TenantRegistry,
+    pub jobs: JobRegistry,
+    // Runs uploads, ingest jobs, reindexing and watcher reloads
+    pub ingest: IngestQueue,
+    pub auth: JwtAuth,
+    // Accounts /login checks credentials against, from USERS_FILE
+    pub users: UserStore,
+    pub readiness: Readiness,
+    pub webhooks: Webhooks,
+    pub notifier: Arc<Notifier>,
+    // Shared by the answering routes and background hackrx runs
+    pub answer_limiter: Arc<ConcurrencyLimiter>,
+    pub usage: UsageTracker,
+    pub audit: AuditLog,
+    // Jobs, feedback, audit entries and document metadata, with DATABASE_URL
+    pub metadata: Option<MetadataStore>,
+    pub downloads: DownloadCache,
+    pub config: Config,
 }
 
 #[tokio::main]
 async fn main() {
     dotenv::dotenv().ok();
-    env_logger::init();
+    let config = Config::parse();
+    if config.otlp_endpoint.is_some() && !cfg!(feature = "otel") {
+        eprintln!("❌ OTEL_EXPORTER_OTLP_ENDPOINT is set but the api was built without the `otel` feature");
+        std::process::exit(2);
+    }
+    // Library and request spans share the subscriber, so every line of a request carries its
+    // request id
+    let logging = Logging::new("info").with_format(config.log_format);
+    #[cfg(feature = "otel")]
+    let telemetry = config.otlp_endpoint.as_deref().map(|endpoint| {
+        telemetry::Telemetry::new(&config, endpoint).unwrap_or_else(|e| {
+            eprintln!("❌ Invalid OpenTelemetry configuration: {:#}", e);
+            std::process::exit(2);
+        })
+    });
+    #[cfg(feature = "otel")]
+    let logging = telemetry.iter().flat_map(|telemetry| telemetry.layers()).fold(logging, Logging::with_layer);
+    logging.try_init().expect("no other subscriber installed");
 
-    let (documents, rag_library) = RagLibrary::new().await.unwrap();
+    if config.log_format == LogFormat::Text {
+        config.print_summary();
+    }
+    let tls_mode = TlsMode::from_config(&config).unwrap_or_else(|e| {
+        eprintln!("❌ Invalid TLS configuration: {}", e);
+        std::process::exit(2);
+    });
+    if config.grpc_port.is_some() && !cfg!(feature = "grpc") {
+        eprintln!("❌ GRPC_PORT is set but the api was built without the `grpc` feature");
+        std::process::exit(2);
+    }
+    if config.slack_signing_secret.is_some() && !cfg!(feature = "slack") {
+        eprintln!("❌ SLACK_SIGNING_SECRET is set but the api was built without the `slack` feature");
+        std::process::exit(2);
+    }
+    #[cfg(feature = "slack")]
+    let slack_app = slack::Slack::from_config(&config).unwrap_or_else(|e| {
+        eprintln!("❌ Invalid Slack configuration: {:#}", e);
+        std::process::exit(2);
+    });
+
+    let reporter = report::Reporter::from_config(&config).unwrap_or_else(|e| {
+        eprintln!("❌ Invalid analytics report configuration: {:#}", e);
+        std::process::exit(2);
+    });
+
+    if let Err(e) = PiiRedactor::new(&parse_redactions(&config.pii_redact), config.pii_ner_url.as_deref()) {
+        eprintln!("❌ Invalid PII redaction configuration: {}", e);
+        std::process::exit(2);
+    }
+
+    // Resolved once, as the key may come from a command, and shared with the metadata database
+    let state_cipher = config.state_key().map(|key| StateCipher::new(&key)).transpose().unwrap_or_else(|e| {
+        eprintln!("❌ Invalid state encryption configuration: {}", e);
+        std::process::exit(2);
+    });
 
+    let mut rag_library = RagLibrary::builder().with_config(config.rag_config());
+    if let Some(cipher) = &state_cipher {
+        rag_library = rag_library.with_state_cipher(cipher.clone());
+    }
+    let rag_library = rag_library.build().await.unwrap();
+
+    let metadata = match &config.database_url {
+        Some(url) => Some(MetadataStore::connect(url, state_cipher).await.unwrap_or_else(|e| {
+            eprintln!("❌ Cannot use the metadata database: {:#}", e);
+            std::process::exit(2);
+        })),
+        None => None,
+    };
+    let jobs = match &metadata {
+        Some(store) => JobRegistry::restore(store.clone()).await.unwrap_or_else(|e| {
+            eprintln!("❌ Cannot read jobs from the metadata database: {:#}", e);
+            std::process::exit(2);
+        }),
+        None => JobRegistry::default(),
+    };
+
+    let users = match &config.users_file {
+        Some(path) => UserStore::from_file(path).unwrap_or_else(|e| {
+            eprintln!("❌ Invalid USERS_FILE: {:#}", e);
+            std::process::exit(2);
+        }),
+        None => UserStore::default(),
+    };
+    if users.is_empty() {
+        tracing::warn!("No accounts in USERS_FILE, every login will be refused");
+    }
+
+    let tenants = TenantRegistry::new(&rag_library);
     let state = Arc::new(AppState {
         rag_library: Arc::new(rag_library),
-        documents: Arc::new(RwLock::new(documents)),
+        tenants,
+        jobs,
+        ingest: IngestQueue::from_config(&config),
+        auth: JwtAuth::from_env().unwrap(),
+        users,
+        readiness: Readiness::default(),
+        webhooks: Webhooks::from_config(&config),
+        notifier: Arc::new(Notifier::from_config(&config)),
+        usage: UsageTracker::from_config(&config),
+        audit: AuditLog::from_config(&config).with_store(metadata.clone()),
+        metadata,
+        downloads: DownloadCache::from_config(&config),
+        answer_limiter: ConcurrencyLimiter::new(
+            "answer",
+            config.max_concurrent_answers,
+            config.max_queued_answers,
+            Duration::from_secs(config.queue_timeout_secs),
+            config.retry_after_secs,
+        ),
+        config,
     });
 
-    // CORS configuration
-    let cors = CorsLayer::new()
-        .allow_methods([Method::GET, Method::POST])
-        .allow_headers(Any)
-        .allow_origin(Any);
+    state.ingest.start(state.clone());
+    watcher::spawn(state.clone());
+    notifier::spawn_watchers(state.clone());
+    if let Some(reporter) = reporter {
+        report::spawn(state.clone(), reporter);
+    }
+
+    // Ingest the corpus in the background; /readyz reports 503 until it is loaded
+    let loader_state = state.clone();
+    tokio::spawn(async move {
+        let state = loader_state;
+        match state.rag_library.load_documents().await {
+            Ok(count) => {
+                if let Some(store) = &state.metadata {
+                    store.save_documents(DEFAULT_TENANT, &state.rag_library.store().read().await);
+                }
+                state.readiness.set_index(IndexState::Loaded { documents: count });
+                state.notifier.notify(Notification::IngestionCompleted {
+                    tenant: DEFAULT_TENANT.to_string(),
+                    kind: "startup".to_string(),
+                    job_id: None,
+                    message: Some(format!("{} documents indexed", count)),
+                });
+            }
+            Err(e) => {
+                tracing::error!("Failed to load documents: {}", e);
+                state.readiness.set_index(IndexState::Failed(e.to_string()));
+                state.notifier.notify(Notification::IngestionFailed {
+                    tenant: DEFAULT_TENANT.to_string(),
+                    kind: "startup".to_string(),
+                    job_id: None,
+                    error: e.to_string(),
+                });
+            }
+        }
+    });
+
+    let cors = cors::cors_layer(&state.config);
 
     // Public routes (no authentication required)
     let public_routes = Router::new()
-        .route("/health", get(health))
-        .route("/login", post(login));
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/health/deep", get(deep_health))
+        // Kept for existing probes; same as /healthz
+        .route("/health", get(healthz))
+        .route("/version", get(version))
+        .route("/login", post(login))
+        .route("/refresh", post(refresh))
+        .merge(SwaggerUi::new("/docs").url("/api-docs/openapi.json", ApiDoc::openapi()));
+    #[cfg(feature = "graphql")]
+    let public_routes = public_routes.route("/graphql", get(graphql::graphiql));
+    #[cfg(feature = "demo")]
+    let public_routes = public_routes.route("/demo", get(demo::demo_page));
+    // Slack signs its requests instead of sending a bearer token
+    #[cfg(feature = "slack")]
+    let public_routes = match slack_app.map(Arc::new) {
+        Some(app) if app.handles_events() => public_routes
+            .route("/slack/commands", post(slack::handle_command).layer(axum::Extension(app.clone())))
+            .route("/slack/events", post(slack::handle_event).layer(axum::Extension(app))),
+        Some(app) => public_routes.route("/slack/commands", post(slack::handle_command).layer(axum::Extension(app))),
+        None => public_routes,
+    };
+
+    // Answering calls the LLM, so those routes share a tighter limit than the API as a whole
+    let config = &state.config;
+    let global_limit = ConcurrencyLimiter::new(
+        "global",
+        config.max_concurrent_requests,
+        config.max_queued_requests,
+        Duration::from_secs(config.queue_timeout_secs),
+        config.retry_after_secs,
+    );
+    let answer_limit = middleware::from_fn_with_state(state.answer_limiter.clone(), limit_concurrency);
+
+    // Routes whose body can carry a base64 document
+    let inline_body_limit = DefaultBodyLimit::max(state.config.inline_body_limit());
 
     // Protected routes (authentication required)
     let protected_routes = Router::new()
-        .route("/hackrx/run", post(handle_hackrx_run))
-        .route("/protected", get(protected))
-        .layer(middleware::from_fn(auth_middleware))
+        .route("/hackrx/run", post(handle_hackrx_run).layer((inline_body_limit, answer_limit.clone())))
+        .route("/query", post(handle_query_with_pdf_url).layer((inline_body_limit, answer_limit.clone())))
+        .route("/query/stream", post(handle_query_stream).layer((inline_body_limit, answer_limit.clone())))
+        .route("/retrieve", post(handle_retrieve).layer(inline_body_limit))
+        .route("/feedback", post(handle_feedback).get(handle_list_feedback))
+        .route("/chat/sessions", post(handle_create_chat_session).get(handle_list_chat_sessions))
+        .route("/chat/sessions/:id", delete(handle_delete_chat_session))
+        .route(
+            "/chat/sessions/:id/messages",
+            post(handle_send_chat_message)
+                .layer((inline_body_limit, answer_limit))
+                .get(handle_list_chat_messages),
+        )
+        .route(
+            "/documents",
+            post(handle_upload_documents)
+                .layer(DefaultBodyLimit::max(state.config.max_upload_bytes))
+                .get(handle_list_documents),
+        )
+        .route("/documents/:id", delete(handle_delete_document))
+        .route("/documents/:id/chunks", get(handle_list_chunks))
+        .route("/clauses", get(handle_list_clauses))
+        .route("/admin/reindex", post(handle_reindex))
+        .route("/admin/reload", post(handle_reload))
+        .route("/jobs", post(handle_create_job).layer(inline_body_limit).get(handle_list_jobs))
+        .route("/jobs/:id", get(handle_get_job))
+        // Earlier paths of the job listing, kept for existing clients
+        .route("/admin/jobs", get(handle_list_jobs))
+        .route("/admin/jobs/:id", get(handle_get_job))
+        .route("/admin/usage", get(handle_usage))
+        .route("/admin/analytics", get(handle_analytics))
+        .route("/admin/audit", get(handle_search_audit))
+        .route("/protected", get(protected));
+    #[cfg(feature = "graphql")]
+    let protected_routes = protected_routes.route(
+        "/graphql",
+        post(graphql::handle_graphql).layer((inline_body_limit, axum::Extension(graphql::schema(state.clone())))),
+    );
+    let protected_routes = protected_routes
+        .layer(middleware::from_fn_with_state(state.clone(), record_usage))
+        .layer(middleware::from_fn_with_state(global_limit, limit_concurrency))
+        .layer(middleware::from_fn_with_state(state.clone(), enforce_deadline))
+        .layer(middleware::from_fn_with_state(state.clone(), auth_middleware))
         .with_state(state.clone());
 
+    // Every request gets an x-request-id (a client-supplied one is kept), echoed on the
+    // response and recorded on the span all of the request's logs are emitted in
+    let request_id_header = HeaderName::from_static(REQUEST_ID_HEADER);
+    let request_tracing = ServiceBuilder::new()
+        .layer(SetRequestIdLayer::new(request_id_header.clone(), MakeRequestUuid))
+        .layer(TraceLayer::new_for_http().make_span_with(|request: &axum::extract::Request| {
+            let request_id = request
+                .headers()
+                .get(REQUEST_ID_HEADER)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or("-");
+            info_span!("request", request_id, method = %request.method(), uri = %request.uri())
+        }))
+        .layer(PropagateRequestIdLayer::new(request_id_header));
+
     // Combine all routes
     let app = Router::new()
         .merge(public_routes)
         .merge(protected_routes)
+        .fallback(error::not_found)
+        .layer(DefaultBodyLimit::max(state.config.max_body_bytes))
+        .layer(middleware::from_fn(request_id_in_errors))
         .layer(cors)
-        .with_state(state);
+        .layer(request_tracing);
+    #[cfg(feature = "otel")]
+    let app = app.layer(middleware::from_fn(telemetry::record_http_metrics));
+    let app = app.with_state(state.clone());
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:8000")
+    let bind_address = state.config.bind_address();
+    let listener = tokio::net::TcpListener::bind(&bind_address)
         .await
         .unwrap();
+    let base_url = format!("{}://{}", tls_mode.scheme(), bind_address);
     
-    println!("🚀 Server starting on http://0.0.0.0:8000");
-    println!("📋 Health check: http://0.0.0.0:8000/health");
-    println!("🔐 Login endpoint: http://0.0.0.0:8000/login");
+    // JSON logs stay one object per line on stdout, so the banner is left out
+    tracing::info!(address = %base_url, "Server starting");
+    if state.config.log_format == LogFormat::Text {
+        print_banner(&base_url);
+    }
+
+    // The gRPC service shares the state, and stops on the same signals
+    #[cfg(feature = "grpc")]
+    if let Some(address) = state.config.grpc_address() {
+        println!("🔌 gRPC service (proto/rag.proto) on {}", address);
+        let state = state.clone();
+        tokio::spawn(async move {
+            if let Err(e) = grpc::serve(state, address, shutdown_signal()).await {
+                tracing::error!("gRPC server error: {}", e);
+            }
+        });
+    }
+    
+    // On SIGTERM/SIGINT stop accepting connections, let in-flight requests and ingestion
+    // jobs finish, and give up on them after the configured shutdown timeout
+    let shutdown_timeout = state.config.shutdown_timeout();
+    let shutdown_started = Arc::new(Notify::new());
+    let server = tls::serve(listener, app, &state.config, {
+        let state = state.clone();
+        let shutdown_started = shutdown_started.clone();
+        async move {
+            shutdown_signal().await;
+            tracing::info!("Shutdown requested, draining in-flight requests (timeout {:?})", shutdown_timeout);
+            state.readiness.start_draining();
+            shutdown_started.notify_one();
+        }
+    });
+
+    let deadline = async {
+        shutdown_started.notified().await;
+        tokio::time::sleep(shutdown_timeout).await;
+    };
+    tokio::pin!(deadline);
+
+    let drained = tokio::select! {
+        result = server => {
+            if let Err(e) = result {
+                tracing::error!("Server error: {}", e);
+            }
+            true
+        }
+        _ = &mut deadline => {
+            tracing::warn!("Requests still running after {:?}, shutting down anyway", shutdown_timeout);
+            false
+        }
+    };
+
+    // Connections are closed; wait for background jobs such as reindexing
+    if drained {
+        let jobs_done = async {
+            while state.jobs.active() > 0 {
+                tokio::time::sleep(Duration::from_millis(200)).await;
+            }
+        };
+        tokio::select! {
+            _ = jobs_done => tracing::info!("All jobs finished"),
+            _ = &mut deadline => tracing::warn!("{} job(s) still running after {:?}, shutting down anyway", state.jobs.active(), shutdown_timeout),
+        }
+    }
+
+    // Feedback is appended to FEEDBACK_LOG as it arrives; buffered spans and metrics are not
+    tracing::info!("Shutdown complete");
+    #[cfg(feature = "otel")]
+    if let Some(telemetry) = telemetry {
+        telemetry.shutdown();
+    }
+}
+
+// Where to find things, printed at startup with text logs
+fn print_banner(base_url: &str) {
+    println!("🚀 Server starting on {}", base_url);
+    println!("📋 Health checks: {0}/healthz (liveness), {0}/readyz (readiness), {0}/health/deep (dependencies)", base_url);
+    println!("🏷️  Build info: {}/version", base_url);
+    println!("🔐 Login endpoint: {}/login (refresh: POST /refresh)", base_url);
+    println!("📖 API docs: {}/docs", base_url);
+    #[cfg(feature = "graphql")]
+    println!("🔎 GraphQL: POST {0}/graphql (GraphiQL: GET {0}/graphql)", base_url);
+    #[cfg(feature = "demo")]
+    println!("💬 Demo UI: {}/demo", base_url);
+    #[cfg(feature = "slack")]
+    println!("🤖 Slack: POST {0}/slack/commands, POST {0}/slack/events (with SLACK_SIGNING_SECRET)", base_url);
     println!("🛡️  Protected endpoints require Authorization: Bearer <token>");
     println!("   - POST /hackrx/run");
+    println!("   - POST /query");
+    println!("   - POST /query/stream (server-sent events)");
+    println!("   - POST /retrieve");
+    println!("   - POST /feedback, GET /feedback");
+    println!("   - POST/GET /chat/sessions, DELETE /chat/sessions/:id, POST/GET /chat/sessions/:id/messages");
+    println!("   - POST /documents (multipart upload)");
+    println!("   - POST /jobs (background ingestion and hackrx runs), GET /jobs/:id");
+    println!("   - POST /admin/reindex, POST /admin/reload, GET /admin/usage, GET /admin/audit");
     println!("   - GET /protected");
-    
-    axum::serve(listener, app).await.unwrap();
-}
\ No newline at end of file
+}
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c().await.expect("failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+// Any origin when no allowlist is configured, otherwise exactly the listed origins
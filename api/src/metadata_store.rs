@@ -0,0 +1,422 @@
+use anyhow::{Context, Result};
+use rag_system::encryption::{open_text, seal_text, StateCipher};
+use rag_system::models::Document;
+use rag_system::Feedback;
+use sqlx::any::{install_default_drivers, AnyPoolOptions};
+use sqlx::{AnyPool, Row};
+use std::collections::HashSet;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::{mpsc, oneshot};
+use uuid::Uuid;
+
+use crate::audit::{AuditEntry, AuditFilter};
+use crate::jobs::Job;
+
+// Plain SQL understood by both SQLite and Postgres. Records are stored whole as JSON, next to
+// the columns they are looked up by.
+const SCHEMA: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS jobs (
+        id TEXT PRIMARY KEY,
+        tenant TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        status TEXT NOT NULL,
+        created_at BIGINT NOT NULL,
+        updated_at BIGINT NOT NULL,
+        record TEXT NOT NULL
+    )",
+    "CREATE INDEX IF NOT EXISTS jobs_tenant ON jobs (tenant, created_at)",
+    "CREATE TABLE IF NOT EXISTS feedback (
+        id TEXT PRIMARY KEY,
+        tenant TEXT,
+        query_id TEXT NOT NULL,
+        rating TEXT NOT NULL,
+        created_at BIGINT NOT NULL,
+        record TEXT NOT NULL
+    )",
+    "CREATE INDEX IF NOT EXISTS feedback_tenant ON feedback (tenant, created_at)",
+    "CREATE TABLE IF NOT EXISTS audit_entries (
+        id TEXT PRIMARY KEY,
+        created_at BIGINT NOT NULL,
+        user_name TEXT NOT NULL,
+        tenant TEXT NOT NULL,
+        token_id TEXT NOT NULL,
+        record TEXT NOT NULL
+    )",
+    "CREATE INDEX IF NOT EXISTS audit_entries_created_at ON audit_entries (created_at)",
+    "CREATE TABLE IF NOT EXISTS documents (
+        tenant TEXT NOT NULL,
+        id TEXT NOT NULL,
+        filename TEXT NOT NULL,
+        source TEXT,
+        version TEXT,
+        tags TEXT NOT NULL,
+        fingerprint TEXT,
+        chunks BIGINT NOT NULL,
+        indexed_at BIGINT NOT NULL,
+        PRIMARY KEY (tenant, id)
+    )",
+    "CREATE TABLE IF NOT EXISTS chunks (
+        tenant TEXT NOT NULL,
+        id TEXT NOT NULL,
+        document_id TEXT NOT NULL,
+        position BIGINT NOT NULL,
+        start_position BIGINT NOT NULL,
+        end_position BIGINT NOT NULL,
+        PRIMARY KEY (tenant, id)
+    )",
+    "CREATE INDEX IF NOT EXISTS chunks_document ON chunks (tenant, document_id)",
+];
+
+// Writes made from synchronous code (job updates, audit entries), applied in order by one
+// background task so a job's rows never go back to an earlier state
+enum Write {
+    Job(Job),
+    Audit(AuditEntry),
+    Documents { tenant: String, documents: Vec<DocumentRow> },
+    // Queued too, so entries still waiting to be written are redacted as well
+    RedactAudit { filename: String, chunk_ids: HashSet<String>, done: oneshot::Sender<Result<usize>> },
+}
+
+// What is kept of an indexed document: everything but its text and embeddings
+struct DocumentRow {
+    id: String,
+    filename: String,
+    source: Option<String>,
+    version: Option<String>,
+    tags: Vec<String>,
+    fingerprint: Option<String>,
+    // (id, start_position, end_position) in document order
+    chunks: Vec<(String, usize, usize)>,
+}
+
+impl DocumentRow {
+    fn new(document: &Document) -> Self {
+        Self {
+            id: document.id.clone(),
+            filename: document.filename.clone(),
+            source: document.metadata.source.clone(),
+            version: document.metadata.version.clone(),
+            tags: document.metadata.tags.clone(),
+            fingerprint: document.metadata.fingerprint.clone(),
+            chunks: document
+                .chunks
+                .iter()
+                .map(|chunk| (chunk.id.clone(), chunk.start_position, chunk.end_position))
+                .collect(),
+        }
+    }
+}
+
+// Job records, feedback, audit entries and the metadata of indexed documents in a SQLite or
+// Postgres database (DATABASE_URL), so they survive restarts. Without it the api keeps them
+// in memory only. Cheap to clone; clones share the pool and writer.
+//
+// With a cipher the stored records, which hold questions and answers, are encrypted; the
+// columns they are looked up by are not. Records stored before are still read.
+#[derive(Clone)]
+pub struct MetadataStore {
+    pool: AnyPool,
+    writes: mpsc::UnboundedSender<Write>,
+    backend: String,
+    cipher: Option<StateCipher>,
+}
+
+impl MetadataStore {
+    // Connects to `url` (sqlite://path/to/file.db or postgres://...) and creates missing
+    // tables. A SQLite file is created if it does not exist.
+    pub async fn connect(url: &str, cipher: Option<StateCipher>) -> Result<Self> {
+        install_default_drivers();
+        let backend = url.split(':').next().unwrap_or_default().to_string();
+        let url = if backend != "sqlite" || url.contains("mode=") {
+            url.to_string()
+        } else if url.contains('?') {
+            format!("{}&mode=rwc", url)
+        } else {
+            format!("{}?mode=rwc", url)
+        };
+        let pool = AnyPoolOptions::new()
+            .max_connections(5)
+            .connect(&url)
+            .await
+            .context("connecting to the metadata database")?;
+        for statement in SCHEMA {
+            sqlx::query(statement)
+                .execute(&pool)
+                .await
+                .context("creating the metadata tables")?;
+        }
+
+        let (writes, queued) = mpsc::unbounded_channel();
+        tokio::spawn(write_queued(pool.clone(), cipher.clone(), queued));
+        Ok(Self { pool, writes, backend, cipher })
+    }
+
+    // e.g. "sqlite"
+    pub fn backend(&self) -> &str {
+        &self.backend
+    }
+
+    pub async fn ping(&self) -> Result<()> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+
+    pub fn save_job(&self, job: &Job) {
+        self.queue(Write::Job(job.clone()));
+    }
+
+    // Every stored job, oldest first
+    pub async fn jobs(&self) -> Result<Vec<Job>> {
+        let rows = sqlx::query("SELECT tenant, record FROM jobs ORDER BY created_at")
+            .fetch_all(&self.pool)
+            .await?;
+        let mut jobs = Vec::with_capacity(rows.len());
+        for row in rows {
+            let mut job: Job = serde_json::from_str(&self.open(row.try_get("record")?)?)?;
+            job.tenant = row.try_get("tenant")?;
+            jobs.push(job);
+        }
+        Ok(jobs)
+    }
+
+    pub fn save_audit_entry(&self, entry: &AuditEntry) {
+        self.queue(Write::Audit(entry.clone()));
+    }
+
+    // Entries matching `filter`, newest first
+    pub async fn audit_entries(&self, filter: &AuditFilter) -> Result<Vec<AuditEntry>> {
+        // Placeholders are numbered, which both backends accept
+        let mut sql = "SELECT record FROM audit_entries WHERE 1 = 1".to_string();
+        let mut texts = Vec::new();
+        for (column, value) in [("user_name", &filter.user), ("tenant", &filter.tenant), ("token_id", &filter.token_id)] {
+            if let Some(value) = value {
+                texts.push(value.clone());
+                sql.push_str(&format!(" AND {} = ${}", column, texts.len()));
+            }
+        }
+        let mut bounds = Vec::new();
+        for (operator, value) in [(">=", filter.since), ("<=", filter.until)] {
+            if let Some(value) = value {
+                bounds.push(value as i64);
+                sql.push_str(&format!(" AND created_at {} ${}", operator, texts.len() + bounds.len()));
+            }
+        }
+        sql.push_str(" ORDER BY created_at DESC, id DESC");
+
+        let mut query = sqlx::query(&sql);
+        for text in texts {
+            query = query.bind(text);
+        }
+        for bound in bounds {
+            query = query.bind(bound);
+        }
+        let mut entries = Vec::new();
+        for row in query.fetch_all(&self.pool).await? {
+            match serde_json::from_str::<AuditEntry>(&self.open(row.try_get("record")?)?) {
+                // The filters on questions and answers are applied to the decoded entries
+                Ok(entry) if filter.matches(&entry) => entries.push(entry),
+                Ok(_) => {}
+                Err(e) => tracing::warn!("Skipping unreadable audit entry: {}", e),
+            }
+        }
+        Ok(entries)
+    }
+
+    // See AuditLog::purge; returns the number of items redacted
+    pub async fn redact_audit_entries(&self, filename: &str, chunk_ids: &HashSet<String>) -> Result<usize> {
+        let (done, redacted) = oneshot::channel();
+        self.queue(Write::RedactAudit { filename: filename.to_string(), chunk_ids: chunk_ids.clone(), done });
+        redacted.await.context("the metadata database writer stopped")?
+    }
+
+    pub async fn save_feedback(&self, feedback: &Feedback) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO feedback (id, tenant, query_id, rating, created_at, record) VALUES ($1, $2, $3, $4, $5, $6)",
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(feedback.tenant.clone())
+        .bind(feedback.query_id.clone())
+        .bind(serde_json::to_value(feedback.rating)?.as_str().unwrap_or_default().to_string())
+        .bind(feedback.timestamp as i64)
+        .bind(seal_text(self.cipher.as_ref(), serde_json::to_string(feedback)?))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    // The tenant's feedback, oldest first
+    pub async fn feedback(&self, tenant: &str) -> Result<Vec<Feedback>> {
+        let rows = sqlx::query("SELECT record FROM feedback WHERE tenant = $1 ORDER BY created_at, id")
+            .bind(tenant.to_string())
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter()
+            .map(|row| Ok(serde_json::from_str(&self.open(row.try_get("record")?)?)?))
+            .collect()
+    }
+
+    // Replaces the stored metadata of the tenant's documents with that of `documents`, the
+    // whole collection
+    pub fn save_documents(&self, tenant: &str, documents: &[Document]) {
+        self.queue(Write::Documents {
+            tenant: tenant.to_string(),
+            documents: documents.iter().map(DocumentRow::new).collect(),
+        });
+    }
+
+    fn queue(&self, write: Write) {
+        // Only fails once the writer is gone, i.e. during shutdown
+        let _ = self.writes.send(write);
+    }
+
+    // A stored record, decrypted
+    fn open(&self, record: String) -> Result<String> {
+        Ok(open_text(self.cipher.as_ref(), record)?)
+    }
+}
+
+async fn write_queued(pool: AnyPool, cipher: Option<StateCipher>, mut queued: mpsc::UnboundedReceiver<Write>) {
+    let cipher = cipher.as_ref();
+    while let Some(write) = queued.recv().await {
+        let (what, result) = match write {
+            Write::RedactAudit { filename, chunk_ids, done } => {
+                let _ = done.send(redact_audit_entries(&pool, cipher, &filename, &chunk_ids).await);
+                continue;
+            }
+            Write::Job(job) => ("job", write_job(&pool, cipher, &job).await),
+            Write::Audit(entry) => ("audit entry", write_audit_entry(&pool, cipher, &entry).await),
+            Write::Documents { tenant, documents } => ("documents", write_documents(&pool, &tenant, documents).await),
+        };
+        if let Err(e) = result {
+            tracing::error!("Failed to store {} in the metadata database: {:#}", what, e);
+        }
+    }
+}
+
+async fn write_job(pool: &AnyPool, cipher: Option<&StateCipher>, job: &Job) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO jobs (id, tenant, kind, status, created_at, updated_at, record)
+         VALUES ($1, $2, $3, $4, $5, $6, $7)
+         ON CONFLICT (id) DO UPDATE SET status = excluded.status, updated_at = excluded.updated_at, record = excluded.record",
+    )
+    .bind(job.id.clone())
+    .bind(job.tenant.clone())
+    .bind(job.kind.clone())
+    .bind(serde_json::to_value(job.status)?.as_str().unwrap_or_default().to_string())
+    .bind(job.created_at as i64)
+    .bind(job.updated_at as i64)
+    .bind(seal_text(cipher, serde_json::to_string(job)?))
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+async fn write_audit_entry(pool: &AnyPool, cipher: Option<&StateCipher>, entry: &AuditEntry) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO audit_entries (id, created_at, user_name, tenant, token_id, record) VALUES ($1, $2, $3, $4, $5, $6)",
+    )
+    .bind(entry.id.clone())
+    .bind(entry.timestamp as i64)
+    .bind(entry.user.clone())
+    .bind(entry.tenant.clone())
+    .bind(entry.token_id.clone())
+    .bind(seal_text(cipher, serde_json::to_string(entry)?))
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+async fn redact_audit_entries(
+    pool: &AnyPool,
+    cipher: Option<&StateCipher>,
+    filename: &str,
+    chunk_ids: &HashSet<String>,
+) -> Result<usize> {
+    // Records may be encrypted, so all of them are read and checked here
+    let rows = sqlx::query("SELECT id, record FROM audit_entries").fetch_all(pool).await?;
+    let mut redacted = 0;
+    for row in rows {
+        let Ok(mut entry) = serde_json::from_str::<AuditEntry>(&open_text(cipher, row.try_get("record")?)?) else {
+            continue;
+        };
+        let items = entry.redact(filename, chunk_ids);
+        if items > 0 {
+            sqlx::query("UPDATE audit_entries SET record = $1 WHERE id = $2")
+                .bind(seal_text(cipher, serde_json::to_string(&entry)?))
+                .bind(row.try_get::<String, _>("id")?)
+                .execute(pool)
+                .await?;
+            redacted += items;
+        }
+    }
+    Ok(redacted)
+}
+
+async fn write_documents(pool: &AnyPool, tenant: &str, documents: Vec<DocumentRow>) -> Result<()> {
+    let mut transaction = pool.begin().await?;
+    let stored: Vec<String> = sqlx::query("SELECT id FROM documents WHERE tenant = $1")
+        .bind(tenant.to_string())
+        .fetch_all(&mut *transaction)
+        .await?
+        .iter()
+        .map(|row| row.try_get("id"))
+        .collect::<Result<_, _>>()?;
+    let current: HashSet<&str> = documents.iter().map(|doc| doc.id.as_str()).collect();
+    for removed in stored.iter().filter(|id| !current.contains(id.as_str())) {
+        sqlx::query("DELETE FROM documents WHERE tenant = $1 AND id = $2")
+            .bind(tenant.to_string())
+            .bind(removed.clone())
+            .execute(&mut *transaction)
+            .await?;
+    }
+    sqlx::query("DELETE FROM chunks WHERE tenant = $1")
+        .bind(tenant.to_string())
+        .execute(&mut *transaction)
+        .await?;
+
+    let now = unix_timestamp() as i64;
+    for document in documents {
+        // indexed_at is when the document first appeared
+        sqlx::query(
+            "INSERT INTO documents (tenant, id, filename, source, version, tags, fingerprint, chunks, indexed_at)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+             ON CONFLICT (tenant, id) DO UPDATE SET filename = excluded.filename, source = excluded.source,
+                 version = excluded.version, tags = excluded.tags, fingerprint = excluded.fingerprint,
+                 chunks = excluded.chunks",
+        )
+        .bind(tenant.to_string())
+        .bind(document.id.clone())
+        .bind(document.filename)
+        .bind(document.source)
+        .bind(document.version)
+        .bind(serde_json::to_string(&document.tags)?)
+        .bind(document.fingerprint)
+        .bind(document.chunks.len() as i64)
+        .bind(now)
+        .execute(&mut *transaction)
+        .await?;
+        for (position, (id, start, end)) in document.chunks.into_iter().enumerate() {
+            sqlx::query(
+                "INSERT INTO chunks (tenant, id, document_id, position, start_position, end_position)
+                 VALUES ($1, $2, $3, $4, $5, $6)",
+            )
+            .bind(tenant.to_string())
+            .bind(id)
+            .bind(document.id.clone())
+            .bind(position as i64)
+            .bind(start as i64)
+            .bind(end as i64)
+            .execute(&mut *transaction)
+            .await?;
+        }
+    }
+    transaction.commit().await?;
+    Ok(())
+}
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
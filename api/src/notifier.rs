@@ -0,0 +1,265 @@
+use serde::Serialize;
+use serde_json::json;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config::Config;
+use crate::usage::{UsageTotals, UsageWindow};
+use crate::AppState;
+
+// How often the circuit breakers are checked for newly opened circuits
+const CIRCUIT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+const DAY_SECS: u64 = 24 * 60 * 60;
+
+// Discord rejects messages longer than this
+const DISCORD_MAX_CHARS: usize = 2000;
+
+// What a notification is about; NOTIFY_EVENTS picks which are sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotifyEvent {
+    IngestionCompleted,
+    IngestionFailed,
+    CircuitOpened,
+    DailyUsage,
+}
+
+impl FromStr for NotifyEvent {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim() {
+            "ingestion_completed" => Ok(Self::IngestionCompleted),
+            "ingestion_failed" => Ok(Self::IngestionFailed),
+            "circuit_opened" => Ok(Self::CircuitOpened),
+            "daily_usage" => Ok(Self::DailyUsage),
+            other => Err(format!(
+                "unknown event {:?}, expected ingestion_completed, ingestion_failed, circuit_opened or daily_usage",
+                other
+            )),
+        }
+    }
+}
+
+// A webhook notifications are posted to, in the shape its service expects
+#[derive(Debug, Clone)]
+pub struct NotifyTarget {
+    kind: TargetKind,
+    url: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TargetKind {
+    // {"content": text}
+    Discord,
+    // {"text": text}, accepted by Teams incoming webhooks and workflows
+    Teams,
+    // The notification as JSON, with a `text` summary
+    Generic,
+}
+
+// `discord=<url>`, `teams=<url>`, `generic=<url>` or a plain URL, which is generic
+impl FromStr for NotifyTarget {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        let (kind, url) = match value.split_once('=') {
+            Some(("discord", url)) => (TargetKind::Discord, url),
+            Some(("teams", url)) => (TargetKind::Teams, url),
+            Some(("generic", url)) => (TargetKind::Generic, url),
+            _ => (TargetKind::Generic, value),
+        };
+        match reqwest::Url::parse(url) {
+            Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => Ok(Self { kind, url: url.to_string() }),
+            _ => Err(format!("{:?} is not an http(s) URL, optionally prefixed with discord=, teams= or generic=", value)),
+        }
+    }
+}
+
+impl NotifyTarget {
+    // The service's name, for logs and the startup summary
+    pub fn kind(&self) -> &'static str {
+        match self.kind {
+            TargetKind::Discord => "discord",
+            TargetKind::Teams => "teams",
+            TargetKind::Generic => "generic",
+        }
+    }
+}
+
+// Something operators should hear about
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Notification {
+    // An ingestion job or the startup ingestion finished
+    IngestionCompleted {
+        tenant: String,
+        // Job kind ("upload", "ingest", "reindex", "reload") or "startup"
+        kind: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        job_id: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        message: Option<String>,
+    },
+    IngestionFailed {
+        tenant: String,
+        kind: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        job_id: Option<String>,
+        error: String,
+    },
+    // Calls to a provider ("llm" or "embedding") started failing fast
+    CircuitOpened { provider: String },
+    // Usage over the past day, all tenants together
+    DailyUsage { since: u64, totals: UsageTotals },
+}
+
+impl Notification {
+    fn event(&self) -> NotifyEvent {
+        match self {
+            Self::IngestionCompleted { .. } => NotifyEvent::IngestionCompleted,
+            Self::IngestionFailed { .. } => NotifyEvent::IngestionFailed,
+            Self::CircuitOpened { .. } => NotifyEvent::CircuitOpened,
+            Self::DailyUsage { .. } => NotifyEvent::DailyUsage,
+        }
+    }
+
+    // One line for chat services
+    fn text(&self) -> String {
+        let job = |job_id: &Option<String>| job_id.as_ref().map(|id| format!(" (job {})", id)).unwrap_or_default();
+        match self {
+            Self::IngestionCompleted { tenant, kind, job_id, message } => format!(
+                "✅ {} ingestion for tenant {} completed{}{}",
+                kind,
+                tenant,
+                job(job_id),
+                message.as_ref().map(|message| format!(": {}", message)).unwrap_or_default()
+            ),
+            Self::IngestionFailed { tenant, kind, job_id, error } => {
+                format!("❌ {} ingestion for tenant {} failed{}: {}", kind, tenant, job(job_id), error)
+            }
+            Self::CircuitOpened { provider } => {
+                format!("⚠️ The {} provider keeps failing; calls to it fail fast and answers are degraded", provider)
+            }
+            Self::DailyUsage { totals, .. } => format!(
+                "📊 Past 24h: {} requests, {} questions, {} LLM calls, {} prompt + {} completion tokens, about ${:.2}",
+                totals.requests,
+                totals.questions,
+                totals.llm_calls,
+                totals.prompt_tokens,
+                totals.completion_tokens,
+                totals.estimated_cost_usd
+            ),
+        }
+    }
+}
+
+// Posts notifications to the NOTIFY_WEBHOOKS targets in the background, so whatever raised
+// them never waits on a chat service. Deliveries are retried like job webhooks.
+pub struct Notifier {
+    targets: Vec<NotifyTarget>,
+    events: Vec<NotifyEvent>,
+    daily_usage_hour: u64,
+    max_attempts: u32,
+    client: reqwest::Client,
+}
+
+impl Notifier {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            targets: config.notify_webhooks.clone(),
+            events: config.notify_events.clone(),
+            daily_usage_hour: config.notify_daily_usage_hour % 24,
+            max_attempts: config.webhook_max_attempts.max(1),
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(config.webhook_timeout_secs))
+                .build()
+                .unwrap_or_default(),
+        }
+    }
+
+    fn wants(&self, event: NotifyEvent) -> bool {
+        !self.targets.is_empty() && self.events.contains(&event)
+    }
+
+    pub fn notify(self: &Arc<Self>, notification: Notification) {
+        if !self.wants(notification.event()) {
+            return;
+        }
+        for index in 0..self.targets.len() {
+            let (notifier, notification) = (self.clone(), notification.clone());
+            tokio::spawn(async move { notifier.deliver(&notifier.targets[index], &notification).await });
+        }
+    }
+
+    async fn deliver(&self, target: &NotifyTarget, notification: &Notification) {
+        let text = notification.text();
+        let body = match target.kind {
+            TargetKind::Discord => json!({ "content": text.chars().take(DISCORD_MAX_CHARS).collect::<String>() }),
+            TargetKind::Teams => json!({ "text": text }),
+            TargetKind::Generic => {
+                let mut body = serde_json::to_value(notification).unwrap_or_default();
+                body["text"] = json!(text);
+                body
+            }
+        };
+
+        let mut backoff = Duration::from_secs(1);
+        for attempt in 1..=self.max_attempts {
+            match self.send(target, &body).await {
+                Ok(()) => return,
+                Err(e) if attempt < self.max_attempts => {
+                    tracing::warn!("Notification attempt {} to {} failed: {}, retrying", attempt, target.kind(), e);
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                Err(e) => tracing::error!("Giving up on a notification to {} after {} attempts: {}", target.kind(), attempt, e),
+            }
+        }
+    }
+
+    async fn send(&self, target: &NotifyTarget, body: &serde_json::Value) -> anyhow::Result<()> {
+        let response = self.client.post(&target.url).json(body).send().await?;
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("{} webhook answered {}", target.kind(), response.status()));
+        }
+        Ok(())
+    }
+}
+
+// Watches for the events nothing else reports: circuits opening and the daily usage summary
+pub fn spawn_watchers(state: Arc<AppState>) {
+    let notifier = state.notifier.clone();
+    if notifier.wants(NotifyEvent::CircuitOpened) {
+        let state = state.clone();
+        tokio::spawn(async move {
+            let mut open: Vec<&'static str> = Vec::new();
+            loop {
+                tokio::time::sleep(CIRCUIT_POLL_INTERVAL).await;
+                let now_open = state.rag_library.open_circuits();
+                for provider in now_open.iter().filter(|provider| !open.contains(provider)) {
+                    state.notifier.notify(Notification::CircuitOpened { provider: provider.to_string() });
+                }
+                open = now_open;
+            }
+        });
+    }
+    if notifier.wants(NotifyEvent::DailyUsage) {
+        tokio::spawn(async move {
+            loop {
+                let now = unix_timestamp();
+                let today = now - now % DAY_SECS + notifier.daily_usage_hour * 60 * 60;
+                let next = if today > now { today } else { today + DAY_SECS };
+                tokio::time::sleep(Duration::from_secs(next - now)).await;
+                let report = state.usage.report(UsageWindow::Day);
+                notifier.notify(Notification::DailyUsage { since: report.since, totals: report.totals });
+            }
+        });
+    }
+}
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
@@ -0,0 +1,117 @@
+use crate::feedback_payload::FeedbackPayload;
+use crate::chat_session::{ChatMessage, ChatReply, ChatSession, ChatTranscript};
+use crate::hackrx_request::HackRxRequest;
+use crate::hackrx_response::{AnswerDetails, AnswerStatus, HackRxResponse};
+use crate::audit::{AuditEntry, AuditItem};
+use crate::answer_format::{AnswerFormat, StructuredAnswer, StructuredCitation};
+use crate::inline_document::InlineDocument;
+use crate::job_request::JobRequest;
+use crate::jobs::{Job, JobStatus};
+use crate::query_payload::QueryPayload;
+use crate::rag_response::RagResponse;
+use crate::reindex_payload::ReindexPayload;
+use crate::reload_response::{ReloadFailure, ReloadResponse, ReloadedDocument};
+use crate::retrieval_options::RetrievalOptions;
+use crate::upload_response::{UploadResponse, UploadedDocument};
+use crate::auth::TokenPair;
+use crate::document_listing::{ChunkSummary, ClauseSummary, DocumentSummary};
+use crate::purge_response::PurgeResponse;
+use crate::analytics::{CacheHitRate, CacheHitRates, DocumentRetrievals, LatencyPercentiles, QueryAnalytics, VolumeInterval};
+use rag_system::cache::CacheStats;
+use crate::error::ErrorBody;
+use crate::webhooks::WebhookEvent;
+use crate::usage::{TokenUsageSummary, UsageReport, UsageTotals, UsageWindow};
+use crate::validation::FieldError;
+use crate::health::{self, ReadinessCheck, ReadinessReport};
+use crate::version::{self, VersionInfo};
+use crate::{utils, LoginRequest, LoginResponse, RefreshRequest};
+
+use rag_system::models::{
+    AbstentionPolicy, AccessControl, Clause, ClauseKind, Entity, EntityKind, PurgeReport, AnswerConfidence, Conflict, ConflictingValue, Decision, DecisionOutcome, DocumentAnswer, QueryDebug,
+    PageSpan, RankingStage, RankingWeights, ResponseMode, RetrievalResponse, RetrievalScores, RetrievedChunk,
+    SourceOffsets, StageScore, StreamEvent, TextSpan,
+};
+use rag_system::{Feedback, Rating, TokenUsage};
+use rag_system::feedback::QueryRecord;
+use utoipa::openapi::security::{Http, HttpAuthScheme, SecurityScheme};
+use utoipa::{Modify, OpenApi, ToSchema};
+
+// Multipart body of POST /documents; only documented, the handler reads the parts directly
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct UploadForm {
+    // One or more PDF, DOCX, TXT or Markdown files
+    #[schema(value_type = Vec<String>, format = Binary)]
+    files: Vec<Vec<u8>>,
+}
+
+// OpenAPI document served at /api-docs/openapi.json and rendered by Swagger UI at /docs
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "HackRx RAG API", description = "Question answering over insurance and policy documents"),
+    paths(
+        health::healthz,
+        health::readyz,
+        health::deep_health,
+        version::version,
+        crate::login,
+        crate::refresh,
+        crate::protected,
+        utils::handle_hackrx_run,
+        utils::handle_query_with_pdf_url,
+        utils::handle_query_stream,
+        utils::handle_retrieve,
+        utils::handle_feedback,
+        utils::handle_list_feedback,
+        utils::handle_create_chat_session,
+        utils::handle_list_chat_sessions,
+        utils::handle_delete_chat_session,
+        utils::handle_delete_document,
+        utils::handle_list_chat_messages,
+        utils::handle_send_chat_message,
+        utils::handle_upload_documents,
+        utils::handle_list_documents,
+        utils::handle_list_chunks,
+        utils::handle_list_clauses,
+        utils::handle_reindex,
+        utils::handle_reload,
+        utils::handle_create_job,
+        utils::handle_list_jobs,
+        utils::handle_get_job,
+        utils::handle_usage,
+        utils::handle_analytics,
+        utils::handle_search_audit,
+    ),
+    components(schemas(
+        LoginRequest, LoginResponse, RefreshRequest, TokenPair, ReadinessReport, ReadinessCheck, VersionInfo,
+        HackRxRequest, HackRxResponse, AnswerDetails, AnswerStatus, RetrievalScores, InlineDocument, AnswerFormat, StructuredAnswer, StructuredCitation, TextSpan, SourceOffsets, PageSpan, QueryPayload, RetrievalOptions, RagResponse, RetrievalResponse,
+        RetrievedChunk, StreamEvent, FeedbackPayload, Feedback, QueryRecord, Rating, UploadForm,
+        ChatSession, ChatMessage, ChatTranscript, ChatReply,
+        UploadResponse, UploadedDocument, ReindexPayload, ReloadResponse, ReloadedDocument, ReloadFailure, JobRequest, Job, JobStatus, ErrorBody, FieldError,
+        DocumentSummary, AccessControl, ChunkSummary, ClauseSummary, Clause, ClauseKind, Entity, EntityKind, PurgeResponse, PurgeReport, WebhookEvent, UsageReport, UsageTotals, TokenUsageSummary, UsageWindow, QueryAnalytics, VolumeInterval, LatencyPercentiles, DocumentRetrievals, CacheHitRates, CacheHitRate, CacheStats, AuditEntry, AuditItem, TokenUsage,
+        RankingWeights, ResponseMode, AbstentionPolicy, Decision, DecisionOutcome, Conflict,
+        ConflictingValue, DocumentAnswer, QueryDebug, AnswerConfidence, RankingStage, StageScore,
+    )),
+    modifiers(&BearerAuth),
+    tags(
+        (name = "health", description = "Liveness and readiness probes, build information"),
+        (name = "auth", description = "Tokens for the protected endpoints"),
+        (name = "query", description = "Question answering and retrieval"),
+        (name = "documents", description = "Document ingestion"),
+        (name = "feedback", description = "Ratings for earlier answers"),
+        (name = "chat", description = "Multi-turn conversations over the same index"),
+        (name = "jobs", description = "Long-running ingestion and question batches with progress"),
+        (name = "admin", description = "Index maintenance, usage statistics and the audit log"),
+    )
+)]
+pub struct ApiDoc;
+
+// Registers the "bearer_auth" scheme referenced by the protected paths
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme("bearer_auth", SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)));
+    }
+}
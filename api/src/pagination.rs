@@ -0,0 +1,52 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 500;
+
+// limit/offset query parameters shared by the listing endpoints
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PageParams {
+    // Items per page (default 50, at most 500)
+    pub limit: Option<usize>,
+    // Items to skip
+    pub offset: Option<usize>,
+}
+
+// One page of a listing together with the number of items matching the filters
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub total: usize,
+    pub limit: usize,
+    pub offset: usize,
+    // Offset of the following page; absent on the last page
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_offset: Option<usize>,
+}
+
+impl PageParams {
+    // Slices `items`, already filtered and in listing order, into the requested page
+    pub fn paginate<T>(&self, items: impl IntoIterator<Item = T>) -> Page<T> {
+        let limit = self.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+        let offset = self.offset.unwrap_or(0);
+
+        let mut total = 0;
+        let mut page = Vec::new();
+        for (i, item) in items.into_iter().enumerate() {
+            total += 1;
+            if i >= offset && page.len() < limit {
+                page.push(item);
+            }
+        }
+
+        Page {
+            items: page,
+            total,
+            limit,
+            offset,
+            next_offset: (offset + limit < total).then_some(offset + limit),
+        }
+    }
+}
@@ -0,0 +1,25 @@
+use rag_system::models::PurgeReport;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+// Query parameters of DELETE /documents/{id}
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DeleteOptions {
+    // Also remove every trace of the document: cached answers citing it, prompt log entries
+    // holding it and the answers based on it in the audit log
+    #[serde(default)]
+    pub purge: bool,
+    // With purge, also delete the file in the documents directory the document was ingested
+    // from; otherwise it is kept and ingested again on the next reload
+    #[serde(default)]
+    pub delete_source: bool,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct PurgeResponse {
+    #[serde(flatten)]
+    pub report: PurgeReport,
+    // Audited answers based on the document, replaced by a placeholder
+    pub audit_items_redacted: usize,
+}
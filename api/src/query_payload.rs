@@ -1,7 +1,39 @@
-use serde::{Deserialize, Serialize};
+use crate::config::Config;
+use crate::error::ApiError;
+use crate::inline_document::{DocumentSource, InlineDocument};
+use crate::retrieval_options::RetrievalOptions;
+use crate::validation::Validator;
+use serde::Deserialize;
+use utoipa::ToSchema;
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct QueryPayload {
     pub query: String,
     pub pdf_url: Option<String>, // New optional field for PDF URL
+    // The document itself, instead of pdf_url
+    pub document: Option<InlineDocument>,
+    #[serde(flatten)]
+    pub options: RetrievalOptions,
+}
+
+impl QueryPayload {
+    pub fn validate(&self, config: &Config) -> Result<(), ApiError> {
+        let mut validator = Validator::default();
+        validator.question("query", &self.query, config);
+        if let Some(url) = &self.pdf_url {
+            validator.document_url("pdf_url", url, config);
+        }
+        if let Some(document) = &self.document {
+            validator.inline_document("document", document, config);
+            if self.pdf_url.as_deref().is_some_and(|url| !url.trim().is_empty()) {
+                validator.error("document", "cannot be combined with pdf_url");
+            }
+        }
+        validator.retrieval_options(&self.options, config);
+        validator.finish()
+    }
+
+    pub fn document_source(&self) -> Option<DocumentSource<'_>> {
+        DocumentSource::from_request(self.pdf_url.as_deref(), self.document.as_ref())
+    }
 }
@@ -1,7 +1,49 @@
+use crate::answer_format::{AnswerFormat, StructuredAnswer};
+use rag_system::models::{Conflict, Decision, DocumentAnswer, QueryDebug};
+use rag_system::QueryResponse;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, ToSchema)]
 pub struct RagResponse {
+    // Pass to POST /feedback to rate this answer
+    pub query_id: String,
     pub answer: String,
     pub context_snippets: Vec<String>,
-}
\ No newline at end of file
+    // How sure the system is of the answer, in [0, 1]; absent when the question skipped
+    // retrieval
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence_score: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub debug: Option<QueryDebug>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decision: Option<Decision>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub conflicts: Vec<Conflict>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub document_answers: Vec<DocumentAnswer>,
+    // Only with format "json"
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub structured: Option<StructuredAnswer>,
+    // Set when a provider is failing: the answer is a stale cached one or says answering is
+    // unavailable
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub degraded: bool,
+}
+
+impl RagResponse {
+    pub fn new(response: QueryResponse, format: AnswerFormat) -> Self {
+        Self {
+            structured: format.structured(&response),
+            degraded: response.status == "degraded",
+            query_id: response.query_id,
+            answer: format.render(&response.response),
+            confidence_score: response.confidence_score,
+            context_snippets: response.citations.into_iter().map(|c| c.text_excerpt).collect(),
+            debug: response.debug,
+            decision: response.decision,
+            conflicts: response.conflicts,
+            document_answers: response.document_answers,
+        }
+    }
+}
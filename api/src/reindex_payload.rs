@@ -0,0 +1,11 @@
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+// Body of POST /admin/reindex; without a document every configured source is re-ingested
+#[derive(Deserialize, Default, ToSchema)]
+pub struct ReindexPayload {
+    pub document_id: Option<String>,
+    pub filename: Option<String>,
+    // Receives the finished job (see WebhookEvent)
+    pub callback_url: Option<String>,
+}
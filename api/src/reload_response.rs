@@ -0,0 +1,29 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+
+#[derive(Serialize, ToSchema)]
+pub struct ReloadedDocument {
+    pub document_id: String,
+    pub filename: String,
+}
+
+// A file that could not be ingested; its previous version, if any, stays indexed
+#[derive(Serialize, ToSchema)]
+pub struct ReloadFailure {
+    pub filename: String,
+    pub error: String,
+}
+
+// Changes POST /admin/reload applied to the index
+#[derive(Serialize, ToSchema)]
+pub struct ReloadResponse {
+    pub added: Vec<ReloadedDocument>,
+    pub updated: Vec<ReloadedDocument>,
+    pub removed: Vec<ReloadedDocument>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub failed: Vec<ReloadFailure>,
+    // Files whose contents did not change
+    pub unchanged: usize,
+    // Documents in the index after the reload
+    pub total_documents: usize,
+}
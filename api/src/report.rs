@@ -0,0 +1,363 @@
+use anyhow::{anyhow, Context};
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use rag_system::TokenUsage;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::analytics::percentile;
+use crate::audit::{AuditEntry, AuditFilter};
+use crate::config::Config;
+use crate::AppState;
+
+const DAY_SECS: u64 = 24 * 60 * 60;
+
+// 1970-01-01 was a Thursday, so the first Monday is day 4
+const FIRST_MONDAY: u64 = 4;
+
+// Answers with these statuses count as unanswered
+const UNANSWERED_STATUSES: [&str; 2] = ["insufficient_information", "error"];
+
+// How often the analytics report is sent; weekly reports go out on Mondays
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportSchedule {
+    Daily,
+    Weekly,
+}
+
+impl FromStr for ReportSchedule {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim() {
+            "daily" => Ok(Self::Daily),
+            "weekly" => Ok(Self::Weekly),
+            other => Err(format!("unknown schedule {:?}, expected daily or weekly", other)),
+        }
+    }
+}
+
+impl ReportSchedule {
+    fn period_secs(self) -> u64 {
+        match self {
+            Self::Daily => DAY_SECS,
+            Self::Weekly => 7 * DAY_SECS,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Daily => "daily",
+            Self::Weekly => "weekly",
+        }
+    }
+
+    // The first time after `now` a report is due, at `hour` UTC
+    fn next_run(self, now: u64, hour: u64) -> u64 {
+        let mut next = now - now % DAY_SECS + hour * 60 * 60;
+        while next <= now || (self == Self::Weekly && (next / DAY_SECS) % 7 != FIRST_MONDAY) {
+            next += DAY_SECS;
+        }
+        next
+    }
+}
+
+// How the connection to the SMTP server is secured
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmtpTls {
+    // Plain connection upgraded with STARTTLS, usually on port 587
+    StartTls,
+    // TLS from the start, usually on port 465
+    Tls,
+    // Unencrypted, for local relays only
+    None,
+}
+
+impl FromStr for SmtpTls {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim() {
+            "starttls" => Ok(Self::StartTls),
+            "tls" => Ok(Self::Tls),
+            "none" => Ok(Self::None),
+            other => Err(format!("unknown SMTP TLS mode {:?}, expected starttls, tls or none", other)),
+        }
+    }
+}
+
+impl SmtpTls {
+    fn default_port(self) -> u16 {
+        match self {
+            Self::StartTls => 587,
+            Self::Tls => 465,
+            Self::None => 25,
+        }
+    }
+}
+
+// What the audit log says about one report period, all tenants together
+#[derive(Debug, Clone)]
+pub struct AnalyticsReport {
+    schedule: ReportSchedule,
+    since: u64,
+    until: u64,
+    requests: usize,
+    questions: usize,
+    unanswered: usize,
+    // Over the questions whose latency was recorded
+    latency_p95_ms: Option<u64>,
+    usage: TokenUsage,
+    cost_usd: f64,
+    // Most asked questions with how often they were asked, compared ignoring case and spacing
+    top_questions: Vec<(String, usize)>,
+}
+
+impl AnalyticsReport {
+    fn from_entries(
+        schedule: ReportSchedule,
+        since: u64,
+        until: u64,
+        entries: &[AuditEntry],
+        max_questions: usize,
+        cost_usd: impl Fn(&TokenUsage) -> f64,
+    ) -> Self {
+        let mut usage = TokenUsage::default();
+        let mut latencies = Vec::new();
+        let (mut questions, mut unanswered) = (0, 0);
+        // Normalized question -> (one of its wordings, count)
+        let mut asked: HashMap<String, (String, usize)> = HashMap::new();
+        for entry in entries {
+            usage += entry.usage;
+            for item in &entry.questions {
+                questions += 1;
+                if UNANSWERED_STATUSES.contains(&item.status.as_str()) {
+                    unanswered += 1;
+                }
+                latencies.extend(item.latency_ms);
+                let key = item.question.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+                asked.entry(key).or_insert_with(|| (item.question.trim().to_string(), 0)).1 += 1;
+            }
+        }
+
+        latencies.sort_unstable();
+        let latency_p95_ms = percentile(&latencies, 95);
+        let mut top_questions: Vec<(String, usize)> = asked.into_values().collect();
+        top_questions.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        top_questions.truncate(max_questions);
+
+        Self {
+            schedule,
+            since,
+            until,
+            requests: entries.len(),
+            questions,
+            unanswered,
+            latency_p95_ms,
+            usage,
+            cost_usd: cost_usd(&usage),
+            top_questions,
+        }
+    }
+
+    fn unanswered_rate(&self) -> f64 {
+        match self.questions {
+            0 => 0.0,
+            questions => self.unanswered as f64 / questions as f64,
+        }
+    }
+
+    fn subject(&self) -> String {
+        let period = match self.schedule {
+            ReportSchedule::Daily => date(self.since),
+            ReportSchedule::Weekly => format!("{} to {}", date(self.since), date(self.until - 1)),
+        };
+        format!("Query analytics {} report, {}", self.schedule.name(), period)
+    }
+
+    // (label, value) rows of the summary table
+    fn summary(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("Requests", self.requests.to_string()),
+            ("Questions", self.questions.to_string()),
+            (
+                "Unanswered",
+                format!("{} ({:.1}%)", self.unanswered, self.unanswered_rate() * 100.0),
+            ),
+            (
+                "Latency p95",
+                self.latency_p95_ms.map(|ms| format!("{} ms", ms)).unwrap_or_else(|| "n/a".to_string()),
+            ),
+            ("LLM calls", self.usage.llm_calls.to_string()),
+            (
+                "Tokens",
+                format!("{} prompt + {} completion", self.usage.prompt_tokens, self.usage.completion_tokens),
+            ),
+            ("Estimated cost", format!("${:.2}", self.cost_usd)),
+        ]
+    }
+
+    fn to_text(&self) -> String {
+        let mut text = format!("{}\n\n", self.subject());
+        for (label, value) in self.summary() {
+            text.push_str(&format!("{}: {}\n", label, value));
+        }
+        if !self.top_questions.is_empty() {
+            text.push_str("\nTop questions:\n");
+            for (question, count) in &self.top_questions {
+                text.push_str(&format!("{:>5}  {}\n", count, question));
+            }
+        }
+        text
+    }
+
+    fn to_html(&self) -> String {
+        let mut html = format!(
+            "<html><body style=\"font-family: sans-serif\">\n<h2>{}</h2>\n<table cellpadding=\"4\">\n",
+            escape(&self.subject())
+        );
+        for (label, value) in self.summary() {
+            html.push_str(&format!("<tr><th align=\"left\">{}</th><td>{}</td></tr>\n", label, escape(&value)));
+        }
+        html.push_str("</table>\n");
+        if !self.top_questions.is_empty() {
+            html.push_str("<h3>Top questions</h3>\n<table cellpadding=\"4\">\n<tr><th align=\"right\">Asked</th><th align=\"left\">Question</th></tr>\n");
+            for (question, count) in &self.top_questions {
+                html.push_str(&format!("<tr><td align=\"right\">{}</td><td>{}</td></tr>\n", count, escape(question)));
+            }
+            html.push_str("</table>\n");
+        }
+        html.push_str("</body></html>\n");
+        html
+    }
+}
+
+// Emails an analytics report built from the audit log to REPORT_TO every day or week
+pub struct Reporter {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+    schedule: ReportSchedule,
+    hour: u64,
+    top_questions: usize,
+}
+
+impl Reporter {
+    // None when no SMTP host is configured
+    pub fn from_config(config: &Config) -> anyhow::Result<Option<Self>> {
+        let Some(host) = &config.smtp_host else {
+            return Ok(None);
+        };
+        if config.report_to.is_empty() {
+            return Err(anyhow!("REPORT_TO must list at least one recipient"));
+        }
+        let from = config
+            .report_from
+            .as_deref()
+            .ok_or_else(|| anyhow!("REPORT_FROM must be set"))?
+            .parse::<Mailbox>()
+            .context("REPORT_FROM is not an email address")?;
+        let to = config
+            .report_to
+            .iter()
+            .map(|address| address.parse::<Mailbox>().with_context(|| format!("{:?} is not an email address", address)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let builder = match config.smtp_tls {
+            SmtpTls::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?,
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host)?,
+            SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+        };
+        let mut builder = builder
+            .port(config.smtp_port.unwrap_or(config.smtp_tls.default_port()))
+            .timeout(Some(Duration::from_secs(config.webhook_timeout_secs)));
+        match (&config.smtp_username, &config.smtp_password) {
+            (Some(username), Some(password)) => {
+                builder = builder.credentials(Credentials::new(username.clone(), password.clone()))
+            }
+            (None, None) => {}
+            _ => return Err(anyhow!("SMTP_USERNAME and SMTP_PASSWORD must be set together")),
+        }
+
+        Ok(Some(Self {
+            transport: builder.build(),
+            from,
+            to,
+            schedule: config.report_schedule,
+            hour: config.report_hour % 24,
+            top_questions: config.report_top_questions,
+        }))
+    }
+
+    // Aggregates the audit entries of the period ending `until`
+    async fn build(&self, state: &AppState, until: u64) -> anyhow::Result<AnalyticsReport> {
+        let since = until.saturating_sub(self.schedule.period_secs());
+        let entries = state
+            .audit
+            .search(AuditFilter { since: Some(since), until: Some(until - 1), ..Default::default() })
+            .await?;
+        Ok(AnalyticsReport::from_entries(
+            self.schedule,
+            since,
+            until,
+            &entries,
+            self.top_questions,
+            |usage| state.usage.cost_usd(usage),
+        ))
+    }
+
+    async fn send(&self, report: &AnalyticsReport) -> anyhow::Result<()> {
+        let mut message = Message::builder().from(self.from.clone()).subject(report.subject());
+        for to in &self.to {
+            message = message.to(to.clone());
+        }
+        let message = message.multipart(MultiPart::alternative_plain_html(report.to_text(), report.to_html()))?;
+        self.transport.send(message).await?;
+        Ok(())
+    }
+}
+
+// Sends the report at REPORT_HOUR (UTC) every day, or every Monday for weekly reports
+pub fn spawn(state: Arc<AppState>, reporter: Reporter) {
+    tokio::spawn(async move {
+        loop {
+            let now = unix_timestamp();
+            let next = reporter.schedule.next_run(now, reporter.hour);
+            tokio::time::sleep(Duration::from_secs(next - now)).await;
+            let sent = match reporter.build(&state, next).await {
+                Ok(report) => reporter.send(&report).await,
+                Err(e) => Err(e),
+            };
+            match sent {
+                Ok(()) => tracing::info!("Sent the {} analytics report to {} recipient(s)", reporter.schedule.name(), reporter.to.len()),
+                Err(e) => tracing::error!("Could not send the {} analytics report: {:#}", reporter.schedule.name(), e),
+            }
+        }
+    });
+}
+
+// YYYY-MM-DD of a unix timestamp, in UTC
+fn date(timestamp: u64) -> String {
+    // Howard Hinnant's civil_from_days
+    let z = (timestamp / DAY_SECS) as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
@@ -0,0 +1,63 @@
+use rag_system::models::{AbstentionPolicy, EntityKind, QueryRequest, RankingWeights, ResponseMode};
+use crate::answer_format::AnswerFormat;
+use crate::config::Config;
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+// Retrieval tuning accepted by every question-answering payload
+#[derive(Deserialize, Default, Clone, ToSchema)]
+pub struct RetrievalOptions {
+    // Restrict retrieval to these indexed documents (by id or filename)
+    pub document_ids: Option<Vec<String>>,
+    pub filenames: Option<Vec<String>>,
+    // Number of chunks retrieved per question
+    pub max_results: Option<usize>,
+    // Minimum similarity a chunk needs to be used; below it the answer abstains
+    pub score_threshold: Option<f32>,
+    // Token budget for the context sent to the LLM
+    pub max_context_tokens: Option<usize>,
+    // Include ranking stages, packed context and prompt in the response
+    pub debug: Option<bool>,
+    // Weights for preferring newer or tagged documents (e.g. {"tags": {"current": 0.2}})
+    pub ranking_weights: Option<RankingWeights>,
+    // "decision" returns a typed approved/rejected/needs_info decision per question,
+    // "compare" answers per document and adds a comparison table
+    pub response_mode: Option<ResponseMode>,
+    // Threshold, wording and general-knowledge fallback for unanswerable questions
+    pub abstention: Option<AbstentionPolicy>,
+    // How answers are rendered: "plain", "markdown" (default, as generated), "json" (adds
+    // the structured decision/citation object) or "csv" (the citations as a spreadsheet).
+    // Ignored by /retrieve.
+    pub format: Option<AnswerFormat>,
+    // Answer questions about a single waiting period, sub-limit, exclusion or sum insured
+    // from the clauses extracted at ingest (CLAUSE_EXTRACTION), without the LLM; default true
+    pub clause_answers: Option<bool>,
+    // Only use chunks mentioning one of these: amount, duration, age, location or procedure
+    pub entity_kinds: Option<Vec<EntityKind>>,
+    // Without entity_kinds, rank chunks mentioning what the question asks for higher, e.g.
+    // durations for a waiting period question; default true
+    pub entity_boost: Option<bool>,
+}
+
+impl RetrievalOptions {
+    // The request for `question`, with max_results and max_context_tokens within the
+    // configured limits even when the options were not validated
+    pub fn to_request(&self, question: String, config: &Config) -> QueryRequest {
+        QueryRequest {
+            query: question,
+            max_results: Some(self.max_results.unwrap_or(config.max_results).min(config.max_results_limit)),
+            document_ids: self.document_ids.clone(),
+            filenames: self.filenames.clone(),
+            score_threshold: self.score_threshold,
+            max_context_tokens: self.max_context_tokens.map(|tokens| tokens.min(config.max_context_tokens_limit)),
+            debug: self.debug,
+            ranking_weights: self.ranking_weights.clone(),
+            response_mode: self.response_mode,
+            abstention: self.abstention.clone(),
+            clause_answers: self.clause_answers,
+            entity_kinds: self.entity_kinds.clone(),
+            entity_boost: self.entity_boost,
+            ..Default::default()
+        }
+    }
+}
@@ -0,0 +1,359 @@
+use anyhow::{bail, Context};
+use axum::{body::Bytes, extract::State, http::HeaderMap, Extension, Json};
+use hmac::{Hmac, Mac};
+use rag_system::{usage, QueryResponse, RagError};
+use reqwest::{Client, Url};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::answer_format::AnswerFormat;
+use crate::audit::{AuditContext, AuditItem};
+use crate::auth::{Claims, TokenType};
+use crate::config::Config;
+use crate::error::ApiError;
+use crate::retrieval_options::RetrievalOptions;
+use crate::tenants::is_valid_tenant;
+use crate::utils::answer_questions;
+use crate::AppState;
+
+const SIGNATURE_HEADER: &str = "x-slack-signature";
+const TIMESTAMP_HEADER: &str = "x-slack-request-timestamp";
+// Set on redeliveries of events Slack did not see acknowledged in time
+const RETRY_HEADER: &str = "x-slack-retry-num";
+
+// Requests signed longer ago than this are rejected as replays
+const MAX_SIGNATURE_AGE_SECS: u64 = 300;
+
+const POST_MESSAGE_URL: &str = "https://slack.com/api/chat.postMessage";
+
+// Documents listed under an answer
+const MAX_SOURCES: usize = 5;
+
+// Answers questions from Slack out of one collection: a slash command (`/ask <question>`)
+// and, with a bot token, mentions of the bot and direct messages to it. Slack authenticates
+// its requests with a signature over the body instead of a bearer token, and gives handlers
+// 3 seconds, so questions are acknowledged at once and answered in the background.
+pub struct Slack {
+    signing_secret: Vec<u8>,
+    bot_token: Option<String>,
+    tenant: String,
+    citation_base_url: Option<Url>,
+    client: Client,
+}
+
+#[derive(Deserialize)]
+struct SlashCommand {
+    #[serde(default)]
+    command: String,
+    #[serde(default)]
+    text: String,
+    user_id: String,
+    response_url: String,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum EventPayload {
+    // Sent once when the request URL is configured
+    UrlVerification { challenge: String },
+    EventCallback { event: Event },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct Event {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    text: String,
+    user: Option<String>,
+    channel: Option<String>,
+    ts: Option<String>,
+    // Set when the message is a reply, so the answer goes to the same thread
+    thread_ts: Option<String>,
+    // Set on the bot's own messages and other bots'
+    bot_id: Option<String>,
+    // Edits, joins and the like; questions are plain messages
+    subtype: Option<String>,
+    channel_type: Option<String>,
+}
+
+impl Slack {
+    // None unless SLACK_SIGNING_SECRET is set
+    pub fn from_config(config: &Config) -> anyhow::Result<Option<Self>> {
+        let Some(secret) = &config.slack_signing_secret else {
+            return Ok(None);
+        };
+        if !is_valid_tenant(&config.slack_tenant) {
+            bail!("SLACK_TENANT must be 1-64 letters, digits, '-' or '_'");
+        }
+        let citation_base_url = match &config.slack_citation_base_url {
+            // Without a trailing slash joining a filename would replace the last segment
+            Some(url) => Some(
+                Url::parse(&format!("{}/", url.trim_end_matches('/')))
+                    .with_context(|| format!("SLACK_CITATION_BASE_URL {:?} is not a URL", url))?,
+            ),
+            None => None,
+        };
+        Ok(Some(Self {
+            signing_secret: secret.as_bytes().to_vec(),
+            bot_token: config.slack_bot_token.clone(),
+            tenant: config.slack_tenant.clone(),
+            citation_base_url,
+            client: Client::builder().timeout(Duration::from_secs(10)).build()?,
+        }))
+    }
+
+    // Whether mentions and direct messages can be answered (see handle_event)
+    pub fn handles_events(&self) -> bool {
+        self.bot_token.is_some()
+    }
+
+    // Checks `v0=<hex HMAC-SHA256 of "v0:<timestamp>:<body>">` and that the timestamp is recent
+    fn verify(&self, headers: &HeaderMap, body: &[u8]) -> Result<(), ApiError> {
+        let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+        let invalid = || ApiError::unauthorized("invalid_slack_signature", "Missing or invalid Slack signature");
+
+        let timestamp = header(TIMESTAMP_HEADER).ok_or_else(invalid)?;
+        let signed_at: u64 = timestamp.parse().map_err(|_| invalid())?;
+        if unix_timestamp().abs_diff(signed_at) > MAX_SIGNATURE_AGE_SECS {
+            return Err(invalid());
+        }
+        let signature = header(SIGNATURE_HEADER)
+            .and_then(|signature| signature.strip_prefix("v0="))
+            .and_then(|signature| hex::decode(signature).ok())
+            .ok_or_else(invalid)?;
+
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.signing_secret).expect("HMAC accepts keys of any length");
+        mac.update(b"v0:");
+        mac.update(timestamp.as_bytes());
+        mac.update(b":");
+        mac.update(body);
+        mac.verify_slice(&signature).map_err(|_| invalid())
+    }
+
+    // Answers `question` for Slack user `user` and formats the reply, recording usage and
+    // the audit entry as the REST routes do
+    async fn answer(&self, state: &AppState, user: &str, question: &str, endpoint: &str) -> String {
+        let claims = self.claims(user);
+        let _permit = state.answer_limiter.wait().await;
+        let request = RetrievalOptions::default().to_request(question.to_string(), &state.config);
+        let (results, usage) = usage::track(answer_questions(state, &claims, None, vec![request])).await;
+        let result = results.map_err(|e| e.message).and_then(|mut results| {
+            results
+                .pop()
+                .unwrap_or_else(|| Err(RagError::Llm("No response generated".to_string())))
+                .map_err(|e| e.to_string())
+        });
+
+        let item = match &result {
+            Ok(response) => AuditItem::answered(question, response),
+            Err(e) => AuditItem::failed(question, e),
+        };
+        let audit = AuditContext { request_id: None, endpoint: format!("Slack {}", endpoint) };
+        state.usage.record(&claims, 1, 1, usage);
+        state.audit.record(&claims, &audit, vec![item], usage);
+
+        match result {
+            Ok(response) => self.reply(&response),
+            Err(e) => {
+                tracing::error!("Failed to answer Slack question from {}: {}", user, e);
+                format!("Sorry, I could not answer that: {}", escape(&e))
+            }
+        }
+    }
+
+    // Slack users are not API users; they are accounted for as "slack:<user id>"
+    fn claims(&self, user: &str) -> Claims {
+        let now = unix_timestamp();
+        Claims {
+            sub: format!("slack:{}", user),
+            tenant: self.tenant.clone(),
+            iss: "slack".to_string(),
+            iat: now,
+            exp: now,
+            jti: format!("slack:{}", user),
+            token_type: TokenType::Access,
+            roles: Vec::new(),
+        }
+    }
+
+    // The answer as plain text, then the cited documents, one link per document and page
+    fn reply(&self, response: &QueryResponse) -> String {
+        let mut reply = escape(&AnswerFormat::Plain.render(&response.response));
+        let mut sources: Vec<(&str, Option<usize>)> = Vec::new();
+        for citation in &response.citations {
+            let source = (citation.document.as_str(), citation.page);
+            if !sources.contains(&source) && sources.len() < MAX_SOURCES {
+                sources.push(source);
+            }
+        }
+        if !sources.is_empty() {
+            let links: Vec<String> = sources.into_iter().map(|(document, page)| self.source_link(document, page)).collect();
+            reply.push_str("\n\n*Sources:* ");
+            reply.push_str(&links.join(", "));
+        }
+        reply
+    }
+
+    // `<url|label>` with SLACK_CITATION_BASE_URL, else the label
+    fn source_link(&self, document: &str, page: Option<usize>) -> String {
+        let label = match page {
+            Some(page) => format!("{}, page {}", document, page),
+            None => document.to_string(),
+        };
+        let url = self.citation_base_url.as_ref().and_then(|base| base.join(document).ok());
+        match url {
+            Some(mut url) => {
+                if let Some(page) = page {
+                    url.set_fragment(Some(&format!("page={}", page)));
+                }
+                format!("<{}|{}>", url, escape(&label))
+            }
+            None => escape(&label),
+        }
+    }
+
+    async fn post(&self, url: &str, token: Option<&str>, body: Value) -> anyhow::Result<()> {
+        let mut request = self.client.post(url).json(&body);
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            bail!("Slack answered {}", response.status());
+        }
+        // The Web API reports errors as { "ok": false, "error": ... } with status 200;
+        // response URLs answer with plain text
+        if let Ok(reply) = response.json::<Value>().await {
+            if reply["ok"] == json!(false) {
+                bail!("Slack answered {}", reply["error"]);
+            }
+        }
+        Ok(())
+    }
+}
+
+// Handler for POST /slack/commands, the slash command's request URL. The question is
+// acknowledged to the asker only, and the answer posted to the channel when it is ready.
+pub async fn handle_command(
+    State(state): State<Arc<AppState>>,
+    Extension(slack): Extension<Arc<Slack>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<Value>, ApiError> {
+    slack.verify(&headers, &body)?;
+    let command: SlashCommand = serde_urlencoded::from_bytes(&body)
+        .map_err(|e| ApiError::bad_request("invalid_slack_payload", format!("Invalid slash command: {}", e)))?;
+
+    let question = command.text.trim().to_string();
+    if question.is_empty() {
+        return Ok(Json(ephemeral(&format!("Ask a question, e.g. `{} What is the grace period?`", command.command))));
+    }
+    if question.chars().count() > state.config.max_question_chars {
+        return Ok(Json(ephemeral(&format!(
+            "Questions can be at most {} characters long",
+            state.config.max_question_chars
+        ))));
+    }
+
+    let acknowledgement = ephemeral(&format!("Looking up: {}", escape(&question)));
+    tokio::spawn(async move {
+        let answer = slack.answer(&state, &command.user_id, &question, &command.command).await;
+        let text = format!("<@{}> asked: {}\n\n{}", command.user_id, escape(&question), answer);
+        let message = json!({ "response_type": "in_channel", "text": text });
+        if let Err(e) = slack.post(&command.response_url, None, message).await {
+            tracing::error!("Failed to post the answer to a Slack command: {}", e);
+        }
+    });
+    Ok(Json(acknowledgement))
+}
+
+// Handler for POST /slack/events, the Events API request URL. Mentions of the bot and direct
+// messages to it are answered in a thread under the question.
+pub async fn handle_event(
+    State(state): State<Arc<AppState>>,
+    Extension(slack): Extension<Arc<Slack>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<Value>, ApiError> {
+    slack.verify(&headers, &body)?;
+    let payload: EventPayload = serde_json::from_slice(&body)
+        .map_err(|e| ApiError::bad_request("invalid_slack_payload", format!("Invalid event: {}", e)))?;
+
+    let event = match payload {
+        EventPayload::UrlVerification { challenge } => return Ok(Json(json!({ "challenge": challenge }))),
+        EventPayload::EventCallback { event } => event,
+        EventPayload::Other => return Ok(Json(json!({}))),
+    };
+    // The first delivery is already being answered
+    if headers.contains_key(RETRY_HEADER) {
+        return Ok(Json(json!({})));
+    }
+    let Some(question) = event_question(&event) else {
+        return Ok(Json(json!({})));
+    };
+    let (Some(user), Some(channel), Some(ts)) = (event.user, event.channel, event.ts) else {
+        return Ok(Json(json!({})));
+    };
+    if question.chars().count() > state.config.max_question_chars {
+        tracing::warn!("Ignoring a Slack question of over {} characters", state.config.max_question_chars);
+        return Ok(Json(json!({})));
+    }
+
+    tokio::spawn(async move {
+        let answer = slack.answer(&state, &user, &question, &event.kind).await;
+        let message = json!({ "channel": channel, "thread_ts": event.thread_ts.unwrap_or(ts), "text": answer });
+        if let Err(e) = slack.post(POST_MESSAGE_URL, slack.bot_token.as_deref(), message).await {
+            tracing::error!("Failed to post the answer to a Slack {}: {}", event.kind, e);
+        }
+    });
+    Ok(Json(json!({})))
+}
+
+// The question in a mention of the bot (without the mention) or a direct message to it;
+// None for other events, bots' messages and empty questions
+fn event_question(event: &Event) -> Option<String> {
+    if event.bot_id.is_some() || event.subtype.is_some() {
+        return None;
+    }
+    let question = match (event.kind.as_str(), event.channel_type.as_deref()) {
+        ("app_mention", _) => strip_mentions(&event.text),
+        ("message", Some("im")) => event.text.trim().to_string(),
+        _ => return None,
+    };
+    (!question.is_empty()).then_some(question)
+}
+
+// Removes user mentions such as <@U012AB3CD>
+fn strip_mentions(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("<@") {
+        stripped.push_str(&rest[..start]);
+        rest = match rest[start..].find('>') {
+            Some(end) => &rest[start + end + 1..],
+            None => "",
+        };
+    }
+    stripped.push_str(rest);
+    stripped.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// Reply only the asker sees
+fn ephemeral(text: &str) -> Value {
+    json!({ "response_type": "ephemeral", "text": text })
+}
+
+// Slack's mrkdwn treats these as control characters
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
@@ -0,0 +1,237 @@
+use anyhow::Context;
+use axum::extract::{MatchedPath, Request};
+use axum::middleware::Next;
+use axum::response::Response;
+use opentelemetry::metrics::{Counter, Histogram, MeterProvider};
+use opentelemetry::trace::TracerProvider;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::{MetricExporter, SpanExporter, WithExportConfig};
+use opentelemetry_sdk::metrics::SdkMeterProvider;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use opentelemetry_sdk::Resource;
+use rag_system::BoxedLayer;
+use std::fmt;
+use std::sync::OnceLock;
+use std::time::Instant;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::Subscriber;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::Context as LayerContext;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+use crate::config::Config;
+
+// OTLP export (HTTP/protobuf) of traces and metrics to OTEL_EXPORTER_OTLP_ENDPOINT, e.g. an
+// OpenTelemetry Collector. Traces are the spans the logs show (request, ingest, embedding,
+// retrieval, llm); the metrics are:
+//
+//   rag.stage.duration        - seconds per ingest, embedding, retrieval and llm span; llm
+//                               calls carry their phase (answer, translate, judge, ...)
+//   rag.llm.tokens            - estimated tokens per phase and type (prompt, completion)
+//   rag.llm.errors            - failed LLM calls per phase
+//   http.server.request.duration - seconds per method, route and status code, from which
+//                               request and error rates follow
+pub struct Telemetry {
+    tracer_provider: SdkTracerProvider,
+    meter_provider: SdkMeterProvider,
+}
+
+// Pipeline stages measured by rag.stage.duration, by span name
+const STAGES: [&str; 4] = ["ingest", "embedding", "retrieval", "llm"];
+
+static HTTP_DURATION: OnceLock<Histogram<f64>> = OnceLock::new();
+
+impl Telemetry {
+    // Exporters for `endpoint` (the collector's base URL, without /v1/traces)
+    pub fn new(config: &Config, endpoint: &str) -> anyhow::Result<Self> {
+        let endpoint = endpoint.trim_end_matches('/');
+        let resource = Resource::builder().with_service_name(config.otel_service_name.clone()).build();
+
+        let spans = SpanExporter::builder()
+            .with_http()
+            .with_endpoint(format!("{}/v1/traces", endpoint))
+            .build()
+            .context("OTLP trace exporter")?;
+        let tracer_provider = SdkTracerProvider::builder()
+            .with_batch_exporter(spans)
+            .with_resource(resource.clone())
+            .build();
+
+        let metrics = MetricExporter::builder()
+            .with_http()
+            .with_endpoint(format!("{}/v1/metrics", endpoint))
+            .build()
+            .context("OTLP metric exporter")?;
+        let meter_provider = SdkMeterProvider::builder()
+            .with_periodic_exporter(metrics)
+            .with_resource(resource)
+            .build();
+
+        Ok(Self { tracer_provider, meter_provider })
+    }
+
+    // Layers for Logging::with_layer: span export, and metrics from the pipeline spans
+    pub fn layers(&self) -> Vec<BoxedLayer> {
+        let meter = self.meter_provider.meter("hackrx-rag");
+        let _ = HTTP_DURATION.set(
+            meter
+                .f64_histogram("http.server.request.duration")
+                .with_unit("s")
+                .with_description("Duration of HTTP requests")
+                .build(),
+        );
+        let stage_metrics = StageMetrics {
+            duration: meter
+                .f64_histogram("rag.stage.duration")
+                .with_unit("s")
+                .with_description("Duration of ingestion, embedding, retrieval and LLM calls")
+                .build(),
+            tokens: meter
+                .u64_counter("rag.llm.tokens")
+                .with_description("Estimated LLM tokens")
+                .build(),
+            errors: meter
+                .u64_counter("rag.llm.errors")
+                .with_description("Failed LLM calls")
+                .build(),
+        };
+
+        let tracer = self.tracer_provider.tracer("hackrx-rag");
+        vec![
+            tracing_opentelemetry::layer().with_tracer(tracer).with_filter(LevelFilter::INFO).boxed(),
+            stage_metrics.boxed(),
+        ]
+    }
+
+    // Exports what is still buffered
+    pub fn shutdown(&self) {
+        if let Err(e) = self.tracer_provider.shutdown() {
+            eprintln!("Failed to flush traces: {}", e);
+        }
+        if let Err(e) = self.meter_provider.shutdown() {
+            eprintln!("Failed to flush metrics: {}", e);
+        }
+    }
+}
+
+// Records http.server.request.duration for every request
+pub async fn record_http_metrics(request: Request, next: Next) -> Response {
+    let method = request.method().to_string();
+    // Templates such as /jobs/:id, so ids do not become separate series
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let started = Instant::now();
+    let response = next.run(request).await;
+
+    if let Some(histogram) = HTTP_DURATION.get() {
+        histogram.record(
+            started.elapsed().as_secs_f64(),
+            &[
+                KeyValue::new("http.request.method", method),
+                KeyValue::new("http.route", route),
+                KeyValue::new("http.response.status_code", i64::from(response.status().as_u16())),
+            ],
+        );
+    }
+    response
+}
+
+struct StageMetrics {
+    duration: Histogram<f64>,
+    tokens: Counter<u64>,
+    errors: Counter<u64>,
+}
+
+// Kept in the extensions of a stage's span until it closes
+struct StageTiming {
+    started: Instant,
+    fields: StageFields,
+}
+
+#[derive(Default)]
+struct StageFields {
+    phase: Option<String>,
+    prompt_tokens: Option<u64>,
+    completion_tokens: Option<u64>,
+    failed: bool,
+}
+
+impl Visit for StageFields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "phase" => self.phase = Some(value.to_string()),
+            "error" => self.failed = true,
+            _ => {}
+        }
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        match field.name() {
+            "prompt_tokens" => self.prompt_tokens = Some(value),
+            "completion_tokens" => self.completion_tokens = Some(value),
+            _ => {}
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        match field.name() {
+            "phase" => self.phase = Some(format!("{:?}", value).trim_matches('"').to_string()),
+            "error" => self.failed = true,
+            _ => {}
+        }
+    }
+}
+
+impl<S> Layer<S> for StageMetrics
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: LayerContext<'_, S>) {
+        if !STAGES.contains(&attrs.metadata().name()) {
+            return;
+        }
+        let Some(span) = ctx.span(id) else { return };
+        let mut fields = StageFields::default();
+        attrs.record(&mut fields);
+        span.extensions_mut().insert(StageTiming { started: Instant::now(), fields });
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: LayerContext<'_, S>) {
+        let Some(span) = ctx.span(id) else { return };
+        let mut extensions = span.extensions_mut();
+        if let Some(timing) = extensions.get_mut::<StageTiming>() {
+            values.record(&mut timing.fields);
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: LayerContext<'_, S>) {
+        let Some(span) = ctx.span(&id) else { return };
+        let Some(timing) = span.extensions_mut().remove::<StageTiming>() else { return };
+
+        let mut attributes = vec![KeyValue::new("stage", span.name())];
+        if let Some(phase) = timing.fields.phase {
+            attributes.push(KeyValue::new("phase", phase));
+        }
+        self.duration.record(timing.started.elapsed().as_secs_f64(), &attributes);
+
+        let with_type = |kind: &'static str| {
+            let mut attributes = attributes.clone();
+            attributes.push(KeyValue::new("type", kind));
+            attributes
+        };
+        if let Some(tokens) = timing.fields.prompt_tokens {
+            self.tokens.add(tokens, &with_type("prompt"));
+        }
+        if let Some(tokens) = timing.fields.completion_tokens {
+            self.tokens.add(tokens, &with_type("completion"));
+        }
+        if timing.fields.failed {
+            self.errors.add(1, &attributes);
+        }
+    }
+}
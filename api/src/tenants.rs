@@ -0,0 +1,58 @@
+use rag_system::{DocumentStore, RagError, RagLibrary};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+// Tenant of tokens issued without one; owns the documents ingested from DOCUMENTS_DIR
+pub const DEFAULT_TENANT: &str = "default";
+
+const MAX_TENANT_LEN: usize = 64;
+
+// One tenant's documents with the embedding provider fitted to them. Each tenant gets its
+// own provider because corpus statistics (e.g. the TF-IDF vocabulary) would otherwise mix
+// collections and re-fitting for one tenant would invalidate another's embeddings.
+pub type Collection = DocumentStore;
+
+pub struct TenantRegistry {
+    collections: RwLock<HashMap<String, Arc<Collection>>>,
+}
+
+impl TenantRegistry {
+    // Registry whose default tenant's collection is the library's own store, holding the
+    // documents of DOCUMENTS_DIR
+    pub fn new(library: &RagLibrary) -> Self {
+        let default = library.store().clone();
+        Self {
+            collections: RwLock::new(HashMap::from([(DEFAULT_TENANT.to_string(), default)])),
+        }
+    }
+
+    // The tenant's collection, created empty on first use
+    pub async fn collection(&self, tenant: &str, library: &RagLibrary) -> Result<Arc<Collection>, RagError> {
+        if let Some(collection) = self.collections.read().await.get(tenant) {
+            return Ok(collection.clone());
+        }
+
+        let store = library.new_store().await?;
+        let mut collections = self.collections.write().await;
+        let collection = collections
+            .entry(tenant.to_string())
+            .or_insert_with(|| {
+                tracing::info!("Creating document collection for tenant {}", tenant);
+                Arc::new(store)
+            })
+            .clone();
+        Ok(collection)
+    }
+
+    pub async fn len(&self) -> usize {
+        self.collections.read().await.len()
+    }
+}
+
+// Tenant ids end up in tokens and logs, so keep them to a short, plain alphabet
+pub fn is_valid_tenant(tenant: &str) -> bool {
+    !tenant.is_empty()
+        && tenant.len() <= MAX_TENANT_LEN
+        && tenant.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
@@ -0,0 +1,120 @@
+use anyhow::{anyhow, Result};
+use axum::Router;
+use axum_server::tls_rustls::RustlsConfig;
+use axum_server::Handle;
+use std::future::Future;
+use tokio::net::TcpListener;
+
+use crate::config::Config;
+
+// How the listener is exposed, decided from the TLS settings in Config
+pub enum TlsMode {
+    Plain,
+    // Certificate chain and private key read from PEM files
+    Files,
+    // Certificates obtained and renewed from Let's Encrypt
+    Acme,
+}
+
+impl TlsMode {
+    pub fn from_config(config: &Config) -> Result<Self> {
+        match (&config.tls_cert, &config.tls_key, config.acme_domains.is_empty()) {
+            (None, None, true) => Ok(Self::Plain),
+            (Some(_), Some(_), true) => Ok(Self::Files),
+            (None, None, false) if cfg!(feature = "acme") => Ok(Self::Acme),
+            (None, None, false) => Err(anyhow!("ACME_DOMAINS is set but the api was built without the `acme` feature")),
+            (Some(_), Some(_), false) => Err(anyhow!("Configure either TLS certificate files or ACME_DOMAINS, not both")),
+            _ => Err(anyhow!("TLS_CERT_PATH and TLS_KEY_PATH must be set together")),
+        }
+    }
+
+    pub fn scheme(&self) -> &'static str {
+        match self {
+            Self::Plain => "http",
+            Self::Files | Self::Acme => "https",
+        }
+    }
+}
+
+// Serves `app` on `listener` until `shutdown` resolves, then lets in-flight requests finish
+pub async fn serve<F>(listener: TcpListener, app: Router, config: &Config, shutdown: F) -> Result<()>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let mode = TlsMode::from_config(config)?;
+    if let TlsMode::Plain = mode {
+        axum::serve(listener, app).with_graceful_shutdown(shutdown).await?;
+        return Ok(());
+    }
+
+    // Only ring is compiled in; installing it explicitly keeps rustls from guessing
+    let _ = rustls::crypto::ring::default_provider().install_default();
+
+    let handle = Handle::new();
+    tokio::spawn({
+        let handle = handle.clone();
+        async move {
+            shutdown.await;
+            handle.graceful_shutdown(None);
+        }
+    });
+
+    let listener = listener.into_std()?;
+    let service = app.into_make_service();
+    match mode {
+        TlsMode::Files => {
+            let (Some(cert), Some(key)) = (&config.tls_cert, &config.tls_key) else {
+                unreachable!("checked by TlsMode::from_config");
+            };
+            let tls = RustlsConfig::from_pem_file(cert, key)
+                .await
+                .map_err(|e| anyhow!("Failed to load TLS certificate {} / key {}: {}", cert.display(), key.display(), e))?;
+            axum_server::from_tcp_rustls(listener, tls).handle(handle).serve(service).await?;
+        }
+        TlsMode::Acme => serve_acme(listener, service, config, handle).await?,
+        TlsMode::Plain => unreachable!(),
+    }
+    Ok(())
+}
+
+#[cfg(feature = "acme")]
+async fn serve_acme(
+    listener: std::net::TcpListener,
+    service: axum::routing::IntoMakeService<Router>,
+    config: &Config,
+    handle: Handle,
+) -> Result<()> {
+    use rustls_acme::caches::DirCache;
+    use rustls_acme::AcmeConfig;
+    use tokio_stream::StreamExt;
+
+    let mut state = AcmeConfig::new(config.acme_domains.clone())
+        .contact(config.acme_contact.iter().map(|email| format!("mailto:{}", email)))
+        .cache(DirCache::new(config.acme_cache_dir.clone()))
+        .directory_lets_encrypt(config.acme_production)
+        .state();
+    let acceptor = state.axum_acceptor(state.default_rustls_config());
+
+    // Drives certificate orders and renewals for the lifetime of the server
+    tokio::spawn(async move {
+        while let Some(event) = state.next().await {
+            match event {
+                Ok(ok) => tracing::info!("ACME: {:?}", ok),
+                Err(e) => tracing::error!("ACME error: {:?}", e),
+            }
+        }
+    });
+
+    axum_server::from_tcp(listener).acceptor(acceptor).handle(handle).serve(service).await?;
+    Ok(())
+}
+
+#[cfg(not(feature = "acme"))]
+async fn serve_acme(
+    _listener: std::net::TcpListener,
+    _service: axum::routing::IntoMakeService<Router>,
+    _config: &Config,
+    _handle: Handle,
+) -> Result<()> {
+    unreachable!("rejected by TlsMode::from_config without the `acme` feature")
+}
@@ -0,0 +1,52 @@
+use rag_system::models::AccessControl;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+// Query parameters of POST /documents
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UploadOptions {
+    // Return the indexing job (202) instead of waiting for it
+    #[serde(default)]
+    pub background: bool,
+    // Comma-separated users, roles and tenants the uploaded documents are restricted to;
+    // public when none is given
+    pub allowed_users: Option<String>,
+    pub allowed_roles: Option<String>,
+    pub allowed_tenants: Option<String>,
+}
+
+impl UploadOptions {
+    // The access control list for the uploaded documents, None when they are public
+    pub fn access(&self) -> Option<AccessControl> {
+        let list = |value: &Option<String>| -> Vec<String> {
+            value
+                .iter()
+                .flat_map(|value| value.split(','))
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(str::to_string)
+                .collect()
+        };
+        let access = AccessControl {
+            users: list(&self.allowed_users),
+            tenants: list(&self.allowed_tenants),
+            roles: list(&self.allowed_roles),
+        };
+        (!access.is_public()).then_some(access)
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct UploadedDocument {
+    pub document_id: String,
+    pub filename: String,
+    pub chunks: usize,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct UploadResponse {
+    pub documents: Vec<UploadedDocument>,
+    // Documents in the index after the upload
+    pub total_documents: usize,
+}
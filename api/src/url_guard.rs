@@ -0,0 +1,274 @@
+use rag_system::download::is_public;
+use reqwest::header::{HeaderMap, LOCATION};
+use reqwest::{redirect, Response, StatusCode, Url};
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use crate::config::Config;
+
+// Why a request to a user-supplied URL was not made or did not complete
+#[derive(Debug)]
+pub enum OutboundError {
+    // The URL, or a URL it redirected to, is not allowed
+    Blocked(String),
+    Request(reqwest::Error),
+}
+
+impl fmt::Display for OutboundError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Blocked(reason) => write!(f, "{}", reason),
+            Self::Request(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for OutboundError {}
+
+impl From<reqwest::Error> for OutboundError {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Request(e)
+    }
+}
+
+// Guards the requests the server makes to URLs from clients (documents to download and
+// callback URLs), so they cannot be pointed at internal services: only http(s), only hosts
+// on URL_ALLOWED_HOSTS when it is set, and unless ALLOW_PRIVATE_URLS is set, only hosts
+// resolving to public addresses. The checked addresses are the ones connected to, so a
+// DNS answer cannot change in between, and every redirect is checked the same way.
+#[derive(Debug, Clone)]
+pub struct UrlGuard {
+    allow_private: bool,
+    allowed_hosts: Vec<String>,
+    max_redirects: usize,
+    timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+}
+
+impl UrlGuard {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            allow_private: config.allow_private_urls,
+            allowed_hosts: config.url_allowed_hosts.iter().map(|host| host.trim().to_ascii_lowercase()).collect(),
+            max_redirects: config.max_redirects,
+            timeout: None,
+            connect_timeout: None,
+            read_timeout: None,
+        }
+    }
+
+    // Whole request, body included
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    // Longest wait for the next bytes of the response, however long it takes overall
+    pub fn with_read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    // The checks that need no DNS lookup: scheme, allowlist and IP address literals
+    pub fn check(&self, url: &str) -> Result<Url, String> {
+        let url = Url::parse(url).map_err(|_| "is not a valid URL".to_string())?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err("must be an http or https URL".to_string());
+        }
+        let Some(host) = url.host_str() else {
+            return Err("must have a host".to_string());
+        };
+        if !self.host_allowed(host) {
+            return Err(format!("host {} is not on the allowed hosts list", host));
+        }
+        if let Some(ip) = ip_literal(&url) {
+            if !self.allow_private && !is_public(ip) {
+                return Err(format!("{} is not a public address", ip));
+            }
+        }
+        Ok(url)
+    }
+
+    // GETs `url`, following up to MAX_REDIRECTS redirects
+    pub async fn get(&self, url: &str, headers: HeaderMap) -> Result<Response, OutboundError> {
+        let mut url = self.check(url).map_err(OutboundError::Blocked)?;
+        for _ in 0..=self.max_redirects {
+            let response = self.client(&url).await?.get(url.clone()).headers(headers.clone()).send().await?;
+            let location = response.headers().get(LOCATION).and_then(|value| value.to_str().ok());
+            let next = match (is_redirect(response.status()), location) {
+                (true, Some(location)) => url
+                    .join(location)
+                    .map_err(|_| OutboundError::Blocked(format!("redirected to an invalid URL: {}", location)))?,
+                _ => return Ok(response),
+            };
+            tracing::info!("{} redirected to {}", url, next);
+            url = self
+                .check(next.as_str())
+                .map_err(|reason| OutboundError::Blocked(format!("redirected to {}, which {}", next, reason)))?;
+        }
+        Err(OutboundError::Blocked(format!("more than {} redirects", self.max_redirects)))
+    }
+
+    // POSTs `body` to `url`; redirects are not followed
+    pub async fn post(&self, url: &str, headers: HeaderMap, body: Vec<u8>) -> Result<Response, OutboundError> {
+        let url = self.check(url).map_err(OutboundError::Blocked)?;
+        Ok(self.client(&url).await?.post(url).headers(headers).body(body).send().await?)
+    }
+
+    // Client for one request to `url`, connecting only to its checked addresses
+    async fn client(&self, url: &Url) -> Result<reqwest::Client, OutboundError> {
+        let mut builder = reqwest::Client::builder().redirect(redirect::Policy::none());
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.read_timeout {
+            builder = builder.read_timeout(timeout);
+        }
+        if !self.allow_private && ip_literal(url).is_none() {
+            let host = url.host_str().unwrap_or_default();
+            let addrs = self.resolve(host, url.port_or_known_default().unwrap_or(80)).await?;
+            builder = builder.resolve_to_addrs(host, &addrs);
+        }
+        Ok(builder.build()?)
+    }
+
+    async fn resolve(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>, OutboundError> {
+        let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|e| OutboundError::Blocked(format!("could not resolve {}: {}", host, e)))?
+            .collect();
+        if addrs.is_empty() {
+            return Err(OutboundError::Blocked(format!("{} did not resolve to any address", host)));
+        }
+        if let Some(private) = addrs.iter().find(|addr| !is_public(addr.ip())) {
+            return Err(OutboundError::Blocked(format!("{} resolves to {}, which is not a public address", host, private.ip())));
+        }
+        Ok(addrs)
+    }
+
+    // Exact hosts, or "*.example.com" for the subdomains of example.com; empty allows any host
+    fn host_allowed(&self, host: &str) -> bool {
+        let host = host.to_ascii_lowercase();
+        self.allowed_hosts.is_empty()
+            || self.allowed_hosts.iter().any(|allowed| match allowed.strip_prefix("*.") {
+                Some(domain) => host.strip_suffix(domain).is_some_and(|prefix| prefix.ends_with('.')),
+                None => *allowed == host,
+            })
+    }
+}
+
+fn is_redirect(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::MOVED_PERMANENTLY
+            | StatusCode::FOUND
+            | StatusCode::SEE_OTHER
+            | StatusCode::TEMPORARY_REDIRECT
+            | StatusCode::PERMANENT_REDIRECT
+    )
+}
+
+fn ip_literal(url: &Url) -> Option<IpAddr> {
+    // IPv6 hosts come bracketed, e.g. "[::1]"
+    url.host_str()?.trim_start_matches('[').trim_end_matches(']').parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn guard(allowed_hosts: &[&str]) -> UrlGuard {
+        UrlGuard {
+            allow_private: false,
+            allowed_hosts: allowed_hosts.iter().map(|host| host.to_string()).collect(),
+            max_redirects: 5,
+            timeout: None,
+            connect_timeout: None,
+            read_timeout: None,
+        }
+    }
+
+    fn public(ip: &str) -> bool {
+        is_public(ip.parse().unwrap())
+    }
+
+    #[test]
+    fn public_addresses_are_allowed() {
+        for ip in ["8.8.8.8", "1.1.1.1", "100.128.0.1", "198.20.0.1", "2606:4700:4700::1111", "::ffff:8.8.8.8"] {
+            assert!(public(ip), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn internal_addresses_are_not_public() {
+        for ip in [
+            "127.0.0.1",
+            "10.0.0.1",
+            "172.16.5.4",
+            "192.168.1.1",
+            // Cloud metadata services
+            "169.254.169.254",
+            "0.0.0.0",
+            "0.1.2.3",
+            "100.64.0.1",
+            "198.18.0.1",
+            "192.0.2.1",
+            "224.0.0.1",
+            "240.0.0.1",
+            "255.255.255.255",
+            "::",
+            "::1",
+            "fc00::1",
+            "fd12:3456::1",
+            "fe80::1",
+            "ff02::1",
+            "2001:db8::1",
+            // IPv4-mapped IPv6 addresses are checked as the IPv4 address
+            "::ffff:127.0.0.1",
+            "::ffff:169.254.169.254",
+        ] {
+            assert!(!public(ip), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn check_rejects_other_schemes_and_private_literals() {
+        let guard = guard(&[]);
+
+        assert!(guard.check("https://example.com/policy.pdf").is_ok());
+        assert!(guard.check("http://8.8.8.8/policy.pdf").is_ok());
+        assert!(guard.check("file:///etc/passwd").is_err());
+        assert!(guard.check("ftp://example.com/policy.pdf").is_err());
+        assert!(guard.check("not a url").is_err());
+        assert!(guard.check("http://127.0.0.1:8080/admin").is_err());
+        assert!(guard.check("http://[::1]/").is_err());
+        assert!(guard.check("http://[::ffff:10.0.0.1]/").is_err());
+        assert!(guard.check("http://169.254.169.254/latest/meta-data/").is_err());
+
+        let guard = UrlGuard { allow_private: true, ..guard };
+        assert!(guard.check("http://127.0.0.1:8080/admin").is_ok());
+    }
+
+    #[test]
+    fn check_applies_the_allowed_hosts() {
+        let guard = guard(&["docs.example.com", "*.insurer.in"]);
+
+        assert!(guard.check("https://docs.example.com/a.pdf").is_ok());
+        assert!(guard.check("https://DOCS.example.com/a.pdf").is_ok());
+        assert!(guard.check("https://cdn.insurer.in/a.pdf").is_ok());
+        assert!(guard.check("https://a.b.insurer.in/a.pdf").is_ok());
+        assert!(guard.check("https://example.com/a.pdf").is_err());
+        assert!(guard.check("https://insurer.in/a.pdf").is_err());
+        assert!(guard.check("https://evilinsurer.in/a.pdf").is_err());
+    }
+}
@@ -0,0 +1,222 @@
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use rag_system::usage;
+use rag_system::TokenUsage;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::audit::{AnsweredQuestions, AuditContext};
+use crate::auth::Claims;
+use crate::config::Config;
+use crate::AppState;
+
+// Usage is kept per minute, for as long as the longest window
+const BUCKET_SECS: u64 = 60;
+const RETENTION_SECS: u64 = 30 * 24 * 60 * 60;
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum UsageWindow {
+    Hour,
+    #[default]
+    Day,
+    Week,
+    Month,
+}
+
+impl UsageWindow {
+    pub fn secs(self) -> u64 {
+        match self {
+            Self::Hour => 60 * 60,
+            Self::Day => 24 * 60 * 60,
+            Self::Week => 7 * 24 * 60 * 60,
+            Self::Month => RETENTION_SECS,
+        }
+    }
+}
+
+// Query parameters of GET /admin/usage
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UsageQuery {
+    // How far back to report (hour, day, week or month; default day)
+    pub window: Option<UsageWindow>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct UsageTotals {
+    pub requests: u64,
+    pub questions: u64,
+    pub llm_calls: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub estimated_cost_usd: f64,
+}
+
+// Usage of one access token (identified by its jti claim)
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct TokenUsageSummary {
+    pub token_id: String,
+    pub user: String,
+    pub tenant: String,
+    #[serde(flatten)]
+    pub totals: UsageTotals,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UsageReport {
+    pub window: String,
+    // Unix timestamp the window starts at
+    pub since: u64,
+    pub totals: UsageTotals,
+    // Busiest tokens (by total LLM tokens) first
+    pub tokens: Vec<TokenUsageSummary>,
+}
+
+#[derive(Default)]
+struct Bucket {
+    user: String,
+    tenant: String,
+    requests: u64,
+    questions: u64,
+    usage: TokenUsage,
+}
+
+#[derive(Default)]
+struct Buckets {
+    // Keyed by (minute, token id)
+    entries: HashMap<(u64, String), Bucket>,
+    last_pruned: u64,
+}
+
+// Request, question and LLM token counts per access token, in memory
+pub struct UsageTracker {
+    buckets: Mutex<Buckets>,
+    prompt_cost_per_million: f64,
+    completion_cost_per_million: f64,
+}
+
+impl UsageTracker {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            buckets: Mutex::default(),
+            prompt_cost_per_million: config.llm_prompt_cost_per_million,
+            completion_cost_per_million: config.llm_completion_cost_per_million,
+        }
+    }
+
+    pub fn record(&self, claims: &Claims, requests: u64, questions: u64, usage: TokenUsage) {
+        let minute = unix_timestamp() / BUCKET_SECS;
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.last_pruned != minute {
+            let oldest = minute.saturating_sub(RETENTION_SECS / BUCKET_SECS);
+            buckets.entries.retain(|(bucket_minute, _), _| *bucket_minute >= oldest);
+            buckets.last_pruned = minute;
+        }
+
+        let bucket = buckets.entries.entry((minute, claims.jti.clone())).or_insert_with(|| Bucket {
+            user: claims.sub.clone(),
+            tenant: claims.tenant.clone(),
+            ..Default::default()
+        });
+        bucket.requests += requests;
+        bucket.questions += questions;
+        bucket.usage += usage;
+    }
+
+    pub fn report(&self, window: UsageWindow) -> UsageReport {
+        let since = unix_timestamp().saturating_sub(window.secs());
+        let first_minute = since / BUCKET_SECS;
+
+        let mut by_token: HashMap<&str, Bucket> = HashMap::new();
+        let buckets = self.buckets.lock().unwrap();
+        for ((minute, token_id), bucket) in buckets.entries.iter() {
+            if *minute < first_minute {
+                continue;
+            }
+            let entry = by_token.entry(token_id).or_insert_with(|| Bucket {
+                user: bucket.user.clone(),
+                tenant: bucket.tenant.clone(),
+                ..Default::default()
+            });
+            entry.requests += bucket.requests;
+            entry.questions += bucket.questions;
+            entry.usage += bucket.usage;
+        }
+
+        let mut all = Bucket::default();
+        let mut tokens: Vec<TokenUsageSummary> = by_token
+            .into_iter()
+            .map(|(token_id, bucket)| {
+                all.requests += bucket.requests;
+                all.questions += bucket.questions;
+                all.usage += bucket.usage;
+                TokenUsageSummary {
+                    token_id: token_id.to_string(),
+                    totals: self.totals(&bucket),
+                    user: bucket.user,
+                    tenant: bucket.tenant,
+                }
+            })
+            .collect();
+        tokens.sort_by(|a, b| {
+            let total = |s: &TokenUsageSummary| s.totals.prompt_tokens + s.totals.completion_tokens;
+            total(b).cmp(&total(a)).then_with(|| a.token_id.cmp(&b.token_id))
+        });
+
+        UsageReport {
+            window: format!("{:?}", window).to_lowercase(),
+            since,
+            totals: self.totals(&all),
+            tokens,
+        }
+    }
+
+    // What `usage` cost at the configured LLM prices, in USD
+    pub fn cost_usd(&self, usage: &TokenUsage) -> f64 {
+        (usage.prompt_tokens as f64 * self.prompt_cost_per_million
+            + usage.completion_tokens as f64 * self.completion_cost_per_million)
+            / 1_000_000.0
+    }
+
+    fn totals(&self, bucket: &Bucket) -> UsageTotals {
+        let usage = bucket.usage;
+        UsageTotals {
+            requests: bucket.requests,
+            questions: bucket.questions,
+            llm_calls: usage.llm_calls,
+            prompt_tokens: usage.prompt_tokens,
+            completion_tokens: usage.completion_tokens,
+            estimated_cost_usd: self.cost_usd(&usage),
+        }
+    }
+}
+
+// Counts each authenticated request, with the LLM tokens spent while handling it, against
+// the caller's token, and writes requests that answered questions to the audit log. Work
+// handed to background tasks is recorded by those tasks.
+pub async fn record_usage(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let Some(claims) = request.extensions().get::<Claims>().cloned() else {
+        return next.run(request).await;
+    };
+    let context = AuditContext::from_parts(request.method(), request.uri(), request.headers());
+
+    let (response, usage) = usage::track(next.run(request)).await;
+    let answered = response.extensions().get::<AnsweredQuestions>().cloned().unwrap_or_default();
+    state.usage.record(&claims, 1, answered.0.len() as u64, usage);
+    state.audit.record(&claims, &context, answered.0, usage);
+    response
+}
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
@@ -1,292 +1,1744 @@
+use crate::document_listing::{ChunkFilter, ChunkSummary, ClauseFilter, ClauseSummary, DocumentFilter, DocumentSummary};
+use crate::feedback_payload::{FeedbackFilter, FeedbackPayload};
 use crate::query_payload::QueryPayload;
 use crate::rag_response::RagResponse;
 use crate::hackrx_request::HackRxRequest;
 use crate::hackrx_response::HackRxResponse;
+use crate::citations_csv::{csv_response, CitationRow};
+use crate::answer_format::AnswerFormat;
+use crate::inline_document::{DocumentSource, InlineDocument};
+use crate::job_request::JobRequest;
+use crate::upload_response::{UploadOptions, UploadResponse, UploadedDocument};
+use crate::ingest_queue::{save_documents, IngestTask};
+use crate::purge_response::{DeleteOptions, PurgeResponse};
+use crate::jobs::{Job, JobFilter, JobStatus};
+use crate::reindex_payload::ReindexPayload;
+use crate::reload_response::{ReloadFailure, ReloadResponse, ReloadedDocument};
+use crate::openapi::UploadForm;
+use crate::auth::Claims;
+use crate::tenants::{Collection, DEFAULT_TENANT};
+use crate::config::Config;
+use crate::error::{ApiError, ApiJson, ApiQuery, ErrorBody};
+use crate::pagination::{Page, PageParams};
+use crate::validation::Validator;
+use crate::usage::{UsageQuery, UsageReport};
+use crate::analytics::{AnalyticsQuery, CacheHitRates, QueryAnalytics};
+use crate::chat_session::{session_owner, ChatReply, ChatSession, ChatTranscript};
+use crate::audit::{AnsweredQuestions, AuditContext, AuditEntry, AuditFilter, AuditItem};
+use crate::deadline::{within, Deadline};
+use crate::url_guard::OutboundError;
+use crate::download_cache::{AdHocIndex, CacheLookup, Cached, Validators};
 use crate::AppState;
 
-use std::process::Command;
-use std::io::{self, ErrorKind, Read};
-use axum::{extract::State, http::StatusCode};
-use axum::Json;
 use tokio::io::AsyncWriteExt;
+use sha2::{Digest, Sha256};
+use std::io;
+use axum::{extract::{multipart::MultipartRejection, Extension, Multipart, Path, State}, http::StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use std::convert::Infallible;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_stream::{wrappers::ReceiverStream, Stream, StreamExt};
 use tempfile::NamedTempFile;
+use std::collections::HashSet;
 use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::Instrument;
 
-use unicode_segmentation::UnicodeSegmentation;
-use tiktoken_rs::{cl100k_base, CoreBPE};
-
-// This struct will hold the extracted text along with metadata
-#[derive(Debug, serde::Serialize)]
-pub struct TextChunk {
-    pub content: String,
-    pub doc_id: String,
-    pub page_number: Option<u32>,
-    pub start_char_index: usize,
-    pub end_char_index: usize,
-}
-
-// Function to extract text using pdftotext (No change)
-pub async fn extract_text_from_pdf_with_pdftotext(file_path: &str) -> Result<String, io::Error> {
-    let output = Command::new("pdftotext")
-        .arg(file_path)
-        .arg("-") // Output to stdout
-        .output()
-        .await?;
-
-    if output.status.success() {
-        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
-    } else {
-        let error_message = String::from_utf8_lossy(&output.stderr);
-        eprintln!("pdftotext error: {}", error_message);
-        Err(io::Error::new(
-            ErrorKind::Other,
-            format!("pdftotext failed: {}", error_message)
-        ))
-    }
-}
-
-// Sentence segmentation function (No change)
-fn segment_text_into_sentences(text: &str) -> Vec<String> {
-    text.unicode_sentences()
-        .map(|s| s.trim().to_string())
-        .filter(|s| !s.is_empty())
-        .collect()
+use rag_system::cache::next_collection_version;
+use rag_system::entities::mentions_any;
+use rag_system::{ingest, usage, CancellationToken, Feedback, RagError, Session, TokenChunker};
+use rag_system::models::{Document, QueryRequest, ResponseMode, RetrievalResponse, StreamEvent};
+
+// Downloads the PDF at `pdf_url`, extracts its text and splits it into token-bounded chunks
+pub async fn fetch_pdf_document(pdf_url: &str, config: &Config) -> Result<Document, ApiError> {
+    let (pdf_file, _) = download_unconditionally(pdf_url, config).await?;
+    pdf_document(pdf_url, &pdf_file, config).await
 }
 
-// --- REFINED: Intelligent Chunking with Token-based limits and Overlap ---
-pub fn create_chunks_token_based(
-    indexed_sentences: Vec<IndexedSentence>,
-    doc_id: &str,
-    tokenizer: &CoreBPE,
-    max_chunk_tokens: usize,
-    overlap_tokens: usize,
-) -> Vec<TextChunk> {
-    let mut chunks: Vec<TextChunk> = Vec::new();
-    let mut current_chunk_sentences_buffer: Vec<IndexedSentence> = Vec::new();
-    let mut current_chunk_tokens = 0;
-
-    for sentence in indexed_sentences {
-        let sentence_tokens = tokenizer.encode_ordinary(&sentence.content).len();
-
-        if current_chunk_tokens + sentence_tokens > max_chunk_tokens && !current_chunk_sentences_buffer.is_empty() {
-            let chunk_content = current_chunk_sentences_buffer.iter()
-                .map(|s| s.content.as_str())
-                .collect::<Vec<&str>>()
-                .join(" ");
-
-            let start_idx = current_chunk_sentences_buffer.first().unwrap().start_char_index;
-            let end_idx = start_idx + chunk_content.len() - 1;
-
-            chunks.push(TextChunk {
-                content: chunk_content,
-                doc_id: doc_id.to_string(),
-                page_number: None,
-                start_char_index: start_idx,
-                end_char_index: end_idx,
-            });
-
-            let mut new_buffer: Vec<IndexedSentence> = Vec::new();
-            let mut new_buffer_tokens = 0;
-
-            for s in current_chunk_sentences_buffer.iter().rev() {
-                let s_tokens = tokenizer.encode_ordinary(&s.content).len();
-                if new_buffer_tokens + s_tokens > overlap_tokens {
-                    break;
-                }
-                new_buffer.insert(0, s.clone());
-                new_buffer_tokens += s_tokens;
-            }
+// Content types accepted for downloaded documents; servers that send none are trusted
+const DOWNLOAD_CONTENT_TYPES: &[&str] = &["application/pdf", "application/x-pdf", "application/octet-stream", "binary/octet-stream"];
+
+enum Download {
+    // Saved to a temporary file, removed when dropped
+    Fetched(NamedTempFile, Validators),
+    // The server confirmed the cached copy is current
+    NotModified,
+}
 
-            current_chunk_sentences_buffer = new_buffer;
-            current_chunk_tokens = new_buffer_tokens;
+async fn download_unconditionally(url: &str, config: &Config) -> Result<(NamedTempFile, Validators), ApiError> {
+    match download(url, None, config).await? {
+        Download::Fetched(file, validators) => Ok((file, validators)),
+        Download::NotModified => Err(ApiError::bad_request(
+            "document_download_failed",
+            "Failed to download PDF: unexpected 304 Not Modified",
+        )),
+    }
+}
+
+// GETs `url` through the UrlGuard and streams the body to a temporary file, rejecting it as
+// soon as it exceeds MAX_DOWNLOAD_BYTES. With the validators of a cached copy the request is
+// conditional.
+async fn download(url: &str, cached: Option<&Validators>, config: &Config) -> Result<Download, ApiError> {
+    tracing::info!("Attempting to download PDF from: {}", url);
+    let mut headers = reqwest::header::HeaderMap::new();
+    if let Some(etag) = cached.and_then(|v| v.etag.as_deref()).and_then(|v| v.parse().ok()) {
+        headers.insert(reqwest::header::IF_NONE_MATCH, etag);
+    }
+    if let Some(last_modified) = cached.and_then(|v| v.last_modified.as_deref()).and_then(|v| v.parse().ok()) {
+        headers.insert(reqwest::header::IF_MODIFIED_SINCE, last_modified);
+    }
+    let download_failed = |e: reqwest::Error| {
+        let reason = if e.is_timeout() { "timed out" } else { "failed" };
+        ApiError::bad_request("document_download_failed", format!("PDF download {}: {}", reason, e)).transient()
+    };
+    let mut response = config.download_guard().get(url, headers).await.map_err(|e| match e {
+        OutboundError::Blocked(reason) => {
+            ApiError::bad_request("url_not_allowed", format!("Refusing to download {}: {}", url, reason))
+        }
+        OutboundError::Request(e) => download_failed(e),
+    })?;
+
+    if response.status() == reqwest::StatusCode::NOT_MODIFIED && cached.is_some() {
+        return Ok(Download::NotModified);
+    }
+    if !response.status().is_success() {
+        let error = ApiError::bad_request(
+            "document_download_failed",
+            format!("Failed to download PDF: server returned {}", response.status()),
+        );
+        let status = response.status();
+        return Err(if status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            error.transient()
+        } else {
+            error
+        });
+    }
+    let header = |name| {
+        response.headers().get(name).and_then(|value: &reqwest::header::HeaderValue| value.to_str().ok()).map(str::to_string)
+    };
+    if let Some(content_type) = header(reqwest::header::CONTENT_TYPE) {
+        let mime_type = content_type.split(';').next().unwrap_or_default().trim().to_lowercase();
+        if !DOWNLOAD_CONTENT_TYPES.contains(&mime_type.as_str()) {
+            return Err(ApiError::new(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "unsupported_media_type",
+                format!("{} is {}, expected a PDF", url, mime_type),
+            ));
         }
+    }
+    let too_large = || {
+        ApiError::new(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "document_too_large",
+            format!("{} is larger than {} bytes", url, config.max_download_bytes),
+        )
+    };
+    if response.content_length().is_some_and(|length| length > config.max_download_bytes) {
+        return Err(too_large());
+    }
+    let etag = header(reqwest::header::ETAG);
+    let last_modified = header(reqwest::header::LAST_MODIFIED);
 
-        current_chunk_sentences_buffer.push(sentence);
-        current_chunk_tokens += sentence_tokens;
+    let temp_error = |e: io::Error| ApiError::internal("document_download_failed", format!("Failed to save PDF: {}", e));
+    let pdf_file = NamedTempFile::new().map_err(temp_error)?;
+    let mut writer = tokio::io::BufWriter::new(tokio::fs::File::from_std(pdf_file.reopen().map_err(temp_error)?));
+    let mut hasher = Sha256::new();
+    let mut size = 0u64;
+    while let Some(chunk) = response.chunk().await.map_err(download_failed)? {
+        size += chunk.len() as u64;
+        if size > config.max_download_bytes {
+            return Err(too_large());
+        }
+        hasher.update(&chunk);
+        writer.write_all(&chunk).await.map_err(temp_error)?;
     }
+    writer.flush().await.map_err(temp_error)?;
+    tracing::info!("Downloaded {} bytes from {}", size, url);
 
-    if !current_chunk_sentences_buffer.is_empty() {
-        let chunk_content = current_chunk_sentences_buffer.iter()
-            .map(|s| s.content.as_str())
-            .collect::<Vec<&str>>()
-            .join(" ");
+    let validators = Validators { etag, last_modified, content_hash: hex::encode(hasher.finalize()) };
+    Ok(Download::Fetched(pdf_file, validators))
+}
 
-        let start_idx = current_chunk_sentences_buffer.first().unwrap().start_char_index;
-        let end_idx = start_idx + chunk_content.len() - 1;
+// Text of a downloaded PDF, chunked and named after the last segment of its URL
+async fn pdf_document(pdf_url: &str, pdf_file: &NamedTempFile, config: &Config) -> Result<Document, ApiError> {
+    let pdf_text = ingest::pdf_text(pdf_file.path()).await
+        .map_err(|e| ApiError::internal("extraction_failed", format!("PDF text extraction failed: {}", e)))?;
 
-        chunks.push(TextChunk {
-            content: chunk_content,
-            doc_id: doc_id.to_string(),
-            page_number: None,
-            start_char_index: start_idx,
-            end_char_index: end_idx,
-        });
+    document_from_text(ingest::filename_from_url(pdf_url), pdf_text, config)
+}
+
+// Extracts the text of an uploaded file based on its extension (PDF, DOCX or TXT)
+async fn extract_uploaded_text(filename: &str, bytes: &[u8]) -> Result<String, ApiError> {
+    let extension = filename.rsplit_once('.').map(|(_, ext)| ext.to_lowercase()).unwrap_or_default();
+    extract_text(&extension, filename, bytes).await
+}
+
+async fn extract_text(extension: &str, filename: &str, bytes: &[u8]) -> Result<String, ApiError> {
+    match extension {
+        "pdf" => ingest::pdf_bytes_text(bytes).await
+            .map_err(|e| ApiError::internal("extraction_failed", format!("PDF text extraction failed: {}", e))),
+        "docx" => ingest::docx_text(bytes).map_err(|e| ApiError::bad_request("invalid_document", format!("{:#}", e))),
+        "txt" | "md" => String::from_utf8(bytes.to_vec())
+            .map_err(|_| ApiError::bad_request("invalid_document", format!("{} is not valid UTF-8 text", filename))),
+        _ => Err(ApiError::new(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "unsupported_media_type",
+            format!("Unsupported file type for {}; expected PDF, DOCX or TXT", filename),
+        )
+        .with_details(serde_json::json!({ "filename": filename, "supported": ["pdf", "docx", "txt", "md"] }))),
     }
+}
 
-    chunks
+// Decodes a base64 document from the request body and splits it into token-bounded chunks
+async fn inline_document(document: &InlineDocument, config: &Config) -> Result<Document, ApiError> {
+    let filename = document.filename();
+    let bytes = document.decode()
+        .map_err(|e| ApiError::bad_request("invalid_document", format!("Invalid base64 document: {}", e)))?;
+    let extension = document.extension().ok_or_else(|| {
+        ApiError::new(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "unsupported_media_type",
+            format!("Unsupported document type {}", document.mime_type),
+        )
+    })?;
+    let text = extract_text(extension, &filename, &bytes).await?;
+    if text.trim().is_empty() {
+        return Err(ApiError::unprocessable("no_text_extracted", format!("No text could be extracted from {}", filename)));
+    }
+    document_from_text(filename, text, config)
 }
 
-// Helper struct to keep track of sentence content and its original start index
-#[derive(Debug, Clone)]
-struct IndexedSentence {
-    content: String,
-    start_char_index: usize,
+// The request's document, downloaded or decoded and chunked
+pub async fn fetch_document(source: DocumentSource<'_>, config: &Config) -> Result<Document, ApiError> {
+    match source {
+        DocumentSource::Url(url) => fetch_pdf_document(url, config).await,
+        DocumentSource::Inline(document) => inline_document(document, config).await,
+    }
 }
 
-// Function to segment text into sentences, preserving their original start character index
-fn segment_text_into_indexed_sentences(text: &str) -> Vec<IndexedSentence> {
-    let mut indexed_sentences = Vec::new();
-    let mut current_char_index = 0;
+// Splits extracted text into token-bounded chunks
+fn document_from_text(filename: String, text: String, config: &Config) -> Result<Document, ApiError> {
+    let chunker = TokenChunker::new(config.upload_chunk_tokens, config.upload_overlap_tokens)
+        .map_err(|e| ApiError::internal("tokenizer_unavailable", e.to_string()))?;
+    Ok(chunker.document(filename, text))
+}
+
+// The request's document indexed on its own. Downloads go through the download cache: an
+// entry within its TTL is used as is, an older one only after the server confirmed it is
+// unchanged (304, or the same content hash), and only changed documents are extracted and
+// embedded again.
+pub async fn ad_hoc_index(state: &AppState, source: DocumentSource<'_>) -> Result<Arc<AdHocIndex>, ApiError> {
+    let url = match source {
+        DocumentSource::Url(url) if state.downloads.enabled() => url,
+        source => return index_ad_hoc(state, fetch_document(source, &state.config).await?).await,
+    };
 
-    for sentence in text.unicode_sentences() {
-        let trimmed_sentence = sentence.trim();
-        if !trimmed_sentence.is_empty() {
-            indexed_sentences.push(IndexedSentence {
-                content: trimmed_sentence.to_string(),
-                start_char_index: current_char_index + (sentence.len() - trimmed_sentence.len()),
-            });
+    let (index, validators) = match state.downloads.lookup(url) {
+        Some(CacheLookup { cached: Cached::Indexed(index), fresh: true, .. }) => {
+            tracing::info!("Using cached download of {}", url);
+            return Ok(index);
         }
-        current_char_index += sentence.len();
+        Some(CacheLookup { cached: Cached::Extracted(document), validators, fetched_at, fresh: true }) => {
+            tracing::info!("Using stored download of {}", url);
+            let index = index_ad_hoc(state, *document).await?;
+            state.downloads.keep_in_memory(url, validators, fetched_at, index.clone());
+            return Ok(index);
+        }
+        Some(stale) => match download(url, Some(&stale.validators), &state.config).await? {
+            Download::NotModified => {
+                tracing::info!("Cached download of {} is still current", url);
+                (reuse_cached(state, stale.cached).await?, stale.validators)
+            }
+            Download::Fetched(_, validators) if validators.content_hash == stale.validators.content_hash => {
+                tracing::info!("Downloaded {} is unchanged since it was cached", url);
+                (reuse_cached(state, stale.cached).await?, validators)
+            }
+            Download::Fetched(pdf_file, validators) => {
+                let document = pdf_document(url, &pdf_file, &state.config).await?;
+                (index_ad_hoc(state, document).await?, validators)
+            }
+        },
+        None => {
+            let (pdf_file, validators) = download_unconditionally(url, &state.config).await?;
+            let document = pdf_document(url, &pdf_file, &state.config).await?;
+            (index_ad_hoc(state, document).await?, validators)
+        }
+    };
+    state.downloads.store(url, validators, index.clone());
+    Ok(index)
+}
+
+async fn reuse_cached(state: &AppState, cached: Cached) -> Result<Arc<AdHocIndex>, ApiError> {
+    match cached {
+        Cached::Indexed(index) => Ok(index),
+        Cached::Extracted(document) => index_ad_hoc(state, *document).await,
     }
-    indexed_sentences
 }
 
+// Embeds `document` with a provider of its own
+async fn index_ad_hoc(state: &AppState, document: Document) -> Result<Arc<AdHocIndex>, ApiError> {
+    let mut documents = vec![document];
+    let embeddings = state.rag_library.index_ad_hoc(&mut documents).await?;
+    Ok(Arc::new(AdHocIndex { documents, embeddings, version: next_collection_version() }))
+}
+
+// Documents a set of questions is answered against: a downloaded document on its own, or the
+// tenant's collection
+enum Corpus {
+    AdHoc(Arc<AdHocIndex>),
+    Collection(Arc<Collection>),
+}
+
+impl Corpus {
+    async fn load(state: &AppState, tenant: &str, source: Option<DocumentSource<'_>>) -> Result<Self, ApiError> {
+        match source {
+            Some(source) => Ok(Self::AdHoc(ad_hoc_index(state, source).await?)),
+            None => Ok(Self::Collection(tenant_collection(state, tenant).await?)),
+        }
+    }
+
+    // Answers `questions` for `claims` as a batch, retrieving the relevant chunks for each
+    // question from the documents the caller may read
+    async fn answer(
+        &self,
+        state: &AppState,
+        claims: &Claims,
+        mut questions: Vec<QueryRequest>,
+    ) -> Vec<Result<rag_system::QueryResponse, RagError>> {
+        let query_service = &state.rag_library.query_service;
+        for question in questions.iter_mut() {
+            question.tenant = Some(claims.tenant.clone());
+            question.principal = Some(claims.principal());
+        }
+
+        let results = match self {
+            Self::AdHoc(index) => {
+                for question in questions.iter_mut() {
+                    question.collection_version = Some(index.version);
+                }
+                query_service
+                    .answer_batch_with_embeddings(&questions, &index.documents, index.embeddings.as_ref())
+                    .await
+            }
+            Self::Collection(collection) => {
+                let documents = collection.read().await;
+                for question in questions.iter_mut() {
+                    question.collection_version = Some(collection.generation());
+                }
+                query_service
+                    .answer_batch_with_embeddings(&questions, &documents, collection.embeddings().as_ref())
+                    .await
+            }
+        };
+
+        results
+    }
+}
+
+// With a document the questions run against that document only; otherwise they run against
+// the tenant's collection.
+pub async fn answer_questions(
+    state: &AppState,
+    claims: &Claims,
+    source: Option<DocumentSource<'_>>,
+    questions: Vec<QueryRequest>,
+) -> Result<Vec<Result<rag_system::QueryResponse, RagError>>, ApiError> {
+    let corpus = Corpus::load(state, &claims.tenant, source).await?;
+    Ok(corpus.answer(state, claims, questions).await)
+}
+
+// Adds `uploaded` to the collection. Corpus statistics (e.g. the TF-IDF vocabulary) change,
+// so the whole index is re-embedded; on failure the collection is left as it was. Returns
+// the number of documents now indexed.
+pub async fn add_to_collection(collection: &Collection, uploaded: Vec<Document>) -> Result<usize, ApiError> {
+    collection
+        .add(uploaded)
+        .await
+        .map_err(|e| ApiError::internal("indexing_failed", format!("Failed to index documents: {}", e)))
+}
+
+pub async fn tenant_collection(state: &AppState, tenant: &str) -> Result<Arc<Collection>, ApiError> {
+    state
+        .tenants
+        .collection(tenant, &state.rag_library)
+        .await
+        .map_err(|e| ApiError::internal("collection_unavailable", format!("Failed to open collection: {}", e)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/query",
+    tag = "query",
+    request_body = QueryPayload,
+    responses(
+        (status = 200, description = "Answer with context snippets; with format \"csv\", a CSV with one line per citation (question, answer, query_id, document, page, excerpt, score, chunk_id)", content(
+            (RagResponse = "application/json"),
+            (String = "text/csv"),
+        )),
+        (status = 400, description = "The document could not be downloaded or parsed", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 413, description = "The request body or the downloaded document is too large", body = ErrorBody),
+        (status = 415, description = "The document is not a PDF", body = ErrorBody),
+        (status = 422, description = "A field is empty or over its limit; details.fields lists each one", body = ErrorBody),
+        (status = 502, description = "The LLM could not be reached or returned an error", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
 pub async fn handle_query_with_pdf_url(
-    Json(payload): Json<QueryPayload>,
-) -> Result<Json<RagResponse>, (StatusCode, String)> {
-    // Clone user_query early if process_rag_query needs its own copy
-    let user_query = payload.query.clone(); // Clone here
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+    ApiJson(payload): ApiJson<QueryPayload>,
+) -> Result<Response, ApiError> {
+    payload.validate(&state.config)?;
+    let request = payload.options.to_request(payload.query.clone(), &state.config);
+    let response = answer_questions(&state, &claims, payload.document_source(), vec![request])
+        .await?
+        .pop()
+        .unwrap_or_else(|| Err(RagError::Llm("No response generated".to_string())))?;
 
-    let mut extracted_text_for_rag = String::new();
+    let format = payload.options.format.unwrap_or_default();
+    let answered = AnsweredQuestions(vec![AuditItem::answered(&payload.query, &response)]);
+    if format == AnswerFormat::Csv {
+        let rows = CitationRow::from_response(&payload.query, &format.render(&response.response), &response);
+        return Ok((Extension(answered), csv_response(&rows, "citations.csv")).into_response());
+    }
+    Ok((Extension(answered), Json(RagResponse::new(response, format))).into_response())
+}
 
-    let bpe = cl100k_base().map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to load tokenizer: {}", e)))?;
+// Streams the answer to a query as server-sent events: "status", "retrieved" and "delta"
+// events while it is produced, then "done" with the full response (or "error")
+#[utoipa::path(
+    post,
+    path = "/query/stream",
+    tag = "query",
+    request_body = QueryPayload,
+    responses(
+        (status = 200, description = "Server-sent events: `status`, `retrieved` and `delta` events carrying a StreamEvent, then `done` with a RagResponse or `error`", content_type = "text/event-stream", body = StreamEvent),
+        (status = 400, description = "The document could not be downloaded or parsed", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 413, description = "The request body or the downloaded document is too large", body = ErrorBody),
+        (status = 415, description = "The document is not a PDF", body = ErrorBody),
+        (status = 422, description = "A field is empty or over its limit; details.fields lists each one", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn handle_query_stream(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+    audit: AuditContext,
+    ApiJson(payload): ApiJson<QueryPayload>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    payload.validate(&state.config)?;
+    let request = payload.options.to_request(payload.query.clone(), &state.config);
+    // Deltas stream as generated; the final "done" event carries the rendered answer
+    let format = payload.options.format.unwrap_or_default();
 
-    if let Some(pdf_url) = payload.pdf_url {
-        println!("Attempting to download PDF from: {}", pdf_url);
-        let response = reqwest::get(&pdf_url).await
-            .map_err(|e| (StatusCode::BAD_REQUEST, format!("Failed to download PDF: {}", e)))?;
+    let (events_tx, mut events_rx) = mpsc::channel::<StreamEvent>(32);
+    let answer = spawn_streaming_answer(state, claims, audit, payload.document_source(), request, events_tx).await?;
 
-        let pdf_bytes = response.bytes().await
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to read PDF bytes: {}", e)))?;
+    let (sse_tx, sse_rx) = mpsc::channel::<Event>(64);
+    tokio::spawn(async move {
+        while let Some(event) = events_rx.recv().await {
+            // The client is gone; dropping the events stops the answer (see spawn_streaming_answer)
+            if sse_tx.send(stream_event(&event)).await.is_err() {
+                break;
+            }
+        }
+        drop(events_rx);
+        let last = match answer.await {
+            Ok(Ok(response)) => Event::default().event("done").json_data(RagResponse::new(response, format)),
+            Ok(Err(e)) => Event::default().event("error").json_data(serde_json::json!({ "error": e.to_string() })),
+            Err(e) => Event::default().event("error").json_data(serde_json::json!({ "error": e.to_string() })),
+        };
+        if let Ok(event) = last {
+            let _ = sse_tx.send(event).await;
+        }
+    }.in_current_span());
 
-        let mut temp_file = NamedTempFile::new()
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to create temp file: {}", e)))?;
-        let temp_path = temp_file.path().to_path_buf();
+    let stream = ReceiverStream::new(sse_rx).map(Ok);
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
 
-        temp_file.write_all(&pdf_bytes).await
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to write to temp file: {}", e)))?;
-        temp_file.flush().await
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to flush temp file: {}", e)))?;
+// Answers `request` in a background task that sends its progress to `events` and returns the
+// answer. The document is fetched first, so download failures are returned before anything
+// is streamed. The answer is generated after the handler returned, so record_usage does not
+// see it: the task records its own usage and audit entry. It gives up with
+// RagError::Cancelled once `events` has no receiver, e.g. after the client disconnected.
+pub async fn spawn_streaming_answer(
+    state: Arc<AppState>,
+    claims: Claims,
+    audit: AuditContext,
+    source: Option<DocumentSource<'_>>,
+    mut request: QueryRequest,
+    events: mpsc::Sender<StreamEvent>,
+) -> Result<JoinHandle<Result<rag_system::QueryResponse, RagError>>, ApiError> {
+    request.tenant = Some(claims.tenant.clone());
+    request.principal = Some(claims.principal());
+    let ad_hoc = match source {
+        Some(source) => Some(ad_hoc_index(&state, source).await?),
+        None => None,
+    };
+    let collection = tenant_collection(&state, &claims.tenant).await?;
 
-        let doc_identifier = pdf_url.split('/').last().unwrap_or("unknown_url_doc").to_string();
-        let pdf_text = extract_text_from_pdf_with_pdftotext(temp_path.to_str().unwrap()).await
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("PDF text extraction failed: {}", e)))?;
+    Ok(tokio::spawn(async move {
+        let answer = async {
+            let query_service = &state.rag_library.query_service;
+            match &ad_hoc {
+                Some(index) => {
+                    request.collection_version = Some(index.version);
+                    query_service.answer_streaming(&request, &index.documents, index.embeddings.as_ref(), &events).await
+                }
+                None => {
+                    let documents = collection.read().await;
+                    request.collection_version = Some(collection.generation());
+                    let embeddings = collection.embeddings().as_ref();
+                    query_service.answer_streaming(&request, &documents, embeddings, &events).await
+                }
+            }
+        };
+        let answer = async {
+            tokio::select! {
+                result = answer => result,
+                _ = events.closed() => Err(RagError::Cancelled),
+            }
+        };
+        let (result, usage) = usage::track(answer).await;
+        drop(events);
+        state.usage.record(&claims, 0, 1, usage);
+        let item = match &result {
+            Ok(response) => AuditItem::answered(&request.query, response),
+            Err(e) => AuditItem::failed(&request.query, &e.to_string()),
+        };
+        state.audit.record(&claims, &audit, vec![item], usage);
+        if let Err(e) = &result {
+            tracing::error!("Streaming query failed: {}", e);
+        }
+        result
+    }.in_current_span()))
+}
+
+// SSE event named after the StreamEvent variant, with the event itself as JSON data
+fn stream_event(event: &StreamEvent) -> Event {
+    let name = match event {
+        StreamEvent::Status { .. } => "status",
+        StreamEvent::Retrieved { .. } => "retrieved",
+        StreamEvent::Delta { .. } => "delta",
+    };
+    Event::default()
+        .event(name)
+        .json_data(event)
+        .unwrap_or_else(|_| Event::default().event(name))
+}
+
+// Returns the ranked chunks for a query without generating an answer
+#[utoipa::path(
+    post,
+    path = "/retrieve",
+    tag = "query",
+    request_body = QueryPayload,
+    responses(
+        (status = 200, description = "Ranked chunks without a generated answer", body = RetrievalResponse),
+        (status = 400, description = "The document could not be downloaded or parsed", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 413, description = "The request body or the downloaded document is too large", body = ErrorBody),
+        (status = 415, description = "The document is not a PDF", body = ErrorBody),
+        (status = 422, description = "A field is empty or over its limit; details.fields lists each one", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn handle_retrieve(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+    ApiJson(payload): ApiJson<QueryPayload>,
+) -> Result<Json<RetrievalResponse>, ApiError> {
+    payload.validate(&state.config)?;
+    let mut request = payload.options.to_request(payload.query.clone(), &state.config);
+    request.tenant = Some(claims.tenant.clone());
+    request.principal = Some(claims.principal());
+    let query_service = &state.rag_library.query_service;
+
+    let result = match payload.document_source() {
+        Some(source) => {
+            let index = ad_hoc_index(&state, source).await?;
+            query_service.retrieve_with_embeddings(&request, &index.documents, index.embeddings.as_ref()).await
+        }
+        None => {
+            let collection = tenant_collection(&state, &claims.tenant).await?;
+            let documents = collection.read().await;
+            query_service.retrieve_with_embeddings(&request, &documents, collection.embeddings().as_ref()).await
+        }
+    };
 
-        let indexed_sentences = segment_text_into_indexed_sentences(&pdf_text);
+    Ok(Json(result?))
+}
 
-        const MAX_CHUNK_TOKENS: usize = 700;
-        const OVERLAP_TOKENS: usize = 100;
+// Handler for the /hackrx/run endpoint. With a callback_url the questions are answered in
+// the background and the response is the job; otherwise the answers are returned directly.
+#[utoipa::path(
+    post,
+    path = "/hackrx/run",
+    tag = "query",
+    request_body = HackRxRequest,
+    responses(
+        (status = 200, description = "One answer per question, in order; `timed_out` is set if the request deadline cut the run short. With format \"csv\", a CSV with one line per citation of every answer instead", content(
+            (HackRxResponse = "application/json"),
+            (String = "text/csv"),
+        )),
+        (status = 202, description = "Run started; the answers are POSTed to callback_url as a WebhookEvent", body = Job),
+        (status = 400, description = "The document could not be downloaded or parsed", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 413, description = "The request body or the downloaded document is too large", body = ErrorBody),
+        (status = 415, description = "The document is not a PDF", body = ErrorBody),
+        (status = 422, description = "A field is empty or over its limit; details.fields lists each one", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn handle_hackrx_run(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+    audit: AuditContext,
+    deadline: Option<Extension<Deadline>>,
+    ApiJson(payload): ApiJson<HackRxRequest>,
+) -> Result<Response, ApiError> {
+    tracing::info!("Received HackRx request with {} questions", payload.questions.len());
+    payload.validate(&state.config)?;
 
-        let chunks = create_chunks_token_based(indexed_sentences, &doc_identifier, &bpe, MAX_CHUNK_TOKENS, OVERLAP_TOKENS);
+    if payload.callback_url.is_none() {
+        let deadline = deadline.map(|Extension(deadline)| deadline);
+        let response = answer_hackrx(&state, &claims, &payload, deadline, |_, _| {}).await?;
+        if response.timed_out {
+            tracing::warn!(
+                "Deadline reached with {} of {} question(s) unanswered",
+                response.unanswered,
+                payload.questions.len()
+            );
+        }
+        let answered = AnsweredQuestions(response.audit.clone());
+        if payload.options.format == Some(AnswerFormat::Csv) {
+            return Ok((Extension(answered), csv_response(&response.citations, "citations.csv")).into_response());
+        }
+        return Ok((Extension(answered), Json(response)).into_response());
+    }
 
-        extracted_text_for_rag = chunks.iter()
-            .map(|c| c.content.clone())
-            .collect::<Vec<String>>()
-            .join("\n\n");
+    let job = start_hackrx_job(&state, &claims, audit, payload)?;
+    Ok((StatusCode::ACCEPTED, Json(job)).into_response())
+}
 
-        let encoded_context_tokens = bpe.encode_ordinary(&extracted_text_for_rag);
-        let max_llm_context_tokens = 4096 - bpe.encode_ordinary(&user_query).len() - 50;
-        if encoded_context_tokens.len() > max_llm_context_tokens {
-            let truncated_context_tokens = encoded_context_tokens[0..max_llm_context_tokens].to_vec();
-            extracted_text_for_rag = bpe.decode(truncated_context_tokens)
-                .unwrap_or_else(|_| "Context truncated due to token limit.".to_string());
-            println!("Context truncated to {} tokens.", bpe.encode_ordinary(&extracted_text_for_rag).len());
+// Answers the questions LLM_BATCH_SIZE at a time, calling `progress` with the answers so far
+// and the size of the batch just answered. If `deadline` passes first, the questions not
+// answered yet are marked as such and the response has `timed_out` set.
+pub async fn answer_hackrx(
+    state: &AppState,
+    claims: &Claims,
+    payload: &HackRxRequest,
+    deadline: Option<Deadline>,
+    mut progress: impl FnMut(&HackRxResponse, usize),
+) -> Result<HackRxResponse, ApiError> {
+    let mut response = new_hackrx_response(payload);
+    let questions = hackrx_questions(state, payload, &payload.questions);
+    let completed = answer_in_batches(state, claims, payload.document_source(), questions, deadline, |batch, results| {
+        for (question, result) in batch.iter().zip(results) {
+            response.push(&question.query, result);
         }
+        progress(&response, batch.len());
+    })
+    .await?;
+    if !completed {
+        response.time_out(&payload.questions[response.answers.len()..]);
     }
+    Ok(response)
+}
+
+// Answers `questions` LLM_BATCH_SIZE at a time, passing each batch and its results to
+// `on_batch`. Returns false if `deadline` passed before every question was answered.
+pub async fn answer_in_batches(
+    state: &AppState,
+    claims: &Claims,
+    source: Option<DocumentSource<'_>>,
+    questions: Vec<QueryRequest>,
+    deadline: Option<Deadline>,
+    mut on_batch: impl FnMut(&[QueryRequest], Vec<Result<rag_system::QueryResponse, RagError>>),
+) -> Result<bool, ApiError> {
+    let Some(corpus) = within(deadline, Corpus::load(state, &claims.tenant, source)).await else {
+        return Ok(false);
+    };
+    let corpus = corpus?;
 
-    // Now, pass the cloned `user_query` and `extracted_text_for_rag`
-    match process_rag_query(user_query, extracted_text_for_rag).await {
-        Ok(response) => Ok(Json(response)),
-        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e)),
+    for batch in questions.chunks(state.config.llm_batch_size.max(1)) {
+        let Some(results) = within(deadline, corpus.answer(state, claims, batch.to_vec())).await else {
+            return Ok(false);
+        };
+        on_batch(batch, results);
     }
+    Ok(true)
 }
 
-// Changed the signature to accept String for user_query and file_context
-// And changed the return type to Result<RagResponse, String>
-pub async fn process_rag_query(user_query: String, file_context: String) -> Result<RagResponse, String> {
-    println!("Received query for RAG: {}", user_query);
-    println!("File context provided: {}", !file_context.is_empty());
+fn new_hackrx_response(payload: &HackRxRequest) -> HackRxResponse {
+    HackRxResponse::new(
+        payload.options.debug.unwrap_or(false),
+        payload.options.response_mode == Some(ResponseMode::Decision),
+        payload.options.format.unwrap_or_default(),
+    )
+}
 
-    let mut all_context_for_llm = String::new();
-    let mut response_context_snippets: Vec<String> = Vec::new();
+fn hackrx_questions(state: &AppState, payload: &HackRxRequest, questions: &[String]) -> Vec<QueryRequest> {
+    questions
+        .iter()
+        .map(|question| payload.options.to_request(question.clone(), &state.config))
+        .collect()
+}
 
-    // 1. Incorporate file context if available
-    if !file_context.is_empty() {
-        all_context_for_llm.push_str("### PROVIDED DOCUMENT CONTEXT:\n");
-        all_context_for_llm.push_str(&file_context);
-        all_context_for_llm.push_str("\n\n");
-        response_context_snippets.push(format!("Context from uploaded file (first {} chars): {}", file_context.len().min(200), &file_context[0..file_context.len().min(200)]));
-        if file_context.len() > 200 { response_context_snippets.push("... (truncated)".to_string()); }
-    } else {
-        // Add general dummy context if no file is provided
-        all_context_for_llm.push_str("### GENERAL KNOWLEDGE BASE CONTEXT:\n");
-        all_context_for_llm.push_str("General information about Rust programming language is available.\n");
-        all_context_for_llm.push_str("Policies often cover terms like 'deductible', 'premium', 'claim process', and 'coverage limits'.\n\n");
-        response_context_snippets.push("General knowledge context used.".to_string());
+// Runs a hackrx request in the background and returns the job to poll. Questions are answered
+// LLM_BATCH_SIZE at a time so the job's progress and partial result move as answers come in.
+fn start_hackrx_job(
+    state: &Arc<AppState>,
+    claims: &Claims,
+    audit: AuditContext,
+    payload: HackRxRequest,
+) -> Result<Job, ApiError> {
+    if state.readiness.is_draining() {
+        return Err(ApiError::unavailable("shutting_down", "Server is shutting down"));
     }
+    let total = payload.questions.len();
+    let mut job = state.jobs.create("hackrx_run", &claims.tenant);
+    state.jobs.update(&job.id, |job| job.total = total);
+    job.total = total;
+
+    let state = state.clone();
+    let claims = claims.clone();
+    let job_id = job.id.clone();
+    tokio::spawn(async move {
+        let permit = state.answer_limiter.wait().await;
+        state.jobs.update(&job_id, |job| {
+            job.status = JobStatus::Running;
+            job.attempts = 1;
+            job.message = Some("Fetching document".to_string());
+        });
+        let (result, usage) = usage::track(run_hackrx_job(&state, &claims, &job_id, &payload)).await;
+        drop(permit);
+        let answered = result.as_ref().map(|response| response.audit.clone()).unwrap_or_default();
+        state.usage.record(&claims, 0, answered.len() as u64, usage);
+        state.audit.record(&claims, &audit, answered, usage);
+        match result {
+            Ok(_) => state.jobs.update(&job_id, |job| {
+                job.status = JobStatus::Completed;
+                job.message = Some(format!("Answered {} question(s)", job.total));
+            }),
+            Err(e) => state.jobs.fail(&job_id, e.message),
+        }
+        notify_callback(&state, &job_id, payload.callback_url.as_deref()).await;
+    }.in_current_span());
+
+    Ok(job)
+}
+
+async fn run_hackrx_job(
+    state: &AppState,
+    claims: &Claims,
+    job_id: &str,
+    payload: &HackRxRequest,
+) -> Result<HackRxResponse, ApiError> {
+    answer_hackrx(state, claims, payload, None, |partial, answered| {
+        let partial = serde_json::to_value(partial).ok();
+        state.jobs.update(job_id, |job| {
+            job.processed += answered;
+            job.message = Some("Answering questions".to_string());
+            job.result = partial;
+        });
+    })
+    .await
+}
+
+// Downloads a document and adds it to the tenant's collection
+pub async fn run_ingest_job(state: &AppState, tenant: &str, job_id: &str, document_url: &str) -> Result<(), ApiError> {
+    // Download and chunking, then embedding, then done
+    state.jobs.update(job_id, |job| {
+        job.status = JobStatus::Running;
+        job.total = 2;
+        job.message = Some("Fetching document".to_string());
+    });
+    let document = fetch_pdf_document(document_url, &state.config).await?;
+    let (document_id, filename, chunks) = (document.id.clone(), document.filename.clone(), document.chunks.len());
+    state.jobs.update(job_id, |job| {
+        job.processed = 1;
+        job.message = Some(format!("Indexing {} chunk(s)", chunks));
+    });
+
+    let collection = tenant_collection(state, tenant).await?;
+    let total_documents = add_to_collection(&collection, vec![document]).await?;
+    tracing::info!("Ingested {} for tenant {}, {} total", document_url, tenant, total_documents);
+
+    let result = UploadResponse {
+        documents: vec![UploadedDocument { document_id, filename, chunks }],
+        total_documents,
+    };
+    state.jobs.update(job_id, |job| {
+        job.status = JobStatus::Completed;
+        job.processed = 2;
+        job.message = Some(format!("Indexed {} chunk(s); {} documents indexed", chunks, total_documents));
+        job.result = serde_json::to_value(&result).ok();
+    });
+    Ok(())
+}
+
+// Handler for POST /jobs: starts an ingestion or a hackrx run in the background and returns
+// the job to poll
+#[utoipa::path(
+    post,
+    path = "/jobs",
+    tag = "jobs",
+    request_body = JobRequest,
+    responses(
+        (status = 202, description = "Job started", body = Job),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 413, description = "The request body is too large", body = ErrorBody),
+        (status = 422, description = "A field is empty or over its limit; details.fields lists each one", body = ErrorBody),
+        (status = 503, description = "Server is shutting down, or the ingestion queue is full", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn handle_create_job(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+    audit: AuditContext,
+    ApiJson(payload): ApiJson<JobRequest>,
+) -> Result<(StatusCode, Json<Job>), ApiError> {
+    payload.validate(&state.config)?;
+
+    let job = match payload {
+        JobRequest::HackrxRun(request) => start_hackrx_job(&state, &claims, audit, *request)?,
+        JobRequest::Ingest { document_url, callback_url } => {
+            if state.readiness.is_draining() {
+                return Err(ApiError::unavailable("shutting_down", "Server is shutting down"));
+            }
+            state.ingest.enqueue(&state.jobs, &claims.tenant, IngestTask::Url { document_url }, callback_url)?
+        }
+    };
+
+    Ok((StatusCode::ACCEPTED, Json(job)))
+}
+
+// Sends the finished job to the client's callback URL, if it gave one
+pub async fn notify_callback(state: &AppState, job_id: &str, callback_url: Option<&str>) {
+    if let (Some(url), Some(job)) = (callback_url, state.jobs.get(job_id)) {
+        state.webhooks.job_finished(url, job).await;
+    }
+}
 
-    // 2. Construct the prompt for the LLM (this is still dummy for now)
-    let llm_prompt = format!(
-        "{}\n\n### USER QUESTION:\n{}\n\n### ANSWER:",
-        all_context_for_llm,
-        user_query // Use user_query directly
+// Records a thumbs-up/down (and optional comment) for an earlier answer
+#[utoipa::path(
+    post,
+    path = "/feedback",
+    tag = "feedback",
+    request_body = FeedbackPayload,
+    responses(
+        (status = 200, description = "Stored feedback", body = Feedback),
+        (status = 400, description = "Missing query_id", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 404, description = "The query id belongs to another tenant", body = ErrorBody),
+        (status = 500, description = "The feedback could not be saved", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn handle_feedback(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+    ApiJson(payload): ApiJson<FeedbackPayload>,
+) -> Result<Json<Feedback>, ApiError> {
+    if payload.query_id.trim().is_empty() {
+        return Err(ApiError::bad_request("missing_query_id", "query_id is required"));
+    }
+
+    let query_service = &state.rag_library.query_service;
+    // Another tenant's query id is treated like an unknown one rather than confirming it exists
+    let foreign = query_service
+        .tracked_query(&payload.query_id)
+        .is_some_and(|record| record.tenant.as_deref() != Some(claims.tenant.as_str()));
+    if foreign {
+        return Err(ApiError::not_found("unknown_query", format!("Unknown query id {}", payload.query_id)));
+    }
+
+    let feedback = query_service.record_feedback(&payload.query_id, payload.rating, payload.comment, Some(&claims.tenant))?;
+    if let Some(store) = &state.metadata {
+        store.save_feedback(&feedback).await.map_err(|e| {
+            tracing::error!("Failed to save feedback: {:#}", e);
+            ApiError::internal("feedback_not_saved", "Failed to save the feedback")
+        })?;
+    }
+    Ok(Json(feedback))
+}
+
+#[utoipa::path(
+    get,
+    path = "/feedback",
+    tag = "feedback",
+    params(PageParams, FeedbackFilter),
+    responses(
+        (status = 200, description = "Feedback received so far, oldest first", body = Page<Feedback>),
+        (status = 400, description = "Invalid query parameters", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 500, description = "The metadata database could not be read", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn handle_list_feedback(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+    ApiQuery(page): ApiQuery<PageParams>,
+    ApiQuery(filter): ApiQuery<FeedbackFilter>,
+) -> Result<Json<Page<Feedback>>, ApiError> {
+    // The database also has the feedback given before a restart
+    let feedback = match &state.metadata {
+        Some(store) => store.feedback(&claims.tenant).await.map_err(|e| {
+            tracing::error!("Failed to read feedback: {:#}", e);
+            ApiError::internal("feedback_unavailable", "Failed to read the stored feedback")
+        })?,
+        None => state.rag_library.query_service.list_feedback(Some(&claims.tenant)),
+    };
+    Ok(Json(page.paginate(
+        feedback
+            .into_iter()
+            .filter(|feedback| filter.rating.is_none_or(|rating| feedback.rating == rating)),
+    )))
+}
+
+// Starts a chat session; send questions to /chat/sessions/{id}/messages
+#[utoipa::path(
+    post,
+    path = "/chat/sessions",
+    tag = "chat",
+    responses(
+        (status = 201, description = "New, empty session", body = ChatSession),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn handle_create_chat_session(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+) -> (StatusCode, Json<ChatSession>) {
+    let session = state.rag_library.query_service.create_session_for(&session_owner(&claims));
+    (StatusCode::CREATED, Json(ChatSession::from(&session)))
+}
+
+// The caller's chat sessions, most recently active first
+#[utoipa::path(
+    get,
+    path = "/chat/sessions",
+    tag = "chat",
+    params(PageParams),
+    responses(
+        (status = 200, description = "The caller's sessions", body = Page<ChatSession>),
+        (status = 400, description = "Invalid query parameters", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn handle_list_chat_sessions(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+    ApiQuery(page): ApiQuery<PageParams>,
+) -> Json<Page<ChatSession>> {
+    let owner = session_owner(&claims);
+    let sessions = state.rag_library.query_service.list_sessions();
+    let owned = sessions
+        .iter()
+        .filter(|session| session.owner.as_deref() == Some(owner.as_str()))
+        .map(ChatSession::from);
+    Json(page.paginate(owned))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/chat/sessions/{id}",
+    tag = "chat",
+    params(("id" = String, Path, description = "Session id")),
+    responses(
+        (status = 204, description = "Session and its history deleted"),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 404, description = "No such session for this user", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn handle_delete_chat_session(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+    Path(session_id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    owned_session(&state, &claims, &session_id)?;
+    state.rag_library.query_service.delete_session(&session_id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// The session's conversation: the summary of older turns and the recent messages
+#[utoipa::path(
+    get,
+    path = "/chat/sessions/{id}/messages",
+    tag = "chat",
+    params(("id" = String, Path, description = "Session id")),
+    responses(
+        (status = 200, description = "Messages kept for the session, oldest first", body = ChatTranscript),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 404, description = "No such session for this user", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn handle_list_chat_messages(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+    Path(session_id): Path<String>,
+) -> Result<Json<ChatTranscript>, ApiError> {
+    let session = owned_session(&state, &claims, &session_id)?;
+    Ok(Json(ChatTranscript::from(&session)))
+}
+
+// Answers a question in the context of the session's earlier turns, against the tenant's
+// collection (or a document given with the question), and adds the turn to the session
+#[utoipa::path(
+    post,
+    path = "/chat/sessions/{id}/messages",
+    tag = "chat",
+    params(("id" = String, Path, description = "Session id")),
+    request_body = QueryPayload,
+    responses(
+        (status = 200, description = "The answer", body = ChatReply),
+        (status = 400, description = "The document could not be downloaded or parsed", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 404, description = "No such session for this user", body = ErrorBody),
+        (status = 413, description = "The request body or the downloaded document is too large", body = ErrorBody),
+        (status = 415, description = "The document is not a PDF", body = ErrorBody),
+        (status = 422, description = "A field is empty or over its limit; details.fields lists each one", body = ErrorBody),
+        (status = 502, description = "The LLM could not be reached or returned an error", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn handle_send_chat_message(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+    Path(session_id): Path<String>,
+    ApiJson(payload): ApiJson<QueryPayload>,
+) -> Result<(Extension<AnsweredQuestions>, Json<ChatReply>), ApiError> {
+    owned_session(&state, &claims, &session_id)?;
+    payload.validate(&state.config)?;
+    let mut request = payload.options.to_request(payload.query.clone(), &state.config);
+    request.session_id = Some(session_id.clone());
+    let response = answer_questions(&state, &claims, payload.document_source(), vec![request])
+        .await?
+        .pop()
+        .unwrap_or_else(|| Err(RagError::Llm("No response generated".to_string())))?;
+
+    let format = payload.options.format.unwrap_or_default();
+    let answered = AnsweredQuestions(vec![AuditItem::answered(&payload.query, &response)]);
+    let reply = ChatReply { session_id, response: RagResponse::new(response, format) };
+    Ok((Extension(answered), Json(reply)))
+}
+
+// The session, if it exists and belongs to the caller; other users' sessions are reported
+// as unknown rather than confirming they exist
+fn owned_session(state: &AppState, claims: &Claims, session_id: &str) -> Result<Session, ApiError> {
+    state
+        .rag_library
+        .query_service
+        .session(session_id)
+        .filter(|session| session.owner.as_deref() == Some(session_owner(claims).as_str()))
+        .ok_or_else(|| ApiError::not_found("unknown_session", format!("Unknown session {}", session_id)))
+}
+
+// Lists the tenant's documents in ingestion order
+#[utoipa::path(
+    get,
+    path = "/documents",
+    tag = "documents",
+    params(PageParams, DocumentFilter),
+    responses(
+        (status = 200, description = "Documents matching the filters", body = Page<DocumentSummary>),
+        (status = 400, description = "Invalid query parameters", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn handle_list_documents(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+    ApiQuery(page): ApiQuery<PageParams>,
+    ApiQuery(filter): ApiQuery<DocumentFilter>,
+) -> Result<Json<Page<DocumentSummary>>, ApiError> {
+    let collection = tenant_collection(&state, &claims.tenant).await?;
+    let documents = collection.read().await;
+    let filename = filter.filename.as_deref().map(str::to_lowercase);
+    let principal = claims.principal();
+
+    let matching = documents
+        .iter()
+        .filter(|doc| doc.readable_by(Some(&principal)))
+        .filter(|doc| filename.as_ref().is_none_or(|part| doc.filename.to_lowercase().contains(part)))
+        .filter(|doc| filter.tag.as_ref().is_none_or(|tag| doc.metadata.tags.contains(tag)))
+        .map(|doc| DocumentSummary {
+            document_id: doc.id.clone(),
+            filename: doc.filename.clone(),
+            chunks: doc.chunks.len(),
+            tags: doc.metadata.tags.clone(),
+            access: doc.metadata.access.clone(),
+        });
+    Ok(Json(page.paginate(matching)))
+}
+
+// Lists the chunks of one of the tenant's documents in document order
+#[utoipa::path(
+    get,
+    path = "/documents/{id}/chunks",
+    tag = "documents",
+    params(("id" = String, Path, description = "Document id"), PageParams, ChunkFilter),
+    responses(
+        (status = 200, description = "Chunks matching the filter", body = Page<ChunkSummary>),
+        (status = 400, description = "Invalid query parameters", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 404, description = "No such document for this tenant", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn handle_list_chunks(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+    Path(document_id): Path<String>,
+    ApiQuery(page): ApiQuery<PageParams>,
+    ApiQuery(filter): ApiQuery<ChunkFilter>,
+) -> Result<Json<Page<ChunkSummary>>, ApiError> {
+    let collection = tenant_collection(&state, &claims.tenant).await?;
+    let documents = collection.read().await;
+    let document = documents
+        .iter()
+        .find(|doc| doc.id == document_id && doc.readable_by(Some(&claims.principal())))
+        .ok_or_else(|| ApiError::not_found("unknown_document", format!("Unknown document {}", document_id)))?;
+    let contains = filter.contains.as_deref().map(str::to_lowercase);
+
+    let matching = document
+        .chunks
+        .iter()
+        .enumerate()
+        .filter(|(_, chunk)| contains.as_ref().is_none_or(|part| chunk.content.to_lowercase().contains(part)))
+        .filter(|(_, chunk)| filter.entity.is_none_or(|kind| mentions_any(chunk, &[kind])))
+        .map(|(index, chunk)| ChunkSummary {
+            chunk_id: chunk.id.clone(),
+            index,
+            content: chunk.content.to_string(),
+            start_position: chunk.start_position,
+            end_position: chunk.end_position,
+            entities: chunk.entities.clone().unwrap_or_default(),
+        });
+    Ok(Json(page.paginate(matching)))
+}
+
+// Lists the clauses extracted from the tenant's documents (CLAUSE_EXTRACTION), in document
+// order; empty while extraction is off
+#[utoipa::path(
+    get,
+    path = "/clauses",
+    tag = "documents",
+    params(PageParams, ClauseFilter),
+    responses(
+        (status = 200, description = "Clauses matching the filters", body = Page<ClauseSummary>),
+        (status = 400, description = "Invalid query parameters", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn handle_list_clauses(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+    ApiQuery(page): ApiQuery<PageParams>,
+    ApiQuery(filter): ApiQuery<ClauseFilter>,
+) -> Result<Json<Page<ClauseSummary>>, ApiError> {
+    let collection = tenant_collection(&state, &claims.tenant).await?;
+    let documents = collection.read().await;
+    let subject = filter.subject.as_deref().map(str::to_lowercase);
+    let principal = claims.principal();
+
+    let matching = documents
+        .iter()
+        .filter(|doc| doc.readable_by(Some(&principal)))
+        .filter(|doc| filter.document_id.as_ref().is_none_or(|id| doc.id == *id))
+        .flat_map(|doc| doc.metadata.clauses.iter().flatten().map(move |clause| (doc, clause)))
+        .filter(|(_, clause)| filter.kind.is_none_or(|kind| clause.kind == kind))
+        .filter(|(_, clause)| subject.as_ref().is_none_or(|part| clause.subject.contains(part)))
+        .map(|(doc, clause)| ClauseSummary {
+            document_id: doc.id.clone(),
+            filename: doc.filename.clone(),
+            clause: clause.clone(),
+        });
+    Ok(Json(page.paginate(matching)))
+}
+
+// Deletes one of the tenant's documents; admins only. With purge=true every trace of it goes
+// too (see RagLibrary::purge_from and AuditLog::purge), and the response reports what was
+// removed.
+#[utoipa::path(
+    delete,
+    path = "/documents/{id}",
+    tag = "documents",
+    params(("id" = String, Path, description = "Document id"), DeleteOptions),
+    responses(
+        (status = 200, description = "Document purged (purge=true)", body = PurgeResponse),
+        (status = 204, description = "Document deleted"),
+        (status = 400, description = "Invalid query parameters, or delete_source without purge", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 403, description = "No admin role", body = ErrorBody),
+        (status = 404, description = "No such document for this tenant", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn handle_delete_document(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+    Path(document_id): Path<String>,
+    ApiQuery(options): ApiQuery<DeleteOptions>,
+) -> Result<Response, ApiError> {
+    claims.require_admin()?;
+    if options.delete_source && !options.purge {
+        return Err(ApiError::bad_request("invalid_options", "delete_source requires purge=true"));
+    }
+    let collection = tenant_collection(&state, &claims.tenant).await?;
+    let (filename, chunk_ids) = {
+        let documents = collection.read().await;
+        let document = documents
+            .iter()
+            .find(|doc| doc.id == document_id && doc.readable_by(Some(&claims.principal())))
+            .ok_or_else(|| ApiError::not_found("unknown_document", format!("Unknown document {}", document_id)))?;
+        let chunk_ids: HashSet<String> = document.chunks.iter().map(|chunk| chunk.id.clone()).collect();
+        (document.filename.clone(), chunk_ids)
+    };
+    let failed = |e: RagError| ApiError::internal("delete_failed", format!("Failed to delete document: {}", e));
+
+    if !options.purge {
+        collection.remove(&document_id).await.map_err(failed)?;
+        if claims.tenant == DEFAULT_TENANT {
+            if let Err(e) = state.rag_library.save_state().await {
+                tracing::warn!("Failed to save the index snapshot: {}", e);
+            }
+        }
+        save_documents(&state, &claims.tenant).await;
+        tracing::info!("Deleted document {} ({}) of tenant {}", document_id, filename, claims.tenant);
+        return Ok(StatusCode::NO_CONTENT.into_response());
+    }
+
+    let mut report = state.rag_library.purge_from(&collection, &document_id, options.delete_source).await.map_err(failed)?;
+    save_documents(&state, &claims.tenant).await;
+    let audit_items_redacted = state.audit.purge(&filename, &chunk_ids).await.map_err(|e| {
+        ApiError::internal("purge_failed", format!("The document was removed but the audit log was not redacted: {:#}", e))
+    })?;
+    // The audit log is searched again so the report only claims what can be checked
+    let audited = state
+        .audit
+        .search(AuditFilter::default())
+        .await
+        .map_err(|e| ApiError::internal("purge_failed", format!("Failed to verify the audit log: {:#}", e)))?;
+    report.verified &= !audited.iter().any(|entry| entry.cites_any(&chunk_ids));
+    tracing::info!(
+        "Purged document {} of tenant {} for user {}: {} chunks, {} cached responses, {} audited answers",
+        document_id,
+        claims.tenant,
+        claims.sub,
+        report.chunks_removed,
+        report.cached_responses_removed,
+        audit_items_redacted
     );
+    Ok(Json(PurgeResponse { report, audit_items_redacted }).into_response())
+}
 
-    println!("Full LLM Prompt (first 500 chars):\n{}", &llm_prompt[0..llm_prompt.len().min(500)]);
+// Handler for POST /documents: extracts every uploaded file and adds it to the tenant's
+// collection through the ingestion queue
+#[utoipa::path(
+    post,
+    path = "/documents",
+    tag = "documents",
+    params(UploadOptions),
+    request_body(content = UploadForm, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "Documents added to the index", body = UploadResponse),
+        (status = 202, description = "Indexing job started (background=true)", body = Job),
+        (status = 400, description = "Invalid multipart body or no files", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 415, description = "Unsupported file type", body = ErrorBody),
+        (status = 422, description = "No text could be extracted", body = ErrorBody),
+        (status = 503, description = "The ingestion queue is full", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn handle_upload_documents(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+    ApiQuery(options): ApiQuery<UploadOptions>,
+    multipart: Result<Multipart, MultipartRejection>,
+) -> Result<Response, ApiError> {
+    let mut multipart = multipart?;
+    let mut uploaded = Vec::new();
 
-    // --- Placeholder LLM Call Logic ---
-    let dummy_answer = if !file_context.is_empty() {
-        format!(
-            "Based on the provided document context and your question about '{}', here is the synthesized answer: [LLM would generate answer here using the text from the PDF]. For instance, if you asked about fire damage, the document states: 'Fire damage to the insured property is covered up to a maximum of INR 10,00,000, as detailed in section 4.1.2.'",
-            user_query
-        )
-    } else {
-        format!(
-            "Based on general knowledge, the answer to your question about '{}' is: Rust is a systems programming language focused on safety, performance, and concurrency. No specific document context was provided.",
-            user_query
-        )
+    while let Some(field) = multipart.next_field().await
+        .map_err(|e| ApiError::new(e.status(), "invalid_multipart", format!("Invalid multipart body: {}", e)))?
+    {
+        // Only file parts are documents; plain form fields are ignored
+        let Some(filename) = field.file_name().map(str::to_string) else {
+            continue;
+        };
+        let bytes = field.bytes().await
+            .map_err(|e| ApiError::new(e.status(), "invalid_multipart", format!("Failed to read {}: {}", filename, e)))?;
+
+        let text = extract_uploaded_text(&filename, &bytes).await?;
+        if text.trim().is_empty() {
+            return Err(ApiError::unprocessable("no_text_extracted", format!("No text could be extracted from {}", filename))
+                .with_details(serde_json::json!({ "filename": filename })));
+        }
+        let mut document = document_from_text(filename, text, &state.config)?;
+        document.metadata.access = options.access();
+        uploaded.push(document);
+    }
+
+    if uploaded.is_empty() {
+        return Err(ApiError::bad_request("no_files", "No files in the upload"));
+    }
+
+    // Indexing runs on the ingestion queue; with background=true the job is returned to poll
+    let task = IngestTask::Upload { documents: uploaded };
+    if options.background {
+        let job = state.ingest.enqueue(&state.jobs, &claims.tenant, task, None)?;
+        return Ok((StatusCode::ACCEPTED, Json(job)).into_response());
+    }
+    Ok(Json(index_and_wait(&state, &claims.tenant, task).await?).into_response())
+}
+
+// Queues an upload and waits for its job to finish. The job is cancelled if this future is
+// dropped first: the client disconnected or the request deadline passed.
+pub async fn index_and_wait(state: &AppState, tenant: &str, task: IngestTask) -> Result<UploadResponse, ApiError> {
+    let cancellation = CancellationToken::new();
+    let guard = cancellation.clone().drop_guard();
+    let (job, outcome) = state.ingest.enqueue_waiting(&state.jobs, tenant, task, cancellation)?;
+    let outcome = outcome.await;
+    guard.disarm();
+    outcome.map_err(|_| ApiError::internal("indexing_failed", "The ingestion worker stopped"))??;
+    state
+        .jobs
+        .get(&job.id)
+        .and_then(|job| serde_json::from_value(job.result?).ok())
+        .ok_or_else(|| ApiError::internal("indexing_failed", "The indexing job left no result"))
+}
+
+// Adds uploaded documents to the tenant's collection
+pub async fn run_upload_job(state: &AppState, tenant: &str, job_id: &str, uploaded: Vec<Document>) -> Result<(), ApiError> {
+    let summaries: Vec<UploadedDocument> = uploaded
+        .iter()
+        .map(|doc| UploadedDocument {
+            document_id: doc.id.clone(),
+            filename: doc.filename.clone(),
+            chunks: doc.chunks.len(),
+        })
+        .collect();
+    state.jobs.update(job_id, |job| {
+        job.total = summaries.len();
+        job.message = Some(format!("Indexing {} document(s)", summaries.len()));
+    });
+
+    let collection = tenant_collection(state, tenant).await?;
+    let total_documents = add_to_collection(&collection, uploaded).await?;
+    tracing::info!(
+        "Indexed {} uploaded documents for tenant {}, {} total",
+        summaries.len(),
+        tenant,
+        total_documents
+    );
+
+    let result = UploadResponse {
+        documents: summaries,
+        total_documents,
     };
-    
-    Ok(RagResponse {
-        answer: dummy_answer,
-        context_snippets: response_context_snippets,
-    })
+    state.jobs.update(job_id, |job| {
+        job.status = JobStatus::Completed;
+        job.processed = job.total;
+        job.message = Some(format!("Indexed {} document(s); {} documents indexed", job.total, total_documents));
+        job.result = serde_json::to_value(&result).ok();
+    });
+    Ok(())
 }
 
-// Handler for the /hackrx/run endpoint
-pub async fn handle_hackrx_run(
+// Handler for POST /admin/reindex: starts re-ingestion of the configured document sources
+// (or of a single document) in the background and returns the job to poll
+#[utoipa::path(
+    post,
+    path = "/admin/reindex",
+    tag = "admin",
+    request_body(content = Option<ReindexPayload>, description = "Document to re-ingest; omit to re-ingest every source"),
+    responses(
+        (status = 202, description = "Reindex job started", body = Job),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 403, description = "No admin role, or not the default tenant", body = ErrorBody),
+        (status = 422, description = "Invalid callback_url", body = ErrorBody),
+        (status = 503, description = "Server is shutting down, or the ingestion queue is full", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn handle_reindex(
     State(state): State<Arc<AppState>>,
-    Json(payload): Json<HackRxRequest>,
-) -> Result<Json<HackRxResponse>, (StatusCode, String)> {
-    log::info!("Received HackRx request with {} questions", payload.questions.len());
-    
-    let mut answers = Vec::new();
-    
-    // Process each question
-    for question in payload.questions {
-        log::info!("Processing question: {}", question);
-        
-        // Use the existing RAG system to get answers
-        match process_rag_query(question.clone(), payload.documents.clone()).await {
-            Ok(rag_response) => {
-                answers.push(rag_response.answer);
+    Extension(claims): Extension<Claims>,
+    payload: Option<ApiJson<ReindexPayload>>,
+) -> Result<(StatusCode, Json<Job>), ApiError> {
+    claims.require_admin()?;
+    // The configured sources make up the default tenant's collection
+    if claims.tenant != DEFAULT_TENANT {
+        return Err(ApiError::forbidden("forbidden", "Only the default tenant can reindex the configured sources"));
+    }
+
+    if state.readiness.is_draining() {
+        return Err(ApiError::unavailable("shutting_down", "Server is shutting down"));
+    }
+
+    let payload = payload.map(|ApiJson(payload)| payload).unwrap_or_default();
+    if let Some(url) = &payload.callback_url {
+        let mut validator = Validator::default();
+        validator.callback_url("callback_url", url, &state.config);
+        validator.finish()?;
+    }
+    let callback_url = payload.callback_url.clone();
+    let job = state.ingest.enqueue(&state.jobs, &claims.tenant, IngestTask::Reindex(payload), callback_url)?;
+    Ok((StatusCode::ACCEPTED, Json(job)))
+}
+
+// Handler for POST /admin/reload: rescans the documents directory and applies what changed
+// since it was indexed (new, edited and deleted files) to the default collection, without
+// re-ingesting unchanged files
+#[utoipa::path(
+    post,
+    path = "/admin/reload",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Changes applied to the index", body = ReloadResponse),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 403, description = "No admin role, or not the default tenant", body = ErrorBody),
+        (status = 500, description = "The documents directory could not be read or the index rebuilt", body = ErrorBody),
+        (status = 503, description = "Server is shutting down", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn handle_reload(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+) -> Result<Json<ReloadResponse>, ApiError> {
+    claims.require_admin()?;
+    if claims.tenant != DEFAULT_TENANT {
+        return Err(ApiError::forbidden("forbidden", "Only the default tenant can reload the configured sources"));
+    }
+    if state.readiness.is_draining() {
+        return Err(ApiError::unavailable("shutting_down", "Server is shutting down"));
+    }
+
+    Ok(Json(reload_documents(&state).await?))
+}
+
+// Rescans the documents directory and applies new, edited and deleted files to the default
+// collection; also run by the directory watcher
+pub async fn reload_documents(state: &AppState) -> Result<ReloadResponse, ApiError> {
+    let collection = tenant_collection(state, DEFAULT_TENANT).await?;
+    let processor = state.rag_library.document_processor();
+    let documents_dir = &state.rag_library.config.documents_dir;
+    let files = processor
+        .list_documents(documents_dir)
+        .map_err(|e| ApiError::internal("reload_failed", format!("Failed to list {}: {}", documents_dir, e)))?;
+
+    let mut documents = collection.read().await.clone();
+    let mut response = ReloadResponse {
+        added: Vec::new(),
+        updated: Vec::new(),
+        removed: Vec::new(),
+        failed: Vec::new(),
+        unchanged: 0,
+        total_documents: 0,
+    };
+    let mut sources = Vec::with_capacity(files.len());
+    for file in &files {
+        let source = file.to_string_lossy().to_string();
+        let filename = file.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+        sources.push(source.clone());
+        let indexed = documents.iter().position(|doc| doc.metadata.source.as_deref() == Some(source.as_str()));
+        let fingerprint = processor.fingerprint(file).ok();
+        if let Some(position) = indexed {
+            if fingerprint.is_some() && documents[position].metadata.fingerprint == fingerprint {
+                response.unchanged += 1;
+                continue;
             }
+        }
+
+        let mut document = match processor.process_file(file).await {
+            Ok(document) => document,
             Err(e) => {
-                log::error!("Error processing question '{}': {}", question, e);
-                answers.push(format!("Error processing question: {}", e));
+                tracing::error!("Failed to ingest {} on reload: {}", file.display(), e);
+                response.failed.push(ReloadFailure { filename, error: e.to_string() });
+                continue;
+            }
+        };
+        let reloaded = ReloadedDocument { document_id: document.id.clone(), filename: document.filename.clone() };
+        match indexed {
+            Some(position) => {
+                // Edited files keep their document id
+                document.id = documents[position].id.clone();
+                response.updated.push(ReloadedDocument { document_id: document.id.clone(), ..reloaded });
+                documents[position] = document;
+            }
+            None => {
+                response.added.push(reloaded);
+                documents.push(document);
+            }
+        }
+    }
+    documents.retain(|doc| match &doc.metadata.source {
+        Some(source) if !sources.contains(source) => {
+            response.removed.push(ReloadedDocument { document_id: doc.id.clone(), filename: doc.filename.clone() });
+            false
+        }
+        _ => true,
+    });
+
+    response.total_documents = documents.len();
+    if response.added.is_empty() && response.updated.is_empty() && response.removed.is_empty() {
+        return Ok(response);
+    }
+    collection
+        .rebuild(documents)
+        .await
+        .map_err(|e| ApiError::internal("reload_failed", format!("Failed to embed documents: {}", e)))?;
+    if let Err(e) = state.rag_library.save_state().await {
+        tracing::warn!("Failed to save the index snapshot: {}", e);
+    }
+
+    tracing::info!(
+        "Reloaded {}: {} added, {} updated, {} removed, {} unchanged",
+        documents_dir,
+        response.added.len(),
+        response.updated.len(),
+        response.removed.len(),
+        response.unchanged
+    );
+    Ok(response)
+}
+
+// Handler for GET /jobs: the tenant's background jobs, newest first
+#[utoipa::path(
+    get,
+    path = "/jobs",
+    tag = "jobs",
+    params(PageParams, JobFilter),
+    responses(
+        (status = 200, description = "Jobs matching the filters", body = Page<Job>),
+        (status = 400, description = "Invalid query parameters", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn handle_list_jobs(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+    ApiQuery(page): ApiQuery<PageParams>,
+    ApiQuery(filter): ApiQuery<JobFilter>,
+) -> Json<Page<Job>> {
+    Json(page.paginate(state.jobs.list(&claims.tenant, &filter)))
+}
+
+// Handler for GET /jobs/:id
+#[utoipa::path(
+    get,
+    path = "/jobs/{id}",
+    tag = "jobs",
+    params(("id" = String, Path, description = "Job id returned when the job was started")),
+    responses(
+        (status = 200, description = "Current job state", body = Job),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 404, description = "Unknown job", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn handle_get_job(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+    Path(job_id): Path<String>,
+) -> Result<Json<Job>, ApiError> {
+    state
+        .jobs
+        .get(&job_id)
+        .filter(|job| job.tenant == claims.tenant)
+        .map(Json)
+        .ok_or_else(|| ApiError::not_found("unknown_job", format!("Unknown job {}", job_id)))
+}
+
+// Handler for GET /admin/usage: requests, questions answered and LLM tokens per access token
+#[utoipa::path(
+    get,
+    path = "/admin/usage",
+    tag = "admin",
+    params(UsageQuery),
+    responses(
+        (status = 200, description = "Usage over the window, busiest tokens first", body = UsageReport),
+        (status = 400, description = "Invalid query parameters", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 403, description = "No admin role", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn handle_usage(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+    ApiQuery(query): ApiQuery<UsageQuery>,
+) -> Result<Json<UsageReport>, ApiError> {
+    // Covers every tenant, so it is limited to operators
+    claims.require_admin()?;
+    Ok(Json(state.usage.report(query.window.unwrap_or_default())))
+}
+
+// Handler for GET /admin/analytics: query volume, latency, abstentions and the most retrieved
+// documents over a window, from the audit log, and the hit rates of the caches
+#[utoipa::path(
+    get,
+    path = "/admin/analytics",
+    tag = "admin",
+    params(AnalyticsQuery),
+    responses(
+        (status = 200, description = "Analytics over the window", body = QueryAnalytics),
+        (status = 400, description = "Invalid query parameters", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 403, description = "No admin role", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn handle_analytics(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+    ApiQuery(query): ApiQuery<AnalyticsQuery>,
+) -> Result<Json<QueryAnalytics>, ApiError> {
+    claims.require_admin()?;
+    let interval_secs = query.interval_secs().map_err(|e| ApiError::bad_request("invalid_interval", e))?;
+    let until = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+    let since = until.saturating_sub(query.window.unwrap_or_default().secs());
+    let filter = AuditFilter { tenant: query.tenant.clone(), since: Some(since), until: Some(until), ..Default::default() };
+    let entries = state
+        .audit
+        .search(filter)
+        .await
+        .map_err(|e| ApiError::internal("audit_unavailable", format!("Failed to read the audit log: {:#}", e)))?;
+    let caches = CacheHitRates {
+        response: state.rag_library.query_service.response_cache_stats().map(Into::into),
+        download: state.downloads.stats().map(Into::into),
+    };
+    Ok(Json(QueryAnalytics::from_entries(&query, since, until, interval_secs, &entries).with_caches(caches)))
+}
+
+// Handler for GET /admin/audit: searches the audit log, newest first
+#[utoipa::path(
+    get,
+    path = "/admin/audit",
+    tag = "admin",
+    params(PageParams, AuditFilter),
+    responses(
+        (status = 200, description = "Audit entries matching the filters", body = Page<AuditEntry>),
+        (status = 400, description = "Invalid query parameters", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 403, description = "No admin role", body = ErrorBody),
+    ),
+    security(("bearer_auth" = []))
+)]
+pub async fn handle_search_audit(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<Claims>,
+    ApiQuery(page): ApiQuery<PageParams>,
+    ApiQuery(filter): ApiQuery<AuditFilter>,
+) -> Result<Json<Page<AuditEntry>>, ApiError> {
+    claims.require_admin()?;
+    let entries = state
+        .audit
+        .search(filter)
+        .await
+        .map_err(|e| ApiError::internal("audit_unavailable", format!("Failed to read the audit log: {}", e)))?;
+    Ok(Json(page.paginate(entries)))
+}
+
+pub async fn run_reindex(state: &AppState, job_id: &str, payload: &ReindexPayload) -> Result<(), ApiError> {
+    let collection = tenant_collection(state, DEFAULT_TENANT).await?;
+    let processor = state.rag_library.document_processor();
+    let documents_dir = &state.rag_library.config.documents_dir;
+    let mut files = processor
+        .list_documents(documents_dir)
+        .map_err(|e| ApiError::internal("reindex_failed", format!("Failed to list {}: {}", documents_dir, e)))?;
+
+    // A single document is matched by id (via its source file) or by filename
+    let single = payload.document_id.is_some() || payload.filename.is_some();
+    if single {
+        let source = match &payload.document_id {
+            Some(id) => {
+                let documents = collection.read().await;
+                let document = documents
+                    .iter()
+                    .find(|doc| &doc.id == id)
+                    .ok_or_else(|| ApiError::not_found("document_not_found", format!("Unknown document {}", id)))?;
+                document.metadata.source.clone().ok_or_else(|| {
+                    ApiError::unprocessable("not_reindexable", format!("Document {} was not ingested from a source file", id))
+                })?
             }
+            None => String::new(),
+        };
+        files.retain(|path| {
+            path.to_string_lossy() == source
+                || payload.filename.as_deref().is_some_and(|name| {
+                    path.file_name().is_some_and(|file| file.to_string_lossy() == name)
+                })
+        });
+        if files.is_empty() {
+            return Err(ApiError::not_found("document_not_found", "Document not found in the configured sources"));
+        }
+    }
+
+    state.jobs.update(job_id, |job| {
+        job.status = JobStatus::Running;
+        job.total = files.len();
+    });
+
+    let mut reindexed = Vec::with_capacity(files.len());
+    for file in &files {
+        let document = processor
+            .process_file(file)
+            .await
+            .map_err(|e| ApiError::unprocessable("ingestion_failed", format!("Failed to ingest {}: {}", file.display(), e)))?;
+        reindexed.push(document);
+        state.jobs.update(job_id, |job| job.processed += 1);
+    }
+
+    // Swap the new versions in, keeping document ids stable for files that were indexed before
+    let mut documents = collection.read().await.clone();
+    let reindexed_sources: Vec<Option<String>> = reindexed.iter().map(|doc| doc.metadata.source.clone()).collect();
+    for document in reindexed.iter_mut() {
+        if let Some(previous) = documents.iter().find(|doc| doc.metadata.source == document.metadata.source) {
+            document.id = previous.id.clone();
+        }
+    }
+    documents.retain(|doc| {
+        if single {
+            !reindexed_sources.contains(&doc.metadata.source)
+        } else {
+            // A full reindex also drops documents whose source file is gone
+            doc.metadata.source.is_none()
         }
+    });
+    documents.extend(reindexed);
+
+    let total = collection
+        .rebuild(documents)
+        .await
+        .map_err(|e| ApiError::internal("indexing_failed", format!("Failed to embed documents: {}", e)))?;
+    if let Err(e) = state.rag_library.save_state().await {
+        tracing::warn!("Failed to save the index snapshot: {}", e);
     }
-    
-    Ok(Json(HackRxResponse { answers }))
-}
\ No newline at end of file
+
+    tracing::info!("Reindexed {} file(s), {} documents indexed", files.len(), total);
+    state.jobs.update(job_id, |job| {
+        job.status = JobStatus::Completed;
+        job.message = Some(format!("Reindexed {} file(s); {} documents indexed", files.len(), total));
+    });
+    Ok(())
+}
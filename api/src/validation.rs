@@ -0,0 +1,146 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::config::Config;
+use crate::inline_document::{InlineDocument, SUPPORTED_MIME_TYPES};
+use crate::error::ApiError;
+use crate::retrieval_options::RetrievalOptions;
+use crate::url_guard::UrlGuard;
+
+// One rejected field, e.g. { "field": "questions[3]", "message": "must be at most 2000 characters" }
+#[derive(Debug, Serialize, ToSchema)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+// Collects field errors and turns them into a single 422
+#[derive(Default)]
+pub struct Validator {
+    errors: Vec<FieldError>,
+}
+
+impl Validator {
+    pub fn error(&mut self, field: impl Into<String>, message: impl Into<String>) {
+        self.errors.push(FieldError {
+            field: field.into(),
+            message: message.into(),
+        });
+    }
+
+    // Question text: present and at most `max_question_chars` characters
+    pub fn question(&mut self, field: impl Into<String>, question: &str, config: &Config) {
+        if question.trim().is_empty() {
+            self.error(field, "must not be empty");
+        } else if question.chars().count() > config.max_question_chars {
+            self.error(field, format!("must be at most {} characters", config.max_question_chars));
+        }
+    }
+
+    // Empty means no document; otherwise an http(s) URL the server may fetch (see UrlGuard)
+    pub fn document_url(&mut self, field: impl Into<String>, url: &str, config: &Config) {
+        if url.len() > config.max_document_url_chars {
+            self.error(field, format!("must be at most {} characters", config.max_document_url_chars));
+        } else if !url.trim().is_empty() {
+            if let Err(reason) = UrlGuard::from_config(config).check(url.trim()) {
+                self.error(field, reason);
+            }
+        }
+    }
+
+    // Supported type, valid base64 and within MAX_INLINE_DOCUMENT_BYTES once decoded
+    pub fn inline_document(&mut self, field: &str, document: &InlineDocument, config: &Config) {
+        if document.extension().is_none() {
+            self.error(
+                format!("{}.mime_type", field),
+                format!("must be one of {}", SUPPORTED_MIME_TYPES.join(", ")),
+            );
+        }
+        if document.decoded_len() > config.max_inline_document_bytes {
+            self.error(
+                format!("{}.content_base64", field),
+                format!("must decode to at most {} bytes", config.max_inline_document_bytes),
+            );
+        } else if document.decode().is_err() {
+            self.error(format!("{}.content_base64", field), "is not valid base64");
+        }
+    }
+
+    // Retrieval tuning: max_results and max_context_tokens within MAX_RESULTS_LIMIT and
+    // MAX_CONTEXT_TOKENS_LIMIT, and score_threshold a similarity from 0 to 1
+    pub fn retrieval_options(&mut self, options: &RetrievalOptions, config: &Config) {
+        if let Some(max_results) = options.max_results {
+            if max_results == 0 || max_results > config.max_results_limit {
+                self.error("max_results", format!("must be between 1 and {}", config.max_results_limit));
+            }
+        }
+        if options.max_context_tokens.is_some_and(|tokens| tokens > config.max_context_tokens_limit) {
+            self.error("max_context_tokens", format!("must be at most {}", config.max_context_tokens_limit));
+        }
+        if options.score_threshold.is_some_and(|threshold| !(0.0..=1.0).contains(&threshold)) {
+            self.error("score_threshold", "must be between 0 and 1");
+        }
+    }
+
+    // Callback URLs are checked like document URLs, but are required
+    pub fn callback_url(&mut self, field: &str, url: &str, config: &Config) {
+        if url.trim().is_empty() {
+            self.error(field, "must be an http or https URL");
+        }
+        self.document_url(field, url, config);
+    }
+
+    pub fn finish(self) -> Result<(), ApiError> {
+        if self.errors.is_empty() {
+            return Ok(());
+        }
+        Err(ApiError::unprocessable(
+            "validation_failed",
+            format!("{} field(s) failed validation", self.errors.len()),
+        )
+        .with_details(serde_json::json!({ "fields": self.errors })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use rag_system::query_service::{DEFAULT_MAX_CONTEXT_TOKENS_LIMIT, DEFAULT_MAX_RESULTS_LIMIT};
+
+    // Fields rejected in `options` under the default limits
+    fn rejected(options: RetrievalOptions) -> Vec<String> {
+        let config = Config::parse_from(["api"]);
+        let mut validator = Validator::default();
+        validator.retrieval_options(&options, &config);
+        validator.errors.into_iter().map(|error| error.field).collect()
+    }
+
+    #[test]
+    fn max_results_must_be_within_the_limit() {
+        assert!(rejected(RetrievalOptions { max_results: Some(1), ..Default::default() }).is_empty());
+        assert!(rejected(RetrievalOptions { max_results: Some(DEFAULT_MAX_RESULTS_LIMIT), ..Default::default() }).is_empty());
+        for max_results in [0, DEFAULT_MAX_RESULTS_LIMIT + 1, usize::MAX] {
+            assert_eq!(rejected(RetrievalOptions { max_results: Some(max_results), ..Default::default() }), ["max_results"]);
+        }
+    }
+
+    #[test]
+    fn max_context_tokens_must_be_within_the_limit() {
+        let options = |tokens| RetrievalOptions { max_context_tokens: Some(tokens), ..Default::default() };
+        assert!(rejected(options(DEFAULT_MAX_CONTEXT_TOKENS_LIMIT)).is_empty());
+        assert_eq!(rejected(options(DEFAULT_MAX_CONTEXT_TOKENS_LIMIT + 1)), ["max_context_tokens"]);
+        assert_eq!(rejected(options(usize::MAX)), ["max_context_tokens"]);
+    }
+
+    #[test]
+    fn score_threshold_must_be_a_similarity() {
+        let options = |threshold| RetrievalOptions { score_threshold: Some(threshold), ..Default::default() };
+        for threshold in [0.0, 0.35, 1.0] {
+            assert!(rejected(options(threshold)).is_empty(), "{}", threshold);
+        }
+        for threshold in [-0.1, 1.5, f32::NAN, f32::INFINITY] {
+            assert_eq!(rejected(options(threshold)), ["score_threshold"], "{}", threshold);
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use axum::{extract::State, Json};
+use serde::Serialize;
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+use crate::AppState;
+
+// Which build is serving, and with which models
+#[derive(Debug, Serialize, ToSchema)]
+pub struct VersionInfo {
+    pub version: String,
+    // "unknown" when built outside a git checkout without GIT_COMMIT
+    pub git_commit: String,
+    // Unix timestamp of the build
+    pub built_at: u64,
+    pub embedding_model: String,
+    pub llm_model: String,
+}
+
+// Build and model information, so callers can tell which deployment answered them
+#[utoipa::path(
+    get,
+    path = "/version",
+    tag = "health",
+    responses((status = 200, description = "Build and model information", body = VersionInfo))
+)]
+pub async fn version(State(state): State<Arc<AppState>>) -> Json<VersionInfo> {
+    Json(VersionInfo {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        git_commit: env!("GIT_COMMIT").to_string(),
+        built_at: env!("BUILD_TIMESTAMP").parse().unwrap_or_default(),
+        embedding_model: state.config.embedding_model(),
+        llm_model: state.config.llm_model(),
+    })
+}
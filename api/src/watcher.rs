@@ -0,0 +1,85 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use crate::health::IndexState;
+use crate::ingest_queue::IngestTask;
+use crate::jobs::{JobFilter, JobStatus};
+use crate::tenants::DEFAULT_TENANT;
+use crate::AppState;
+
+// Size and modification time of every document in the directory, with its metadata sidecar
+type Listing = Vec<(PathBuf, Option<(u64, SystemTime)>, Option<(u64, SystemTime)>)>;
+
+// Checks the documents directory every WATCH_INTERVAL_SECS and queues a reload job (see
+// reload_documents) when files were added, changed or removed, so the index follows the
+// directory without calls to POST /admin/reload. Does nothing when the interval is 0.
+pub fn spawn(state: Arc<AppState>) {
+    let interval = Duration::from_secs(state.config.watch_interval_secs);
+    if interval.is_zero() {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut indexed = listing(&state);
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if state.readiness.is_draining() {
+                return;
+            }
+            // Before the first load the whole directory is about to be indexed anyway
+            if !matches!(state.readiness.index(), IndexState::Loaded { .. }) {
+                indexed = listing(&state);
+                continue;
+            }
+
+            let current = listing(&state);
+            // Changes made while a reload is pending are picked up on a later check
+            if current == indexed || reload_pending(&state) {
+                continue;
+            }
+            match state.ingest.enqueue(&state.jobs, DEFAULT_TENANT, IngestTask::Reload, None) {
+                Ok(job) => {
+                    tracing::info!("Documents directory changed, queued reload job {}", job.id);
+                    indexed = current;
+                }
+                Err(e) => tracing::warn!("Could not queue a reload of the documents directory: {}", e.message),
+            }
+        }
+    });
+}
+
+fn listing(state: &AppState) -> Listing {
+    let documents_dir = &state.rag_library.config.documents_dir;
+    let files = match state.rag_library.document_processor().list_documents(documents_dir) {
+        Ok(files) => files,
+        Err(e) => {
+            tracing::warn!("Failed to list {}: {}", documents_dir, e);
+            return Vec::new();
+        }
+    };
+    let stat = |path: &PathBuf| {
+        let metadata = fs::metadata(path).ok()?;
+        Some((metadata.len(), metadata.modified().ok()?))
+    };
+    let mut listing: Listing = files
+        .into_iter()
+        .map(|file| {
+            let (document, sidecar) = (stat(&file), stat(&file.with_extension("meta.json")));
+            (file, document, sidecar)
+        })
+        .collect();
+    listing.sort_by(|a, b| a.0.cmp(&b.0));
+    listing
+}
+
+fn reload_pending(state: &AppState) -> bool {
+    let filter = JobFilter { kind: Some("reload".to_string()), ..Default::default() };
+    state
+        .jobs
+        .list(DEFAULT_TENANT, &filter)
+        .iter()
+        .any(|job| matches!(job.status, JobStatus::Queued | JobStatus::Running))
+}
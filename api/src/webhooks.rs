@@ -0,0 +1,115 @@
+use hmac::{Hmac, Mac};
+use reqwest::header::HeaderValue;
+use serde::Serialize;
+use sha2::Sha256;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use utoipa::ToSchema;
+
+use crate::config::Config;
+use crate::jobs::{Job, JobStatus};
+use crate::url_guard::{OutboundError, UrlGuard};
+
+const SIGNATURE_HEADER: &str = "x-hackrx-signature";
+const TIMESTAMP_HEADER: &str = "x-hackrx-timestamp";
+const EVENT_HEADER: &str = "x-hackrx-event";
+
+// Body POSTed to a callback URL when a job finishes
+#[derive(Serialize, ToSchema)]
+pub struct WebhookEvent {
+    // "<job kind>.<completed|failed>", e.g. "reindex.completed"
+    pub event: String,
+    pub job: Job,
+}
+
+// Delivers job results to client callback URLs. With WEBHOOK_SECRET set every delivery carries
+// `x-hackrx-signature: sha256=<hex HMAC-SHA256 of "<timestamp>.<body>">` and the timestamp in
+// `x-hackrx-timestamp`, so receivers can check authenticity and reject replays.
+pub struct Webhooks {
+    guard: UrlGuard,
+    secret: Option<Vec<u8>>,
+    max_attempts: u32,
+}
+
+impl Webhooks {
+    pub fn from_config(config: &Config) -> Self {
+        if config.webhook_secret.is_none() {
+            tracing::warn!("WEBHOOK_SECRET not set, webhook deliveries will be unsigned");
+        }
+        Self {
+            guard: UrlGuard::from_config(config).with_timeout(Duration::from_secs(config.webhook_timeout_secs)),
+            secret: config.webhook_secret.as_ref().map(|secret| secret.as_bytes().to_vec()),
+            max_attempts: config.webhook_max_attempts.max(1),
+        }
+    }
+
+    // Posts the finished job to `url`, retrying with exponential backoff on failures
+    pub async fn job_finished(&self, url: &str, job: Job) {
+        let status = match job.status {
+            JobStatus::Failed => "failed",
+            _ => "completed",
+        };
+        let event = WebhookEvent {
+            event: format!("{}.{}", job.kind, status),
+            job,
+        };
+        let body = match serde_json::to_vec(&event) {
+            Ok(body) => body,
+            Err(e) => {
+                tracing::error!("Failed to serialize webhook for job {}: {}", event.job.id, e);
+                return;
+            }
+        };
+
+        let mut backoff = Duration::from_secs(1);
+        for attempt in 1..=self.max_attempts {
+            match self.send(url, &event.event, &body).await {
+                Ok(()) => {
+                    tracing::info!("Delivered {} for job {} to {}", event.event, event.job.id, url);
+                    return;
+                }
+                Err(e) if matches!(e.downcast_ref(), Some(OutboundError::Blocked(_))) => {
+                    tracing::error!("Not delivering webhook for job {} to {}: {}", event.job.id, url, e);
+                    return;
+                }
+                Err(e) if attempt < self.max_attempts => {
+                    tracing::warn!("Webhook attempt {} for job {} failed: {}, retrying", attempt, event.job.id, e);
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                Err(e) => tracing::error!("Giving up on webhook for job {} after {} attempts: {}", event.job.id, attempt, e),
+            }
+        }
+    }
+
+    async fn send(&self, url: &str, event: &str, body: &[u8]) -> anyhow::Result<()> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default()
+            .to_string();
+
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert(reqwest::header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        headers.insert(EVENT_HEADER, event.parse()?);
+        headers.insert(TIMESTAMP_HEADER, timestamp.parse()?);
+        if let Some(signature) = self.sign(&timestamp, body) {
+            headers.insert(SIGNATURE_HEADER, signature.parse()?);
+        }
+
+        // Callback URLs are checked (and their host resolved) again on every attempt
+        let response = self.guard.post(url, headers, body.to_vec()).await?;
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("callback answered {}", response.status()));
+        }
+        Ok(())
+    }
+
+    fn sign(&self, timestamp: &str, body: &[u8]) -> Option<String> {
+        let secret = self.secret.as_ref()?;
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+        mac.update(timestamp.as_bytes());
+        mac.update(b".");
+        mac.update(body);
+        Some(format!("sha256={}", hex::encode(mac.finalize().into_bytes())))
+    }
+}
@@ -0,0 +1,375 @@
+// End-to-end tests of the HTTP API: each test starts the api binary on a free port, with the
+// fixture PDF as its documents directory and Gemini served by a wiremock server.
+
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use std::net::TcpListener;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+use tempfile::TempDir;
+use wiremock::matchers::{method, path_regex, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const GEMINI_KEY: &str = "test-key";
+// Argon2 hash of "secret1", the password of the accounts in users.conf
+const PASSWORD_HASH: &str = "$argon2id$v=19$m=19456,t=2,p=1$aGFja3J4LXRlc3Qtc2FsdA$wic1ar8dmDIgGK4els+3QJUukHI1Y56TUSOy5u1Y/uM";
+const ANSWER: &str = "A grace period of thirty days is allowed for payment of the renewal premium.";
+
+struct TestServer {
+    child: Child,
+    base_url: String,
+    client: reqwest::Client,
+    // Documents directory, removed with the server unless the test owns it
+    _dir: Option<TempDir>,
+}
+
+impl TestServer {
+    // Starts the api against `gemini` and waits until it has indexed the fixture
+    async fn start(gemini: &MockServer) -> Self {
+        let dir = documents_dir();
+        let mut server = Self::start_in(gemini, dir.path(), &[]).await;
+        server._dir = Some(dir);
+        server
+    }
+
+    // Same as start, with `dir` (see documents_dir) as documents and working directory and
+    // extra environment variables. The directory outlives the server, so it can be restarted.
+    async fn start_in(gemini: &MockServer, dir: &Path, env: &[(&str, String)]) -> Self {
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let users = dir.join("users.conf");
+        std::fs::write(&users, format!("tester:{0}:default\noperator:{0}:default\n", PASSWORD_HASH)).unwrap();
+
+        // A clean environment, run from the temporary directory so no .env is picked up
+        let child = Command::new(env!("CARGO_BIN_EXE_api"))
+            .env_clear()
+            .env("PATH", std::env::var("PATH").unwrap_or_default())
+            .env("HOST", "127.0.0.1")
+            .env("PORT", port.to_string())
+            .env("DOCUMENTS_DIR", dir)
+            .env("GEMINI_API_KEY", GEMINI_KEY)
+            .env("GEMINI_BASE_URL", gemini.uri())
+            .env("JWT_SECRET", "integration-test-secret")
+            .env("USERS_FILE", &users)
+            .env("USER_ROLES", "operator=admin")
+            .envs(env.iter().map(|(name, value)| (name, value)))
+            .current_dir(dir)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("api binary starts");
+
+        let server = Self {
+            child,
+            base_url: format!("http://127.0.0.1:{}", port),
+            client: reqwest::Client::new(),
+            _dir: None,
+        };
+        server.wait_ready().await;
+        server
+    }
+
+    async fn wait_ready(&self) {
+        for _ in 0..300 {
+            if let Ok(response) = self.client.get(self.url("/readyz")).send().await {
+                if response.status() == StatusCode::OK {
+                    return;
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("api did not become ready");
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    async fn login(&self) -> String {
+        self.login_as("tester").await
+    }
+
+    // Access token of an account in users.conf; "operator" has the admin role
+    async fn login_as(&self, user: &str) -> String {
+        let response = self
+            .client
+            .post(self.url("/login"))
+            .json(&json!({ "username": user, "password": "secret1" }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: Value = response.json().await.unwrap();
+        body["token"].as_str().expect("access token").to_string()
+    }
+
+    async fn get(&self, path: &str, token: &str) -> Value {
+        let response = self.client.get(self.url(path)).bearer_auth(token).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        response.json().await.unwrap()
+    }
+
+    async fn post(&self, path: &str, token: &str, body: Value) -> reqwest::Response {
+        self.client
+            .post(self.url(path))
+            .bearer_auth(token)
+            .json(&body)
+            .send()
+            .await
+            .unwrap()
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+// Temporary directory holding the fixture PDF
+fn documents_dir() -> TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/policy.pdf");
+    std::fs::copy(fixture, dir.path().join("policy.pdf")).unwrap();
+    dir
+}
+
+fn gemini_reply(text: &str) -> Value {
+    json!({ "candidates": [{ "content": { "parts": [{ "text": text }] } }] })
+}
+
+async fn mock_generate(gemini: &MockServer, response: ResponseTemplate) {
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/v1beta/models/[^/]+:generateContent$"))
+        .and(query_param("key", GEMINI_KEY))
+        .respond_with(response)
+        .mount(gemini)
+        .await;
+}
+
+async fn error_code(response: reqwest::Response) -> (StatusCode, Value) {
+    let status = response.status();
+    (status, response.json().await.unwrap())
+}
+
+#[tokio::test]
+async fn protected_routes_need_a_valid_access_token() {
+    let gemini = MockServer::start().await;
+    let server = TestServer::start(&gemini).await;
+
+    let response = server.client.get(server.url("/protected")).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = server.client.get(server.url("/protected")).bearer_auth("not-a-jwt").send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = server
+        .client
+        .post(server.url("/login"))
+        .json(&json!({ "username": "tester", "password": "secret2" }))
+        .send()
+        .await
+        .unwrap();
+    let (status, body) = error_code(response).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["error"], "invalid_credentials");
+
+    let response = server
+        .client
+        .post(server.url("/login"))
+        .json(&json!({ "username": "stranger", "password": "secret1" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    // The tenant comes from the account, not the client
+    let response = server
+        .client
+        .post(server.url("/login"))
+        .json(&json!({ "username": "tester", "password": "secret1", "tenant": "other" }))
+        .send()
+        .await
+        .unwrap();
+    let (status, body) = error_code(response).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["error"], "tenant_not_allowed");
+
+    let response = server
+        .client
+        .post(server.url("/login"))
+        .json(&json!({ "username": "tester", "password": "secret1" }))
+        .send()
+        .await
+        .unwrap();
+    let tokens: Value = response.json().await.unwrap();
+    let access = tokens["token"].as_str().unwrap();
+    let refresh = tokens["refresh_token"].as_str().unwrap();
+
+    let response = server.client.get(server.url("/protected")).bearer_auth(access).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Refresh tokens are only good for /refresh, and access tokens are not refresh tokens
+    let response = server.client.get(server.url("/protected")).bearer_auth(refresh).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = server.post("/refresh", access, json!({ "refresh_token": access })).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = server.post("/refresh", access, json!({ "refresh_token": refresh })).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let renewed: Value = response.json().await.unwrap();
+    assert!(renewed["token"].as_str().is_some_and(|token| !token.is_empty()));
+}
+
+#[tokio::test]
+async fn hackrx_run_answers_from_the_fixture_pdf() {
+    let gemini = MockServer::start().await;
+    mock_generate(&gemini, ResponseTemplate::new(200).set_body_json(gemini_reply(ANSWER))).await;
+    let server = TestServer::start(&gemini).await;
+    let token = server.login().await;
+
+    let response = server
+        .post("/hackrx/run", &token, json!({ "questions": ["What is the grace period for premium payment?"] }))
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["answers"], json!([ANSWER]));
+    assert_eq!(body["results"][0]["status"], "answered");
+    assert!(body["query_ids"][0].as_str().is_some_and(|id| !id.is_empty()));
+
+    // The prompt carried the question and the matching passage of the PDF
+    let requests = gemini.received_requests().await.unwrap();
+    let prompt: Value = requests.last().expect("Gemini was called").body_json().unwrap();
+    let prompt = prompt["contents"][0]["parts"][0]["text"].as_str().unwrap();
+    assert!(prompt.contains("What is the grace period for premium payment?"));
+    assert!(prompt.contains("grace period of thirty days"));
+}
+
+#[tokio::test]
+async fn gemini_rate_limit_is_reported_as_unavailable() {
+    let gemini = MockServer::start().await;
+    let quota = json!({ "error": { "code": 429, "status": "RESOURCE_EXHAUSTED" } });
+    mock_generate(&gemini, ResponseTemplate::new(429).set_body_json(quota)).await;
+    let server = TestServer::start(&gemini).await;
+    let token = server.login().await;
+
+    let response = server.post("/query", &token, json!({ "query": "What is the grace period?" })).await;
+    let (status, body) = error_code(response).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["error"], "llm_rate_limited");
+    assert!(body["request_id"].as_str().is_some());
+}
+
+#[tokio::test]
+async fn gemini_server_error_is_reported_as_bad_gateway() {
+    let gemini = MockServer::start().await;
+    mock_generate(&gemini, ResponseTemplate::new(500).set_body_string("internal error")).await;
+    let server = TestServer::start(&gemini).await;
+    let token = server.login().await;
+
+    let response = server.post("/query", &token, json!({ "query": "What is the grace period?" })).await;
+    let (status, body) = error_code(response).await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+    assert_eq!(body["error"], "llm_error");
+    assert_eq!(body["details"]["status"], 500);
+
+    // A run reports the failure per question instead of failing as a whole
+    let response = server.post("/hackrx/run", &token, json!({ "questions": ["What is the grace period?"] })).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["results"][0]["status"], "error");
+    assert!(body["answers"][0].as_str().unwrap().starts_with("Error processing question"));
+}
+
+#[tokio::test]
+async fn query_stream_forwards_gemini_deltas() {
+    let gemini = MockServer::start().await;
+    let (first, second) = ANSWER.split_at(ANSWER.find(" is allowed").unwrap());
+    let events = format!(
+        "data: {}\r\n\r\ndata: {}\r\n\r\n",
+        gemini_reply(first),
+        gemini_reply(second)
+    );
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/v1beta/models/[^/]+:streamGenerateContent$"))
+        .and(query_param("alt", "sse"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(events, "text/event-stream"))
+        .mount(&gemini)
+        .await;
+    let server = TestServer::start(&gemini).await;
+    let token = server.login().await;
+
+    let response = server.post("/query/stream", &token, json!({ "query": "What is the grace period?" })).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers()["content-type"].to_str().unwrap().starts_with("text/event-stream"));
+
+    // The stream ends after the "done" event
+    let body = response.text().await.unwrap();
+    let events: Vec<(&str, Value)> = body
+        .split("\n\n")
+        .filter_map(|event| {
+            let name = event.lines().find_map(|line| line.strip_prefix("event:"))?.trim();
+            let data = event.lines().find_map(|line| line.strip_prefix("data:"))?.trim();
+            Some((name, serde_json::from_str(data).ok()?))
+        })
+        .collect();
+
+    let deltas: Vec<&str> = events
+        .iter()
+        .filter(|(name, _)| *name == "delta")
+        .filter_map(|(_, data)| data["text"].as_str())
+        .collect();
+    assert_eq!(deltas, [first, second]);
+    assert!(events.iter().any(|(name, data)| *name == "retrieved" && data["documents"] == json!(["policy.pdf"])));
+    let (name, done) = events.last().expect("events were streamed");
+    assert_eq!(*name, "done");
+    assert_eq!(done["answer"], ANSWER);
+}
+
+#[tokio::test]
+async fn jobs_feedback_and_audit_entries_survive_a_restart_with_a_database() {
+    let gemini = MockServer::start().await;
+    mock_generate(&gemini, ResponseTemplate::new(200).set_body_json(gemini_reply(ANSWER))).await;
+    let dir = documents_dir();
+    let database = format!("sqlite://{}", dir.path().join("metadata.db").display());
+    let env = [("DATABASE_URL", database)];
+    let server = TestServer::start_in(&gemini, dir.path(), &env).await;
+    let token = server.login().await;
+
+    let response = server.post("/query", &token, json!({ "query": "What is the grace period?" })).await;
+    let body: Value = response.json().await.unwrap();
+    let query_id = body["query_id"].as_str().unwrap().to_string();
+    let response = server.post("/feedback", &token, json!({ "query_id": query_id, "rating": "up" })).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = server
+        .post("/jobs", &token, json!({ "type": "hackrx_run", "questions": ["What is the grace period?"] }))
+        .await;
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let job: Value = response.json().await.unwrap();
+    let job_path = format!("/jobs/{}", job["id"].as_str().unwrap());
+    for _ in 0..100 {
+        if server.get(&job_path, &token).await["status"] == "completed" {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    // Job updates and audit entries are written in the background
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    drop(server);
+
+    let server = TestServer::start_in(&gemini, dir.path(), &env).await;
+    let token = server.login().await;
+    let feedback = server.get("/feedback", &token).await;
+    assert_eq!(feedback["items"][0]["query_id"], json!(query_id));
+    assert_eq!(feedback["items"][0]["rating"], "up");
+    let job = server.get(&job_path, &token).await;
+    assert_eq!(job["status"], "completed");
+    assert_eq!(job["result"]["answers"], json!([ANSWER]));
+    let response = server.client.get(server.url("/admin/audit")).bearer_auth(&token).send().await.unwrap();
+    let (status, body) = error_code(response).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["error"], "admin_required");
+    let audit = server.get("/admin/audit?user=tester", &server.login_as("operator").await).await;
+    assert_eq!(audit["total"], 2);
+}
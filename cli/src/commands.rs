@@ -0,0 +1,226 @@
+use anyhow::bail;
+#[cfg(feature = "url-ingestion")]
+use rag_system::document_processor::filename_from_url;
+#[cfg(feature = "url-ingestion")]
+use rag_system::DocumentInput;
+use rag_system::eval::read_cases;
+use rag_system::{ErrorResponse, EvalReport, Evaluator, QueryRequest, QueryResponse, RagLibrary};
+use serde_json::json;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+// Loads the snapshot or indexes the configured sources, then downloads `urls` into it.
+// URLs whose document is already in the snapshot are not fetched again unless `rebuild`.
+pub async fn ingest(library: &RagLibrary, urls: &[&String], rebuild: bool) -> anyhow::Result<()> {
+    #[cfg(not(feature = "url-ingestion"))]
+    if !urls.is_empty() {
+        bail!("Fetching URLs needs the url-ingestion feature");
+    }
+    if let Some(path) = library.snapshot_path().filter(|path| rebuild && path.exists()) {
+        fs::remove_file(path)?;
+    }
+    library.load_documents().await?;
+
+    #[cfg(feature = "url-ingestion")]
+    for url in urls {
+        let filename = filename_from_url(url);
+        let indexed = library
+            .store()
+            .read()
+            .await
+            .iter()
+            .any(|doc| doc.metadata.source.is_none() && (doc.filename == filename || doc.filename == format!("{}.pdf", filename)));
+        if indexed {
+            eprintln!("{} is already indexed, skipping (--rebuild fetches it again)", url);
+            continue;
+        }
+        let id = library.add_document(DocumentInput::Url(url.to_string())).await?;
+        eprintln!("Added {} as {}", url, id);
+    }
+    if !urls.is_empty() {
+        library.save_state().await?;
+    }
+
+    let documents = library.store().read().await;
+    let chunks: usize = documents.iter().map(|doc| doc.chunks.len()).sum();
+    println!(
+        "Indexed {} documents ({} chunks) in {}",
+        documents.len(),
+        chunks,
+        library.snapshot_path().map(|path| path.display().to_string()).unwrap_or_default()
+    );
+    Ok(())
+}
+
+// Answers each question, from the arguments or else stdin. Failed questions are reported on
+// stderr and make the command fail once all were tried.
+pub async fn query(library: &RagLibrary, questions: &[String], max_results: Option<usize>, json: bool) -> anyhow::Result<()> {
+    let questions = if questions.is_empty() {
+        io::stdin()
+            .lock()
+            .lines()
+            .collect::<io::Result<Vec<String>>>()?
+            .into_iter()
+            .filter(|line| !line.trim().is_empty())
+            .collect()
+    } else {
+        questions.to_vec()
+    };
+    library.load_documents().await?;
+
+    let mut failed = 0;
+    for question in &questions {
+        let request = QueryRequest { query: question.clone(), max_results, ..Default::default() };
+        match library.answer(&request).await {
+            Ok(response) if json => println!("{}", serde_json::to_string(&response)?),
+            Ok(response) => print_answer(question, &response),
+            Err(e) => {
+                eprintln!("Failed to answer \"{}\": {}", question, e);
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        bail!("{} of {} questions failed", failed, questions.len());
+    }
+    Ok(())
+}
+
+fn print_answer(question: &str, response: &QueryResponse) {
+    println!("Q: {}", question);
+    println!("A: {}", response.response);
+    if let Some(confidence) = response.confidence_score {
+        println!("Confidence: {:.2}", confidence);
+    }
+    for (i, citation) in response.citations.iter().enumerate() {
+        let page = citation.page.map(|page| format!(", page {}", page)).unwrap_or_default();
+        println!("  [{}] {}{} ({:.2}): {}", i + 1, citation.document, page, citation.confidence_score, citation.text_excerpt);
+    }
+    println!();
+}
+
+// Reads one JSON QueryRequest per line of stdin and writes one JSON line back for each: the
+// QueryResponse, or an ErrorResponse for invalid requests and failed answers. Ends at EOF.
+pub async fn serve(library: &RagLibrary) -> anyhow::Result<()> {
+    let count = library.load_documents().await?;
+    eprintln!("Ready with {} documents", count);
+
+    let mut stdout = io::stdout();
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match serde_json::from_str::<QueryRequest>(&line) {
+            Ok(request) => match library.answer(&request).await {
+                Ok(response) => serde_json::to_string(&response)?,
+                Err(e) => error_line(e.to_string())?,
+            },
+            Err(e) => error_line(format!("Invalid request: {}", e))?,
+        };
+        writeln!(stdout, "{}", reply)?;
+        stdout.flush()?;
+    }
+    Ok(())
+}
+
+fn error_line(error: String) -> serde_json::Result<String> {
+    serde_json::to_string(&ErrorResponse { status: "error".to_string(), error })
+}
+
+pub async fn export(library: &RagLibrary, output: &Path) -> anyhow::Result<()> {
+    let count = library.load_documents().await?;
+    library.save(output).await?;
+    println!("Exported {} documents to {}", count, output.display());
+    Ok(())
+}
+
+pub async fn eval(
+    library: &RagLibrary,
+    cases: &Path,
+    max_results: Option<usize>,
+    judge: bool,
+    report_path: Option<&Path>,
+    json: bool,
+) -> anyhow::Result<()> {
+    let cases = read_cases(cases)?;
+    library.load_documents().await?;
+
+    let evaluator = Evaluator::new(library).with_request(QueryRequest { max_results, ..Default::default() });
+    let evaluator = if judge { evaluator } else { evaluator.with_judge(None) };
+    let report = evaluator.run(library, &cases).await;
+
+    if let Some(path) = report_path {
+        fs::write(path, serde_json::to_vec_pretty(&report)?)?;
+    }
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print_report(&report);
+    }
+    Ok(())
+}
+
+fn print_report(report: &EvalReport) {
+    for (i, result) in report.results.iter().enumerate() {
+        let rank = result.hit_rank.map(|rank| format!("hit@{}", rank)).unwrap_or_else(|| "miss".to_string());
+        let faithfulness = result.faithfulness.map(|score| format!("{:.2}", score)).unwrap_or_else(|| "-".to_string());
+        println!(
+            "[{}] {:<6} similarity {:.2}  faithfulness {}  {}",
+            i + 1,
+            rank,
+            result.answer_similarity,
+            faithfulness,
+            result.question
+        );
+        if let Some(error) = &result.error {
+            println!("      error: {}", error);
+        }
+    }
+    println!();
+    println!("Cases:        {} ({} failed)", report.cases, report.failed);
+    println!("Hit rate:     {:.3}", report.hit_rate);
+    println!("MRR:          {:.3}", report.mrr);
+    println!("Similarity:   {:.3}", report.mean_answer_similarity);
+    match report.mean_faithfulness {
+        Some(score) => println!("Faithfulness: {:.3} ({} judged)", score, report.judged),
+        None => println!("Faithfulness: -"),
+    }
+    println!("LLM usage:    {} calls, {} tokens", report.usage.llm_calls, report.usage.total_tokens());
+}
+
+pub async fn stats(library: &RagLibrary, json: bool) -> anyhow::Result<()> {
+    library.load_documents().await?;
+    let documents = library.store().read().await;
+    let chunks: usize = documents.iter().map(|doc| doc.chunks.len()).sum();
+    let characters: usize = documents.iter().map(|doc| doc.content.chars().count()).sum();
+    let embeddings = library.store().embeddings().kind();
+
+    if json {
+        let stats = json!({
+            "documents": documents.len(),
+            "chunks": chunks,
+            "characters": characters,
+            "embeddings": embeddings,
+            "snapshot": library.snapshot_path(),
+            "files": documents.iter().map(|doc| json!({
+                "document_id": doc.id,
+                "filename": doc.filename,
+                "chunks": doc.chunks.len(),
+                "source": doc.metadata.source,
+            })).collect::<Vec<_>>(),
+        });
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+        return Ok(());
+    }
+
+    println!("Documents:  {}", documents.len());
+    println!("Chunks:     {}", chunks);
+    println!("Characters: {}", characters);
+    println!("Embeddings: {}", embeddings);
+    for doc in documents.iter() {
+        println!("  {}  {} ({} chunks)", doc.id, doc.filename, doc.chunks.len());
+    }
+    Ok(())
+}
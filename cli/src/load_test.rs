@@ -0,0 +1,259 @@
+use anyhow::{bail, Context};
+use reqwest::multipart::{Form, Part};
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::synthetic::QUESTIONS_FILE;
+
+// How to reach the api server and what to send it
+#[derive(Debug, Clone)]
+pub struct LoadTest {
+    pub url: String,
+    // Bearer token, else one is obtained from /login
+    pub token: Option<String>,
+    pub username: String,
+    pub password: Option<String>,
+    // Files uploaded to POST /documents before the questions are fired
+    pub upload: Option<PathBuf>,
+    // One question per line, plain or a JSON object with a "question" (e.g. an evaluation set)
+    pub questions: Option<PathBuf>,
+    pub concurrency: usize,
+    // Questions to send in total, cycling through the questions file
+    pub requests: usize,
+    // Keep sending until this much time has passed instead of stopping after `requests`
+    pub duration: Option<Duration>,
+    pub timeout: Duration,
+    pub max_results: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Latencies {
+    pub mean_ms: f64,
+    pub p50_ms: u64,
+    pub p90_ms: u64,
+    pub p95_ms: u64,
+    pub p99_ms: u64,
+    pub max_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UploadSummary {
+    pub documents: usize,
+    pub elapsed_secs: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LoadReport {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upload: Option<UploadSummary>,
+    pub concurrency: usize,
+    pub requests: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    // HTTP status (or "error" when no response came back) -> count
+    pub statuses: BTreeMap<String, usize>,
+    pub elapsed_secs: f64,
+    // Requests completed per second, failures included
+    pub throughput_rps: f64,
+    // Of the successful requests; None when there were none
+    pub latency: Option<Latencies>,
+}
+
+#[derive(Deserialize)]
+struct Question {
+    question: String,
+}
+
+// (status, latency) of one request
+type Outcome = (Result<StatusCode, String>, Duration);
+
+impl LoadTest {
+    pub async fn run(&self) -> anyhow::Result<LoadReport> {
+        let client = Client::builder().timeout(self.timeout).build()?;
+        let url = self.url.trim_end_matches('/').to_string();
+        let token = match &self.token {
+            Some(token) => token.clone(),
+            None => self.login(&client, &url).await?,
+        };
+        let questions = Arc::new(self.read_questions()?);
+        let upload = match &self.upload {
+            Some(dir) => Some(upload(&client, &url, &token, dir).await?),
+            None => None,
+        };
+
+        eprintln!("Sending {} with {} concurrent clients", self.budget(), self.concurrency);
+        let next = Arc::new(AtomicUsize::new(0));
+        let started = Instant::now();
+        let deadline = self.duration.map(|duration| started + duration);
+        let mut workers = Vec::with_capacity(self.concurrency);
+        for _ in 0..self.concurrency.max(1) {
+            let (client, url, token, questions, next) =
+                (client.clone(), url.clone(), token.clone(), questions.clone(), next.clone());
+            let (requests, max_results) = (self.requests, self.max_results);
+            workers.push(tokio::spawn(async move {
+                let mut outcomes: Vec<Outcome> = Vec::new();
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let done = match deadline {
+                        Some(deadline) => Instant::now() >= deadline,
+                        None => index >= requests,
+                    };
+                    if done {
+                        return outcomes;
+                    }
+                    let body = json!({ "query": questions[index % questions.len()], "max_results": max_results });
+                    let sent = Instant::now();
+                    let status = client
+                        .post(format!("{}/query", url))
+                        .bearer_auth(&token)
+                        .json(&body)
+                        .send()
+                        .await
+                        .map(|response| response.status())
+                        .map_err(|e| e.to_string());
+                    outcomes.push((status, sent.elapsed()));
+                }
+            }));
+        }
+        let mut outcomes = Vec::new();
+        for worker in workers {
+            outcomes.extend(worker.await?);
+        }
+        Ok(report(upload, self.concurrency, &outcomes, started.elapsed()))
+    }
+
+    fn budget(&self) -> String {
+        match self.duration {
+            Some(duration) => format!("questions for {}s", duration.as_secs()),
+            None => format!("{} questions", self.requests),
+        }
+    }
+
+    async fn login(&self, client: &Client, url: &str) -> anyhow::Result<String> {
+        let Some(password) = &self.password else {
+            bail!("Pass --token, or --password to log in");
+        };
+        let response = client
+            .post(format!("{}/login", url))
+            .json(&json!({ "username": self.username, "password": password }))
+            .send()
+            .await
+            .with_context(|| format!("Cannot reach {}", url))?;
+        if !response.status().is_success() {
+            bail!("Login failed ({}): {}", response.status(), response.text().await.unwrap_or_default());
+        }
+        let body: serde_json::Value = response.json().await?;
+        body["token"].as_str().map(str::to_string).context("The login response has no token")
+    }
+
+    // The questions file, else the one written with the uploaded corpus
+    fn read_questions(&self) -> anyhow::Result<Vec<String>> {
+        let path = match (&self.questions, &self.upload) {
+            (Some(path), _) => path.clone(),
+            (None, Some(dir)) => dir.join(QUESTIONS_FILE),
+            (None, None) => bail!("Pass --questions, or --upload a directory from `rag-cli synth`"),
+        };
+        let raw = fs::read_to_string(&path).with_context(|| format!("Cannot read {}", path.display()))?;
+        let questions: Vec<String> = raw
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(|line| match serde_json::from_str::<Question>(line) {
+                Ok(parsed) => parsed.question,
+                Err(_) => line.to_string(),
+            })
+            .collect();
+        if questions.is_empty() {
+            bail!("{} has no questions", path.display());
+        }
+        Ok(questions)
+    }
+}
+
+// Uploads the files in `dir` one by one, as a client adding documents would
+async fn upload(client: &Client, url: &str, token: &str, dir: &Path) -> anyhow::Result<UploadSummary> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<_>>()?;
+    paths.retain(|path| path.is_file() && path.file_name().is_some_and(|name| name != QUESTIONS_FILE));
+    paths.sort();
+
+    let started = Instant::now();
+    for path in &paths {
+        let filename = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let form = Form::new().part("files", Part::bytes(fs::read(path)?).file_name(filename.clone()));
+        let response = client.post(format!("{}/documents", url)).bearer_auth(token).multipart(form).send().await?;
+        if !response.status().is_success() {
+            bail!("Uploading {} failed ({}): {}", filename, response.status(), response.text().await.unwrap_or_default());
+        }
+    }
+    let summary = UploadSummary { documents: paths.len(), elapsed_secs: started.elapsed().as_secs_f64() };
+    eprintln!("Uploaded {} documents in {:.1}s", summary.documents, summary.elapsed_secs);
+    Ok(summary)
+}
+
+fn report(upload: Option<UploadSummary>, concurrency: usize, outcomes: &[Outcome], elapsed: Duration) -> LoadReport {
+    let mut statuses = BTreeMap::new();
+    let mut latencies = Vec::new();
+    for (status, latency) in outcomes {
+        let key = match status {
+            Ok(status) => status.as_u16().to_string(),
+            Err(_) => "error".to_string(),
+        };
+        *statuses.entry(key).or_insert(0) += 1;
+        if status.as_ref().is_ok_and(|status| status.is_success()) {
+            latencies.push(latency.as_millis() as u64);
+        }
+    }
+    latencies.sort_unstable();
+    let succeeded = latencies.len();
+    let latency = (!latencies.is_empty()).then(|| Latencies {
+        mean_ms: latencies.iter().sum::<u64>() as f64 / succeeded as f64,
+        p50_ms: percentile(&latencies, 50),
+        p90_ms: percentile(&latencies, 90),
+        p95_ms: percentile(&latencies, 95),
+        p99_ms: percentile(&latencies, 99),
+        max_ms: latencies[succeeded - 1],
+    });
+    LoadReport {
+        upload,
+        concurrency,
+        requests: outcomes.len(),
+        succeeded,
+        failed: outcomes.len() - succeeded,
+        statuses,
+        elapsed_secs: elapsed.as_secs_f64(),
+        throughput_rps: outcomes.len() as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+        latency,
+    }
+}
+
+// Nearest-rank `percent`th percentile of the non-empty `sorted`
+fn percentile(sorted: &[u64], percent: usize) -> u64 {
+    sorted[(sorted.len() * percent).div_ceil(100).max(1) - 1]
+}
+
+pub fn print_report(report: &LoadReport) {
+    if let Some(upload) = &report.upload {
+        println!("Uploaded:    {} documents in {:.1}s", upload.documents, upload.elapsed_secs);
+    }
+    println!("Requests:    {} ({} failed) with {} clients", report.requests, report.failed, report.concurrency);
+    let statuses: Vec<String> = report.statuses.iter().map(|(status, count)| format!("{} x{}", status, count)).collect();
+    println!("Statuses:    {}", statuses.join(", "));
+    println!("Elapsed:     {:.1}s", report.elapsed_secs);
+    println!("Throughput:  {:.1} requests/s", report.throughput_rps);
+    match &report.latency {
+        Some(latency) => println!(
+            "Latency:     mean {:.0} ms, p50 {} ms, p90 {} ms, p95 {} ms, p99 {} ms, max {} ms",
+            latency.mean_ms, latency.p50_ms, latency.p90_ms, latency.p95_ms, latency.p99_ms, latency.max_ms
+        ),
+        None => println!("Latency:     -"),
+    }
+}
@@ -0,0 +1,249 @@
+mod commands;
+#[cfg(feature = "load-test")]
+mod load_test;
+mod synthetic;
+
+use clap::{Parser, Subcommand};
+use rag_system::{LogFormat, Logging, RagConfig, RagLibrary};
+use std::path::PathBuf;
+#[cfg(feature = "load-test")]
+use std::time::Duration;
+
+// Command-line access to the RAG pipeline for scripts and CI, without booting the api server.
+// The index is kept in the state directory, so only the first run (or one after the sources
+// changed) pays for ingestion and embedding. Providers are picked from the environment as in
+// the api: EMBEDDING_PROVIDER, LLM_PROVIDER, GEMINI_API_KEY and GEMINI_MODEL.
+#[derive(Debug, Parser)]
+#[command(name = "rag-cli", version, about = "Ingest documents and answer questions with the RAG library")]
+struct Cli {
+    // Directory whose PDFs are indexed
+    #[arg(long, env = "DOCUMENTS_DIR", default_value = ".", global = true)]
+    documents_dir: String,
+
+    // Where the index snapshot is kept between runs
+    #[arg(long, env = "STATE_DIR", default_value = ".rag", global = true)]
+    state_dir: PathBuf,
+
+    // Further files or directories to index. Pass the same ones to every command: a snapshot
+    // is only reused while the configured sources are unchanged.
+    #[arg(long = "source", global = true)]
+    sources: Vec<PathBuf>,
+
+    #[arg(long, env = "CHUNK_SIZE", global = true)]
+    chunk_size: Option<usize>,
+
+    #[arg(long, env = "CHUNK_OVERLAP", global = true)]
+    chunk_overlap: Option<usize>,
+
+    // "text" or "json"; levels are set with RUST_LOG
+    #[arg(long, env = "LOG_FORMAT", default_value = "text", global = true)]
+    log_format: LogFormat,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    #[command(about = "Index the documents directory, sources and given paths or URLs into the state directory")]
+    Ingest {
+        // Files and directories are indexed like --source; http(s) URLs are downloaded and
+        // added to the snapshot
+        paths: Vec<String>,
+
+        // Reindex everything even when the saved snapshot is current
+        #[arg(long)]
+        rebuild: bool,
+    },
+    #[command(about = "Answer the given questions, or one question per line of stdin")]
+    Query {
+        questions: Vec<String>,
+
+        #[arg(long)]
+        max_results: Option<usize>,
+
+        // One JSON response per line instead of text
+        #[arg(long)]
+        json: bool,
+    },
+    #[command(about = "Answer JSON query requests read line by line from stdin, one JSON response per line")]
+    Serve,
+    #[command(about = "Write the index snapshot (documents, chunks and embeddings) to a file")]
+    Export { output: PathBuf },
+    #[command(about = "Score answers against a JSONL evaluation set of {question, reference_answer, source_doc}")]
+    Eval {
+        cases: PathBuf,
+
+        #[arg(long)]
+        max_results: Option<usize>,
+
+        // Skip the LLM faithfulness judge
+        #[arg(long)]
+        no_judge: bool,
+
+        // Also write the full report, with every case, to this file as JSON
+        #[arg(long)]
+        report: Option<PathBuf>,
+
+        // Print the full report as JSON instead of the summary
+        #[arg(long)]
+        json: bool,
+    },
+    #[command(about = "Show what the index holds")]
+    Stats {
+        #[arg(long)]
+        json: bool,
+    },
+    #[command(about = "Generate synthetic documents and an evaluation set of questions about them")]
+    Synth {
+        // Directory the documents and questions.jsonl are written to
+        output: PathBuf,
+
+        #[arg(long, default_value_t = 20)]
+        documents: usize,
+
+        // Words per document
+        #[arg(long, default_value_t = 2000)]
+        words: usize,
+
+        #[arg(long, default_value_t = 100)]
+        questions: usize,
+
+        // The same seed generates the same corpus
+        #[arg(long, default_value_t = 42)]
+        seed: u64,
+    },
+    #[cfg(feature = "load-test")]
+    #[command(about = "Fire concurrent questions at a running api server and report throughput and latency")]
+    LoadTest {
+        // Base URL of the api server
+        #[arg(long, default_value = "http://localhost:8080")]
+        url: String,
+
+        // Bearer token; without it one is requested from /login
+        #[arg(long, env = "RAG_TOKEN", hide_env_values = true)]
+        token: Option<String>,
+
+        #[arg(long, default_value = "load-test")]
+        username: String,
+
+        #[arg(long, env = "RAG_PASSWORD", hide_env_values = true)]
+        password: Option<String>,
+
+        // Upload the files of this directory (e.g. one from `synth`) first; its questions.jsonl
+        // is used unless --questions is given
+        #[arg(long)]
+        upload: Option<PathBuf>,
+
+        // One question per line, plain text or JSON with a "question" field
+        #[arg(long)]
+        questions: Option<PathBuf>,
+
+        #[arg(long, default_value_t = 8)]
+        concurrency: usize,
+
+        // Questions to send in total
+        #[arg(long, default_value_t = 200)]
+        requests: usize,
+
+        // Send for this many seconds instead of a fixed number of requests
+        #[arg(long, conflicts_with = "requests")]
+        duration_secs: Option<u64>,
+
+        // Per request
+        #[arg(long, default_value_t = 120)]
+        timeout_secs: u64,
+
+        #[arg(long)]
+        max_results: Option<usize>,
+
+        // Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    dotenv::dotenv().ok();
+    let cli = Cli::parse();
+    // Logs go to stderr and stay quiet unless RUST_LOG asks for more, so stdout can be piped
+    Logging::new("warn").with_format(cli.log_format).with_stderr().try_init().map_err(|e| anyhow::anyhow!(e))?;
+
+    match &cli.command {
+        Command::Ingest { paths, rebuild } => {
+            let (urls, files): (Vec<&String>, Vec<&String>) =
+                paths.iter().partition(|path| path.starts_with("http://") || path.starts_with("https://"));
+            let library = library(&cli, files.into_iter().map(PathBuf::from).collect()).await?;
+            commands::ingest(&library, &urls, *rebuild).await
+        }
+        Command::Query { questions, max_results, json } => {
+            let library = library(&cli, Vec::new()).await?;
+            commands::query(&library, questions, *max_results, *json).await
+        }
+        Command::Serve => commands::serve(&library(&cli, Vec::new()).await?).await,
+        Command::Export { output } => commands::export(&library(&cli, Vec::new()).await?, output).await,
+        Command::Eval { cases, max_results, no_judge, report, json } => {
+            let library = library(&cli, Vec::new()).await?;
+            commands::eval(&library, cases, *max_results, !*no_judge, report.as_deref(), *json).await
+        }
+        Command::Stats { json } => commands::stats(&library(&cli, Vec::new()).await?, *json).await,
+        Command::Synth { output, documents, words, questions, seed } => {
+            let spec = synthetic::CorpusSpec { documents: *documents, words: *words, questions: *questions, seed: *seed };
+            let written = synthetic::generate(&spec, output)?;
+            println!("Wrote {} documents and {} questions to {}", documents, written, output.display());
+            Ok(())
+        }
+        #[cfg(feature = "load-test")]
+        Command::LoadTest {
+            url,
+            token,
+            username,
+            password,
+            upload,
+            questions,
+            concurrency,
+            requests,
+            duration_secs,
+            timeout_secs,
+            max_results,
+            json,
+        } => {
+            let test = load_test::LoadTest {
+                url: url.clone(),
+                token: token.clone(),
+                username: username.clone(),
+                password: password.clone(),
+                upload: upload.clone(),
+                questions: questions.clone(),
+                concurrency: *concurrency,
+                requests: *requests,
+                duration: duration_secs.map(Duration::from_secs),
+                timeout: Duration::from_secs(*timeout_secs),
+                max_results: *max_results,
+            };
+            let report = test.run().await?;
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                load_test::print_report(&report);
+            }
+            Ok(())
+        }
+    }
+}
+
+// The library for the global options; documents are loaded by each command
+async fn library(cli: &Cli, extra_sources: Vec<PathBuf>) -> anyhow::Result<RagLibrary> {
+    let mut config = RagConfig::from_env();
+    config.documents_dir = cli.documents_dir.clone();
+    config.extra_sources = cli.sources.iter().cloned().chain(extra_sources).collect();
+    config.state_dir = Some(cli.state_dir.clone());
+    if let Some(chunk_size) = cli.chunk_size {
+        config.chunk_size = chunk_size;
+    }
+    if let Some(chunk_overlap) = cli.chunk_overlap {
+        config.chunk_overlap = chunk_overlap;
+    }
+    Ok(RagLibrary::builder().with_config(config).build().await?)
+}
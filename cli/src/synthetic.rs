@@ -0,0 +1,136 @@
+use rag_system::EvalCase;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+// Filename of the questions written next to the documents
+pub const QUESTIONS_FILE: &str = "questions.jsonl";
+
+const FILLER: &[&str] = &[
+    "policy", "insured", "premium", "coverage", "hospitalisation", "claim", "benefit", "exclusion", "treatment",
+    "surgery", "renewal", "sum", "deductible", "cashless", "network", "hospital", "disease", "room", "rent", "charges",
+    "ambulance", "domiciliary", "daycare", "limit", "per", "annum", "the", "of", "and", "is", "for", "any", "shall", "be",
+    "under", "this", "to", "in", "not",
+];
+
+// What the facts are about, e.g. "Under policy 3 the waiting period for cataract surgery is 24 months."
+const TOPICS: &[&str] = &[
+    "cataract surgery", "maternity expenses", "knee replacement", "pre-existing diseases", "organ donor expenses",
+    "ayush treatment", "icu charges", "ambulance cover", "modern treatments", "mental illness", "dental treatment",
+    "obesity treatment", "hernia repair", "joint replacement", "bariatric surgery", "kidney stones",
+];
+const FACTS: &[(&str, &str)] = &[
+    ("waiting period for", "months"),
+    ("sub-limit on", "percent of the sum insured"),
+    ("grace period for claims on", "days"),
+    ("co-payment on", "percent"),
+];
+
+// How big a corpus to generate
+#[derive(Debug, Clone, Copy)]
+pub struct CorpusSpec {
+    pub documents: usize,
+    // Per document
+    pub words: usize,
+    // In total, spread over the documents
+    pub questions: usize,
+    // Same seed, same corpus
+    pub seed: u64,
+}
+
+// Deterministic pseudo-random numbers (xorshift)
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound.max(1) as u64) as usize
+    }
+}
+
+// One generated document, and the facts it states as (question, answer)
+struct SyntheticDocument {
+    filename: String,
+    text: String,
+    facts: Vec<(String, String)>,
+}
+
+// Filler sentences of 8-20 words with a fact stated every few sentences, so questions about
+// the facts have one right answer in one document
+fn document(index: usize, words: usize, rng: &mut Rng) -> SyntheticDocument {
+    let filename = format!("synthetic-{:04}.txt", index);
+    let mut text = format!("Synthetic policy document {}.\n\n", index);
+    let mut facts = Vec::new();
+    let mut written = 0;
+    while written < words {
+        let (fact, unit) = FACTS[rng.below(FACTS.len())];
+        let topic = TOPICS[rng.below(TOPICS.len())];
+        let question = format!("Under policy {}, what is the {} {}?", index, fact, topic);
+        // Each fact is stated once, so its question has one answer
+        if rng.below(4) == 0 && !facts.iter().any(|(asked, _)| *asked == question) {
+            let value = format!("{} {}", 1 + (index * 7 + rng.below(60)) % 90, unit);
+            let sentence = format!("Under policy {} the {} {} is {}.", index, fact, topic, value);
+            written += sentence.split_whitespace().count();
+            text.push_str(&sentence);
+            facts.push((question, sentence));
+        } else {
+            let length = 8 + rng.below(13);
+            let sentence: Vec<&str> = (0..length).map(|_| FILLER[rng.below(FILLER.len())]).collect();
+            text.push_str(&sentence.join(" "));
+            text.push('.');
+            written += length;
+        }
+        text.push(if rng.below(6) == 0 { '\n' } else { ' ' });
+    }
+    SyntheticDocument { filename, text, facts }
+}
+
+// Writes the documents and QUESTIONS_FILE (an evaluation set for `rag-cli eval`, which
+// `rag-cli load-test` also reads) to `output`. Returns the number of questions written, fewer
+// than asked for when the documents state fewer facts.
+pub fn generate(spec: &CorpusSpec, output: &Path) -> anyhow::Result<usize> {
+    fs::create_dir_all(output)?;
+    let mut rng = Rng::new(spec.seed);
+    let mut facts: Vec<Vec<EvalCase>> = Vec::with_capacity(spec.documents);
+    for index in 0..spec.documents {
+        let document = document(index, spec.words, &mut rng);
+        fs::write(output.join(&document.filename), &document.text)?;
+        facts.push(
+            document
+                .facts
+                .into_iter()
+                .map(|(question, reference_answer)| EvalCase {
+                    question,
+                    reference_answer,
+                    source_doc: document.filename.clone(),
+                })
+                .collect(),
+        );
+    }
+
+    // Round-robin over the documents, so the questions cover the corpus evenly
+    let mut questions = fs::File::create(output.join(QUESTIONS_FILE))?;
+    let mut written = 0;
+    let mut round = 0;
+    while written < spec.questions && facts.iter().any(|cases| round < cases.len()) {
+        for case in facts.iter().filter_map(|cases| cases.get(round)) {
+            if written == spec.questions {
+                break;
+            }
+            writeln!(questions, "{}", serde_json::to_string(case)?)?;
+            written += 1;
+        }
+        round += 1;
+    }
+    Ok(written)
+}